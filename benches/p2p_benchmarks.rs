@@ -1,6 +1,11 @@
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use neverust_core::{create_swarm, Block, BlockStore, Metrics};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use neverust_core::cid_blake3::verify_blake3_batch;
+use neverust_core::dht_provider::cid_to_node_id;
+#[cfg(feature = "blake3-node-id")]
+use neverust_core::dht_provider::cid_to_node_id_blake3;
+use neverust_core::{create_swarm, verify_blake3, Block, BlockStore, Metrics};
 use std::sync::Arc;
+use tiny_keccak::{Hasher, Sha3};
 use tokio::runtime::Runtime;
 
 /// Benchmark: Block creation and CID generation
@@ -21,6 +26,86 @@ fn bench_block_creation(c: &mut Criterion) {
     });
 }
 
+/// Benchmark: Sequential vs parallel verification of 100 64 KiB blocks
+fn bench_block_verification(c: &mut Criterion) {
+    let blocks: Vec<_> = (0..100u32)
+        .map(|i| {
+            let block = Block::new(i.to_le_bytes().repeat(16 * 1024)).unwrap();
+            (block.data, block.cid)
+        })
+        .collect();
+    let mut group = c.benchmark_group("verify_100_blocks_64kb");
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            for (data, cid) in &blocks {
+                std::hint::black_box(verify_blake3(data, cid)).unwrap();
+            }
+        });
+    });
+
+    group.bench_function("batch", |b| {
+        b.iter(|| std::hint::black_box(verify_blake3_batch(&blocks)));
+    });
+
+    group.finish();
+}
+
+/// Benchmark: CID to DHT key hashing
+///
+/// Keccak-256 is what Archivist uses (see `cid_to_node_id`); SHA3-256 and
+/// BLAKE3 give different keys and are measured for comparison only. The
+/// BLAKE3 case needs the `blake3-node-id` feature.
+fn bench_cid_to_node_id(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cid_to_node_id");
+
+    for &batch_size in &[1u32, 100, 10_000] {
+        let cids: Vec<_> = (0..batch_size)
+            .map(|i| Block::new(i.to_le_bytes().to_vec()).unwrap().cid)
+            .collect();
+        group.throughput(Throughput::Elements(batch_size as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("keccak256", batch_size),
+            &cids,
+            |b, cids| {
+                b.iter(|| {
+                    for cid in cids {
+                        std::hint::black_box(cid_to_node_id(cid));
+                    }
+                });
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("sha3_256", batch_size),
+            &cids,
+            |b, cids| {
+                b.iter(|| {
+                    for cid in cids {
+                        let mut hasher = Sha3::v256();
+                        let mut hash = [0u8; 32];
+                        hasher.update(&cid.to_bytes());
+                        hasher.finalize(&mut hash);
+                        std::hint::black_box(hash);
+                    }
+                });
+            },
+        );
+
+        #[cfg(feature = "blake3-node-id")]
+        group.bench_with_input(BenchmarkId::new("blake3", batch_size), &cids, |b, cids| {
+            b.iter(|| {
+                for cid in cids {
+                    std::hint::black_box(cid_to_node_id_blake3(cid));
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
 /// Benchmark: BlockStore operations (in-memory)
 fn bench_block_store(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
@@ -142,6 +227,8 @@ fn bench_concurrent_operations(c: &mut Criterion) {
 criterion_group!(
     benches,
     bench_block_creation,
+    bench_block_verification,
+    bench_cid_to_node_id,
     bench_block_store,
     bench_swarm_creation,
     bench_metrics,
@@ -633,6 +633,8 @@ impl<K: EnrKey> Enr<K> {
         enr_key: &K,
     ) -> Result<Option<Bytes>, Error> {
         check_spec_reserved_keys(key.as_ref(), &value)?;
+        // Records using the Archivist NodeId keep it when re-signed
+        let archivist_node_id = self.node_id != NodeId::from(self.public_key());
         let raw_key = key.as_ref().to_vec();
         let previous_value = self.content.insert(raw_key.clone(), value);
         // add the new public key
@@ -669,6 +671,9 @@ impl<K: EnrKey> Enr<K> {
 
         // update the node id
         self.node_id = NodeId::from(enr_key.public());
+        if archivist_node_id {
+            self.use_archivist_node_id()?;
+        }
 
         if self.size() > MAX_ENR_SIZE {
             // in case the signature size changes, inform the user the size has exceeded the maximum
@@ -10,7 +10,7 @@
 //! cargo run --example advertiser_example
 //! ```
 
-use neverust_core::{Advertiser, Discovery};
+use neverust_core::{Advertiser, Discovery, DiscoveryConfig};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -29,7 +29,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let listen_addr = "127.0.0.1:9000".parse()?;
     let announce_addrs = vec!["/ip4/127.0.0.1/tcp/8070".to_string()];
 
-    let discovery = Arc::new(Discovery::new(&keypair, listen_addr, announce_addrs, vec![]).await?);
+    let discovery = Arc::new(
+        Discovery::new(
+            &keypair,
+            listen_addr,
+            announce_addrs,
+            vec![],
+            DiscoveryConfig::default(),
+        )
+        .await?,
+    );
 
     println!("   ✓ Discovery service created");
     println!("   Local Peer ID: {}\n", discovery.local_peer_id());
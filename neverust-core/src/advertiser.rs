@@ -35,15 +35,34 @@
 //! ```
 
 use cid::Cid;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{mpsc, RwLock, Semaphore};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify, RwLock, Semaphore};
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info, warn};
 
+use crate::config::Config;
 use crate::discovery::Discovery;
-use crate::storage::BlockStore;
+use crate::metrics::Metrics;
+use crate::storage::{BlockStore, StorageEvent, ITERATE_BUFFER};
+
+/// Default maximum number of concurrent advertisement requests
+const DEFAULT_MAX_CONCURRENT: usize = 10;
+
+/// Default interval between re-advertisements of a block
+const DEFAULT_READVERTISE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Default time `flush` waits for the queue to drain
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest number of blocks sent in one batched announcement
+const MAX_BATCH_SIZE: usize = 50;
+
+/// Fraction of the re-advertisement interval a record must age before the
+/// local store loop announces it again
+const READVERTISE_THRESHOLD: f64 = 0.8;
 
 #[derive(Debug, thiserror::Error)]
 pub enum AdvertiserError {
@@ -58,10 +77,16 @@ pub enum AdvertiserError {
 
     #[error("Channel send failed")]
     ChannelSendFailed,
+
+    #[error("Timed out waiting for the advertisement queue to drain")]
+    DrainTimeout,
 }
 
 type Result<T> = std::result::Result<T, AdvertiserError>;
 
+/// Called with each CID whose provider record was stored on the DHT
+pub type BlockStoredCallback = Arc<dyn Fn(Cid) + Send + Sync>;
+
 /// Message types for the advertiser queue
 #[derive(Debug, Clone)]
 enum AdvertiseMessage {
@@ -88,9 +113,15 @@ pub struct Advertiser {
     /// Set of blocks currently in-flight (being advertised)
     in_flight: Arc<RwLock<HashSet<Cid>>>,
 
+    /// When each block was last successfully advertised
+    last_advertised: Arc<RwLock<HashMap<Cid, Instant>>>,
+
     /// Maximum concurrent advertisements
     max_concurrent: usize,
 
+    /// Number of queued blocks announced together in one batch
+    batch_size: usize,
+
     /// Re-advertisement interval
     readvertise_interval: Duration,
 
@@ -100,8 +131,30 @@ pub struct Advertiser {
     /// Handle to the re-advertisement loop task (local store)
     local_store_handle: Arc<RwLock<Option<JoinHandle<()>>>>,
 
+    /// Handle to the task queueing newly stored blocks
+    storage_events_handle: Arc<RwLock<Option<JoinHandle<()>>>>,
+
     /// Running state
     running: Arc<RwLock<bool>>,
+
+    /// Blocks queued or in-flight that have not finished advertising
+    pending: Arc<AtomicUsize>,
+
+    /// Notified whenever `pending` drops to zero
+    drained: Arc<Notify>,
+
+    /// Maximum time `flush` waits for the queue to drain
+    drain_timeout: Duration,
+
+    /// Whether `stop` flushes the queue before shutting down
+    flush_on_stop: bool,
+
+    /// Where advertisement outcomes and queue depth are recorded, if anywhere
+    metrics: Option<Metrics>,
+
+    /// Notified of each successfully advertised block, see
+    /// [`Advertiser::set_on_block_stored`]
+    on_block_stored: Option<BlockStoredCallback>,
 }
 
 impl Advertiser {
@@ -125,32 +178,110 @@ impl Advertiser {
             tx,
             rx: Arc::new(RwLock::new(rx)),
             in_flight: Arc::new(RwLock::new(HashSet::new())),
+            last_advertised: Arc::new(RwLock::new(HashMap::new())),
             max_concurrent,
+            batch_size: 1,
             readvertise_interval,
             task_handle: Arc::new(RwLock::new(None)),
             local_store_handle: Arc::new(RwLock::new(None)),
+            storage_events_handle: Arc::new(RwLock::new(None)),
             running: Arc::new(RwLock::new(false)),
+            pending: Arc::new(AtomicUsize::new(0)),
+            drained: Arc::new(Notify::new()),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            flush_on_stop: false,
+            metrics: None,
+            on_block_stored: None,
         }
     }
 
     /// Create with default settings (10 concurrent, 30 minute re-advertisement)
     pub fn with_defaults(discovery: Arc<Discovery>) -> Self {
-        Self::new(discovery, 10, Duration::from_secs(30 * 60))
+        Self::new(
+            discovery,
+            DEFAULT_MAX_CONCURRENT,
+            DEFAULT_READVERTISE_INTERVAL,
+        )
+        .with_max_concurrent(DEFAULT_MAX_CONCURRENT)
+        .with_readvertise_interval(DEFAULT_READVERTISE_INTERVAL)
+    }
+
+    /// Create with default settings, taking stop behaviour and batch size from
+    /// the node config
+    pub fn from_config(discovery: Arc<Discovery>, config: &Config) -> Self {
+        let mut advertiser = Self::new(
+            discovery,
+            DEFAULT_MAX_CONCURRENT,
+            DEFAULT_READVERTISE_INTERVAL,
+        )
+        .with_max_concurrent(DEFAULT_MAX_CONCURRENT)
+        .with_readvertise_interval(DEFAULT_READVERTISE_INTERVAL);
+        advertiser.set_flush_on_stop(config.advertiser_flush_on_stop);
+        advertiser.set_batch_size(config.advertiser_batch_size);
+        advertiser
+    }
+
+    /// Set how long `flush` waits for the queue to drain
+    pub fn set_drain_timeout(&mut self, timeout: Duration) {
+        self.drain_timeout = timeout;
+    }
+
+    /// Set whether `stop` flushes the queue before shutting down
+    pub fn set_flush_on_stop(&mut self, flush_on_stop: bool) {
+        self.flush_on_stop = flush_on_stop;
+    }
+
+    /// Set how many queued blocks are announced per batch (1 to 50)
+    ///
+    /// With a batch size above 1, queued blocks are sent to each DHT node in a
+    /// single `add_provider_batch` TALK request instead of one request per block.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size.clamp(1, MAX_BATCH_SIZE);
+    }
+
+    /// Record advertisement outcomes and queue depth in `metrics`
+    ///
+    /// Applies to blocks queued after the advertiser is started.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Call `callback` for each block once its announcement succeeds
+    ///
+    /// A batch fires the callback for every CID it carried. Applies to blocks
+    /// queued after the advertiser is started.
+    pub fn set_on_block_stored(&mut self, callback: BlockStoredCallback) {
+        self.on_block_stored = Some(callback);
     }
 
-    /// Set the block store for periodic local store advertisement
+    /// Use a block store for periodic local store advertisement
     ///
     /// When a block store is set, the advertiser will periodically iterate
-    /// all blocks in the store and advertise them to the DHT.
-    pub fn set_block_store(&mut self, block_store: Arc<BlockStore>) {
+    /// all blocks in the store and advertise them to the DHT, and queues
+    /// each newly stored block as soon as the store reports it.
+    pub fn with_block_store(mut self, block_store: Arc<BlockStore>) -> Self {
         self.block_store = Some(block_store);
+        self
+    }
+
+    /// Set the maximum number of concurrent advertisement requests
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent;
+        self
+    }
+
+    /// Set the interval for re-advertising blocks
+    pub fn with_readvertise_interval(mut self, readvertise_interval: Duration) -> Self {
+        self.readvertise_interval = readvertise_interval;
+        self
     }
 
     /// Start the advertiser engine
     ///
-    /// Spawns two or three background tasks:
+    /// Spawns one or three background tasks:
     /// 1. Advertisement loop - processes queued blocks
     /// 2. Local store loop - periodically iterates all blocks in BlockStore (if set)
+    /// 3. Storage event loop - queues blocks as they are stored (if set)
     pub async fn start(&self) -> Result<()> {
         let mut running = self.running.write().await;
         if *running {
@@ -173,6 +304,8 @@ impl Advertiser {
         if self.block_store.is_some() {
             let local_store_handle = self.spawn_advertise_local_store_loop();
             *self.local_store_handle.write().await = Some(local_store_handle);
+            let storage_events_handle = self.spawn_storage_events_loop();
+            *self.storage_events_handle.write().await = Some(storage_events_handle);
             info!("Started local store re-advertisement loop");
         } else {
             info!("No block store set, skipping local store re-advertisement");
@@ -182,7 +315,16 @@ impl Advertiser {
     }
 
     /// Stop the advertiser engine
+    ///
+    /// If flush-on-stop is enabled, queued blocks are advertised first
+    /// (bounded by the drain timeout).
     pub async fn stop(&self) {
+        if self.flush_on_stop && *self.running.read().await {
+            if let Err(e) = self.flush().await {
+                warn!("Stopping advertiser without draining queue: {}", e);
+            }
+        }
+
         let mut running = self.running.write().await;
         if !*running {
             return;
@@ -204,6 +346,10 @@ impl Advertiser {
             handle.abort();
         }
 
+        if let Some(handle) = self.storage_events_handle.write().await.take() {
+            handle.abort();
+        }
+
         info!("Advertiser engine stopped");
     }
 
@@ -214,12 +360,40 @@ impl Advertiser {
         }
 
         debug!("Queueing block for advertisement: {}", cid);
+        queue_block(
+            *cid,
+            &self.tx,
+            &self.pending,
+            &self.drained,
+            self.metrics.as_ref(),
+        )
+    }
+
+    /// Wait until every queued block has finished advertising
+    ///
+    /// Returns `DrainTimeout` if the queue is not empty within the drain timeout.
+    pub async fn flush(&self) -> Result<()> {
+        let drained = async {
+            loop {
+                let notified = self.drained.notified();
+                tokio::pin!(notified);
+                // Register before checking so a concurrent notify isn't missed
+                notified.as_mut().enable();
+                if self.pending.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
 
-        self.tx
-            .send(AdvertiseMessage::Advertise(*cid))
-            .map_err(|_| AdvertiserError::ChannelSendFailed)?;
+        tokio::time::timeout(self.drain_timeout, drained)
+            .await
+            .map_err(|_| AdvertiserError::DrainTimeout)
+    }
 
-        Ok(())
+    /// Get the number of blocks queued or in-flight
+    pub fn pending_count(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
     }
 
     /// Get the number of blocks currently in-flight
@@ -227,6 +401,21 @@ impl Advertiser {
         self.in_flight.read().await.len()
     }
 
+    /// Maximum number of concurrent advertisement requests
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    /// Interval between re-advertisements of a block
+    pub fn readvertise_interval(&self) -> Duration {
+        self.readvertise_interval
+    }
+
+    /// Get the number of blocks that have been successfully advertised
+    pub async fn advertised_count(&self) -> usize {
+        self.last_advertised.read().await.len()
+    }
+
     /// Check if a block is currently being advertised
     pub async fn is_in_flight(&self, cid: &Cid) -> bool {
         self.in_flight.read().await.contains(cid)
@@ -237,8 +426,14 @@ impl Advertiser {
         let discovery = Arc::clone(&self.discovery);
         let rx = Arc::clone(&self.rx);
         let in_flight = Arc::clone(&self.in_flight);
+        let last_advertised = Arc::clone(&self.last_advertised);
         let running = Arc::clone(&self.running);
+        let pending = Arc::clone(&self.pending);
+        let drained = Arc::clone(&self.drained);
         let max_concurrent = self.max_concurrent;
+        let batch_size = self.batch_size;
+        let metrics = self.metrics.clone();
+        let on_block_stored = self.on_block_stored.clone();
 
         tokio::spawn(async move {
             let semaphore = Arc::new(Semaphore::new(max_concurrent));
@@ -249,48 +444,84 @@ impl Advertiser {
                     break;
                 }
 
-                // Get next message from queue
-                let message = {
+                // Get next message(s) from queue
+                let (queued, stop) = {
                     let mut rx_guard = rx.write().await;
-                    rx_guard.recv().await
+                    let received = recv_batch(&mut rx_guard, batch_size).await;
+                    if let Some(metrics) = &metrics {
+                        metrics.set_advertisement_queue_depth(rx_guard.len());
+                    }
+                    received
                 };
 
-                match message {
-                    Some(AdvertiseMessage::Advertise(cid)) => {
-                        // Skip if already in-flight
-                        {
-                            let mut in_flight_guard = in_flight.write().await;
-                            if in_flight_guard.contains(&cid) {
-                                debug!("Block {} already in-flight, skipping", cid);
-                                continue;
-                            }
-                            in_flight_guard.insert(cid);
+                // Skip blocks already in-flight
+                let mut batch = Vec::with_capacity(queued.len());
+                {
+                    let mut in_flight_guard = in_flight.write().await;
+                    for cid in queued {
+                        if in_flight_guard.insert(cid) {
+                            batch.push(cid);
+                        } else {
+                            debug!("Block {} already in-flight, skipping", cid);
+                            finish_pending(&pending, &drained);
                         }
+                    }
+                }
 
-                        let permit = semaphore.clone().acquire_owned().await.unwrap();
-                        let discovery = Arc::clone(&discovery);
-                        let in_flight = Arc::clone(&in_flight);
-
-                        tokio::spawn(async move {
-                            if let Err(e) = discovery.provide(&cid).await {
-                                error!("Failed to advertise block {}: {}", cid, e);
-                            } else {
-                                debug!("Successfully advertised block: {}", cid);
+                if !batch.is_empty() {
+                    let permit = semaphore.clone().acquire_owned().await.unwrap();
+                    let discovery = Arc::clone(&discovery);
+                    let in_flight = Arc::clone(&in_flight);
+                    let last_advertised = Arc::clone(&last_advertised);
+                    let pending = Arc::clone(&pending);
+                    let drained = Arc::clone(&drained);
+                    let metrics = metrics.clone();
+                    let on_block_stored = on_block_stored.clone();
+
+                    tokio::spawn(async move {
+                        let result = match batch.as_slice() {
+                            [cid] => discovery.provide(cid).await,
+                            cids => discovery.provide_batch(cids).await,
+                        };
+
+                        match result {
+                            Ok(()) => {
+                                debug!("Successfully advertised {} block(s)", batch.len());
+                                if let Some(metrics) = &metrics {
+                                    metrics.advertisements_succeeded_add(batch.len() as u64);
+                                }
+                                let now = Instant::now();
+                                let mut last_advertised = last_advertised.write().await;
+                                for cid in &batch {
+                                    last_advertised.insert(*cid, now);
+                                }
+                                drop(last_advertised);
+                                if let Some(callback) = &on_block_stored {
+                                    for cid in &batch {
+                                        callback(*cid);
+                                    }
+                                }
                             }
+                            Err(e) => {
+                                error!("Failed to advertise {} block(s): {}", batch.len(), e);
+                                if let Some(metrics) = &metrics {
+                                    metrics.advertisements_failed_add(batch.len() as u64);
+                                }
+                            }
+                        }
 
-                            // Remove from in-flight
-                            in_flight.write().await.remove(&cid);
-                            drop(permit);
-                        });
-                    }
-                    Some(AdvertiseMessage::Stop) => {
-                        info!("Received stop message, shutting down advertisement loop");
-                        break;
-                    }
-                    None => {
-                        warn!("Advertisement queue channel closed");
-                        break;
-                    }
+                        // Remove from in-flight
+                        let mut in_flight = in_flight.write().await;
+                        for cid in &batch {
+                            in_flight.remove(cid);
+                            finish_pending(&pending, &drained);
+                        }
+                        drop(permit);
+                    });
+                }
+
+                if stop {
+                    break;
                 }
             }
 
@@ -300,15 +531,21 @@ impl Advertiser {
 
     /// Spawn the periodic local store advertisement loop
     ///
-    /// Iterates all blocks in BlockStore every `readvertise_interval` and queues them
-    /// for advertisement. Tracks in-flight requests to avoid duplicates.
+    /// Iterates all blocks in BlockStore every `readvertise_interval` and queues those
+    /// not advertised within the last 80% of the interval. Tracks in-flight requests
+    /// to avoid duplicates.
     ///
     /// Reference: Archivist advertiser.nim:83-97
     fn spawn_advertise_local_store_loop(&self) -> JoinHandle<()> {
         let block_store = self.block_store.clone().expect("BlockStore must be set");
+        let last_advertised = Arc::clone(&self.last_advertised);
         let running = Arc::clone(&self.running);
         let readvertise_interval = self.readvertise_interval;
+        let min_age = readvertise_interval.mul_f64(READVERTISE_THRESHOLD);
         let tx = self.tx.clone();
+        let pending = Arc::clone(&self.pending);
+        let drained = Arc::clone(&self.drained);
+        let metrics = self.metrics.clone();
 
         tokio::spawn(async move {
             let mut cycle = 0u64;
@@ -328,29 +565,18 @@ impl Advertiser {
                     cycle
                 );
 
-                // Get all CIDs from the block store
-                let cids = block_store.list_cids().await;
-                let total_count = cids.len();
+                let (queued, total_count) = queue_stale_blocks(
+                    &block_store,
+                    &last_advertised,
+                    min_age,
+                    &tx,
+                    &pending,
+                    &drained,
+                    metrics.as_ref(),
+                )
+                .await;
 
                 if total_count > 0 {
-                    info!(
-                        "Advertiser: Found {} blocks in local store to advertise",
-                        total_count
-                    );
-
-                    // Queue each block for advertisement
-                    let mut queued = 0;
-                    for cid in cids {
-                        if let Err(e) = tx.send(AdvertiseMessage::Advertise(cid)) {
-                            error!(
-                                "Advertiser: Failed to queue block {} for advertisement: {}",
-                                cid, e
-                            );
-                        } else {
-                            queued += 1;
-                        }
-                    }
-
                     info!(
                         "Advertiser: Cycle #{} complete - queued {}/{} blocks for advertisement",
                         cycle, queued, total_count
@@ -366,6 +592,151 @@ impl Advertiser {
             info!("Advertiser: Local store re-advertisement loop terminated");
         })
     }
+
+    /// Spawn the loop that queues blocks as the block store reports them
+    fn spawn_storage_events_loop(&self) -> JoinHandle<()> {
+        use futures::StreamExt;
+
+        let block_store = self.block_store.clone().expect("BlockStore must be set");
+        let mut events = block_store.event_stream();
+        let discovery = Arc::clone(&self.discovery);
+        let last_advertised = Arc::clone(&self.last_advertised);
+        let tx = self.tx.clone();
+        let pending = Arc::clone(&self.pending);
+        let drained = Arc::clone(&self.drained);
+        let metrics = self.metrics.clone();
+
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                match event {
+                    StorageEvent::BlockStored(cid) => {
+                        debug!("Advertiser: Queueing newly stored block {}", cid);
+                        if queue_block(cid, &tx, &pending, &drained, metrics.as_ref()).is_err() {
+                            break;
+                        }
+                    }
+                    StorageEvent::BlockDeleted(cid) => {
+                        discovery.unprovide(&cid).await;
+                        last_advertised.write().await.remove(&cid);
+                    }
+                    _ => {}
+                }
+            }
+            debug!("Advertiser: Storage event loop terminated");
+        })
+    }
+}
+
+/// Wait for the next queued block, then take up to `batch_size` blocks that
+/// are already queued without waiting further.
+///
+/// Returns the blocks and whether the loop should stop afterwards.
+async fn recv_batch(
+    rx: &mut mpsc::UnboundedReceiver<AdvertiseMessage>,
+    batch_size: usize,
+) -> (Vec<Cid>, bool) {
+    let mut batch = Vec::new();
+    let mut next = rx.recv().await;
+
+    loop {
+        match next {
+            Some(AdvertiseMessage::Advertise(cid)) => batch.push(cid),
+            Some(AdvertiseMessage::Stop) => {
+                info!("Received stop message, shutting down advertisement loop");
+                return (batch, true);
+            }
+            None => {
+                warn!("Advertisement queue channel closed");
+                return (batch, true);
+            }
+        }
+
+        if batch.len() >= batch_size {
+            return (batch, false);
+        }
+        match rx.try_recv() {
+            Ok(message) => next = Some(message),
+            Err(_) => return (batch, false),
+        }
+    }
+}
+
+/// Queue every block in `block_store` not advertised within `min_age`.
+///
+/// Returns the number of blocks queued and the number of blocks in the store.
+async fn queue_stale_blocks(
+    block_store: &BlockStore,
+    last_advertised: &RwLock<HashMap<Cid, Instant>>,
+    min_age: Duration,
+    tx: &mpsc::UnboundedSender<AdvertiseMessage>,
+    pending: &AtomicUsize,
+    drained: &Notify,
+    metrics: Option<&Metrics>,
+) -> (usize, usize) {
+    use futures::StreamExt;
+
+    // Stream CIDs from the block store rather than listing them all at once
+    let mut chunks = block_store.iterate_cids().chunks(ITERATE_BUFFER);
+    let mut total_count = 0;
+    let mut queued = 0;
+
+    while let Some(chunk) = chunks.next().await {
+        let last_advertised = last_advertised.read().await;
+
+        // Queue each stale block for advertisement
+        for cid in chunk {
+            let cid = match cid {
+                Ok(cid) => cid,
+                Err(e) => {
+                    error!("Advertiser: Failed to scan local store: {}", e);
+                    continue;
+                }
+            };
+            total_count += 1;
+            if let Some(at) = last_advertised.get(&cid) {
+                if at.elapsed() < min_age {
+                    continue;
+                }
+            }
+
+            if let Err(e) = queue_block(cid, tx, pending, drained, metrics) {
+                error!(
+                    "Advertiser: Failed to queue block {} for advertisement: {}",
+                    cid, e
+                );
+            } else {
+                queued += 1;
+            }
+        }
+    }
+
+    (queued, total_count)
+}
+
+/// Queue one block for advertisement, counting it as pending
+fn queue_block(
+    cid: Cid,
+    tx: &mpsc::UnboundedSender<AdvertiseMessage>,
+    pending: &AtomicUsize,
+    drained: &Notify,
+    metrics: Option<&Metrics>,
+) -> Result<()> {
+    pending.fetch_add(1, Ordering::SeqCst);
+    if tx.send(AdvertiseMessage::Advertise(cid)).is_err() {
+        finish_pending(pending, drained);
+        return Err(AdvertiserError::ChannelSendFailed);
+    }
+    if let Some(metrics) = metrics {
+        metrics.advertisement_queued();
+    }
+    Ok(())
+}
+
+/// Mark one queued block as finished, waking `flush` callers once the queue is empty.
+fn finish_pending(pending: &AtomicUsize, drained: &Notify) {
+    if pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+        drained.notify_waiters();
+    }
 }
 
 impl Drop for Advertiser {
@@ -381,6 +752,7 @@ impl Drop for Advertiser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::discovery::DiscoveryConfig;
     use libp2p::identity::Keypair;
 
     async fn create_test_discovery() -> Arc<Discovery> {
@@ -391,9 +763,15 @@ mod tests {
         let announce_addrs = vec!["/ip4/127.0.0.1/tcp/8070".to_string()];
 
         Arc::new(
-            Discovery::new(&keypair, listen_addr, announce_addrs, vec![])
-                .await
-                .unwrap(),
+            Discovery::new(
+                &keypair,
+                listen_addr,
+                announce_addrs,
+                vec![],
+                DiscoveryConfig::default(),
+            )
+            .await
+            .unwrap(),
         )
     }
 
@@ -413,6 +791,46 @@ mod tests {
         assert_eq!(advertiser.in_flight_count().await, 0);
     }
 
+    #[tokio::test]
+    async fn test_builder_chain_matches_manual_construction() {
+        use crate::discovery::mock::fake_enr;
+        use crate::storage::Block;
+
+        let advertisers = [false, true].map(|chained| {
+            let (discovery, net) = Discovery::new_mock();
+            net.add_node(fake_enr(&libp2p::PeerId::random(), 9300));
+            let block_store = Arc::new(BlockStore::new());
+            let advertiser = if chained {
+                Advertiser::with_defaults(Arc::new(discovery))
+                    .with_block_store(block_store.clone())
+                    .with_max_concurrent(4)
+                    .with_readvertise_interval(Duration::from_secs(90))
+            } else {
+                Advertiser::new(Arc::new(discovery), 4, Duration::from_secs(90))
+                    .with_block_store(block_store.clone())
+            };
+            (advertiser, block_store, net)
+        });
+
+        for (advertiser, block_store, _net) in &advertisers {
+            assert_eq!(advertiser.max_concurrent(), 4);
+            assert_eq!(advertiser.readvertise_interval(), Duration::from_secs(90));
+
+            // Both advertise what is stored in their block store
+            advertiser.start().await.unwrap();
+            let block = Block::new(b"builder block".to_vec()).unwrap();
+            block_store.put(block).await.unwrap();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while advertiser.advertised_count().await == 0 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("stored block was not advertised");
+            advertiser.stop().await;
+        }
+    }
+
     #[tokio::test]
     async fn test_advertiser_with_defaults() {
         let discovery = create_test_discovery().await;
@@ -561,8 +979,8 @@ mod tests {
         block_store.put(block2.clone()).await.unwrap();
 
         // Use short re-advertisement interval for testing
-        let mut advertiser = Advertiser::new(discovery, 10, Duration::from_millis(200));
-        advertiser.set_block_store(block_store.clone());
+        let advertiser = Advertiser::new(discovery, 10, Duration::from_millis(200))
+            .with_block_store(block_store.clone());
 
         advertiser.start().await.unwrap();
 
@@ -599,6 +1017,301 @@ mod tests {
         advertiser.stop().await;
     }
 
+    #[tokio::test]
+    async fn test_flush_waits_for_all_blocks() {
+        use crate::cid_blake3::blake3_cid;
+        use crate::discovery::mock::{fake_enr, MockRequest};
+
+        let (discovery, mut net) = Discovery::new_mock();
+        net.add_node(fake_enr(&libp2p::PeerId::random(), 9200));
+        let advertiser = Advertiser::new(Arc::new(discovery), 4, Duration::from_secs(3600));
+        advertiser.start().await.unwrap();
+
+        for i in 0..50 {
+            let cid = blake3_cid(format!("block {}", i).as_bytes()).unwrap();
+            advertiser.advertise_block(&cid).await.unwrap();
+        }
+        advertiser.flush().await.unwrap();
+
+        assert_eq!(advertiser.pending_count(), 0);
+        assert_eq!(advertiser.in_flight_count().await, 0);
+        // Each provide reaches the mock DHT node before flush returns
+        let mut add_providers = 0;
+        while let Ok(request) = net.try_next_request() {
+            assert!(matches!(request, MockRequest::AddProvider { .. }));
+            add_providers += 1;
+        }
+        assert_eq!(add_providers, 50);
+
+        advertiser.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_flush_times_out_when_not_draining() {
+        let (discovery, _net) = Discovery::new_mock();
+        let mut advertiser = Advertiser::with_defaults(Arc::new(discovery));
+        advertiser.set_drain_timeout(Duration::from_millis(50));
+
+        // A queued block that no running loop will ever process
+        advertiser.pending.fetch_add(1, Ordering::SeqCst);
+
+        let result = advertiser.flush().await;
+        assert!(matches!(result, Err(AdvertiserError::DrainTimeout)));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_advertisements() {
+        use crate::cid_blake3::blake3_cid;
+
+        let (discovery, _net) = Discovery::new_mock();
+        let metrics = Metrics::new();
+        let mut advertiser = Advertiser::with_defaults(Arc::new(discovery));
+        advertiser.set_metrics(metrics.clone());
+        advertiser.start().await.unwrap();
+
+        for i in 0..5 {
+            let cid = blake3_cid(format!("block {}", i).as_bytes()).unwrap();
+            advertiser.advertise_block(&cid).await.unwrap();
+        }
+        assert_eq!(metrics.advertisements_queued(), 5);
+        advertiser.flush().await.unwrap();
+
+        assert_eq!(metrics.advertisements_succeeded(), 5);
+        assert_eq!(metrics.advertisements_failed(), 0);
+        assert_eq!(metrics.advertisement_queue_depth(), 0);
+        advertiser.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_stop_flushes_when_configured() {
+        use crate::cid_blake3::blake3_cid;
+
+        let (discovery, _net) = Discovery::new_mock();
+        let config = Config {
+            advertiser_flush_on_stop: true,
+            ..Config::default()
+        };
+        let advertiser = Advertiser::from_config(Arc::new(discovery), &config);
+        advertiser.start().await.unwrap();
+
+        for i in 0..10 {
+            let cid = blake3_cid(format!("block {}", i).as_bytes()).unwrap();
+            advertiser.advertise_block(&cid).await.unwrap();
+        }
+        advertiser.stop().await;
+
+        assert_eq!(advertiser.pending_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_recently_advertised_block_not_requeued() {
+        use crate::storage::Block;
+
+        let (discovery, _net) = Discovery::new_mock();
+        let block_store = Arc::new(BlockStore::new());
+        let fresh = Block::new(b"fresh block".to_vec()).unwrap();
+        let stale = Block::new(b"stale block".to_vec()).unwrap();
+        block_store.put(fresh.clone()).await.unwrap();
+        block_store.put(stale.clone()).await.unwrap();
+
+        let advertiser = Advertiser::new(Arc::new(discovery), 10, Duration::from_secs(3600))
+            .with_block_store(block_store.clone());
+        advertiser.start().await.unwrap();
+        advertiser.advertise_block(&fresh.cid).await.unwrap();
+        advertiser.flush().await.unwrap();
+        assert_eq!(advertiser.advertised_count().await, 1);
+
+        // Run a cycle right away: only the never-advertised block is queued
+        let (queued, total) = queue_stale_blocks(
+            &block_store,
+            &advertiser.last_advertised,
+            advertiser
+                .readvertise_interval
+                .mul_f64(READVERTISE_THRESHOLD),
+            &advertiser.tx,
+            &advertiser.pending,
+            &advertiser.drained,
+            None,
+        )
+        .await;
+        assert_eq!((queued, total), (1, 2));
+
+        advertiser.flush().await.unwrap();
+        assert_eq!(advertiser.advertised_count().await, 2);
+
+        advertiser.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_newly_stored_blocks_are_advertised() {
+        use crate::discovery::mock::{fake_enr, MockRequest};
+        use crate::storage::Block;
+
+        let (discovery, mut net) = Discovery::new_mock();
+        net.add_node(fake_enr(&libp2p::PeerId::random(), 9300));
+        let block_store = Arc::new(BlockStore::new());
+        let advertiser = Advertiser::new(Arc::new(discovery), 10, Duration::from_secs(3600))
+            .with_block_store(block_store.clone());
+        advertiser.start().await.unwrap();
+
+        let block = Block::new(b"stored after start".to_vec()).unwrap();
+        block_store.put(block.clone()).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while advertiser.advertised_count().await == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("stored block was not advertised");
+        assert!(matches!(
+            net.try_next_request(),
+            Ok(MockRequest::AddProvider { .. })
+        ));
+
+        // Deleting the block stops providing it
+        block_store.delete(&block.cid).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while advertiser.advertised_count().await > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("deleted block is still advertised");
+        let store = advertiser.discovery.provider_store().read().await;
+        assert_eq!(store.total_providers(), 0);
+        drop(store);
+
+        advertiser.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_batch_size_groups_talk_requests() {
+        use crate::cid_blake3::blake3_cid;
+        use crate::dht_provider::TALK_PROTOCOL_ADD_PROVIDER_BATCH;
+        use crate::discovery::mock::{fake_enr, MockRequest};
+
+        let (discovery, mut net) = Discovery::new_mock();
+        net.add_node(fake_enr(&libp2p::PeerId::random(), 9201));
+        let talk_requests = tokio::spawn(async move {
+            let mut count = 0;
+            while let Some(request) = net.next_request().await {
+                match request {
+                    MockRequest::Talk {
+                        protocol, reply, ..
+                    } => {
+                        assert_eq!(protocol, TALK_PROTOCOL_ADD_PROVIDER_BATCH);
+                        count += 1;
+                        reply.send(Vec::new()).unwrap();
+                    }
+                    other => panic!("expected a batched TALK request, got {:?}", other),
+                }
+            }
+            count
+        });
+
+        let discovery = Arc::new(discovery);
+        let mut advertiser = Advertiser::new(discovery.clone(), 10, Duration::from_secs(3600));
+        advertiser.set_batch_size(10);
+        let stored = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        let on_block_stored = stored.clone();
+        advertiser.set_on_block_stored(Arc::new(move |cid| {
+            on_block_stored.lock().unwrap().insert(cid);
+        }));
+
+        // Queue everything before the loop starts so batches fill up
+        let cids: Vec<Cid> = (0..100)
+            .map(|i| blake3_cid(format!("block {}", i).as_bytes()).unwrap())
+            .collect();
+        for cid in &cids {
+            advertiser.pending.fetch_add(1, Ordering::SeqCst);
+            advertiser
+                .tx
+                .send(AdvertiseMessage::Advertise(*cid))
+                .unwrap();
+        }
+        advertiser.start().await.unwrap();
+        advertiser.flush().await.unwrap();
+
+        assert_eq!(advertiser.advertised_count().await, 100);
+        // Every CID of each successful batch reaches the hook
+        assert_eq!(*stored.lock().unwrap(), cids.iter().copied().collect());
+        advertiser.stop().await;
+        drop(advertiser);
+        drop(discovery);
+        assert_eq!(talk_requests.await.unwrap(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_config_batch_size_sends_provide_batches() {
+        use crate::cid_blake3::blake3_cid;
+        use crate::discovery::mock::{fake_enr, MockRequest};
+
+        let (discovery, mut net) = Discovery::new_mock();
+        net.add_node(fake_enr(&libp2p::PeerId::random(), 9202));
+        let talk_requests = tokio::spawn(async move {
+            let mut protocols = Vec::new();
+            while let Some(request) = net.next_request().await {
+                match request {
+                    MockRequest::Talk {
+                        protocol, reply, ..
+                    } => {
+                        protocols.push(protocol);
+                        reply.send(Vec::new()).unwrap();
+                    }
+                    other => panic!("expected a TALK request, got {:?}", other),
+                }
+            }
+            protocols
+        });
+
+        let config = Config {
+            advertiser_batch_size: 5,
+            ..Config::default()
+        };
+        let discovery = Arc::new(discovery);
+        let advertiser = Advertiser::from_config(discovery.clone(), &config);
+        assert_eq!(advertiser.batch_size, 5);
+
+        for i in 0..10 {
+            let cid = blake3_cid(format!("batched {}", i).as_bytes()).unwrap();
+            advertiser.pending.fetch_add(1, Ordering::SeqCst);
+            advertiser
+                .tx
+                .send(AdvertiseMessage::Advertise(cid))
+                .unwrap();
+        }
+        advertiser.start().await.unwrap();
+        advertiser.flush().await.unwrap();
+
+        assert_eq!(advertiser.advertised_count().await, 10);
+        advertiser.stop().await;
+        drop(advertiser);
+        drop(discovery);
+
+        // Two provide_batch calls, no single-CID provides
+        let protocols = talk_requests.await.unwrap();
+        assert_eq!(
+            protocols,
+            vec![
+                crate::dht_provider::TALK_PROTOCOL_ADD_PROVIDER_BATCH.to_vec(),
+                crate::dht_provider::TALK_PROTOCOL_ADD_PROVIDER_BATCH.to_vec(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_batch_size_clamps() {
+        let (discovery, _net) = Discovery::new_mock();
+        let mut advertiser = Advertiser::with_defaults(Arc::new(discovery));
+        assert_eq!(advertiser.batch_size, 1);
+
+        advertiser.set_batch_size(0);
+        assert_eq!(advertiser.batch_size, 1);
+        advertiser.set_batch_size(500);
+        assert_eq!(advertiser.batch_size, MAX_BATCH_SIZE);
+    }
+
     #[tokio::test]
     async fn test_advertiser_drop() {
         let discovery = create_test_discovery().await;
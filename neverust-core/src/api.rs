@@ -2,9 +2,12 @@
 
 use axum::{
     body::Body,
-    extract::{DefaultBodyLimit, Path, State},
+    extract::{DefaultBodyLimit, Path, Query, State},
     http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing::{get, post},
     Json, Router,
 };
@@ -15,25 +18,35 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tower_http::trace::TraceLayer;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::archivist_tree::ArchivistTree;
+use crate::archivist_tree::{ArchivistProof, ArchivistTree, ArchivistTreeError};
 use crate::folder_manifest::{self, DirectoryEntry, DirectoryManifest, DIRECTORY_CODEC};
 use crate::botg::BoTgProtocol;
+use crate::config::Config;
+use crate::content_router::ContentRouter;
+use crate::discovery_engine::DiscoveryEngineHandle;
+use crate::erasure::{ErasureEncoder, ErasureParams};
+use crate::runtime::RuntimeHandle;
+use crate::fetcher::{BlockFetcher, FetchError};
 use crate::citadel::{
     run_defederation_simulation, CitadelSyncPullRequest, CitadelSyncPullResponse,
     CitadelSyncPushRequest, CitadelSyncPushResponse, DefederationNode,
     DefederationSimulationConfig,
 };
-use crate::manifest::{Manifest, SHA256_CODEC};
+use crate::manifest::{Manifest, StrategyType, BLOCK_CODEC, SHA256_CODEC};
 use crate::marketplace::{
     ActiveSlotResponse, MarketplaceRuntimeInfo, MarketplaceStore, PurchaseResponse,
     SaleAvailabilityInput, SalesSlotResponse, StorageRequestInput,
 };
-use crate::metrics::Metrics;
-use crate::storage::{Block, BlockStore, StorageError};
-use libp2p::{identity::Keypair, Multiaddr};
+use crate::metrics::{
+    prefers_openmetrics, Metrics, OPENMETRICS_CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE,
+};
+use crate::prefetch::PrefetchEngine;
+use crate::rate_limit::{RateLimitConfig, RateLimitLayer};
+use crate::request_log::RequestLogLayer;
+use crate::storage::{Block, BlockStore, StorageError, StorageEvent};
+use libp2p::{identity::Keypair, Multiaddr, PeerId};
 use std::sync::RwLock;
 use tokio::sync::RwLock as AsyncRwLock;
 
@@ -91,7 +104,10 @@ fn upload_inflight_batches() -> usize {
         .clamp(1, 16)
 }
 
-fn configured_http_fallback_peers() -> Vec<String> {
+/// HTTP peers to fall back to when a block is unavailable over P2P
+///
+/// Empty unless `NEVERUST_HTTP_FALLBACK_PEERS` lists peer base URLs.
+pub(crate) fn fallback_http_peer_urls() -> Vec<String> {
     std::env::var("NEVERUST_HTTP_FALLBACK_PEERS")
         .ok()
         .map(|raw| {
@@ -104,36 +120,6 @@ fn configured_http_fallback_peers() -> Vec<String> {
         .unwrap_or_default()
 }
 
-fn default_http_fallback_peers() -> Vec<String> {
-    let mut peer_urls = vec!["http://bootstrap:8080".to_string()];
-
-    // Docker network peers
-    for i in 1..50 {
-        peer_urls.push(format!("http://node{}:8080", i));
-    }
-
-    // Known external peers and common Archivist API ports
-    let external_peers = vec![
-        "91.98.135.54",
-        "10.7.1.200", // blackberry
-    ];
-    for peer in external_peers {
-        for port in [8080, 8070, 8000, 3000] {
-            peer_urls.push(format!("http://{}:{}", peer, port));
-        }
-    }
-
-    peer_urls
-}
-
-fn fallback_http_peer_urls() -> Vec<String> {
-    let configured = configured_http_fallback_peers();
-    if !configured.is_empty() {
-        return configured;
-    }
-    default_http_fallback_peers()
-}
-
 fn hash_raw_blocks_parallel(
     raw_blocks: Vec<Vec<u8>>,
     workers: usize,
@@ -186,11 +172,41 @@ fn hash_raw_blocks_parallel(
         .collect()
 }
 
+/// Store the blocks not already in the block store, returning how many were
+/// written
+///
+/// Skipped blocks are counted in the upload deduplication metrics.
+async fn put_new_blocks(
+    block_store: &BlockStore,
+    metrics: &Metrics,
+    blocks: Vec<Block>,
+) -> Result<usize, ApiError> {
+    let total = blocks.len();
+    let cids: Vec<Cid> = blocks.iter().map(|block| block.cid).collect();
+    let present = block_store.has_many(&cids).await;
+    let to_store: Vec<Block> = blocks
+        .into_iter()
+        .zip(present)
+        .filter_map(|(block, present)| (!present).then_some(block))
+        .collect();
+    let stored = to_store.len();
+    metrics.upload_blocks_deduplicated_add((total - stored) as u64);
+
+    if !to_store.is_empty() {
+        block_store
+            .put_many(to_store)
+            .await
+            .map_err(|e| ApiError::Internal(format!("Failed to store block batch: {}", e)))?;
+    }
+    Ok(stored)
+}
+
 async fn flush_upload_raw_batch(
     state: &ApiState,
     raw_batch: &mut Vec<Vec<u8>>,
     seen_cids: &mut Option<std::collections::HashSet<Cid>>,
     block_cids: &mut Vec<Cid>,
+    stored_blocks: &mut usize,
 ) -> Result<(), ApiError> {
     if raw_batch.is_empty() {
         return Ok(());
@@ -217,23 +233,19 @@ async fn flush_upload_raw_batch(
         }
     }
 
-    if !to_store.is_empty() {
-        state
-            .block_store
-            .put_many(to_store)
-            .await
-            .map_err(|e| ApiError::Internal(format!("Failed to store block batch: {}", e)))?;
-    }
-
+    *stored_blocks += put_new_blocks(&state.block_store, &state.metrics, to_store).await?;
     Ok(())
 }
 
+/// Hash and store a batch without checking for repeats within the upload,
+/// returning the batch's CIDs and how many blocks were written
 async fn process_upload_raw_batch_no_dedupe(
     block_store: Arc<BlockStore>,
+    metrics: Metrics,
     raw_batch: Vec<Vec<u8>>,
-) -> Result<Vec<Cid>, ApiError> {
+) -> Result<(Vec<Cid>, usize), ApiError> {
     if raw_batch.is_empty() {
-        return Ok(Vec::new());
+        return Ok((Vec::new(), 0));
     }
 
     let workers = upload_hash_workers();
@@ -243,11 +255,8 @@ async fn process_upload_raw_batch_no_dedupe(
         .map_err(|e| ApiError::Internal(format!("Failed to hash upload batch: {}", e)))?;
 
     let cids: Vec<Cid> = blocks.iter().map(|b| b.cid).collect();
-    block_store
-        .put_many(blocks)
-        .await
-        .map_err(|e| ApiError::Internal(format!("Failed to store block batch: {}", e)))?;
-    Ok(cids)
+    let stored = put_new_blocks(&block_store, &metrics, blocks).await?;
+    Ok((cids, stored))
 }
 
 /// Convert CID to base58btc string (Archivist format with 'z' prefix)
@@ -256,6 +265,55 @@ fn cid_to_string(cid: &Cid) -> String {
         .unwrap_or_else(|_| cid.to_string())
 }
 
+/// How long a generated SPR is served before it is regenerated
+const SPR_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Most recently generated fallback SPR
+#[derive(Debug, Clone)]
+pub struct SprCache {
+    pub spr: String,
+    pub generated_at: std::time::Instant,
+    pub seq: u64,
+    /// Listen addresses the SPR was generated from
+    listen_addrs: Vec<Multiaddr>,
+    /// Set by `SprCacheHandle::invalidate`
+    invalidated: bool,
+}
+
+impl SprCache {
+    /// Whether the cached SPR can be served for the current listen addresses
+    fn is_fresh(&self, listen_addrs: &[Multiaddr]) -> bool {
+        !self.invalidated
+            && self.generated_at.elapsed() < SPR_CACHE_TTL
+            && self.listen_addrs == listen_addrs
+    }
+}
+
+/// Shared slot for the most recently generated fallback SPR
+///
+/// The swarm loop holds a clone to invalidate the SPR when the node starts
+/// listening on a new address.
+#[derive(Debug, Clone, Default)]
+pub struct SprCacheHandle(Arc<RwLock<Option<SprCache>>>);
+
+impl SprCacheHandle {
+    /// Force the next SPR request to regenerate the SPR
+    pub fn invalidate(&self) {
+        if let Some(cache) = self.0.write().unwrap().as_mut() {
+            cache.invalidated = true;
+        }
+    }
+}
+
+/// Sequence number for a regenerated SPR: the current Unix time, but always
+/// greater than the previous SPR's
+fn next_spr_seq(previous: Option<u64>, now_secs: u64) -> u64 {
+    match previous {
+        Some(seq) => now_secs.max(seq + 1),
+        None => now_secs,
+    }
+}
+
 /// API state shared across handlers
 #[derive(Clone)]
 pub struct ApiState {
@@ -267,12 +325,57 @@ pub struct ApiState {
     pub listen_addrs: Arc<RwLock<Vec<Multiaddr>>>,
     pub announce_addrs: Vec<String>,
     pub discovery: Option<Arc<crate::discovery::Discovery>>,
+    pub discovery_engine: Option<DiscoveryEngineHandle>,
+    pub block_fetcher: Option<Arc<BlockFetcher>>,
+    /// Lookahead fetching of manifest blocks served by range downloads
+    pub prefetch: Option<Arc<PrefetchEngine>>,
+    pub content_router: Option<Arc<ContentRouter>>,
+    pub erasure: Option<ErasureParams>,
+    pub runtime: Option<RuntimeHandle>,
     pub fallback_http_peers: Arc<Vec<String>>,
     pub fallback_http_client: reqwest::Client,
     pub ipfs_cluster_pins: Arc<AsyncRwLock<HashMap<String, IpfsClusterPinRecord>>>,
+    /// Manifest CID of each uploaded dataset, keyed by the tree root of its
+    /// data blocks
+    pub upload_manifests: Arc<RwLock<HashMap<Cid, Cid>>>,
     pub citadel_node: Option<Arc<AsyncRwLock<DefederationNode>>>,
     pub marketplace: Option<MarketplaceStore>,
     pub marketplace_runtime: MarketplaceRuntimeInfo,
+    pub runtime_config: Arc<RuntimeConfig>,
+    pub spr_cache: SprCacheHandle,
+}
+
+/// Node settings exposed read-only through the API
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeConfig {
+    pub version: String,
+    pub block_store_path: String,
+    /// Size of the blocks uploads are chunked into
+    pub max_block_size: u64,
+    pub node_mode: String,
+    /// Bootstrap peers as configured, before any fallback or SPR resolution
+    pub bootstrap_peers: Vec<String>,
+    /// Per-client request limit applied to the API
+    pub api_rate_limit: Option<RateLimitConfig>,
+}
+
+impl From<&Config> for RuntimeConfig {
+    fn from(config: &Config) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            block_store_path: config.data_dir.join("blocks").display().to_string(),
+            max_block_size: upload_block_size() as u64,
+            node_mode: config.mode.clone(),
+            bootstrap_peers: config.bootstrap_nodes.clone(),
+            api_rate_limit: config.api_rate_limit.clone(),
+        }
+    }
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self::from(&Config::default())
+    }
 }
 
 /// Response for storing a block
@@ -296,6 +399,8 @@ pub struct HealthResponse {
     pub status: String,
     pub block_count: usize,
     pub total_bytes: usize,
+    /// Bytes the block store takes up on disk, if it could be measured
+    pub disk_bytes: Option<u64>,
 }
 
 /// Manifest view compatible with Archivist DataItem schema
@@ -311,6 +416,79 @@ pub struct ManifestItemResponse {
     pub mimetype: Option<String>,
 }
 
+/// Erasure coding parameters of a protected manifest
+#[derive(Serialize, Deserialize)]
+pub struct ManifestErasureResponse {
+    pub ec_k: u32,
+    pub ec_m: u32,
+    pub original_tree_cid: String,
+    pub original_dataset_size: u64,
+    pub protected_strategy: String,
+}
+
+/// Full manifest metadata (GET /api/archivist/v1/manifest/:cid)
+#[derive(Serialize, Deserialize)]
+pub struct ManifestDetailResponse {
+    pub cid: String,
+    pub tree_cid: String,
+    pub block_size: u64,
+    pub dataset_size: u64,
+    pub blocks_count: usize,
+    /// Dataset codec as a hex string, e.g. "0xcd02"
+    pub codec: String,
+    pub filename: Option<String>,
+    pub mimetype: Option<String>,
+    pub is_protected: bool,
+    pub is_verifiable: bool,
+    pub erasure: Option<ManifestErasureResponse>,
+}
+
+impl ManifestDetailResponse {
+    pub(crate) fn new(cid: &Cid, manifest: &Manifest) -> Self {
+        Self {
+            cid: cid_to_string(cid),
+            tree_cid: cid_to_string(&manifest.tree_cid),
+            block_size: manifest.block_size,
+            dataset_size: manifest.dataset_size,
+            blocks_count: manifest.blocks_count(),
+            codec: format!("0x{:x}", manifest.codec),
+            filename: manifest.filename.clone(),
+            mimetype: manifest.mimetype.clone(),
+            is_protected: manifest.is_protected(),
+            is_verifiable: manifest.is_verifiable(),
+            erasure: manifest
+                .erasure
+                .as_ref()
+                .map(|erasure| ManifestErasureResponse {
+                    ec_k: erasure.ec_k,
+                    ec_m: erasure.ec_m,
+                    original_tree_cid: cid_to_string(&erasure.original_tree_cid),
+                    original_dataset_size: erasure.original_dataset_size,
+                    protected_strategy: format!("{:?}", erasure.protected_strategy),
+                }),
+        }
+    }
+}
+
+/// Download size estimate for a manifest (GET /api/archivist/v1/manifest/:cid/info)
+#[derive(Serialize, Deserialize)]
+pub struct ManifestInfoResponse {
+    pub size_bytes: u64,
+    pub block_count: usize,
+    /// Estimated download time at the node's average receive rate, or `None`
+    /// if nothing has been received yet
+    pub estimated_seconds: Option<f64>,
+    pub filename: Option<String>,
+    pub mimetype: Option<String>,
+}
+
+/// A block of a manifest's dataset and whether it is stored locally
+#[derive(Serialize, Deserialize)]
+pub struct ManifestBlockEntry {
+    pub cid: String,
+    pub local: bool,
+}
+
 /// Archivist DataItem
 #[derive(Serialize, Deserialize)]
 pub struct DataItemResponse {
@@ -391,6 +569,26 @@ impl IpfsClusterPinRecord {
     }
 }
 
+/// Optional services and settings behind the REST API
+///
+/// Endpoints whose service is absent answer `503 Service Unavailable`.
+#[derive(Clone, Default)]
+pub struct ApiDeps {
+    pub citadel_node: Option<Arc<AsyncRwLock<DefederationNode>>>,
+    pub marketplace: Option<MarketplaceStore>,
+    pub marketplace_runtime: MarketplaceRuntimeInfo,
+    pub announce_addrs: Vec<String>,
+    pub discovery: Option<Arc<crate::discovery::Discovery>>,
+    pub block_fetcher: Option<Arc<BlockFetcher>>,
+    pub prefetch: Option<Arc<PrefetchEngine>>,
+    pub content_router: Option<Arc<ContentRouter>>,
+    pub erasure: Option<ErasureParams>,
+    pub runtime: Option<RuntimeHandle>,
+    pub discovery_engine: Option<DiscoveryEngineHandle>,
+    pub runtime_config: Arc<RuntimeConfig>,
+    pub spr_cache: SprCacheHandle,
+}
+
 /// Create the REST API router
 pub fn create_router(
     block_store: Arc<BlockStore>,
@@ -407,11 +605,7 @@ pub fn create_router(
         botg,
         keypair,
         listen_addrs,
-        None,
-        None,
-        MarketplaceRuntimeInfo::default(),
-        Vec::new(),
-        None,
+        ApiDeps::default(),
     )
 }
 
@@ -432,15 +626,14 @@ pub fn create_router_with_citadel(
         botg,
         keypair,
         listen_addrs,
-        citadel_node,
-        None,
-        MarketplaceRuntimeInfo::default(),
-        Vec::new(),
-        None,
+        ApiDeps {
+            citadel_node,
+            ..ApiDeps::default()
+        },
     )
 }
 
-/// Create the REST API router with optional Citadel and marketplace state.
+/// Create the REST API router with the node's optional services.
 pub fn create_router_with_runtime(
     block_store: Arc<BlockStore>,
     metrics: Metrics,
@@ -448,18 +641,30 @@ pub fn create_router_with_runtime(
     botg: Arc<BoTgProtocol>,
     keypair: Arc<Keypair>,
     listen_addrs: Arc<RwLock<Vec<Multiaddr>>>,
-    citadel_node: Option<Arc<AsyncRwLock<DefederationNode>>>,
-    marketplace: Option<MarketplaceStore>,
-    marketplace_runtime: MarketplaceRuntimeInfo,
-    announce_addrs: Vec<String>,
-    discovery: Option<Arc<crate::discovery::Discovery>>,
+    deps: ApiDeps,
 ) -> Router {
+    let ApiDeps {
+        citadel_node,
+        marketplace,
+        marketplace_runtime,
+        announce_addrs,
+        discovery,
+        block_fetcher,
+        prefetch,
+        content_router,
+        erasure,
+        runtime,
+        discovery_engine,
+        runtime_config,
+        spr_cache,
+    } = deps;
     let fallback_http_peers = Arc::new(fallback_http_peer_urls());
     let fallback_http_client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(2))
         .build()
         .unwrap_or_else(|_| reqwest::Client::new());
 
+    let rate_limit = runtime_config.api_rate_limit.clone();
     let state = ApiState {
         block_store,
         metrics,
@@ -469,15 +674,24 @@ pub fn create_router_with_runtime(
         listen_addrs,
         announce_addrs,
         discovery,
+        discovery_engine,
+        block_fetcher,
+        prefetch,
+        content_router,
+        erasure,
+        runtime,
         fallback_http_peers,
         fallback_http_client,
         ipfs_cluster_pins: Arc::new(AsyncRwLock::new(HashMap::new())),
+        upload_manifests: Arc::new(RwLock::new(HashMap::new())),
         citadel_node,
         marketplace,
         marketplace_runtime,
+        runtime_config,
+        spr_cache,
     };
 
-    Router::new()
+    let router = Router::new()
         .route("/health", get(health_check))
         .route("/metrics", get(metrics_endpoint))
         .route("/api/v1/blocks", post(store_block))
@@ -496,6 +710,10 @@ pub fn create_router_with_runtime(
             get(archivist_download_local).delete(archivist_delete),
         )
         .route("/api/archivist/v1/data/{cid}/exists", get(archivist_exists))
+        .route(
+            "/api/archivist/v1/data/{cid}/blocks",
+            get(archivist_list_blocks),
+        )
         .route(
             "/api/archivist/v1/data/{cid}/network",
             post(archivist_download_network_manifest),
@@ -508,11 +726,37 @@ pub fn create_router_with_runtime(
             "/api/archivist/v1/data/{cid}/network/manifest",
             get(archivist_download_network_manifest),
         )
+        .route("/api/archivist/v1/manifest/{cid}", get(archivist_manifest))
+        .route(
+            "/api/archivist/v1/manifest/{cid}/info",
+            get(archivist_manifest_info),
+        )
         .route("/api/archivist/v1/space", get(archivist_space))
         .route("/api/archivist/v1/peer-id", get(peer_id_endpoint))
         .route("/api/archivist/v1/peerid", get(peer_id_endpoint))
         .route("/api/archivist/v1/stats", get(archivist_stats))
+        .route("/api/archivist/v1/config", get(archivist_config))
+        .route("/api/archivist/v1/stats/codecs", get(archivist_codec_stats))
+        .route("/api/archivist/v1/events", get(archivist_events))
+        .route(
+            "/api/archivist/v1/discovery/stats",
+            get(archivist_discovery_stats),
+        )
+        .route(
+            "/api/archivist/v1/discovery/engine/stats",
+            get(archivist_discovery_engine_stats),
+        )
+        .route(
+            "/api/archivist/v1/behaviour/stats",
+            get(archivist_behaviour_stats),
+        )
+        .route("/api/archivist/v1/wantlist", get(archivist_wantlist))
         .route("/api/archivist/v1/spr", get(spr_endpoint))
+        .route(
+            "/api/archivist/v1/proof/{tree_cid}/{index}",
+            get(archivist_proof),
+        )
+        .route("/api/archivist/v1/routing/{cid}", get(archivist_routing))
         // Directory manifest endpoint (Archivist-compatible)
         .route(
             "/api/archivist/v1/directory",
@@ -553,6 +797,11 @@ pub fn create_router_with_runtime(
             "/api/archivist/v1/connect/{peer_id}",
             get(connect_not_supported),
         )
+        .route("/api/archivist/v1/peers/dial", post(archivist_dial_peer))
+        .route(
+            "/api/archivist/v1/peers/{peer_id}/latency_ms",
+            get(archivist_peer_latency),
+        )
         .route("/api/archivist/v1/sales/slots", get(list_sales_slots))
         .route(
             "/api/archivist/v1/sales/slots/{slot_id}",
@@ -590,35 +839,62 @@ pub fn create_router_with_runtime(
         .with_state(state)
         // Axum applies a 2 MiB default body limit for `Bytes` extractors.
         // Disable it so upload size is constrained only by host resources.
-        .layer(DefaultBodyLimit::disable())
-        .layer(TraceLayer::new_for_http())
+        .layer(DefaultBodyLimit::disable());
+    let router = match rate_limit {
+        Some(limit) => {
+            router.layer(RateLimitLayer::new(limit).with_exempt_paths(&["/health", "/metrics"]))
+        }
+        None => router,
+    };
+    router.layer(RequestLogLayer)
 }
 
 /// Health check endpoint
 async fn health_check(State(state): State<ApiState>) -> impl IntoResponse {
     let stats = state.block_store.stats().await;
+    let disk_bytes = match state.block_store.size_on_disk().await {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            warn!("Failed to measure block store disk usage: {}", e);
+            None
+        }
+    };
 
     Json(HealthResponse {
         status: "ok".to_string(),
         block_count: stats.block_count,
         total_bytes: stats.total_size,
+        disk_bytes,
     })
 }
 
 /// Prometheus metrics endpoint
-async fn metrics_endpoint(State(state): State<ApiState>) -> impl IntoResponse {
+///
+/// Serves OpenMetrics to clients whose `Accept` header prefers it.
+async fn metrics_endpoint(State(state): State<ApiState>, headers: HeaderMap) -> impl IntoResponse {
     let stats = state.block_store.stats().await;
 
-    // Generate Prometheus-compatible metrics using the Metrics module
-    let metrics = state
-        .metrics
-        .to_prometheus(stats.block_count, stats.total_size);
+    let accept = headers
+        .get("accept")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let (content_type, metrics) = if prefers_openmetrics(accept) {
+        (
+            OPENMETRICS_CONTENT_TYPE,
+            state
+                .metrics
+                .to_openmetrics(stats.block_count, stats.total_size),
+        )
+    } else {
+        (
+            PROMETHEUS_CONTENT_TYPE,
+            state
+                .metrics
+                .to_prometheus(stats.block_count, stats.total_size),
+        )
+    };
 
-    (
-        StatusCode::OK,
-        [("content-type", "text/plain; version=0.0.4")],
-        metrics,
-    )
+    (StatusCode::OK, [("content-type", content_type)], metrics)
 }
 
 async fn citadel_status(State(state): State<ApiState>) -> impl IntoResponse {
@@ -907,10 +1183,7 @@ async fn get_block(
         .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
 
     // Get block from store
-    let block = state.block_store.get(&cid).await.map_err(|e| match e {
-        StorageError::BlockNotFound(_) => ApiError::NotFound(cid_str.clone()),
-        _ => ApiError::Internal(format!("Failed to retrieve block: {}", e)),
-    })?;
+    let block = state.block_store.get(&cid).await?;
 
     let total_size = block.size();
 
@@ -1016,47 +1289,32 @@ fn manifest_to_response(cid: &Cid, manifest: &Manifest) -> DataItemResponse {
     }
 }
 
-fn metadata_cid_from_manifest(manifest: &Manifest) -> Option<Cid> {
-    manifest
-        .filename
-        .as_ref()
-        .and_then(|s| s.strip_prefix("metadata:"))
-        .and_then(|s| s.parse().ok())
-}
-
 async fn retrieve_local_cid_data(
     state: &ApiState,
     cid: &Cid,
     cid_str: &str,
 ) -> Result<Vec<u8>, ApiError> {
-    let block = state.block_store.get(cid).await.map_err(|e| match e {
-        StorageError::BlockNotFound(_) => ApiError::NotFound(cid_str.to_string()),
-        _ => ApiError::Internal(format!("Failed to retrieve block: {}", e)),
-    })?;
+    let block = state.block_store.get(cid).await?;
 
     // Manifests need local block reconstruction.
     if cid.codec() == 0xcd01 {
         let manifest = Manifest::from_block(&block)
             .map_err(|e| ApiError::Internal(format!("Failed to decode manifest: {}", e)))?;
 
-        let metadata_cid = metadata_cid_from_manifest(&manifest).ok_or_else(|| {
+        let metadata_cid = manifest.metadata_cid().ok_or_else(|| {
             ApiError::Internal("Manifest missing metadata CID in filename field".to_string())
         })?;
 
-        let tree_metadata_block =
-            state
-                .block_store
-                .get(&metadata_cid)
-                .await
-                .map_err(|e| match e {
-                    StorageError::BlockNotFound(_) => {
-                        ApiError::NotFound(format!("metadata for manifest {} not found", cid_str))
-                    }
-                    _ => ApiError::Internal(format!(
-                        "Failed to fetch tree metadata {}: {}",
-                        metadata_cid, e
-                    )),
-                })?;
+        let tree_metadata_block = state.block_store.get(&metadata_cid).await.map_err(|e| {
+            if e.is_not_found() {
+                ApiError::NotFound(format!("metadata for manifest {} not found", cid_str))
+            } else {
+                ApiError::Internal(format!(
+                    "Failed to fetch tree metadata {}: {}",
+                    metadata_cid, e
+                ))
+            }
+        })?;
 
         let block_cids =
             ArchivistTree::deserialize_block_list(&tree_metadata_block.data).map_err(|e| {
@@ -1071,26 +1329,24 @@ async fn retrieve_local_cid_data(
             )));
         }
 
-        let mut data: Vec<u8> = Vec::with_capacity(manifest.dataset_size as usize);
-        for block_cid in &block_cids {
-            let b = state
-                .block_store
-                .get(block_cid)
-                .await
-                .map_err(|e| match e {
-                    StorageError::BlockNotFound(_) => {
-                        ApiError::NotFound(format!("manifest block {} not found", block_cid))
-                    }
-                    _ => ApiError::Internal(format!("Failed to fetch block {}: {}", block_cid, e)),
-                })?;
+        // Parity blocks of protected manifests follow the original content
+        let mut data: Vec<u8> = Vec::with_capacity(manifest.original_dataset_size() as usize);
+        for block_cid in block_cids.iter().take(manifest.original_blocks_count()) {
+            let b = state.block_store.get(block_cid).await.map_err(|e| {
+                if e.is_not_found() {
+                    ApiError::NotFound(format!("manifest block {} not found", block_cid))
+                } else {
+                    ApiError::Internal(format!("Failed to fetch block {}: {}", block_cid, e))
+                }
+            })?;
             data.extend_from_slice(&b.data);
         }
 
-        if data.len() != manifest.dataset_size as usize {
+        if data.len() != manifest.original_dataset_size() as usize {
             return Err(ApiError::Internal(format!(
                 "Data size mismatch: assembled {} bytes but manifest expects {} bytes",
                 data.len(),
-                manifest.dataset_size
+                manifest.original_dataset_size()
             )));
         }
 
@@ -1100,10 +1356,36 @@ async fn retrieve_local_cid_data(
     }
 }
 
+/// Get a block from the local store, fetching it through the block source
+/// chain when it is missing and a fetcher is configured
+///
+/// The expected size of a fetched block is recorded in the content router so
+/// the wantlist endpoint can report pending bytes.
+async fn get_or_fetch_block(
+    state: &ApiState,
+    cid: &Cid,
+    expected_size: u64,
+) -> Result<Block, StorageError> {
+    match &state.block_fetcher {
+        Some(fetcher) => {
+            let fetch = |cid| {
+                if let Some(router) = &state.content_router {
+                    router.record_size(cid, expected_size);
+                }
+                fetcher.fetch_block(cid)
+            };
+            state.block_store.get_or_fetch(cid, &fetch).await
+        }
+        None => state.block_store.get(cid).await,
+    }
+}
+
 /// Retrieve a byte range from a manifest's data blocks.
 ///
 /// Only reads the blocks that overlap with [range_start, range_end) (end exclusive).
-/// This avoids loading the entire file for Range requests.
+/// This avoids loading the entire file for Range requests. When prefetching
+/// is enabled, the blocks following each one read are requested ahead of
+/// the next range request.
 async fn retrieve_manifest_range(
     state: &ApiState,
     manifest: &Manifest,
@@ -1115,22 +1397,27 @@ async fn retrieve_manifest_range(
     let first_block = range_start / block_size;
     let last_block = (range_end - 1) / block_size; // inclusive
 
+    if let Some(prefetch) = &state.prefetch {
+        if let Err(e) = prefetch.hint_manifest(manifest, first_block).await {
+            debug!("Prefetch hint for tree {} failed: {}", manifest.tree_cid, e);
+        }
+    }
+
     let mut data = Vec::with_capacity(range_end - range_start);
 
     for idx in first_block..=last_block.min(block_cids.len() - 1) {
-        let block_data = state
-            .block_store
-            .get(&block_cids[idx])
+        let block_data = get_or_fetch_block(state, &block_cids[idx], block_size as u64)
             .await
-            .map_err(|e| match e {
-                StorageError::BlockNotFound(_) => {
+            .map_err(|e| {
+                if e.is_not_found() {
                     ApiError::NotFound(format!("manifest block {} not found", block_cids[idx]))
+                } else {
+                    ApiError::from(e)
                 }
-                _ => ApiError::Internal(format!(
-                    "Failed to fetch block {}: {}",
-                    block_cids[idx], e
-                )),
             })?;
+        if let Some(prefetch) = &state.prefetch {
+            prefetch.prefetch_after(&block_cids[idx]).await;
+        }
 
         let block_start_byte = idx * block_size;
         let slice_start = if idx == first_block {
@@ -1156,31 +1443,25 @@ async fn load_manifest_metadata(
     cid: &Cid,
     cid_str: &str,
 ) -> Result<(Manifest, Vec<Cid>), ApiError> {
-    let block = state.block_store.get(cid).await.map_err(|e| match e {
-        StorageError::BlockNotFound(_) => ApiError::NotFound(cid_str.to_string()),
-        _ => ApiError::Internal(format!("Failed to retrieve block: {}", e)),
-    })?;
+    let block = state.block_store.get(cid).await?;
 
     let manifest = Manifest::from_block(&block)
         .map_err(|e| ApiError::Internal(format!("Failed to decode manifest: {}", e)))?;
 
-    let metadata_cid = metadata_cid_from_manifest(&manifest).ok_or_else(|| {
+    let metadata_cid = manifest.metadata_cid().ok_or_else(|| {
         ApiError::Internal("Manifest missing metadata CID in filename field".to_string())
     })?;
 
-    let tree_metadata_block = state
-        .block_store
-        .get(&metadata_cid)
-        .await
-        .map_err(|e| match e {
-            StorageError::BlockNotFound(_) => {
-                ApiError::NotFound(format!("metadata for manifest {} not found", cid_str))
-            }
-            _ => ApiError::Internal(format!(
+    let tree_metadata_block = state.block_store.get(&metadata_cid).await.map_err(|e| {
+        if e.is_not_found() {
+            ApiError::NotFound(format!("metadata for manifest {} not found", cid_str))
+        } else {
+            ApiError::Internal(format!(
                 "Failed to fetch tree metadata {}: {}",
                 metadata_cid, e
-            )),
-        })?;
+            ))
+        }
+    })?;
 
     let block_cids =
         ArchivistTree::deserialize_block_list(&tree_metadata_block.data).map_err(|e| {
@@ -1224,10 +1505,7 @@ async fn archivist_download_local(
 
     // Directory manifests get special treatment
     if folder_manifest::is_directory(&cid) {
-        let block = state.block_store.get(&cid).await.map_err(|e| match e {
-            StorageError::BlockNotFound(_) => ApiError::NotFound(cid_str.clone()),
-            _ => ApiError::Internal(format!("Failed to retrieve block: {}", e)),
-        })?;
+        let block = state.block_store.get(&cid).await?;
 
         let directory = DirectoryManifest::from_block(&block)
             .map_err(|e| ApiError::Internal(format!("Failed to decode directory: {}", e)))?;
@@ -1283,7 +1561,7 @@ async fn archivist_download_local(
     // For manifests, use block-level Range serving to avoid loading the entire file
     if cid.codec() == 0xcd01 {
         let (manifest, block_cids) = load_manifest_metadata(&state, &cid, &cid_str).await?;
-        let total_size = manifest.dataset_size as usize;
+        let total_size = manifest.original_dataset_size() as usize;
 
         let range_header = headers.get("range").and_then(|v| v.to_str().ok());
 
@@ -1317,9 +1595,8 @@ async fn archivist_download_local(
         return build_range_response(&headers, data, "application/octet-stream");
     }
 
-    // Non-manifest block — direct file read with Range support
-    let data = retrieve_local_cid_data(&state, &cid, &cid_str).await?;
-    build_range_response(&headers, data, "application/octet-stream")
+    // Non-manifest block — size from metadata, then read with Range support
+    build_block_response(&state, &headers, &cid).await
 }
 
 /// Archivist delete endpoint (DELETE /api/archivist/v1/data/:cid)
@@ -1334,7 +1611,7 @@ async fn archivist_delete(
     if cid.codec() == 0xcd01 {
         if let Ok(manifest_block) = state.block_store.get(&cid).await {
             if let Ok(manifest) = Manifest::from_block(&manifest_block) {
-                if let Some(metadata_cid) = metadata_cid_from_manifest(&manifest) {
+                if let Some(metadata_cid) = manifest.metadata_cid() {
                     if let Ok(metadata_block) = state.block_store.get(&metadata_cid).await {
                         if let Ok(block_cids) =
                             ArchivistTree::deserialize_block_list(&metadata_block.data)
@@ -1370,6 +1647,85 @@ async fn archivist_exists(
     })))
 }
 
+/// Query parameters for the manifest block listing
+#[derive(Deserialize)]
+struct ListBlocksQuery {
+    /// Only list blocks that are not stored locally
+    #[serde(default)]
+    only_missing: bool,
+}
+
+/// Archivist block listing endpoint (GET /api/archivist/v1/data/:cid/blocks)
+///
+/// Lists the blocks of a manifest's dataset in order, flagging the ones
+/// already stored locally so clients can fetch only what is missing.
+async fn archivist_list_blocks(
+    State(state): State<ApiState>,
+    Path(cid_str): Path<String>,
+    Query(query): Query<ListBlocksQuery>,
+) -> Result<Json<Vec<ManifestBlockEntry>>, ApiError> {
+    let cid: Cid = cid_str
+        .parse()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
+    let (_, block_cids) = load_manifest_metadata(&state, &cid, &cid_str).await?;
+
+    let mut entries = Vec::with_capacity(block_cids.len());
+    for block_cid in &block_cids {
+        let local = state.block_store.has(block_cid).await;
+        if query.only_missing && local {
+            continue;
+        }
+        entries.push(ManifestBlockEntry {
+            cid: cid_to_string(block_cid),
+            local,
+        });
+    }
+    Ok(Json(entries))
+}
+
+/// Archivist manifest endpoint (GET /api/archivist/v1/manifest/:cid)
+///
+/// Decodes a locally stored manifest without touching its data blocks.
+async fn archivist_manifest(
+    State(state): State<ApiState>,
+    Path(cid_str): Path<String>,
+) -> Result<Json<ManifestDetailResponse>, ApiError> {
+    let cid: Cid = cid_str
+        .parse()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
+    let block = state.block_store.get(&cid).await?;
+    let manifest = Manifest::from_block(&block)
+        .map_err(|e| ApiError::Unprocessable(format!("{} is not a manifest: {}", cid_str, e)))?;
+    Ok(Json(ManifestDetailResponse::new(&cid, &manifest)))
+}
+
+/// Archivist manifest info endpoint (GET /api/archivist/v1/manifest/:cid/info)
+///
+/// Reports a dataset's size and estimated download time from its manifest
+/// alone.
+async fn archivist_manifest_info(
+    State(state): State<ApiState>,
+    Path(cid_str): Path<String>,
+) -> Result<Json<ManifestInfoResponse>, ApiError> {
+    let cid: Cid = cid_str
+        .parse()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
+    let block = state.block_store.get(&cid).await?;
+    let manifest = Manifest::from_block(&block)
+        .map_err(|e| ApiError::Unprocessable(format!("{} is not a manifest: {}", cid_str, e)))?;
+
+    let estimated_seconds =
+        Some(manifest.estimated_download_seconds(state.metrics.bytes_per_second_received()))
+            .filter(|seconds| seconds.is_finite());
+    Ok(Json(ManifestInfoResponse {
+        size_bytes: manifest.total_uncompressed_size(),
+        block_count: manifest.blocks_count(),
+        estimated_seconds,
+        filename: manifest.filename.clone(),
+        mimetype: manifest.mimetype.clone(),
+    }))
+}
+
 async fn set_ipfs_cluster_pin_status(
     state: &ApiState,
     cid_str: &str,
@@ -1388,12 +1744,14 @@ async fn ensure_pin_locally(state: &ApiState, cid_str: &str) -> Result<(), Strin
         .parse()
         .map_err(|e| format!("invalid cid {}: {}", cid_str, e))?;
     if state.block_store.has(&cid).await {
+        state.block_store.record_pin(cid, true);
         return Ok(());
     }
     let _ = fetch_cid_from_peers(state, &cid, cid_str)
         .await
         .map_err(|e| format!("{:?}", e))?;
     if state.block_store.has(&cid).await {
+        state.block_store.record_pin(cid, true);
         Ok(())
     } else {
         Err("content fetched but not persisted under requested CID".to_string())
@@ -1460,9 +1818,9 @@ async fn ipfs_cluster_unpin_cid(
     let cid: Cid = cid_str
         .parse()
         .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
-    {
-        let mut pins = state.ipfs_cluster_pins.write().await;
-        pins.remove(&cid_str);
+    let removed = state.ipfs_cluster_pins.write().await.remove(&cid_str);
+    if removed.is_some() {
+        state.block_store.record_pin(cid, false);
     }
     let _ = state.block_store.delete(&cid).await;
     Ok(StatusCode::ACCEPTED)
@@ -1509,10 +1867,7 @@ async fn archivist_download_network_manifest(
         .parse()
         .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
 
-    let block = state.block_store.get(&cid).await.map_err(|e| match e {
-        StorageError::BlockNotFound(_) => ApiError::NotFound(cid_str.clone()),
-        _ => ApiError::Internal(format!("Failed to retrieve manifest: {}", e)),
-    })?;
+    let block = state.block_store.get(&cid).await?;
 
     let manifest = Manifest::from_block(&block)
         .map_err(|e| ApiError::Internal(format!("Failed to decode manifest: {}", e)))?;
@@ -1549,6 +1904,62 @@ async fn connect_not_supported() -> Result<StatusCode, ApiError> {
     ))
 }
 
+#[derive(Deserialize)]
+struct DialPeerRequest {
+    addr: String,
+}
+
+/// Dial peer endpoint (POST /api/archivist/v1/peers/dial)
+///
+/// Queues a dial of the given multiaddr on the running node's swarm.
+async fn archivist_dial_peer(
+    State(state): State<ApiState>,
+    Json(request): Json<DialPeerRequest>,
+) -> Result<StatusCode, ApiError> {
+    let runtime = state
+        .runtime
+        .as_ref()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Node runtime is not available".to_string()))?;
+    let addr: Multiaddr = request
+        .addr
+        .parse()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid multiaddr: {}", e)))?;
+
+    info!("Archivist API: Dialing {}", addr);
+    runtime
+        .dial(addr)
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(e.to_string()))?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Peer ping latency (GET /api/archivist/v1/peers/{peer_id}/latency_ms)
+///
+/// Returns the latest ping round-trip time in milliseconds, or 404 if the
+/// peer is not connected or has not answered a ping yet.
+async fn archivist_peer_latency(
+    State(state): State<ApiState>,
+    Path(peer_id): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let runtime = state
+        .runtime
+        .as_ref()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Node runtime is not available".to_string()))?;
+    let peer_id: PeerId = peer_id
+        .parse()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid peer ID: {}", e)))?;
+
+    let rtt = runtime
+        .peer_latency(peer_id)
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(e.to_string()))?
+        .ok_or_else(|| ApiError::NotFound(format!("No ping latency for peer {}", peer_id)))?;
+    Ok(Json(serde_json::json!({
+        "peer_id": peer_id.to_string(),
+        "latency_ms": rtt.as_millis() as u64,
+    })))
+}
+
 fn marketplace_store(state: &ApiState) -> Result<MarketplaceStore, ApiError> {
     state
         .marketplace
@@ -1652,7 +2063,7 @@ async fn marketplace_slot_size(state: &ApiState, cid: &Cid) -> Result<u64, ApiEr
                 Ok(block.data.len() as u64)
             }
         }
-        Err(StorageError::BlockNotFound(_)) => Err(ApiError::NotFound(cid.to_string())),
+        Err(err) if err.is_not_found() => Err(ApiError::NotFound(cid.to_string())),
         Err(err) => Err(ApiError::Internal(format!(
             "Failed to inspect marketplace request content: {}",
             err
@@ -1733,6 +2144,7 @@ async fn archivist_upload(State(state): State<ApiState>, body: Body) -> Result<S
     let max_inflight_batches = upload_inflight_batches();
     let mut inflight_no_dedupe = futures::stream::FuturesUnordered::new();
     let mut next_batch_start_idx: usize = 0;
+    let mut stored_blocks: usize = 0;
     let mut seen_cids = if dedupe_blocks {
         Some(HashSet::<Cid>::new())
     } else {
@@ -1767,26 +2179,36 @@ async fn archivist_upload(State(state): State<ApiState>, body: Body) -> Result<S
             }
             if raw_batch.len() >= commit_batch_blocks {
                 if dedupe_blocks {
-                    flush_upload_raw_batch(&state, &mut raw_batch, &mut seen_cids, &mut block_cids)
-                        .await?;
+                    flush_upload_raw_batch(
+                        &state,
+                        &mut raw_batch,
+                        &mut seen_cids,
+                        &mut block_cids,
+                        &mut stored_blocks,
+                    )
+                    .await?;
                 } else {
                     let start_idx = next_batch_start_idx;
                     let expected_len = raw_batch.len();
                     next_batch_start_idx = next_batch_start_idx.saturating_add(expected_len);
                     let batch = std::mem::take(&mut raw_batch);
                     let block_store = Arc::clone(&state.block_store);
+                    let metrics = state.metrics.clone();
                     inflight_no_dedupe.push(tokio::spawn(async move {
-                        let cids = process_upload_raw_batch_no_dedupe(block_store, batch).await?;
-                        Ok::<(usize, usize, Vec<Cid>), ApiError>((start_idx, expected_len, cids))
+                        let (cids, stored) =
+                            process_upload_raw_batch_no_dedupe(block_store, metrics, batch).await?;
+                        Ok::<_, ApiError>((start_idx, expected_len, cids, stored))
                     }));
 
                     while inflight_no_dedupe.len() > max_inflight_batches {
                         let completed = inflight_no_dedupe.next().await.ok_or_else(|| {
                             ApiError::Internal("Upload pipeline ended unexpectedly".to_string())
                         })?;
-                        let (start_idx, expected_len, cids) = completed.map_err(|e| {
-                            ApiError::Internal(format!("Upload batch task failed: {}", e))
-                        })??;
+                        let (start_idx, expected_len, cids, stored) =
+                            completed.map_err(|e| {
+                                ApiError::Internal(format!("Upload batch task failed: {}", e))
+                            })??;
+                        stored_blocks += stored;
                         if cids.len() != expected_len {
                             return Err(ApiError::Internal(format!(
                                 "Upload batch CID count mismatch: expected {}, got {}",
@@ -1819,21 +2241,31 @@ async fn archivist_upload(State(state): State<ApiState>, body: Body) -> Result<S
     }
 
     if dedupe_blocks {
-        flush_upload_raw_batch(&state, &mut raw_batch, &mut seen_cids, &mut block_cids).await?;
+        flush_upload_raw_batch(
+            &state,
+            &mut raw_batch,
+            &mut seen_cids,
+            &mut block_cids,
+            &mut stored_blocks,
+        )
+        .await?;
     } else {
         if !raw_batch.is_empty() {
             let start_idx = next_batch_start_idx;
             let expected_len = raw_batch.len();
             let block_store = Arc::clone(&state.block_store);
+            let metrics = state.metrics.clone();
             let batch = std::mem::take(&mut raw_batch);
             inflight_no_dedupe.push(tokio::spawn(async move {
-                let cids = process_upload_raw_batch_no_dedupe(block_store, batch).await?;
-                Ok::<(usize, usize, Vec<Cid>), ApiError>((start_idx, expected_len, cids))
+                let (cids, stored) =
+                    process_upload_raw_batch_no_dedupe(block_store, metrics, batch).await?;
+                Ok::<_, ApiError>((start_idx, expected_len, cids, stored))
             }));
         }
         while let Some(completed) = inflight_no_dedupe.next().await {
-            let (start_idx, expected_len, cids) = completed
+            let (start_idx, expected_len, cids, stored) = completed
                 .map_err(|e| ApiError::Internal(format!("Upload batch task failed: {}", e)))??;
+            stored_blocks += stored;
             if cids.len() != expected_len {
                 return Err(ApiError::Internal(format!(
                     "Upload batch CID count mismatch: expected {}, got {}",
@@ -1868,20 +2300,62 @@ async fn archivist_upload(State(state): State<ApiState>, body: Body) -> Result<S
     }
 
     info!(
-        "Archivist API: Stored {} blocks for dataset ({} bytes)",
+        "Archivist API: Stored {} of {} blocks for dataset ({} bytes)",
+        stored_blocks,
         block_cids.len(),
         dataset_size
     );
-
-    // Step 2: Build Archivist tree from block CIDs
-    let tree = ArchivistTree::new(block_cids)
+    let data_tree_cid = ArchivistTree::new(block_cids.clone())
+        .and_then(|tree| tree.root_cid())
         .map_err(|e| ApiError::Internal(format!("Failed to create tree: {}", e)))?;
 
-    let tree_cid = tree
-        .root_cid()
-        .map_err(|e| ApiError::Internal(format!("Failed to compute tree CID: {}", e)))?;
-
-    info!("Archivist API: Built tree with root CID {}", tree_cid);
+    // A re-upload of stored data gets the manifest built the first time,
+    // as long as that manifest is still stored
+    if stored_blocks == 0 {
+        info!("Archivist API: Upload fully deduplicated against stored blocks");
+        state.metrics.upload_deduplicated();
+        let existing = state
+            .upload_manifests
+            .read()
+            .unwrap()
+            .get(&data_tree_cid)
+            .copied();
+        if let Some(manifest_cid) = existing {
+            if state.block_store.has(&manifest_cid).await {
+                info!(
+                    "Archivist API: Returned existing manifest {} for tree {}",
+                    manifest_cid, data_tree_cid
+                );
+                return Ok(cid_to_string(&manifest_cid));
+            }
+        }
+    }
+
+    // Step 1.5: Erasure-code the dataset, appending parity blocks to the tree
+    let protection = match state.erasure {
+        Some(params) => {
+            let parity_cids = store_parity_blocks(&state, params, &block_cids, block_size).await?;
+            info!(
+                "Archivist API: Stored {} parity blocks (ec_k={}, ec_m={})",
+                parity_cids.len(),
+                params.ec_k,
+                params.ec_m
+            );
+            block_cids.extend(parity_cids);
+            Some((params, data_tree_cid))
+        }
+        None => None,
+    };
+
+    // Step 2: Build Archivist tree from block CIDs
+    let tree = ArchivistTree::new(block_cids)
+        .map_err(|e| ApiError::Internal(format!("Failed to create tree: {}", e)))?;
+
+    let tree_cid = tree
+        .root_cid()
+        .map_err(|e| ApiError::Internal(format!("Failed to compute tree CID: {}", e)))?;
+
+    info!("Archivist API: Built tree with root CID {}", tree_cid);
 
     // Step 2.5: Store the tree's block list as a metadata block
     // We serialize the block CIDs and store them with a predictable key derived from tree_cid
@@ -1910,16 +2384,33 @@ async fn archivist_upload(State(state): State<ApiState>, body: Body) -> Result<S
     // Step 3: Create manifest
     // Store metadata CID in filename field for retrieval during download
     // Format: "metadata:<cid>"
-    let manifest = Manifest::new(
-        tree_cid,
-        block_size as u64,
-        dataset_size,
-        None,                                            // codec (uses default 0xcd02)
-        Some(SHA256_CODEC),                              // hcodec (SHA2-256)
-        None,                                            // version (uses default 1)
-        Some(format!("metadata:{}", tree_metadata_cid)), // filename (stores metadata CID)
-        None,                                            // mimetype
-    );
+    let manifest = match protection {
+        Some((params, original_tree_cid)) => Manifest::new_protected(
+            tree_cid,
+            block_size as u64,
+            (tree.block_cids().len() * block_size) as u64,
+            BLOCK_CODEC,
+            SHA256_CODEC,
+            1,
+            params.ec_k as u32,
+            params.ec_m as u32,
+            original_tree_cid,
+            dataset_size,
+            StrategyType::LinearStrategy,
+            Some(format!("metadata:{}", tree_metadata_cid)),
+            None,
+        ),
+        None => Manifest::new(
+            tree_cid,
+            block_size as u64,
+            dataset_size,
+            None,                                            // codec (uses default 0xcd02)
+            Some(SHA256_CODEC),                              // hcodec (SHA2-256)
+            None,                                            // version (uses default 1)
+            Some(format!("metadata:{}", tree_metadata_cid)), // filename (stores metadata CID)
+            None,                                            // mimetype
+        ),
+    };
 
     info!(
         "Archivist API: Created manifest for tree {} ({} blocks, {} bytes)",
@@ -1941,6 +2432,11 @@ async fn archivist_upload(State(state): State<ApiState>, body: Body) -> Result<S
         .put(manifest_block)
         .await
         .map_err(|e| ApiError::Internal(format!("Failed to store manifest: {}", e)))?;
+    state
+        .upload_manifests
+        .write()
+        .unwrap()
+        .insert(data_tree_cid, manifest_cid);
 
     info!(
         "Archivist API: Uploaded manifest {} (tree: {}, blocks: {}, size: {})",
@@ -1965,6 +2461,37 @@ async fn archivist_upload(State(state): State<ApiState>, body: Body) -> Result<S
     Ok(cid_to_string(&manifest_cid))
 }
 
+/// Encode and store the parity blocks for an uploaded dataset
+///
+/// Stripes are read back from the block store one at a time, so memory use
+/// is bounded by a single stripe. Returns the parity block CIDs in stripe
+/// order.
+async fn store_parity_blocks(
+    state: &ApiState,
+    params: ErasureParams,
+    block_cids: &[Cid],
+    block_size: usize,
+) -> Result<Vec<Cid>, ApiError> {
+    let mut parity_cids = Vec::with_capacity(params.stripes_for(block_cids.len()) * params.ec_m);
+    for stripe_cids in block_cids.chunks(params.ec_k) {
+        let mut stripe = Vec::with_capacity(params.ec_k);
+        for cid in stripe_cids {
+            stripe.push(state.block_store.get(cid).await?.data);
+        }
+
+        let parity = ErasureEncoder::encode_blocks(&stripe, block_size, params)
+            .map_err(|e| ApiError::Internal(format!("Erasure coding failed: {}", e)))?;
+        let blocks = parity
+            .into_iter()
+            .map(Block::new_sha256)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ApiError::Internal(format!("Failed to create parity block: {}", e)))?;
+        parity_cids.extend(blocks.iter().map(|block| block.cid));
+        put_new_blocks(&state.block_store, &state.metrics, blocks).await?;
+    }
+    Ok(parity_cids)
+}
+
 /// Fast path upload endpoint (POST /api/archivist/v1/data/raw)
 /// Stores request body as a single block and returns block CID as plain text.
 async fn archivist_upload_raw_block(
@@ -2099,7 +2626,7 @@ async fn archivist_download(
     // For manifests with Range headers, use block-level Range serving
     if cid.codec() == 0xcd01 {
         if let Ok((manifest, block_cids)) = load_manifest_metadata(&state, &cid, &cid_str).await {
-            let total_size = manifest.dataset_size as usize;
+            let total_size = manifest.original_dataset_size() as usize;
 
             let range_header = headers.get("range").and_then(|v| v.to_str().ok());
             if let Some(range_str) = range_header {
@@ -2122,7 +2649,30 @@ async fn archivist_download(
         }
     }
 
-    // Full content — try local, then peers
+    // Fetch through the configured block source chain
+    if let Some(fetcher) = &state.block_fetcher {
+        let data = if cid.codec() != 0xcd01 {
+            let fetch = |cid| fetcher.fetch_block(cid);
+            let block = state
+                .block_store
+                .get_or_fetch(&cid, &fetch)
+                .await
+                .map_err(|e| {
+                    info!("Archivist API: Fetch of {} failed: {}", cid_str, e);
+                    ApiError::from(e)
+                })?;
+            block.data
+        } else {
+            fetcher.fetch_manifest(cid).await.map_err(|e| {
+                info!("Archivist API: Fetch of manifest {} failed: {}", cid_str, e);
+                ApiError::from(e)
+            })?
+        };
+        return build_range_response(&headers, data, "application/octet-stream");
+    }
+
+    // Full content — try local, then the configured HTTP peers (legacy mode
+    // without a block fetcher)
     let data = match retrieve_local_cid_data(&state, &cid, &cid_str).await {
         Ok(data) => data,
         Err(ApiError::NotFound(_)) => fetch_cid_from_peers(&state, &cid, &cid_str).await?,
@@ -2144,9 +2694,233 @@ async fn archivist_stats(State(state): State<ApiState>) -> impl IntoResponse {
     Json(serde_json::json!({
         "block_count": stats.block_count,
         "total_size": stats.total_size,
+        "version": state.runtime_config.version,
+        "config": *state.runtime_config,
     }))
 }
 
+/// Runtime configuration (GET /api/archivist/v1/config)
+async fn archivist_config(State(state): State<ApiState>) -> Json<RuntimeConfig> {
+    Json((*state.runtime_config).clone())
+}
+
+/// Stored blocks per CID codec (GET /api/archivist/v1/stats/codecs)
+async fn archivist_codec_stats(
+    State(state): State<ApiState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut counts: Vec<(u64, usize)> = state
+        .block_store
+        .count_by_codec()
+        .await?
+        .into_iter()
+        .collect();
+    counts.sort_unstable();
+
+    Ok(Json(serde_json::Value::Array(
+        counts
+            .into_iter()
+            .map(|(codec, count)| {
+                serde_json::json!({
+                    "codec": crate::cid_blake3::codec_name(codec),
+                    "count": count,
+                })
+            })
+            .collect(),
+    )))
+}
+
+/// DHT discovery statistics (GET /api/archivist/v1/discovery/stats)
+async fn archivist_discovery_stats(
+    State(state): State<ApiState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let discovery = state
+        .discovery
+        .as_ref()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Discovery is not running".to_string()))?;
+    let stats = discovery.stats();
+
+    Ok(Json(serde_json::json!({
+        "connected_peers": stats.connected_peers,
+        "local_peer_id": stats.local_peer_id.to_string(),
+        "local_enr": stats.local_enr,
+        "avg_query_rtt_ms": stats.avg_query_rtt_ms,
+        "total_queries": stats.total_queries,
+        "failed_queries": stats.failed_queries,
+        "providers_found": stats.providers_found,
+        "cache_hits": stats.cache_hits,
+    })))
+}
+
+/// Discovery engine statistics (GET /api/archivist/v1/discovery/engine/stats)
+async fn archivist_discovery_engine_stats(
+    State(state): State<ApiState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let engine = state.discovery_engine.as_ref().ok_or_else(|| {
+        ApiError::ServiceUnavailable("Discovery engine is not running".to_string())
+    })?;
+    let stats = engine.stats().await;
+
+    let latencies: serde_json::Map<String, serde_json::Value> = stats
+        .peer_query_latencies
+        .iter()
+        .map(|(peer_id, samples)| (peer_id.to_string(), serde_json::json!(samples)))
+        .collect();
+    Ok(Json(serde_json::json!({
+        "pending_count": stats.pending_count,
+        "in_flight_count": stats.in_flight_count,
+        "max_concurrent": stats.max_concurrent,
+        "min_peers": stats.min_peers,
+        "known_peers": stats.known_peers,
+        "total_queries": stats.total_queries,
+        "total_cache_hits": stats.total_cache_hits,
+        "query_success_rate": stats.query_success_rate,
+        "fastest_peer": stats.fastest_peer().map(|p| p.to_string()),
+        "slowest_peer": stats.slowest_peer().map(|p| p.to_string()),
+        "peer_query_latencies": latencies,
+    })))
+}
+
+/// Network protocol statistics (GET /api/archivist/v1/behaviour/stats)
+async fn archivist_behaviour_stats(
+    State(state): State<ApiState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let runtime = state
+        .runtime
+        .as_ref()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Node runtime is not available".to_string()))?;
+    let stats = runtime
+        .stats()
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(e.to_string()))?;
+
+    Ok(Json(serde_json::json!({
+        "connected_peers": stats.connected_peers,
+        "blockexc_pending_requests": stats.blockexc_pending_requests,
+        "blockexc_connected_peers": stats.blockexc_connected_peers,
+        "discovery_routing_table_size": stats.discovery_routing_table_size,
+        "botg_pending_rollups": stats.botg_pending_rollups,
+    })))
+}
+
+/// Blocks the node is waiting for (GET /api/archivist/v1/wantlist)
+///
+/// `total_pending_bytes` only counts blocks whose size is known to the
+/// content router.
+async fn archivist_wantlist(
+    State(state): State<ApiState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let runtime = state
+        .runtime
+        .as_ref()
+        .ok_or_else(|| ApiError::ServiceUnavailable("Node runtime is not available".to_string()))?;
+    let wants = runtime
+        .wantlist()
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(e.to_string()))?;
+
+    let total_pending_bytes: u64 = state.content_router.as_ref().map_or(0, |router| {
+        wants
+            .iter()
+            .filter_map(|want| router.size_of(&want.cid))
+            .sum()
+    });
+    let entries: Vec<serde_json::Value> = wants
+        .iter()
+        .map(|want| {
+            serde_json::json!({
+                "cid": want.cid.to_string(),
+                "requested_from": want
+                    .requested_from
+                    .iter()
+                    .map(|peer| peer.to_string())
+                    .collect::<Vec<_>>(),
+                "age_ms": want.age.as_millis() as u64,
+                "retry_count": want.retry_count,
+            })
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "entries": entries,
+        "total_pending_bytes": total_pending_bytes,
+    })))
+}
+
+/// Storage event stream (GET /api/archivist/v1/events)
+///
+/// Server-sent events named after the change (`block_stored`,
+/// `block_deleted`, `block_pinned`, `block_unpinned`) with the CID as data.
+async fn archivist_events(
+    State(state): State<ApiState>,
+) -> Sse<impl futures::Stream<Item = Result<Event, std::convert::Infallible>>> {
+    use futures::StreamExt;
+
+    let events = state.block_store.event_stream().map(|event| {
+        let (name, cid) = match event {
+            StorageEvent::BlockStored(cid) => ("block_stored", cid),
+            StorageEvent::BlockDeleted(cid) => ("block_deleted", cid),
+            StorageEvent::BlockPinned(cid) => ("block_pinned", cid),
+            StorageEvent::BlockUnpinned(cid) => ("block_unpinned", cid),
+        };
+        Ok(Event::default().event(name).data(cid.to_string()))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Merkle proof endpoint (GET /api/archivist/v1/proof/:tree_cid/:index)
+///
+/// Rebuilds the Archivist tree for a stored manifest and returns the inclusion
+/// proof for the block at `index`, with the path hex-encoded.
+async fn archivist_proof(
+    State(state): State<ApiState>,
+    Path((cid_str, index)): Path<(String, usize)>,
+) -> Result<Json<ArchivistProof>, ApiError> {
+    let cid: Cid = cid_str
+        .parse()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
+
+    if cid.codec() != 0xcd01 {
+        return Err(ApiError::NotFound(format!("{} is not a manifest", cid_str)));
+    }
+
+    let (_, block_cids) = load_manifest_metadata(&state, &cid, &cid_str).await?;
+    let tree = ArchivistTree::new(block_cids)
+        .map_err(|e| ApiError::Internal(format!("Failed to build tree: {}", e)))?;
+
+    let proof = tree.get_proof(index).map_err(|e| match e {
+        ArchivistTreeError::IndexOutOfBounds { .. } => ApiError::BadRequest(e.to_string()),
+        e => ApiError::Internal(format!("Failed to build proof: {}", e)),
+    })?;
+
+    Ok(Json(proof))
+}
+
+/// Content routing endpoint (GET /api/archivist/v1/routing/:cid)
+///
+/// Returns the peers known (from BlockExc presence responses) to have the block.
+async fn archivist_routing(
+    State(state): State<ApiState>,
+    Path(cid_str): Path<String>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let cid: Cid = cid_str
+        .parse()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid CID: {}", e)))?;
+
+    let peers: Vec<String> = state
+        .content_router
+        .as_ref()
+        .map(|router| router.peers_for(&cid))
+        .unwrap_or_default()
+        .iter()
+        .map(|peer| peer.to_string())
+        .collect();
+
+    Ok(Json(json!({
+        "cid": cid_str,
+        "peers": peers,
+    })))
+}
+
 /// SPR endpoint (GET /api/archivist/v1/spr)
 /// Returns the Signed Peer Record for this node
 async fn spr_endpoint(State(state): State<ApiState>) -> Result<String, ApiError> {
@@ -2164,14 +2938,24 @@ async fn spr_endpoint(State(state): State<ApiState>) -> Result<String, ApiError>
 
     use crate::spr::generate_spr;
 
-    // Use current timestamp as sequence number
-    let seq = SystemTime::now()
+    // Read listen addresses from shared state
+    let addrs_snapshot = state.listen_addrs.read().unwrap().clone();
+
+    let mut cache = state.spr_cache.0.write().unwrap();
+    if let Some(cached) = cache.as_ref().filter(|c| c.is_fresh(&addrs_snapshot)) {
+        debug!(
+            "Returned cached SPR (seq {}) for peer {}",
+            cached.seq, state.peer_id
+        );
+        return Ok(cached.spr.clone());
+    }
+
+    // Use current timestamp as sequence number, kept increasing across refreshes
+    let now_secs = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs();
-
-    // Read listen addresses from shared state
-    let addrs_snapshot = state.listen_addrs.read().unwrap().clone();
+    let seq = next_spr_seq(cache.as_ref().map(|c| c.seq), now_secs);
 
     // Filter listen addresses to only include UDP addresses (Archivist format)
     // Archivist SPRs contain UDP addresses for discovery
@@ -2204,6 +2988,14 @@ async fn spr_endpoint(State(state): State<ApiState>) -> Result<String, ApiError>
         state.peer_id
     );
 
+    *cache = Some(SprCache {
+        spr: spr.clone(),
+        generated_at: std::time::Instant::now(),
+        seq,
+        listen_addrs: addrs_snapshot,
+        invalidated: false,
+    });
+
     Ok(spr)
 }
 
@@ -2258,6 +3050,60 @@ fn build_range_response(
         .map_err(|e| ApiError::Internal(format!("Response build error: {}", e)))
 }
 
+/// Serve a single stored block with Range support.
+///
+/// The block size comes from `BlockStore::get_metadata`, so Content-Length and
+/// Content-Range are known before any block data is read, unsatisfiable ranges
+/// are rejected without touching the block, and partial requests only read the
+/// requested bytes.
+async fn build_block_response(
+    state: &ApiState,
+    headers: &HeaderMap,
+    cid: &Cid,
+) -> Result<Response, ApiError> {
+    let total = state.block_store.get_metadata(cid).await?.size;
+
+    let range_header = headers.get("range").and_then(|v| v.to_str().ok());
+
+    if let Some(range_str) = range_header {
+        let Some((start, end_exclusive)) = parse_range_header(range_str, total) else {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("Content-Range", format!("bytes */{}", total))
+                .body(Body::empty())
+                .map_err(|e| ApiError::Internal(format!("Response build error: {}", e)));
+        };
+
+        let (slice, _) = state
+            .block_store
+            .get_range(cid, start as u64, (end_exclusive - start) as u64)
+            .await?;
+        let end_inclusive = end_exclusive - 1;
+        return Response::builder()
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header("Content-Type", "application/octet-stream")
+            .header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end_inclusive, total),
+            )
+            .header("Content-Length", slice.len().to_string())
+            .header("Accept-Ranges", "bytes")
+            .header("Cache-Control", "public, max-age=31536000, immutable")
+            .body(Body::from(slice))
+            .map_err(|e| ApiError::Internal(format!("Response build error: {}", e)));
+    }
+
+    let block = state.block_store.get(cid).await?;
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/octet-stream")
+        .header("Content-Length", total.to_string())
+        .header("Accept-Ranges", "bytes")
+        .header("Cache-Control", "public, max-age=31536000, immutable")
+        .body(Body::from(block.data))
+        .map_err(|e| ApiError::Internal(format!("Response build error: {}", e)))
+}
+
 // --- Directory manifest types and handlers (Archivist-compatible) ---
 
 #[derive(Deserialize)]
@@ -2335,7 +3181,7 @@ async fn archivist_create_directory(
         } else if let Ok(block) = state.block_store.get(&cid).await {
             if cid.codec() == 0xcd01 {
                 Manifest::from_block(&block)
-                    .map(|m| m.dataset_size)
+                    .map(|m| m.original_dataset_size())
                     .unwrap_or(block.data.len() as u64)
             } else {
                 block.data.len() as u64
@@ -2624,9 +3470,12 @@ fn html_escape(s: &str) -> String {
 enum ApiError {
     BadRequest(String),
     NotFound(String),
+    Conflict(String),
     Unprocessable(String),
     ServiceUnavailable(String),
     NotImplemented(String),
+    BadGateway(String),
+    GatewayTimeout(String),
     Internal(String),
 }
 
@@ -2635,9 +3484,12 @@ impl IntoResponse for ApiError {
         let (status, message) = match self {
             ApiError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
             ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
+            ApiError::Conflict(msg) => (StatusCode::CONFLICT, msg),
             ApiError::Unprocessable(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg),
             ApiError::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
             ApiError::NotImplemented(msg) => (StatusCode::NOT_IMPLEMENTED, msg),
+            ApiError::BadGateway(msg) => (StatusCode::BAD_GATEWAY, msg),
+            ApiError::GatewayTimeout(msg) => (StatusCode::GATEWAY_TIMEOUT, msg),
             ApiError::Internal(msg) => {
                 error!("API error: {}", msg);
                 (StatusCode::INTERNAL_SERVER_ERROR, msg)
@@ -2649,6 +3501,36 @@ impl IntoResponse for ApiError {
     }
 }
 
+impl ApiError {
+    /// Error with `status`, for the statuses storage and fetch errors map to
+    fn from_status(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => ApiError::BadRequest(message),
+            StatusCode::NOT_FOUND => ApiError::NotFound(message),
+            StatusCode::CONFLICT => ApiError::Conflict(message),
+            StatusCode::UNPROCESSABLE_ENTITY => ApiError::Unprocessable(message),
+            StatusCode::SERVICE_UNAVAILABLE => ApiError::ServiceUnavailable(message),
+            StatusCode::BAD_GATEWAY => ApiError::BadGateway(message),
+            StatusCode::GATEWAY_TIMEOUT => ApiError::GatewayTimeout(message),
+            _ => ApiError::Internal(message),
+        }
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(err: StorageError) -> Self {
+        let message = err.to_string();
+        ApiError::from_status(StatusCode::from(err), message)
+    }
+}
+
+impl From<FetchError> for ApiError {
+    fn from(err: FetchError) -> Self {
+        let message = err.to_string();
+        ApiError::from_status(StatusCode::from(err), message)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2660,38 +3542,53 @@ mod tests {
     use axum::http::{Request, StatusCode};
     use tower::util::ServiceExt;
 
-    async fn marketplace_test_app(block_store: Arc<BlockStore>) -> (Router, tempfile::TempDir) {
+    /// Router over a fresh in-memory block store, which is returned for
+    /// direct inspection
+    fn create_test_router() -> (Router, Arc<BlockStore>) {
+        let block_store = Arc::new(BlockStore::new());
+        (
+            create_test_router_with(block_store.clone(), ApiDeps::default()),
+            block_store,
+        )
+    }
+
+    /// Router over `block_store` and `deps` with a test peer ID, keypair and
+    /// listen address
+    fn create_test_router_with(block_store: Arc<BlockStore>, deps: ApiDeps) -> Router {
         use crate::botg::BoTgConfig;
         use libp2p::identity::Keypair;
 
+        create_router_with_runtime(
+            block_store,
+            Metrics::new(),
+            "12D3KooWTest123".to_string(),
+            Arc::new(BoTgProtocol::new(BoTgConfig::default())),
+            Arc::new(Keypair::generate_ed25519()),
+            Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
+                .parse()
+                .unwrap()])),
+            deps,
+        )
+    }
+
+    async fn marketplace_test_app(block_store: Arc<BlockStore>) -> (Router, tempfile::TempDir) {
         let tmp = tempfile::tempdir().unwrap();
-        let metrics = Metrics::new();
-        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
-        let keypair = Arc::new(Keypair::generate_ed25519());
-        let listen_addrs = Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
-            .parse()
-            .unwrap()]));
         let marketplace = MarketplaceStore::open(tmp.path().join("marketplace.json"))
             .await
             .unwrap();
 
-        let app = create_router_with_runtime(
+        let app = create_test_router_with(
             block_store,
-            metrics,
-            "12D3KooWTest123".to_string(),
-            botg,
-            keypair,
-            listen_addrs,
-            None,
-            Some(marketplace),
-            MarketplaceRuntimeInfo {
-                persistence_enabled: true,
-                quota_max_bytes: 4096,
-                eth_account: Some("0xabc".to_string()),
-                ..MarketplaceRuntimeInfo::default()
+            ApiDeps {
+                marketplace: Some(marketplace),
+                marketplace_runtime: MarketplaceRuntimeInfo {
+                    persistence_enabled: true,
+                    quota_max_bytes: 4096,
+                    eth_account: Some("0xabc".to_string()),
+                    ..MarketplaceRuntimeInfo::default()
+                },
+                ..ApiDeps::default()
             },
-            Vec::new(),
-            None,
         );
 
         (app, tmp)
@@ -2699,18 +3596,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_health_check() {
-        use crate::botg::BoTgConfig;
-        use libp2p::identity::Keypair;
-
-        let block_store = Arc::new(BlockStore::new());
-        let metrics = Metrics::new();
-        let peer_id = "12D3KooWTest123".to_string();
-        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
-        let keypair = Arc::new(Keypair::generate_ed25519());
-        let listen_addrs = Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
-            .parse()
-            .unwrap()]));
-        let app = create_router(block_store, metrics, peer_id, botg, keypair, listen_addrs);
+        let (app, block_store) = create_test_router();
+        block_store
+            .put(Block::new(b"health".to_vec()).unwrap())
+            .await
+            .unwrap();
 
         let request = Request::builder()
             .uri("/health")
@@ -2719,28 +3609,17 @@ mod tests {
 
         let response = app.clone().oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health["block_count"], 1);
+        assert!(health["disk_bytes"].as_u64().unwrap() > 0);
     }
 
     #[tokio::test]
     async fn test_store_and_get_block() {
-        use crate::botg::BoTgConfig;
-        use libp2p::identity::Keypair;
-
-        let block_store = Arc::new(BlockStore::new());
-        let metrics = Metrics::new();
-        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
-        let keypair = Arc::new(Keypair::generate_ed25519());
-        let listen_addrs = Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
-            .parse()
-            .unwrap()]));
-        let app = create_router(
-            block_store,
-            metrics,
-            "12D3KooWTest123".to_string(),
-            botg,
-            keypair,
-            listen_addrs,
-        );
+        let (app, _) = create_test_router();
 
         // Store a block
         let test_data = b"Hello, REST API!";
@@ -2782,24 +3661,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_nonexistent_block() {
-        use crate::botg::BoTgConfig;
-        use libp2p::identity::Keypair;
-
-        let block_store = Arc::new(BlockStore::new());
-        let metrics = Metrics::new();
-        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
-        let keypair = Arc::new(Keypair::generate_ed25519());
-        let listen_addrs = Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
-            .parse()
-            .unwrap()]));
-        let app = create_router(
-            block_store,
-            metrics,
-            "12D3KooWTest123".to_string(),
-            botg,
-            keypair,
-            listen_addrs,
-        );
+        let (app, _) = create_test_router();
 
         let request = Request::builder()
             .uri("/api/v1/blocks/bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi")
@@ -2812,59 +3674,1088 @@ mod tests {
 
     #[tokio::test]
     async fn test_large_archivist_upload_over_2mb() {
+        let (app, _) = create_test_router();
+
+        // 3 MiB payload exceeds Axum's default 2 MiB limit.
+        let payload = vec![0xAB; 3 * 1024 * 1024];
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/archivist/v1/data")
+            .header("content-type", "application/octet-stream")
+            .body(Body::from(payload))
+            .unwrap();
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_archivist_upload_deduplicates_stored_blocks() {
         use crate::botg::BoTgConfig;
         use libp2p::identity::Keypair;
 
         let block_store = Arc::new(BlockStore::new());
         let metrics = Metrics::new();
-        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
-        let keypair = Arc::new(Keypair::generate_ed25519());
-        let listen_addrs = Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
-            .parse()
-            .unwrap()]));
         let app = create_router(
-            block_store,
-            metrics,
+            block_store.clone(),
+            metrics.clone(),
             "12D3KooWTest123".to_string(),
-            botg,
-            keypair,
-            listen_addrs,
+            Arc::new(BoTgProtocol::new(BoTgConfig::default())),
+            Arc::new(Keypair::generate_ed25519()),
+            Arc::new(RwLock::new(Vec::new())),
         );
 
-        // 3 MiB payload exceeds Axum's default 2 MiB limit.
-        let payload = vec![0xAB; 3 * 1024 * 1024];
+        // A little over one block, so the file spans a full and a short block
+        let payload: Vec<u8> = (0..upload_block_size() + 17)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let upload = |payload: Vec<u8>| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .method("POST")
+                            .uri("/api/archivist/v1/data")
+                            .header("content-type", "application/octet-stream")
+                            .body(Body::from(payload))
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        let first = upload(payload.clone()).await;
+        assert_eq!(metrics.upload_blocks_deduplicated(), 0);
+        assert_eq!(metrics.uploads_deduplicated(), 0);
+        let stored = block_store.stats().await.block_count;
+
+        let mut events = block_store.subscribe();
+        let puts = block_store.put_stats();
+        let second = upload(payload).await;
+        assert_eq!(second, first);
+        assert_eq!(block_store.put_stats(), puts);
+        assert_eq!(metrics.upload_blocks_deduplicated(), 2);
+        assert_eq!(metrics.uploads_deduplicated(), 1);
+        assert_eq!(block_store.stats().await.block_count, stored);
+        assert!(events.try_recv().is_err(), "second upload wrote blocks");
+    }
+
+    #[tokio::test]
+    async fn test_archivist_proof_endpoint() {
+        let (app, block_store) = create_test_router();
+
+        let payload = vec![0x5A; 3 * upload_block_size() + 17];
         let request = Request::builder()
             .method("POST")
             .uri("/api/archivist/v1/data")
             .header("content-type", "application/octet-stream")
             .body(Body::from(payload))
             .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let manifest_cid: Cid = String::from_utf8(body.to_vec()).unwrap().parse().unwrap();
+
+        let manifest =
+            Manifest::from_block(&block_store.get(&manifest_cid).await.unwrap()).unwrap();
+        let metadata_cid = manifest.metadata_cid().unwrap();
+        let block_cids = ArchivistTree::deserialize_block_list(
+            &block_store.get(&metadata_cid).await.unwrap().data,
+        )
+        .unwrap();
+        let root_cid = ArchivistTree::new(block_cids.clone())
+            .unwrap()
+            .root_cid()
+            .unwrap();
 
+        let request = Request::builder()
+            .uri(format!("/api/archivist/v1/proof/{}/2", manifest_cid))
+            .body(Body::empty())
+            .unwrap();
         let response = app.clone().oneshot(request).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let proof: ArchivistProof = serde_json::from_slice(&body).unwrap();
+        assert_eq!(proof.index, 2);
+        assert_eq!(proof.nleaves, block_cids.len());
+        assert!(
+            crate::archivist_tree::verify_inclusion(&block_cids[2], &root_cid, &proof).unwrap()
+        );
+
+        // Plain blocks are not manifests
+        let request = Request::builder()
+            .uri(format!("/api/archivist/v1/proof/{}/0", block_cids[0]))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_marketplace_endpoints_require_persistence() {
+    async fn test_archivist_manifest_endpoint() {
+        let block_store = Arc::new(BlockStore::new());
+
+        let tree_cid = Block::new(b"tree".to_vec()).unwrap().cid;
+        let original_tree_cid = Block::new(b"original tree".to_vec()).unwrap().cid;
+        let manifest = Manifest::new_protected(
+            tree_cid,
+            65536,
+            6 * 65536,
+            BLOCK_CODEC,
+            SHA256_CODEC,
+            1,
+            4,
+            2,
+            original_tree_cid,
+            4 * 65536 - 100,
+            StrategyType::LinearStrategy,
+            Some("data.bin".to_string()),
+            Some("application/octet-stream".to_string()),
+        );
+        let manifest_block = manifest.to_block().unwrap();
+        let manifest_cid = manifest_block.cid;
+        block_store.put(manifest_block).await.unwrap();
+        let app = create_test_router_with(block_store.clone(), ApiDeps::default());
+
+        let request = Request::builder()
+            .uri(format!("/api/archivist/v1/manifest/{}", manifest_cid))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let detail: ManifestDetailResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(detail.tree_cid, cid_to_string(&tree_cid));
+        assert_eq!(detail.block_size, 65536);
+        assert_eq!(detail.dataset_size, 6 * 65536);
+        assert_eq!(detail.blocks_count, 6);
+        assert_eq!(detail.codec, "0xcd02");
+        assert_eq!(detail.filename.as_deref(), Some("data.bin"));
+        assert_eq!(detail.mimetype.as_deref(), Some("application/octet-stream"));
+        assert!(detail.is_protected);
+        assert!(!detail.is_verifiable);
+        let erasure = detail.erasure.unwrap();
+        assert_eq!((erasure.ec_k, erasure.ec_m), (4, 2));
+        assert_eq!(erasure.original_tree_cid, cid_to_string(&original_tree_cid));
+        assert_eq!(erasure.original_dataset_size, 4 * 65536 - 100);
+        assert_eq!(erasure.protected_strategy, "LinearStrategy");
+
+        // Unknown CID
+        let missing = Block::new(b"missing".to_vec()).unwrap().cid;
+        let request = Request::builder()
+            .uri(format!("/api/archivist/v1/manifest/{}", missing))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Plain blocks are not manifests
+        let raw = Block::new(b"not a manifest".to_vec()).unwrap();
+        let raw_cid = raw.cid;
+        block_store.put(raw).await.unwrap();
+        let request = Request::builder()
+            .uri(format!("/api/archivist/v1/manifest/{}", raw_cid))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_archivist_manifest_info_endpoint() {
         use crate::botg::BoTgConfig;
         use libp2p::identity::Keypair;
 
         let block_store = Arc::new(BlockStore::new());
         let metrics = Metrics::new();
-        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
-        let keypair = Arc::new(Keypair::generate_ed25519());
+        let app = create_router(
+            block_store.clone(),
+            metrics.clone(),
+            "12D3KooWTest123".to_string(),
+            Arc::new(BoTgProtocol::new(BoTgConfig::default())),
+            Arc::new(Keypair::generate_ed25519()),
+            Arc::new(RwLock::new(Vec::new())),
+        );
+
+        let manifest = Manifest::new(
+            Block::new(b"info tree".to_vec()).unwrap().cid,
+            65536,
+            5 * 65536,
+            None,
+            None,
+            None,
+            Some("video.mp4".to_string()),
+            Some("video/mp4".to_string()),
+        );
+        let manifest_block = manifest.to_block().unwrap();
+        let manifest_cid = manifest_block.cid;
+        block_store.put(manifest_block).await.unwrap();
+
+        let get_info = || {
+            let app = app.clone();
+            async move {
+                let request = Request::builder()
+                    .uri(format!("/api/archivist/v1/manifest/{}/info", manifest_cid))
+                    .body(Body::empty())
+                    .unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+
+        // Nothing received yet, so there is no rate to estimate from
+        let info = get_info().await;
+        assert_eq!(info["size_bytes"], 5 * 65536);
+        assert_eq!(info["block_count"], 5);
+        assert!(info["estimated_seconds"].is_null());
+        assert_eq!(info["filename"], "video.mp4");
+        assert_eq!(info["mimetype"], "video/mp4");
+
+        metrics.block_received(65536);
+        let info = get_info().await;
+        let estimated = info["estimated_seconds"].as_f64().unwrap();
+        assert!(estimated >= 5.0, "estimated {}s", estimated);
+    }
+
+    #[tokio::test]
+    async fn test_archivist_list_blocks_endpoint() {
+        let (app, block_store) = create_test_router();
+
+        let block_size = upload_block_size();
+        let payload: Vec<u8> = (0..3 * block_size + 17).map(|i| (i / 7) as u8).collect();
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/archivist/v1/data")
+            .header("content-type", "application/octet-stream")
+            .body(Body::from(payload.clone()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let manifest_cid = String::from_utf8(body.to_vec()).unwrap();
+
+        let list_blocks = |query: &str| {
+            let app = app.clone();
+            let uri = format!("/api/archivist/v1/data/{}/blocks{}", manifest_cid, query);
+            async move {
+                let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<Vec<ManifestBlockEntry>>(&body).unwrap()
+            }
+        };
+
+        let entries = list_blocks("").await;
+        assert_eq!(entries.len(), 4);
+        assert!(entries.iter().all(|entry| entry.local));
+        assert!(list_blocks("?only_missing=true").await.is_empty());
+
+        // Drop one block and it is reported as missing
+        let missing_cid: Cid = entries[1].cid.parse().unwrap();
+        let missing_block = block_store.get(&missing_cid).await.unwrap();
+        block_store.delete(&missing_cid).await.unwrap();
+        let entries = list_blocks("").await;
+        assert!(!entries[1].local);
+        assert_eq!(entries.iter().filter(|entry| entry.local).count(), 3);
+        let missing = list_blocks("?only_missing=true").await;
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].cid, cid_to_string(&missing_cid));
+        assert!(!missing[0].local);
+
+        // Storing it again marks it local
+        block_store.put(missing_block).await.unwrap();
+        assert!(list_blocks("").await[1].local);
+        assert!(list_blocks("?only_missing=true").await.is_empty());
+
+        let request = Request::builder()
+            .uri(format!(
+                "/api/archivist/v1/data/{}/blocks",
+                Block::new(b"missing".to_vec()).unwrap().cid
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_archivist_events_streams_stored_blocks() {
+        use futures::StreamExt;
+
+        let (app, block_store) = create_test_router();
+
+        let request = Request::builder()
+            .uri("/api/archivist/v1/events")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let block = Block::new(b"event stream block".to_vec()).unwrap();
+        block_store.put(block.clone()).await.unwrap();
+
+        let mut body = response.into_body().into_data_stream();
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.next())
+            .await
+            .expect("no event received")
+            .unwrap()
+            .unwrap();
+        let text = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(text.contains("event: block_stored"));
+        assert!(text.contains(&format!("data: {}", block.cid)));
+    }
+
+    #[tokio::test]
+    async fn test_archivist_discovery_engine_stats_endpoint() {
+        use crate::discovery::Discovery;
+        use crate::discovery_engine::DiscoveryEngine;
+
+        let router = |discovery_engine| {
+            create_test_router_with(
+                Arc::new(BlockStore::new()),
+                ApiDeps {
+                    discovery_engine,
+                    ..ApiDeps::default()
+                },
+            )
+        };
+        let request = || {
+            Request::builder()
+                .uri("/api/archivist/v1/discovery/engine/stats")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router(None).oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let (discovery, _net) = Discovery::new_mock();
+        let (_engine, _tx, handle) = DiscoveryEngine::new(Arc::new(discovery));
+
+        let response = router(Some(handle)).oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats["total_queries"], 0);
+        assert_eq!(stats["query_success_rate"], 0.0);
+        assert!(stats["fastest_peer"].is_null());
+        assert!(stats["slowest_peer"].is_null());
+        assert_eq!(stats["peer_query_latencies"], serde_json::json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_archivist_discovery_stats_endpoint() {
+        use crate::discovery::Discovery;
+
+        let router = |discovery| {
+            create_test_router_with(
+                Arc::new(BlockStore::new()),
+                ApiDeps {
+                    discovery,
+                    ..ApiDeps::default()
+                },
+            )
+        };
+        let request = || {
+            Request::builder()
+                .uri("/api/archivist/v1/discovery/stats")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router(None).oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let (discovery, _net) = Discovery::new_mock();
+        let discovery = Arc::new(discovery);
+        let cid = Block::new(b"discovery stats".to_vec()).unwrap().cid;
+        discovery.provide(&cid).await.unwrap();
+        discovery.find(&cid).await.unwrap();
+
+        let response = router(Some(discovery.clone()))
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            stats["local_peer_id"],
+            discovery.local_peer_id().to_string()
+        );
+        assert_eq!(stats["total_queries"], 1);
+        assert_eq!(stats["failed_queries"], 0);
+        assert_eq!(stats["cache_hits"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_network_download_caches_fetched_block() {
+        use crate::blockexc::BlockExcClient;
+        use crate::fetcher::mock::spawn_mock_swarm;
+
+        let remote = Arc::new(BlockStore::new());
+        let local = Arc::new(BlockStore::new());
+        let block = Block::new(b"fetched over the network".to_vec()).unwrap();
+        remote.put(block.clone()).await.unwrap();
+
+        let (tx, requested) = spawn_mock_swarm(remote, HashMap::new());
+        let client = Arc::new(BlockExcClient::new(local.clone(), Metrics::new(), 3, tx));
+        let fetcher = Arc::new(BlockFetcher::new(local.clone(), client));
+        let app = create_test_router_with(
+            local.clone(),
+            ApiDeps {
+                block_fetcher: Some(fetcher),
+                ..ApiDeps::default()
+            },
+        );
+
+        for _ in 0..2 {
+            let request = Request::builder()
+                .uri(format!(
+                    "/api/archivist/v1/data/{}/network/stream",
+                    block.cid
+                ))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], &block.data[..]);
+        }
+        // The second download is served from the local copy
+        assert_eq!(requested.lock().unwrap().as_slice(), &[block.cid]);
+        assert!(local.has(&block.cid).await);
+    }
+
+    #[tokio::test]
+    async fn test_range_download_prefetches_following_blocks() {
+        use crate::blockexc::BlockExcClient;
+        use crate::fetcher::mock::{build_dataset, spawn_mock_swarm};
+        use crate::prefetch::DEFAULT_LOOKAHEAD;
+
+        // The local store holds the manifest, its metadata and block 0
+        let remote = Arc::new(BlockStore::new());
+        let local = Arc::new(BlockStore::new());
+        let (manifest_cid, data, block_cids) = build_dataset(&remote, 8).await;
+        let manifest = Manifest::from_block(&remote.get(&manifest_cid).await.unwrap()).unwrap();
+        let metadata_cid = manifest.metadata_cid().unwrap();
+        for cid in [manifest_cid, metadata_cid, block_cids[0]] {
+            local.put(remote.get(&cid).await.unwrap()).await.unwrap();
+        }
+
+        let (tx, requested) = spawn_mock_swarm(remote, HashMap::new());
+        let client = Arc::new(BlockExcClient::new(local.clone(), Metrics::new(), 3, tx));
+        let prefetch = Arc::new(PrefetchEngine::new(
+            local.clone(),
+            client,
+            DEFAULT_LOOKAHEAD,
+        ));
+        let app = create_test_router_with(
+            local.clone(),
+            ApiDeps {
+                prefetch: Some(prefetch.clone()),
+                ..ApiDeps::default()
+            },
+        );
+
+        let request = Request::builder()
+            .uri(format!("/api/archivist/v1/data/{}", manifest_cid))
+            .header("range", "bytes=0-99")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], &data[..100]);
+
+        // Reading block 0 requests the next DEFAULT_LOOKAHEAD blocks
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while !local
+                .has_many(&block_cids[1..=DEFAULT_LOOKAHEAD])
+                .await
+                .into_iter()
+                .all(|present| present)
+            {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("following blocks not prefetched");
+        let mut seen = requested.lock().unwrap().clone();
+        seen.sort();
+        let mut expected = block_cids[1..=DEFAULT_LOOKAHEAD].to_vec();
+        expected.sort();
+        assert_eq!(seen, expected);
+        assert_eq!(prefetch.hinted_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_archivist_upload_with_erasure_coding() {
+        use crate::erasure::ErasureParams;
+
+        let block_store = Arc::new(BlockStore::new());
+        let app = create_test_router_with(
+            block_store.clone(),
+            ApiDeps {
+                erasure: Some(ErasureParams::new(2, 1).unwrap()),
+                ..ApiDeps::default()
+            },
+        );
+
+        let block_size = upload_block_size();
+        let payload: Vec<u8> = (0..3 * block_size + 17).map(|i| (i % 251) as u8).collect();
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/archivist/v1/data")
+            .header("content-type", "application/octet-stream")
+            .body(Body::from(payload.clone()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let manifest_cid: Cid = String::from_utf8(body.to_vec()).unwrap().parse().unwrap();
+
+        // 4 data blocks in 2 stripes of 2, plus 1 parity block per stripe
+        let manifest =
+            Manifest::from_block(&block_store.get(&manifest_cid).await.unwrap()).unwrap();
+        let erasure = manifest.erasure.as_ref().unwrap();
+        assert_eq!((erasure.ec_k, erasure.ec_m), (2, 1));
+        assert_eq!(erasure.original_dataset_size, payload.len() as u64);
+        assert_eq!(manifest.blocks_count(), 6);
+        let block_cids = ArchivistTree::deserialize_block_list(
+            &block_store
+                .get(&manifest.metadata_cid().unwrap())
+                .await
+                .unwrap()
+                .data,
+        )
+        .unwrap();
+        assert_eq!(block_cids.len(), 6);
+
+        // Downloads serve only the original content
+        let request = Request::builder()
+            .uri(format!(
+                "/api/archivist/v1/data/{}/network/stream",
+                manifest_cid
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.as_ref(), payload.as_slice());
+
+        // The short final block can be recovered from its stripe's parity
+        let block = |i: usize| {
+            let store = block_store.clone();
+            let cid = block_cids[i];
+            async move { store.get(&cid).await.unwrap().data }
+        };
+        let shards = vec![Some(block(2).await), None, Some(block(5).await)];
+        let recovered = ErasureEncoder::decode(shards, 2, 1).unwrap();
+        assert_eq!(&recovered[1][..17], &payload[3 * block_size..]);
+    }
+
+    #[tokio::test]
+    async fn test_spr_endpoint_caches_until_listen_addrs_change() {
+        use crate::botg::BoTgConfig;
+        use libp2p::identity::Keypair;
+
         let listen_addrs = Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
             .parse()
             .unwrap()]));
         let app = create_router(
-            block_store,
-            metrics,
+            Arc::new(BlockStore::new()),
+            Metrics::new(),
             "12D3KooWTest123".to_string(),
-            botg,
-            keypair,
-            listen_addrs,
+            Arc::new(BoTgProtocol::new(BoTgConfig::default())),
+            Arc::new(Keypair::generate_ed25519()),
+            listen_addrs.clone(),
+        );
+
+        let get_spr = || async {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/api/archivist/v1/spr")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        // Cache hit: the same SPR is served without regenerating
+        let first = get_spr().await;
+        assert_eq!(get_spr().await, first);
+
+        // A new listen address invalidates the cache
+        listen_addrs
+            .write()
+            .unwrap()
+            .push("/ip4/127.0.0.1/tcp/8071".parse().unwrap());
+        let refreshed = get_spr().await;
+        assert_ne!(refreshed, first);
+        let records = crate::spr::parse_spr_records(&refreshed).unwrap();
+        assert_eq!(records[0].addrs.len(), 2);
+        assert_eq!(get_spr().await, refreshed);
+    }
+
+    #[test]
+    fn test_spr_cache_invalidation_and_seq() {
+        let addrs: Vec<Multiaddr> = vec!["/ip4/127.0.0.1/udp/8070".parse().unwrap()];
+        let handle = SprCacheHandle::default();
+        handle.invalidate();
+        *handle.0.write().unwrap() = Some(SprCache {
+            spr: "spr:test".to_string(),
+            generated_at: std::time::Instant::now(),
+            seq: 100,
+            listen_addrs: addrs.clone(),
+            invalidated: false,
+        });
+        let cache = || handle.0.read().unwrap().clone().unwrap();
+        assert!(cache().is_fresh(&addrs));
+        assert!(!cache().is_fresh(&[]));
+
+        handle.clone().invalidate();
+        assert!(!cache().is_fresh(&addrs));
+
+        // Sequence numbers never go backwards, even if the clock does
+        assert_eq!(next_spr_seq(None, 50), 50);
+        assert_eq!(next_spr_seq(Some(cache().seq), 50), 101);
+        assert_eq!(next_spr_seq(Some(cache().seq), 200), 200);
+    }
+
+    #[tokio::test]
+    async fn test_dial_peer_endpoint() {
+        use crate::runtime::RuntimeCommand;
+
+        let (runtime, mut command_rx) = RuntimeHandle::channel();
+        let app = create_test_router_with(
+            Arc::new(BlockStore::new()),
+            ApiDeps {
+                runtime: Some(runtime),
+                ..ApiDeps::default()
+            },
+        );
+
+        let dial = |addr: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/archivist/v1/peers/dial")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({ "addr": addr }).to_string()))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(dial("/ip4/10.0.0.1/tcp/8070"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let expected: Multiaddr = "/ip4/10.0.0.1/tcp/8070".parse().unwrap();
+        assert!(matches!(
+            command_rx.recv().await.unwrap(),
+            RuntimeCommand::Dial(addr) if addr == expected
+        ));
+
+        let response = app.clone().oneshot(dial("not a multiaddr")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_codec_stats_endpoint() {
+        let (app, block_store) = create_test_router();
+        let manifest = Manifest::new(
+            crate::cid_blake3::blake3_cid(b"tree").unwrap(),
+            1024,
+            2048,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        block_store.put(manifest.to_block().unwrap()).await.unwrap();
+        block_store.put_data(b"first".to_vec()).await.unwrap();
+        block_store.put_data(b"second".to_vec()).await.unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/archivist/v1/stats/codecs")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "codec": "archivist-manifest", "count": 1 },
+                { "codec": "archivist-block", "count": 2 },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_config_and_stats_report_runtime_config() {
+        let (app, _block_store) = create_test_router();
+        let manifest: toml::Value = toml::from_str(include_str!("../Cargo.toml")).unwrap();
+        let version = manifest["package"]["version"].as_str().unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/archivist/v1/config")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let config: RuntimeConfig = serde_json::from_slice(&body).unwrap();
+        assert_eq!(config.version, version);
+        assert_eq!(config, RuntimeConfig::default());
+        assert_eq!(config.node_mode, "altruistic");
+        assert_eq!(config.max_block_size, upload_block_size() as u64);
+        assert!(config.block_store_path.ends_with("blocks"));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/archivist/v1/stats")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["block_count"], 0);
+        assert_eq!(json["version"], version);
+        assert_eq!(json["config"], serde_json::to_value(&config).unwrap());
+
+        // The config endpoint is read-only
+        let (app, _block_store) = create_test_router();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/archivist/v1/config")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_negotiates_openmetrics() {
+        use crate::botg::BoTgConfig;
+        use libp2p::identity::Keypair;
+
+        let app = create_router(
+            Arc::new(BlockStore::new()),
+            Metrics::new(),
+            "12D3KooWTest123".to_string(),
+            Arc::new(BoTgProtocol::new(BoTgConfig::default())),
+            Arc::new(Keypair::generate_ed25519()),
+            Arc::new(RwLock::new(Vec::new())),
+        );
+        let scrape = |accept: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::builder()
+                            .uri("/metrics")
+                            .header("accept", accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let content_type = response.headers()["content-type"]
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (content_type, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (content_type, body) =
+            scrape("application/openmetrics-text;version=1.0.0,text/plain;q=0.5").await;
+        assert_eq!(content_type, OPENMETRICS_CONTENT_TYPE);
+        assert!(body.ends_with("# EOF\n"));
+
+        let (content_type, body) = scrape("*/*").await;
+        assert_eq!(content_type, PROMETHEUS_CONTENT_TYPE);
+        assert!(!body.contains("# EOF"));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_spares_health_and_metrics() {
+        let app = create_test_router_with(
+            Arc::new(BlockStore::new()),
+            ApiDeps {
+                runtime_config: Arc::new(RuntimeConfig {
+                    api_rate_limit: Some(RateLimitConfig {
+                        requests_per_second: 1,
+                        burst_size: 1,
+                        trusted_proxies: Vec::new(),
+                    }),
+                    ..RuntimeConfig::default()
+                }),
+                ..ApiDeps::default()
+            },
+        );
+        let status = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+
+        assert_eq!(status("/api/archivist/v1/stats").await, StatusCode::OK);
+        assert_eq!(
+            status("/api/archivist/v1/stats").await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        for _ in 0..3 {
+            assert_eq!(status("/health").await, StatusCode::OK);
+            assert_eq!(status("/metrics").await, StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_behaviour_stats_endpoint() {
+        use crate::p2p::BehaviourStats;
+        use crate::runtime::RuntimeCommand;
+
+        let router = |runtime| {
+            create_test_router_with(
+                Arc::new(BlockStore::new()),
+                ApiDeps {
+                    runtime,
+                    ..ApiDeps::default()
+                },
+            )
+        };
+        let request = || {
+            Request::builder()
+                .uri("/api/archivist/v1/behaviour/stats")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router(None).oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Answer the stats query the way the event loop would
+        let (runtime, mut command_rx) = RuntimeHandle::channel();
+        tokio::spawn(async move {
+            if let Some(RuntimeCommand::Stats(reply)) = command_rx.recv().await {
+                let _ = reply.send(BehaviourStats {
+                    connected_peers: 3,
+                    blockexc_pending_requests: 2,
+                    blockexc_connected_peers: 1,
+                    discovery_routing_table_size: 12,
+                    botg_pending_rollups: 0,
+                });
+            }
+        });
+
+        let response = router(Some(runtime)).oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["connected_peers"], 3);
+        assert_eq!(json["blockexc_pending_requests"], 2);
+        assert_eq!(json["blockexc_connected_peers"], 1);
+        assert_eq!(json["discovery_routing_table_size"], 12);
+        assert_eq!(json["botg_pending_rollups"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_wantlist_endpoint() {
+        use crate::blockexc::WantEntry;
+        use crate::runtime::RuntimeCommand;
+
+        let sized = crate::cid_blake3::blake3_cid(b"sized want").unwrap();
+        let unsized_cid = crate::cid_blake3::blake3_cid(b"unsized want").unwrap();
+        let peer = libp2p::PeerId::random();
+        let content_router = Arc::new(ContentRouter::new());
+        content_router.record_size(sized, 65536);
+
+        // Answer the wantlist query the way the event loop would
+        let (runtime, mut command_rx) = RuntimeHandle::channel();
+        tokio::spawn(async move {
+            if let Some(RuntimeCommand::Wantlist(reply)) = command_rx.recv().await {
+                let _ = reply.send(vec![
+                    WantEntry {
+                        cid: sized,
+                        requested_from: vec![peer],
+                        age: std::time::Duration::from_millis(1500),
+                        retry_count: 2,
+                    },
+                    WantEntry {
+                        cid: unsized_cid,
+                        requested_from: Vec::new(),
+                        age: std::time::Duration::from_millis(10),
+                        retry_count: 0,
+                    },
+                ]);
+            }
+        });
+        let app = create_test_router_with(
+            Arc::new(BlockStore::new()),
+            ApiDeps {
+                content_router: Some(content_router),
+                runtime: Some(runtime),
+                ..ApiDeps::default()
+            },
         );
 
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/archivist/v1/wantlist")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["total_pending_bytes"], 65536);
+        let entries = json["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["cid"], sized.to_string());
+        assert_eq!(entries[0]["requested_from"][0], peer.to_string());
+        assert_eq!(entries[0]["age_ms"], 1500);
+        assert_eq!(entries[0]["retry_count"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_peer_latency_endpoint() {
+        use crate::runtime::RuntimeCommand;
+
+        let pinged = libp2p::PeerId::random();
+        let (runtime, mut command_rx) = RuntimeHandle::channel();
+        tokio::spawn(async move {
+            while let Some(command) = command_rx.recv().await {
+                if let RuntimeCommand::PeerLatency(peer, reply) = command {
+                    let rtt = (peer == pinged).then(|| std::time::Duration::from_millis(12));
+                    let _ = reply.send(rtt);
+                }
+            }
+        });
+        let app = create_test_router_with(
+            Arc::new(BlockStore::new()),
+            ApiDeps {
+                runtime: Some(runtime),
+                ..ApiDeps::default()
+            },
+        );
+        let get = |uri: String| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = get(format!("/api/archivist/v1/peers/{}/latency_ms", pinged))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["peer_id"], pinged.to_string());
+        assert_eq!(json["latency_ms"], 12);
+
+        let unknown = libp2p::PeerId::random();
+        let response = get(format!("/api/archivist/v1/peers/{}/latency_ms", unknown))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = get("/api/archivist/v1/peers/not-a-peer/latency_ms".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_marketplace_endpoints_require_persistence() {
+        let (app, _) = create_test_router();
+
         let request = Request::builder()
             .uri("/api/archivist/v1/sales/availability")
             .body(Body::empty())
@@ -2954,24 +4845,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_citadel_status_disabled_when_mode_off() {
-        use crate::botg::BoTgConfig;
-        use libp2p::identity::Keypair;
-
-        let block_store = Arc::new(BlockStore::new());
-        let metrics = Metrics::new();
-        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
-        let keypair = Arc::new(Keypair::generate_ed25519());
-        let listen_addrs = Arc::new(RwLock::new(vec!["/ip4/127.0.0.1/tcp/8070"
-            .parse()
-            .unwrap()]));
-        let app = create_router(
-            block_store,
-            metrics,
-            "12D3KooWTest123".to_string(),
-            botg,
-            keypair,
-            listen_addrs,
-        );
+        let (app, _) = create_test_router();
 
         let request = Request::builder()
             .uri("/api/citadel/v1/status")
@@ -1,36 +1,117 @@
 //! BlockExc protocol implementation
 //!
 //! Implements Archivist's custom BlockExc protocol for block exchange.
-//! Protocol ID: /archivist/blockexc/1.0.0
-
+//! Protocol IDs: /archivist/blockexc/1.0.0 and /archivist/blockexc/2.0.0
+//!
+//! Version 2 keeps the v1 messages but frames them with a session ID that
+//! responses echo back, answers HAVE wants with CID-only presences (no
+//! price), and runs its streams inside the connection handler instead of
+//! spawning a task per stream. Nodes that prefer v2 still speak v1 to peers
+//! that don't support it.
+
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
 use futures::AsyncReadExt;
 use futures::AsyncWriteExt;
-use libp2p::core::upgrade::ReadyUpgrade;
+use futures::FutureExt;
+use futures::StreamExt;
+use libp2p::core::upgrade::{ReadyUpgrade, SelectUpgrade};
 use libp2p::swarm::{
     handler::{ConnectionEvent, FullyNegotiatedInbound, FullyNegotiatedOutbound},
     ConnectionHandler, ConnectionHandlerEvent, StreamProtocol, SubstreamProtocol,
 };
-use libp2p::PeerId;
+use libp2p::{PeerId, Stream};
 use std::io;
 use std::sync::Arc;
 use tracing::{debug, info, warn};
 
 use crate::archivist_tree::ArchivistTree;
-use crate::manifest::Manifest;
+use crate::content_router::ContentRouter;
+use crate::manifest::{Manifest, MANIFEST_CODEC};
 use crate::messages::{
     ArchivistProof, BlockDelivery, BlockPresence, BlockPresenceType, ProofNode, WantType,
 };
 use crate::metrics::Metrics;
-use crate::storage::BlockStore;
+use crate::pending_blocks::PendingBlocksManager;
+use crate::storage::{BlockStore, StorageEvent};
+use crate::traffic::TrafficLimiter;
 
 pub const PROTOCOL_ID: &str = "/archivist/blockexc/1.0.0";
 
-/// Read a length-prefixed message from a stream
-async fn read_length_prefixed<R: AsyncReadExt + Unpin>(
-    reader: &mut R,
-    max_size: usize,
-) -> io::Result<Vec<u8>> {
-    // Read the length prefix (unsigned varint)
+/// Protocol ID of the session-based BlockExc version 2
+pub const PROTOCOL_ID_V2: &str = "/archivist/blockexc/2.0.0";
+
+/// Default time [`BlockExcClient::request_block`] waits for a block
+pub const DEFAULT_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Framing version of v2 frames, whose payload starts with a big-endian
+/// 8-byte session ID
+pub const FRAME_VERSION_V2: u8 = 0x02;
+
+/// Default largest BlockExc message accepted from a peer
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Default largest number of wantlist entries accepted in one message
+pub const DEFAULT_MAX_WANTLIST_ENTRIES: usize = 1024;
+
+/// Default largest number of block requests waiting for a response
+pub const DEFAULT_MAX_PENDING_REQUESTS: usize = 4096;
+
+/// Longest wait for a peer to take the cancels sent while a connection closes
+const CANCEL_SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Protocol version and limits of BlockExc streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockExcConfig {
+    /// Largest message payload accepted, in bytes
+    pub max_message_bytes: usize,
+    /// Largest number of wantlist entries handled per message
+    pub max_wantlist_entries: usize,
+    /// Largest number of outstanding block requests; new requests beyond it
+    /// are dropped
+    pub max_pending_requests: usize,
+    /// Offer v2 ahead of v1; when false only v1 is offered
+    pub enable_v2: bool,
+}
+
+impl Default for BlockExcConfig {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            max_wantlist_entries: DEFAULT_MAX_WANTLIST_ENTRIES,
+            max_pending_requests: DEFAULT_MAX_PENDING_REQUESTS,
+            enable_v2: true,
+        }
+    }
+}
+
+/// BlockExc protocol version negotiated on a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolVersion {
+    /// `/archivist/blockexc/1.0.0`, as spoken by Archivist
+    V1,
+    /// `/archivist/blockexc/2.0.0`, with session IDs and CID-only presences
+    V2,
+}
+
+impl ProtocolVersion {
+    /// libp2p protocol ID of this version
+    pub fn protocol_id(self) -> &'static str {
+        match self {
+            ProtocolVersion::V1 => PROTOCOL_ID,
+            ProtocolVersion::V2 => PROTOCOL_ID_V2,
+        }
+    }
+}
+
+/// Upgrade negotiating BlockExc: v1 alone, or v2 with a fallback to v1
+type BlockExcUpgrade = either::Either<
+    ReadyUpgrade<StreamProtocol>,
+    SelectUpgrade<ReadyUpgrade<StreamProtocol>, ReadyUpgrade<StreamProtocol>>,
+>;
+
+/// Read an unsigned varint length prefix
+async fn read_length<R: AsyncReadExt + Unpin>(reader: &mut R) -> io::Result<u64> {
     let mut length = 0u64;
     let mut shift = 0;
     loop {
@@ -42,7 +123,7 @@ async fn read_length_prefixed<R: AsyncReadExt + Unpin>(
         shift += 7;
 
         if byte & 0x80 == 0 {
-            break;
+            return Ok(length);
         }
 
         if shift >= 64 {
@@ -52,38 +133,261 @@ async fn read_length_prefixed<R: AsyncReadExt + Unpin>(
             ));
         }
     }
+}
+
+/// Write `length` as an unsigned varint
+async fn write_length<W: AsyncWriteExt + Unpin>(writer: &mut W, length: u64) -> io::Result<()> {
+    let mut length = length;
+    while length >= 0x80 {
+        writer.write_all(&[(length as u8) | 0x80]).await?;
+        length >>= 7;
+    }
+    writer.write_all(&[length as u8]).await
+}
 
+fn check_frame_size(length: u64, max_size: usize) -> io::Result<()> {
     if length > max_size as u64 {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
             format!("message too large: {} > {}", length, max_size),
         ));
     }
+    Ok(())
+}
+
+/// Read a length-prefixed message from a stream
+///
+/// The frame is `varint(len) | data`, as sent by Archivist on
+/// `/archivist/blockexc/1.0.0`. Empty messages are rejected as malformed.
+pub(crate) async fn read_length_prefixed<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+    max_size: usize,
+) -> io::Result<Vec<u8>> {
+    let length = read_length(reader).await?;
+    check_frame_size(length, max_size)?;
+    if length == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "empty message"));
+    }
 
     let mut data = vec![0u8; length as usize];
     reader.read_exact(&mut data).await?;
     Ok(data)
 }
 
-/// Write a length-prefixed message to a stream
-async fn write_length_prefixed<W: AsyncWriteExt + Unpin>(
+/// Write a length-prefixed message to a stream as `varint(len) | data`
+pub(crate) async fn write_length_prefixed<W: AsyncWriteExt + Unpin>(
     writer: &mut W,
     data: &[u8],
 ) -> io::Result<()> {
-    // Write length as unsigned varint
-    let mut length = data.len() as u64;
-    while length >= 0x80 {
-        writer.write_all(&[(length as u8) | 0x80]).await?;
-        length >>= 7;
+    write_length(writer, data.len() as u64).await?;
+    writer.write_all(data).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read a versioned frame from a stream, returning its version byte and
+/// payload
+///
+/// The frame is `varint(len) | version | data`, where `len` counts the
+/// version byte. Only v2 streams use it; v1 frames carry no version.
+pub(crate) async fn read_versioned_frame<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+    max_size: usize,
+) -> io::Result<(u8, Vec<u8>)> {
+    let length = read_length(reader).await?;
+    if length == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame is missing its version byte",
+        ));
     }
-    writer.write_all(&[length as u8]).await?;
+    let length = length - 1;
+    check_frame_size(length, max_size)?;
+
+    let mut version = [0u8; 1];
+    reader.read_exact(&mut version).await?;
 
-    // Write data
+    let mut data = vec![0u8; length as usize];
+    reader.read_exact(&mut data).await?;
+    Ok((version[0], data))
+}
+
+async fn write_versioned_frame<W: AsyncWriteExt + Unpin>(
+    writer: &mut W,
+    version: u8,
+    data: &[u8],
+) -> io::Result<()> {
+    write_length(writer, data.len() as u64 + 1).await?;
+    writer.write_all(&[version]).await?;
     writer.write_all(data).await?;
     writer.flush().await?;
     Ok(())
 }
 
+/// Read a message framed for `version`, returning its session ID and payload
+///
+/// v1 frames carry no session and report session 0.
+pub(crate) async fn read_frame<R: AsyncReadExt + Unpin>(
+    reader: &mut R,
+    version: ProtocolVersion,
+    max_size: usize,
+) -> io::Result<(u64, Vec<u8>)> {
+    if version == ProtocolVersion::V1 {
+        return Ok((0, read_length_prefixed(reader, max_size).await?));
+    }
+
+    let (frame_version, mut data) = read_versioned_frame(reader, max_size + 8).await?;
+    if frame_version != FRAME_VERSION_V2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported frame version 0x{:02x}", frame_version),
+        ));
+    }
+    if data.len() <= 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "empty message"));
+    }
+    let message = data.split_off(8);
+    let session = u64::from_be_bytes(data.try_into().expect("8 byte session ID"));
+    Ok((session, message))
+}
+
+/// Write a message framed for `version`; `session` is ignored for v1
+pub(crate) async fn write_frame<W: AsyncWriteExt + Unpin>(
+    writer: &mut W,
+    version: ProtocolVersion,
+    session: u64,
+    data: &[u8],
+) -> io::Result<()> {
+    match version {
+        ProtocolVersion::V1 => write_length_prefixed(writer, data).await,
+        ProtocolVersion::V2 => {
+            let mut payload = Vec::with_capacity(8 + data.len());
+            payload.extend_from_slice(&session.to_be_bytes());
+            payload.extend_from_slice(data);
+            write_versioned_frame(writer, FRAME_VERSION_V2, &payload).await
+        }
+    }
+}
+
+/// Tell a peer on `stream` that the blocks in `cids` are no longer wanted
+async fn send_cancel_all<S: AsyncWriteExt + Unpin>(
+    stream: &mut S,
+    cids: &[Cid],
+    peer_id: PeerId,
+    config: BlockExcConfig,
+    version: ProtocolVersion,
+    session: u64,
+) {
+    use crate::messages::{encode_message_checked, Message};
+
+    let msg = Message::cancel_all(cids.iter().map(|cid| cid.to_bytes()).collect());
+    let bytes = match encode_message_checked(&msg, config.max_message_bytes) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("BlockExc: Failed to encode cancels for {}: {}", peer_id, e);
+            return;
+        }
+    };
+    let send = write_frame(stream, version, session, &bytes);
+    match tokio::time::timeout(CANCEL_SEND_TIMEOUT, send).await {
+        Ok(Ok(())) => debug!(
+            "BlockExc: Cancelled {} want(s) with {}",
+            cids.len(),
+            peer_id
+        ),
+        Ok(Err(e)) => debug!("BlockExc: Failed to send cancels to {}: {}", peer_id, e),
+        Err(_) => debug!("BlockExc: Timed out sending cancels to {}", peer_id),
+    }
+}
+
+/// Room kept free in each response message for the integrity field
+const RESPONSE_OVERHEAD: usize = 16;
+
+/// Bytes a repeated message field adds to an encoded message: its payload
+/// plus the tag and length prefix
+fn field_cost(len: usize) -> usize {
+    len + 1 + prost::length_delimiter_len(len)
+}
+
+/// Split a response into messages that each encode within `max_bytes`
+///
+/// Presences go in the first message and blocks fill messages in order. A
+/// single block too large for any message gets one of its own, which the
+/// sender then refuses to encode. Always returns at least one message.
+fn split_response(
+    blocks: Vec<BlockDelivery>,
+    presences: Vec<BlockPresence>,
+    max_bytes: usize,
+) -> Vec<crate::messages::Message> {
+    use crate::messages::Message;
+    use prost::Message as _;
+
+    let budget = max_bytes.saturating_sub(RESPONSE_OVERHEAD);
+    let mut used: usize = presences.iter().map(|p| field_cost(p.encoded_len())).sum();
+    let mut messages = vec![Message {
+        block_presences: presences,
+        ..Default::default()
+    }];
+    for block in blocks {
+        let cost = field_cost(block.encoded_len());
+        let current = messages.last_mut().expect("at least one message");
+        let is_empty = current.payload.is_empty() && current.block_presences.is_empty();
+        if used + cost > budget && !is_empty {
+            messages.push(Message::default());
+            used = 0;
+        }
+        used += cost;
+        messages
+            .last_mut()
+            .expect("at least one message")
+            .payload
+            .push(block);
+    }
+    messages
+}
+
+/// Send a response to `peer_id`, split so every message fits the size limit
+///
+/// Blocks are counted as sent only once the message carrying them has been
+/// written. A message that can't be encoded is skipped; a write error ends
+/// the response and is returned.
+#[allow(clippy::too_many_arguments)]
+async fn send_response<S: AsyncWriteExt + Unpin>(
+    stream: &mut S,
+    version: ProtocolVersion,
+    session: u64,
+    blocks: Vec<BlockDelivery>,
+    presences: Vec<BlockPresence>,
+    config: BlockExcConfig,
+    metrics: &Metrics,
+    peer_id: PeerId,
+) -> io::Result<()> {
+    use crate::messages::encode_message_checked;
+
+    let messages = split_response(blocks, presences, config.max_message_bytes);
+    if messages.len() > 1 {
+        debug!(
+            "BlockExc: Splitting response to {} over {} messages",
+            peer_id,
+            messages.len()
+        );
+    }
+    for message in messages {
+        let bytes = match encode_message_checked(&message, config.max_message_bytes) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("BlockExc: Failed to encode response to {}: {}", peer_id, e);
+                continue;
+            }
+        };
+        write_frame(stream, version, session, &bytes).await?;
+        for block in &message.payload {
+            metrics.block_sent(block.data.len());
+        }
+    }
+    Ok(())
+}
+
 async fn resolve_leaf_delivery(
     block_store: &BlockStore,
     tree_cid: &Cid,
@@ -171,8 +475,24 @@ pub struct BlockExcHandler {
     price_per_byte: u64,
     /// Metrics collector for tracking P2P traffic
     metrics: Metrics,
+    /// Message limits for this connection's streams
+    config: BlockExcConfig,
     /// Pending block request (if any)
     pending_request: Option<cid::Cid>,
+    /// Rate limit shared by all inbound streams from this peer
+    inbound_limiter: Option<Arc<TrafficLimiter>>,
+    /// Events reported by stream tasks, forwarded to the behaviour
+    events_tx: mpsc::UnboundedSender<BlockExcToBehaviour>,
+    events_rx: mpsc::UnboundedReceiver<BlockExcToBehaviour>,
+    /// Set when the connection closes; each outbound stream holds a receiver
+    /// until it has cancelled its want
+    closing: Arc<tokio::sync::watch::Sender<bool>>,
+    /// Resolves once every outbound stream has dropped its `closing` receiver
+    close_wait: Option<futures::future::BoxFuture<'static, ()>>,
+    /// Version negotiated on the most recent stream with this peer
+    protocol_version: Option<ProtocolVersion>,
+    /// v2 streams, polled by the handler so they end with the connection
+    streams: FuturesUnordered<BoxFuture<'static, ()>>,
 }
 
 impl BlockExcHandler {
@@ -182,7 +502,9 @@ impl BlockExcHandler {
         mode: String,
         price_per_byte: u64,
         metrics: Metrics,
+        config: BlockExcConfig,
     ) -> Self {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
         BlockExcHandler {
             peer_id,
             outbound_requested: false,
@@ -191,8 +513,89 @@ impl BlockExcHandler {
             mode,
             price_per_byte,
             metrics,
+            config,
             pending_request: None,
+            inbound_limiter: None,
+            events_tx,
+            events_rx,
+            closing: Arc::new(tokio::sync::watch::channel(false).0),
+            close_wait: None,
+            protocol_version: None,
+            streams: FuturesUnordered::new(),
+        }
+    }
+
+    /// Throttle inbound messages from this peer with `limiter`
+    pub fn with_inbound_limiter(mut self, limiter: Option<Arc<TrafficLimiter>>) -> Self {
+        self.inbound_limiter = limiter;
+        self
+    }
+
+    /// Version negotiated on the most recent stream, if any
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.protocol_version
+    }
+
+    /// Upgrade offered on new streams: v2 then v1, or only v1 when v2 is
+    /// disabled
+    fn upgrade(&self) -> BlockExcUpgrade {
+        let protocol = |version: ProtocolVersion| {
+            ReadyUpgrade::new(StreamProtocol::new(version.protocol_id()))
+        };
+        if self.config.enable_v2 {
+            either::Either::Right(SelectUpgrade::new(
+                protocol(ProtocolVersion::V2),
+                protocol(ProtocolVersion::V1),
+            ))
+        } else {
+            either::Either::Left(protocol(ProtocolVersion::V1))
+        }
+    }
+
+    /// Record which version a negotiated stream speaks
+    fn negotiated(
+        &mut self,
+        stream: futures::future::Either<Stream, futures::future::Either<Stream, Stream>>,
+    ) -> (Stream, ProtocolVersion) {
+        use futures::future::Either;
+
+        let (stream, version) = match stream {
+            Either::Left(stream) => (stream, ProtocolVersion::V1),
+            Either::Right(Either::Left(stream)) => (stream, ProtocolVersion::V2),
+            Either::Right(Either::Right(stream)) => (stream, ProtocolVersion::V1),
+        };
+        if self.protocol_version != Some(version) {
+            debug!(
+                "BlockExc: Negotiated {} with {}",
+                version.protocol_id(),
+                self.peer_id
+            );
+            self.protocol_version = Some(version);
+            let _ = self
+                .events_tx
+                .send(BlockExcToBehaviour::ProtocolNegotiated { version });
         }
+        (stream, version)
+    }
+
+    /// Run a stream's task: v1 streams are spawned, v2 streams are polled
+    /// by the handler
+    fn run_stream(
+        &mut self,
+        version: ProtocolVersion,
+        task: impl std::future::Future<Output = ()> + Send + 'static,
+    ) {
+        match version {
+            ProtocolVersion::V1 => {
+                tokio::spawn(task);
+            }
+            ProtocolVersion::V2 => self.streams.push(task.boxed()),
+        }
+    }
+
+    /// Drive v2 streams until they are all waiting
+    fn poll_streams(&mut self, cx: &mut std::task::Context<'_>) {
+        while let std::task::Poll::Ready(Some(())) = self.streams.poll_next_unpin(cx) {}
     }
 }
 
@@ -201,27 +604,47 @@ impl BlockExcHandler {
 pub enum BlockExcFromBehaviour {
     /// Request a block from this peer
     RequestBlock { cid: cid::Cid },
+    /// Drop a request for a block that has not been sent yet
+    CancelRequest { cid: cid::Cid },
 }
 
 /// Messages from BlockExcHandler to BlockExcBehaviour
 #[derive(Debug, Clone)]
 pub enum BlockExcToBehaviour {
-    /// Block delivered from peer
-    BlockReceived { cid: cid::Cid, data: Vec<u8> },
+    /// Blocks delivered from peer in one message
+    BlocksReceived { blocks: Vec<(Vec<u8>, cid::Cid)> },
     /// Peer indicated they have this block
     BlockPresence { cid: cid::Cid, has_block: bool },
+    /// An outbound block request to the peer finished
+    RequestCompleted {
+        cid: cid::Cid,
+        outcome: DeliveryOutcome,
+    },
+    /// A stream with the peer negotiated a different protocol version
+    ProtocolNegotiated { version: ProtocolVersion },
+}
+
+/// How a peer answered an outbound block request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// The block was delivered and matched its CID
+    Delivered,
+    /// The stream ended without a valid block
+    Missing,
+    /// The peer sent data that did not match the requested CID
+    CidMismatch,
 }
 
 impl ConnectionHandler for BlockExcHandler {
     type FromBehaviour = BlockExcFromBehaviour;
     type ToBehaviour = BlockExcToBehaviour;
-    type InboundProtocol = ReadyUpgrade<StreamProtocol>;
-    type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type InboundProtocol = BlockExcUpgrade;
+    type OutboundProtocol = BlockExcUpgrade;
     type InboundOpenInfo = ();
     type OutboundOpenInfo = cid::Cid;
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
-        SubstreamProtocol::new(ReadyUpgrade::new(StreamProtocol::new(PROTOCOL_ID)), ())
+        SubstreamProtocol::new(self.upgrade(), ())
     }
 
     fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
@@ -234,6 +657,16 @@ impl ConnectionHandler for BlockExcHandler {
                 self.pending_request = Some(cid);
                 self.outbound_requested = false; // Reset so poll() will create new stream
             }
+            BlockExcFromBehaviour::CancelRequest { cid } => {
+                // Streams already opened for the block run to completion
+                if self.pending_request == Some(cid) {
+                    debug!(
+                        "BlockExc: Cancelled request for {} to {}",
+                        cid, self.peer_id
+                    );
+                    self.pending_request = None;
+                }
+            }
         }
     }
 
@@ -244,10 +677,15 @@ impl ConnectionHandler for BlockExcHandler {
 
     fn poll(
         &mut self,
-        _cx: &mut std::task::Context<'_>,
+        cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<
         ConnectionHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::ToBehaviour>,
     > {
+        self.poll_streams(cx);
+        if let std::task::Poll::Ready(Some(event)) = self.events_rx.poll_recv(cx) {
+            return std::task::Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(event));
+        }
+
         // On-demand outbound stream creation: when we have a pending block request
         if let Some(cid) = self.pending_request.take() {
             if !self.outbound_requested {
@@ -257,10 +695,7 @@ impl ConnectionHandler for BlockExcHandler {
                 );
                 self.outbound_requested = true;
                 return std::task::Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
-                    protocol: SubstreamProtocol::new(
-                        ReadyUpgrade::new(StreamProtocol::new(PROTOCOL_ID)),
-                        cid,
-                    ),
+                    protocol: SubstreamProtocol::new(self.upgrade(), cid),
                 });
             }
         }
@@ -268,6 +703,23 @@ impl ConnectionHandler for BlockExcHandler {
         std::task::Poll::Pending
     }
 
+    fn poll_close(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::ToBehaviour>> {
+        // Have outbound streams cancel their wants while the connection is up
+        self.closing.send_replace(true);
+        self.poll_streams(cx);
+        if self.closing.receiver_count() == 0 {
+            return std::task::Poll::Ready(None);
+        }
+        let closing = self.closing.clone();
+        self.close_wait
+            .get_or_insert_with(|| async move { closing.closed().await }.boxed())
+            .poll_unpin(cx)
+            .map(|()| None)
+    }
+
     fn on_connection_event(
         &mut self,
         event: ConnectionEvent<
@@ -282,17 +734,25 @@ impl ConnectionHandler for BlockExcHandler {
                 protocol: stream,
                 ..
             }) => {
+                let (stream, version) = self.negotiated(stream);
                 self.has_active_stream = true;
                 let peer_id = self.peer_id;
                 let block_store = self.block_store.clone();
                 let mode = self.mode.clone();
                 let price_per_byte = self.price_per_byte;
                 let metrics = self.metrics.clone();
+                let limiter = self.inbound_limiter.clone();
+                let config = self.config;
                 info!("BlockExc: Fully negotiated inbound stream from {} (mode: {}, price: {} per byte)", peer_id, mode, price_per_byte);
-
-                // Spawn task to handle the stream - read messages from remote peer
-                tokio::spawn(async move {
-                    use crate::messages::{decode_message, encode_message, Message};
+                // v2 HAVE presences carry only the CID
+                let presence_price = match version {
+                    ProtocolVersion::V1 => vec![0],
+                    ProtocolVersion::V2 => Vec::new(),
+                };
+
+                // Task to handle the stream - read messages from remote peer
+                let task = async move {
+                    use crate::messages::decode_message;
                     use cid::Cid;
 
                     let mut stream = stream;
@@ -300,13 +760,35 @@ impl ConnectionHandler for BlockExcHandler {
 
                     loop {
                         // Try to read a length-prefixed message
-                        match read_length_prefixed(&mut stream, 100 * 1024 * 1024).await {
-                            Ok(data) => {
+                        match read_frame(&mut stream, version, config.max_message_bytes).await {
+                            Ok((session, data)) => {
                                 info!("BlockExc: Received {} bytes from {}", data.len(), peer_id);
 
+                                // Hold off reading further messages while the peer is over its rate
+                                if let Some(limiter) = &limiter {
+                                    let waited = limiter.consume(data.len()).await;
+                                    if !waited.is_zero() {
+                                        debug!(
+                                            "BlockExc: Throttled {} for {:?} ({} bytes/s limit)",
+                                            peer_id,
+                                            waited,
+                                            limiter.bytes_per_second()
+                                        );
+                                    }
+                                }
+
                                 // Try to decode the message
                                 match decode_message(&data) {
-                                    Ok(msg) => {
+                                    Ok(mut msg) => {
+                                        if let Err(e) =
+                                            msg.expand_wantlist(config.max_wantlist_entries)
+                                        {
+                                            warn!(
+                                                "BlockExc: Ignoring message from {}: {}",
+                                                peer_id, e
+                                            );
+                                            continue;
+                                        }
                                         debug!("BlockExc: Decoded message from {}: wantlist={}, blocks={}, presences={}",
                                             peer_id,
                                             msg.wantlist.is_some(),
@@ -315,7 +797,16 @@ impl ConnectionHandler for BlockExcHandler {
                                         );
 
                                         // If they sent a wantlist, respond with presences and/or blocks.
-                                        if let Some(wantlist) = msg.wantlist {
+                                        if let Some(wantlist) = msg.wantlist.as_ref().filter(|w| {
+                                            w.entries.len() > config.max_wantlist_entries
+                                        }) {
+                                            warn!(
+                                                "BlockExc: Ignoring wantlist with {} entries from {} (limit {})",
+                                                wantlist.entries.len(),
+                                                peer_id,
+                                                config.max_wantlist_entries
+                                            );
+                                        } else if let Some(wantlist) = msg.wantlist {
                                             if mode == "altruistic" {
                                                 // ALTRUISTIC MODE: follow Archivist semantics.
                                                 info!(
@@ -364,7 +855,8 @@ impl ConnectionHandler for BlockExcHandler {
                                                                             r#type:
                                                                                 BlockPresenceType::PresenceHave
                                                                                     as i32,
-                                                                            price: vec![0],
+                                                                            price: presence_price
+                                                                                .clone(),
                                                                         },
                                                                     );
                                                                 } else if entry.send_dont_have {
@@ -376,7 +868,8 @@ impl ConnectionHandler for BlockExcHandler {
                                                                             r#type:
                                                                                 BlockPresenceType::PresenceDontHave
                                                                                     as i32,
-                                                                            price: vec![0],
+                                                                            price: presence_price
+                                                                                .clone(),
                                                                         },
                                                                     );
                                                                 }
@@ -390,9 +883,6 @@ impl ConnectionHandler for BlockExcHandler {
                                                                     )
                                                                     .await
                                                                 {
-                                                                    metrics.block_sent(
-                                                                        delivery.data.len(),
-                                                                    );
                                                                     response_blocks.push(delivery);
                                                                 }
                                                             }
@@ -411,40 +901,26 @@ impl ConnectionHandler for BlockExcHandler {
                                                                 let have = block.is_some();
                                                                 if have {
                                                                     response_presences.push(
-                                                                        BlockPresence {
-                                                                            address: Some(
-                                                                                address.clone(),
-                                                                            ),
-                                                                            r#type:
-                                                                                BlockPresenceType::PresenceHave
-                                                                                    as i32,
-                                                                            price: vec![0],
-                                                                        },
+                                                                        BlockPresence::from_valid_cid(
+                                                                            &cid,
+                                                                            BlockPresenceType::PresenceHave,
+                                                                            presence_price.clone(),
+                                                                        ),
                                                                     );
                                                                 } else if entry.send_dont_have {
                                                                     response_presences.push(
-                                                                        BlockPresence {
-                                                                            address: Some(
-                                                                                address.clone(),
-                                                                            ),
-                                                                            r#type:
-                                                                                BlockPresenceType::PresenceDontHave
-                                                                                    as i32,
-                                                                            price: vec![0],
-                                                                        },
+                                                                        BlockPresence::from_valid_cid(
+                                                                            &cid,
+                                                                            BlockPresenceType::PresenceDontHave,
+                                                                            presence_price.clone(),
+                                                                        ),
                                                                     );
                                                                 }
                                                             }
                                                             WantType::WantBlock => {
                                                                 if let Some(block) = block {
-                                                                    metrics.block_sent(
-                                                                        block.data.len(),
-                                                                    );
                                                                     response_blocks.push(
-                                                                        BlockDelivery::from_cid_and_data(
-                                                                            cid.to_bytes(),
-                                                                            block.data,
-                                                                        ),
+                                                                        BlockDelivery::from_verified_block(&block),
                                                                     );
                                                                 }
                                                             }
@@ -455,27 +931,20 @@ impl ConnectionHandler for BlockExcHandler {
                                                 if !response_blocks.is_empty()
                                                     || !response_presences.is_empty()
                                                 {
-                                                    let response = Message {
-                                                        wantlist: None,
-                                                        payload: response_blocks,
-                                                        block_presences: response_presences,
-                                                        pending_bytes: 0,
-                                                        account: None,
-                                                        payment: None,
-                                                    };
-
-                                                    if let Ok(response_bytes) =
-                                                        encode_message(&response)
+                                                    if let Err(e) = send_response(
+                                                        &mut stream,
+                                                        version,
+                                                        session,
+                                                        response_blocks,
+                                                        response_presences,
+                                                        config,
+                                                        &metrics,
+                                                        peer_id,
+                                                    )
+                                                    .await
                                                     {
-                                                        if let Err(e) = write_length_prefixed(
-                                                            &mut stream,
-                                                            &response_bytes,
-                                                        )
-                                                        .await
-                                                        {
-                                                            warn!("BlockExc: Failed to send response to {}: {}", peer_id, e);
-                                                            break;
-                                                        }
+                                                        warn!("BlockExc: Failed to send response to {}: {}", peer_id, e);
+                                                        break;
                                                     }
                                                 }
                                             } else if mode == "marketplace" {
@@ -505,41 +974,28 @@ impl ConnectionHandler for BlockExcHandler {
                                                                     info!("BlockExc: Serving full block {} to {} (paid) - {} bytes",
                                                                     cid, peer_id, total_size);
 
-                                                                    metrics.block_sent(
-                                                                        block.data.len(),
-                                                                    ); // Track P2P traffic!
                                                                     response_blocks.push(
-                                                                        BlockDelivery::from_cid_and_data(
-                                                                            cid.to_bytes(),
-                                                                            block.data.clone(),
-                                                                        )
+                                                                        BlockDelivery::from_verified_block(&block)
                                                                     );
                                                                 }
                                                             }
                                                         }
                                                     }
 
-                                                    let response = Message {
-                                                        wantlist: None,
-                                                        payload: response_blocks,
-                                                        block_presences: vec![],
-                                                        pending_bytes: 0,
-                                                        account: None,
-                                                        payment: None,
-                                                    };
-
-                                                    if let Ok(response_bytes) =
-                                                        encode_message(&response)
+                                                    if let Err(e) = send_response(
+                                                        &mut stream,
+                                                        version,
+                                                        session,
+                                                        response_blocks,
+                                                        Vec::new(),
+                                                        config,
+                                                        &metrics,
+                                                        peer_id,
+                                                    )
+                                                    .await
                                                     {
-                                                        if let Err(e) = write_length_prefixed(
-                                                            &mut stream,
-                                                            &response_bytes,
-                                                        )
-                                                        .await
-                                                        {
-                                                            warn!("BlockExc: Failed to send response to {}: {}", peer_id, e);
-                                                            break;
-                                                        }
+                                                        warn!("BlockExc: Failed to send response to {}: {}", peer_id, e);
+                                                        break;
                                                     }
                                                 } else {
                                                     // No payment - send block presences with prices
@@ -561,8 +1017,8 @@ impl ConnectionHandler for BlockExcHandler {
                                                                     info!("BlockExc: Block {} available for {} units", cid, block_price);
 
                                                                     block_presences.push(
-                                                                        BlockPresence::from_cid(
-                                                                            cid.to_bytes(),
+                                                                        BlockPresence::from_valid_cid(
+                                                                            &cid,
                                                                             crate::messages::BlockPresenceType::PresenceHave,
                                                                             block_price.to_le_bytes().to_vec(),
                                                                         )
@@ -572,27 +1028,20 @@ impl ConnectionHandler for BlockExcHandler {
                                                         }
                                                     }
 
-                                                    let response = Message {
-                                                        wantlist: None,
-                                                        payload: vec![],
+                                                    if let Err(e) = send_response(
+                                                        &mut stream,
+                                                        version,
+                                                        session,
+                                                        Vec::new(),
                                                         block_presences,
-                                                        pending_bytes: 0,
-                                                        account: None,
-                                                        payment: None,
-                                                    };
-
-                                                    if let Ok(response_bytes) =
-                                                        encode_message(&response)
+                                                        config,
+                                                        &metrics,
+                                                        peer_id,
+                                                    )
+                                                    .await
                                                     {
-                                                        if let Err(e) = write_length_prefixed(
-                                                            &mut stream,
-                                                            &response_bytes,
-                                                        )
-                                                        .await
-                                                        {
-                                                            warn!("BlockExc: Failed to send response to {}: {}", peer_id, e);
-                                                            break;
-                                                        }
+                                                        warn!("BlockExc: Failed to send response to {}: {}", peer_id, e);
+                                                        break;
                                                     }
                                                 }
                                             } else {
@@ -618,30 +1067,40 @@ impl ConnectionHandler for BlockExcHandler {
                     }
 
                     info!("BlockExc: Finished reading from {}", peer_id);
-                });
+                };
+                self.run_stream(version, task);
             }
             ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
                 protocol: stream,
                 info: requested_cid,
             }) => {
+                let (stream, version) = self.negotiated(stream);
                 self.has_active_stream = true;
                 let peer_id = self.peer_id;
                 let block_store = self.block_store.clone();
                 let metrics = self.metrics.clone();
+                let events_tx = self.events_tx.clone();
+                let config = self.config;
+                let mut closing = self.closing.subscribe();
                 info!(
                     "BlockExc: Fully negotiated outbound stream to {} for block {}",
                     peer_id, requested_cid
                 );
 
-                // Spawn task to handle outbound stream - send WantList and receive blocks
-                tokio::spawn(async move {
-                    use crate::cid_blake3::verify_blake3;
+                // Responses must echo the session; v1 has none
+                let session = match version {
+                    ProtocolVersion::V1 => 0,
+                    ProtocolVersion::V2 => rand::random(),
+                };
+
+                // Task to handle outbound stream - send WantList and receive blocks
+                let task = async move {
                     use crate::messages::{
-                        decode_message, encode_message, Message, WantType, Wantlist, WantlistEntry,
+                        decode_message, encode_message_checked, Message, Wantlist, WantlistEntry,
                     };
-                    use crate::storage::Block;
 
                     let mut stream = stream;
+                    let mut outcome = DeliveryOutcome::Missing;
 
                     info!(
                         "BlockExc: Requesting block {} from {}",
@@ -650,9 +1109,9 @@ impl ConnectionHandler for BlockExcHandler {
 
                     // Create WantList with requested CID using new BlockAddress structure
                     let wantlist = Wantlist {
-                        entries: vec![WantlistEntry::from_cid(
+                        entries: vec![WantlistEntry::from_cid_typed(
                             requested_cid.to_bytes(),
-                            WantType::WantBlock,
+                            requested_cid.codec() != MANIFEST_CODEC,
                         )],
                         full: true,
                     };
@@ -664,9 +1123,11 @@ impl ConnectionHandler for BlockExcHandler {
                         pending_bytes: 0,
                         account: None,
                         payment: None,
+                        integrity: None,
+                        compressed_wantlist: None,
                     };
 
-                    let msg_bytes = match encode_message(&msg) {
+                    let msg_bytes = match encode_message_checked(&msg, config.max_message_bytes) {
                         Ok(bytes) => bytes,
                         Err(e) => {
                             warn!("BlockExc: Failed to encode WantList: {}", e);
@@ -679,15 +1140,40 @@ impl ConnectionHandler for BlockExcHandler {
                         msg_bytes.len(),
                         peer_id
                     );
-                    if let Err(e) = write_length_prefixed(&mut stream, &msg_bytes).await {
+                    if let Err(e) = write_frame(&mut stream, version, session, &msg_bytes).await {
                         warn!("BlockExc: Failed to send WantList to {}: {}", peer_id, e);
                         return;
                     }
 
                     // Listen for responses (blocks or presences)
                     loop {
-                        match read_length_prefixed(&mut stream, 100 * 1024 * 1024).await {
-                            Ok(data) => {
+                        let read = tokio::select! {
+                            read = read_frame(&mut stream, version, config.max_message_bytes) => Some(read),
+                            _ = closing.wait_for(|closing| *closing) => None,
+                        };
+                        let Some(read) = read else {
+                            // The connection is closing; withdraw the want if still open
+                            if outcome != DeliveryOutcome::Delivered {
+                                send_cancel_all(
+                                    &mut stream,
+                                    &[requested_cid],
+                                    peer_id,
+                                    config,
+                                    version,
+                                    session,
+                                )
+                                .await;
+                            }
+                            break;
+                        };
+                        match read {
+                            Ok((response_session, _)) if response_session != session => {
+                                debug!(
+                                    "BlockExc: Ignoring response from {} for session {}",
+                                    peer_id, response_session
+                                );
+                            }
+                            Ok((_, data)) => {
                                 info!(
                                     "BlockExc: Received {} bytes from {} on outbound stream",
                                     data.len(),
@@ -695,7 +1181,7 @@ impl ConnectionHandler for BlockExcHandler {
                                 );
 
                                 match decode_message(&data) {
-                                    Ok(response) => {
+                                    Ok(mut response) => {
                                         info!(
                                             "BlockExc: Response from {}: blocks={}, presences={}",
                                             peer_id,
@@ -703,47 +1189,38 @@ impl ConnectionHandler for BlockExcHandler {
                                             response.block_presences.len()
                                         );
 
-                                        // Store received blocks
-                                        for msg_block in &response.payload {
-                                            info!(
-                                                "BlockExc: Received block! cid_len={}, data_len={}",
-                                                msg_block.cid.len(),
-                                                msg_block.data.len()
-                                            );
-
-                                            if let Err(e) =
-                                                verify_blake3(&msg_block.data, &requested_cid)
+                                        // Verify and store all blocks of the response at once
+                                        let blocks: Vec<(Vec<u8>, Cid)> = response
+                                            .payload
+                                            .drain(..)
+                                            .map(|msg_block| (msg_block.data, requested_cid))
+                                            .collect();
+                                        if !blocks.is_empty() {
+                                            let received = blocks.len();
+                                            match store_received_blocks(
+                                                blocks,
+                                                &block_store,
+                                                &metrics,
+                                            )
+                                            .await
                                             {
-                                                warn!(
-                                                    "BlockExc: CID verification failed for requested {}: {}",
-                                                    requested_cid, e
-                                                );
-                                                continue;
-                                            }
-
-                                            let block = match Block::from_cid_and_data(
-                                                requested_cid,
-                                                msg_block.data.clone(),
-                                            ) {
-                                                Ok(b) => b,
-                                                Err(e) => {
-                                                    warn!(
-                                                        "BlockExc: Failed to create block for {}: {}",
-                                                        requested_cid, e
-                                                    );
-                                                    continue;
+                                                Ok(0) => {
+                                                    if outcome == DeliveryOutcome::Missing {
+                                                        outcome = DeliveryOutcome::CidMismatch;
+                                                    }
                                                 }
-                                            };
-
-                                            let block_size = msg_block.data.len();
-                                            match block_store.put(block).await {
-                                                Ok(_) => {
-                                                    info!("BlockExc: Stored block {} from {} - {} bytes", requested_cid, peer_id, block_size);
-                                                    metrics.block_received(block_size);
-                                                    // Track P2P traffic!
+                                                Ok(stored) => {
+                                                    info!(
+                                                        "BlockExc: Stored {} of {} block(s) for {} from {}",
+                                                        stored, received, requested_cid, peer_id
+                                                    );
+                                                    outcome = DeliveryOutcome::Delivered;
                                                 }
                                                 Err(e) => {
-                                                    warn!("BlockExc: Failed to store block: {}", e);
+                                                    warn!(
+                                                        "BlockExc: Failed to store blocks: {}",
+                                                        e
+                                                    );
                                                 }
                                             }
                                         }
@@ -777,7 +1254,12 @@ impl ConnectionHandler for BlockExcHandler {
                     }
 
                     info!("BlockExc: Finished outbound stream to {}", peer_id);
-                });
+                    let _ = events_tx.send(BlockExcToBehaviour::RequestCompleted {
+                        cid: requested_cid,
+                        outcome,
+                    });
+                };
+                self.run_stream(version, task);
             }
             ConnectionEvent::DialUpgradeError(err) => {
                 warn!(
@@ -804,6 +1286,166 @@ pub struct BlockRequest {
         Arc<tokio::sync::Mutex<Option<tokio::sync::oneshot::Sender<crate::storage::Block>>>>,
 }
 
+impl BlockRequest {
+    /// A request without a responder, telling the behaviour to drop an
+    /// earlier request for `cid` whose client gave up waiting
+    pub fn cancellation(cid: cid::Cid) -> Self {
+        Self {
+            cid,
+            response_tx: Arc::new(tokio::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Whether nobody is waiting for this request's block any more
+    fn is_abandoned(&self) -> bool {
+        self.response_tx
+            .try_lock()
+            .is_ok_and(|responder| responder.as_ref().is_none_or(|tx| tx.is_closed()))
+    }
+}
+
+/// Dial attempts made to reconnect a lost peer before giving up
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Delay before the first reconnect attempt, doubled after each failed dial
+pub const RECONNECT_BASE_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Most dial addresses remembered per peer for reconnecting
+const MAX_ADDRESSES_PER_PEER: usize = 8;
+
+/// Peer count above which the dial addresses of peers that are neither
+/// connected nor being reconnected are forgotten
+const MAX_REMEMBERED_PEERS: usize = 1024;
+
+/// How long a peer's DontHave answer keeps it from being asked again
+const DONT_HAVE_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Have and DontHave answers remembered per peer, each
+const MAX_PRESENCES_PER_PEER: usize = 4096;
+
+/// Failed deliveries a peer may accumulate before it can be auto-evicted
+pub const EVICTION_MIN_FAILURES: u64 = 10;
+
+/// Failed-to-served ratio above which a peer is auto-evicted
+pub const EVICTION_FAILURE_RATIO: f64 = 0.5;
+
+/// Delivery record of a peer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerScore {
+    /// Requested blocks the peer delivered
+    pub served: u64,
+    /// Requested blocks the peer failed to deliver or delivered corrupted
+    pub failed: u64,
+}
+
+impl PeerScore {
+    /// Whether the peer has failed often enough to be evicted
+    pub fn should_evict(&self) -> bool {
+        self.failed > EVICTION_MIN_FAILURES
+            && (self.failed as f64 / self.served as f64) > EVICTION_FAILURE_RATIO
+    }
+
+    /// Fraction of requests the peer served, 0.5 for a peer with no record
+    pub fn success_ratio(&self) -> f64 {
+        let total = self.served + self.failed;
+        if total == 0 {
+            return 0.5;
+        }
+        self.served as f64 / total as f64
+    }
+}
+
+/// Why a peer was disconnected by [`BlockExcBehaviour::evict_peer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// The peer failed too many block requests
+    TooManyFailures,
+    /// The peer kept sending faster than its rate limit
+    RateLimitViolation,
+    /// The peer sent data that did not match the requested CID
+    CidMismatch,
+}
+
+impl std::fmt::Display for EvictionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvictionReason::TooManyFailures => write!(f, "too many failed requests"),
+            EvictionReason::RateLimitViolation => write!(f, "rate limit violation"),
+            EvictionReason::CidMismatch => write!(f, "CID mismatch"),
+        }
+    }
+}
+
+/// Snapshot of a [`BlockExcBehaviour`]'s request and peer state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockExcStats {
+    /// Block requests waiting for a response
+    pub pending_requests: usize,
+    /// Peers with an open BlockExc connection
+    pub connected_peers: usize,
+}
+
+/// A block the node is waiting for, as reported by
+/// [`BlockExcBehaviour::wantlist_snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WantEntry {
+    pub cid: Cid,
+    /// Peers the want was sent to
+    pub requested_from: Vec<PeerId>,
+    /// Time since the block was first requested
+    pub age: std::time::Duration,
+    /// Times the want was re-broadcast
+    pub retry_count: u32,
+}
+
+/// Blocks a peer has said it has, and blocks it has said it doesn't have,
+/// with when it said so
+type PresenceSets = (PresenceSet, PresenceSet);
+
+/// One kind of presence answer from one peer, at most
+/// [`MAX_PRESENCES_PER_PEER`] of them
+#[derive(Default)]
+struct PresenceSet {
+    /// When the latest answer for each block arrived
+    answers: std::collections::HashMap<Cid, std::time::Instant>,
+    /// Answers in arrival order, including ones since replaced or removed
+    order: std::collections::VecDeque<(Cid, std::time::Instant)>,
+}
+
+impl PresenceSet {
+    /// Record an answer for `cid`, dropping the oldest answer when full
+    fn insert(&mut self, cid: Cid, at: std::time::Instant) {
+        self.answers.insert(cid, at);
+        self.order.push_back((cid, at));
+        if self.answers.len() > MAX_PRESENCES_PER_PEER {
+            while let Some((oldest, answered)) = self.order.pop_front() {
+                if self.answers.get(&oldest) == Some(&answered) {
+                    self.answers.remove(&oldest);
+                    break;
+                }
+            }
+        }
+        // Drop stale entries once they make up half the queue, which keeps
+        // this amortised O(1)
+        if self.order.len() > 2 * MAX_PRESENCES_PER_PEER {
+            let answers = &self.answers;
+            self.order.retain(|(cid, at)| answers.get(cid) == Some(at));
+        }
+    }
+
+    fn remove(&mut self, cid: &Cid) {
+        self.answers.remove(cid);
+    }
+
+    fn get(&self, cid: &Cid) -> Option<&std::time::Instant> {
+        self.answers.get(cid)
+    }
+
+    fn len(&self) -> usize {
+        self.answers.len()
+    }
+}
+
 /// BlockExc network behaviour
 pub struct BlockExcBehaviour {
     block_store: Arc<BlockStore>,
@@ -812,12 +1454,55 @@ pub struct BlockExcBehaviour {
     metrics: Metrics,
     /// Channel for receiving block requests
     request_rx: mpsc::UnboundedReceiver<BlockRequest>,
+    /// Sender for wants to re-broadcast (e.g. retries of expired requests)
+    want_tx: mpsc::UnboundedSender<Cid>,
+    /// Wants queued for `broadcast_want`
+    want_rx: mpsc::UnboundedReceiver<Cid>,
     /// Pending block requests
     pending_requests: std::collections::HashMap<cid::Cid, BlockRequest>,
+    /// Admits pending requests up to `config.max_pending_requests`
+    pending_limit: PendingBlocksManager,
+    /// When each wanted block was first requested
+    requested_at: std::collections::HashMap<Cid, std::time::Instant>,
+    /// Peers each wanted block was requested from, and its re-broadcasts
+    want_sends: std::collections::HashMap<Cid, (Vec<PeerId>, u32)>,
     /// Connected peers
     connected_peers: std::collections::HashSet<PeerId>,
     /// Pending events to send to handlers
     pending_events: std::collections::VecDeque<(PeerId, BlockExcFromBehaviour)>,
+    /// Peers known to have specific blocks (from BlockPresence responses)
+    content_router: Arc<ContentRouter>,
+    /// Message limits handed to every connection handler
+    config: BlockExcConfig,
+    /// Per-peer inbound rate limit in bytes per second (None = unlimited)
+    inbound_rate_limit: Option<u64>,
+    /// Inbound rate limiters of connected peers, shared across their connections
+    peer_limiters: std::collections::HashMap<PeerId, Arc<TrafficLimiter>>,
+    /// Delivery records of peers we have requested blocks from
+    peer_scores: std::collections::HashMap<PeerId, PeerScore>,
+    /// Latest ping round-trip time of connected peers
+    ping_rtts: std::collections::HashMap<PeerId, std::time::Duration>,
+    /// Blocks each connected peer has told us it has and doesn't have
+    peer_block_cache: std::collections::HashMap<PeerId, PresenceSets>,
+    /// BlockExc version last negotiated with each connected peer
+    peer_protocols: std::collections::HashMap<PeerId, ProtocolVersion>,
+    /// Evicted peers whose new connections are refused
+    banned_peers: std::collections::HashSet<PeerId>,
+    /// Evicted peers whose connections still have to be closed
+    pending_evictions: std::collections::VecDeque<PeerId>,
+    /// Block store events, used to answer pending requests for blocks that
+    /// arrive through other paths (uploads, BoTG, HTTP fallback)
+    storage_events: futures::stream::BoxStream<'static, StorageEvent>,
+    /// Addresses we have dialed each peer at, or that identify reported for
+    /// it, kept for reconnecting
+    peer_addresses: std::collections::HashMap<PeerId, Vec<libp2p::Multiaddr>>,
+    /// Lost peers being reconnected, with their addresses and dial attempts
+    reconnect_peers: std::collections::HashMap<PeerId, (Vec<libp2p::Multiaddr>, u32)>,
+    /// Peers we are disconnecting from on purpose, which are not reconnected
+    intentional_disconnects: std::collections::HashSet<PeerId>,
+    /// Backoff delays of scheduled reconnect dials
+    reconnect_timers:
+        futures::stream::FuturesUnordered<futures::future::BoxFuture<'static, PeerId>>,
 }
 
 impl BlockExcBehaviour {
@@ -828,22 +1513,191 @@ impl BlockExcBehaviour {
         metrics: Metrics,
     ) -> (Self, mpsc::UnboundedSender<BlockRequest>) {
         let (request_tx, request_rx) = mpsc::unbounded_channel();
+        let (want_tx, want_rx) = mpsc::unbounded_channel();
+        let config = BlockExcConfig::default();
         let behaviour = Self {
+            storage_events: block_store.event_stream(),
             block_store,
             mode,
             price_per_byte,
             metrics,
             request_rx,
+            want_tx,
+            want_rx,
             pending_requests: std::collections::HashMap::new(),
+            pending_limit: PendingBlocksManager::new_with_limit(config.max_pending_requests),
+            requested_at: std::collections::HashMap::new(),
+            want_sends: std::collections::HashMap::new(),
             connected_peers: std::collections::HashSet::new(),
             pending_events: std::collections::VecDeque::new(),
+            content_router: Arc::new(ContentRouter::new()),
+            config,
+            inbound_rate_limit: None,
+            peer_limiters: std::collections::HashMap::new(),
+            peer_scores: std::collections::HashMap::new(),
+            ping_rtts: std::collections::HashMap::new(),
+            peer_block_cache: std::collections::HashMap::new(),
+            peer_protocols: std::collections::HashMap::new(),
+            banned_peers: std::collections::HashSet::new(),
+            pending_evictions: std::collections::VecDeque::new(),
+            peer_addresses: std::collections::HashMap::new(),
+            reconnect_peers: std::collections::HashMap::new(),
+            intentional_disconnects: std::collections::HashSet::new(),
+            reconnect_timers: futures::stream::FuturesUnordered::new(),
         };
         (behaviour, request_tx)
     }
 
-    /// Request a specific block from a specific peer
+    /// Set the stream settings used by connections established after the call
+    pub fn set_config(&mut self, config: BlockExcConfig) {
+        self.pending_limit = PendingBlocksManager::new_with_limit(config.max_pending_requests);
+        for cid in self.pending_requests.keys() {
+            self.pending_limit.add_pending(*cid);
+        }
+        self.config = config;
+    }
+
+    /// Limit inbound traffic from each peer to `bytes_per_second`
     ///
-    /// Sends a WantBlock message to the specified peer to request the given CID.
+    /// Applies to connections established after the call; `None` removes
+    /// the limit.
+    pub fn set_inbound_rate_limit(&mut self, bytes_per_second: Option<u64>) {
+        self.inbound_rate_limit = bytes_per_second;
+        self.peer_limiters.clear();
+    }
+
+    /// Get or create the inbound rate limiter for `peer`
+    fn peer_limiter(&mut self, peer: PeerId) -> Option<Arc<TrafficLimiter>> {
+        let rate = self.inbound_rate_limit?;
+        Some(
+            self.peer_limiters
+                .entry(peer)
+                .or_insert_with(|| Arc::new(TrafficLimiter::new(rate)))
+                .clone(),
+        )
+    }
+
+    /// Disconnect `peer_id` and refuse its future connections
+    ///
+    /// The peer's connections are closed the next time the swarm polls this
+    /// behaviour. Use [`Self::unban_peer`] to accept it again.
+    pub fn evict_peer(&mut self, peer_id: PeerId, reason: EvictionReason) {
+        if !self.banned_peers.insert(peer_id) {
+            return;
+        }
+        warn!("BlockExc: Evicting peer {}: {}", peer_id, reason);
+        self.pending_evictions.push_back(peer_id);
+        self.pending_events.retain(|(peer, _)| *peer != peer_id);
+        self.connected_peers.remove(&peer_id);
+        self.ping_rtts.remove(&peer_id);
+        self.peer_block_cache.remove(&peer_id);
+    }
+
+    /// Schedule the next reconnect dial to `peer_id` after the backoff for
+    /// `attempts` failed dials
+    fn schedule_reconnect(&mut self, peer_id: PeerId, attempts: u32) {
+        let delay = RECONNECT_BASE_DELAY * 2u32.pow(attempts);
+        self.reconnect_timers.push(Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            peer_id
+        }));
+    }
+
+    /// Note that connections to `peer_id` are being closed on purpose
+    ///
+    /// The peer is not reconnected when its last connection closes, and a
+    /// reconnect already underway is abandoned.
+    pub fn expect_disconnect(&mut self, peer_id: PeerId) {
+        self.reconnect_peers.remove(&peer_id);
+        self.peer_addresses.remove(&peer_id);
+        if self.connected_peers.contains(&peer_id) {
+            self.intentional_disconnects.insert(peer_id);
+        }
+    }
+
+    /// Remember that `peer_id` can be dialed at `address`, keeping the most
+    /// recent [`MAX_ADDRESSES_PER_PEER`] addresses per peer
+    fn remember_address(&mut self, peer_id: PeerId, address: &libp2p::Multiaddr) {
+        let addresses = self.peer_addresses.entry(peer_id).or_default();
+        addresses.retain(|known| known != address);
+        addresses.push(address.clone());
+        if addresses.len() > MAX_ADDRESSES_PER_PEER {
+            addresses.remove(0);
+        }
+
+        if self.peer_addresses.len() > MAX_REMEMBERED_PEERS {
+            let (connected, reconnecting) = (&self.connected_peers, &self.reconnect_peers);
+            self.peer_addresses
+                .retain(|peer, _| connected.contains(peer) || reconnecting.contains_key(peer));
+        }
+    }
+
+    /// Accept connections from a previously evicted peer again
+    pub fn unban_peer(&mut self, peer_id: &PeerId) -> bool {
+        self.peer_scores.remove(peer_id);
+        self.banned_peers.remove(peer_id)
+    }
+
+    /// Whether `peer_id` has been evicted and is refused
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.banned_peers.contains(peer_id)
+    }
+
+    /// Delivery record of `peer_id`
+    pub fn peer_score(&self, peer_id: &PeerId) -> PeerScore {
+        self.peer_scores.get(peer_id).copied().unwrap_or_default()
+    }
+
+    /// Record the round-trip time of a successful ping to `peer_id`
+    pub fn record_ping_rtt(&mut self, peer_id: PeerId, rtt: std::time::Duration) {
+        self.ping_rtts.insert(peer_id, rtt);
+    }
+
+    /// BlockExc version last negotiated with `peer_id`, if connected
+    pub fn protocol_version(&self, peer_id: &PeerId) -> Option<ProtocolVersion> {
+        self.peer_protocols.get(peer_id).copied()
+    }
+
+    /// Latest ping round-trip time of `peer_id`, if it has been pinged
+    pub fn ping_rtt(&self, peer_id: &PeerId) -> Option<std::time::Duration> {
+        self.ping_rtts.get(peer_id).copied()
+    }
+
+    /// Connected peer with the lowest ping round-trip time
+    pub fn best_latency_peer(&self) -> Option<PeerId> {
+        self.ping_rtts
+            .iter()
+            .filter(|(peer, _)| self.connected_peers.contains(peer))
+            .min_by_key(|(_, rtt)| **rtt)
+            .map(|(peer, _)| *peer)
+    }
+
+    /// Update a peer's score with the outcome of a block request, evicting
+    /// it once its failure rate crosses the threshold
+    ///
+    /// A missing block only counts as a failure if the peer had announced
+    /// having it, since wants are broadcast to peers that may not.
+    fn record_outcome(&mut self, peer_id: PeerId, cid: &Cid, outcome: DeliveryOutcome) {
+        let score = self.peer_scores.entry(peer_id).or_default();
+        match outcome {
+            DeliveryOutcome::Delivered => score.served += 1,
+            DeliveryOutcome::CidMismatch => score.failed += 1,
+            DeliveryOutcome::Missing => {
+                if self.content_router.peers_for(cid).contains(&peer_id) {
+                    score.failed += 1;
+                }
+            }
+        }
+
+        let score = *score;
+        if score.should_evict() {
+            self.evict_peer(peer_id, EvictionReason::TooManyFailures);
+        }
+    }
+
+    /// Request a specific block from a specific peer
+    ///
+    /// Sends a WantBlock message to the specified peer to request the given CID.
     /// The peer will respond with the block if they have it.
     ///
     /// # Arguments
@@ -864,43 +1718,188 @@ impl BlockExcBehaviour {
         );
 
         // Queue the RequestBlock event for this specific peer
+        self.record_want(cid, &[peer_id], false);
         self.pending_events
             .push_back((peer_id, BlockExcFromBehaviour::RequestBlock { cid }));
 
         Ok(())
     }
 
-    /// Broadcast a want for a block to all connected peers
+    /// Broadcast a want for a block
     ///
-    /// Sends WantBlock messages to all currently connected peers requesting the given CID.
-    /// This is useful when you don't know which peer has the block.
+    /// Sends WantBlock messages to the connected peers known (via the content
+    /// router) to have the CID, or to all connected peers if none are known.
+    /// Peers that answered DontHave for the CID are skipped either way.
     ///
     /// # Arguments
     /// * `cid` - The CID of the block to request
     ///
     /// # Returns
     /// * `Ok(usize)` - Number of peers the request was sent to
-    /// * `Err(BlockExcError::NoPeers)` if no peers are connected
+    /// * `Err(BlockExcError::NoPeers)` if no connected peer is left to ask
     pub fn broadcast_want(&mut self, cid: Cid) -> Result<usize, BlockExcError> {
-        if self.connected_peers.is_empty() {
+        let targets = self.target_peers(&cid);
+        if targets.is_empty() {
             return Err(BlockExcError::NoPeers);
         }
-
-        let peer_count = self.connected_peers.len();
+        let peer_count = targets.len();
         info!(
             "BlockExc: Broadcasting want for block {} to {} peers",
             cid, peer_count
         );
+        let retry = self.requested_at.contains_key(&cid);
+        self.record_want(cid, &targets, retry);
 
-        // Queue RequestBlock events for all connected peers
-        for peer_id in &self.connected_peers {
+        for peer_id in targets {
             self.pending_events
-                .push_back((*peer_id, BlockExcFromBehaviour::RequestBlock { cid }));
+                .push_back((peer_id, BlockExcFromBehaviour::RequestBlock { cid }));
         }
 
         Ok(peer_count)
     }
 
+    /// Request a block from the single best peer known to have it
+    ///
+    /// Among the connected peers the content router lists for `cid`, the one
+    /// with the highest [`PeerScore::success_ratio`] is asked, preferring
+    /// the lower ping round-trip time on a tie. Without a routing hint this
+    /// falls back to [`Self::broadcast_want`].
+    ///
+    /// # Returns
+    /// * `Ok(())` if the request was queued
+    /// * `Err(BlockExcError::NoPeers)` if no peers are connected
+    pub fn get_block_from_best_peer(&mut self, cid: Cid) -> Result<(), BlockExcError> {
+        let best = self
+            .content_router
+            .peers_for(&cid)
+            .into_iter()
+            .filter(|peer| self.connected_peers.contains(peer))
+            .max_by(|a, b| {
+                // Unpinged peers lose latency ties
+                let rtt = |peer| self.ping_rtt(peer).unwrap_or(std::time::Duration::MAX);
+                self.peer_score(a)
+                    .success_ratio()
+                    .total_cmp(&self.peer_score(b).success_ratio())
+                    .then_with(|| rtt(b).cmp(&rtt(a)))
+            });
+
+        let Some(peer_id) = best else {
+            return self.broadcast_want(cid).map(|_| ());
+        };
+        debug!("BlockExc: Best known peer for block {} is {}", cid, peer_id);
+        let retry = self.requested_at.contains_key(&cid);
+        self.record_want(cid, &[peer_id], retry);
+        self.pending_events
+            .push_back((peer_id, BlockExcFromBehaviour::RequestBlock { cid }));
+        Ok(())
+    }
+
+    /// Get a sender whose CIDs are passed to [`Self::broadcast_want`] when
+    /// the swarm next polls this behaviour
+    ///
+    /// Lets code outside the swarm, such as the
+    /// [`PendingBlocksManager`](crate::pending_blocks::PendingBlocksManager)
+    /// retry callback, re-send wants.
+    pub fn want_sender(&self) -> mpsc::UnboundedSender<Cid> {
+        self.want_tx.clone()
+    }
+
+    /// Remove the pending request for `cid`, freeing its slot
+    fn remove_pending_request(&mut self, cid: &Cid) -> Option<BlockRequest> {
+        self.pending_limit.cancel(cid);
+        self.pending_requests.remove(cid)
+    }
+
+    /// Drop the pending request for `cid` if its client stopped waiting,
+    /// and tell the peers it was sent to not to fetch it
+    ///
+    /// # Returns
+    /// Whether a pending request was removed
+    fn cancel_request(&mut self, cid: Cid) -> bool {
+        if !self
+            .pending_requests
+            .get(&cid)
+            .is_some_and(BlockRequest::is_abandoned)
+        {
+            return false;
+        }
+        self.remove_pending_request(&cid);
+        self.forget_want(&cid);
+        info!("BlockExc behaviour: Cancelled request for block {}", cid);
+
+        self.pending_events.retain(|(_, event)| {
+            !matches!(event, BlockExcFromBehaviour::RequestBlock { cid: queued } if *queued == cid)
+        });
+        for peer_id in self.target_peers(&cid) {
+            self.pending_events
+                .push_back((peer_id, BlockExcFromBehaviour::CancelRequest { cid }));
+        }
+        true
+    }
+
+    /// Connected peers to ask for `cid`: those the content router knows have
+    /// it, falling back to every connected peer
+    fn target_peers(&self, cid: &Cid) -> Vec<PeerId> {
+        let known: Vec<PeerId> = self
+            .content_router
+            .peers_for(cid)
+            .into_iter()
+            .filter(|peer| self.connected_peers.contains(peer))
+            .filter(|peer| !self.peer_lacks(peer, cid))
+            .collect();
+
+        if known.is_empty() {
+            self.connected_peers
+                .iter()
+                .filter(|peer| !self.peer_lacks(peer, cid))
+                .copied()
+                .collect()
+        } else {
+            known
+        }
+    }
+
+    /// Remember a peer's Have or DontHave answer for `cid`
+    ///
+    /// Each set keeps at most [`MAX_PRESENCES_PER_PEER`] answers, dropping
+    /// the oldest first.
+    fn record_presence(&mut self, peer_id: PeerId, cid: Cid, has_block: bool) {
+        if !self.connected_peers.contains(&peer_id) {
+            return;
+        }
+        let (has, dont_have) = self.peer_block_cache.entry(peer_id).or_default();
+        let (set, other) = if has_block {
+            (has, dont_have)
+        } else {
+            (dont_have, has)
+        };
+        other.remove(&cid);
+        set.insert(cid, std::time::Instant::now());
+    }
+
+    /// Whether `peer_id` told us in the last [`DONT_HAVE_TTL`] that it
+    /// doesn't have `cid`
+    fn peer_lacks(&self, peer_id: &PeerId, cid: &Cid) -> bool {
+        self.peer_block_cache
+            .get(peer_id)
+            .and_then(|(_, dont_have)| dont_have.get(cid))
+            .is_some_and(|at| at.elapsed() < DONT_HAVE_TTL)
+    }
+
+    /// Total sizes of the have and dont-have presence caches across peers
+    pub fn cache_stats(&self) -> (usize, usize) {
+        self.peer_block_cache
+            .values()
+            .fold((0, 0), |(have, dont_have), (has, lacks)| {
+                (have + has.len(), dont_have + lacks.len())
+            })
+    }
+
+    /// Get the content routing table shared with this behaviour
+    pub fn content_router(&self) -> Arc<ContentRouter> {
+        self.content_router.clone()
+    }
+
     /// Get the number of currently connected peers
     pub fn connected_peer_count(&self) -> usize {
         self.connected_peers.len()
@@ -910,6 +1909,81 @@ impl BlockExcBehaviour {
     pub fn connected_peers(&self) -> Vec<PeerId> {
         self.connected_peers.iter().copied().collect()
     }
+
+    /// List the blocks the node is currently waiting for, oldest first
+    pub fn wantlist_snapshot(&self) -> Vec<WantEntry> {
+        let mut entries: Vec<WantEntry> = self
+            .requested_at
+            .iter()
+            .map(|(cid, requested_at)| {
+                let (requested_from, retry_count) =
+                    self.want_sends.get(cid).cloned().unwrap_or_default();
+                WantEntry {
+                    cid: *cid,
+                    requested_from,
+                    age: requested_at.elapsed(),
+                    retry_count,
+                }
+            })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.age));
+        entries
+    }
+
+    /// Record that a want for `cid` was sent to `peers`
+    fn record_want(&mut self, cid: Cid, peers: &[PeerId], retry: bool) {
+        self.requested_at
+            .entry(cid)
+            .or_insert_with(std::time::Instant::now);
+        let (requested_from, retries) = self.want_sends.entry(cid).or_default();
+        for peer in peers {
+            if !requested_from.contains(peer) {
+                requested_from.push(*peer);
+            }
+        }
+        if retry {
+            *retries += 1;
+        }
+    }
+
+    /// Drop the wantlist state of `cid` once it is delivered or cancelled
+    fn forget_want(&mut self, cid: &Cid) {
+        self.requested_at.remove(cid);
+        self.want_sends.remove(cid);
+    }
+
+    /// Get a snapshot of pending requests and connected peers
+    pub fn stats(&self) -> BlockExcStats {
+        BlockExcStats {
+            pending_requests: self.pending_requests.len(),
+            connected_peers: self.connected_peers.len(),
+        }
+    }
+
+    /// Stop accepting block requests and drop every queued or in-flight one
+    ///
+    /// Used during shutdown. Dropping a request's responder makes the waiting
+    /// client fail immediately instead of waiting for its timeout, and new
+    /// requests are rejected once the queue is closed.
+    ///
+    /// # Returns
+    /// The number of requests that were dropped
+    pub fn drain_pending_requests(&mut self) -> usize {
+        self.request_rx.close();
+        self.want_rx.close();
+        while self.want_rx.try_recv().is_ok() {}
+        let mut drained = 0;
+        while self.request_rx.try_recv().is_ok() {
+            drained += 1;
+        }
+        drained += self.pending_requests.len();
+        self.pending_requests.clear();
+        self.pending_limit.clear();
+        self.requested_at.clear();
+        self.want_sends.clear();
+        self.pending_events.clear();
+        drained
+    }
 }
 
 //
@@ -918,6 +1992,68 @@ impl BlockExcBehaviour {
 
 use cid::Cid;
 
+/// Verify received blocks in parallel and store the valid ones
+///
+/// Blocks that fail verification are logged and skipped. Returns the number
+/// of blocks stored.
+pub async fn verify_and_store_batch(
+    blocks: Vec<(Vec<u8>, Cid)>,
+    store: &BlockStore,
+) -> Result<usize, BlockExcError> {
+    let valid = verify_blocks(blocks).await?;
+    let stored = valid.len();
+    if stored > 0 {
+        store.put_many(valid).await?;
+    }
+    Ok(stored)
+}
+
+/// Verify and store all blocks of one received message, counting the
+/// stored blocks as received traffic
+async fn store_received_blocks(
+    blocks: Vec<(Vec<u8>, Cid)>,
+    store: &BlockStore,
+    metrics: &Metrics,
+) -> Result<usize, BlockExcError> {
+    let valid = verify_blocks(blocks).await?;
+    let sizes: Vec<usize> = valid.iter().map(|block| block.data.len()).collect();
+    if !valid.is_empty() {
+        store.put_many(valid).await?;
+    }
+    for size in &sizes {
+        metrics.block_received(*size);
+    }
+    Ok(sizes.len())
+}
+
+/// Verify blocks in parallel, keeping the ones whose data matches their CID
+async fn verify_blocks(
+    blocks: Vec<(Vec<u8>, Cid)>,
+) -> Result<Vec<crate::storage::Block>, BlockExcError> {
+    use crate::cid_blake3::verify_blake3_batch;
+    use crate::storage::Block;
+
+    // Hashing is CPU-bound, keep it off the async workers
+    let (blocks, results) = tokio::task::spawn_blocking(move || {
+        let results = verify_blake3_batch(&blocks);
+        (blocks, results)
+    })
+    .await
+    .map_err(|e| BlockExcError::RequestFailed(format!("Verification task failed: {}", e)))?;
+
+    Ok(blocks
+        .into_iter()
+        .zip(results)
+        .filter_map(|((data, cid), result)| match result {
+            Ok(()) => Some(Block { cid, data }),
+            Err(e) => {
+                warn!("BlockExc: Rejecting block {}: {}", cid, e);
+                None
+            }
+        })
+        .collect())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum BlockExcError {
     #[error("Block request failed: {0}")]
@@ -932,6 +2068,12 @@ pub enum BlockExcError {
     #[error("No peers available")]
     NoPeers,
 
+    #[error("Node is shutting down")]
+    Shutdown,
+
+    #[error("Peer {0} is banned")]
+    PeerBanned(String),
+
     #[error("CID mismatch: expected {expected}, got {got}")]
     CidMismatch { expected: String, got: String },
 
@@ -950,6 +2092,10 @@ pub struct BlockExcClient {
     block_store: Arc<BlockStore>,
     /// Metrics
     metrics: Metrics,
+    /// How long [`Self::request_block`] waits for a block
+    timeout: std::time::Duration,
+    /// Tracks outstanding requests so unanswered wants are re-broadcast
+    want_retries: Option<PendingBlocksManager>,
 }
 
 impl BlockExcClient {
@@ -963,13 +2109,46 @@ impl BlockExcClient {
             request_tx,
             block_store,
             metrics,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            want_retries: None,
         }
     }
 
+    /// Set how long [`Self::request_block`] waits for a block
+    /// (default [`DEFAULT_REQUEST_TIMEOUT`])
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Re-broadcast the wants of unanswered requests through `pending`
+    ///
+    /// Each request is added to `pending` with a timeout that splits the
+    /// request timeout evenly between the first attempt and the manager's
+    /// retries. `pending`'s retry callback and expiry task are left to the
+    /// caller, typically sending to [`BlockExcBehaviour::want_sender`].
+    pub fn with_want_retries(mut self, pending: PendingBlocksManager) -> Self {
+        self.want_retries = Some(pending);
+        self
+    }
+
     /// Request a block from the network via BlockExc protocol
     ///
     /// Sends a request to the swarm which broadcasts WantBlock messages to all connected peers
     pub async fn request_block(&self, cid: Cid) -> Result<crate::storage::Block, BlockExcError> {
+        self.request_block_with_timeout(cid, self.timeout).await
+    }
+
+    /// Request a block, waiting at most `timeout` instead of the client's
+    /// default
+    ///
+    /// On timeout the swarm is told to cancel the request, so a late block is
+    /// no longer forwarded to this client.
+    pub async fn request_block_with_timeout(
+        &self,
+        cid: Cid,
+        timeout: std::time::Duration,
+    ) -> Result<crate::storage::Block, BlockExcError> {
         info!("BlockExc client: Requesting block {}", cid);
 
         // Check if block is already in local store
@@ -983,26 +2162,83 @@ impl BlockExcClient {
         let response_tx = Arc::new(tokio::sync::Mutex::new(Some(response_tx)));
 
         // Send block request to swarm via channel
+        // Keep only a weak handle so a dropped request still closes the channel
+        let responder = Arc::downgrade(&response_tx);
         let block_request = BlockRequest { cid, response_tx };
 
         if self.request_tx.send(block_request).is_err() {
-            return Err(BlockExcError::RequestFailed(
-                "Failed to send request to swarm".to_string(),
-            ));
+            // The request queue only closes when the node drains it on shutdown
+            return Err(BlockExcError::Shutdown);
         }
 
         info!("BlockExc client: Sent request for block {} to swarm", cid);
 
+        // Only the request that started tracking a block stops it
+        let tracked = self.want_retries.as_ref().filter(|pending| {
+            if pending.is_pending(&cid) {
+                return false;
+            }
+            drop(pending.add_pending(cid));
+            pending.set_timeout(&cid, timeout / (pending.max_retries() + 1))
+        });
+
         // Wait for block to arrive (with timeout)
-        match tokio::time::timeout(std::time::Duration::from_secs(30), response_rx).await {
+        let result = tokio::time::timeout(timeout, response_rx).await;
+        if let Some(pending) = tracked {
+            pending.cancel(&cid);
+        }
+        match result {
             Ok(Ok(block)) => {
                 info!("BlockExc client: Successfully received block {}", cid);
                 self.metrics.block_received(block.data.len());
                 Ok(block)
             }
+            Ok(Err(_)) if self.request_tx.is_closed() => Err(BlockExcError::Shutdown),
             Ok(Err(_)) => Err(BlockExcError::RequestFailed("Channel closed".to_string())),
-            Err(_) => Err(BlockExcError::Timeout),
+            Err(_) => {
+                warn!("BlockExc client: Request for block {} timed out", cid);
+                if let Some(responder) = responder.upgrade() {
+                    responder.lock().await.take();
+                }
+                let _ = self.request_tx.send(BlockRequest::cancellation(cid));
+                Err(BlockExcError::Timeout)
+            }
+        }
+    }
+}
+
+impl BlockExcBehaviour {
+    /// Complete a pending request for `cid` from the local store
+    fn complete_from_store(&mut self, cid: Cid) {
+        self.forget_want(&cid);
+        let Some(request) = self.remove_pending_request(&cid) else {
+            return;
+        };
+        debug!(
+            "BlockExc behaviour: Block {} stored locally, completing pending request",
+            cid
+        );
+        let block_store = self.block_store.clone();
+        tokio::spawn(async move {
+            match block_store.get(&cid).await {
+                Ok(block) => {
+                    if let Some(tx) = request.response_tx.lock().await.take() {
+                        let _ = tx.send(block);
+                    }
+                }
+                Err(e) => warn!("BlockExc: Stored block {} could not be read: {}", cid, e),
+            }
+        });
+    }
+
+    fn deny_banned(&self, peer: PeerId) -> Result<(), libp2p::swarm::ConnectionDenied> {
+        if self.is_banned(&peer) {
+            debug!("BlockExc: Refusing connection from banned peer {}", peer);
+            return Err(libp2p::swarm::ConnectionDenied::new(
+                BlockExcError::PeerBanned(peer.to_string()),
+            ));
         }
+        Ok(())
     }
 }
 
@@ -1017,13 +2253,17 @@ impl libp2p::swarm::NetworkBehaviour for BlockExcBehaviour {
         _local_addr: &libp2p::Multiaddr,
         _remote_addr: &libp2p::Multiaddr,
     ) -> Result<libp2p::swarm::THandler<Self>, libp2p::swarm::ConnectionDenied> {
+        self.deny_banned(peer)?;
+        let limiter = self.peer_limiter(peer);
         Ok(BlockExcHandler::new(
             peer,
             self.block_store.clone(),
             self.mode.clone(),
             self.price_per_byte,
             self.metrics.clone(),
-        ))
+            self.config,
+        )
+        .with_inbound_limiter(limiter))
     }
 
     fn handle_established_outbound_connection(
@@ -1034,13 +2274,17 @@ impl libp2p::swarm::NetworkBehaviour for BlockExcBehaviour {
         _role_override: libp2p::core::Endpoint,
         _port_use: libp2p::core::transport::PortUse,
     ) -> Result<libp2p::swarm::THandler<Self>, libp2p::swarm::ConnectionDenied> {
+        self.deny_banned(peer)?;
+        let limiter = self.peer_limiter(peer);
         Ok(BlockExcHandler::new(
             peer,
             self.block_store.clone(),
             self.mode.clone(),
             self.price_per_byte,
             self.metrics.clone(),
-        ))
+            self.config,
+        )
+        .with_inbound_limiter(limiter))
     }
 
     fn on_swarm_event(&mut self, event: libp2p::swarm::FromSwarm) {
@@ -1048,11 +2292,65 @@ impl libp2p::swarm::NetworkBehaviour for BlockExcBehaviour {
             libp2p::swarm::FromSwarm::ConnectionEstablished(conn) => {
                 info!("BlockExc: Connection established with {}", conn.peer_id);
                 self.connected_peers.insert(conn.peer_id);
+                if let libp2p::core::ConnectedPoint::Dialer { address, .. } = conn.endpoint {
+                    self.remember_address(conn.peer_id, address);
+                }
+                if let Some((_, attempts)) = self.reconnect_peers.remove(&conn.peer_id) {
+                    info!(
+                        "BlockExc: Reconnected to {} after {} attempt(s)",
+                        conn.peer_id, attempts
+                    );
+                }
             }
             libp2p::swarm::FromSwarm::ConnectionClosed(conn) => {
                 if conn.remaining_established == 0 {
                     info!("BlockExc: All connections closed with {}", conn.peer_id);
                     self.connected_peers.remove(&conn.peer_id);
+                    self.peer_limiters.remove(&conn.peer_id);
+                    self.ping_rtts.remove(&conn.peer_id);
+                    self.peer_block_cache.remove(&conn.peer_id);
+                    self.peer_protocols.remove(&conn.peer_id);
+
+                    // Evicted and deliberately disconnected peers are
+                    // dropped on purpose; only reconnect peers we know how
+                    // to dial
+                    let intentional = self.intentional_disconnects.remove(&conn.peer_id);
+                    if !intentional && !self.is_banned(&conn.peer_id) {
+                        if let Some(addresses) = self.peer_addresses.get(&conn.peer_id) {
+                            self.reconnect_peers
+                                .insert(conn.peer_id, (addresses.clone(), 0));
+                            self.schedule_reconnect(conn.peer_id, 0);
+                        }
+                    }
+                }
+            }
+            // Identify reports the listen addresses of peers that dialed us,
+            // which are the only way to reconnect inbound peers
+            libp2p::swarm::FromSwarm::NewExternalAddrOfPeer(new_addr)
+                if self.connected_peers.contains(&new_addr.peer_id) =>
+            {
+                self.remember_address(new_addr.peer_id, new_addr.addr);
+            }
+            libp2p::swarm::FromSwarm::DialFailure(failure) => {
+                let Some(peer_id) = failure.peer_id else {
+                    return;
+                };
+                let Some(&(_, attempts)) = self.reconnect_peers.get(&peer_id) else {
+                    return;
+                };
+                if attempts >= MAX_RECONNECT_ATTEMPTS {
+                    warn!(
+                        "BlockExc: Giving up reconnecting to {} after {} attempts: {}",
+                        peer_id, attempts, failure.error
+                    );
+                    self.reconnect_peers.remove(&peer_id);
+                    self.peer_addresses.remove(&peer_id);
+                } else {
+                    debug!(
+                        "BlockExc: Reconnect attempt {} to {} failed: {}",
+                        attempts, peer_id, failure.error
+                    );
+                    self.schedule_reconnect(peer_id, attempts);
                 }
             }
             _ => {}
@@ -1066,39 +2364,30 @@ impl libp2p::swarm::NetworkBehaviour for BlockExcBehaviour {
         event: libp2p::swarm::THandlerOutEvent<Self>,
     ) {
         match event {
-            BlockExcToBehaviour::BlockReceived { cid, data } => {
+            BlockExcToBehaviour::BlocksReceived { blocks } => {
                 info!(
-                    "BlockExc behaviour: Received block {} from {} ({} bytes)",
-                    cid,
-                    peer_id,
-                    data.len()
+                    "BlockExc behaviour: Received {} block(s) from {}",
+                    blocks.len(),
+                    peer_id
                 );
+                for (_, cid) in &blocks {
+                    self.forget_want(cid);
+                }
 
-                // Store in block store
-                let block = crate::storage::Block { cid, data };
+                // Pending requests complete from the storage event once the
+                // blocks are stored; rejected blocks leave them waiting
                 let block_store = self.block_store.clone();
                 let metrics = self.metrics.clone();
-
-                // Complete pending request if exists
-                if let Some(request) = self.pending_requests.remove(&cid) {
-                    let response_tx = request.response_tx.clone();
-                    let block_clone = block.clone();
-                    tokio::spawn(async move {
-                        let mut tx_guard = response_tx.lock().await;
-                        if let Some(tx) = tx_guard.take() {
-                            let _ = tx.send(block_clone);
-                        }
-                    });
-                }
-
                 tokio::spawn(async move {
-                    match block_store.put(block.clone()).await {
-                        Ok(_) => {
-                            metrics.block_received(block.data.len());
-                        }
-                        Err(e) => {
-                            warn!("Failed to store received block: {}", e);
-                        }
+                    let received = blocks.len();
+                    match store_received_blocks(blocks, &block_store, &metrics).await {
+                        Ok(stored) if stored < received => warn!(
+                            "Dropped {} block(s) from {} that failed verification",
+                            received - stored,
+                            peer_id
+                        ),
+                        Ok(_) => {}
+                        Err(e) => warn!("Failed to store received blocks: {}", e),
                     }
                 });
             }
@@ -1109,7 +2398,27 @@ impl libp2p::swarm::NetworkBehaviour for BlockExcBehaviour {
                     if has_block { "has" } else { "doesn't have" },
                     cid
                 );
-                // TODO: Track which peers have which blocks for smarter routing
+                if has_block {
+                    self.content_router.record(cid, peer_id);
+                } else {
+                    self.content_router.remove(&cid, &peer_id);
+                }
+                self.record_presence(peer_id, cid, has_block);
+            }
+            BlockExcToBehaviour::RequestCompleted { cid, outcome } => {
+                debug!(
+                    "BlockExc behaviour: Request for {} to {} finished: {:?}",
+                    cid, peer_id, outcome
+                );
+                self.record_outcome(peer_id, &cid, outcome);
+            }
+            BlockExcToBehaviour::ProtocolNegotiated { version } => {
+                info!(
+                    "BlockExc behaviour: Speaking {} with {}",
+                    version.protocol_id(),
+                    peer_id
+                );
+                self.peer_protocols.insert(peer_id, version);
             }
         }
     }
@@ -1119,6 +2428,35 @@ impl libp2p::swarm::NetworkBehaviour for BlockExcBehaviour {
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<libp2p::swarm::ToSwarm<Self::ToSwarm, libp2p::swarm::THandlerInEvent<Self>>>
     {
+        if let Some(peer_id) = self.pending_evictions.pop_front() {
+            return std::task::Poll::Ready(libp2p::swarm::ToSwarm::CloseConnection {
+                peer_id,
+                connection: libp2p::swarm::CloseConnection::All,
+            });
+        }
+
+        // Redial lost peers whose backoff has elapsed
+        while let std::task::Poll::Ready(Some(peer_id)) = self.reconnect_timers.poll_next_unpin(cx)
+        {
+            if self.connected_peers.contains(&peer_id) || self.is_banned(&peer_id) {
+                self.reconnect_peers.remove(&peer_id);
+                continue;
+            }
+            let Some((addresses, attempts)) = self.reconnect_peers.get_mut(&peer_id) else {
+                continue;
+            };
+            *attempts += 1;
+            info!(
+                "BlockExc: Reconnecting to {} (attempt {}/{})",
+                peer_id, attempts, MAX_RECONNECT_ATTEMPTS
+            );
+            return std::task::Poll::Ready(libp2p::swarm::ToSwarm::Dial {
+                opts: libp2p::swarm::dial_opts::DialOpts::peer_id(peer_id)
+                    .addresses(addresses.clone())
+                    .build(),
+            });
+        }
+
         // Process pending handler events first
         if let Some((peer_id, event)) = self.pending_events.pop_front() {
             return std::task::Poll::Ready(libp2p::swarm::ToSwarm::NotifyHandler {
@@ -1128,21 +2466,68 @@ impl libp2p::swarm::NetworkBehaviour for BlockExcBehaviour {
             });
         }
 
+        // Re-broadcast queued wants
+        while let std::task::Poll::Ready(Some(cid)) = self.want_rx.poll_recv(cx) {
+            if let Err(e) = self.broadcast_want(cid) {
+                debug!("BlockExc: Cannot re-broadcast want for {}: {}", cid, e);
+            }
+        }
+        if let Some((peer_id, event)) = self.pending_events.pop_front() {
+            return std::task::Poll::Ready(libp2p::swarm::ToSwarm::NotifyHandler {
+                peer_id,
+                handler: libp2p::swarm::NotifyHandler::Any,
+                event,
+            });
+        }
+
+        // Answer pending requests for blocks that were stored locally
+        while let std::task::Poll::Ready(Some(event)) = self.storage_events.poll_next_unpin(cx) {
+            if let StorageEvent::BlockStored(cid) = event {
+                self.complete_from_store(cid);
+            }
+        }
+
         // Process incoming block requests
         while let std::task::Poll::Ready(Some(request)) = self.request_rx.poll_recv(cx) {
+            if request.is_abandoned() {
+                self.cancel_request(request.cid);
+                if let Some((peer_id, event)) = self.pending_events.pop_front() {
+                    return std::task::Poll::Ready(libp2p::swarm::ToSwarm::NotifyHandler {
+                        peer_id,
+                        handler: libp2p::swarm::NotifyHandler::Any,
+                        event,
+                    });
+                }
+                continue;
+            }
+
+            if let Err(e) = self.pending_limit.try_insert(request.cid) {
+                // Dropping the request fails its waiter right away
+                warn!(
+                    "BlockExc behaviour: {} ({} pending), dropping request for block {}",
+                    e,
+                    self.pending_limit.len(),
+                    request.cid
+                );
+                continue;
+            }
+
+            let targets = self.target_peers(&request.cid);
             info!(
-                "BlockExc behaviour: Received request for block {} from {} connected peers",
+                "BlockExc behaviour: Received request for block {}, asking {} of {} connected peers",
                 request.cid,
+                targets.len(),
                 self.connected_peers.len()
             );
 
             // Store the pending request
             self.pending_requests.insert(request.cid, request.clone());
+            self.record_want(request.cid, &targets, false);
 
-            // Queue RequestBlock events for all connected peers
-            for peer_id in &self.connected_peers {
+            // Queue RequestBlock events, preferring peers known to have the block
+            for peer_id in targets {
                 self.pending_events.push_back((
-                    *peer_id,
+                    peer_id,
                     BlockExcFromBehaviour::RequestBlock { cid: request.cid },
                 ));
             }
@@ -1184,6 +2569,75 @@ mod tests {
         assert!(matches!(result.unwrap_err(), BlockExcError::NoPeers));
     }
 
+    #[tokio::test]
+    async fn test_frame_version_byte() {
+        // v1 frames are plain, as Archivist sends them
+        let mut buf = Vec::new();
+        write_length_prefixed(&mut buf, b"hello").await.unwrap();
+        assert_eq!(buf, [5, b'h', b'e', b'l', b'l', b'o']);
+        let mut reader = futures::io::Cursor::new(buf);
+        assert_eq!(
+            read_length_prefixed(&mut reader, 1024).await.unwrap(),
+            b"hello"
+        );
+
+        // Versioned frames surface their version byte
+        let mut buf = Vec::new();
+        write_versioned_frame(&mut buf, 0x03, b"hello")
+            .await
+            .unwrap();
+        assert_eq!(buf, [6, 0x03, b'h', b'e', b'l', b'l', b'o']);
+        let mut reader = futures::io::Cursor::new(buf);
+        let (version, data) = read_versioned_frame(&mut reader, 1024).await.unwrap();
+        assert_eq!((version, data.as_slice()), (0x03, b"hello".as_slice()));
+
+        // A zero-length frame has no room for the version byte
+        let mut reader = futures::io::Cursor::new(vec![0u8]);
+        assert!(read_versioned_frame(&mut reader, 1024).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_message_size_limit() {
+        let limit = BlockExcConfig::default().max_message_bytes;
+        let read = |payload_len: usize| async move {
+            let mut buf = Vec::new();
+            write_length_prefixed(&mut buf, &vec![7u8; payload_len])
+                .await
+                .unwrap();
+            read_length_prefixed(&mut futures::io::Cursor::new(buf), limit).await
+        };
+
+        assert_eq!(read(limit - 1).await.unwrap().len(), limit - 1);
+        assert_eq!(read(limit).await.unwrap().len(), limit);
+        assert_eq!(
+            read(limit + 1).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        // An empty message is malformed
+        assert_eq!(
+            read(0).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_peer_limiters_shared_per_peer() {
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let peer = PeerId::random();
+        assert!(behaviour.peer_limiter(peer).is_none());
+
+        behaviour.set_inbound_rate_limit(Some(4096));
+        let first = behaviour.peer_limiter(peer).unwrap();
+        let second = behaviour.peer_limiter(peer).unwrap();
+        let other = behaviour.peer_limiter(PeerId::random()).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert_eq!(first.bytes_per_second(), 4096);
+
+        behaviour.set_inbound_rate_limit(None);
+        assert!(behaviour.peer_limiter(peer).is_none());
+    }
+
     #[test]
     fn test_broadcast_want_no_peers() {
         let (mut behaviour, _tx) = create_test_behaviour();
@@ -1212,11 +2666,7 @@ mod tests {
         assert_eq!(behaviour.pending_events.len(), 1);
         let (queued_peer, event) = behaviour.pending_events.front().unwrap();
         assert_eq!(*queued_peer, peer_id);
-        match event {
-            BlockExcFromBehaviour::RequestBlock { cid } => {
-                assert_eq!(*cid, test_cid);
-            }
-        }
+        assert!(matches!(event, BlockExcFromBehaviour::RequestBlock { cid } if *cid == test_cid));
     }
 
     #[test]
@@ -1242,11 +2692,9 @@ mod tests {
 
         // Verify all events are for the correct CID
         for (_, event) in &behaviour.pending_events {
-            match event {
-                BlockExcFromBehaviour::RequestBlock { cid } => {
-                    assert_eq!(*cid, test_cid);
-                }
-            }
+            assert!(
+                matches!(event, BlockExcFromBehaviour::RequestBlock { cid } if *cid == test_cid)
+            );
         }
     }
 
@@ -1278,6 +2726,229 @@ mod tests {
         assert!(peers.contains(&peer2));
     }
 
+    #[test]
+    fn test_presence_routes_wants_to_known_peer() {
+        use libp2p::swarm::NetworkBehaviour;
+
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let test_cid = blake3_cid(b"routed data").unwrap();
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+
+        behaviour.connected_peers.insert(peer1);
+        behaviour.connected_peers.insert(peer2);
+
+        // Without routing information the want goes to both peers
+        assert_eq!(behaviour.broadcast_want(test_cid).unwrap(), 2);
+        behaviour.pending_events.clear();
+
+        // peer2 announces it has the block
+        behaviour.on_connection_handler_event(
+            peer2,
+            libp2p::swarm::ConnectionId::new_unchecked(0),
+            BlockExcToBehaviour::BlockPresence {
+                cid: test_cid,
+                has_block: true,
+            },
+        );
+        assert_eq!(behaviour.content_router().peers_for(&test_cid), vec![peer2]);
+
+        assert_eq!(behaviour.broadcast_want(test_cid).unwrap(), 1);
+        assert_eq!(behaviour.pending_events.len(), 1);
+        assert_eq!(behaviour.pending_events[0].0, peer2);
+        behaviour.pending_events.clear();
+
+        // A DontHave withdraws the route and we fall back to broadcasting,
+        // now without the peer that just said it lacks the block
+        behaviour.on_connection_handler_event(
+            peer2,
+            libp2p::swarm::ConnectionId::new_unchecked(0),
+            BlockExcToBehaviour::BlockPresence {
+                cid: test_cid,
+                has_block: false,
+            },
+        );
+        assert_eq!(behaviour.broadcast_want(test_cid).unwrap(), 1);
+        assert_eq!(behaviour.pending_events[0].0, peer1);
+    }
+
+    #[test]
+    fn test_dont_have_prevents_retransmission() {
+        use libp2p::swarm::NetworkBehaviour;
+
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let wanted = blake3_cid(b"wanted").unwrap();
+        let other = blake3_cid(b"other").unwrap();
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+        behaviour.connected_peers.insert(peer1);
+        behaviour.connected_peers.insert(peer2);
+
+        let presence = |behaviour: &mut BlockExcBehaviour, peer, cid, has_block| {
+            behaviour.on_connection_handler_event(
+                peer,
+                libp2p::swarm::ConnectionId::new_unchecked(0),
+                BlockExcToBehaviour::BlockPresence { cid, has_block },
+            );
+        };
+        presence(&mut behaviour, peer1, wanted, false);
+        presence(&mut behaviour, peer1, other, true);
+        presence(&mut behaviour, peer2, wanted, false);
+        assert_eq!(behaviour.cache_stats(), (1, 2));
+
+        // Neither peer is asked for the block again, other blocks still are
+        assert!(matches!(
+            behaviour.broadcast_want(wanted),
+            Err(BlockExcError::NoPeers)
+        ));
+        assert!(behaviour.pending_events.is_empty());
+        assert_eq!(behaviour.broadcast_want(other).unwrap(), 1);
+        behaviour.pending_events.clear();
+
+        // A later Have replaces the DontHave
+        presence(&mut behaviour, peer2, wanted, true);
+        assert_eq!(behaviour.cache_stats(), (2, 1));
+        assert_eq!(behaviour.broadcast_want(wanted).unwrap(), 1);
+        assert_eq!(behaviour.pending_events[0].0, peer2);
+
+        // Entries go away with the peer
+        behaviour.evict_peer(peer1, EvictionReason::TooManyFailures);
+        assert_eq!(behaviour.cache_stats(), (1, 0));
+    }
+
+    #[test]
+    fn test_dont_have_expires() {
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let wanted = blake3_cid(b"wanted").unwrap();
+        let peer = PeerId::random();
+        behaviour.connected_peers.insert(peer);
+        behaviour.record_presence(peer, wanted, false);
+        assert!(behaviour.broadcast_want(wanted).is_err());
+
+        // Once the answer is older than the TTL the peer is asked again
+        let answered = std::time::Instant::now() - DONT_HAVE_TTL;
+        behaviour
+            .peer_block_cache
+            .get_mut(&peer)
+            .unwrap()
+            .1
+            .insert(wanted, answered);
+        assert_eq!(behaviour.broadcast_want(wanted).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_presence_cache_is_bounded_per_peer() {
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let peer = PeerId::random();
+        behaviour.connected_peers.insert(peer);
+        let cids: Vec<Cid> = (0..=MAX_PRESENCES_PER_PEER + 1)
+            .map(|i| blake3_cid(&i.to_le_bytes()).unwrap())
+            .collect();
+        for cid in &cids[..MAX_PRESENCES_PER_PEER] {
+            behaviour.record_presence(peer, *cid, false);
+        }
+        // A repeated answer counts as the newest
+        behaviour.record_presence(peer, cids[0], false);
+        for cid in &cids[MAX_PRESENCES_PER_PEER..] {
+            behaviour.record_presence(peer, *cid, false);
+        }
+        assert_eq!(behaviour.cache_stats(), (0, MAX_PRESENCES_PER_PEER));
+        assert!(behaviour.peer_lacks(&peer, &cids[0]));
+        assert!(!behaviour.peer_lacks(&peer, &cids[1]));
+        assert!(!behaviour.peer_lacks(&peer, &cids[2]));
+        assert!(behaviour.peer_lacks(&peer, &cids[3]));
+
+        // Answers that flip back and forth don't grow the queue unbounded
+        for _ in 0..4 * MAX_PRESENCES_PER_PEER {
+            behaviour.record_presence(peer, cids[0], true);
+            behaviour.record_presence(peer, cids[0], false);
+        }
+        let (has, dont_have) = &behaviour.peer_block_cache[&peer];
+        assert!(has.order.len() <= 2 * MAX_PRESENCES_PER_PEER);
+        assert!(dont_have.order.len() <= 2 * MAX_PRESENCES_PER_PEER);
+        assert_eq!(behaviour.cache_stats(), (0, MAX_PRESENCES_PER_PEER));
+    }
+
+    #[test]
+    fn test_routed_peer_ignored_when_disconnected() {
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let test_cid = blake3_cid(b"routed data").unwrap();
+        let connected = PeerId::random();
+        let gone = PeerId::random();
+
+        behaviour.connected_peers.insert(connected);
+        behaviour.content_router().record(test_cid, gone);
+
+        assert_eq!(behaviour.broadcast_want(test_cid).unwrap(), 1);
+        assert_eq!(behaviour.pending_events[0].0, connected);
+    }
+
+    #[test]
+    fn test_best_peer_receives_request() {
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let test_cid = blake3_cid(b"routed data").unwrap();
+        let flaky = PeerId::random();
+        let reliable = PeerId::random();
+        let unknown = PeerId::random();
+
+        for (peer, served, failed) in [(flaky, 2, 6), (reliable, 9, 1), (unknown, 0, 0)] {
+            behaviour.connected_peers.insert(peer);
+            behaviour.content_router().record(test_cid, peer);
+            behaviour
+                .peer_scores
+                .insert(peer, PeerScore { served, failed });
+        }
+
+        behaviour.get_block_from_best_peer(test_cid).unwrap();
+        assert_eq!(behaviour.pending_events.len(), 1);
+        let (queued_peer, event) = &behaviour.pending_events[0];
+        assert_eq!(*queued_peer, reliable);
+        assert!(matches!(event, BlockExcFromBehaviour::RequestBlock { cid } if *cid == test_cid));
+    }
+
+    #[test]
+    fn test_ping_rtt_breaks_score_ties() {
+        use std::time::Duration;
+
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let test_cid = blake3_cid(b"routed data").unwrap();
+        let slow = PeerId::random();
+        let fast = PeerId::random();
+        let unpinged = PeerId::random();
+
+        for peer in [slow, fast, unpinged] {
+            behaviour.connected_peers.insert(peer);
+            behaviour.content_router().record(test_cid, peer);
+        }
+        behaviour.record_ping_rtt(slow, Duration::from_millis(80));
+        behaviour.record_ping_rtt(fast, Duration::from_millis(20));
+        assert_eq!(behaviour.ping_rtt(&fast), Some(Duration::from_millis(20)));
+        assert_eq!(behaviour.ping_rtt(&unpinged), None);
+        assert_eq!(behaviour.best_latency_peer(), Some(fast));
+
+        behaviour.get_block_from_best_peer(test_cid).unwrap();
+        assert_eq!(behaviour.pending_events[0].0, fast);
+
+        // Disconnected peers no longer count
+        behaviour.connected_peers.remove(&fast);
+        assert_eq!(behaviour.best_latency_peer(), Some(slow));
+    }
+
+    #[test]
+    fn test_best_peer_falls_back_to_broadcast() {
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let test_cid = blake3_cid(b"unrouted data").unwrap();
+        assert!(matches!(
+            behaviour.get_block_from_best_peer(test_cid),
+            Err(BlockExcError::NoPeers)
+        ));
+
+        behaviour.connected_peers.insert(PeerId::random());
+        behaviour.connected_peers.insert(PeerId::random());
+        behaviour.get_block_from_best_peer(test_cid).unwrap();
+        assert_eq!(behaviour.pending_events.len(), 2);
+    }
+
     #[test]
     fn test_multiple_requests_queue_correctly() {
         let (mut behaviour, _tx) = create_test_behaviour();
@@ -1303,11 +2974,775 @@ mod tests {
         assert_eq!(*p1, peer1);
         assert_eq!(*p2, peer2);
 
-        match evt1 {
-            BlockExcFromBehaviour::RequestBlock { cid } => assert_eq!(*cid, test_cid1),
+        assert!(matches!(evt1, BlockExcFromBehaviour::RequestBlock { cid } if *cid == test_cid1));
+        assert!(matches!(evt2, BlockExcFromBehaviour::RequestBlock { cid } if *cid == test_cid2));
+    }
+    #[test]
+    fn test_want_sender_broadcasts_on_poll() {
+        use libp2p::swarm::{NetworkBehaviour, NotifyHandler, ToSwarm};
+
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let test_cid = blake3_cid(b"retry me").unwrap();
+        let peer_id = PeerId::random();
+        behaviour.connected_peers.insert(peer_id);
+
+        behaviour.want_sender().send(test_cid).unwrap();
+
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        match behaviour.poll(&mut cx) {
+            std::task::Poll::Ready(ToSwarm::NotifyHandler {
+                peer_id: target,
+                handler: NotifyHandler::Any,
+                event: BlockExcFromBehaviour::RequestBlock { cid },
+            }) => {
+                assert_eq!(target, peer_id);
+                assert_eq!(cid, test_cid);
+            }
+            other => panic!("expected a RequestBlock, got {:?}", other.is_ready()),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_client_rebroadcasts_unanswered_wants() {
+        use libp2p::swarm::{NetworkBehaviour, ToSwarm};
+        use std::time::Duration;
+
+        let (mut behaviour, tx) = create_test_behaviour();
+        let peer_id = PeerId::random();
+        behaviour.connected_peers.insert(peer_id);
+
+        let pending = PendingBlocksManager::new();
+        let want_tx = behaviour.want_sender();
+        pending.on_retry(move |cid| {
+            let _ = want_tx.send(cid);
+        });
+        let client = BlockExcClient::new(Arc::new(BlockStore::new()), Metrics::new(), 3, tx)
+            .with_timeout(Duration::from_secs(4))
+            .with_want_retries(pending.clone());
+
+        let block = crate::storage::Block::new(b"slow block".to_vec()).unwrap();
+        let cid = block.cid;
+        let request = tokio::spawn(async move { client.request_block(cid).await });
+        while !pending.is_pending(&cid) {
+            tokio::task::yield_now().await;
+        }
+
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut sent_wants = || {
+            let mut wants = 0;
+            while let std::task::Poll::Ready(event) = behaviour.poll(&mut cx) {
+                if matches!(
+                    event,
+                    ToSwarm::NotifyHandler {
+                        event: BlockExcFromBehaviour::RequestBlock { .. },
+                        ..
+                    }
+                ) {
+                    wants += 1;
+                }
+            }
+            wants
+        };
+        assert_eq!(sent_wants(), 1);
+
+        // Each attempt gets a quarter of the request timeout
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(pending.expire_timed_out(), 1);
+        assert_eq!(sent_wants(), 1);
+        assert_eq!(behaviour.wantlist_snapshot()[0].retry_count, 1);
+
+        let responder = behaviour.pending_requests[&cid].response_tx.clone();
+        let _ = responder.lock().await.take().unwrap().send(block);
+        assert_eq!(request.await.unwrap().unwrap().cid, cid);
+        assert!(!pending.is_pending(&cid));
+    }
+
+    #[test]
+    fn test_failing_peer_is_evicted_after_threshold() {
+        use libp2p::swarm::{CloseConnection, ConnectionId, NetworkBehaviour, ToSwarm};
+
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let peer_id = PeerId::random();
+        behaviour.connected_peers.insert(peer_id);
+        let test_cid = blake3_cid(b"corrupted").unwrap();
+        let complete = |behaviour: &mut BlockExcBehaviour, outcome| {
+            behaviour.on_connection_handler_event(
+                peer_id,
+                ConnectionId::new_unchecked(0),
+                BlockExcToBehaviour::RequestCompleted {
+                    cid: test_cid,
+                    outcome,
+                },
+            );
+        };
+
+        complete(&mut behaviour, DeliveryOutcome::Delivered);
+        for _ in 0..EVICTION_MIN_FAILURES {
+            complete(&mut behaviour, DeliveryOutcome::CidMismatch);
         }
-        match evt2 {
-            BlockExcFromBehaviour::RequestBlock { cid } => assert_eq!(*cid, test_cid2),
+        // Missing blocks the peer never announced are not held against it
+        complete(&mut behaviour, DeliveryOutcome::Missing);
+        assert_eq!(
+            behaviour.peer_score(&peer_id),
+            PeerScore {
+                served: 1,
+                failed: EVICTION_MIN_FAILURES
+            }
+        );
+        assert!(!behaviour.is_banned(&peer_id));
+
+        behaviour.content_router().record(test_cid, peer_id);
+        complete(&mut behaviour, DeliveryOutcome::Missing);
+        assert!(behaviour.is_banned(&peer_id));
+        assert_eq!(behaviour.connected_peer_count(), 0);
+
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(matches!(
+            behaviour.poll(&mut cx),
+            std::task::Poll::Ready(ToSwarm::CloseConnection {
+                peer_id: closed,
+                connection: CloseConnection::All,
+            }) if closed == peer_id
+        ));
+        assert!(behaviour.poll(&mut cx).is_pending());
+
+        assert!(behaviour.unban_peer(&peer_id));
+        assert!(!behaviour.is_banned(&peer_id));
+        assert_eq!(behaviour.peer_score(&peer_id), PeerScore::default());
+    }
+
+    #[test]
+    fn test_reliable_peer_is_not_evicted() {
+        let score = PeerScore {
+            served: 40,
+            failed: 15,
+        };
+        assert!(!score.should_evict());
+        let score = PeerScore {
+            served: 0,
+            failed: EVICTION_MIN_FAILURES,
+        };
+        assert!(!score.should_evict());
+        let score = PeerScore {
+            served: 0,
+            failed: EVICTION_MIN_FAILURES + 1,
+        };
+        assert!(score.should_evict());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_pending_requests_fails_waiting_clients() {
+        let block_store = Arc::new(BlockStore::new());
+        let (mut behaviour, tx) = BlockExcBehaviour::new(
+            block_store.clone(),
+            "altruistic".to_string(),
+            0,
+            Metrics::new(),
+        );
+        let late_client = BlockExcClient::new(block_store.clone(), Metrics::new(), 3, tx.clone());
+        let client = BlockExcClient::new(block_store, Metrics::new(), 3, tx);
+
+        let queued = blake3_cid(b"queued").unwrap();
+        let in_flight = blake3_cid(b"in flight").unwrap();
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        behaviour.pending_requests.insert(
+            in_flight,
+            BlockRequest {
+                cid: in_flight,
+                response_tx: Arc::new(tokio::sync::Mutex::new(Some(response_tx))),
+            },
+        );
+
+        let waiting = tokio::spawn(async move { client.request_block(queued).await });
+        while behaviour.request_rx.is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        let start = tokio::time::Instant::now();
+        assert_eq!(behaviour.drain_pending_requests(), 2);
+        assert!(behaviour.pending_requests.is_empty());
+        assert!(response_rx.await.is_err());
+        // Waiters fail with Shutdown right away instead of timing out
+        assert!(matches!(
+            waiting.await.unwrap(),
+            Err(BlockExcError::Shutdown)
+        ));
+        assert_eq!(start.elapsed(), std::time::Duration::ZERO);
+
+        // Requests made after the drain are rejected
+        assert!(matches!(
+            late_client.request_block(in_flight).await,
+            Err(BlockExcError::Shutdown)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_locally_stored_block_completes_pending_request() {
+        use libp2p::swarm::NetworkBehaviour;
+
+        let block_store = Arc::new(BlockStore::new());
+        let (mut behaviour, _tx) = BlockExcBehaviour::new(
+            block_store.clone(),
+            "altruistic".to_string(),
+            0,
+            Metrics::new(),
+        );
+        let block = crate::storage::Block::new(b"uploaded meanwhile".to_vec()).unwrap();
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        behaviour.pending_requests.insert(
+            block.cid,
+            BlockRequest {
+                cid: block.cid,
+                response_tx: Arc::new(tokio::sync::Mutex::new(Some(response_tx))),
+            },
+        );
+
+        block_store.put(block.clone()).await.unwrap();
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(behaviour.poll(&mut cx).is_pending());
+
+        assert!(behaviour.pending_requests.is_empty());
+        assert_eq!(response_rx.await.unwrap(), block);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_request_timeout_cancels_pending_request() {
+        use libp2p::swarm::{NetworkBehaviour, NotifyHandler, ToSwarm};
+
+        let block_store = Arc::new(BlockStore::new());
+        let (mut behaviour, tx) = BlockExcBehaviour::new(
+            block_store.clone(),
+            "altruistic".to_string(),
+            0,
+            Metrics::new(),
+        );
+        let peer_id = PeerId::random();
+        behaviour.connected_peers.insert(peer_id);
+        let client = BlockExcClient::new(block_store, Metrics::new(), 3, tx)
+            .with_timeout(std::time::Duration::from_secs(60));
+        let test_cid = blake3_cid(b"never delivered").unwrap();
+
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut next_event = |behaviour: &mut BlockExcBehaviour| match behaviour.poll(&mut cx) {
+            std::task::Poll::Ready(ToSwarm::NotifyHandler {
+                peer_id: target,
+                handler: NotifyHandler::Any,
+                event,
+            }) => {
+                assert_eq!(target, peer_id);
+                event
+            }
+            other => panic!("expected a handler event, got {:?}", other.is_ready()),
+        };
+
+        let started = tokio::time::Instant::now();
+        let waiting = tokio::spawn(async move {
+            client
+                .request_block_with_timeout(test_cid, std::time::Duration::from_secs(5))
+                .await
+        });
+        while behaviour.request_rx.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert!(matches!(
+            next_event(&mut behaviour),
+            BlockExcFromBehaviour::RequestBlock { cid } if cid == test_cid
+        ));
+        assert!(behaviour.pending_requests.contains_key(&test_cid));
+
+        // The per-request timeout overrides the client's 60s default
+        assert!(matches!(
+            waiting.await.unwrap(),
+            Err(BlockExcError::Timeout)
+        ));
+        assert_eq!(started.elapsed(), std::time::Duration::from_secs(5));
+
+        assert!(matches!(
+            next_event(&mut behaviour),
+            BlockExcFromBehaviour::CancelRequest { cid } if cid == test_cid
+        ));
+        assert!(behaviour.pending_requests.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lost_peer_is_reconnected_with_backoff() {
+        use libp2p::core::transport::PortUse;
+        use libp2p::core::{ConnectedPoint, Endpoint};
+        use libp2p::swarm::behaviour::{ConnectionClosed, ConnectionEstablished, DialFailure};
+        use libp2p::swarm::{ConnectionId, DialError, FromSwarm, NetworkBehaviour, ToSwarm};
+        use std::time::Duration;
+
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let peer_id = PeerId::random();
+        let address: libp2p::Multiaddr = "/ip4/10.0.0.1/tcp/8070".parse().unwrap();
+        let endpoint = ConnectedPoint::Dialer {
+            address: address.clone(),
+            role_override: Endpoint::Dialer,
+            port_use: PortUse::Reuse,
+        };
+        let connection_id = ConnectionId::new_unchecked(0);
+        let established = FromSwarm::ConnectionEstablished(ConnectionEstablished {
+            peer_id,
+            connection_id,
+            endpoint: &endpoint,
+            failed_addresses: &[],
+            other_established: 0,
+        });
+        let closed = FromSwarm::ConnectionClosed(ConnectionClosed {
+            peer_id,
+            connection_id,
+            endpoint: &endpoint,
+            cause: None,
+            remaining_established: 0,
+        });
+        let dial_error = DialError::Aborted;
+        let dial_failed = FromSwarm::DialFailure(DialFailure {
+            peer_id: Some(peer_id),
+            error: &dial_error,
+            connection_id,
+        });
+
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        // Advance time until the behaviour dials the lost peer, returning the wait
+        let mut wait_for_dial = async |behaviour: &mut BlockExcBehaviour| {
+            let started = tokio::time::Instant::now();
+            loop {
+                match behaviour.poll(&mut cx) {
+                    std::task::Poll::Ready(ToSwarm::Dial { opts }) => {
+                        assert_eq!(opts.get_peer_id(), Some(peer_id));
+                        return started.elapsed();
+                    }
+                    std::task::Poll::Ready(_) => panic!("expected a dial"),
+                    std::task::Poll::Pending => {
+                        tokio::time::advance(Duration::from_millis(100)).await
+                    }
+                }
+            }
+        };
+
+        // A lost peer is redialed after 1s, and reconnecting clears its state
+        behaviour.on_swarm_event(established);
+        behaviour.on_swarm_event(closed);
+        assert_eq!(wait_for_dial(&mut behaviour).await, Duration::from_secs(1));
+        behaviour.on_swarm_event(established);
+        assert!(behaviour.reconnect_peers.is_empty());
+
+        // Failed dials back off exponentially until the attempts run out
+        behaviour.on_swarm_event(closed);
+        for expected_secs in [1, 2, 4, 8, 16] {
+            assert_eq!(
+                wait_for_dial(&mut behaviour).await,
+                Duration::from_secs(expected_secs)
+            );
+            behaviour.on_swarm_event(dial_failed);
+        }
+        assert!(behaviour.reconnect_peers.is_empty());
+        assert!(behaviour.reconnect_timers.is_empty());
+        assert!(behaviour.peer_addresses.is_empty());
+
+        // Peers disconnected on purpose are not redialed
+        behaviour.on_swarm_event(established);
+        behaviour.expect_disconnect(peer_id);
+        behaviour.on_swarm_event(closed);
+        assert!(behaviour.reconnect_peers.is_empty());
+        assert!(behaviour.intentional_disconnects.is_empty());
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(behaviour.poll(&mut cx).is_pending());
+
+        // Only the most recent addresses of a peer are remembered
+        for port in 0..2 * MAX_ADDRESSES_PER_PEER as u16 {
+            let address = format!("/ip4/10.0.0.1/tcp/{}", port).parse().unwrap();
+            behaviour.remember_address(peer_id, &address);
+        }
+        let addresses = &behaviour.peer_addresses[&peer_id];
+        assert_eq!(addresses.len(), MAX_ADDRESSES_PER_PEER);
+        assert_eq!(
+            addresses.last().unwrap().to_string(),
+            format!("/ip4/10.0.0.1/tcp/{}", 2 * MAX_ADDRESSES_PER_PEER - 1)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_inbound_peer_is_reconnected_at_identified_address() {
+        use libp2p::core::ConnectedPoint;
+        use libp2p::swarm::behaviour::{
+            ConnectionClosed, ConnectionEstablished, NewExternalAddrOfPeer,
+        };
+        use libp2p::swarm::{ConnectionId, FromSwarm, NetworkBehaviour, ToSwarm};
+
+        let (mut behaviour, _tx) = create_test_behaviour();
+        let peer_id = PeerId::random();
+        let listen_addr: libp2p::Multiaddr = "/ip4/10.0.0.2/tcp/8070".parse().unwrap();
+        let endpoint = ConnectedPoint::Listener {
+            local_addr: "/ip4/10.0.0.1/tcp/8070".parse().unwrap(),
+            send_back_addr: "/ip4/10.0.0.2/tcp/53412".parse().unwrap(),
+        };
+        let connection_id = ConnectionId::new_unchecked(0);
+        behaviour.on_swarm_event(FromSwarm::ConnectionEstablished(ConnectionEstablished {
+            peer_id,
+            connection_id,
+            endpoint: &endpoint,
+            failed_addresses: &[],
+            other_established: 0,
+        }));
+        // The ephemeral port an inbound peer dialed from is not remembered
+        assert!(behaviour.peer_addresses.is_empty());
+
+        // Addresses of peers we are not connected to are ignored
+        behaviour.on_swarm_event(FromSwarm::NewExternalAddrOfPeer(NewExternalAddrOfPeer {
+            peer_id: PeerId::random(),
+            addr: &listen_addr,
+        }));
+        behaviour.on_swarm_event(FromSwarm::NewExternalAddrOfPeer(NewExternalAddrOfPeer {
+            peer_id,
+            addr: &listen_addr,
+        }));
+        assert_eq!(behaviour.peer_addresses.len(), 1);
+
+        behaviour.on_swarm_event(FromSwarm::ConnectionClosed(ConnectionClosed {
+            peer_id,
+            connection_id,
+            endpoint: &endpoint,
+            cause: None,
+            remaining_established: 0,
+        }));
+        assert_eq!(
+            behaviour.reconnect_peers.get(&peer_id),
+            Some(&(vec![listen_addr], 0))
+        );
+
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let started = tokio::time::Instant::now();
+        loop {
+            match behaviour.poll(&mut cx) {
+                std::task::Poll::Ready(ToSwarm::Dial { opts }) => {
+                    assert_eq!(opts.get_peer_id(), Some(peer_id));
+                    break;
+                }
+                std::task::Poll::Ready(_) => panic!("expected a dial"),
+                std::task::Poll::Pending => {
+                    tokio::time::advance(std::time::Duration::from_millis(100)).await
+                }
+            }
+        }
+        assert_eq!(started.elapsed(), std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_pending_limit_are_dropped() {
+        use libp2p::swarm::NetworkBehaviour;
+
+        let (mut behaviour, tx) = create_test_behaviour();
+        behaviour.set_config(BlockExcConfig {
+            max_pending_requests: 3,
+            ..BlockExcConfig::default()
+        });
+        behaviour.connected_peers.insert(PeerId::random());
+
+        let mut receivers = Vec::new();
+        for i in 0..4 {
+            let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+            tx.send(BlockRequest {
+                cid: blake3_cid(format!("limited {}", i).as_bytes()).unwrap(),
+                response_tx: Arc::new(tokio::sync::Mutex::new(Some(response_tx))),
+            })
+            .unwrap();
+            receivers.push(response_rx);
+        }
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        while behaviour.poll(&mut cx).is_ready() {}
+
+        assert_eq!(behaviour.pending_requests.len(), 3);
+        assert_eq!(behaviour.pending_limit.remaining_capacity(), 0);
+        // The fourth request was dropped, failing its waiter
+        let dropped = receivers.pop().unwrap();
+        assert!(dropped.await.is_err());
+        for mut receiver in receivers {
+            assert!(matches!(
+                receiver.try_recv(),
+                Err(tokio::sync::oneshot::error::TryRecvError::Empty)
+            ));
+        }
+
+        // Draining frees every slot
+        behaviour.drain_pending_requests();
+        assert_eq!(behaviour.pending_limit.remaining_capacity(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_wantlist_snapshot_tracks_pending_requests() {
+        use libp2p::swarm::{ConnectionId, NetworkBehaviour};
+
+        let (mut behaviour, tx) = create_test_behaviour();
+        let peer_id = PeerId::random();
+        behaviour.connected_peers.insert(peer_id);
+        let block = crate::storage::Block::new(b"wanted block".to_vec()).unwrap();
+
+        let (response_tx, _response_rx) = tokio::sync::oneshot::channel();
+        tx.send(BlockRequest {
+            cid: block.cid,
+            response_tx: Arc::new(tokio::sync::Mutex::new(Some(response_tx))),
+        })
+        .unwrap();
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(behaviour.poll(&mut cx).is_ready());
+
+        let snapshot = behaviour.wantlist_snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].cid, block.cid);
+        assert_eq!(snapshot[0].requested_from, vec![peer_id]);
+        assert_eq!(snapshot[0].retry_count, 0);
+
+        // Re-broadcasting the want counts as a retry
+        behaviour.broadcast_want(block.cid).unwrap();
+        assert_eq!(behaviour.wantlist_snapshot()[0].retry_count, 1);
+
+        behaviour.on_connection_handler_event(
+            peer_id,
+            ConnectionId::new_unchecked(0),
+            BlockExcToBehaviour::BlocksReceived {
+                blocks: vec![(block.data, block.cid)],
+            },
+        );
+        assert!(behaviour.wantlist_snapshot().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_blocks_received_are_stored_in_one_batch() {
+        use libp2p::swarm::{ConnectionId, NetworkBehaviour};
+
+        let block_store = Arc::new(BlockStore::new());
+        let metrics = Metrics::new();
+        let (mut behaviour, tx) = BlockExcBehaviour::new(
+            block_store.clone(),
+            "altruistic".to_string(),
+            0,
+            metrics.clone(),
+        );
+        let peer_id = PeerId::random();
+        behaviour.connected_peers.insert(peer_id);
+        let wanted = crate::storage::Block::new(b"wanted block".to_vec()).unwrap();
+        let other = crate::storage::Block::new(b"other block".to_vec()).unwrap();
+        let forged = blake3_cid(b"forged block").unwrap();
+
+        let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+        tx.send(BlockRequest {
+            cid: wanted.cid,
+            response_tx: Arc::new(tokio::sync::Mutex::new(Some(response_tx))),
+        })
+        .unwrap();
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        assert!(behaviour.poll(&mut cx).is_ready());
+
+        behaviour.on_connection_handler_event(
+            peer_id,
+            ConnectionId::new_unchecked(0),
+            BlockExcToBehaviour::BlocksReceived {
+                blocks: vec![
+                    (wanted.data.clone(), wanted.cid),
+                    (other.data.clone(), other.cid),
+                    (b"tampered".to_vec(), forged),
+                ],
+            },
+        );
+        // Metrics are counted once the batch is written
+        while metrics.blocks_received() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert!(block_store.has(&wanted.cid).await);
+        assert!(block_store.has(&other.cid).await);
+        assert!(!block_store.has(&forged).await);
+        assert_eq!(metrics.blocks_received(), 2);
+
+        // The pending request completes from the storage event
+        while behaviour.poll(&mut cx).is_ready() {}
+        let block = response_rx.await.unwrap();
+        assert_eq!(block.data, wanted.data);
+    }
+
+    #[test]
+    fn test_poll_close_waits_for_outbound_cancels() {
+        use libp2p::swarm::ConnectionHandler;
+
+        let mut handler = BlockExcHandler::new(
+            PeerId::random(),
+            Arc::new(BlockStore::new()),
+            "altruistic".to_string(),
+            0,
+            Metrics::new(),
+            BlockExcConfig::default(),
+        );
+        let mut cx = std::task::Context::from_waker(futures::task::noop_waker_ref());
+
+        // With no outbound streams there is nothing to cancel
+        assert!(matches!(
+            handler.poll_close(&mut cx),
+            std::task::Poll::Ready(None)
+        ));
+
+        // An open stream is told to cancel and holds the close until done
+        let stream = handler.closing.subscribe();
+        assert!(handler.poll_close(&mut cx).is_pending());
+        assert!(*stream.borrow());
+        drop(stream);
+        assert!(matches!(
+            handler.poll_close(&mut cx),
+            std::task::Poll::Ready(None)
+        ));
+        assert!(matches!(
+            handler.poll_close(&mut cx),
+            std::task::Poll::Ready(None)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_frames_round_trip_per_version() {
+        let mut v1 = Vec::new();
+        write_frame(&mut v1, ProtocolVersion::V1, 7, b"message")
+            .await
+            .unwrap();
+        let mut v2 = Vec::new();
+        write_frame(&mut v2, ProtocolVersion::V2, 7, b"message")
+            .await
+            .unwrap();
+        // v1 frames match Archivist's; v2 frames add a version byte and
+        // carry the session ID ahead of the message
+        assert_eq!(v1, [7, b'm', b'e', b's', b's', b'a', b'g', b'e']);
+        assert_eq!(v2.len(), v1.len() + 9);
+        assert_eq!(v2[1], FRAME_VERSION_V2);
+
+        let read = read_frame(&mut v1.as_slice(), ProtocolVersion::V1, 1024).await;
+        assert_eq!(read.unwrap(), (0, b"message".to_vec()));
+        let read = read_frame(&mut v2.as_slice(), ProtocolVersion::V2, 1024).await;
+        assert_eq!(read.unwrap(), (7, b"message".to_vec()));
+
+        // v2 readers reject plain v1 frames
+        assert!(read_frame(&mut v1.as_slice(), ProtocolVersion::V2, 1024)
+            .await
+            .is_err());
+    }
+
+    fn delivery(i: u8, len: usize) -> BlockDelivery {
+        BlockDelivery::from_cid_and_data(vec![i; 36], vec![i; len])
+    }
+
+    #[test]
+    fn test_split_response_fits_each_message_under_limit() {
+        use prost::Message as _;
+
+        let blocks: Vec<_> = (0..10u8).map(|i| delivery(i, 400)).collect();
+        let presences = vec![BlockPresence::from_cid(
+            vec![0xff; 36],
+            BlockPresenceType::PresenceDontHave,
+            vec![],
+        )];
+        let messages = split_response(blocks, presences, 1024);
+
+        assert!(messages.len() > 1);
+        assert_eq!(messages[0].block_presences.len(), 1);
+        assert!(messages[1..].iter().all(|m| m.block_presences.is_empty()));
+        for message in &messages {
+            assert!(message.encoded_len() + RESPONSE_OVERHEAD <= 1024);
+        }
+        let sent: Vec<u8> = messages
+            .iter()
+            .flat_map(|m| m.payload.iter().map(|b| b.data[0]))
+            .collect();
+        assert_eq!(sent, (0..10u8).collect::<Vec<_>>());
+
+        // An empty response is still sent, and an oversized block goes alone
+        assert_eq!(split_response(vec![], vec![], 1024).len(), 1);
+        let messages = split_response(vec![delivery(0, 100), delivery(1, 4096)], vec![], 1024);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].payload.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_response_counts_blocks_after_write() {
+        let config = BlockExcConfig {
+            max_message_bytes: 1024,
+            ..BlockExcConfig::default()
+        };
+        let peer_id = PeerId::random();
+        let blocks = || (0..4u8).map(|i| delivery(i, 400)).collect::<Vec<_>>();
+
+        let metrics = Metrics::new();
+        let mut out = Vec::new();
+        send_response(
+            &mut out,
+            ProtocolVersion::V1,
+            0,
+            blocks(),
+            vec![],
+            config,
+            &metrics,
+            peer_id,
+        )
+        .await
+        .unwrap();
+        assert_eq!(metrics.blocks_sent(), 4);
+
+        // Every frame decodes and stays under the limit
+        let mut reader = out.as_slice();
+        let mut received = 0;
+        while !reader.is_empty() {
+            let (_, bytes) = read_frame(&mut reader, ProtocolVersion::V1, 1024)
+                .await
+                .unwrap();
+            received += crate::messages::decode_message(&bytes)
+                .unwrap()
+                .payload
+                .len();
+        }
+        assert_eq!(received, 4);
+
+        // Nothing is counted when the write fails
+        let metrics = Metrics::new();
+        let mut full = futures::io::Cursor::new(&mut [0u8; 0][..]);
+        let result = send_response(
+            &mut full,
+            ProtocolVersion::V1,
+            0,
+            blocks(),
+            vec![],
+            config,
+            &metrics,
+            peer_id,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(metrics.blocks_sent(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_verify_and_store_batch_skips_invalid_blocks() {
+        let store = BlockStore::new();
+        let mut blocks: Vec<(Vec<u8>, Cid)> = (0..4u8)
+            .map(|i| {
+                let block = crate::storage::Block::new(vec![i; 1024]).unwrap();
+                (block.data, block.cid)
+            })
+            .collect();
+        blocks[1].0[0] ^= 0xff;
+        let bad = blocks[1].1;
+
+        let stored = verify_and_store_batch(blocks.clone(), &store)
+            .await
+            .unwrap();
+        assert_eq!(stored, 3);
+        assert!(!store.has(&bad).await);
+        for (_, cid) in blocks.iter().filter(|(_, cid)| *cid != bad) {
+            assert!(store.has(cid).await);
         }
     }
 }
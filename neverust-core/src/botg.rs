@@ -11,16 +11,37 @@
 //! - Optimize for Neverust-to-Neverust block exchange
 
 use cid::Cid;
+use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tracing::{debug, error, info, warn};
 
+use crate::storage::StorageEvent;
+
 /// Stream identifier for BoTG/TGP sessions.
 pub type StreamId = u128;
 
+/// Number of probe packets sent per bandwidth estimate
+pub const PROBE_COUNT: u32 = 5;
+
+/// Wire size of each bandwidth probe packet in bytes
+pub const PROBE_SIZE: usize = 1200;
+
+/// How long to wait for probe acknowledgements
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Bytes of each MTU kept free for framing when batching announcements
+const ANNOUNCE_FRAMING_BYTES: usize = 20;
+
+/// Delivers (sequence number, arrival time) of probe acknowledgements
+type ProbeAckSender = mpsc::UnboundedSender<(u32, Instant)>;
+
 /// Local compatibility struct for TGP configuration.
 ///
 /// The original external `consensus-tgp` crate is not available in this
@@ -67,6 +88,37 @@ impl TransportHandle {
     }
 }
 
+/// Split CIDs into batches whose serialized [`BoTgMessage::Announce`] is at
+/// most `max_bytes` long
+///
+/// A CID that does not fit on its own still gets a batch of its own.
+fn announce_batches(cids: Vec<Vec<u8>>, max_bytes: usize) -> Vec<Vec<Vec<u8>>> {
+    let encoded_len = |value: &Vec<u8>| serde_json::to_vec(value).map_or(0, |json| json.len());
+    let overhead = serde_json::to_vec(&BoTgMessage::Announce { cids: Vec::new() })
+        .map_or(0, |json| json.len());
+
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_len = overhead;
+    for cid in cids {
+        // Every CID after the first in a batch also needs a separating comma
+        let cid_len = encoded_len(&cid) + usize::from(!batch.is_empty());
+        if !batch.is_empty() && batch_len + cid_len > max_bytes {
+            batches.push(std::mem::take(&mut batch));
+            batch_len = overhead;
+            batch.push(cid);
+            batch_len += cid_len - 1;
+        } else {
+            batch.push(cid);
+            batch_len += cid_len;
+        }
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
 /// BoTG message types for UDP communication
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BoTgMessage {
@@ -87,6 +139,55 @@ pub enum BoTgMessage {
         /// Block data
         data: Vec<u8>,
     },
+    /// Bandwidth probe, padded to [`PROBE_SIZE`] bytes on the wire
+    Probe {
+        /// Identifies the probe train
+        nonce: u64,
+        /// Position in the train
+        seq: u32,
+        /// Filler bringing the packet up to the probe size
+        padding: String,
+    },
+    /// Acknowledgement of a bandwidth probe
+    ProbeAck {
+        /// Nonce of the acknowledged probe train
+        nonce: u64,
+        /// Sequence number of the acknowledged probe
+        seq: u32,
+    },
+    /// Liveness check sent to every known peer each heartbeat interval
+    Heartbeat {
+        /// Sender's TGP peer ID
+        peer_id: u64,
+        /// Sender's wall-clock time in milliseconds since the Unix epoch
+        timestamp_ms: u64,
+    },
+    /// Answer to a heartbeat
+    HeartbeatAck {
+        /// Responder's TGP peer ID
+        peer_id: u64,
+        /// Timestamp of the heartbeat being answered
+        echo_timestamp_ms: u64,
+    },
+}
+
+impl BoTgMessage {
+    /// Build probe `seq` of train `nonce`, padded to [`PROBE_SIZE`] bytes
+    fn probe(nonce: u64, seq: u32) -> Result<Self, BoTgError> {
+        let unpadded = Self::Probe {
+            nonce,
+            seq,
+            padding: String::new(),
+        };
+        let len = serde_json::to_vec(&unpadded)
+            .map_err(|e| BoTgError::EncodingError(format!("Failed to serialize probe: {}", e)))?
+            .len();
+        Ok(Self::Probe {
+            nonce,
+            seq,
+            padding: "0".repeat(PROBE_SIZE.saturating_sub(len)),
+        })
+    }
 }
 
 /// Block identifier (CID-compatible)
@@ -96,6 +197,35 @@ pub struct BlockId {
     pub cid: Vec<u8>,
 }
 
+impl BlockId {
+    /// Block identifier for a CID
+    pub fn from_cid(cid: &Cid) -> Self {
+        Self {
+            cid: cid.to_bytes(),
+        }
+    }
+
+    /// Parse the raw bytes back into a CID
+    pub fn to_cid(&self) -> Result<Cid, BoTgError> {
+        Cid::try_from(&self.cid[..])
+            .map_err(|e| BoTgError::EncodingError(format!("Invalid CID: {}", e)))
+    }
+}
+
+impl From<Cid> for BlockId {
+    fn from(cid: Cid) -> Self {
+        Self::from_cid(&cid)
+    }
+}
+
+impl TryFrom<BlockId> for Cid {
+    type Error = BoTgError;
+
+    fn try_from(block_id: BlockId) -> Result<Self, Self::Error> {
+        block_id.to_cid()
+    }
+}
+
 /// Block rollup - a batch of blocks being exchanged
 #[derive(Debug, Clone)]
 pub struct BlockRollup {
@@ -122,6 +252,9 @@ pub struct BoTgConfig {
     pub local_peer_id: u64,
     /// TGP epoch
     pub epoch: u32,
+    /// How often heartbeats are sent to known peers; peers silent for three
+    /// intervals are dropped
+    pub heartbeat_interval: Duration,
 }
 
 impl Default for BoTgConfig {
@@ -132,10 +265,22 @@ impl Default for BoTgConfig {
             mtu: 1200,                           // Optimal MTU from TGP benchmarks
             local_peer_id: rand::random(),
             epoch: 0,
+            heartbeat_interval: Duration::from_secs(10),
         }
     }
 }
 
+/// Snapshot of a [`BoTgProtocol`]'s queues and peers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BoTgStats {
+    /// Outbound rollups not yet sent
+    pub pending_rollups: usize,
+    /// Peer addresses blocks are announced to
+    pub known_peers: usize,
+    /// Active TGP handles
+    pub active_handles: usize,
+}
+
 /// BoTG protocol state machine
 pub struct BoTgProtocol {
     config: BoTgConfig,
@@ -157,6 +302,14 @@ pub struct BoTgProtocol {
     block_store: Option<Arc<crate::storage::BlockStore>>,
     /// Metrics for tracking BoTG traffic
     metrics: Option<crate::metrics::Metrics>,
+    /// Latest bandwidth estimate per peer in bits per second
+    peer_bandwidth: Arc<DashMap<SocketAddr, u64>>,
+    /// Probe trains awaiting acknowledgements, by nonce
+    probe_waiters: Arc<RwLock<HashMap<u64, ProbeAckSender>>>,
+    /// When each known peer last answered or sent a heartbeat
+    peer_last_seen: Arc<RwLock<HashMap<SocketAddr, Instant>>>,
+    /// Configured peers, kept however long they stay silent
+    static_peers: Arc<RwLock<HashSet<SocketAddr>>>,
 }
 
 impl BoTgProtocol {
@@ -173,6 +326,19 @@ impl BoTgProtocol {
             udp_socket: None,
             block_store: None,
             metrics: None,
+            peer_bandwidth: Arc::new(DashMap::new()),
+            probe_waiters: Arc::new(RwLock::new(HashMap::new())),
+            peer_last_seen: Arc::new(RwLock::new(HashMap::new())),
+            static_peers: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    /// Get a snapshot of pending rollups, known peers and TGP handles
+    pub async fn stats(&self) -> BoTgStats {
+        BoTgStats {
+            pending_rollups: self.pending_rollups.read().await.len(),
+            known_peers: self.peer_addrs.read().await.len(),
+            active_handles: self.handles.read().await.len(),
         }
     }
 
@@ -197,9 +363,76 @@ impl BoTgProtocol {
         if !peers.contains(&addr) {
             info!("BoTG: Added peer {}", addr);
             peers.push(addr);
+            // New peers get a full liveness window to answer heartbeats
+            self.peer_last_seen
+                .write()
+                .await
+                .insert(addr, Instant::now());
+        }
+    }
+
+    /// Add a configured peer address
+    ///
+    /// Unlike peers added with [`Self::add_peer`], configured peers are never
+    /// dropped for missing heartbeats, so a peer that is down when the node
+    /// starts is still used once it comes up.
+    pub async fn add_static_peer(&self, addr: SocketAddr) {
+        self.static_peers.write().await.insert(addr);
+        self.add_peer(addr).await;
+    }
+
+    /// Drop peers that have been silent for three heartbeat intervals, then
+    /// send a heartbeat to each remaining peer
+    ///
+    /// Configured peers are kept and keep receiving heartbeats.
+    async fn send_heartbeats(&self) {
+        let timeout = self.config.heartbeat_interval * 3;
+        let now = Instant::now();
+        let peers = {
+            let static_peers = self.static_peers.read().await;
+            let mut peers = self.peer_addrs.write().await;
+            let mut last_seen = self.peer_last_seen.write().await;
+            peers.retain(|addr| {
+                if static_peers.contains(addr) {
+                    return true;
+                }
+                let seen = *last_seen.entry(*addr).or_insert(now);
+                let alive = now.duration_since(seen) < timeout;
+                if !alive {
+                    warn!(
+                        "BoTG: Removing peer {}: no heartbeat for {:?}",
+                        addr,
+                        now.duration_since(seen)
+                    );
+                    last_seen.remove(addr);
+                }
+                alive
+            });
+            peers.clone()
+        };
+
+        let msg = BoTgMessage::Heartbeat {
+            peer_id: self.config.local_peer_id,
+            timestamp_ms: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        };
+        for peer_addr in peers {
+            if let Err(e) = self.send_message(peer_addr, &msg).await {
+                debug!("BoTG: Failed to send heartbeat to {}: {}", peer_addr, e);
+            }
         }
     }
 
+    /// Record that `peer_addr` is alive
+    async fn mark_seen(&self, peer_addr: SocketAddr) {
+        self.peer_last_seen
+            .write()
+            .await
+            .insert(peer_addr, Instant::now());
+    }
+
     /// Send a BoTG message to a peer via UDP
     async fn send_message(&self, addr: SocketAddr, msg: &BoTgMessage) -> Result<(), BoTgError> {
         if let Some(socket) = &self.udp_socket {
@@ -228,7 +461,7 @@ impl BoTgProtocol {
 
     /// Announce that we have new blocks (called when blocks are stored)
     pub async fn announce_blocks(&self, cids: Vec<Cid>) {
-        let block_ids: Vec<BlockId> = cids.iter().map(Self::cid_to_block_id).collect();
+        let block_ids: Vec<BlockId> = cids.iter().map(BlockId::from_cid).collect();
 
         info!("BoTG: Announcing {} new blocks to network", block_ids.len());
 
@@ -240,30 +473,66 @@ impl BoTgProtocol {
             }
         }
 
-        // Send announcement to all known peers via UDP
+        // Send announcement to all known peers via UDP, in packets that fit the MTU
         let peers = self.peer_addrs.read().await;
         if !peers.is_empty() {
             let cid_bytes: Vec<Vec<u8>> = cids.iter().map(|c| c.to_bytes()).collect();
-            let msg = BoTgMessage::Announce { cids: cid_bytes };
+            let batches = announce_batches(
+                cid_bytes,
+                self.config.mtu.saturating_sub(ANNOUNCE_FRAMING_BYTES),
+            );
 
             for peer_addr in peers.iter() {
-                if let Err(e) = self.send_message(*peer_addr, &msg).await {
-                    warn!("BoTG: Failed to announce to {}: {}", peer_addr, e);
+                for batch in &batches {
+                    let msg = BoTgMessage::Announce {
+                        cids: batch.clone(),
+                    };
+                    if let Err(e) = self.send_message(*peer_addr, &msg).await {
+                        warn!("BoTG: Failed to announce to {}: {}", peer_addr, e);
+                        break;
+                    }
                 }
             }
             info!(
-                "BoTG: Announced {} blocks to {} peers via UDP",
+                "BoTG: Announced {} blocks to {} peers via UDP ({} packets each)",
                 cids.len(),
-                peers.len()
+                peers.len(),
+                batches.len()
             );
         } else {
             debug!("BoTG: No peers to announce to");
         }
     }
 
+    /// Drop the CIDs that are already in the local block store
+    ///
+    /// Without a block store every CID is kept.
+    pub async fn filter_local(&self, cids: Vec<Cid>) -> Vec<Cid> {
+        let Some(store) = &self.block_store else {
+            return cids;
+        };
+        let mut missing = Vec::with_capacity(cids.len());
+        for cid in cids {
+            if store.has(&cid).await {
+                debug!("Skipping CID {} already in local store", cid);
+            } else {
+                missing.push(cid);
+            }
+        }
+        missing
+    }
+
     /// Request blocks from the network (called when we need blocks)
-    pub async fn request_blocks_by_cid(&self, cids: Vec<Cid>) {
-        let block_ids: Vec<BlockId> = cids.iter().map(Self::cid_to_block_id).collect();
+    ///
+    /// Blocks that are already stored locally, e.g. because they arrived
+    /// after the caller decided to fetch them, are skipped. Returns the
+    /// number of blocks actually requested, so 0 means all were local.
+    pub async fn request_blocks_by_cid(&self, cids: Vec<Cid>) -> usize {
+        let cids = self.filter_local(cids).await;
+        if cids.is_empty() {
+            return 0;
+        }
+        let block_ids: Vec<BlockId> = cids.iter().map(BlockId::from_cid).collect();
 
         info!("BoTG: Requesting {} blocks from network", block_ids.len());
 
@@ -275,6 +544,20 @@ impl BoTgProtocol {
             }
         }
 
+        // With bandwidth estimates for several peers, split the rollup so
+        // each peer serves a share proportional to its bandwidth
+        if let Some(assignments) = self.split_rollup(&cids).await {
+            for (peer_addr, share) in assignments {
+                let msg = BoTgMessage::Request {
+                    cids: share.iter().map(|c| c.to_bytes()).collect(),
+                };
+                if let Err(e) = self.send_message(peer_addr, &msg).await {
+                    warn!("BoTG: Failed to request from {}: {}", peer_addr, e);
+                }
+            }
+            return cids.len();
+        }
+
         // Send request to all known peers via UDP
         let peers = self.peer_addrs.read().await;
         if !peers.is_empty() {
@@ -294,6 +577,117 @@ impl BoTgProtocol {
         } else {
             debug!("BoTG: No peers to request from");
         }
+        cids.len()
+    }
+
+    /// Estimate the bandwidth to a peer in bits per second
+    ///
+    /// Sends [`PROBE_COUNT`] back-to-back probes of [`PROBE_SIZE`] bytes and
+    /// times the acknowledgements: the bottleneck link spreads the train
+    /// out, so the gap between the first and last acknowledgement gives the
+    /// rate at which the probes got through. The estimate is remembered for
+    /// [`BoTgProtocol::peer_bandwidth`] and rollup splitting. The receive
+    /// loop must be running to collect the acknowledgements.
+    pub async fn estimate_bandwidth(&self, peer_addr: SocketAddr) -> Result<u64, BoTgError> {
+        let nonce = rand::random();
+        let (ack_tx, mut ack_rx) = mpsc::unbounded_channel();
+        self.probe_waiters.write().await.insert(nonce, ack_tx);
+
+        let result = async {
+            for seq in 0..PROBE_COUNT {
+                self.send_message(peer_addr, &BoTgMessage::probe(nonce, seq)?)
+                    .await?;
+            }
+
+            let mut acks = Vec::with_capacity(PROBE_COUNT as usize);
+            let deadline = Instant::now() + PROBE_TIMEOUT;
+            while acks.len() < PROBE_COUNT as usize {
+                match tokio::time::timeout_at(deadline, ack_rx.recv()).await {
+                    Ok(Some((_, arrived))) => acks.push(arrived),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+            Self::dispersion_estimate(&acks).ok_or_else(|| {
+                BoTgError::ProbeFailed(format!(
+                    "{} of {} probes to {} acknowledged",
+                    acks.len(),
+                    PROBE_COUNT,
+                    peer_addr
+                ))
+            })
+        }
+        .await;
+        self.probe_waiters.write().await.remove(&nonce);
+
+        let bandwidth = result?;
+        info!(
+            "BoTG: Estimated bandwidth to {} at {} bit/s",
+            peer_addr, bandwidth
+        );
+        self.peer_bandwidth.insert(peer_addr, bandwidth);
+        Ok(bandwidth)
+    }
+
+    /// Latest bandwidth estimate for a peer in bits per second
+    pub async fn peer_bandwidth(&self, peer_addr: &SocketAddr) -> Option<u64> {
+        self.peer_bandwidth.get(peer_addr).map(|bw| *bw)
+    }
+
+    /// Bandwidth from probe acknowledgement arrival times, which need at
+    /// least two acknowledgements
+    fn dispersion_estimate(arrivals: &[Instant]) -> Option<u64> {
+        if arrivals.len() < 2 {
+            return None;
+        }
+        let first = arrivals.iter().min()?;
+        let last = arrivals.iter().max()?;
+        let bits = ((arrivals.len() - 1) * PROBE_SIZE * 8) as f64;
+        let gap = (*last - *first).as_secs_f64().max(1e-6);
+        Some((bits / gap) as u64)
+    }
+
+    /// Split a rollup across peers in proportion to their estimated
+    /// bandwidth
+    ///
+    /// Returns `None` unless at least two known peers have an estimate, in
+    /// which case the request goes to every peer as before.
+    async fn split_rollup(&self, cids: &[Cid]) -> Option<Vec<(SocketAddr, Vec<Cid>)>> {
+        let peers: Vec<(SocketAddr, u64)> = self
+            .peer_addrs
+            .read()
+            .await
+            .iter()
+            .filter_map(|addr| self.peer_bandwidth.get(addr).map(|bw| (*addr, *bw)))
+            .filter(|&(_, bw)| bw > 0)
+            .collect();
+        if peers.len() < 2 || cids.len() < 2 {
+            return None;
+        }
+
+        let total: u128 = peers.iter().map(|&(_, bw)| bw as u128).sum();
+        let mut shares: Vec<usize> = peers
+            .iter()
+            .map(|&(_, bw)| (cids.len() as u128 * bw as u128 / total) as usize)
+            .collect();
+        // Hand blocks lost to rounding to the fastest peer
+        let fastest = (0..peers.len()).max_by_key(|&i| peers[i].1)?;
+        shares[fastest] += cids.len() - shares.iter().sum::<usize>();
+
+        let mut rest = cids;
+        let mut assignments = Vec::with_capacity(peers.len());
+        for ((addr, _), share) in peers.into_iter().zip(shares) {
+            let (taken, remaining) = rest.split_at(share);
+            rest = remaining;
+            if !taken.is_empty() {
+                assignments.push((addr, taken.to_vec()));
+            }
+        }
+        debug!(
+            "BoTG: Split rollup of {} blocks across {} peers by bandwidth",
+            cids.len(),
+            assignments.len()
+        );
+        Some(assignments)
     }
 
     /// Create a new BoTG protocol with UDP transport
@@ -322,19 +716,6 @@ impl BoTgProtocol {
         Ok((protocol, transport))
     }
 
-    /// Convert CID to BlockId
-    pub fn cid_to_block_id(cid: &Cid) -> BlockId {
-        BlockId {
-            cid: cid.to_bytes(),
-        }
-    }
-
-    /// Convert BlockId to CID
-    pub fn block_id_to_cid(block_id: &BlockId) -> Result<Cid, BoTgError> {
-        Cid::try_from(&block_id.cid[..])
-            .map_err(|e| BoTgError::EncodingError(format!("Invalid CID: {}", e)))
-    }
-
     /// Create a TGP handle for a peer
     pub async fn connect_to_peer(&self, peer_id: u64) -> Result<(), BoTgError> {
         info!("BoTG: Setting up TGP handle for peer {}", peer_id);
@@ -467,8 +848,24 @@ impl BoTgProtocol {
     }
 
     /// Start UDP receive loop to handle incoming BoTG messages
-    pub fn start_receive_loop(self: Arc<Self>) {
-        tokio::spawn(async move {
+    ///
+    /// Heartbeats are sent to known peers every
+    /// [`BoTgConfig::heartbeat_interval`] alongside the loop, and peers that
+    /// stop answering are dropped. Both run until the returned handle is
+    /// aborted.
+    pub fn start_receive_loop(self: Arc<Self>) -> JoinHandle<()> {
+        let heartbeats = {
+            let protocol = self.clone();
+            async move {
+                let mut interval = tokio::time::interval(protocol.config.heartbeat_interval);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    protocol.send_heartbeats().await;
+                }
+            }
+        };
+        let receive = async move {
             if let Some(socket) = &self.udp_socket {
                 info!("BoTG: Starting UDP receive loop");
                 let mut buf = vec![0u8; 65536]; // 64KB buffer
@@ -510,7 +907,47 @@ impl BoTgProtocol {
             } else {
                 error!("BoTG: Cannot start receive loop - UDP socket not initialized");
             }
-        });
+        };
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = receive => {}
+                _ = heartbeats => {}
+            }
+        })
+    }
+
+    /// Start tracking the block store's contents as local blocks
+    ///
+    /// Blocks are added to and removed from the local block set as the store
+    /// reports them. The loop runs until the returned handle is aborted.
+    pub fn start_storage_listener(self: Arc<Self>) -> JoinHandle<()> {
+        use futures::StreamExt;
+
+        tokio::spawn(async move {
+            let Some(store) = &self.block_store else {
+                error!("BoTG: Cannot track stored blocks - block store not set");
+                return;
+            };
+            let mut events = store.event_stream();
+            while let Some(event) = events.next().await {
+                match event {
+                    StorageEvent::BlockStored(cid) => {
+                        self.local_blocks
+                            .write()
+                            .await
+                            .insert(BlockId::from_cid(&cid));
+                    }
+                    StorageEvent::BlockDeleted(cid) => {
+                        self.local_blocks
+                            .write()
+                            .await
+                            .remove(&BlockId::from_cid(&cid));
+                    }
+                    StorageEvent::BlockPinned(_) | StorageEvent::BlockUnpinned(_) => {}
+                }
+            }
+        })
     }
 
     /// Handle incoming BoTG message
@@ -547,6 +984,36 @@ impl BoTgProtocol {
                 );
                 self.handle_block_response(cid, data).await
             }
+            BoTgMessage::Probe { nonce, seq, .. } => {
+                self.send_message(peer_addr, &BoTgMessage::ProbeAck { nonce, seq })
+                    .await
+            }
+            BoTgMessage::ProbeAck { nonce, seq } => {
+                let arrived = Instant::now();
+                if let Some(waiter) = self.probe_waiters.read().await.get(&nonce) {
+                    let _ = waiter.send((seq, arrived));
+                } else {
+                    debug!("BoTG: Ignoring stale probe ack from {}", peer_addr);
+                }
+                Ok(())
+            }
+            BoTgMessage::Heartbeat {
+                peer_id,
+                timestamp_ms,
+            } => {
+                debug!("BoTG: Heartbeat from {} ({})", peer_addr, peer_id);
+                self.mark_seen(peer_addr).await;
+                let ack = BoTgMessage::HeartbeatAck {
+                    peer_id: self.config.local_peer_id,
+                    echo_timestamp_ms: timestamp_ms,
+                };
+                self.send_message(peer_addr, &ack).await
+            }
+            BoTgMessage::HeartbeatAck { peer_id, .. } => {
+                debug!("BoTG: Heartbeat ack from {} ({})", peer_addr, peer_id);
+                self.mark_seen(peer_addr).await;
+                Ok(())
+            }
         }
     }
 
@@ -625,6 +1092,9 @@ pub enum BoTgError {
 
     #[error("Decoding error: {0}")]
     DecodingError(String),
+
+    #[error("Bandwidth probe failed: {0}")]
+    ProbeFailed(String),
 }
 
 #[cfg(test)]
@@ -679,4 +1149,267 @@ mod tests {
         // Verify format: [rollup_id:8][num_blocks:4][block_cid_len:4][block_cid:3]
         assert_eq!(encoded.len(), 8 + 4 + 4 + 3);
     }
+
+    /// Start a protocol instance on a loopback UDP socket
+    async fn spawn_protocol() -> Arc<BoTgProtocol> {
+        spawn_protocol_with(BoTgConfig::default()).await
+    }
+
+    async fn spawn_protocol_with(config: BoTgConfig) -> Arc<BoTgProtocol> {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut protocol = BoTgProtocol::new(config);
+        protocol.set_udp_socket(Arc::new(socket));
+        let protocol = Arc::new(protocol);
+        protocol.clone().start_receive_loop();
+        protocol
+    }
+
+    /// Stand-in for a peer behind a bottleneck link: acknowledges probe `i`
+    /// of the next train `i * gap` after the first, handing the
+    /// acknowledgements straight to the waiting estimate so arrival times
+    /// do not depend on scheduling
+    fn inject_probe_acks(protocol: Arc<BoTgProtocol>, gap: Duration) {
+        tokio::spawn(async move {
+            let waiter = loop {
+                if let Some(waiter) = protocol.probe_waiters.read().await.values().next() {
+                    break waiter.clone();
+                }
+                tokio::task::yield_now().await;
+            };
+            let start = Instant::now();
+            for seq in 0..PROBE_COUNT {
+                let _ = waiter.send((seq, start + gap * seq));
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_estimate_bandwidth_from_probe_gaps() {
+        let protocol = spawn_protocol().await;
+        // A bound socket that swallows the probes
+        let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer = silent.local_addr().unwrap();
+        // 1200-byte probes acknowledged 20ms apart: 480 kbit/s
+        inject_probe_acks(protocol.clone(), Duration::from_millis(20));
+        let expected = (PROBE_SIZE * 8) as f64 / 0.020;
+
+        let estimate = protocol.estimate_bandwidth(peer).await.unwrap();
+        let error = (estimate as f64 - expected).abs() / expected;
+        assert!(
+            error < 0.2,
+            "estimate {} bit/s not within 20% of {}",
+            estimate,
+            expected
+        );
+        assert_eq!(protocol.peer_bandwidth(&peer).await, Some(estimate));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_bandwidth_fails_without_acks() {
+        let protocol = spawn_protocol().await;
+        // A bound socket that never answers
+        let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let result = protocol
+            .estimate_bandwidth(silent.local_addr().unwrap())
+            .await;
+        assert!(matches!(result, Err(BoTgError::ProbeFailed(_))));
+        assert!(protocol.probe_waiters.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_peer_is_pruned() {
+        let protocol = spawn_protocol_with(BoTgConfig {
+            heartbeat_interval: Duration::from_millis(50),
+            ..BoTgConfig::default()
+        })
+        .await;
+        let responsive = spawn_protocol().await;
+        let responsive_addr = responsive
+            .udp_socket
+            .as_ref()
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        // A bound socket that never answers
+        let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let silent_addr = silent.local_addr().unwrap();
+
+        // A configured peer that never answers either
+        let configured = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let configured_addr = configured.local_addr().unwrap();
+
+        protocol.add_peer(responsive_addr).await;
+        protocol.add_peer(silent_addr).await;
+        protocol.add_static_peer(configured_addr).await;
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while protocol.peer_addrs.read().await.contains(&silent_addr) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("silent peer was not pruned");
+
+        assert_eq!(
+            *protocol.peer_addrs.read().await,
+            vec![responsive_addr, configured_addr]
+        );
+        assert!(!protocol
+            .peer_last_seen
+            .read()
+            .await
+            .contains_key(&silent_addr));
+    }
+
+    #[tokio::test]
+    async fn test_split_rollup_by_bandwidth() {
+        let protocol = BoTgProtocol::new(BoTgConfig::default());
+        let fast: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let slow: SocketAddr = "127.0.0.1:9002".parse().unwrap();
+        let unmeasured: SocketAddr = "127.0.0.1:9003".parse().unwrap();
+        for addr in [fast, slow, unmeasured] {
+            protocol.add_peer(addr).await;
+        }
+        let cids: Vec<Cid> = (0..10u8)
+            .map(|i| crate::cid_blake3::blake3_cid(&[i]).unwrap())
+            .collect();
+
+        // A single estimate is not enough to split
+        protocol.peer_bandwidth.insert(fast, 3_000_000);
+        assert!(protocol.split_rollup(&cids).await.is_none());
+
+        protocol.peer_bandwidth.insert(slow, 1_000_000);
+        let assignments = protocol.split_rollup(&cids).await.unwrap();
+        assert_eq!(assignments.len(), 2);
+        assert_eq!(assignments[0], (fast, cids[..8].to_vec()));
+        assert_eq!(assignments[1], (slow, cids[8..].to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_storage_listener_tracks_local_blocks() {
+        let store = Arc::new(crate::storage::BlockStore::new());
+        let mut protocol = BoTgProtocol::new(BoTgConfig::default());
+        protocol.set_block_store(store.clone());
+        let protocol = Arc::new(protocol);
+        let listener = protocol.clone().start_storage_listener();
+        tokio::task::yield_now().await;
+
+        let block = crate::storage::Block::new(b"botg local".to_vec()).unwrap();
+        let id = BlockId::from_cid(&block.cid);
+        store.put(block.clone()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !protocol.local_blocks.read().await.contains(&id) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("stored block not tracked");
+
+        store.delete(&block.cid).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while protocol.local_blocks.read().await.contains(&id) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("deleted block still tracked");
+        listener.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_announce_blocks_batches_to_mtu() {
+        let protocol = spawn_protocol().await;
+        let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        protocol.add_peer(peer.local_addr().unwrap()).await;
+
+        // Drain the peer socket while announcing so no packet is dropped
+        let receiver = tokio::spawn(async move {
+            let mut buf = vec![0u8; 65536];
+            let mut packets = Vec::new();
+            while let Ok(Ok(len)) =
+                tokio::time::timeout(Duration::from_millis(500), peer.recv(&mut buf)).await
+            {
+                // Heartbeats may be interleaved with the announcements
+                if let Ok(BoTgMessage::Announce { cids }) = serde_json::from_slice(&buf[..len]) {
+                    packets.push((len, cids));
+                }
+            }
+            packets
+        });
+
+        let cids: Vec<Cid> = (0u32..1000)
+            .map(|i| crate::cid_blake3::blake3_cid(&i.to_le_bytes()).unwrap())
+            .collect();
+        let cid_bytes: Vec<Vec<u8>> = cids.iter().map(|cid| cid.to_bytes()).collect();
+        let expected_batches = announce_batches(cid_bytes.clone(), 1200 - ANNOUNCE_FRAMING_BYTES);
+        protocol.announce_blocks(cids).await;
+
+        let packets = receiver.await.unwrap();
+        assert_eq!(packets.len(), expected_batches.len());
+        assert_eq!(packets.len(), 125);
+        assert!(packets
+            .iter()
+            .all(|(len, _)| *len <= 1200 - ANNOUNCE_FRAMING_BYTES));
+        let received: Vec<Vec<u8>> = packets.into_iter().flat_map(|(_, cids)| cids).collect();
+        assert_eq!(received, cid_bytes);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_request_skips_blocks_in_local_store() {
+        let store = Arc::new(crate::storage::BlockStore::new());
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut protocol = BoTgProtocol::new(BoTgConfig::default());
+        protocol.set_udp_socket(Arc::new(socket));
+        protocol.set_block_store(store.clone());
+        let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        protocol.add_peer(peer.local_addr().unwrap()).await;
+
+        let local = crate::storage::Block::new(b"botg already local".to_vec()).unwrap();
+        store.put(local.clone()).await.unwrap();
+        let remote = crate::cid_blake3::blake3_cid(b"botg still missing").unwrap();
+        assert_eq!(
+            protocol.filter_local(vec![local.cid, remote]).await,
+            vec![remote]
+        );
+
+        // Fully local: nothing is sent
+        assert_eq!(protocol.request_blocks_by_cid(vec![local.cid]).await, 0);
+        assert!(protocol.want_blocks.read().await.is_empty());
+        let mut buf = vec![0u8; 65536];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), peer.recv(&mut buf))
+                .await
+                .is_err(),
+            "request sent for a local block"
+        );
+
+        // Mixed: only the missing block is requested
+        assert_eq!(
+            protocol
+                .request_blocks_by_cid(vec![local.cid, remote])
+                .await,
+            1
+        );
+        let len = tokio::time::timeout(Duration::from_secs(1), peer.recv(&mut buf))
+            .await
+            .expect("no request sent")
+            .unwrap();
+        match serde_json::from_slice(&buf[..len]).unwrap() {
+            BoTgMessage::Request { cids } => assert_eq!(cids, vec![remote.to_bytes()]),
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_block_id_cid_round_trip() {
+        let cid = crate::cid_blake3::blake3_cid(b"botg block id").unwrap();
+
+        assert_eq!(BlockId::from_cid(&cid).to_cid().unwrap(), cid);
+        let block_id: BlockId = cid.into();
+        assert_eq!(Cid::try_from(block_id).unwrap(), cid);
+
+        let invalid = BlockId { cid: vec![1, 2, 3] };
+        assert!(matches!(invalid.to_cid(), Err(BoTgError::EncodingError(_))));
+    }
 }
@@ -0,0 +1,286 @@
+//! CARv1 import and export of block store contents
+//!
+//! A CAR (Content Addressable aRchive) file is a DAG-CBOR header naming the
+//! archive's root CIDs followed by the blocks, each framed as
+//! `varint(len) | CID | data`. Exports name the store's manifests as roots;
+//! imports verify every block against its CID before storing it.
+
+use ciborium::Value;
+use cid::Cid;
+use futures::{future, TryStreamExt};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+use tracing::info;
+
+use crate::manifest::MANIFEST_CODEC;
+use crate::storage::{Block, BlockStore, StorageError};
+
+/// The only CAR version read and written
+pub const CAR_VERSION: u64 = 1;
+
+/// Largest header or block frame accepted when importing
+pub const MAX_FRAME_SIZE: u64 = 64 * 1024 * 1024;
+
+/// CBOR tag of a CID in DAG-CBOR
+const CID_TAG: u64 = 42;
+
+/// Blocks stored per `put_many` call while importing
+const IMPORT_BATCH_SIZE: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum CarError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Invalid CAR header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Invalid CAR block: {0}")]
+    InvalidBlock(String),
+}
+
+/// Write every block in `store` to `writer` as a CARv1 archive
+///
+/// The manifests in the store are the archive's roots. The store is streamed
+/// twice, once for the roots the header needs and once for the blocks, so
+/// its CIDs are never all held in memory. Returns the number of blocks
+/// written.
+pub async fn export_car<W: Write>(store: &BlockStore, writer: &mut W) -> Result<usize, CarError> {
+    let roots: Vec<Cid> = store
+        .iterate_cids()
+        .try_filter(|cid| future::ready(cid.codec() == MANIFEST_CODEC))
+        .try_collect()
+        .await?;
+    write_header(writer, &roots)?;
+
+    let mut written = 0;
+    let mut blocks = store.iterate();
+    while let Some(block) = blocks.try_next().await? {
+        let cid_bytes = block.cid.to_bytes();
+        write_varint(writer, (cid_bytes.len() + block.data.len()) as u64)?;
+        writer.write_all(&cid_bytes)?;
+        writer.write_all(&block.data)?;
+        written += 1;
+    }
+    writer.flush()?;
+
+    info!(
+        "Exported {} blocks with {} roots to CAR",
+        written,
+        roots.len()
+    );
+    Ok(written)
+}
+
+/// Store every block of the CARv1 archive read from `reader`
+///
+/// Blocks whose data does not match their CID fail the import; blocks
+/// stored before the failure are kept. Returns the archive's roots and the
+/// number of blocks read.
+pub async fn import_car<R: Read>(
+    store: &BlockStore,
+    reader: &mut R,
+) -> Result<(Vec<Cid>, usize), CarError> {
+    let roots = read_header(reader)?;
+
+    let mut imported = 0;
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    while let Some(frame) = read_frame(reader)? {
+        let mut cursor = frame.as_slice();
+        let cid = Cid::read_bytes(&mut cursor)
+            .map_err(|e| CarError::InvalidBlock(format!("bad CID: {}", e)))?;
+        let block = Block::from_cid_and_data(cid, cursor.to_vec())
+            .map_err(|e| CarError::InvalidBlock(format!("{}: {}", cid, e)))?;
+        batch.push(block);
+        imported += 1;
+
+        if batch.len() == IMPORT_BATCH_SIZE {
+            store.put_many(std::mem::take(&mut batch)).await?;
+        }
+    }
+    if !batch.is_empty() {
+        store.put_many(batch).await?;
+    }
+
+    info!(
+        "Imported {} blocks with {} roots from CAR",
+        imported,
+        roots.len()
+    );
+    Ok((roots, imported))
+}
+
+fn write_header<W: Write>(writer: &mut W, roots: &[Cid]) -> Result<(), CarError> {
+    // DAG-CBOR orders map keys by length, so "roots" precedes "version"
+    let roots = roots
+        .iter()
+        .map(|cid| {
+            // CIDs are tagged byte strings with a leading multibase identity byte
+            let mut bytes = vec![0];
+            bytes.extend_from_slice(&cid.to_bytes());
+            Value::Tag(CID_TAG, Box::new(Value::Bytes(bytes)))
+        })
+        .collect();
+    let header = Value::Map(vec![
+        (Value::Text("roots".to_string()), Value::Array(roots)),
+        (
+            Value::Text("version".to_string()),
+            Value::Integer(CAR_VERSION.into()),
+        ),
+    ]);
+
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&header, &mut bytes)
+        .map_err(|e| CarError::InvalidHeader(e.to_string()))?;
+    write_varint(writer, bytes.len() as u64)?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<Vec<Cid>, CarError> {
+    let bytes =
+        read_frame(reader)?.ok_or_else(|| CarError::InvalidHeader("empty archive".to_string()))?;
+    let header: Value = ciborium::from_reader(bytes.as_slice())
+        .map_err(|e| CarError::InvalidHeader(e.to_string()))?;
+    let Value::Map(entries) = header else {
+        return Err(CarError::InvalidHeader("header is not a map".to_string()));
+    };
+    let field = |name: &str| {
+        entries
+            .iter()
+            .find(|(key, _)| key.as_text() == Some(name))
+            .map(|(_, value)| value)
+    };
+
+    let version = field("version")
+        .and_then(Value::as_integer)
+        .and_then(|version| u64::try_from(version).ok());
+    if version != Some(CAR_VERSION) {
+        return Err(CarError::InvalidHeader(format!(
+            "unsupported version {:?}",
+            version
+        )));
+    }
+
+    let Some(Value::Array(roots)) = field("roots") else {
+        return Err(CarError::InvalidHeader("missing roots".to_string()));
+    };
+    roots
+        .iter()
+        .map(|root| match root {
+            Value::Tag(CID_TAG, value) => match value.as_ref() {
+                Value::Bytes(bytes) if bytes.first() == Some(&0) => Cid::try_from(&bytes[1..])
+                    .map_err(|e| CarError::InvalidHeader(format!("bad root CID: {}", e))),
+                _ => Err(CarError::InvalidHeader("bad root CID".to_string())),
+            },
+            _ => Err(CarError::InvalidHeader("root is not a CID".to_string())),
+        })
+        .collect()
+}
+
+/// Read a varint length-prefixed frame, or `None` at the end of the archive
+fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, CarError> {
+    let Some(length) = read_varint(reader)? else {
+        return Ok(None);
+    };
+    if length > MAX_FRAME_SIZE {
+        return Err(CarError::InvalidBlock(format!(
+            "frame too large: {} > {}",
+            length, MAX_FRAME_SIZE
+        )));
+    }
+    let mut frame = vec![0u8; length as usize];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+fn write_varint<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    let mut buf = unsigned_varint::encode::u64_buffer();
+    writer.write_all(unsigned_varint::encode::u64(value, &mut buf))
+}
+
+/// Read an unsigned varint, or `None` if the reader is already at its end
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint too long",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> (BlockStore, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        (BlockStore::new_with_path(dir.path()).unwrap(), dir)
+    }
+
+    #[tokio::test]
+    async fn test_export_import_round_trip() {
+        let (source, _source_dir) = temp_store();
+        let mut cids = Vec::new();
+        for i in 0..5u8 {
+            let block = Block::new(vec![i; 1000 + i as usize]).unwrap();
+            cids.push(block.cid);
+            source.put(block).await.unwrap();
+        }
+        let manifest =
+            crate::manifest::Manifest::new(cids[0], 1024, 5000, None, None, None, None, None);
+        let manifest_block = manifest.to_block().unwrap();
+        source.put(manifest_block.clone()).await.unwrap();
+
+        let mut car = Vec::new();
+        assert_eq!(export_car(&source, &mut car).await.unwrap(), 6);
+
+        let (target, _target_dir) = temp_store();
+        let (roots, imported) = import_car(&target, &mut car.as_slice()).await.unwrap();
+        assert_eq!(roots, vec![manifest_block.cid]);
+        assert_eq!(imported, 6);
+        for cid in cids.iter().chain([&manifest_block.cid]) {
+            assert_eq!(
+                target.get(cid).await.unwrap(),
+                source.get(cid).await.unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_corrupt_block() {
+        let (source, _source_dir) = temp_store();
+        source
+            .put(Block::new(b"car block".to_vec()).unwrap())
+            .await
+            .unwrap();
+        let mut car = Vec::new();
+        export_car(&source, &mut car).await.unwrap();
+        *car.last_mut().unwrap() ^= 0xff;
+
+        let (target, _target_dir) = temp_store();
+        assert!(matches!(
+            import_car(&target, &mut car.as_slice()).await,
+            Err(CarError::InvalidBlock(_))
+        ));
+        assert!(matches!(
+            import_car(&target, &mut b"".as_slice()).await,
+            Err(CarError::InvalidHeader(_))
+        ));
+    }
+}
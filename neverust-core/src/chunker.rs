@@ -69,6 +69,12 @@ impl<R: AsyncRead + Unpin> Chunker<R> {
         self.chunk_size
     }
 
+    /// Change the chunk size used for subsequent chunks
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        assert!(chunk_size > 0, "chunk_size must be greater than 0");
+        self.chunk_size = chunk_size;
+    }
+
     /// Check if EOF has been reached
     pub fn is_eof(&self) -> bool {
         self.eof_reached
@@ -218,6 +224,18 @@ mod tests {
         assert_eq!(chunker_default.chunk_size(), DEFAULT_BLOCK_SIZE);
     }
 
+    #[tokio::test]
+    async fn test_set_chunk_size() {
+        let data = b"hello world";
+        let mut chunker = Chunker::with_chunk_size(&data[..], 5);
+        assert_eq!(chunker.next_chunk().await.unwrap(), Some(b"hello".to_vec()));
+
+        chunker.set_chunk_size(3);
+        assert_eq!(chunker.chunk_size(), 3);
+        assert_eq!(chunker.next_chunk().await.unwrap(), Some(b" wo".to_vec()));
+        assert_eq!(chunker.next_chunk().await.unwrap(), Some(b"rld".to_vec()));
+    }
+
     #[tokio::test]
     async fn test_eof_flag() {
         let data = b"test";
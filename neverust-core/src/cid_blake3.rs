@@ -5,10 +5,13 @@
 
 use cid::Cid;
 use multihash::Multihash;
+use rayon::prelude::*;
 use sha2::{Digest, Sha256};
 use std::io::{self, Read};
 use thiserror::Error;
 
+use crate::manifest::MANIFEST_CODEC;
+
 /// BLAKE3 multihash code
 /// See: https://github.com/multiformats/multicodec/blob/master/table.csv
 const BLAKE3_CODE: u64 = 0x1e;
@@ -19,6 +22,12 @@ const SHA2_256_CODE: u64 = 0x12;
 /// 0xcd01 = codex-manifest (for metadata)
 /// 0xcd02 = codex-block (for actual data blocks)
 const ARCHIVIST_BLOCK_CODEC: u64 = 0xcd02; // Changed from 0xcd01!
+/// Archivist tree root codec
+const ARCHIVIST_ROOT_CODEC: u64 = 0xcd03;
+/// dag-pb codec, the only codec CIDv0 can express
+const DAG_PB_CODEC: u64 = 0x70;
+/// raw binary codec
+const RAW_CODEC: u64 = 0x55;
 
 #[derive(Debug, Error)]
 pub enum CidError {
@@ -33,6 +42,16 @@ pub enum CidError {
 
     #[error("Multihash error: {0}")]
     Multihash(String),
+
+    #[error("CID cannot be expressed as CIDv0: {0}")]
+    IncompatibleVersion(String),
+
+    #[error("Block {index} in batch failed verification: {source}")]
+    Batch {
+        index: usize,
+        #[source]
+        source: Box<CidError>,
+    },
 }
 
 /// Compute BLAKE3 hash of data
@@ -68,6 +87,35 @@ pub fn sha256_cid(data: &[u8]) -> Result<Cid, CidError> {
     Ok(Cid::new_v1(ARCHIVIST_BLOCK_CODEC, mh))
 }
 
+/// Compute a legacy CIDv0 for data
+///
+/// Despite the name, CIDv0 only supports SHA2-256 over dag-pb, so this
+/// hashes with SHA2-256. Use it when handing content to tools that only
+/// understand `Qm...` addresses; Archivist blocks themselves are addressed
+/// with [`blake3_cid`].
+pub fn blake3_cid_v0(data: &[u8]) -> Result<Cid, CidError> {
+    let hash = sha256_hash(data);
+    let mh = Multihash::wrap(SHA2_256_CODE, &hash)
+        .map_err(|e| CidError::Multihash(format!("Failed to create multihash: {}", e)))?;
+    Cid::new_v0(mh).map_err(|e| CidError::IncompatibleVersion(e.to_string()))
+}
+
+/// Convert a CIDv1 to the equivalent CIDv0
+///
+/// Only dag-pb CIDs with a SHA2-256 hash have a CIDv0 form; Archivist block
+/// and manifest CIDs do not. Use this to print a CID the way older IPFS
+/// tooling expects it. CIDv0 input is returned unchanged.
+pub fn cid_to_v0(cid: &Cid) -> Result<Cid, CidError> {
+    if cid.codec() != DAG_PB_CODEC || cid.hash().code() != SHA2_256_CODE {
+        return Err(CidError::IncompatibleVersion(format!(
+            "{} with {} hash",
+            codec_name(cid.codec()),
+            codec_name(cid.hash().code())
+        )));
+    }
+    Cid::new_v0(*cid.hash()).map_err(|e| CidError::IncompatibleVersion(e.to_string()))
+}
+
 /// Streaming BLAKE3 verifier for blocks
 pub struct StreamingVerifier {
     hasher: blake3::Hasher,
@@ -156,6 +204,9 @@ impl Default for StreamingVerifier {
 }
 
 /// Verify data against a CID using BLAKE3
+///
+/// Only the multihash is compared, so blocks with any codec (e.g. manifests)
+/// verify as long as their data hashes to the CID's digest.
 pub fn verify_blake3(data: &[u8], expected_cid: &Cid) -> Result<(), CidError> {
     let computed_cid = match expected_cid.hash().code() {
         BLAKE3_CODE => blake3_cid(data)?,
@@ -168,7 +219,7 @@ pub fn verify_blake3(data: &[u8], expected_cid: &Cid) -> Result<(), CidError> {
         }
     };
 
-    if &computed_cid != expected_cid {
+    if computed_cid.hash() != expected_cid.hash() {
         return Err(CidError::HashMismatch {
             expected: expected_cid.to_string(),
             actual: computed_cid.to_string(),
@@ -178,11 +229,45 @@ pub fn verify_blake3(data: &[u8], expected_cid: &Cid) -> Result<(), CidError> {
     Ok(())
 }
 
+/// Verify many `(data, cid)` pairs in parallel
+///
+/// Returns one result per pair, in input order. Failures are wrapped in
+/// [`CidError::Batch`] carrying the index of the failing pair.
+pub fn verify_blake3_batch(blocks: &[(Vec<u8>, Cid)]) -> Vec<Result<(), CidError>> {
+    blocks
+        .par_iter()
+        .enumerate()
+        .map(|(index, (data, cid))| {
+            verify_blake3(data, cid).map_err(|e| CidError::Batch {
+                index,
+                source: Box::new(e),
+            })
+        })
+        .collect()
+}
+
 /// Parse a CID from bytes
 pub fn parse_cid(bytes: &[u8]) -> Result<Cid, CidError> {
     Cid::try_from(bytes).map_err(|e| CidError::InvalidCid(e.to_string()))
 }
 
+/// Human-readable name for a CID codec or multihash code, for log and error
+/// messages
+///
+/// Returns `"unknown"` for codes not used by Archivist.
+pub fn codec_name(codec: u64) -> &'static str {
+    match codec {
+        MANIFEST_CODEC => "archivist-manifest",
+        ARCHIVIST_BLOCK_CODEC => "archivist-block",
+        ARCHIVIST_ROOT_CODEC => "archivist-root",
+        DAG_PB_CODEC => "dag-pb",
+        RAW_CODEC => "raw",
+        SHA2_256_CODE => "sha2-256",
+        BLAKE3_CODE => "blake3",
+        _ => "unknown",
+    }
+}
+
 /// Parse a CID from string
 pub fn parse_cid_str(s: &str) -> Result<Cid, CidError> {
     s.parse()
@@ -243,6 +328,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_verify_ignores_codec() {
+        let data = b"manifest bytes";
+        for hash in [blake3_cid(data).unwrap(), sha256_cid(data).unwrap()].map(|cid| *cid.hash()) {
+            for codec in [0xcd01, 0xcd03, 0x55] {
+                let cid = Cid::new_v1(codec, hash);
+                assert!(verify_blake3(data, &cid).is_ok(), "codec 0x{:x}", codec);
+                assert!(verify_blake3(b"other bytes", &cid).is_err());
+            }
+        }
+
+        // The hash function is part of the multihash, so a BLAKE3 digest
+        // labelled as SHA-256 still fails
+        let digest = blake3_cid(data).unwrap().hash().digest().to_vec();
+        let relabelled = Cid::new_v1(0xcd01, Multihash::wrap(SHA2_256_CODE, &digest).unwrap());
+        assert!(verify_blake3(data, &relabelled).is_err());
+    }
+
     #[test]
     fn test_verify_sha256() {
         let data = b"hello world";
@@ -251,6 +354,31 @@ mod tests {
         assert!(verify_blake3(b"not hello world", &cid).is_err());
     }
 
+    #[test]
+    fn test_verify_blake3_batch() {
+        let mut blocks: Vec<(Vec<u8>, Cid)> = (0..8u8)
+            .map(|i| {
+                let data = vec![i; 256];
+                let cid = blake3_cid(&data).unwrap();
+                (data, cid)
+            })
+            .collect();
+        blocks[5].0[0] ^= 0xff;
+
+        let results = verify_blake3_batch(&blocks);
+        assert_eq!(results.len(), blocks.len());
+        for (i, result) in results.iter().enumerate() {
+            match result {
+                Err(CidError::Batch { index, source }) => {
+                    assert_eq!((i, *index), (5, 5));
+                    assert!(matches!(**source, CidError::HashMismatch { .. }));
+                }
+                Err(e) => panic!("unexpected error for block {}: {}", i, e),
+                Ok(()) => assert_ne!(i, 5),
+            }
+        }
+    }
+
     #[test]
     fn test_streaming_verifier() {
         let data = b"hello world";
@@ -360,6 +488,54 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_blake3_cid_v0_is_base58_qm() {
+        let cid = blake3_cid_v0(b"hello world").unwrap();
+        assert_eq!(cid.version(), cid::Version::V0);
+        assert_eq!(cid.hash().digest(), sha256_hash(b"hello world").as_slice());
+
+        let cid_str = cid.to_string();
+        assert!(cid_str.starts_with("Qm"));
+        assert_eq!(cid_str.len(), 46);
+        assert!(cid_str
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() && !"0OIl".contains(c)));
+        assert_eq!(parse_cid_str(&cid_str).unwrap(), cid);
+    }
+
+    #[test]
+    fn test_cid_to_v0() {
+        let v0 = blake3_cid_v0(b"hello world").unwrap();
+        let v1 = Cid::new_v1(DAG_PB_CODEC, *v0.hash());
+        assert_eq!(cid_to_v0(&v1).unwrap(), v0);
+        assert_eq!(cid_to_v0(&v0).unwrap(), v0);
+        assert!(cid_to_v0(&v1).unwrap().to_string().starts_with("Qm"));
+
+        // Archivist block CIDs use a codec CIDv0 cannot express
+        for cid in [
+            blake3_cid(b"hello world").unwrap(),
+            sha256_cid(b"hello world").unwrap(),
+            Cid::new_v1(DAG_PB_CODEC, *blake3_cid(b"hello world").unwrap().hash()),
+        ] {
+            assert!(matches!(
+                cid_to_v0(&cid),
+                Err(CidError::IncompatibleVersion(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_codec_name() {
+        assert_eq!(codec_name(0xcd01), "archivist-manifest");
+        assert_eq!(codec_name(0xcd02), "archivist-block");
+        assert_eq!(codec_name(0xcd03), "archivist-root");
+        assert_eq!(codec_name(0x70), "dag-pb");
+        assert_eq!(codec_name(0x55), "raw");
+        assert_eq!(codec_name(0x12), "sha2-256");
+        assert_eq!(codec_name(0x1e), "blake3");
+        assert_eq!(codec_name(0xdead), "unknown");
+    }
+
     #[test]
     fn test_decode_archivist_cid() {
         // Example CID from Archivist testnet
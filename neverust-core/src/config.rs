@@ -2,12 +2,18 @@
 //!
 //! Handles CLI argument parsing, config file loading, and defaults.
 
+use cid::Cid;
 use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
+use crate::fetcher::{FetchSource, DEFAULT_FETCH_STRATEGY};
+use crate::offline::OfflineCommand;
+use crate::rate_limit::RateLimitConfig;
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("IO error: {0}")]
@@ -18,8 +24,37 @@ pub enum ConfigError {
 
     #[error("Invalid configuration: {0}")]
     Invalid(String),
+
+    #[error("Missing environment variable: {0}")]
+    MissingEnvVar(String),
+
+    #[error("Bootstrap node fetch failed: {0}")]
+    BootstrapFetchFailed(String),
 }
 
+/// Environment variable that must be set for [`Config::from_env`]
+///
+/// Containers should always mount their data directory explicitly rather than
+/// fall back to `./data` inside the image.
+pub const DATA_DIR_ENV: &str = "NEVERUST_DATA_DIR";
+
+/// Archivist testnet SPR list
+const TESTNET_SPR_URL: &str = "https://spr.archivist.storage/testnet";
+
+/// Attempts made by [`Config::fetch_testnet_bootstrap_nodes`]
+pub const BOOTSTRAP_FETCH_ATTEMPTS: u32 = 5;
+
+/// Delay before the first bootstrap fetch retry, doubled after each failure
+const BOOTSTRAP_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Last-known Archivist testnet bootstrap nodes, used when the SPR list
+/// cannot be fetched
+const FALLBACK_BOOTSTRAP_NODES: &[&str] = &[
+    "/ip4/78.47.168.170/tcp/30010/p2p/16Uiu2HAmNzgyd948rRhmuZ6HSLU2r78kzDXkg5pi12atgQe48vNz",
+    "/ip4/5.161.24.19/tcp/30020/p2p/16Uiu2HAkw9nTRAQ9UXVtmbvXvZtzNwJVbzmG72x9hBmLtYqwyrbH",
+    "/ip4/5.223.21.208/tcp/30030/p2p/16Uiu2HAm31FhvC51bowz9ERL73FmdVvXXz7vwNWFZ4WnXBnBvHUk",
+];
+
 #[derive(Parser, Debug)]
 #[command(name = "neverust")]
 #[command(about = "Archivist Storage Node in Rust", long_about = None)]
@@ -35,6 +70,17 @@ enum Commands {
     Start(StartCommand),
     /// Generate a new Ethereum private key for marketplace operations
     GenerateKey(GenerateKeyCommand),
+    /// Print block store statistics (offline)
+    Info(StorePathArgs),
+    /// Check every stored block against its CID (offline)
+    Verify(StorePathArgs),
+    /// Export all blocks to a CAR file (offline)
+    Export(ExportCommand),
+    /// Import the blocks of a CAR file (offline)
+    Import(ImportCommand),
+    /// Inspect stored manifests (offline)
+    #[command(subcommand)]
+    Manifest(ManifestCommand),
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -44,129 +90,286 @@ pub struct GenerateKeyCommand {
     pub output: PathBuf,
 }
 
+#[derive(Parser, Debug, Clone)]
+pub struct StorePathArgs {
+    /// Block store directory of a stopped node, e.g. ./data/blocks
+    pub store_path: PathBuf,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ExportCommand {
+    /// Block store directory of a stopped node, e.g. ./data/blocks
+    pub store_path: PathBuf,
+    /// CAR file to write
+    pub output: PathBuf,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ImportCommand {
+    /// Block store directory, created if missing
+    pub store_path: PathBuf,
+    /// CAR file to read
+    pub input: PathBuf,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ManifestCommand {
+    /// Decode and print a manifest as JSON
+    Show {
+        /// Block store directory of a stopped node, e.g. ./data/blocks
+        store_path: PathBuf,
+        /// CID of the manifest block
+        manifest_cid: Cid,
+    },
+}
+
 #[derive(Parser, Debug, Clone)]
 pub struct StartCommand {
     /// Data directory for node configuration and storage
-    #[arg(long, default_value = "./data")]
+    #[arg(long, env = "NEVERUST_DATA_DIR", default_value = "./data")]
     pub data_dir: PathBuf,
 
     /// TCP port for P2P transport
-    #[arg(long, default_value_t = 8070)]
+    #[arg(long, env = "NEVERUST_LISTEN_PORT", default_value_t = 8070)]
     pub listen_port: u16,
 
     /// UDP port for peer discovery
-    #[arg(long, default_value_t = 8090)]
+    #[arg(long, env = "NEVERUST_DISC_PORT", default_value_t = 8090)]
     pub disc_port: u16,
 
     /// HTTP port for REST API
-    #[arg(long, default_value_t = 8080)]
+    #[arg(long, env = "NEVERUST_API_PORT", default_value_t = 8080)]
     pub api_port: u16,
 
     /// Bind address for the REST API (e.g. 127.0.0.1 to restrict to localhost)
-    #[arg(long, default_value = "0.0.0.0")]
+    #[arg(long, env = "NEVERUST_API_BIND", default_value = "0.0.0.0")]
     pub api_bind: String,
 
     /// Node operating mode: altruistic (free blocks) or marketplace (paid blocks)
-    #[arg(long, default_value = "altruistic")]
+    #[arg(long, env = "NEVERUST_MODE", default_value = "altruistic")]
     pub mode: String,
 
     /// Price per byte in marketplace mode (in smallest currency unit)
-    #[arg(long, default_value_t = 1)]
+    #[arg(long, env = "NEVERUST_PRICE_PER_BYTE", default_value_t = 1)]
     pub price_per_byte: u64,
 
     /// Enable marketplace persistence and stateful API flows.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_PERSISTENCE")]
     pub persistence: bool,
 
     /// Maximum local storage quota exposed through the Archivist-compatible API.
-    #[arg(long, default_value_t = 1024 * 1024 * 1024)]
+    #[arg(long, env = "NEVERUST_QUOTA_BYTES", default_value_t = 1024 * 1024 * 1024)]
     pub quota_bytes: u64,
 
     /// Ethereum RPC endpoint used for marketplace integration.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_ETH_PROVIDER")]
     pub eth_provider: Option<String>,
 
     /// Explicit Ethereum account/address for the node.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_ETH_ACCOUNT")]
     pub eth_account: Option<String>,
 
     /// Path to the Ethereum private key file.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_ETH_PRIVATE_KEY")]
     pub eth_private_key: Option<PathBuf>,
 
     /// Marketplace contract address.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_MARKETPLACE_ADDRESS")]
     pub marketplace_address: Option<String>,
 
     /// Contracts map as JSON, matching Archivist CLI usage.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_CONTRACTS_ADDRESSES")]
     pub contracts_addresses: Option<String>,
 
     /// Enable validator-side marketplace behavior.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_VALIDATOR")]
     pub validator: bool,
 
     /// Enable prover-side marketplace behavior.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_PROVER")]
     pub prover: bool,
 
     /// Logging level (trace, debug, info, warn, error)
-    #[arg(long, default_value = "info")]
+    #[arg(long, env = "NEVERUST_LOG_LEVEL", default_value = "info")]
     pub log_level: String,
 
     /// Bootstrap node for peer connections or discovery.
     /// Supports libp2p multiaddrs for TCP dialing and `spr:` / `enr:` entries for DiscV5.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_BOOTSTRAP_PEERS", value_delimiter = ',')]
     pub bootstrap_node: Vec<String>,
 
+    /// Start without bootstrap peers instead of falling back to the
+    /// Archivist testnet when no bootstrap node is given.
+    #[arg(long, env = "NEVERUST_NO_BOOTSTRAP")]
+    pub no_bootstrap: bool,
+
     /// Public address to announce to peers (e.g. /ip4/1.2.3.4/tcp/10700).
     /// Can be specified multiple times.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_ANNOUNCE_ADDRS", value_delimiter = ',')]
     pub announce_addr: Vec<String>,
 
     /// Enable Citadel/Lens mode inside Neverust.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_CITADEL_MODE")]
     pub citadel_mode: bool,
 
     /// Local Lens Site ID used by Citadel mode.
-    #[arg(long, default_value_t = 1)]
+    #[arg(long, env = "NEVERUST_CITADEL_SITE_ID", default_value_t = 1)]
     pub citadel_site_id: u64,
 
     /// Optional explicit Citadel origin/node ID. If 0, derived from peer ID.
-    #[arg(long, default_value_t = 0)]
+    #[arg(long, env = "NEVERUST_CITADEL_NODE_ID", default_value_t = 0)]
     pub citadel_node_id: u32,
 
     /// Optional host/domain bucket ID for Citadel admission guards.
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_CITADEL_HOST_ID")]
     pub citadel_host_id: Option<u8>,
 
     /// Optional Flagship trust snapshot URL (JSON).
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_CITADEL_FLAGSHIP_URL")]
     pub citadel_flagship_url: Option<String>,
 
     /// Trusted origin IDs (can be specified multiple times).
-    #[arg(long)]
+    #[arg(long, env = "NEVERUST_CITADEL_TRUSTED_ORIGINS", value_delimiter = ',')]
     pub citadel_trusted_origin: Vec<u32>,
 
     /// Idle control-plane bandwidth cap in KiB/s (per node).
-    #[arg(long, default_value_t = 100)]
+    #[arg(
+        long,
+        env = "NEVERUST_CITADEL_IDLE_BANDWIDTH_KIB",
+        default_value_t = 100
+    )]
     pub citadel_idle_bandwidth_kib: u64,
 
     /// Base PoW bits required for unknown origins.
-    #[arg(long, default_value_t = 8)]
+    #[arg(long, env = "NEVERUST_CITADEL_POW_BITS", default_value_t = 8)]
     pub citadel_pow_bits: u8,
 
     /// Reduced PoW bits required for trusted origins.
-    #[arg(long, default_value_t = 4)]
+    #[arg(long, env = "NEVERUST_CITADEL_TRUSTED_POW_BITS", default_value_t = 4)]
     pub citadel_trusted_pow_bits: u8,
 
     /// Per-origin op rate cap per simulation/runtime round.
-    #[arg(long, default_value_t = 96)]
+    #[arg(
+        long,
+        env = "NEVERUST_CITADEL_MAX_OPS_PER_ORIGIN_PER_ROUND",
+        default_value_t = 96
+    )]
     pub citadel_max_ops_per_origin_per_round: u32,
 
     /// Max new origins admitted per host per round.
-    #[arg(long, default_value_t = 12)]
+    #[arg(
+        long,
+        env = "NEVERUST_CITADEL_MAX_NEW_ORIGINS_PER_HOST_PER_ROUND",
+        default_value_t = 12
+    )]
     pub citadel_max_new_origins_per_host_per_round: u32,
+
+    /// Finish advertising queued blocks before the advertiser stops.
+    #[arg(long, env = "NEVERUST_ADVERTISER_FLUSH_ON_STOP")]
+    pub advertiser_flush_on_stop: bool,
+
+    /// Queued blocks announced together in one DHT batch (1 to 50).
+    #[arg(long, env = "NEVERUST_ADVERTISER_BATCH_SIZE", default_value_t = 1)]
+    pub advertiser_batch_size: usize,
+
+    /// Order in which block sources are tried when a block is not local
+    /// (comma-separated: local, blockexc, botg, http).
+    #[arg(
+        long,
+        env = "NEVERUST_FETCH_STRATEGY",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "local,blockexc,botg,http"
+    )]
+    pub fetch_strategy: Vec<FetchSource>,
+
+    /// Erasure coding data blocks per stripe for uploads (0 disables erasure coding).
+    #[arg(long, env = "NEVERUST_EC_K", default_value_t = 0)]
+    pub ec_k: u32,
+
+    /// Erasure coding parity blocks per stripe for uploads.
+    #[arg(long, env = "NEVERUST_EC_M", default_value_t = 0)]
+    pub ec_m: u32,
+
+    /// Seconds to wait for in-progress work to finish when shutting down.
+    #[arg(long, env = "NEVERUST_SHUTDOWN_TIMEOUT_SECS", default_value_t = 30)]
+    pub shutdown_timeout_secs: u64,
+
+    /// Per-peer inbound BlockExc rate limit in bytes per second (0 disables).
+    #[arg(long, env = "NEVERUST_PEER_RATE_LIMIT_BYTES", default_value_t = 0)]
+    pub peer_rate_limit_bytes: u64,
+
+    /// Rebuild the block metadata index from the stored blocks at startup.
+    #[arg(long, env = "NEVERUST_REBUILD_INDEX")]
+    pub rebuild_index: bool,
+
+    /// Re-verify every block against its CID when it is read from the store.
+    #[arg(long, env = "NEVERUST_VERIFY_ON_READ")]
+    pub verify_on_read: bool,
+
+    /// Delete blocks that fail verification on read.
+    #[arg(long, env = "NEVERUST_DELETE_CORRUPTED_ON_READ")]
+    pub delete_corrupted_on_read: bool,
+
+    /// Seconds to wait for a block requested over BlockExc.
+    #[arg(
+        long,
+        env = "NEVERUST_BLOCKEXC_REQUEST_TIMEOUT_SECS",
+        default_value_t = 30
+    )]
+    pub blockexc_request_timeout_secs: u64,
+
+    /// Largest BlockExc message accepted from a peer, in bytes.
+    #[arg(
+        long,
+        env = "NEVERUST_BLOCKEXC_MAX_MESSAGE_BYTES",
+        default_value_t = crate::blockexc::DEFAULT_MAX_MESSAGE_BYTES
+    )]
+    pub blockexc_max_message_bytes: usize,
+
+    /// Warn at startup when the data directory has less free space than
+    /// this many bytes (0 disables the check).
+    #[arg(long, env = "NEVERUST_MIN_FREE_DISK_BYTES", default_value_t = 0)]
+    pub min_free_disk_bytes: u64,
+
+    /// Flush the block store to disk every this many seconds (unset disables
+    /// periodic checkpoints).
+    #[arg(long, env = "NEVERUST_CHECKPOINT_INTERVAL_SECS")]
+    pub checkpoint_interval_secs: Option<u64>,
+
+    /// Dial the bootstrap peers as soon as the node is listening.
+    #[arg(
+        long,
+        env = "NEVERUST_DIAL_ON_STARTUP",
+        default_value_t = true,
+        action = clap::ArgAction::Set
+    )]
+    pub dial_on_startup: bool,
+
+    /// Limit each client IP to this many REST API requests per second
+    /// (unset disables rate limiting). `/health` and `/metrics` are exempt.
+    #[arg(long, env = "NEVERUST_API_RATE_LIMIT_RPS")]
+    pub api_rate_limit_rps: Option<u32>,
+
+    /// Requests a client may burst above the API rate limit (defaults to
+    /// the per-second rate).
+    #[arg(long, env = "NEVERUST_API_RATE_LIMIT_BURST")]
+    pub api_rate_limit_burst: Option<u32>,
+
+    /// Reverse proxies trusted to set `X-Forwarded-For` for the API rate
+    /// limit (unset ignores the header).
+    #[arg(long, env = "NEVERUST_API_TRUSTED_PROXIES", value_delimiter = ',')]
+    pub api_trusted_proxy: Vec<std::net::IpAddr>,
+
+    /// Offer BlockExc v2 ahead of v1 (false speaks v1 only). Peers without
+    /// v2 are always served over v1.
+    #[arg(
+        long,
+        env = "NEVERUST_ENABLE_BLOCKEXC_V2",
+        default_value_t = true,
+        action = clap::ArgAction::Set
+    )]
+    pub enable_blockexc_v2: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -181,6 +384,8 @@ pub struct Config {
     #[serde(default)]
     pub bootstrap_nodes: Vec<String>,
     #[serde(default)]
+    pub no_bootstrap: bool,
+    #[serde(default)]
     pub announce_addrs: Vec<String>,
     pub mode: String,
     pub price_per_byte: u64,
@@ -224,6 +429,40 @@ pub struct Config {
     pub citadel_max_ops_per_origin_per_round: u32,
     #[serde(default = "default_citadel_max_new_origins_per_host_per_round")]
     pub citadel_max_new_origins_per_host_per_round: u32,
+    #[serde(default)]
+    pub advertiser_flush_on_stop: bool,
+    #[serde(default = "default_advertiser_batch_size")]
+    pub advertiser_batch_size: usize,
+    #[serde(default = "default_fetch_strategy")]
+    pub fetch_strategy: Vec<FetchSource>,
+    #[serde(default)]
+    pub ec_k: u32,
+    #[serde(default)]
+    pub ec_m: u32,
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    #[serde(default)]
+    pub peer_rate_limit_bytes: u64,
+    #[serde(default)]
+    pub rebuild_index: bool,
+    #[serde(default)]
+    pub verify_on_read: bool,
+    #[serde(default)]
+    pub delete_corrupted_on_read: bool,
+    #[serde(default = "default_blockexc_request_timeout_secs")]
+    pub blockexc_request_timeout_secs: u64,
+    #[serde(default = "default_blockexc_max_message_bytes")]
+    pub blockexc_max_message_bytes: usize,
+    #[serde(default)]
+    pub min_free_disk_bytes: u64,
+    #[serde(default)]
+    pub checkpoint_interval_secs: Option<u64>,
+    #[serde(default = "default_dial_on_startup")]
+    pub dial_on_startup: bool,
+    #[serde(default)]
+    pub api_rate_limit: Option<RateLimitConfig>,
+    #[serde(default = "default_enable_blockexc_v2")]
+    pub enable_blockexc_v2: bool,
 }
 
 fn default_api_bind() -> String {
@@ -258,6 +497,34 @@ fn default_citadel_max_new_origins_per_host_per_round() -> u32 {
     12
 }
 
+fn default_advertiser_batch_size() -> usize {
+    1
+}
+
+fn default_dial_on_startup() -> bool {
+    true
+}
+
+fn default_enable_blockexc_v2() -> bool {
+    true
+}
+
+fn default_fetch_strategy() -> Vec<FetchSource> {
+    DEFAULT_FETCH_STRATEGY.to_vec()
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_blockexc_request_timeout_secs() -> u64 {
+    crate::blockexc::DEFAULT_REQUEST_TIMEOUT.as_secs()
+}
+
+fn default_blockexc_max_message_bytes() -> usize {
+    crate::blockexc::DEFAULT_MAX_MESSAGE_BYTES
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -268,6 +535,7 @@ impl Default for Config {
             api_bind: default_api_bind(),
             log_level: "info".to_string(),
             bootstrap_nodes: Vec::new(),
+            no_bootstrap: false,
             announce_addrs: Vec::new(),
             mode: "altruistic".to_string(),
             price_per_byte: 1,
@@ -291,6 +559,23 @@ impl Default for Config {
             citadel_trusted_pow_bits: 4,
             citadel_max_ops_per_origin_per_round: 96,
             citadel_max_new_origins_per_host_per_round: 12,
+            advertiser_flush_on_stop: false,
+            advertiser_batch_size: 1,
+            fetch_strategy: default_fetch_strategy(),
+            ec_k: 0,
+            ec_m: 0,
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            peer_rate_limit_bytes: 0,
+            rebuild_index: false,
+            verify_on_read: false,
+            delete_corrupted_on_read: false,
+            blockexc_request_timeout_secs: default_blockexc_request_timeout_secs(),
+            blockexc_max_message_bytes: default_blockexc_max_message_bytes(),
+            min_free_disk_bytes: 0,
+            checkpoint_interval_secs: None,
+            dial_on_startup: default_dial_on_startup(),
+            api_rate_limit: None,
+            enable_blockexc_v2: default_enable_blockexc_v2(),
         }
     }
 }
@@ -302,6 +587,8 @@ pub enum CliAction {
     Start(Config),
     /// Generate an ETH key at the given path, then exit.
     GenerateKey(PathBuf),
+    /// Run a block store command without starting the node, then exit.
+    Offline(OfflineCommand),
 }
 
 impl Config {
@@ -311,16 +598,58 @@ impl Config {
         match cli.command {
             Commands::Start(cmd) => Ok(CliAction::Start(cmd.into())),
             Commands::GenerateKey(cmd) => Ok(CliAction::GenerateKey(cmd.output)),
+            Commands::Info(args) => Ok(CliAction::Offline(OfflineCommand::Info {
+                store_path: args.store_path,
+            })),
+            Commands::Verify(args) => Ok(CliAction::Offline(OfflineCommand::Verify {
+                store_path: args.store_path,
+            })),
+            Commands::Export(cmd) => Ok(CliAction::Offline(OfflineCommand::Export {
+                store_path: cmd.store_path,
+                output: cmd.output,
+            })),
+            Commands::Import(cmd) => Ok(CliAction::Offline(OfflineCommand::Import {
+                store_path: cmd.store_path,
+                input: cmd.input,
+            })),
+            Commands::Manifest(ManifestCommand::Show {
+                store_path,
+                manifest_cid,
+            }) => Ok(CliAction::Offline(OfflineCommand::ManifestShow {
+                store_path,
+                manifest_cid,
+            })),
+        }
+    }
+
+    /// Create config from `NEVERUST_*` environment variables
+    ///
+    /// Every `start` flag can be set through the variable named after it
+    /// (e.g. `NEVERUST_LOG_LEVEL`; list flags take comma-separated values, as
+    /// in `NEVERUST_BOOTSTRAP_PEERS`). Unset variables fall back to the CLI
+    /// defaults, except [`DATA_DIR_ENV`], which is required.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        if std::env::var_os(DATA_DIR_ENV).is_none() {
+            return Err(ConfigError::MissingEnvVar(DATA_DIR_ENV.to_string()));
         }
+        let cmd = StartCommand::try_parse_from(["neverust"])
+            .map_err(|e| ConfigError::Invalid(e.to_string()))?;
+        Ok(cmd.into())
     }
 
     /// Create config from CLI arguments (convenience wrapper for `start`).
+    ///
+    /// `NEVERUST_*` environment variables provide defaults for flags that are
+    /// not given on the command line.
     pub fn from_cli() -> Result<Self, ConfigError> {
         match Self::parse_cli()? {
             CliAction::Start(cfg) => Ok(cfg),
             CliAction::GenerateKey(_) => Err(ConfigError::Invalid(
                 "generate-key command should be handled by main".to_string(),
             )),
+            CliAction::Offline(_) => Err(ConfigError::Invalid(
+                "offline commands should be handled by main".to_string(),
+            )),
         }
     }
 
@@ -443,16 +772,90 @@ impl Config {
 
         // Fall back to Archivist testnet bootstrap nodes
         tracing::info!("Falling back to Archivist testnet bootstrap nodes");
-        Self::fetch_testnet_bootstrap_nodes().await
+        match Self::fetch_testnet_bootstrap_nodes().await {
+            Ok(nodes) => Ok(nodes),
+            Err(e) => {
+                tracing::warn!("{}; using last-known testnet bootstrap nodes", e);
+                Ok(Self::fallback_bootstrap_nodes())
+            }
+        }
     }
 
     /// Fetch bootstrap nodes from Archivist testnet
+    ///
+    /// Retries up to [`BOOTSTRAP_FETCH_ATTEMPTS`] times with exponential
+    /// backoff.
     pub async fn fetch_testnet_bootstrap_nodes() -> Result<Vec<String>, ConfigError> {
+        Self::fetch_testnet_bootstrap_nodes_with_retry(BOOTSTRAP_FETCH_ATTEMPTS).await
+    }
+
+    /// Fetch bootstrap nodes from Archivist testnet, making up to
+    /// `max_attempts` attempts
+    ///
+    /// Waits 1s before the first retry and doubles the delay after each
+    /// failure. Returns [`ConfigError::BootstrapFetchFailed`] with the last
+    /// error once all attempts have failed.
+    pub async fn fetch_testnet_bootstrap_nodes_with_retry(
+        max_attempts: u32,
+    ) -> Result<Vec<String>, ConfigError> {
+        Self::fetch_spr_bootstrap_nodes_with_retry(
+            TESTNET_SPR_URL,
+            max_attempts,
+            BOOTSTRAP_RETRY_BASE_DELAY,
+        )
+        .await
+    }
+
+    /// Last-known Archivist testnet bootstrap multiaddrs
+    ///
+    /// Lets a node start when the testnet SPR list is unreachable.
+    pub fn fallback_bootstrap_nodes() -> Vec<String> {
+        FALLBACK_BOOTSTRAP_NODES
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect()
+    }
+
+    async fn fetch_spr_bootstrap_nodes_with_retry(
+        url: &str,
+        max_attempts: u32,
+        base_delay: Duration,
+    ) -> Result<Vec<String>, ConfigError> {
+        let mut delay = base_delay;
+        let mut last_error = String::from("no attempts made");
+
+        for attempt in 1..=max_attempts {
+            match Self::fetch_spr_bootstrap_nodes(url).await {
+                Ok(nodes) => return Ok(nodes),
+                Err(e) => {
+                    tracing::warn!(
+                        "Bootstrap fetch attempt {}/{} from {} failed: {}",
+                        attempt,
+                        max_attempts,
+                        url,
+                        e
+                    );
+                    last_error = e.to_string();
+                }
+            }
+
+            if attempt < max_attempts {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+
+        Err(ConfigError::BootstrapFetchFailed(last_error))
+    }
+
+    /// Fetch and convert the SPR list at `url` once
+    async fn fetch_spr_bootstrap_nodes(url: &str) -> Result<Vec<String>, ConfigError> {
         use crate::spr::parse_spr_records;
 
-        // Fetch SPR records from testnet
-        let response = reqwest::get("https://spr.archivist.storage/testnet")
+        // Fetch SPR records
+        let response = reqwest::get(url)
             .await
+            .and_then(|response| response.error_for_status())
             .map_err(|e| ConfigError::Io(std::io::Error::other(e.to_string())))?
             .text()
             .await
@@ -469,19 +872,19 @@ impl Config {
         // Convert UDP discovery addresses to TCP for actual connections
         // Archivist testnet nodes use TCP+Noise+Mplex (NOT QUIC)
         let mut multiaddrs = Vec::new();
-        for (peer_id, addrs) in records {
-            for addr in addrs {
+        for record in records {
+            for addr in record.to_multiaddrs() {
                 let addr_str = addr.to_string();
                 // SPR contains UDP addresses - convert to TCP multiaddrs
                 if addr_str.contains("/udp/") {
                     // Convert /ip4/X.X.X.X/udp/PORT to /ip4/X.X.X.X/tcp/PORT/p2p/PEER_ID
                     let tcp_addr = addr_str.replace("/udp/", "/tcp/");
-                    let full_addr = format!("{}/p2p/{}", tcp_addr, peer_id);
+                    let full_addr = format!("{}/p2p/{}", tcp_addr, record.peer_id);
                     tracing::info!("Converted UDP to TCP: {} -> {}", addr_str, full_addr);
                     multiaddrs.push(full_addr);
                 } else {
                     // For other protocols, just add peer ID
-                    let full_addr = format!("{}/p2p/{}", addr, peer_id);
+                    let full_addr = format!("{}/p2p/{}", addr, record.peer_id);
                     tracing::info!("Other protocol: {}", full_addr);
                     multiaddrs.push(full_addr);
                 }
@@ -556,7 +959,7 @@ impl Config {
             }
         }
 
-        let response = reqwest::get("https://spr.archivist.storage/testnet")
+        let response = reqwest::get(TESTNET_SPR_URL)
             .await
             .map_err(|e| ConfigError::Io(std::io::Error::other(e.to_string())))?
             .text()
@@ -618,6 +1021,7 @@ impl From<StartCommand> for Config {
             api_bind: cmd.api_bind,
             log_level: cmd.log_level,
             bootstrap_nodes: cmd.bootstrap_node,
+            no_bootstrap: cmd.no_bootstrap,
             announce_addrs: cmd.announce_addr,
             mode: cmd.mode,
             price_per_byte: cmd.price_per_byte,
@@ -642,6 +1046,29 @@ impl From<StartCommand> for Config {
             citadel_max_ops_per_origin_per_round: cmd.citadel_max_ops_per_origin_per_round,
             citadel_max_new_origins_per_host_per_round: cmd
                 .citadel_max_new_origins_per_host_per_round,
+            advertiser_flush_on_stop: cmd.advertiser_flush_on_stop,
+            advertiser_batch_size: cmd.advertiser_batch_size,
+            fetch_strategy: cmd.fetch_strategy,
+            ec_k: cmd.ec_k,
+            ec_m: cmd.ec_m,
+            shutdown_timeout_secs: cmd.shutdown_timeout_secs,
+            peer_rate_limit_bytes: cmd.peer_rate_limit_bytes,
+            rebuild_index: cmd.rebuild_index,
+            verify_on_read: cmd.verify_on_read,
+            delete_corrupted_on_read: cmd.delete_corrupted_on_read,
+            blockexc_request_timeout_secs: cmd.blockexc_request_timeout_secs,
+            blockexc_max_message_bytes: cmd.blockexc_max_message_bytes,
+            min_free_disk_bytes: cmd.min_free_disk_bytes,
+            checkpoint_interval_secs: cmd.checkpoint_interval_secs,
+            dial_on_startup: cmd.dial_on_startup,
+            api_rate_limit: cmd
+                .api_rate_limit_rps
+                .map(|requests_per_second| RateLimitConfig {
+                    requests_per_second,
+                    burst_size: cmd.api_rate_limit_burst.unwrap_or(requests_per_second),
+                    trusted_proxies: cmd.api_trusted_proxy.clone(),
+                }),
+            enable_blockexc_v2: cmd.enable_blockexc_v2,
         }
     }
 }
@@ -649,6 +1076,39 @@ impl From<StartCommand> for Config {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serializes tests that change process environment variables
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    /// Holds [`ENV_LOCK`] and restores the saved variables when dropped
+    struct EnvGuard {
+        saved: Vec<(&'static str, Option<std::ffi::OsString>)>,
+        _lock: std::sync::MutexGuard<'static, ()>,
+    }
+
+    impl EnvGuard {
+        fn new(names: impl IntoIterator<Item = &'static str>) -> Self {
+            let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let saved = names
+                .into_iter()
+                .map(|name| (name, std::env::var_os(name)))
+                .collect();
+            Self { saved, _lock: lock }
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for (name, value) in &self.saved {
+                match value {
+                    Some(value) => std::env::set_var(name, value),
+                    None => std::env::remove_var(name),
+                }
+            }
+        }
+    }
 
     #[test]
     fn test_default_config() {
@@ -659,6 +1119,12 @@ mod tests {
         assert_eq!(config.log_level, "info");
         assert!(!config.citadel_mode);
         assert_eq!(config.citadel_idle_bandwidth_kib, 100);
+        assert_eq!(config.fetch_strategy, DEFAULT_FETCH_STRATEGY.to_vec());
+        assert!(config.dial_on_startup);
+        assert!(!config.verify_on_read);
+        assert!(!config.delete_corrupted_on_read);
+        assert_eq!(config.api_rate_limit, None);
+        assert!(config.enable_blockexc_v2);
     }
 
     #[test]
@@ -682,6 +1148,7 @@ mod tests {
             prover: true,
             log_level: "debug".to_string(),
             bootstrap_node: vec!["/ip4/1.2.3.4/tcp/8070/p2p/12D3KooTest".to_string()],
+            no_bootstrap: true,
             announce_addr: vec![],
             citadel_mode: true,
             citadel_site_id: 42,
@@ -694,6 +1161,25 @@ mod tests {
             citadel_trusted_pow_bits: 5,
             citadel_max_ops_per_origin_per_round: 32,
             citadel_max_new_origins_per_host_per_round: 6,
+            advertiser_flush_on_stop: true,
+            advertiser_batch_size: 5,
+            fetch_strategy: vec![FetchSource::Http, FetchSource::Local],
+            ec_k: 4,
+            ec_m: 2,
+            shutdown_timeout_secs: 5,
+            peer_rate_limit_bytes: 1 << 20,
+            rebuild_index: true,
+            verify_on_read: true,
+            delete_corrupted_on_read: true,
+            blockexc_request_timeout_secs: 10,
+            blockexc_max_message_bytes: 4 << 20,
+            min_free_disk_bytes: 1 << 30,
+            checkpoint_interval_secs: Some(60),
+            dial_on_startup: false,
+            api_rate_limit_rps: Some(10),
+            api_rate_limit_burst: None,
+            api_trusted_proxy: vec!["10.0.0.1".parse().unwrap()],
+            enable_blockexc_v2: false,
         };
 
         let config: Config = cmd.into();
@@ -710,8 +1196,35 @@ mod tests {
         assert_eq!(config.marketplace_address.as_deref(), Some("0xdef"));
         assert!(config.validator);
         assert!(config.prover);
+        assert!(config.advertiser_flush_on_stop);
+        assert_eq!(config.advertiser_batch_size, 5);
+        assert_eq!(
+            config.fetch_strategy,
+            vec![FetchSource::Http, FetchSource::Local]
+        );
+        assert_eq!((config.ec_k, config.ec_m), (4, 2));
+        assert_eq!(config.shutdown_timeout_secs, 5);
+        assert_eq!(config.peer_rate_limit_bytes, 1 << 20);
+        assert!(config.rebuild_index);
+        assert!(config.verify_on_read);
+        assert!(config.delete_corrupted_on_read);
+        assert_eq!(config.blockexc_request_timeout_secs, 10);
+        assert_eq!(config.blockexc_max_message_bytes, 4 << 20);
+        assert_eq!(config.min_free_disk_bytes, 1 << 30);
+        assert_eq!(config.checkpoint_interval_secs, Some(60));
+        assert!(!config.dial_on_startup);
+        assert_eq!(
+            config.api_rate_limit,
+            Some(RateLimitConfig {
+                requests_per_second: 10,
+                burst_size: 10,
+                trusted_proxies: vec!["10.0.0.1".parse().unwrap()],
+            })
+        );
+        assert!(!config.enable_blockexc_v2);
         assert_eq!(config.log_level, "debug");
         assert_eq!(config.bootstrap_nodes.len(), 1);
+        assert!(config.no_bootstrap);
         assert!(config.citadel_mode);
         assert_eq!(config.citadel_site_id, 42);
         assert_eq!(config.citadel_node_id, 7);
@@ -720,6 +1233,60 @@ mod tests {
         assert_eq!(config.citadel_idle_bandwidth_kib, 64);
     }
 
+    #[test]
+    fn test_config_from_env() {
+        let vars = [
+            ("NEVERUST_LISTEN_PORT", "9100"),
+            ("NEVERUST_LOG_LEVEL", "debug"),
+            (
+                "NEVERUST_BOOTSTRAP_PEERS",
+                "/ip4/1.2.3.4/tcp/8070,spr:abc123",
+            ),
+            ("NEVERUST_VALIDATOR", "true"),
+            ("NEVERUST_ETH_PROVIDER", "https://rpc.example"),
+        ];
+        let _env = EnvGuard::new(vars.iter().map(|(name, _)| *name).chain([DATA_DIR_ENV]));
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+
+        std::env::remove_var(DATA_DIR_ENV);
+        assert!(matches!(
+            Config::from_env(),
+            Err(ConfigError::MissingEnvVar(name)) if name == DATA_DIR_ENV
+        ));
+
+        std::env::set_var(DATA_DIR_ENV, "/var/lib/neverust");
+        let config = Config::from_env().unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/neverust"));
+        assert_eq!(config.listen_port, 9100);
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(
+            config.bootstrap_nodes,
+            vec!["/ip4/1.2.3.4/tcp/8070", "spr:abc123"]
+        );
+        assert!(config.validator);
+        assert_eq!(config.eth_provider.as_deref(), Some("https://rpc.example"));
+        assert_eq!(config.api_port, 8080);
+
+        // CLI flags take precedence over the environment
+        let cmd = StartCommand::try_parse_from([
+            "neverust",
+            "--log-level",
+            "warn",
+            "--listen-port",
+            "9200",
+        ])
+        .unwrap();
+        let config: Config = cmd.into();
+        assert_eq!(config.log_level, "warn");
+        assert_eq!(config.listen_port, 9200);
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/neverust"));
+
+        std::env::set_var("NEVERUST_LISTEN_PORT", "not-a-port");
+        assert!(matches!(Config::from_env(), Err(ConfigError::Invalid(_))));
+    }
+
     #[test]
     fn test_filter_discv5_bootstrap_nodes() {
         let nodes = vec![
@@ -741,4 +1308,73 @@ mod tests {
 
         assert_eq!(extracted, vec!["spr:first", "enr:-second"]);
     }
+
+    /// Serve `spr_text` from a local SPR list endpoint, failing the first
+    /// `failures` requests with a 503
+    async fn spawn_spr_server(
+        spr_text: &'static str,
+        failures: usize,
+    ) -> (String, Arc<AtomicUsize>) {
+        use axum::extract::State;
+        use axum::http::StatusCode;
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new()
+            .route(
+                "/testnet",
+                axum::routing::get(move |State(hits): State<Arc<AtomicUsize>>| async move {
+                    if hits.fetch_add(1, Ordering::SeqCst) < failures {
+                        Err(StatusCode::SERVICE_UNAVAILABLE)
+                    } else {
+                        Ok(spr_text)
+                    }
+                }),
+            )
+            .with_state(hits.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}/testnet", addr), hits)
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_fetch_retries_until_success() {
+        let spr_text = "spr:CiUIAhIhA5mg11LZgFQ4XzIRb1T5xw9muFW1ALNKTijyKhQmvKYXEgIDARpJCicAJQgCEiEDmaDXUtmAVDhfMhFvVPnHD2a4VbUAs0pOKPIqFCa8phcQl-XFxQYaCwoJBE4vqKqRAnU6GgsKCQROL6iqkQJ1OipHMEUCIQDfzVYbN6A_O4i29e_FtDDUo7GJS3bkXRQtoteYbPSFtgIgcc8Kgj2ggVJyK16EY9xi4bY2lpTTeNIRjvslXSRdN5w";
+        let (url, hits) = spawn_spr_server(spr_text, 2).await;
+
+        let nodes = Config::fetch_spr_bootstrap_nodes_with_retry(&url, 5, Duration::from_millis(1))
+            .await
+            .unwrap();
+
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(!nodes.is_empty());
+        assert!(nodes.iter().all(|node| node.contains("/tcp/")));
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_fetch_gives_up_after_max_attempts() {
+        let (url, hits) = spawn_spr_server("", usize::MAX).await;
+
+        let result =
+            Config::fetch_spr_bootstrap_nodes_with_retry(&url, 4, Duration::from_millis(1)).await;
+
+        assert!(matches!(result, Err(ConfigError::BootstrapFetchFailed(e)) if e.contains("503")));
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_fallback_bootstrap_nodes_are_dialable() {
+        let nodes = Config::fallback_bootstrap_nodes();
+        assert!(!nodes.is_empty());
+        for node in nodes {
+            let addr: libp2p::Multiaddr = node.parse().unwrap();
+            assert!(matches!(
+                addr.iter().last(),
+                Some(libp2p::multiaddr::Protocol::P2p(_))
+            ));
+        }
+    }
 }
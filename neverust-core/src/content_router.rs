@@ -0,0 +1,161 @@
+//! Content routing table
+//!
+//! Remembers which peers announced (via BlockExc `BlockPresence::Have`) that
+//! they hold a block, so later requests for that block can go to those peers
+//! instead of being broadcast. Entries are timestamped and evicted once they
+//! are older than a TTL.
+//!
+//! Expected block sizes (e.g. from a manifest being downloaded) can be
+//! recorded too, so pending wants can be reported in bytes. They expire with
+//! the same TTL.
+
+use cid::Cid;
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Default age after which a routing entry is considered stale
+pub const DEFAULT_ROUTE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Default interval between stale-entry sweeps
+pub const DEFAULT_EVICT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maps block CIDs to the peers known to have them
+#[derive(Debug, Default)]
+pub struct ContentRouter {
+    table: RwLock<HashMap<Cid, Vec<(PeerId, Instant)>>>,
+    sizes: RwLock<HashMap<Cid, (u64, Instant)>>,
+}
+
+impl ContentRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `peer` has `cid`, refreshing the timestamp if already known
+    pub fn record(&self, cid: Cid, peer: PeerId) {
+        let mut table = self.table.write().unwrap();
+        let peers = table.entry(cid).or_default();
+        let now = Instant::now();
+        match peers.iter_mut().find(|(p, _)| *p == peer) {
+            Some(entry) => entry.1 = now,
+            None => peers.push((peer, now)),
+        }
+    }
+
+    /// Forget that `peer` has `cid` (e.g. after a `DontHave` presence)
+    pub fn remove(&self, cid: &Cid, peer: &PeerId) {
+        let mut table = self.table.write().unwrap();
+        if let Some(peers) = table.get_mut(cid) {
+            peers.retain(|(p, _)| p != peer);
+            if peers.is_empty() {
+                table.remove(cid);
+            }
+        }
+    }
+
+    /// Peers known to have `cid`, most recently seen first
+    pub fn peers_for(&self, cid: &Cid) -> Vec<PeerId> {
+        let table = self.table.read().unwrap();
+        let Some(peers) = table.get(cid) else {
+            return Vec::new();
+        };
+        let mut peers = peers.clone();
+        peers.sort_by_key(|(_, seen)| std::cmp::Reverse(*seen));
+        peers.into_iter().map(|(peer, _)| peer).collect()
+    }
+
+    /// Record the expected size of `cid` in bytes
+    pub fn record_size(&self, cid: Cid, size: u64) {
+        self.sizes
+            .write()
+            .unwrap()
+            .insert(cid, (size, Instant::now()));
+    }
+
+    /// Expected size of `cid` in bytes, if known
+    pub fn size_of(&self, cid: &Cid) -> Option<u64> {
+        self.sizes.read().unwrap().get(cid).map(|(size, _)| *size)
+    }
+
+    /// Remove entries older than `ttl`, returning how many were evicted
+    ///
+    /// Only peer entries are counted; stale sizes are dropped as well.
+    pub fn evict_stale(&self, ttl: Duration) -> usize {
+        self.sizes
+            .write()
+            .unwrap()
+            .retain(|_, (_, recorded)| recorded.elapsed() < ttl);
+
+        let mut table = self.table.write().unwrap();
+        let mut evicted = 0;
+        table.retain(|_, peers| {
+            let before = peers.len();
+            peers.retain(|(_, seen)| seen.elapsed() < ttl);
+            evicted += before - peers.len();
+            !peers.is_empty()
+        });
+        if evicted > 0 {
+            debug!("Content router: evicted {} stale entries", evicted);
+        }
+        evicted
+    }
+
+    /// Number of CIDs with at least one known peer
+    pub fn len(&self) -> usize {
+        self.table.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cid_blake3::blake3_cid;
+
+    #[test]
+    fn test_record_and_lookup() {
+        let router = ContentRouter::new();
+        let cid = blake3_cid(b"routed block").unwrap();
+        let peer1 = PeerId::random();
+        let peer2 = PeerId::random();
+
+        router.record(cid, peer1);
+        std::thread::sleep(Duration::from_millis(2));
+        router.record(cid, peer2);
+        router.record(cid, peer2);
+
+        assert_eq!(router.peers_for(&cid), vec![peer2, peer1]);
+        assert_eq!(router.len(), 1);
+
+        router.remove(&cid, &peer2);
+        assert_eq!(router.peers_for(&cid), vec![peer1]);
+        router.remove(&cid, &peer1);
+        assert!(router.is_empty());
+    }
+
+    #[test]
+    fn test_evict_stale() {
+        let router = ContentRouter::new();
+        let old = blake3_cid(b"old block").unwrap();
+        let fresh = blake3_cid(b"fresh block").unwrap();
+        let peer = PeerId::random();
+
+        router.record(old, peer);
+        router.record_size(old, 1024);
+        std::thread::sleep(Duration::from_millis(30));
+        router.record(fresh, peer);
+        router.record_size(fresh, 2048);
+
+        assert_eq!(router.evict_stale(Duration::from_millis(20)), 1);
+        assert!(router.peers_for(&old).is_empty());
+        assert_eq!(router.peers_for(&fresh), vec![peer]);
+        assert_eq!(router.size_of(&old), None);
+        assert_eq!(router.size_of(&fresh), Some(2048));
+    }
+}
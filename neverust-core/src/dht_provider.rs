@@ -6,6 +6,7 @@
 
 use cid::Cid;
 use discv5::enr::NodeId;
+use prost::Message;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -17,10 +18,35 @@ use tracing::{debug, info, warn};
 const MAX_PROVIDERS_PER_ENTRY: usize = 20;
 
 /// Default TTL for provider records (24 hours).
-const PROVIDER_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+pub const PROVIDER_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// TALK protocol carrying several AddProvider records in one request.
+pub const TALK_PROTOCOL_ADD_PROVIDER_BATCH: &[u8] = b"add_provider_batch";
+
+/// One content ID / provider record pair inside a batch.
+#[derive(Clone, PartialEq, Message)]
+pub struct ProviderEntry {
+    #[prost(bytes = "vec", tag = "1")]
+    pub content_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub provider_record: Vec<u8>,
+}
+
+/// Body of an `add_provider_batch` TALK request.
+#[derive(Clone, PartialEq, Message)]
+pub struct AddProviderBatchRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub records: Vec<ProviderEntry>,
+}
 
 /// Convert a CID to a DiscV5 NodeId via keccak256, matching Archivist's
 /// `toNodeId` function: `readUintBE[256](keccak256.digest(cid.data.buffer).data)`.
+///
+/// The hash must stay Keccak-256: the NodeId decides which DHT nodes store a
+/// CID's provider records, so a node deriving it any other way (including
+/// SHA3-256, which pads differently and gives a different digest) would
+/// announce to and query the wrong part of the keyspace, and its records
+/// would be invisible to Archivist nodes.
 pub fn cid_to_node_id(cid: &Cid) -> NodeId {
     let cid_bytes = cid.to_bytes();
     let mut hasher = Keccak::v256();
@@ -30,73 +56,165 @@ pub fn cid_to_node_id(cid: &Cid) -> NodeId {
     NodeId::new(&hash)
 }
 
-/// A single provider record with expiry.
+/// Convert a CID to a DiscV5 NodeId via BLAKE3
+///
+/// Faster than [`cid_to_node_id`] but not compatible with it, so it is only
+/// usable in a future protocol version where every node switches over.
+#[cfg(feature = "blake3-node-id")]
+pub fn cid_to_node_id_blake3(cid: &Cid) -> NodeId {
+    NodeId::new(blake3::hash(&cid.to_bytes()).as_bytes())
+}
+
+/// Convert a libp2p PeerId to a DiscV5 NodeId via keccak256 of its bytes,
+/// giving the DHT key to search when looking up a peer.
+pub fn peer_id_to_node_id(peer_id: &libp2p::PeerId) -> NodeId {
+    let mut hasher = Keccak::v256();
+    let mut hash = [0u8; 32];
+    hasher.update(&peer_id.to_bytes());
+    hasher.finalize(&mut hash);
+    NodeId::new(&hash)
+}
+
+/// A single provider record with the time it was added or last refreshed.
 #[derive(Clone, Debug)]
 struct ProviderRecord {
     /// The raw SignedPeerRecord bytes (protobuf-encoded).
     signed_peer_record: Vec<u8>,
-    /// When this record expires.
-    expires: Instant,
+    /// When this record was added or last refreshed.
+    timestamp: Instant,
+}
+
+impl ProviderRecord {
+    fn is_live(&self, now: Instant, ttl: Duration) -> bool {
+        now.saturating_duration_since(self.timestamp) < ttl
+    }
 }
 
 /// In-memory store of provider records keyed by content NodeId.
 pub struct ProviderStore {
     records: HashMap<NodeId, Vec<ProviderRecord>>,
+    /// CIDs this node provides itself, with when they were last provided.
+    local: HashMap<NodeId, (Cid, Instant)>,
+    /// Age after which provider records expire.
+    ttl: Duration,
 }
 
 impl ProviderStore {
     pub fn new() -> Self {
+        Self::new_with_ttl(PROVIDER_TTL)
+    }
+
+    /// Create a store whose provider records expire after `ttl`.
+    pub fn new_with_ttl(ttl: Duration) -> Self {
         Self {
             records: HashMap::new(),
+            local: HashMap::new(),
+            ttl,
         }
     }
 
+    /// Change the age after which provider records expire.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
     /// Add a provider record for a content ID.
     pub fn add(&mut self, content_id: NodeId, signed_peer_record: Vec<u8>) {
+        self.add_at(content_id, signed_peer_record, Instant::now());
+    }
+
+    fn add_at(&mut self, content_id: NodeId, signed_peer_record: Vec<u8>, now: Instant) {
+        let ttl = self.ttl;
         let entry = self.records.entry(content_id).or_default();
 
         // Remove expired records first.
-        let now = Instant::now();
-        entry.retain(|r| r.expires > now);
-
-        // Check for duplicate (same SPR bytes).
-        if entry.iter().any(|r| r.signed_peer_record == signed_peer_record) {
-            // Update expiry of existing record.
-            for r in entry.iter_mut() {
-                if r.signed_peer_record == signed_peer_record {
-                    r.expires = now + PROVIDER_TTL;
-                    return;
-                }
-            }
+        entry.retain(|r| r.is_live(now, ttl));
+
+        // Refresh the timestamp of a duplicate (same SPR bytes).
+        if let Some(existing) = entry
+            .iter_mut()
+            .find(|r| r.signed_peer_record == signed_peer_record)
+        {
+            existing.timestamp = now;
+            return;
         }
 
         // Evict oldest if at capacity.
         if entry.len() >= MAX_PROVIDERS_PER_ENTRY {
-            entry.sort_by_key(|r| r.expires);
+            entry.sort_by_key(|r| r.timestamp);
             entry.remove(0);
         }
 
         entry.push(ProviderRecord {
             signed_peer_record,
-            expires: now + PROVIDER_TTL,
+            timestamp: now,
         });
     }
 
+    /// Add our own provider record for a CID, remembering the CID so it can
+    /// be provided again once the record expires.
+    pub fn add_local(&mut self, cid: &Cid, signed_peer_record: Vec<u8>) {
+        self.add_local_at(cid, signed_peer_record, Instant::now());
+    }
+
+    fn add_local_at(&mut self, cid: &Cid, signed_peer_record: Vec<u8>, now: Instant) {
+        let content_id = cid_to_node_id(cid);
+        self.add_at(content_id, signed_peer_record, now);
+        self.local.insert(content_id, (*cid, now));
+    }
+
     /// Get provider records for a content ID.
     pub fn get(&mut self, content_id: &NodeId) -> Vec<Vec<u8>> {
         let now = Instant::now();
         if let Some(entry) = self.records.get_mut(content_id) {
-            entry.retain(|r| r.expires > now);
+            entry.retain(|r| r.is_live(now, self.ttl));
             entry.iter().map(|r| r.signed_peer_record.clone()).collect()
         } else {
             Vec::new()
         }
     }
 
+    /// Stop providing a CID locally, dropping our record for it.
+    pub fn remove_local(&mut self, cid: &Cid, signed_peer_record: &[u8]) {
+        let content_id = cid_to_node_id(cid);
+        self.local.remove(&content_id);
+        if let Some(entry) = self.records.get_mut(&content_id) {
+            entry.retain(|r| r.signed_peer_record != signed_peer_record);
+            if entry.is_empty() {
+                self.records.remove(&content_id);
+            }
+        }
+    }
+
+    /// Remove local and remote provider records that have expired.
+    ///
+    /// Returns the local CIDs last provided more than half of the TTL ago,
+    /// so they can be provided again before other nodes drop their records.
+    pub fn evict_expired(&mut self) -> Vec<Cid> {
+        let now = Instant::now();
+        let ttl = self.ttl;
+
+        self.records.retain(|_, entry| {
+            entry.retain(|r| r.is_live(now, ttl));
+            !entry.is_empty()
+        });
+
+        self.local
+            .values()
+            .filter(|(_, timestamp)| now.saturating_duration_since(*timestamp) >= ttl / 2)
+            .map(|(cid, _)| *cid)
+            .collect()
+    }
+
     /// Number of content IDs with provider records.
     pub fn len(&self) -> usize {
         self.records.len()
     }
+
+    /// Number of provider records across all content IDs.
+    pub fn total_providers(&self) -> usize {
+        self.records.values().map(Vec::len).sum()
+    }
 }
 
 /// Thread-safe provider store.
@@ -132,6 +250,24 @@ pub async fn handle_add_provider(
     );
 }
 
+/// Handle an inbound `add_provider_batch` TALK request. Returns the number of
+/// records stored, or `None` if the body does not decode.
+pub async fn handle_add_provider_batch(store: &SharedProviderStore, body: &[u8]) -> Option<usize> {
+    let batch = match AddProviderBatchRequest::decode(body) {
+        Ok(batch) => batch,
+        Err(e) => {
+            warn!("AddProviderBatch: failed to decode request: {}", e);
+            return None;
+        }
+    };
+
+    let count = batch.records.len();
+    for entry in batch.records {
+        handle_add_provider(store, &entry.content_id, entry.provider_record).await;
+    }
+    Some(count)
+}
+
 /// Handle an inbound GetProviders message. Returns (total, provider_records).
 pub async fn handle_get_providers(
     store: &SharedProviderStore,
@@ -183,6 +319,17 @@ mod tests {
         assert_ne!(cid_to_node_id(&cid1), cid_to_node_id(&cid2));
     }
 
+    #[cfg(feature = "blake3-node-id")]
+    #[test]
+    fn test_cid_to_node_id_blake3_differs_from_keccak() {
+        let cid: Cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
+            .parse()
+            .unwrap();
+        let id = cid_to_node_id_blake3(&cid);
+        assert_eq!(id.raw(), *blake3::hash(&cid.to_bytes()).as_bytes());
+        assert_ne!(id, cid_to_node_id(&cid));
+    }
+
     #[test]
     fn test_provider_store_add_and_get() {
         let mut store = ProviderStore::new();
@@ -240,4 +387,88 @@ mod tests {
         let id = NodeId::new(&[1u8; 32]);
         assert_eq!(store.get(&id).len(), 0);
     }
+
+    #[test]
+    fn test_evict_expired_removes_old_records() {
+        let mut store = ProviderStore::new_with_ttl(Duration::from_secs(10 * 60));
+        let now = Instant::now();
+        let hour_ago = now - Duration::from_secs(60 * 60);
+        let fresh_remote = NodeId::new(&[1u8; 32]);
+        let old_remote = NodeId::new(&[2u8; 32]);
+        let fresh_cid = crate::cid_blake3::blake3_cid(b"fresh").unwrap();
+        let old_cid = crate::cid_blake3::blake3_cid(b"old").unwrap();
+
+        store.add_at(fresh_remote, vec![0xAA], now);
+        store.add_at(old_remote, vec![0xBB], hour_ago);
+        store.add_at(fresh_remote, vec![0xCC], hour_ago);
+        store.add_local_at(&fresh_cid, vec![0xDD], now);
+        store.add_local_at(&old_cid, vec![0xDD], hour_ago);
+        assert_eq!(store.total_providers(), 5);
+
+        let due = store.evict_expired();
+        assert_eq!(due, vec![old_cid]);
+        assert_eq!(store.total_providers(), 2);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get(&fresh_remote), vec![vec![0xAA]]);
+        assert!(store.get(&old_remote).is_empty());
+        assert_eq!(store.get(&cid_to_node_id(&fresh_cid)), vec![vec![0xDD]]);
+        assert!(store.get(&cid_to_node_id(&old_cid)).is_empty());
+
+        // Providing again refreshes the CID, leaving nothing to evict
+        store.add_local_at(&old_cid, vec![0xDD], now);
+        assert!(store.evict_expired().is_empty());
+        assert_eq!(store.total_providers(), 3);
+    }
+
+    #[test]
+    fn test_local_cids_are_due_at_half_ttl() {
+        let mut store = ProviderStore::new_with_ttl(Duration::from_secs(10 * 60));
+        let now = Instant::now();
+        let cid = crate::cid_blake3::blake3_cid(b"half").unwrap();
+        store.add_local_at(&cid, vec![0xDD], now - Duration::from_secs(6 * 60));
+
+        // Due to be provided again, but the record itself is still live
+        assert_eq!(store.evict_expired(), vec![cid]);
+        assert_eq!(store.get(&cid_to_node_id(&cid)), vec![vec![0xDD]]);
+    }
+
+    #[test]
+    fn test_remove_local_keeps_other_providers() {
+        let mut store = ProviderStore::new();
+        let cid = crate::cid_blake3::blake3_cid(b"deleted").unwrap();
+        store.add(cid_to_node_id(&cid), vec![0xAA]);
+        store.add_local(&cid, vec![0xDD]);
+
+        store.remove_local(&cid, &[0xDD]);
+        assert_eq!(store.get(&cid_to_node_id(&cid)), vec![vec![0xAA]]);
+        store.set_ttl(Duration::ZERO);
+        assert!(store.evict_expired().is_empty());
+        store.set_ttl(PROVIDER_TTL);
+
+        store.add_local(&cid, vec![0xDD]);
+        store.remove_local(&cid, &[0xDD]);
+        store.remove_local(&cid, &[0xAA]);
+        assert_eq!(store.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_add_provider_batch_stores_every_record() {
+        let store = new_provider_store();
+        let records = (0..3u8)
+            .map(|i| ProviderEntry {
+                content_id: vec![i; 32],
+                provider_record: vec![i; 4],
+            })
+            .collect();
+        let body = AddProviderBatchRequest { records }.encode_to_vec();
+
+        assert_eq!(handle_add_provider_batch(&store, &body).await, Some(3));
+        for i in 0..3u8 {
+            let (total, providers) = handle_get_providers(&store, &[i; 32]).await;
+            assert_eq!(total, 1);
+            assert_eq!(providers[0], vec![i; 4]);
+        }
+
+        assert_eq!(handle_add_provider_batch(&store, b"\xff\xff").await, None);
+    }
 }
@@ -7,20 +7,45 @@
 use cid::Cid;
 use discv5::{
     enr, rpc::RequestBody, rpc::ResponseBody, ConfigBuilder, Discv5, Event as Discv5Event,
-    ListenConfig,
+    ListenConfig, TalkRequest,
 };
+use futures::future::{self, BoxFuture, FutureExt, TryFutureExt};
+use prost::Message;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot, OwnedSemaphorePermit, RwLock, Semaphore};
 use tracing::{debug, info, warn};
 
 use crate::dht_provider::{
-    cid_to_node_id, handle_add_provider, handle_get_providers, new_provider_store,
-    SharedProviderStore,
+    cid_to_node_id, handle_add_provider, handle_add_provider_batch, handle_get_providers,
+    peer_id_to_node_id, AddProviderBatchRequest, ProviderEntry, ProviderStore, SharedProviderStore,
+    PROVIDER_TTL, TALK_PROTOCOL_ADD_PROVIDER_BATCH,
 };
 use crate::identify_spr::create_signed_peer_record;
-use crate::spr::{parse_spr_records_full, SprRecord};
+use crate::spr::{parse_spr_records, SprRecord};
 
 use libp2p::identity::PeerId;
+use libp2p::Multiaddr;
+
+/// Capacity of the discovery event broadcast channel
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Number of recent query round-trip times averaged in [`DiscoveryStats`]
+const RTT_SAMPLE_WINDOW: usize = 100;
+
+/// Routing table nodes nearest to a CID that identify its routing region
+/// in [`Discovery::provide_batch`]
+const REGION_NODES: usize = 3;
+
+/// How often expired provider records are evicted
+const PROVIDER_EVICTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// ENR key holding our libp2p listen addresses as a CBOR array of
+/// multiaddr byte strings
+pub const ARCHIVIST_ADDRS_ENR_KEY: &str = "archivist-addrs";
 
 #[derive(Debug, thiserror::Error)]
 pub enum DiscoveryError {
@@ -36,12 +61,239 @@ pub enum DiscoveryError {
     #[error("No providers found for CID: {0}")]
     NoProviders(String),
 
+    #[error("DHT operation queue closed")]
+    QueueClosed,
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
 
 type Result<T> = std::result::Result<T, DiscoveryError>;
 
+/// Called with the peer ID and TCP address of each discovered libp2p node
+pub type DialCallback = Arc<dyn Fn(PeerId, Multiaddr) + Send + Sync>;
+
+/// Concurrency limits and record lifetimes for DHT operations issued by
+/// [`Discovery`]
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    /// Maximum concurrent `find_node` / `get_providers` lookups
+    pub max_concurrent_lookups: usize,
+    /// Maximum concurrent AddProvider sends
+    pub max_concurrent_provides: usize,
+    /// Timeout for a single lookup before falling back to the routing table
+    pub lookup_timeout: Duration,
+    /// Age after which provider records are evicted from the local store
+    pub provider_ttl: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_lookups: 10,
+            max_concurrent_provides: 10,
+            lookup_timeout: Duration::from_secs(30),
+            provider_ttl: PROVIDER_TTL,
+        }
+    }
+}
+
+/// FIFO admission queue bounding the number of concurrent DHT operations.
+///
+/// Every caller enqueues a waiter on an mpsc channel; a dispatcher task hands
+/// out semaphore permits in arrival order as slots become available.
+#[derive(Clone)]
+struct OpLimiter {
+    queue_tx: mpsc::UnboundedSender<oneshot::Sender<OwnedSemaphorePermit>>,
+}
+
+impl OpLimiter {
+    fn new(max_concurrent: usize) -> Self {
+        let slots = Arc::new(Semaphore::new(max_concurrent.max(1)));
+        let (queue_tx, mut queue_rx) =
+            mpsc::unbounded_channel::<oneshot::Sender<OwnedSemaphorePermit>>();
+
+        tokio::spawn(async move {
+            while let Some(waiter) = queue_rx.recv().await {
+                let Ok(permit) = slots.clone().acquire_owned().await else {
+                    break;
+                };
+                // A waiter that gave up drops the permit straight back into the pool.
+                let _ = waiter.send(permit);
+            }
+        });
+
+        Self { queue_tx }
+    }
+
+    /// Reserve a place in the queue now and wait for a slot when awaited.
+    fn acquire(&self) -> impl Future<Output = Result<OwnedSemaphorePermit>> {
+        let (tx, rx) = oneshot::channel();
+        let queued = self.queue_tx.send(tx).is_ok();
+
+        async move {
+            if !queued {
+                return Err(DiscoveryError::QueueClosed);
+            }
+            rx.await.map_err(|_| DiscoveryError::QueueClosed)
+        }
+    }
+}
+
+/// Query counters behind [`DiscoveryStats`]
+#[derive(Debug, Default)]
+struct QueryStats {
+    /// Round-trip times of the last [`RTT_SAMPLE_WINDOW`] queries, in ms
+    rtt_samples: VecDeque<u64>,
+    total_queries: u64,
+    failed_queries: u64,
+    providers_found: u64,
+    cache_hits: u64,
+}
+
+impl QueryStats {
+    fn record_query(&mut self, rtt: Duration, succeeded: bool) {
+        if self.rtt_samples.len() == RTT_SAMPLE_WINDOW {
+            self.rtt_samples.pop_front();
+        }
+        self.rtt_samples.push_back(rtt.as_millis() as u64);
+        self.total_queries += 1;
+        if !succeeded {
+            self.failed_queries += 1;
+        }
+    }
+
+    fn avg_rtt_ms(&self) -> u64 {
+        if self.rtt_samples.is_empty() {
+            return 0;
+        }
+        self.rtt_samples.iter().sum::<u64>() / self.rtt_samples.len() as u64
+    }
+}
+
+/// Result of [`Discovery::find_from`]
+#[derive(Debug)]
+pub struct FindOutcome {
+    /// Provider records found, as returned by [`Discovery::find`]
+    pub providers: Result<Vec<Vec<u8>>>,
+    /// Round-trip time of each DHT peer asked during the lookup; peers that
+    /// failed to answer are reported with the full lookup timeout
+    pub peer_rtts: Vec<(PeerId, Duration)>,
+}
+
+/// Peer lifecycle events published to [`Discovery::subscribe`] receivers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryEvent {
+    /// A libp2p peer was found in the DHT, with the addresses from its ENR
+    PeerDiscovered(PeerId, Vec<Multiaddr>),
+    /// The DiscV5 session with a previously discovered peer expired
+    PeerLost(PeerId),
+}
+
+/// Pending request to a single DHT node
+type RequestFuture<T> = BoxFuture<'static, std::result::Result<T, discv5::RequestError>>;
+
+/// DHT requests and events [`Discovery`] exchanges with other nodes
+///
+/// Implemented over the DiscV5 service, and by an in-memory network in unit
+/// tests.
+trait DhtTransport: Send + Sync {
+    /// Run a `find_node` lookup for the nodes closest to `node_id`
+    fn find_node(
+        &self,
+        node_id: enr::NodeId,
+    ) -> BoxFuture<'static, Result<Vec<enr::Enr<enr::CombinedKey>>>>;
+
+    /// Send our provider record for `content_id` to `enr`
+    fn add_provider(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        content_id: Vec<u8>,
+        provider_record: Vec<u8>,
+    ) -> RequestFuture<()>;
+
+    /// Ask `enr` for the provider records of `content_id`
+    fn get_providers(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        content_id: Vec<u8>,
+    ) -> RequestFuture<(u32, Vec<Vec<u8>>)>;
+
+    /// Send a TALK request to `enr`
+    fn talk(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        protocol: Vec<u8>,
+        request: Vec<u8>,
+    ) -> RequestFuture<Vec<u8>>;
+
+    /// Start receiving DHT events
+    fn event_stream(&self) -> BoxFuture<'static, Result<mpsc::Receiver<Discv5Event>>>;
+}
+
+/// [`DhtTransport`] over the DiscV5 service
+struct Discv5Transport(Arc<Discv5>);
+
+impl DhtTransport for Discv5Transport {
+    fn find_node(
+        &self,
+        node_id: enr::NodeId,
+    ) -> BoxFuture<'static, Result<Vec<enr::Enr<enr::CombinedKey>>>> {
+        self.0
+            .find_node(node_id)
+            .map_err(|e| DiscoveryError::Discv5Error(e.to_string()))
+            .boxed()
+    }
+
+    fn add_provider(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        content_id: Vec<u8>,
+        provider_record: Vec<u8>,
+    ) -> RequestFuture<()> {
+        match discv5::handler::NodeContact::try_from_enr(enr, self.0.ip_mode()) {
+            Ok(contact) => self
+                .0
+                .send_add_provider(contact, content_id, provider_record)
+                .boxed(),
+            Err(_) => future::ready(Err(discv5::RequestError::InvalidEnr(
+                "ENR has no usable address",
+            )))
+            .boxed(),
+        }
+    }
+
+    fn get_providers(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        content_id: Vec<u8>,
+    ) -> RequestFuture<(u32, Vec<Vec<u8>>)> {
+        self.0.get_providers(enr, content_id).boxed()
+    }
+
+    fn talk(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        protocol: Vec<u8>,
+        request: Vec<u8>,
+    ) -> RequestFuture<Vec<u8>> {
+        match discv5::handler::NodeContact::try_from_enr(enr, self.0.ip_mode()) {
+            Ok(contact) => self.0.talk_req(contact, protocol, request).boxed(),
+            Err(_) => future::ready(Err(discv5::RequestError::InvalidEnr(
+                "ENR has no usable address",
+            )))
+            .boxed(),
+        }
+    }
+
+    fn event_stream(&self) -> BoxFuture<'static, Result<mpsc::Receiver<Discv5Event>>> {
+        self.0
+            .event_stream()
+            .map_err(|e| DiscoveryError::Discv5Error(e.to_string()))
+            .boxed()
+    }
+}
+
 /// Peer discovery service using DiscV5
 pub struct Discovery {
     /// DiscV5 protocol instance
@@ -55,6 +307,30 @@ pub struct Discovery {
 
     /// Our own signed peer record bytes for provider announcements
     local_provider_record: Vec<u8>,
+
+    /// Concurrency and timeout settings
+    config: DiscoveryConfig,
+
+    /// Queue limiting concurrent lookups
+    lookup_limiter: OpLimiter,
+
+    /// Queue limiting concurrent AddProvider sends
+    provide_limiter: OpLimiter,
+
+    /// Broadcast channel for peer discovery events
+    events_tx: broadcast::Sender<DiscoveryEvent>,
+
+    /// libp2p peer IDs of discovered nodes, used to report `PeerLost`
+    discovered_peers: RwLock<HashMap<enr::NodeId, PeerId>>,
+
+    /// Lookup round-trip times and counters
+    query_stats: Mutex<QueryStats>,
+
+    /// Dials discovered peers over libp2p, see [`Discovery::set_dial_callback`]
+    dial_callback: Option<DialCallback>,
+
+    /// DHT requests and events, over `discv5` outside of unit tests
+    transport: Box<dyn DhtTransport>,
 }
 
 impl Discovery {
@@ -64,6 +340,7 @@ impl Discovery {
         listen_addr: SocketAddr,
         announce_addrs: Vec<String>,
         bootstrap_peers: Vec<String>,
+        config: DiscoveryConfig,
     ) -> Result<Self> {
         info!("Initializing DiscV5 peer discovery on {}", listen_addr);
 
@@ -113,9 +390,9 @@ impl Discovery {
             port: listen_addr.port(),
         };
 
-        let config = ConfigBuilder::new(listen_config).build();
+        let discv5_config = ConfigBuilder::new(listen_config).build();
 
-        let mut discv5 = Discv5::new(enr, enr_key, config)
+        let mut discv5 = Discv5::new(enr, enr_key, discv5_config)
             .map_err(|e| DiscoveryError::Discv5Error(e.to_string()))?;
 
         discv5
@@ -146,7 +423,7 @@ impl Discovery {
             if !peer_str.starts_with("spr:") {
                 continue;
             }
-            match parse_spr_records_full(peer_str) {
+            match parse_spr_records(peer_str) {
                 Ok(records) => {
                     for record in records {
                         if let Err(e) = bootstrap_from_spr(&discv5_arc, &record).await {
@@ -162,13 +439,31 @@ impl Discovery {
         let local_provider_record = build_provider_record(keypair, &announce_addrs);
 
         Ok(Self {
+            transport: Box::new(Discv5Transport(discv5_arc.clone())),
             discv5: discv5_arc,
             peer_id,
-            provider_store: new_provider_store(),
+            provider_store: Arc::new(RwLock::new(ProviderStore::new_with_ttl(
+                config.provider_ttl,
+            ))),
             local_provider_record,
+            lookup_limiter: OpLimiter::new(config.max_concurrent_lookups),
+            provide_limiter: OpLimiter::new(config.max_concurrent_provides),
+            config,
+            events_tx: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            discovered_peers: RwLock::new(HashMap::new()),
+            query_stats: Mutex::new(QueryStats::default()),
+            dial_callback: None,
         })
     }
 
+    /// Set the callback used to dial discovered peers over libp2p
+    ///
+    /// It is called for every discovered node whose ENR carries a `libp2p`
+    /// peer ID together with `ip4` and `tcp` fields.
+    pub fn set_dial_callback(&mut self, callback: DialCallback) {
+        self.dial_callback = Some(callback);
+    }
+
     /// Get local peer ID
     pub fn local_peer_id(&self) -> &PeerId {
         &self.peer_id
@@ -184,6 +479,49 @@ impl Discovery {
         self.discv5.local_spr_bytes()
     }
 
+    /// Publish our libp2p listen addresses in the local ENR
+    ///
+    /// The addresses are stored under [`ARCHIVIST_ADDRS_ENR_KEY`] so peers
+    /// can dial us directly, see [`Discovery::extract_listen_addrs`]. An ENR
+    /// is limited to 300 bytes, so trailing addresses that do not fit are
+    /// left out. Republishing the current addresses is a no-op.
+    pub fn set_listen_addrs(&self, mut addrs: Vec<Multiaddr>) -> Result<()> {
+        if Self::extract_listen_addrs(&self.local_enr()) == addrs {
+            return Ok(());
+        }
+
+        loop {
+            match self
+                .discv5
+                .enr_insert(ARCHIVIST_ADDRS_ENR_KEY, &encode_listen_addrs(&addrs))
+            {
+                Ok(_) => {
+                    debug!("Published {} listen addresses in ENR", addrs.len());
+                    return Ok(());
+                }
+                Err(enr::Error::ExceedsMaxSize) if !addrs.is_empty() => {
+                    let dropped = addrs.pop();
+                    warn!(
+                        "ENR full, not publishing listen address {}",
+                        dropped.expect("addrs is not empty")
+                    );
+                }
+                Err(e) => return Err(DiscoveryError::EnrError(e.to_string())),
+            }
+        }
+    }
+
+    /// Listen addresses a peer published with [`Discovery::set_listen_addrs`]
+    ///
+    /// Returns an empty list if the ENR has no such field or it does not
+    /// decode; individual invalid multiaddrs are skipped.
+    pub fn extract_listen_addrs(enr: &enr::Enr<enr::CombinedKey>) -> Vec<Multiaddr> {
+        match enr.get_decodable::<Vec<u8>>(ARCHIVIST_ADDRS_ENR_KEY) {
+            Some(Ok(bytes)) => decode_listen_addrs(&bytes),
+            _ => Vec::new(),
+        }
+    }
+
     /// Announce that we provide a specific CID to the DHT.
     ///
     /// Finds the K closest nodes to the CID's NodeId and sends
@@ -195,16 +533,14 @@ impl Discovery {
         info!("Providing CID {} to DHT (NodeId: {})", cid, node_id);
 
         // Store locally too
-        handle_add_provider(
-            &self.provider_store,
-            &content_id,
-            self.local_provider_record.clone(),
-        )
-        .await;
+        self.provider_store
+            .write()
+            .await
+            .add_local(cid, self.local_provider_record.clone());
 
         // Find K closest nodes to this content ID.
         // If find_node returns no peers, fall back to all known table entries.
-        let closest_nodes = match self.discv5.find_node(node_id).await {
+        let closest_nodes = match self.lookup_closest(node_id).await {
             Ok(nodes) if !nodes.is_empty() => nodes,
             Ok(_) => {
                 // No peers from find_node — use all routing table entries as fallback
@@ -238,26 +574,20 @@ impl Discovery {
         // (The discv5 crate's talk_req sends type 0x05, but we need type 0x0B.
         //  We use the raw request API to send AddProvider directly.)
         for enr in &closest_nodes {
-            let contact = match discv5::handler::NodeContact::try_from_enr(
+            let send = self.transport.add_provider(
                 enr.clone(),
-                self.discv5.ip_mode(),
-            ) {
-                Ok(c) => c,
-                Err(_) => continue,
-            };
-
-            // Send AddProvider (0x0B) directly via the discv5 service.
-            let discv5_clone = self.discv5.clone();
-            let cid_clone = content_id.clone();
-            let record_clone = self.local_provider_record.clone();
-            let enr_clone = enr.clone();
+                content_id.clone(),
+                self.local_provider_record.clone(),
+            );
+            let node_id = enr.node_id();
+            let slot = self.provide_limiter.acquire();
             tokio::spawn(async move {
-                match discv5_clone
-                    .send_add_provider(contact, cid_clone, record_clone)
-                    .await
-                {
-                    Ok(()) => info!("AddProvider sent to {}", enr_clone.node_id()),
-                    Err(e) => debug!("AddProvider to {} failed: {}", enr_clone.node_id(), e),
+                let Ok(_permit) = slot.await else {
+                    return;
+                };
+                match send.await {
+                    Ok(()) => info!("AddProvider sent to {}", node_id),
+                    Err(e) => debug!("AddProvider to {} failed: {}", node_id, e),
                 }
             });
         }
@@ -266,8 +596,43 @@ impl Discovery {
         Ok(())
     }
 
+    /// Stop providing `cid`, e.g. because its block was deleted.
+    ///
+    /// Drops our own provider record and stops republishing it; records
+    /// already sent to other DHT nodes expire on their own.
+    pub async fn unprovide(&self, cid: &Cid) {
+        debug!("No longer providing CID {}", cid);
+        self.provider_store
+            .write()
+            .await
+            .remove_local(cid, &self.local_provider_record);
+    }
+
     /// Find providers for a specific CID from the DHT.
     pub async fn find(&self, cid: &Cid) -> Result<Vec<Vec<u8>>> {
+        self.find_from(cid, &[]).await.providers
+    }
+
+    /// Find providers for `cid`, asking the `preferred` peers before any
+    /// other candidate node, in the order given, and stopping at the first
+    /// node that returns providers.
+    ///
+    /// Also reports how long each DHT peer asked took to answer.
+    pub async fn find_from(&self, cid: &Cid, preferred: &[PeerId]) -> FindOutcome {
+        let mut peer_rtts = Vec::new();
+        let providers = self.find_ranked(cid, preferred, &mut peer_rtts).await;
+        FindOutcome {
+            providers,
+            peer_rtts,
+        }
+    }
+
+    async fn find_ranked(
+        &self,
+        cid: &Cid,
+        preferred: &[PeerId],
+        peer_rtts: &mut Vec<(PeerId, Duration)>,
+    ) -> Result<Vec<Vec<u8>>> {
         let node_id = cid_to_node_id(cid);
         let content_id = node_id.raw().to_vec();
 
@@ -277,6 +642,7 @@ impl Discovery {
         let (_, local_providers) =
             handle_get_providers(&self.provider_store, &content_id).await;
         if !local_providers.is_empty() {
+            self.query_stats.lock().unwrap().cache_hits += 1;
             info!(
                 "Found {} local providers for CID {}",
                 local_providers.len(),
@@ -285,7 +651,7 @@ impl Discovery {
             return Ok(local_providers);
         }
 
-        let candidate_nodes = match self.discv5.find_node(node_id).await {
+        let mut candidate_nodes = match self.lookup_closest(node_id).await {
             Ok(nodes) if !nodes.is_empty() => nodes,
             Ok(_) => self.discv5.table_entries_enr(),
             Err(e) => {
@@ -293,20 +659,33 @@ impl Discovery {
                 self.discv5.table_entries_enr()
             }
         };
+        candidate_nodes.sort_by_key(|enr| {
+            enr_peer_id(enr)
+                .and_then(|peer_id| preferred.iter().position(|p| *p == peer_id))
+                .unwrap_or(usize::MAX)
+        });
 
         if candidate_nodes.is_empty() {
             warn!("No DHT peers available to query for providers");
             return Err(DiscoveryError::NoProviders(cid.to_string()));
         }
 
+        // Ask candidates in order until one of them knows providers
         let mut found = Vec::new();
         for enr in candidate_nodes {
-            match self
-                .discv5
-                .get_providers(enr.clone(), content_id.clone())
+            let _permit = self.lookup_limiter.acquire().await?;
+            let request = self.request_providers(enr.clone(), content_id.clone());
+            let started = Instant::now();
+            let result = tokio::time::timeout(self.config.lookup_timeout, request)
                 .await
-            {
+                .unwrap_or(Err(discv5::RequestError::Timeout));
+            let rtt = started.elapsed();
+            self.record_query(rtt, result.is_ok());
+            match result {
                 Ok((total, providers)) => {
+                    if let Some(peer_id) = enr_peer_id(&enr) {
+                        peer_rtts.push((peer_id, rtt));
+                    }
                     debug!(
                         "GetProviders from {} returned total={} providers={}",
                         enr.node_id(),
@@ -314,8 +693,15 @@ impl Discovery {
                         providers.len()
                     );
                     found.extend(providers);
+                    if !found.is_empty() {
+                        break;
+                    }
                 }
                 Err(e) => {
+                    // Rank failed peers as if they had taken the full timeout
+                    if let Some(peer_id) = enr_peer_id(&enr) {
+                        peer_rtts.push((peer_id, rtt.max(self.config.lookup_timeout)));
+                    }
                     debug!("GetProviders to {} failed: {}", enr.node_id(), e);
                 }
             }
@@ -325,25 +711,264 @@ impl Discovery {
             return Err(DiscoveryError::NoProviders(cid.to_string()));
         }
 
+        self.query_stats.lock().unwrap().providers_found += found.len() as u64;
         info!("Found {} remote providers for CID {}", found.len(), cid);
         Ok(found)
     }
 
+    /// Announce several CIDs at once.
+    ///
+    /// CIDs are grouped by their routing region, the [`REGION_NODES`] routing
+    /// table nodes nearest to them, and a single `find_node` walk is run per
+    /// region instead of per CID. Each node found is then sent one
+    /// `add_provider_batch` TALK request carrying all of its records.
+    pub async fn provide_batch(&self, cids: &[Cid]) -> Result<()> {
+        let table = self.discv5.table_entries_id();
+        let mut regions: HashMap<Vec<enr::NodeId>, Vec<enr::NodeId>> = HashMap::new();
+        let mut provider_store = self.provider_store.write().await;
+        for cid in cids {
+            let node_id = cid_to_node_id(cid);
+            provider_store.add_local(cid, self.local_provider_record.clone());
+
+            regions
+                .entry(routing_region(&table, &node_id))
+                .or_default()
+                .push(node_id);
+        }
+        drop(provider_store);
+        debug!(
+            "Providing {} CIDs from {} routing regions",
+            cids.len(),
+            regions.len()
+        );
+
+        let mut batches: HashMap<enr::NodeId, (enr::Enr<enr::CombinedKey>, Vec<ProviderEntry>)> =
+            HashMap::new();
+        for content_ids in regions.into_values() {
+            // Every CID of a region shares its nearest nodes, so any of them
+            // leads the walk to the same neighbourhood
+            let closest_nodes = match self.lookup_closest(content_ids[0]).await {
+                Ok(nodes) if !nodes.is_empty() => nodes,
+                Ok(_) => self.discv5.table_entries_enr(),
+                Err(e) => {
+                    warn!("Failed to find nodes for batch provide: {}", e);
+                    self.discv5.table_entries_enr()
+                }
+            };
+
+            for enr in closest_nodes {
+                batches
+                    .entry(enr.node_id())
+                    .or_insert_with(|| (enr, Vec::new()))
+                    .1
+                    .extend(content_ids.iter().map(|content_id| ProviderEntry {
+                        content_id: content_id.raw().to_vec(),
+                        provider_record: self.local_provider_record.clone(),
+                    }));
+            }
+        }
+
+        if batches.is_empty() {
+            info!("No DHT peers available for batch provide, stored locally only");
+            return Ok(());
+        }
+
+        info!(
+            "Sending {} CIDs to {} DHT nodes in batches",
+            cids.len(),
+            batches.len()
+        );
+
+        let nodes = batches.len();
+        let mut failed = 0;
+        for (enr, records) in batches.into_values() {
+            let count = records.len();
+            let body = AddProviderBatchRequest { records }.encode_to_vec();
+            let _permit = self.provide_limiter.acquire().await?;
+            let request = self.send_talk(enr.clone(), TALK_PROTOCOL_ADD_PROVIDER_BATCH, body);
+            match tokio::time::timeout(self.config.lookup_timeout, request)
+                .await
+                .unwrap_or(Err(discv5::RequestError::Timeout))
+            {
+                Ok(_) => debug!("AddProviderBatch of {} sent to {}", count, enr.node_id()),
+                Err(e) => {
+                    debug!("AddProviderBatch to {} failed: {}", enr.node_id(), e);
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed == nodes {
+            return Err(DiscoveryError::Discv5Error(format!(
+                "AddProviderBatch failed on all {} DHT nodes",
+                nodes
+            )));
+        }
+        if failed > 0 {
+            warn!(
+                "AddProviderBatch failed on {} of {} DHT nodes",
+                failed, nodes
+            );
+        }
+        Ok(())
+    }
+
+    /// Find the addresses of a libp2p peer via its ENR.
+    ///
+    /// Checks the local routing table first, then runs a `find_node` lookup
+    /// towards the keccak256 hash of the peer ID and looks for an ENR whose
+    /// `libp2p` field matches.
+    pub async fn find_peer(&self, peer_id: &PeerId) -> Result<Vec<Multiaddr>> {
+        let node_id = peer_id_to_node_id(peer_id);
+        debug!("Finding peer {} (NodeId: {})", peer_id, node_id);
+
+        if let Some(enr) = find_enr_for_peer(self.discv5.table_entries_enr(), peer_id) {
+            return Ok(enr_multiaddrs(&enr));
+        }
+
+        match self.lookup_closest(node_id).await {
+            Ok(nodes) => {
+                if let Some(enr) = find_enr_for_peer(nodes, peer_id) {
+                    info!(
+                        "Found peer {} via DHT lookup (ENR: {})",
+                        peer_id,
+                        enr.node_id()
+                    );
+                    return Ok(enr_multiaddrs(&enr));
+                }
+            }
+            Err(e) => warn!("DHT lookup for peer {} failed: {}", peer_id, e),
+        }
+
+        Err(DiscoveryError::NoProviders(peer_id.to_string()))
+    }
+
+    /// Run a `find_node` lookup once a lookup slot is free, bounded by `lookup_timeout`.
+    async fn lookup_closest(
+        &self,
+        node_id: enr::NodeId,
+    ) -> Result<Vec<enr::Enr<enr::CombinedKey>>> {
+        let _permit = self.lookup_limiter.acquire().await?;
+        let started = Instant::now();
+        let result = match tokio::time::timeout(
+            self.config.lookup_timeout,
+            self.transport.find_node(node_id),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(DiscoveryError::Discv5Error(format!(
+                "lookup timed out after {:?}",
+                self.config.lookup_timeout
+            ))),
+        };
+        self.record_query(started.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Record the outcome and round-trip time of a DHT query
+    fn record_query(&self, rtt: Duration, succeeded: bool) {
+        self.query_stats
+            .lock()
+            .unwrap()
+            .record_query(rtt, succeeded);
+    }
+
+    /// Send a GetProviders request to a single node.
+    async fn request_providers(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        content_id: Vec<u8>,
+    ) -> std::result::Result<(u32, Vec<Vec<u8>>), discv5::RequestError> {
+        self.transport.get_providers(enr, content_id).await
+    }
+
+    /// Send a TALK request to a single node.
+    async fn send_talk(
+        &self,
+        enr: enr::Enr<enr::CombinedKey>,
+        protocol: &[u8],
+        request: Vec<u8>,
+    ) -> std::result::Result<Vec<u8>, discv5::RequestError> {
+        self.transport.talk(enr, protocol.to_vec(), request).await
+    }
+
+    /// Get the concurrency configuration
+    pub fn config(&self) -> &DiscoveryConfig {
+        &self.config
+    }
+
     /// Get connected peer count
     pub fn connected_peers(&self) -> usize {
         self.discv5.connected_peers()
     }
 
+    /// Get the number of nodes in the routing table
+    pub fn routing_table_size(&self) -> usize {
+        self.discv5.table_entries_id().len()
+    }
+
+    /// Subscribe to peer discovery events
+    ///
+    /// Each receiver sees every event sent after it subscribed. Slow
+    /// receivers lag rather than block the discovery loop.
+    pub fn subscribe(&self) -> broadcast::Receiver<DiscoveryEvent> {
+        self.events_tx.subscribe()
+    }
+
     /// Get the provider store for external use
     pub fn provider_store(&self) -> &SharedProviderStore {
         &self.provider_store
     }
 
+    /// Evict provider records older than [`DiscoveryConfig::provider_ttl`]
+    /// and provide again the local CIDs last provided over half of it ago
+    ///
+    /// Republishing at half the TTL keeps our records alive on other nodes,
+    /// which drop them after the same TTL.
+    ///
+    /// Returns the number of records evicted.
+    pub async fn evict_expired_providers(&self) -> usize {
+        let (evicted, due) = {
+            let mut store = self.provider_store.write().await;
+            let before = store.total_providers();
+            let due = store.evict_expired();
+            (before - store.total_providers(), due)
+        };
+        if evicted > 0 || !due.is_empty() {
+            info!(
+                "Evicted {} expired provider records, re-providing {} local CIDs",
+                evicted,
+                due.len()
+            );
+        }
+
+        for cid in &due {
+            if let Err(e) = self.provide(cid).await {
+                warn!("Failed to re-provide {}: {}", cid, e);
+            }
+        }
+        evicted
+    }
+
     /// Run the discovery event loop
     pub async fn run(self: Arc<Self>) {
         info!("Starting DiscV5 event loop");
 
-        let mut event_stream = match self.discv5.event_stream().await {
+        let evictor = Arc::downgrade(&self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PROVIDER_EVICTION_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(discovery) = evictor.upgrade() else {
+                    break;
+                };
+                discovery.evict_expired_providers().await;
+            }
+        });
+
+        let mut event_stream = match self.transport.event_stream().await {
             Ok(stream) => stream,
             Err(e) => {
                 warn!("DiscV5 event stream failed to start: {}", e);
@@ -370,6 +995,25 @@ impl Discovery {
                             peer_id,
                             enr.node_id()
                         );
+                        self.discovered_peers
+                            .write()
+                            .await
+                            .insert(enr.node_id(), peer_id);
+                        // No subscribers is fine; the event is simply dropped.
+                        let _ = self.events_tx.send(DiscoveryEvent::PeerDiscovered(
+                            peer_id,
+                            enr_multiaddrs(&enr),
+                        ));
+
+                        if let (Some(dial), Some(ip), Some(tcp)) =
+                            (&self.dial_callback, enr.ip4(), enr.tcp4())
+                        {
+                            let addr = Multiaddr::empty()
+                                .with(libp2p::multiaddr::Protocol::Ip4(ip))
+                                .with(libp2p::multiaddr::Protocol::Tcp(tcp));
+                            debug!("Dialing discovered peer {} at {}", peer_id, addr);
+                            dial(peer_id, addr);
+                        }
                     }
                 }
             }
@@ -387,6 +1031,18 @@ impl Discovery {
                     socket_addr
                 );
             }
+            Discv5Event::SessionsExpired(expired) => {
+                let mut discovered = self.discovered_peers.write().await;
+                for node in expired {
+                    if let Some(peer_id) = discovered.remove(&node.node_id) {
+                        debug!("Session expired for peer {}", peer_id);
+                        let _ = self.events_tx.send(DiscoveryEvent::PeerLost(peer_id));
+                    }
+                }
+            }
+            Discv5Event::TalkRequest(req) => {
+                self.handle_talk_request(req).await;
+            }
             Discv5Event::ProviderRequest(req) => {
                 match req.body() {
                     RequestBody::AddProvider {
@@ -429,12 +1085,43 @@ impl Discovery {
         }
     }
 
+    /// Handle an inbound TALK request
+    async fn handle_talk_request(&self, req: TalkRequest) {
+        if req.protocol() == TALK_PROTOCOL_ADD_PROVIDER_BATCH {
+            match handle_add_provider_batch(&self.provider_store, req.body()).await {
+                Some(count) => info!(
+                    "Received AddProviderBatch of {} records from {}",
+                    count,
+                    req.node_id()
+                ),
+                None => warn!("Invalid AddProviderBatch from {}", req.node_id()),
+            }
+        } else {
+            debug!(
+                "Unsupported TALK protocol {} from {}",
+                hex::encode(req.protocol()),
+                req.node_id()
+            );
+        }
+
+        // An empty response acknowledges the request.
+        if let Err(e) = req.respond(Vec::new()) {
+            debug!("Failed to respond to TALK request: {:?}", e);
+        }
+    }
+
     /// Get statistics
     pub fn stats(&self) -> DiscoveryStats {
+        let query_stats = self.query_stats.lock().unwrap();
         DiscoveryStats {
             connected_peers: self.connected_peers(),
             local_peer_id: self.peer_id,
             local_enr: self.local_enr().to_base64(),
+            avg_query_rtt_ms: query_stats.avg_rtt_ms(),
+            total_queries: query_stats.total_queries,
+            failed_queries: query_stats.failed_queries,
+            providers_found: query_stats.providers_found,
+            cache_hits: query_stats.cache_hits,
         }
     }
 }
@@ -519,6 +1206,72 @@ async fn bootstrap_from_spr(
     Ok(())
 }
 
+/// Return the first ENR whose `libp2p` field decodes to `peer_id`.
+fn find_enr_for_peer(
+    enrs: Vec<enr::Enr<enr::CombinedKey>>,
+    peer_id: &PeerId,
+) -> Option<enr::Enr<enr::CombinedKey>> {
+    enrs.into_iter()
+        .find(|enr| enr_peer_id(enr).as_ref() == Some(peer_id))
+}
+
+/// The libp2p peer ID advertised in an ENR's `libp2p` field, if any.
+fn enr_peer_id(enr: &enr::Enr<enr::CombinedKey>) -> Option<PeerId> {
+    match enr.get_decodable::<Vec<u8>>("libp2p") {
+        Some(Ok(bytes)) => PeerId::from_bytes(&bytes).ok(),
+        _ => None,
+    }
+}
+
+/// Build libp2p multiaddrs from the IP and TCP/UDP ports advertised in an ENR.
+fn enr_multiaddrs(enr: &enr::Enr<enr::CombinedKey>) -> Vec<Multiaddr> {
+    let mut addrs = Vec::new();
+    if let Some(ip) = enr.ip4() {
+        if let Some(tcp) = enr.tcp4() {
+            addrs.push(format!("/ip4/{}/tcp/{}", ip, tcp));
+        }
+        if let Some(udp) = enr.udp4() {
+            addrs.push(format!("/ip4/{}/udp/{}", ip, udp));
+        }
+    }
+    if let Some(ip) = enr.ip6() {
+        if let Some(tcp) = enr.tcp6() {
+            addrs.push(format!("/ip6/{}/tcp/{}", ip, tcp));
+        }
+        if let Some(udp) = enr.udp6() {
+            addrs.push(format!("/ip6/{}/udp/{}", ip, udp));
+        }
+    }
+    addrs.into_iter().filter_map(|a| a.parse().ok()).collect()
+}
+
+/// CBOR-encode multiaddrs as an array of byte strings.
+fn encode_listen_addrs(addrs: &[Multiaddr]) -> Vec<u8> {
+    let value = ciborium::Value::Array(
+        addrs
+            .iter()
+            .map(|addr| ciborium::Value::Bytes(addr.to_vec()))
+            .collect(),
+    );
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&value, &mut bytes).expect("writing to a Vec cannot fail");
+    bytes
+}
+
+/// Decode the output of [`encode_listen_addrs`], skipping invalid entries.
+fn decode_listen_addrs(bytes: &[u8]) -> Vec<Multiaddr> {
+    match ciborium::from_reader(bytes) {
+        Ok(ciborium::Value::Array(values)) => values
+            .into_iter()
+            .filter_map(|value| match value {
+                ciborium::Value::Bytes(bytes) => Multiaddr::try_from(bytes).ok(),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 /// Build a proper libp2p SignedPeerRecord for provider announcements.
 ///
 /// This is the format Archivist expects in AddProvider messages: a
@@ -528,8 +1281,6 @@ fn build_provider_record(
     keypair: &libp2p::identity::Keypair,
     announce_addrs: &[String],
 ) -> Vec<u8> {
-    use libp2p::Multiaddr;
-
     let peer_id = keypair.public().to_peer_id();
     let addrs: Vec<Multiaddr> = announce_addrs
         .iter()
@@ -540,19 +1291,289 @@ fn build_provider_record(
         .expect("Failed to create nim-libp2p compatible SignedPeerRecord")
 }
 
+/// The [`REGION_NODES`] nodes of `table` closest to `target` by XOR
+/// distance, sorted so CIDs sharing them map to the same key
+fn routing_region(table: &[enr::NodeId], target: &enr::NodeId) -> Vec<enr::NodeId> {
+    let distance = |node: &enr::NodeId| -> [u8; 32] {
+        let (node, target) = (node.raw(), target.raw());
+        std::array::from_fn(|i| node[i] ^ target[i])
+    };
+    let mut region = table.to_vec();
+    region.sort_by_key(distance);
+    region.truncate(REGION_NODES);
+    region.sort_by_key(|node| node.raw());
+    region
+}
+
 /// Discovery statistics
 #[derive(Debug, Clone)]
 pub struct DiscoveryStats {
     pub connected_peers: usize,
     pub local_peer_id: PeerId,
     pub local_enr: String,
+    /// Average round-trip time of the last 100 DHT queries
+    pub avg_query_rtt_ms: u64,
+    /// `find_node` and GetProviders queries sent
+    pub total_queries: u64,
+    /// Queries that failed or timed out
+    pub failed_queries: u64,
+    /// Provider records received from remote nodes
+    pub providers_found: u64,
+    /// Provider lookups answered from the local provider store
+    pub cache_hits: u64,
+}
+
+/// In-memory stand-in for the DiscV5 network, for unit tests that should not
+/// bind sockets.
+///
+/// Lookups return the nodes registered with [`MockDiscoveryHandle::add_node`],
+/// outbound AddProvider/GetProviders requests are delivered to the handle
+/// instead of the wire, and events injected through the handle are processed
+/// by [`Discovery::run`] as if DiscV5 had emitted them.
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// An outbound DHT request intercepted by the mock
+    #[derive(Debug)]
+    pub(crate) enum MockRequest {
+        AddProvider {
+            node_id: enr::NodeId,
+            content_id: Vec<u8>,
+            provider_record: Vec<u8>,
+        },
+        GetProviders {
+            node_id: enr::NodeId,
+            content_id: Vec<u8>,
+            reply: oneshot::Sender<Vec<Vec<u8>>>,
+        },
+        Talk {
+            node_id: enr::NodeId,
+            protocol: Vec<u8>,
+            request: Vec<u8>,
+            reply: oneshot::Sender<Vec<u8>>,
+        },
+    }
+
+    /// Discovery-side half of the mock
+    pub(crate) struct MockNetwork {
+        nodes: Arc<Mutex<Vec<enr::Enr<enr::CombinedKey>>>>,
+        requests_tx: mpsc::UnboundedSender<MockRequest>,
+        events_rx: Mutex<Option<mpsc::Receiver<Discv5Event>>>,
+    }
+
+    impl DhtTransport for MockNetwork {
+        fn find_node(
+            &self,
+            _node_id: enr::NodeId,
+        ) -> BoxFuture<'static, Result<Vec<enr::Enr<enr::CombinedKey>>>> {
+            future::ready(Ok(self.nodes.lock().unwrap().clone())).boxed()
+        }
+
+        fn add_provider(
+            &self,
+            enr: enr::Enr<enr::CombinedKey>,
+            content_id: Vec<u8>,
+            provider_record: Vec<u8>,
+        ) -> RequestFuture<()> {
+            // Sent straight away, so the request is queued once `provide` returns
+            let _ = self.requests_tx.send(MockRequest::AddProvider {
+                node_id: enr.node_id(),
+                content_id,
+                provider_record,
+            });
+            future::ready(Ok(())).boxed()
+        }
+
+        fn get_providers(
+            &self,
+            enr: enr::Enr<enr::CombinedKey>,
+            content_id: Vec<u8>,
+        ) -> RequestFuture<(u32, Vec<Vec<u8>>)> {
+            let (reply, response) = oneshot::channel();
+            let sent = self.requests_tx.send(MockRequest::GetProviders {
+                node_id: enr.node_id(),
+                content_id,
+                reply,
+            });
+            async move {
+                sent.map_err(|_| discv5::RequestError::ChannelFailed("mock closed".into()))?;
+                let providers = response.await.map_err(|_| discv5::RequestError::Timeout)?;
+                Ok((providers.len() as u32, providers))
+            }
+            .boxed()
+        }
+
+        fn talk(
+            &self,
+            enr: enr::Enr<enr::CombinedKey>,
+            protocol: Vec<u8>,
+            request: Vec<u8>,
+        ) -> RequestFuture<Vec<u8>> {
+            let (reply, response) = oneshot::channel();
+            let sent = self.requests_tx.send(MockRequest::Talk {
+                node_id: enr.node_id(),
+                protocol,
+                request,
+                reply,
+            });
+            async move {
+                sent.map_err(|_| discv5::RequestError::ChannelFailed("mock closed".into()))?;
+                response.await.map_err(|_| discv5::RequestError::Timeout)
+            }
+            .boxed()
+        }
+
+        fn event_stream(&self) -> BoxFuture<'static, Result<mpsc::Receiver<Discv5Event>>> {
+            let events = self.events_rx.lock().unwrap().take().ok_or_else(|| {
+                DiscoveryError::Discv5Error("mock event stream already taken".to_string())
+            });
+            future::ready(events).boxed()
+        }
+    }
+
+    /// Test-side half of the mock
+    pub(crate) struct MockDiscoveryHandle {
+        nodes: Arc<Mutex<Vec<enr::Enr<enr::CombinedKey>>>>,
+        events_tx: mpsc::Sender<Discv5Event>,
+        requests_rx: mpsc::UnboundedReceiver<MockRequest>,
+    }
+
+    impl MockDiscoveryHandle {
+        /// Add a node that lookups will return as one of the closest
+        pub(crate) fn add_node(&self, enr: enr::Enr<enr::CombinedKey>) {
+            self.nodes.lock().unwrap().push(enr);
+        }
+
+        /// Feed an event to the discovery loop as if DiscV5 had emitted it
+        pub(crate) fn inject_event(&self, event: Discv5Event) {
+            let _ = self.events_tx.try_send(event);
+        }
+
+        /// Wait for the next intercepted outbound request
+        pub(crate) async fn next_request(&mut self) -> Option<MockRequest> {
+            self.requests_rx.recv().await
+        }
+
+        /// Take an intercepted request if one is already queued
+        pub(crate) fn try_next_request(
+            &mut self,
+        ) -> std::result::Result<MockRequest, mpsc::error::TryRecvError> {
+            self.requests_rx.try_recv()
+        }
+
+        /// Answer every intercepted request from `store`, acting as the
+        /// remote DHT nodes.
+        pub(crate) fn serve(mut self, store: SharedProviderStore) -> tokio::task::JoinHandle<()> {
+            tokio::spawn(async move {
+                while let Some(request) = self.next_request().await {
+                    match request {
+                        MockRequest::AddProvider {
+                            content_id,
+                            provider_record,
+                            ..
+                        } => handle_add_provider(&store, &content_id, provider_record).await,
+                        MockRequest::GetProviders {
+                            content_id, reply, ..
+                        } => {
+                            let (_, providers) = handle_get_providers(&store, &content_id).await;
+                            let _ = reply.send(providers);
+                        }
+                        MockRequest::Talk {
+                            protocol,
+                            request,
+                            reply,
+                            ..
+                        } => {
+                            if protocol == TALK_PROTOCOL_ADD_PROVIDER_BATCH {
+                                handle_add_provider_batch(&store, &request).await;
+                            }
+                            let _ = reply.send(Vec::new());
+                        }
+                    }
+                }
+            })
+        }
+    }
+
+    /// Build an ENR advertising `peer_id` in its `libp2p` field.
+    pub(crate) fn fake_enr(peer_id: &PeerId, udp_port: u16) -> enr::Enr<enr::CombinedKey> {
+        let key = enr::CombinedKey::generate_secp256k1();
+        let mut builder = enr::Enr::builder();
+        builder.ip4(Ipv4Addr::LOCALHOST);
+        builder.udp4(udp_port);
+        builder.add_value("libp2p", &peer_id.to_bytes());
+        builder.build(&key).unwrap()
+    }
+
+    impl Discovery {
+        /// Create a Discovery backed by an in-memory network.
+        ///
+        /// The DiscV5 service is constructed but never started, so no socket
+        /// is bound.
+        pub(crate) fn new_mock() -> (Discovery, MockDiscoveryHandle) {
+            let keypair = libp2p::identity::Keypair::generate_secp256k1();
+            let peer_id = keypair.public().to_peer_id();
+            let enr_key = enr::CombinedKey::generate_secp256k1();
+            let mut builder = enr::Enr::builder();
+            builder.ip4(Ipv4Addr::LOCALHOST);
+            builder.udp4(0);
+            builder.add_value("libp2p", &peer_id.to_bytes());
+            let enr = builder.build(&enr_key).unwrap();
+
+            let listen_config = ListenConfig::Ipv4 {
+                ip: Ipv4Addr::LOCALHOST,
+                port: 0,
+            };
+            let discv5 = Discv5::new(enr, enr_key, ConfigBuilder::new(listen_config).build())
+                .expect("mock DiscV5 service");
+
+            let nodes = Arc::new(Mutex::new(Vec::new()));
+            let (requests_tx, requests_rx) = mpsc::unbounded_channel();
+            let (events_tx, events_rx) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+            let config = DiscoveryConfig::default();
+
+            let discovery = Discovery {
+                transport: Box::new(MockNetwork {
+                    nodes: nodes.clone(),
+                    requests_tx,
+                    events_rx: Mutex::new(Some(events_rx)),
+                }),
+                discv5: Arc::new(discv5),
+                peer_id,
+                provider_store: Arc::new(RwLock::new(ProviderStore::new_with_ttl(
+                    config.provider_ttl,
+                ))),
+                local_provider_record: build_provider_record(
+                    &keypair,
+                    &["/ip4/127.0.0.1/tcp/8070".to_string()],
+                ),
+                lookup_limiter: OpLimiter::new(config.max_concurrent_lookups),
+                provide_limiter: OpLimiter::new(config.max_concurrent_provides),
+                config,
+                events_tx: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+                discovered_peers: RwLock::new(HashMap::new()),
+                query_stats: Mutex::new(QueryStats::default()),
+                dial_callback: None,
+            };
+            let handle = MockDiscoveryHandle {
+                nodes,
+                events_tx,
+                requests_rx,
+            };
+            (discovery, handle)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dht_provider::new_provider_store;
     use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
     use libp2p::identity::Keypair;
+    use std::collections::HashSet;
 
     #[tokio::test]
     async fn test_discovery_creation() {
@@ -560,9 +1581,15 @@ mod tests {
         let listen_addr = "127.0.0.1:9000".parse().unwrap();
         let announce_addrs = vec!["/ip4/127.0.0.1/tcp/8070".to_string()];
 
-        let discovery = Discovery::new(&keypair, listen_addr, announce_addrs, vec![])
-            .await
-            .unwrap();
+        let discovery = Discovery::new(
+            &keypair,
+            listen_addr,
+            announce_addrs,
+            vec![],
+            DiscoveryConfig::default(),
+        )
+        .await
+        .unwrap();
 
         assert_eq!(discovery.connected_peers(), 0);
         assert_eq!(discovery.local_peer_id(), &keypair.public().to_peer_id());
@@ -574,9 +1601,15 @@ mod tests {
         let listen_addr = "127.0.0.1:9001".parse().unwrap();
         let announce_addrs = vec!["/ip4/127.0.0.1/tcp/8070".to_string()];
 
-        let discovery = Discovery::new(&keypair, listen_addr, announce_addrs, vec![])
-            .await
-            .unwrap();
+        let discovery = Discovery::new(
+            &keypair,
+            listen_addr,
+            announce_addrs,
+            vec![],
+            DiscoveryConfig::default(),
+        )
+        .await
+        .unwrap();
 
         let cid: Cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
             .parse()
@@ -590,6 +1623,589 @@ mod tests {
         assert_eq!(providers.len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_unprovide_stops_republishing() {
+        let (discovery, _handle) = Discovery::new_mock();
+        discovery
+            .provider_store()
+            .write()
+            .await
+            .set_ttl(Duration::ZERO);
+        let cid = crate::cid_blake3::blake3_cid(b"deleted block").unwrap();
+
+        discovery.provide(&cid).await.unwrap();
+        discovery.unprovide(&cid).await;
+        assert!(discovery.find(&cid).await.is_err());
+        assert_eq!(discovery.evict_expired_providers().await, 0);
+        assert_eq!(discovery.provider_store().read().await.total_providers(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_evict_expired_providers_reprovides_local_cids() {
+        let (discovery, _handle) = Discovery::new_mock();
+        discovery
+            .provider_store()
+            .write()
+            .await
+            .set_ttl(Duration::ZERO);
+        let cid = crate::cid_blake3::blake3_cid(b"provided block").unwrap();
+        let remote_id = enr::NodeId::new(&[7u8; 32]);
+
+        discovery.provide(&cid).await.unwrap();
+        handle_add_provider(
+            discovery.provider_store(),
+            &remote_id.raw(),
+            b"remote".to_vec(),
+        )
+        .await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        assert_eq!(discovery.evict_expired_providers().await, 2);
+        // The remote record is gone, our own was provided again
+        let mut store = discovery.provider_store().write().await;
+        store.set_ttl(PROVIDER_TTL);
+        assert!(store.get(&remote_id).is_empty());
+        assert_eq!(store.get(&cid_to_node_id(&cid)).len(), 1);
+        assert_eq!(store.total_providers(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queued_lookups_run_in_fifo_order() {
+        let limiter = OpLimiter::new(2);
+        let order = Arc::new(tokio::sync::Mutex::new(Vec::new()));
+
+        // Queue positions are taken when `acquire` is called, not when awaited.
+        let mut tasks = Vec::new();
+        for i in 0..6 {
+            let slot = limiter.acquire();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = slot.await.unwrap();
+                order.lock().await.push((i, tokio::time::Instant::now()));
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }));
+        }
+
+        let start = tokio::time::Instant::now();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let order = order.lock().await;
+        let ids: Vec<usize> = order.iter().map(|(i, _)| *i).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4, 5]);
+
+        // Two slots: ops start in pairs, one second apart.
+        let started: Vec<u64> = order
+            .iter()
+            .map(|(_, at)| at.duration_since(start).as_secs())
+            .collect();
+        assert_eq!(started, vec![0, 0, 1, 1, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiter_releases_slot() {
+        let limiter = OpLimiter::new(1);
+
+        let held = limiter.acquire().await.unwrap();
+        let abandoned = limiter.acquire();
+        drop(abandoned);
+        drop(held);
+
+        // The abandoned waiter's permit must flow back to the pool.
+        let permit = tokio::time::timeout(Duration::from_secs(1), limiter.acquire())
+            .await
+            .expect("slot should be released");
+        assert!(permit.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_receives_discovered_peer() {
+        let keypair = Keypair::generate_secp256k1();
+        let discovery = Discovery::new(
+            &keypair,
+            "127.0.0.1:9002".parse().unwrap(),
+            vec![],
+            vec![],
+            DiscoveryConfig::default(),
+        )
+        .await
+        .unwrap();
+        let mut events = discovery.subscribe();
+
+        let remote_peer = Keypair::generate_secp256k1().public().to_peer_id();
+        let enr_key = enr::CombinedKey::generate_secp256k1();
+        let mut builder = enr::Enr::builder();
+        builder.ip4(Ipv4Addr::new(10, 0, 0, 5));
+        builder.tcp4(8070);
+        builder.udp4(8090);
+        builder.add_value("libp2p", &remote_peer.to_bytes());
+        let remote_enr = builder.build(&enr_key).unwrap();
+
+        discovery
+            .handle_event(Discv5Event::Discovered(remote_enr))
+            .await;
+
+        let event = events.try_recv().expect("event should be queued");
+        let expected_addrs: Vec<Multiaddr> = vec![
+            "/ip4/10.0.0.5/tcp/8070".parse().unwrap(),
+            "/ip4/10.0.0.5/udp/8090".parse().unwrap(),
+        ];
+        assert_eq!(
+            event,
+            DiscoveryEvent::PeerDiscovered(remote_peer, expected_addrs)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dial_callback_fires_for_tcp_enr() {
+        let (mut discovery, _net) = Discovery::new_mock();
+        let dialed = Arc::new(Mutex::new(Vec::new()));
+        let sink = dialed.clone();
+        discovery.set_dial_callback(Arc::new(move |peer_id, addr| {
+            sink.lock().unwrap().push((peer_id, addr));
+        }));
+
+        let remote_peer = Keypair::generate_secp256k1().public().to_peer_id();
+        let mut builder = enr::Enr::builder();
+        builder.ip4(Ipv4Addr::new(10, 0, 0, 7));
+        builder.tcp4(8070);
+        builder.udp4(8090);
+        builder.add_value("libp2p", &remote_peer.to_bytes());
+        let remote_enr = builder
+            .build(&enr::CombinedKey::generate_secp256k1())
+            .unwrap();
+        discovery
+            .handle_event(Discv5Event::Discovered(remote_enr))
+            .await;
+
+        // A node without a TCP port can't be dialed over libp2p
+        let mut builder = enr::Enr::builder();
+        builder.ip4(Ipv4Addr::new(10, 0, 0, 8));
+        builder.udp4(8090);
+        builder.add_value("libp2p", &PeerId::random().to_bytes());
+        let udp_only = builder
+            .build(&enr::CombinedKey::generate_secp256k1())
+            .unwrap();
+        discovery
+            .handle_event(Discv5Event::Discovered(udp_only))
+            .await;
+
+        assert_eq!(
+            *dialed.lock().unwrap(),
+            vec![(remote_peer, "/ip4/10.0.0.7/tcp/8070".parse().unwrap())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_peer_returns_enr_addresses() {
+        let keypair = Keypair::generate_secp256k1();
+        let discovery = Discovery::new(
+            &keypair,
+            "127.0.0.1:9003".parse().unwrap(),
+            vec![],
+            vec![],
+            DiscoveryConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let remote_peer = Keypair::generate_secp256k1().public().to_peer_id();
+        let enr_key = enr::CombinedKey::generate_secp256k1();
+        let mut builder = enr::Enr::builder();
+        builder.ip4(Ipv4Addr::LOCALHOST);
+        builder.udp4(9004);
+        builder.add_value("libp2p", &remote_peer.to_bytes());
+        discovery
+            .discv5
+            .add_enr(builder.build(&enr_key).unwrap())
+            .unwrap();
+
+        let addrs = discovery.find_peer(&remote_peer).await.unwrap();
+        assert_eq!(
+            addrs,
+            vec!["/ip4/127.0.0.1/udp/9004".parse::<Multiaddr>().unwrap()]
+        );
+
+        let unknown_peer = Keypair::generate_secp256k1().public().to_peer_id();
+        assert!(matches!(
+            discovery.find_peer(&unknown_peer).await,
+            Err(DiscoveryError::NoProviders(_))
+        ));
+    }
+
+    #[test]
+    fn test_extract_listen_addrs_round_trip() {
+        let addrs: Vec<Multiaddr> = vec![
+            "/ip4/192.0.2.1/tcp/8070".parse().unwrap(),
+            "/ip6/2001:db8::1/tcp/8070".parse().unwrap(),
+            "/dns4/node.example.com/tcp/443".parse().unwrap(),
+        ];
+        let enr_key = enr::CombinedKey::generate_secp256k1();
+        let mut builder = enr::Enr::builder();
+        builder.ip4(Ipv4Addr::LOCALHOST);
+        builder.udp4(9004);
+        builder.add_value(ARCHIVIST_ADDRS_ENR_KEY, &encode_listen_addrs(&addrs));
+        let enr = builder.build(&enr_key).unwrap();
+
+        // Survives the base64 text form peers exchange
+        let enr: enr::Enr<enr::CombinedKey> = enr.to_base64().parse().unwrap();
+        assert_eq!(Discovery::extract_listen_addrs(&enr), addrs);
+
+        let mut builder = enr::Enr::builder();
+        builder.add_value(ARCHIVIST_ADDRS_ENR_KEY, &b"not cbor".to_vec());
+        let garbage = builder.build(&enr_key).unwrap();
+        assert!(Discovery::extract_listen_addrs(&garbage).is_empty());
+        let missing = enr::Enr::builder().build(&enr_key).unwrap();
+        assert!(Discovery::extract_listen_addrs(&missing).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_set_listen_addrs_updates_local_enr() {
+        let discovery = Discovery::new(
+            &Keypair::generate_secp256k1(),
+            "127.0.0.1:9005".parse().unwrap(),
+            vec![],
+            vec![],
+            DiscoveryConfig::default(),
+        )
+        .await
+        .unwrap();
+        let node_id = discovery.local_enr().node_id();
+        let seq = discovery.local_enr().seq();
+
+        let addrs: Vec<Multiaddr> = vec!["/ip4/192.0.2.1/tcp/8070".parse().unwrap()];
+        discovery.set_listen_addrs(addrs.clone()).unwrap();
+        let enr = discovery.local_enr();
+        assert_eq!(Discovery::extract_listen_addrs(&enr), addrs);
+        assert_eq!(enr.seq(), seq + 1);
+        // Re-signing keeps the Archivist NodeId
+        assert_eq!(enr.node_id(), node_id);
+
+        // Unchanged addresses leave the record alone
+        discovery.set_listen_addrs(addrs).unwrap();
+        assert_eq!(discovery.local_enr().seq(), seq + 1);
+
+        // More addresses than fit are truncated instead of failing
+        let many: Vec<Multiaddr> = (0..64)
+            .map(|i| format!("/ip4/192.0.2.{}/tcp/8070", i).parse().unwrap())
+            .collect();
+        discovery.set_listen_addrs(many.clone()).unwrap();
+        let published = Discovery::extract_listen_addrs(&discovery.local_enr());
+        assert!(!published.is_empty());
+        assert_eq!(published, many[..published.len()]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_provide_find_round_trip() {
+        let remote_store = new_provider_store();
+        let remote_node = mock::fake_enr(&PeerId::random(), 9100);
+
+        let (provider, provider_net) = Discovery::new_mock();
+        provider_net.add_node(remote_node.clone());
+        provider_net.serve(remote_store.clone());
+
+        let (seeker, seeker_net) = Discovery::new_mock();
+        seeker_net.add_node(remote_node);
+        seeker_net.serve(remote_store.clone());
+
+        let cid: Cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
+            .parse()
+            .unwrap();
+        provider.provide(&cid).await.unwrap();
+
+        // Wait for the spawned AddProvider send to reach the remote store
+        let content_id = cid_to_node_id(&cid).raw().to_vec();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while handle_get_providers(&remote_store, &content_id).await.0 == 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("AddProvider should reach the remote store");
+
+        let providers = seeker.find(&cid).await.unwrap();
+        assert_eq!(providers.len(), 1);
+        let spr_text = format!("spr:{}", URL_SAFE_NO_PAD.encode(&providers[0]));
+        let parsed = parse_spr_records(&spr_text).unwrap();
+        assert_eq!(&parsed[0].peer_id, provider.local_peer_id());
+    }
+
+    #[tokio::test]
+    async fn test_mock_intercepts_outbound_requests() {
+        let (discovery, mut net) = Discovery::new_mock();
+        let remote_node = mock::fake_enr(&PeerId::random(), 9101);
+        net.add_node(remote_node.clone());
+
+        let cid: Cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
+            .parse()
+            .unwrap();
+        discovery.provide(&cid).await.unwrap();
+
+        match net.next_request().await {
+            Some(mock::MockRequest::AddProvider {
+                node_id,
+                content_id,
+                provider_record,
+            }) => {
+                assert_eq!(node_id, remote_node.node_id());
+                assert_eq!(content_id, cid_to_node_id(&cid).raw().to_vec());
+                assert_eq!(provider_record, discovery.local_provider_record);
+            }
+            other => panic!("expected AddProvider, got {:?}", other),
+        }
+
+        // The local store answers first, so query a fresh node for the CID
+        let (seeker, mut seeker_net) = Discovery::new_mock();
+        seeker_net.add_node(remote_node.clone());
+        let find = tokio::spawn(async move { seeker.find(&cid).await });
+
+        match seeker_net.next_request().await {
+            Some(mock::MockRequest::GetProviders { node_id, reply, .. }) => {
+                assert_eq!(node_id, remote_node.node_id());
+                reply.send(vec![b"record".to_vec()]).unwrap();
+            }
+            other => panic!("expected GetProviders, got {:?}", other),
+        }
+        assert_eq!(find.await.unwrap().unwrap(), vec![b"record".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_find_from_asks_preferred_peers_first() {
+        let (discovery, mut net) = Discovery::new_mock();
+        let slow = PeerId::random();
+        let fast = PeerId::random();
+        let slow_node = mock::fake_enr(&slow, 9102);
+        let fast_node = mock::fake_enr(&fast, 9103);
+        net.add_node(slow_node.clone());
+        net.add_node(fast_node.clone());
+
+        let cid = crate::cid_blake3::blake3_cid(b"preferred peers").unwrap();
+        let find = tokio::spawn(async move { discovery.find_from(&cid, &[fast]).await });
+
+        match net.next_request().await {
+            Some(mock::MockRequest::GetProviders { node_id, reply, .. }) => {
+                assert_eq!(node_id, fast_node.node_id());
+                reply.send(vec![b"record".to_vec()]).unwrap();
+            }
+            other => panic!("expected GetProviders, got {:?}", other),
+        }
+
+        // The first node to return providers ends the lookup
+        let outcome = find.await.unwrap();
+        assert_eq!(outcome.providers.unwrap().len(), 1);
+        let answered: Vec<PeerId> = outcome.peer_rtts.iter().map(|(peer, _)| *peer).collect();
+        assert_eq!(answered, vec![fast]);
+        assert!(net.try_next_request().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_from_penalises_failed_peers() {
+        let (discovery, mut net) = Discovery::new_mock();
+        let lookup_timeout = discovery.config.lookup_timeout;
+        let failing = PeerId::random();
+        let answering = PeerId::random();
+        let failing_node = mock::fake_enr(&failing, 9104);
+        net.add_node(failing_node.clone());
+        net.add_node(mock::fake_enr(&answering, 9105));
+
+        let cid = crate::cid_blake3::blake3_cid(b"failed peers").unwrap();
+        let find = tokio::spawn(async move { discovery.find_from(&cid, &[failing]).await });
+
+        // Dropping the reply fails the first request
+        match net.next_request().await {
+            Some(mock::MockRequest::GetProviders { node_id, .. }) => {
+                assert_eq!(node_id, failing_node.node_id());
+            }
+            other => panic!("expected GetProviders, got {:?}", other),
+        }
+        match net.next_request().await {
+            Some(mock::MockRequest::GetProviders { reply, .. }) => {
+                reply.send(vec![b"record".to_vec()]).unwrap();
+            }
+            other => panic!("expected GetProviders, got {:?}", other),
+        }
+
+        let outcome = find.await.unwrap();
+        assert_eq!(outcome.providers.unwrap().len(), 1);
+        assert_eq!(outcome.peer_rtts[0], (failing, lookup_timeout));
+        assert_eq!(outcome.peer_rtts[1].0, answering);
+        assert!(outcome.peer_rtts[1].1 < lookup_timeout);
+    }
+
+    #[tokio::test]
+    async fn test_provide_batch_round_trip() {
+        use crate::cid_blake3::blake3_cid;
+
+        let remote_store = new_provider_store();
+        let remote_node = mock::fake_enr(&PeerId::random(), 9103);
+
+        let (provider, mut provider_net) = Discovery::new_mock();
+        provider_net.add_node(remote_node.clone());
+        let cids = vec![
+            blake3_cid(b"batch block 1").unwrap(),
+            blake3_cid(b"batch block 2").unwrap(),
+        ];
+        let send = tokio::spawn(async move { provider.provide_batch(&cids).await });
+
+        // Both CIDs share the only known node, so one TALK request carries them
+        match provider_net.next_request().await {
+            Some(mock::MockRequest::Talk {
+                node_id,
+                protocol,
+                request,
+                reply,
+            }) => {
+                assert_eq!(node_id, remote_node.node_id());
+                assert_eq!(protocol, TALK_PROTOCOL_ADD_PROVIDER_BATCH);
+                assert_eq!(
+                    handle_add_provider_batch(&remote_store, &request).await,
+                    Some(2)
+                );
+                reply.send(Vec::new()).unwrap();
+            }
+            other => panic!("expected a batched TALK request, got {:?}", other),
+        }
+        send.await.unwrap().unwrap();
+
+        let (seeker, seeker_net) = Discovery::new_mock();
+        seeker_net.add_node(remote_node);
+        seeker_net.serve(remote_store);
+        for data in [&b"batch block 1"[..], b"batch block 2"] {
+            let providers = seeker.find(&blake3_cid(data).unwrap()).await.unwrap();
+            assert_eq!(providers.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provide_batch_fails_when_no_node_accepts() {
+        use crate::cid_blake3::blake3_cid;
+
+        let (provider, mut provider_net) = Discovery::new_mock();
+        provider_net.add_node(mock::fake_enr(&PeerId::random(), 9104));
+        let cids = vec![
+            blake3_cid(b"batch block 1").unwrap(),
+            blake3_cid(b"batch block 2").unwrap(),
+        ];
+        let send = tokio::spawn(async move { provider.provide_batch(&cids).await });
+
+        // Dropping the reply fails the only TALK request
+        match provider_net.next_request().await {
+            Some(mock::MockRequest::Talk { .. }) => {}
+            other => panic!("expected a batched TALK request, got {:?}", other),
+        }
+        assert!(matches!(
+            send.await.unwrap(),
+            Err(DiscoveryError::Discv5Error(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_provide_batch_walks_once_per_region() {
+        use crate::cid_blake3::blake3_cid;
+
+        let (provider, mut provider_net) = Discovery::new_mock();
+        for port in 9200..9208 {
+            let node = mock::fake_enr(&PeerId::random(), port);
+            provider.discv5.add_enr(node.clone()).unwrap();
+            provider_net.add_node(node);
+        }
+        let provider = Arc::new(provider);
+        let cids: Vec<Cid> = (0..100)
+            .map(|i| blake3_cid(format!("batch block {}", i).as_bytes()).unwrap())
+            .collect();
+
+        let responder = tokio::spawn(async move {
+            let mut talks = 0;
+            while let Some(request) = provider_net.next_request().await {
+                if let mock::MockRequest::Talk { reply, .. } = request {
+                    talks += 1;
+                    let _ = reply.send(Vec::new());
+                }
+            }
+            talks
+        });
+
+        let table = provider.discv5.table_entries_id();
+        let regions: HashSet<Vec<enr::NodeId>> = cids
+            .iter()
+            .map(|cid| routing_region(&table, &cid_to_node_id(cid)))
+            .collect();
+
+        provider.provide_batch(&cids).await.unwrap();
+
+        // One walk per region
+        let lookups = provider.stats().total_queries;
+        assert_eq!(lookups, regions.len() as u64);
+        drop(provider);
+        // Each walk reaches at most the whole table, and a node gets a single
+        // batch however many regions it is close to
+        let talks = responder.await.unwrap();
+        assert!(talks < cids.len(), "{} TALK requests for 100 CIDs", talks);
+        assert!(talks <= regions.len() * table.len());
+        assert!(talks <= table.len());
+    }
+
+    #[tokio::test]
+    async fn test_mock_injected_event_reaches_subscribers() {
+        let (discovery, net) = Discovery::new_mock();
+        let discovery = Arc::new(discovery);
+        let mut events = discovery.subscribe();
+        tokio::spawn(discovery.clone().run());
+
+        let remote_peer = PeerId::random();
+        net.inject_event(Discv5Event::Discovered(mock::fake_enr(&remote_peer, 9102)));
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            DiscoveryEvent::PeerDiscovered(
+                remote_peer,
+                vec!["/ip4/127.0.0.1/udp/9102".parse().unwrap()]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_track_query_rtt() {
+        let remote_store = new_provider_store();
+        let (discovery, net) = Discovery::new_mock();
+        net.add_node(mock::fake_enr(&PeerId::random(), 9104));
+        net.serve(remote_store.clone());
+
+        // Simulated round trips of 40 ms and a 100 ms failure
+        discovery.record_query(Duration::from_millis(40), true);
+        discovery.record_query(Duration::from_millis(100), false);
+        let stats = discovery.stats();
+        assert_eq!(stats.total_queries, 2);
+        assert_eq!(stats.failed_queries, 1);
+        assert_eq!(stats.avg_query_rtt_ms, 70);
+
+        // A remote find runs a lookup plus one GetProviders query
+        let cid = crate::cid_blake3::blake3_cid(b"stats block").unwrap();
+        let content_id = cid_to_node_id(&cid).raw().to_vec();
+        handle_add_provider(&remote_store, &content_id, b"record".to_vec()).await;
+        assert_eq!(discovery.find(&cid).await.unwrap().len(), 1);
+        let stats = discovery.stats();
+        assert_eq!(stats.total_queries, 4);
+        assert_eq!(stats.providers_found, 1);
+        assert_eq!(stats.cache_hits, 0);
+
+        // Once provided locally, lookups are answered from the local store
+        discovery.provide(&cid).await.unwrap();
+        discovery.find(&cid).await.unwrap();
+        assert_eq!(discovery.stats().cache_hits, 1);
+
+        // Only the most recent samples are averaged
+        for _ in 0..RTT_SAMPLE_WINDOW {
+            discovery.record_query(Duration::from_millis(5), true);
+        }
+        assert_eq!(discovery.stats().avg_query_rtt_ms, 5);
+    }
+
     #[test]
     fn test_build_provider_record_matches_archivist_spr_format() {
         let keypair = Keypair::generate_secp256k1();
@@ -598,7 +2214,7 @@ mod tests {
 
         let spr_bytes = build_provider_record(&keypair, &announce_addrs);
         let spr_text = format!("spr:{}", URL_SAFE_NO_PAD.encode(spr_bytes));
-        let parsed = parse_spr_records_full(&spr_text).expect("should parse generated SPR");
+        let parsed = parse_spr_records(&spr_text).expect("should parse generated SPR");
 
         assert_eq!(parsed.len(), 1);
         assert_eq!(parsed[0].peer_id, peer_id);
@@ -9,13 +9,17 @@
 //! Based on Archivist's blockexchange/engine/discovery.nim pattern
 
 use cid::Cid;
-use libp2p::PeerId;
+use futures::future::BoxFuture;
+use libp2p::{Multiaddr, PeerId};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::time::Instant;
 use tracing::{debug, info, trace, warn};
 
-use crate::discovery::Discovery;
+use crate::discovery::{Discovery, DiscoveryError, DiscoveryEvent, FindOutcome};
+use crate::spr::parse_spr_bytes;
 
 /// Default maximum number of concurrent DHT queries
 const DEFAULT_MAX_CONCURRENT: usize = 10;
@@ -23,6 +27,73 @@ const DEFAULT_MAX_CONCURRENT: usize = 10;
 /// Default minimum number of peers required per block
 const DEFAULT_MIN_PEERS: usize = 3;
 
+/// Default number of times a failed or insufficient query is retried
+const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// How long queries are held back while no peers are connected
+const NO_PEERS_QUERY_DELAY: Duration = Duration::from_secs(5);
+
+/// Query round-trip times kept per DHT peer
+const LATENCY_SAMPLES_PER_PEER: usize = 20;
+
+/// Reports the number of currently connected peers
+pub type PeerCountSource = Arc<dyn Fn() -> usize + Send + Sync>;
+
+/// Provider lookups used by the discovery engine
+///
+/// Implemented by [`Discovery`] for the DiscV5 DHT; tests substitute an
+/// in-memory backend.
+pub trait DiscoveryBackend: Send + Sync {
+    /// Find provider records (signed peer records) for `cid`
+    fn find<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> BoxFuture<'a, std::result::Result<Vec<Vec<u8>>, DiscoveryError>>;
+
+    /// Find provider records for `cid`, asking the `preferred` DHT peers
+    /// first and reporting how long each peer that answered took
+    ///
+    /// Backends that cannot attribute round trips to peers fall back to
+    /// [`find`](Self::find) and report none.
+    fn find_from<'a>(
+        &'a self,
+        cid: &'a Cid,
+        preferred: &'a [PeerId],
+    ) -> BoxFuture<'a, FindOutcome> {
+        let _ = preferred;
+        Box::pin(async move {
+            FindOutcome {
+                providers: self.find(cid).await,
+                peer_rtts: Vec::new(),
+            }
+        })
+    }
+
+    /// Subscribe to peer discovered/lost events
+    fn subscribe(&self) -> broadcast::Receiver<DiscoveryEvent>;
+}
+
+impl DiscoveryBackend for Discovery {
+    fn find<'a>(
+        &'a self,
+        cid: &'a Cid,
+    ) -> BoxFuture<'a, std::result::Result<Vec<Vec<u8>>, DiscoveryError>> {
+        Box::pin(Discovery::find(self, cid))
+    }
+
+    fn find_from<'a>(
+        &'a self,
+        cid: &'a Cid,
+        preferred: &'a [PeerId],
+    ) -> BoxFuture<'a, FindOutcome> {
+        Box::pin(Discovery::find_from(self, cid, preferred))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<DiscoveryEvent> {
+        Discovery::subscribe(self)
+    }
+}
+
 /// Error type for discovery engine operations
 #[derive(Debug, thiserror::Error)]
 pub enum DiscoveryEngineError {
@@ -69,6 +140,8 @@ struct CidDiscoveryState {
     providers: HashSet<PeerId>,
     /// Whether this CID is currently being queried
     in_flight: bool,
+    /// Queries started for this CID so far
+    attempts: usize,
     /// Callback to notify when complete
     callback: Option<Arc<tokio::sync::Mutex<Option<mpsc::UnboundedSender<DiscoveryResult>>>>>,
 }
@@ -85,6 +158,25 @@ struct EngineState {
     max_concurrent: usize,
     /// Minimum peers required per CID
     min_peers: usize,
+    /// Maximum retries after a CID's first query
+    max_retries: usize,
+    /// Peers currently known to the DHT, with their advertised addresses
+    known_peers: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Providers of CIDs whose discovery completed, reused for later requests
+    found: HashMap<Cid, HashSet<PeerId>>,
+    /// Queries sent to the discovery backend
+    total_queries: u64,
+    /// Requests answered from `found` without a query
+    total_cache_hits: u64,
+    /// Queries that have finished, successfully or not
+    completed_queries: u64,
+    /// Finished queries for which the backend returned providers
+    successful_queries: u64,
+    /// Last [`LATENCY_SAMPLES_PER_PEER`] query round-trip times per DHT
+    /// peer, in ms
+    peer_latencies: HashMap<PeerId, VecDeque<u64>>,
+    /// When held-back queries may start while no peers are connected
+    no_peers_until: Option<Instant>,
 }
 
 /// Discovery engine for finding block providers
@@ -93,25 +185,30 @@ struct EngineState {
 /// with concurrency limits and peer dialing.
 pub struct DiscoveryEngine {
     /// Discovery service for DHT queries
-    discovery: Arc<Discovery>,
+    discovery: Arc<dyn DiscoveryBackend>,
     /// Internal state
     state: Arc<RwLock<EngineState>>,
     /// Channel for receiving discovery requests
     request_rx: mpsc::UnboundedReceiver<DiscoveryRequest>,
+    /// Peer events from the discovery service
+    events_rx: broadcast::Receiver<DiscoveryEvent>,
     /// Shutdown signal
     shutdown: Arc<RwLock<bool>>,
+    /// Connected peer count, used to throttle queries while it is zero
+    peer_count_source: Option<PeerCountSource>,
 }
 
 impl DiscoveryEngine {
     /// Create a new discovery engine
     pub fn new(
-        discovery: Arc<Discovery>,
+        discovery: Arc<dyn DiscoveryBackend>,
     ) -> (
         Self,
         mpsc::UnboundedSender<DiscoveryRequest>,
         DiscoveryEngineHandle,
     ) {
         let (request_tx, request_rx) = mpsc::unbounded_channel();
+        let events_rx = discovery.subscribe();
         let shutdown = Arc::new(RwLock::new(false));
 
         let state = Arc::new(RwLock::new(EngineState {
@@ -120,11 +217,21 @@ impl DiscoveryEngine {
             in_flight_count: 0,
             max_concurrent: DEFAULT_MAX_CONCURRENT,
             min_peers: DEFAULT_MIN_PEERS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            known_peers: HashMap::new(),
+            found: HashMap::new(),
+            total_queries: 0,
+            total_cache_hits: 0,
+            completed_queries: 0,
+            successful_queries: 0,
+            peer_latencies: HashMap::new(),
+            no_peers_until: None,
         }));
 
         let handle = DiscoveryEngineHandle {
             request_tx: request_tx.clone(),
             shutdown: shutdown.clone(),
+            state: state.clone(),
         };
 
         (
@@ -132,7 +239,9 @@ impl DiscoveryEngine {
                 discovery,
                 state,
                 request_rx,
+                events_rx,
                 shutdown,
+                peer_count_source: None,
             },
             request_tx,
             handle,
@@ -141,7 +250,7 @@ impl DiscoveryEngine {
 
     /// Create a new discovery engine with custom configuration
     pub fn with_config(
-        discovery: Arc<Discovery>,
+        discovery: Arc<dyn DiscoveryBackend>,
         max_concurrent: usize,
         min_peers: usize,
     ) -> (
@@ -160,6 +269,23 @@ impl DiscoveryEngine {
         (engine, request_tx, handle)
     }
 
+    /// Set how many times a CID's query is retried after failing or finding
+    /// too few providers
+    pub fn with_max_retries(self, max_retries: usize) -> Self {
+        if let Ok(mut state) = self.state.try_write() {
+            state.max_retries = max_retries;
+        }
+        self
+    }
+
+    /// Set where the engine reads the connected peer count from
+    ///
+    /// While the source reports zero peers, queries are delayed by
+    /// five seconds instead of hitting the DHT on every poll.
+    pub fn set_peer_count_source(&mut self, source: PeerCountSource) {
+        self.peer_count_source = Some(source);
+    }
+
     /// Run the discovery engine event loop
     pub async fn run(mut self) {
         info!(
@@ -181,6 +307,15 @@ impl DiscoveryEngine {
                     self.handle_request(request).await;
                 }
 
+                // Track peers found or lost by the DHT
+                event = self.events_rx.recv() => match event {
+                    Ok(event) => self.handle_discovery_event(event).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Discovery engine lagged behind peer events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {}
+                },
+
                 // Process pending discoveries
                 _ = tokio::time::sleep(tokio::time::Duration::from_millis(100)) => {
                     self.process_pending().await;
@@ -196,6 +331,23 @@ impl DiscoveryEngine {
         debug!(count = request.cids.len(), "Queuing CIDs for discovery");
 
         for cid in request.cids {
+            // Answer from completed discoveries without querying again
+            if let Some(providers) = state.found.get(&cid) {
+                let result = DiscoveryResult {
+                    cid,
+                    providers: providers.iter().copied().collect(),
+                    sufficient: true,
+                };
+                state.total_cache_hits += 1;
+                trace!(cid = %cid, "Providers already discovered");
+                if let Some(ref callback_mutex) = request.callback {
+                    if let Some(callback) = callback_mutex.lock().await.as_ref() {
+                        let _ = callback.send(result);
+                    }
+                }
+                continue;
+            }
+
             // Skip if already in-flight or pending
             if state.in_flight.contains_key(&cid) || state.pending.iter().any(|s| s.cid == cid) {
                 trace!(cid = %cid, "CID already queued for discovery");
@@ -206,6 +358,7 @@ impl DiscoveryEngine {
                 cid,
                 providers: HashSet::new(),
                 in_flight: false,
+                attempts: 0,
                 callback: request.callback.clone(),
             });
         }
@@ -217,10 +370,41 @@ impl DiscoveryEngine {
         );
     }
 
+    /// Apply a peer event from the discovery service to the routing state
+    async fn handle_discovery_event(&self, event: DiscoveryEvent) {
+        let mut state = self.state.write().await;
+
+        match event {
+            DiscoveryEvent::PeerDiscovered(peer_id, addrs) => {
+                trace!(peer = %peer_id, addrs = addrs.len(), "Peer discovered");
+                state.known_peers.insert(peer_id, addrs);
+            }
+            DiscoveryEvent::PeerLost(peer_id) => {
+                trace!(peer = %peer_id, "Peer lost");
+                state.known_peers.remove(&peer_id);
+                state.peer_latencies.remove(&peer_id);
+
+                // A lost peer no longer counts towards a CID's providers
+                for discovery_state in state.pending.iter_mut() {
+                    discovery_state.providers.remove(&peer_id);
+                }
+                let min_peers = state.min_peers;
+                state.found.retain(|_, providers| {
+                    providers.remove(&peer_id);
+                    providers.len() >= min_peers
+                });
+            }
+        }
+    }
+
     /// Process pending discoveries
     async fn process_pending(&self) {
         let mut state = self.state.write().await;
 
+        if state.pending.is_empty() || self.throttled(&mut state) {
+            return;
+        }
+
         // Launch new queries if we have capacity
         while state.in_flight_count < state.max_concurrent {
             if let Some(mut discovery_state) = state.pending.pop_front() {
@@ -234,80 +418,19 @@ impl DiscoveryEngine {
                 );
 
                 discovery_state.in_flight = true;
+                discovery_state.attempts += 1;
                 state.in_flight.insert(cid, discovery_state);
                 state.in_flight_count += 1;
+                state.total_queries += 1;
 
-                // Spawn discovery task
+                // Spawn discovery task, asking the fastest peers first
                 let discovery = self.discovery.clone();
                 let engine_state = self.state.clone();
-                let min_peers = state.min_peers;
+                let preferred = state.peers_by_latency();
 
                 tokio::spawn(async move {
-                    match discovery.find(&cid).await {
-                        Ok(providers) => {
-                            info!(
-                                cid = %cid,
-                                count = providers.len(),
-                                "Found providers for CID"
-                            );
-
-                            // Update state with providers
-                            let mut state = engine_state.write().await;
-                            if let Some(mut discovery_state) = state.in_flight.remove(&cid) {
-                                state.in_flight_count = state.in_flight_count.saturating_sub(1);
-
-                                discovery_state.providers.extend(providers.iter());
-                                let sufficient = discovery_state.providers.len() >= min_peers;
-
-                                // Notify callback if present
-                                if let Some(ref callback_mutex) = discovery_state.callback {
-                                    if let Some(callback) = callback_mutex.lock().await.as_ref() {
-                                        let result = DiscoveryResult {
-                                            cid,
-                                            providers: discovery_state
-                                                .providers
-                                                .iter()
-                                                .copied()
-                                                .collect(),
-                                            sufficient,
-                                        };
-                                        let _ = callback.send(result);
-                                    }
-                                }
-
-                                if !sufficient {
-                                    // Re-queue for another attempt
-                                    debug!(
-                                        cid = %cid,
-                                        found = discovery_state.providers.len(),
-                                        needed = min_peers,
-                                        "Insufficient providers, re-queuing"
-                                    );
-                                    discovery_state.in_flight = false;
-                                    state.pending.push_back(discovery_state);
-                                } else {
-                                    info!(
-                                        cid = %cid,
-                                        count = discovery_state.providers.len(),
-                                        "Discovery complete for CID"
-                                    );
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!(cid = %cid, error = %e, "Discovery failed for CID");
-
-                            // Remove from in-flight
-                            let mut state = engine_state.write().await;
-                            if let Some(mut discovery_state) = state.in_flight.remove(&cid) {
-                                state.in_flight_count = state.in_flight_count.saturating_sub(1);
-
-                                // Re-queue for retry
-                                discovery_state.in_flight = false;
-                                state.pending.push_back(discovery_state);
-                            }
-                        }
-                    }
+                    let outcome = discovery.find_from(&cid, &preferred).await;
+                    Self::finish_query(&engine_state, cid, outcome).await;
                 });
             } else {
                 // No more pending items
@@ -316,23 +439,180 @@ impl DiscoveryEngine {
         }
     }
 
+    /// Whether queries are held back because no peers are connected
+    ///
+    /// With zero peers, queries start at most once per
+    /// [`NO_PEERS_QUERY_DELAY`]; as soon as a peer connects they start on
+    /// the next poll.
+    fn throttled(&self, state: &mut EngineState) -> bool {
+        let Some(ref peer_count) = self.peer_count_source else {
+            return false;
+        };
+        if peer_count() > 0 {
+            state.no_peers_until = None;
+            return false;
+        }
+
+        let now = Instant::now();
+        match state.no_peers_until {
+            Some(until) if now >= until => {
+                state.no_peers_until = Some(now + NO_PEERS_QUERY_DELAY);
+                false
+            }
+            Some(_) => true,
+            None => {
+                debug!("No peers connected, delaying discovery query");
+                state.no_peers_until = Some(now + NO_PEERS_QUERY_DELAY);
+                true
+            }
+        }
+    }
+
+    /// Record the outcome of a CID's query, re-queuing it if it failed or
+    /// found too few providers and has retries left
+    async fn finish_query(engine_state: &RwLock<EngineState>, cid: Cid, outcome: FindOutcome) {
+        let mut state = engine_state.write().await;
+        let Some(mut discovery_state) = state.in_flight.remove(&cid) else {
+            return;
+        };
+        state.in_flight_count = state.in_flight_count.saturating_sub(1);
+        let min_peers = state.min_peers;
+        let can_retry = discovery_state.attempts <= state.max_retries;
+
+        state.completed_queries += 1;
+        if outcome.providers.is_ok() {
+            state.successful_queries += 1;
+        }
+        for (peer_id, rtt) in outcome.peer_rtts {
+            state.record_latency(peer_id, rtt);
+        }
+
+        match outcome.providers {
+            Ok(providers) => {
+                info!(
+                    cid = %cid,
+                    count = providers.len(),
+                    "Found providers for CID"
+                );
+
+                discovery_state.providers.extend(
+                    providers
+                        .iter()
+                        .filter_map(|record| parse_spr_bytes(record).ok())
+                        .map(|record| record.peer_id),
+                );
+                let sufficient = discovery_state.providers.len() >= min_peers;
+
+                // Notify callback if present
+                if let Some(ref callback_mutex) = discovery_state.callback {
+                    if let Some(callback) = callback_mutex.lock().await.as_ref() {
+                        let result = DiscoveryResult {
+                            cid,
+                            providers: discovery_state.providers.iter().copied().collect(),
+                            sufficient,
+                        };
+                        let _ = callback.send(result);
+                    }
+                }
+
+                if sufficient {
+                    info!(
+                        cid = %cid,
+                        count = discovery_state.providers.len(),
+                        "Discovery complete for CID"
+                    );
+                    state.found.insert(cid, discovery_state.providers);
+                    return;
+                }
+                debug!(
+                    cid = %cid,
+                    found = discovery_state.providers.len(),
+                    needed = min_peers,
+                    "Insufficient providers"
+                );
+            }
+            Err(e) => {
+                warn!(cid = %cid, error = %e, "Discovery failed for CID");
+            }
+        }
+
+        if can_retry {
+            // Re-queue for another attempt
+            discovery_state.in_flight = false;
+            state.pending.push_back(discovery_state);
+        } else {
+            warn!(
+                cid = %cid,
+                attempts = discovery_state.attempts,
+                "Giving up on discovery for CID"
+            );
+        }
+    }
+
     /// Get current queue statistics
     pub async fn stats(&self) -> DiscoveryEngineStats {
-        let state = self.state.read().await;
+        self.state.read().await.stats()
+    }
+}
+
+impl EngineState {
+    /// Add a query round trip to `peer_id`'s latency samples
+    fn record_latency(&mut self, peer_id: PeerId, rtt: Duration) {
+        let samples = self.peer_latencies.entry(peer_id).or_default();
+        if samples.len() == LATENCY_SAMPLES_PER_PEER {
+            samples.pop_front();
+        }
+        samples.push_back(rtt.as_millis() as u64);
+    }
+
+    /// Peers with latency samples, fastest first
+    fn peers_by_latency(&self) -> Vec<PeerId> {
+        let mut peers: Vec<(PeerId, f64)> = self
+            .peer_latencies
+            .iter()
+            .filter_map(|(peer_id, samples)| Some((*peer_id, mean_latency_ms(samples)?)))
+            .collect();
+        peers.sort_by(|a, b| a.1.total_cmp(&b.1));
+        peers.into_iter().map(|(peer_id, _)| peer_id).collect()
+    }
+
+    fn stats(&self) -> DiscoveryEngineStats {
         DiscoveryEngineStats {
-            pending_count: state.pending.len(),
-            in_flight_count: state.in_flight_count,
-            max_concurrent: state.max_concurrent,
-            min_peers: state.min_peers,
+            pending_count: self.pending.len(),
+            in_flight_count: self.in_flight_count,
+            max_concurrent: self.max_concurrent,
+            min_peers: self.min_peers,
+            known_peers: self.known_peers.len(),
+            total_queries: self.total_queries,
+            total_cache_hits: self.total_cache_hits,
+            peer_query_latencies: self
+                .peer_latencies
+                .iter()
+                .map(|(peer_id, samples)| (*peer_id, samples.iter().copied().collect()))
+                .collect(),
+            query_success_rate: if self.completed_queries == 0 {
+                0.0
+            } else {
+                self.successful_queries as f64 / self.completed_queries as f64
+            },
         }
     }
 }
 
+/// Mean of a peer's latency samples, or `None` without any
+fn mean_latency_ms<'a>(samples: impl IntoIterator<Item = &'a u64>) -> Option<f64> {
+    let (sum, count) = samples
+        .into_iter()
+        .fold((0u64, 0u64), |(sum, count), ms| (sum + ms, count + 1));
+    (count > 0).then(|| sum as f64 / count as f64)
+}
+
 /// Handle for controlling the discovery engine
 #[derive(Clone)]
 pub struct DiscoveryEngineHandle {
     request_tx: mpsc::UnboundedSender<DiscoveryRequest>,
     shutdown: Arc<RwLock<bool>>,
+    state: Arc<RwLock<EngineState>>,
 }
 
 impl DiscoveryEngineHandle {
@@ -370,6 +650,33 @@ impl DiscoveryEngineHandle {
         Ok(rx)
     }
 
+    /// Find providers for a single CID
+    ///
+    /// Waits until the engine finds enough providers or runs out of retries,
+    /// returning whatever providers were found. Fails with `NoProviders` if
+    /// none were.
+    pub async fn find(&self, cid: Cid) -> Result<Vec<PeerId>> {
+        let mut results = self.queue_find_blocks_with_callback(vec![cid])?;
+        let mut providers = Vec::new();
+        while let Some(result) = results.recv().await {
+            providers = result.providers;
+            if result.sufficient {
+                break;
+            }
+        }
+
+        if providers.is_empty() {
+            Err(DiscoveryEngineError::NoProviders(cid))
+        } else {
+            Ok(providers)
+        }
+    }
+
+    /// Get current queue statistics from the running engine
+    pub async fn stats(&self) -> DiscoveryEngineStats {
+        self.state.read().await.stats()
+    }
+
     /// Shutdown the discovery engine
     pub async fn shutdown(&self) {
         *self.shutdown.write().await = true;
@@ -387,20 +694,166 @@ pub struct DiscoveryEngineStats {
     pub max_concurrent: usize,
     /// Minimum peers required per CID
     pub min_peers: usize,
+    /// Number of peers currently known from discovery events
+    pub known_peers: usize,
+    /// Queries sent to the discovery backend
+    pub total_queries: u64,
+    /// Requests answered from already completed discoveries
+    pub total_cache_hits: u64,
+    /// Recent query round-trip times per DHT peer in ms, oldest first
+    pub peer_query_latencies: HashMap<PeerId, Vec<u64>>,
+    /// Fraction of finished queries that returned providers
+    pub query_success_rate: f64,
+}
+
+impl DiscoveryEngineStats {
+    /// The peer with the highest mean query latency
+    pub fn slowest_peer(&self) -> Option<PeerId> {
+        self.peers_by_mean_latency()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(peer_id, _)| peer_id)
+    }
+
+    /// The peer with the lowest mean query latency
+    pub fn fastest_peer(&self) -> Option<PeerId> {
+        self.peers_by_mean_latency()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(peer_id, _)| peer_id)
+    }
+
+    fn peers_by_mean_latency(&self) -> impl Iterator<Item = (PeerId, f64)> + '_ {
+        self.peer_query_latencies
+            .iter()
+            .filter_map(|(peer_id, samples)| Some((*peer_id, mean_latency_ms(samples)?)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::cid_blake3::blake3_cid;
+    use crate::discovery::DiscoveryConfig;
+    use crate::identify_spr::create_signed_peer_record;
+    use libp2p::identity::Keypair;
     use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// In-memory discovery backend answering from a fixed provider table
+    #[derive(Clone)]
+    struct MockDiscovery {
+        providers: Arc<Mutex<HashMap<Cid, Vec<Vec<u8>>>>>,
+        queries: Arc<AtomicUsize>,
+        events_tx: broadcast::Sender<DiscoveryEvent>,
+        /// DHT peers answering every lookup, with their round-trip times
+        dht_peers: Arc<Mutex<Vec<(PeerId, Duration)>>>,
+        /// Preferred peers passed to each lookup
+        preferred: Arc<Mutex<Vec<Vec<PeerId>>>>,
+    }
+
+    impl MockDiscovery {
+        fn new() -> Self {
+            Self {
+                providers: Arc::new(Mutex::new(HashMap::new())),
+                queries: Arc::new(AtomicUsize::new(0)),
+                events_tx: broadcast::channel(16).0,
+                dht_peers: Arc::new(Mutex::new(Vec::new())),
+                preferred: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+
+        /// Have `peer_id` answer every lookup after `rtt`
+        fn add_dht_peer(&self, peer_id: PeerId, rtt: Duration) {
+            self.dht_peers.lock().unwrap().push((peer_id, rtt));
+        }
+
+        /// Register a provider for `cid`, returning its peer ID
+        fn add_provider(&self, cid: Cid) -> PeerId {
+            let keypair = Keypair::generate_secp256k1();
+            let peer_id = keypair.public().to_peer_id();
+            let record = create_signed_peer_record(
+                &keypair,
+                peer_id,
+                vec!["/ip4/127.0.0.1/tcp/8070".parse().unwrap()],
+            )
+            .unwrap();
+            self.providers
+                .lock()
+                .unwrap()
+                .entry(cid)
+                .or_default()
+                .push(record);
+            peer_id
+        }
+
+        fn queries(&self) -> usize {
+            self.queries.load(Ordering::SeqCst)
+        }
+    }
+
+    impl DiscoveryBackend for MockDiscovery {
+        fn find<'a>(
+            &'a self,
+            cid: &'a Cid,
+        ) -> BoxFuture<'a, std::result::Result<Vec<Vec<u8>>, DiscoveryError>> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            let providers = self.providers.lock().unwrap().get(cid).cloned();
+            Box::pin(async move {
+                providers.ok_or_else(|| DiscoveryError::NoProviders(cid.to_string()))
+            })
+        }
+
+        fn find_from<'a>(
+            &'a self,
+            cid: &'a Cid,
+            preferred: &'a [PeerId],
+        ) -> BoxFuture<'a, FindOutcome> {
+            self.preferred.lock().unwrap().push(preferred.to_vec());
+            let peer_rtts = self.dht_peers.lock().unwrap().clone();
+            Box::pin(async move {
+                FindOutcome {
+                    providers: self.find(cid).await,
+                    peer_rtts,
+                }
+            })
+        }
+
+        fn subscribe(&self) -> broadcast::Receiver<DiscoveryEvent> {
+            self.events_tx.subscribe()
+        }
+    }
+
+    impl DiscoveryEngine {
+        fn new_with_mock(mock: MockDiscovery) -> (DiscoveryEngine, DiscoveryEngineHandle) {
+            let (engine, _tx, handle) = DiscoveryEngine::new(Arc::new(mock));
+            (engine, handle)
+        }
+    }
+
+    /// Wait until no queries are in flight
+    async fn wait_idle(engine: &DiscoveryEngine) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while engine.stats().await.in_flight_count > 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("queries did not finish");
+    }
 
     async fn create_test_discovery() -> Arc<Discovery> {
         let keypair = libp2p::identity::Keypair::generate_secp256k1();
         let listen_addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
-        let discovery = Discovery::new(&keypair, listen_addr, vec![], vec![])
-            .await
-            .unwrap();
+        let discovery = Discovery::new(
+            &keypair,
+            listen_addr,
+            vec![],
+            vec![],
+            DiscoveryConfig::default(),
+        )
+        .await
+        .unwrap();
         Arc::new(discovery)
     }
 
@@ -530,4 +983,245 @@ mod tests {
         assert_eq!(stats.pending_count, 3);
         assert_eq!(stats.in_flight_count, 0);
     }
+
+    #[tokio::test]
+    async fn test_discovery_events_update_known_peers() {
+        let discovery = create_test_discovery().await;
+        let (engine, _tx, _handle) = DiscoveryEngine::new(discovery);
+
+        let peer_id = PeerId::random();
+        let addrs: Vec<Multiaddr> = vec!["/ip4/10.0.0.5/tcp/8070".parse().unwrap()];
+
+        engine
+            .handle_discovery_event(DiscoveryEvent::PeerDiscovered(peer_id, addrs.clone()))
+            .await;
+        assert_eq!(engine.stats().await.known_peers, 1);
+        assert_eq!(
+            engine.state.read().await.known_peers.get(&peer_id),
+            Some(&addrs)
+        );
+
+        engine
+            .handle_discovery_event(DiscoveryEvent::PeerLost(peer_id))
+            .await;
+        assert_eq!(engine.stats().await.known_peers, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_handle_find_returns_mock_providers() {
+        let mock = MockDiscovery::new();
+        let cid = blake3_cid(b"provided block").unwrap();
+        let mut expected: Vec<PeerId> = (0..DEFAULT_MIN_PEERS)
+            .map(|_| mock.add_provider(cid))
+            .collect();
+        let (engine, handle) = DiscoveryEngine::new_with_mock(mock.clone());
+        tokio::spawn(engine.run());
+
+        let mut providers = handle.find(cid).await.unwrap();
+        providers.sort();
+        expected.sort();
+        assert_eq!(providers, expected);
+        assert_eq!(mock.queries(), 1);
+
+        // A second lookup is served from the completed discovery
+        assert_eq!(handle.find(cid).await.unwrap().len(), DEFAULT_MIN_PEERS);
+        assert_eq!(mock.queries(), 1);
+        handle.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_providers_retried_up_to_max_retries() {
+        let mock = MockDiscovery::new();
+        let (engine, handle) = DiscoveryEngine::new_with_mock(mock.clone());
+        let engine = engine.with_max_retries(2);
+        tokio::spawn(engine.run());
+
+        let cid = blake3_cid(b"nobody has this").unwrap();
+        assert!(matches!(
+            handle.find(cid).await,
+            Err(DiscoveryEngineError::NoProviders(missing)) if missing == cid
+        ));
+        // The first query plus two retries
+        assert_eq!(mock.queries(), 3);
+        handle.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stats_count_queries_and_cache_hits() {
+        let mock = MockDiscovery::new();
+        let provided = blake3_cid(b"provided").unwrap();
+        let missing = blake3_cid(b"missing").unwrap();
+        for _ in 0..DEFAULT_MIN_PEERS {
+            mock.add_provider(provided);
+        }
+        let (engine, _handle) = DiscoveryEngine::new_with_mock(mock.clone());
+        let engine = engine.with_max_retries(0);
+
+        engine
+            .handle_request(DiscoveryRequest {
+                cids: vec![provided, missing],
+                callback: None,
+            })
+            .await;
+        engine.process_pending().await;
+        wait_idle(&engine).await;
+
+        let stats = engine.stats().await;
+        assert_eq!(stats.total_queries, 2);
+        assert_eq!(stats.total_cache_hits, 0);
+        assert_eq!(stats.pending_count, 0);
+        assert_eq!(stats.in_flight_count, 0);
+
+        // Only the completed CID is cached; the missing one is queried again
+        engine
+            .handle_request(DiscoveryRequest {
+                cids: vec![provided, missing],
+                callback: None,
+            })
+            .await;
+        let stats = engine.stats().await;
+        assert_eq!(stats.total_cache_hits, 1);
+        assert_eq!(stats.pending_count, 1);
+
+        engine.process_pending().await;
+        wait_idle(&engine).await;
+        let stats = engine.stats().await;
+        assert_eq!(stats.total_queries, 3);
+        assert_eq!(mock.queries(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_zero_peers_delays_queries() {
+        let mock = MockDiscovery::new();
+        let (mut engine, _handle) = DiscoveryEngine::new_with_mock(mock.clone());
+        let peers = Arc::new(AtomicUsize::new(0));
+        let source = peers.clone();
+        engine.set_peer_count_source(Arc::new(move || source.load(Ordering::SeqCst)));
+
+        engine
+            .handle_request(DiscoveryRequest {
+                cids: vec![blake3_cid(b"held back").unwrap()],
+                callback: None,
+            })
+            .await;
+        engine.process_pending().await;
+        assert_eq!(engine.stats().await.total_queries, 0);
+
+        tokio::time::advance(NO_PEERS_QUERY_DELAY - Duration::from_millis(1)).await;
+        engine.process_pending().await;
+        assert_eq!(engine.stats().await.total_queries, 0);
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        engine.process_pending().await;
+        assert_eq!(engine.stats().await.total_queries, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connected_peers_allow_queries() {
+        let mock = MockDiscovery::new();
+        let (mut engine, _handle) = DiscoveryEngine::new_with_mock(mock.clone());
+        let engine_state = engine.state.clone();
+        let peers = Arc::new(AtomicUsize::new(0));
+        let source = peers.clone();
+        engine.set_peer_count_source(Arc::new(move || source.load(Ordering::SeqCst)));
+
+        engine
+            .handle_request(DiscoveryRequest {
+                cids: vec![blake3_cid(b"first").unwrap()],
+                callback: None,
+            })
+            .await;
+        engine.process_pending().await;
+        assert_eq!(engine.stats().await.total_queries, 0);
+
+        // A peer connecting releases the queue without waiting out the delay
+        peers.store(1, Ordering::SeqCst);
+        engine.process_pending().await;
+        assert_eq!(engine.stats().await.total_queries, 1);
+        assert!(engine_state.read().await.no_peers_until.is_none());
+
+        engine
+            .handle_request(DiscoveryRequest {
+                cids: vec![blake3_cid(b"second").unwrap()],
+                callback: None,
+            })
+            .await;
+        engine.process_pending().await;
+        assert_eq!(engine.stats().await.total_queries, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stats_track_peer_latencies_and_success_rate() {
+        let mock = MockDiscovery::new();
+        let fast = PeerId::random();
+        let slow = PeerId::random();
+        mock.add_dht_peer(fast, Duration::from_millis(10));
+        mock.add_dht_peer(slow, Duration::from_millis(250));
+        let provided = blake3_cid(b"provided").unwrap();
+        for _ in 0..DEFAULT_MIN_PEERS {
+            mock.add_provider(provided);
+        }
+        let (engine, _handle) = DiscoveryEngine::new_with_mock(mock.clone());
+        let engine = engine.with_max_retries(0);
+
+        let stats = engine.stats().await;
+        assert!(stats.peer_query_latencies.is_empty());
+        assert_eq!(stats.query_success_rate, 0.0);
+        assert_eq!(stats.fastest_peer(), None);
+        assert_eq!(stats.slowest_peer(), None);
+
+        engine
+            .handle_request(DiscoveryRequest {
+                cids: vec![provided, blake3_cid(b"missing").unwrap()],
+                callback: None,
+            })
+            .await;
+        engine.process_pending().await;
+        wait_idle(&engine).await;
+
+        let stats = engine.stats().await;
+        assert_eq!(stats.peer_query_latencies[&fast], vec![10, 10]);
+        assert_eq!(stats.peer_query_latencies[&slow], vec![250, 250]);
+        assert_eq!(stats.query_success_rate, 0.5);
+        assert_eq!(stats.fastest_peer(), Some(fast));
+        assert_eq!(stats.slowest_peer(), Some(slow));
+
+        // Later queries ask the faster peer first
+        engine
+            .handle_request(DiscoveryRequest {
+                cids: vec![blake3_cid(b"another").unwrap()],
+                callback: None,
+            })
+            .await;
+        engine.process_pending().await;
+        wait_idle(&engine).await;
+        assert_eq!(
+            mock.preferred.lock().unwrap().last().unwrap(),
+            &vec![fast, slow]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_latency_samples_are_capped_per_peer() {
+        let mock = MockDiscovery::new();
+        let (engine, handle) = DiscoveryEngine::new_with_mock(mock);
+        let peer_id = PeerId::random();
+
+        {
+            let mut state = engine.state.write().await;
+            for ms in 0..(LATENCY_SAMPLES_PER_PEER as u64 + 5) {
+                state.record_latency(peer_id, Duration::from_millis(ms));
+            }
+        }
+
+        // The handle reads the same stats as the engine
+        let samples = handle.stats().await.peer_query_latencies[&peer_id].clone();
+        assert_eq!(samples.len(), LATENCY_SAMPLES_PER_PEER);
+        assert_eq!(samples[0], 5);
+
+        engine
+            .handle_discovery_event(DiscoveryEvent::PeerLost(peer_id))
+            .await;
+        assert!(handle.stats().await.peer_query_latencies.is_empty());
+    }
 }
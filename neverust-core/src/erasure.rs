@@ -0,0 +1,271 @@
+//! Reed-Solomon erasure coding for protected manifests
+//!
+//! A dataset's blocks are grouped into stripes of `ec_k` data shards, and each
+//! stripe is extended with `ec_m` parity shards. Any `ec_k` of a stripe's
+//! `ec_k + ec_m` shards are enough to recover its data shards.
+//!
+//! Stripes are contiguous: stripe `i` holds data blocks `i * ec_k` to
+//! `(i + 1) * ec_k - 1`. Short blocks are zero-padded to the block size and a
+//! short final stripe is filled with zero blocks before encoding.
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use thiserror::Error;
+
+/// Maximum total shards per stripe supported by GF(2^8)
+pub const MAX_SHARDS: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum ErasureError {
+    #[error("Invalid erasure parameters: {0}")]
+    InvalidParameters(String),
+
+    #[error("Shard size mismatch: expected {expected} bytes, got {actual}")]
+    ShardSizeMismatch { expected: usize, actual: usize },
+
+    #[error("Expected {expected} shards, got {actual}")]
+    ShardCountMismatch { expected: usize, actual: usize },
+
+    #[error("Reed-Solomon error: {0}")]
+    ReedSolomon(#[from] reed_solomon_erasure::Error),
+}
+
+/// Erasure coding parameters for a protected dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErasureParams {
+    /// Number of data shards per stripe
+    pub ec_k: usize,
+    /// Number of parity shards per stripe
+    pub ec_m: usize,
+}
+
+impl ErasureParams {
+    /// Create validated erasure parameters
+    pub fn new(ec_k: usize, ec_m: usize) -> Result<Self, ErasureError> {
+        if ec_k == 0 || ec_m == 0 {
+            return Err(ErasureError::InvalidParameters(format!(
+                "ec_k ({}) and ec_m ({}) must both be positive",
+                ec_k, ec_m
+            )));
+        }
+        if ec_k + ec_m > MAX_SHARDS {
+            return Err(ErasureError::InvalidParameters(format!(
+                "ec_k + ec_m ({}) exceeds {} shards",
+                ec_k + ec_m,
+                MAX_SHARDS
+            )));
+        }
+        Ok(Self { ec_k, ec_m })
+    }
+
+    /// Number of stripes needed for `blocks` data blocks
+    pub fn stripes_for(&self, blocks: usize) -> usize {
+        blocks.div_ceil(self.ec_k)
+    }
+}
+
+/// Reed-Solomon encoder/decoder over GF(2^8)
+pub struct ErasureEncoder;
+
+impl ErasureEncoder {
+    /// Compute `ec_m` parity shards for `data` (the `ec_k` data shards)
+    ///
+    /// All data shards must have the same, non-zero length.
+    pub fn encode(data: &[Vec<u8>], ec_m: usize) -> Result<Vec<Vec<u8>>, ErasureError> {
+        let params = ErasureParams::new(data.len(), ec_m)?;
+        let shard_len = Self::shard_len(data.iter().map(|shard| shard.len()))?;
+
+        let codec = ReedSolomon::new(params.ec_k, params.ec_m)?;
+        let mut parity = vec![vec![0u8; shard_len]; params.ec_m];
+        codec.encode_sep(data, &mut parity)?;
+        Ok(parity)
+    }
+
+    /// Recover the `ec_k` data shards from any `ec_k` of the `ec_k + ec_m`
+    /// shards
+    ///
+    /// `shards` holds the data shards followed by the parity shards, with
+    /// `None` for each missing shard.
+    pub fn decode(
+        mut shards: Vec<Option<Vec<u8>>>,
+        ec_k: usize,
+        ec_m: usize,
+    ) -> Result<Vec<Vec<u8>>, ErasureError> {
+        let params = ErasureParams::new(ec_k, ec_m)?;
+        if shards.len() != params.ec_k + params.ec_m {
+            return Err(ErasureError::ShardCountMismatch {
+                expected: params.ec_k + params.ec_m,
+                actual: shards.len(),
+            });
+        }
+        Self::shard_len(shards.iter().flatten().map(|shard| shard.len()))?;
+
+        let codec = ReedSolomon::new(params.ec_k, params.ec_m)?;
+        codec.reconstruct_data(&mut shards)?;
+
+        Ok(shards
+            .into_iter()
+            .take(params.ec_k)
+            .map(|shard| shard.expect("data shards are present after reconstruction"))
+            .collect())
+    }
+
+    /// Compute the parity blocks for a whole dataset
+    ///
+    /// Blocks are padded to `block_size` and grouped into contiguous stripes
+    /// of `ec_k` (the final stripe is filled with zero blocks). Returns the
+    /// parity blocks stripe by stripe, `ec_m` per stripe.
+    pub fn encode_blocks(
+        blocks: &[Vec<u8>],
+        block_size: usize,
+        params: ErasureParams,
+    ) -> Result<Vec<Vec<u8>>, ErasureError> {
+        let mut parity = Vec::with_capacity(params.stripes_for(blocks.len()) * params.ec_m);
+        for stripe in blocks.chunks(params.ec_k) {
+            let data = Self::pad_stripe(stripe, block_size, params.ec_k)?;
+            parity.extend(Self::encode(&data, params.ec_m)?);
+        }
+        Ok(parity)
+    }
+
+    /// Pad a stripe's blocks to `block_size` and fill it to `ec_k` shards
+    pub fn pad_stripe(
+        stripe: &[Vec<u8>],
+        block_size: usize,
+        ec_k: usize,
+    ) -> Result<Vec<Vec<u8>>, ErasureError> {
+        let mut data = Vec::with_capacity(ec_k);
+        for block in stripe {
+            if block.len() > block_size {
+                return Err(ErasureError::ShardSizeMismatch {
+                    expected: block_size,
+                    actual: block.len(),
+                });
+            }
+            let mut shard = block.clone();
+            shard.resize(block_size, 0);
+            data.push(shard);
+        }
+        data.resize(ec_k, vec![0u8; block_size]);
+        Ok(data)
+    }
+
+    /// Check that all shard lengths are equal and non-zero, returning the length
+    fn shard_len(mut lengths: impl Iterator<Item = usize>) -> Result<usize, ErasureError> {
+        let expected = lengths.next().unwrap_or(0);
+        if expected == 0 {
+            return Err(ErasureError::InvalidParameters(
+                "shards must not be empty".to_string(),
+            ));
+        }
+        for actual in lengths {
+            if actual != expected {
+                return Err(ErasureError::ShardSizeMismatch { expected, actual });
+            }
+        }
+        Ok(expected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_shards(ec_k: usize, len: usize) -> Vec<Vec<u8>> {
+        (0..ec_k)
+            .map(|i| (0..len).map(|j| (i * 31 + j * 7) as u8).collect())
+            .collect()
+    }
+
+    /// All subsets of `n` indices of size `k`
+    fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+        if k == 0 {
+            return vec![Vec::new()];
+        }
+        if n < k {
+            return Vec::new();
+        }
+        let mut result = combinations(n - 1, k);
+        for mut combo in combinations(n - 1, k - 1) {
+            combo.push(n - 1);
+            result.push(combo);
+        }
+        result
+    }
+
+    #[test]
+    fn test_decode_from_any_k_shards() {
+        let (ec_k, ec_m) = (4, 2);
+        let data = data_shards(ec_k, 64);
+        let parity = ErasureEncoder::encode(&data, ec_m).unwrap();
+        assert_eq!(parity.len(), ec_m);
+
+        let all: Vec<Vec<u8>> = data.iter().chain(parity.iter()).cloned().collect();
+        for kept in combinations(ec_k + ec_m, ec_k) {
+            let shards = all
+                .iter()
+                .enumerate()
+                .map(|(i, shard)| kept.contains(&i).then(|| shard.clone()))
+                .collect();
+            let recovered = ErasureEncoder::decode(shards, ec_k, ec_m).unwrap();
+            assert_eq!(recovered, data, "failed to recover from shards {:?}", kept);
+        }
+    }
+
+    #[test]
+    fn test_decode_fails_with_too_few_shards() {
+        let data = data_shards(3, 16);
+        let parity = ErasureEncoder::encode(&data, 2).unwrap();
+
+        let shards = vec![
+            Some(data[0].clone()),
+            None,
+            None,
+            Some(parity[0].clone()),
+            None,
+        ];
+        assert!(matches!(
+            ErasureEncoder::decode(shards, 3, 2),
+            Err(ErasureError::ReedSolomon(
+                reed_solomon_erasure::Error::TooFewShardsPresent
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_encode_rejects_invalid_input() {
+        assert!(matches!(
+            ErasureEncoder::encode(&data_shards(3, 16), 0),
+            Err(ErasureError::InvalidParameters(_))
+        ));
+        assert!(matches!(
+            ErasureEncoder::encode(&[vec![1; 8], vec![2; 9]], 1),
+            Err(ErasureError::ShardSizeMismatch {
+                expected: 8,
+                actual: 9
+            })
+        ));
+        assert!(ErasureParams::new(200, 57).is_err());
+    }
+
+    #[test]
+    fn test_encode_blocks_pads_final_stripe() {
+        let params = ErasureParams::new(3, 2).unwrap();
+        let blocks = vec![vec![1u8; 8], vec![2u8; 8], vec![3u8; 8], vec![4u8; 5]];
+
+        let parity = ErasureEncoder::encode_blocks(&blocks, 8, params).unwrap();
+        assert_eq!(parity.len(), 2 * params.ec_m);
+
+        // Recover the short final stripe from its parity alone plus padding
+        let shards = vec![
+            None,
+            Some(vec![0u8; 8]),
+            Some(vec![0u8; 8]),
+            Some(parity[2].clone()),
+            Some(parity[3].clone()),
+        ];
+        let recovered = ErasureEncoder::decode(shards, 3, 2).unwrap();
+        let mut expected = vec![4u8; 5];
+        expected.resize(8, 0);
+        assert_eq!(recovered[0], expected);
+    }
+}
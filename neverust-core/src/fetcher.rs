@@ -0,0 +1,900 @@
+//! Manifest-aware block fetching
+//!
+//! Resolves a manifest into its data blocks and fetches them through a
+//! configurable chain of block sources, reassembling the dataset in order.
+//!
+//! ## Architecture
+//!
+//! - **Dependency resolution**: manifest block -> tree metadata -> data blocks
+//! - **Fallback chain**: each block is tried against the sources in the
+//!   configured strategy (default: local -> BlockExc -> BoTG -> HTTP), each
+//!   with its own timeout; the first success is stored locally and later
+//!   sources are skipped
+//! - **Bounded concurrency**: data blocks are fetched in waves of `parallelism`
+//!   concurrent requests (default: 8)
+//! - **Retries**: the chain is retried up to `max_retries` times (default: 3)
+//!   before the fetch fails, all within `fetch_timeout` (default: 60s)
+//! - **HTTP datasets**: HTTP peers serve a manifest CID as its whole dataset,
+//!   so manifests whose blocks cannot be fetched are downloaded from them in
+//!   one request
+
+use cid::Cid;
+use futures::future::join_all;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::archivist_tree::ArchivistTree;
+use crate::blockexc::{BlockExcClient, BlockExcError};
+use crate::botg::BoTgProtocol;
+use crate::manifest::{Manifest, MANIFEST_CODEC};
+use crate::storage::{Block, BlockStore, StorageError};
+
+/// Default number of concurrent block requests
+pub const DEFAULT_PARALLELISM: usize = 8;
+
+/// Default number of retries per block after the first attempt
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Default limit on the total time spent fetching one block, across all
+/// sources and retries
+pub const DEFAULT_FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default order in which block sources are tried
+pub const DEFAULT_FETCH_STRATEGY: [FetchSource; 4] = [
+    FetchSource::Local,
+    FetchSource::BlockExc,
+    FetchSource::BoTG,
+    FetchSource::Http,
+];
+
+/// Base delay between retries (multiplied by the attempt number)
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Interval at which the BoTG stage checks whether the block has arrived
+const BOTG_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Maximum number of HTTP peers tried per block
+const MAX_HTTP_PEERS: usize = 25;
+
+/// A source blocks can be retrieved from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchSource {
+    /// The local block store
+    Local,
+    /// BlockExc want/have exchange with connected libp2p peers
+    #[value(name = "blockexc")]
+    BlockExc,
+    /// BoTG block requests over UDP
+    #[value(name = "botg")]
+    BoTG,
+    /// HTTP download from the configured fallback peers
+    Http,
+}
+
+impl FetchSource {
+    /// Default timeout for a single attempt against this source
+    pub fn default_timeout(self) -> Duration {
+        match self {
+            FetchSource::Local => Duration::from_secs(5),
+            FetchSource::BlockExc => Duration::from_secs(10),
+            FetchSource::BoTG => Duration::from_secs(5),
+            FetchSource::Http => Duration::from_secs(30),
+        }
+    }
+}
+
+/// Why a single source failed to provide a block
+#[derive(Debug, Error)]
+pub enum SourceError {
+    #[error("{0:?} source is not configured")]
+    NotConfigured(FetchSource),
+
+    #[error("block not found")]
+    NotFound,
+
+    #[error("timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("BlockExc error: {0}")]
+    BlockExc(#[from] BlockExcError),
+
+    #[error("HTTP error: {0}")]
+    Http(String),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("CID {0} is not a manifest")]
+    NotAManifest(Cid),
+
+    #[error("Invalid manifest {cid}: {reason}")]
+    InvalidManifest { cid: Cid, reason: String },
+
+    #[error("Block {cid} unavailable after {attempts} attempts: {source}")]
+    BlockUnavailable {
+        cid: Cid,
+        attempts: u32,
+        #[source]
+        source: SourceError,
+    },
+
+    #[error("Size mismatch: assembled {actual} bytes but manifest expects {expected}")]
+    SizeMismatch { expected: u64, actual: u64 },
+
+    #[error("Block {cid} not fetched within {timeout:?}")]
+    DeadlineExceeded { cid: Cid, timeout: Duration },
+
+    #[error("Fetched block {actual} instead of {expected}")]
+    UnexpectedBlock { expected: Cid, actual: Cid },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+impl From<FetchError> for axum::http::StatusCode {
+    fn from(err: FetchError) -> Self {
+        use axum::http::StatusCode;
+
+        match err {
+            FetchError::NotAManifest(_) => StatusCode::BAD_REQUEST,
+            FetchError::InvalidManifest { .. }
+            | FetchError::SizeMismatch { .. }
+            | FetchError::UnexpectedBlock { .. } => StatusCode::BAD_GATEWAY,
+            FetchError::DeadlineExceeded { .. } => StatusCode::GATEWAY_TIMEOUT,
+            FetchError::BlockUnavailable { source, .. } => match source {
+                SourceError::NotConfigured(_)
+                | SourceError::NotFound
+                | SourceError::BlockExc(BlockExcError::NoPeers) => StatusCode::NOT_FOUND,
+                SourceError::Timeout(_) | SourceError::BlockExc(BlockExcError::Timeout) => {
+                    StatusCode::GATEWAY_TIMEOUT
+                }
+                SourceError::BlockExc(BlockExcError::Shutdown) => StatusCode::SERVICE_UNAVAILABLE,
+                SourceError::BlockExc(_) | SourceError::Http(_) => StatusCode::BAD_GATEWAY,
+                SourceError::Storage(e) => e.into(),
+            },
+            FetchError::Storage(e) => e.into(),
+        }
+    }
+}
+
+/// HTTP peers used by the [`FetchSource::Http`] stage
+struct HttpFallback {
+    client: reqwest::Client,
+    peers: Vec<String>,
+}
+
+/// Fetches blocks and manifests through a chain of block sources
+pub struct BlockFetcher {
+    block_store: Arc<BlockStore>,
+    blockexc: Arc<BlockExcClient>,
+    botg: Option<Arc<BoTgProtocol>>,
+    http: Option<HttpFallback>,
+    strategy: Vec<FetchSource>,
+    stage_timeouts: HashMap<FetchSource, Duration>,
+    parallelism: usize,
+    max_retries: u32,
+    fetch_timeout: Duration,
+}
+
+impl BlockFetcher {
+    /// Create a new fetcher with the default strategy, parallelism and retry
+    /// limits
+    ///
+    /// The BoTG and HTTP stages are skipped until configured with
+    /// [`set_botg`](Self::set_botg) and
+    /// [`set_http_fallback`](Self::set_http_fallback).
+    pub fn new(block_store: Arc<BlockStore>, blockexc: Arc<BlockExcClient>) -> Self {
+        Self {
+            block_store,
+            blockexc,
+            botg: None,
+            http: None,
+            strategy: DEFAULT_FETCH_STRATEGY.to_vec(),
+            stage_timeouts: HashMap::new(),
+            parallelism: DEFAULT_PARALLELISM,
+            max_retries: DEFAULT_MAX_RETRIES,
+            fetch_timeout: DEFAULT_FETCH_TIMEOUT,
+        }
+    }
+
+    /// Set the number of concurrent block requests (minimum 1)
+    pub fn set_parallelism(&mut self, parallelism: usize) {
+        self.parallelism = parallelism.max(1);
+    }
+
+    /// Set the number of retries per block after the first attempt
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = max_retries;
+    }
+
+    /// Set the total time allowed for fetching one block, across all sources
+    /// and retries
+    pub fn set_fetch_timeout(&mut self, timeout: Duration) {
+        self.fetch_timeout = timeout;
+    }
+
+    /// Set the order in which block sources are tried
+    pub fn set_strategy(&mut self, strategy: Vec<FetchSource>) {
+        self.strategy = strategy;
+    }
+
+    /// Get the order in which block sources are tried
+    pub fn strategy(&self) -> &[FetchSource] {
+        &self.strategy
+    }
+
+    /// Set the timeout for a single attempt against `source`
+    pub fn set_stage_timeout(&mut self, source: FetchSource, timeout: Duration) {
+        self.stage_timeouts.insert(source, timeout);
+    }
+
+    /// Get the timeout for a single attempt against `source`
+    pub fn stage_timeout(&self, source: FetchSource) -> Duration {
+        self.stage_timeouts
+            .get(&source)
+            .copied()
+            .unwrap_or_else(|| source.default_timeout())
+    }
+
+    /// Enable the BoTG stage
+    pub fn set_botg(&mut self, botg: Arc<BoTgProtocol>) {
+        self.botg = Some(botg);
+    }
+
+    /// Enable the HTTP stage, downloading from `peers` (base URLs)
+    pub fn set_http_fallback(&mut self, client: reqwest::Client, peers: Vec<String>) {
+        self.http = Some(HttpFallback { client, peers });
+    }
+
+    /// Fetch a manifest's full dataset
+    ///
+    /// Fetches the manifest block and its tree metadata, then all data blocks
+    /// concurrently, and returns the data reassembled in order. Fetched blocks
+    /// are stored locally. If the manifest block cannot be fetched and the
+    /// HTTP stage is enabled, the dataset is downloaded from an HTTP peer
+    /// instead.
+    pub async fn fetch_manifest(&self, manifest_cid: Cid) -> Result<Vec<u8>, FetchError> {
+        if manifest_cid.codec() != MANIFEST_CODEC {
+            return Err(FetchError::NotAManifest(manifest_cid));
+        }
+
+        let invalid = |reason: String| FetchError::InvalidManifest {
+            cid: manifest_cid,
+            reason,
+        };
+
+        let manifest_block = match self.fetch_block(manifest_cid).await {
+            Ok(block) => block,
+            Err(e) if self.http.is_some() && self.strategy.contains(&FetchSource::Http) => {
+                info!(
+                    "Manifest {} unavailable as blocks ({}), downloading it from HTTP peers",
+                    manifest_cid, e
+                );
+                return self
+                    .fetch_dataset_from_http(manifest_cid)
+                    .await
+                    .map_err(|source| FetchError::BlockUnavailable {
+                        cid: manifest_cid,
+                        attempts: 1,
+                        source,
+                    });
+            }
+            Err(e) => return Err(e),
+        };
+        let manifest = Manifest::from_block(&manifest_block).map_err(|e| invalid(e.to_string()))?;
+
+        let metadata_cid = manifest
+            .metadata_cid()
+            .ok_or_else(|| invalid("missing metadata CID in filename field".to_string()))?;
+        let metadata_block = self.fetch_block(metadata_cid).await?;
+        let block_cids = ArchivistTree::deserialize_block_list(&metadata_block.data)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        if block_cids.len() != manifest.blocks_count() {
+            return Err(invalid(format!(
+                "tree has {} blocks but manifest expects {}",
+                block_cids.len(),
+                manifest.blocks_count()
+            )));
+        }
+
+        // Parity blocks of protected manifests follow the original content
+        let content_cids = &block_cids[..manifest.original_blocks_count().min(block_cids.len())];
+        info!(
+            "Fetching {} blocks for manifest {} ({} concurrent)",
+            content_cids.len(),
+            manifest_cid,
+            self.parallelism
+        );
+
+        let mut data = Vec::with_capacity(manifest.original_dataset_size() as usize);
+        for wave in content_cids.chunks(self.parallelism) {
+            let blocks = join_all(wave.iter().map(|cid| self.fetch_block(*cid))).await;
+            for block in blocks {
+                data.extend_from_slice(&block?.data);
+            }
+        }
+
+        if data.len() as u64 != manifest.original_dataset_size() {
+            return Err(FetchError::SizeMismatch {
+                expected: manifest.original_dataset_size(),
+                actual: data.len() as u64,
+            });
+        }
+
+        Ok(data)
+    }
+
+    /// Fetch a single block through the source chain
+    ///
+    /// Sources are tried in strategy order and the first success is stored
+    /// locally. The whole chain is retried up to `max_retries` times, giving
+    /// up with [`FetchError::DeadlineExceeded`] once `fetch_timeout` has
+    /// passed. A failed fetch reports the last error other than "not found",
+    /// so timeouts and peer errors are not hidden by later sources that
+    /// simply lack the block.
+    pub async fn fetch_block(&self, cid: Cid) -> Result<Block, FetchError> {
+        let deadline = tokio::time::Instant::now() + self.fetch_timeout;
+        let deadline_exceeded = || FetchError::DeadlineExceeded {
+            cid,
+            timeout: self.fetch_timeout,
+        };
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut last_error = SourceError::NotFound;
+
+            for &source in &self.strategy {
+                // HTTP peers answer manifest CIDs with the whole dataset, see
+                // `fetch_manifest`
+                if source == FetchSource::Http && cid.codec() == MANIFEST_CODEC {
+                    continue;
+                }
+                let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                if remaining.is_zero() {
+                    return Err(deadline_exceeded());
+                }
+                let timeout = self.stage_timeout(source).min(remaining);
+                let result = match tokio::time::timeout(timeout, self.fetch_from(source, cid)).await
+                {
+                    Ok(result) => result,
+                    Err(_) => Err(SourceError::Timeout(timeout)),
+                };
+
+                match result {
+                    Ok(block) => {
+                        if source != FetchSource::Local {
+                            self.block_store.put(block.clone()).await?;
+                        }
+                        debug!("Block {} fetched via {:?}", cid, source);
+                        return Ok(block);
+                    }
+                    Err(SourceError::NotConfigured(_)) => {}
+                    Err(e @ SourceError::BlockExc(BlockExcError::Shutdown)) => {
+                        // The node is stopping; later sources and retries
+                        // would only hold up shutdown
+                        return Err(FetchError::BlockUnavailable {
+                            cid,
+                            attempts: attempt,
+                            source: e,
+                        });
+                    }
+                    Err(e) => {
+                        debug!("Block {} not available via {:?}: {}", cid, source, e);
+                        if !matches!(e, SourceError::NotFound) {
+                            last_error = e;
+                        }
+                    }
+                }
+            }
+
+            if attempt > self.max_retries {
+                warn!(
+                    "Giving up on block {} after {} attempts: {}",
+                    cid, attempt, last_error
+                );
+                return Err(FetchError::BlockUnavailable {
+                    cid,
+                    attempts: attempt,
+                    source: last_error,
+                });
+            }
+            debug!("Block {} attempt {} failed: {}", cid, attempt, last_error);
+            let backoff = tokio::time::Instant::now() + RETRY_BACKOFF * attempt;
+            if backoff >= deadline {
+                return Err(deadline_exceeded());
+            }
+            tokio::time::sleep_until(backoff).await;
+        }
+    }
+
+    /// Try to retrieve a block from a single source
+    async fn fetch_from(&self, source: FetchSource, cid: Cid) -> Result<Block, SourceError> {
+        match source {
+            FetchSource::Local => match self.block_store.get(&cid).await {
+                Ok(block) => Ok(block),
+                Err(e) if e.is_not_found() => Err(SourceError::NotFound),
+                Err(e) => Err(e.into()),
+            },
+            FetchSource::BlockExc => Ok(self.blockexc.request_block(cid).await?),
+            FetchSource::BoTG => self.fetch_from_botg(cid).await,
+            FetchSource::Http => self.fetch_from_http(cid).await,
+        }
+    }
+
+    /// Request a block over BoTG and wait for it to land in the local store
+    async fn fetch_from_botg(&self, cid: Cid) -> Result<Block, SourceError> {
+        let botg = self
+            .botg
+            .as_ref()
+            .ok_or(SourceError::NotConfigured(FetchSource::BoTG))?;
+
+        // BoTG delivers responses asynchronously into the block store
+        botg.request_blocks_by_cid(vec![cid]).await;
+        loop {
+            match self.block_store.get(&cid).await {
+                Ok(block) => return Ok(block),
+                Err(e) if e.is_not_found() => {}
+                Err(e) => return Err(e.into()),
+            }
+            tokio::time::sleep(BOTG_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Download a block from the HTTP fallback peers, verifying its hash
+    async fn fetch_from_http(&self, cid: Cid) -> Result<Block, SourceError> {
+        self.download_from_http(cid, |data| Block::from_cid_and_data(cid, data))
+            .await
+    }
+
+    /// Download a manifest's dataset from the HTTP fallback peers
+    ///
+    /// Peers are trusted to serve the right data, since it cannot be checked
+    /// without the manifest.
+    async fn fetch_dataset_from_http(&self, manifest_cid: Cid) -> Result<Vec<u8>, SourceError> {
+        self.download_from_http(manifest_cid, Ok::<_, std::convert::Infallible>)
+            .await
+    }
+
+    /// Download `cid` from the first HTTP fallback peer whose response
+    /// `decode` accepts
+    async fn download_from_http<T, E: std::fmt::Display>(
+        &self,
+        cid: Cid,
+        decode: impl Fn(Vec<u8>) -> Result<T, E>,
+    ) -> Result<T, SourceError> {
+        let http = self
+            .http
+            .as_ref()
+            .ok_or(SourceError::NotConfigured(FetchSource::Http))?;
+
+        let mut peers = http.peers.clone();
+        if peers.len() > 1 {
+            peers.shuffle(&mut rand::thread_rng());
+        }
+
+        let mut last_error = SourceError::NotFound;
+        for base_url in peers.iter().take(MAX_HTTP_PEERS) {
+            let url = format!("{}/api/archivist/v1/data/{}/network/stream", base_url, cid);
+            let data = match http.client.get(&url).send().await {
+                Ok(resp) if resp.status().is_success() => match resp.bytes().await {
+                    Ok(data) => data,
+                    Err(e) => {
+                        last_error = SourceError::Http(format!("{}: {}", base_url, e));
+                        continue;
+                    }
+                },
+                // Keep any earlier peer's error over a plain "not found"
+                Ok(resp) if resp.status() == reqwest::StatusCode::NOT_FOUND => continue,
+                Ok(resp) => {
+                    last_error = SourceError::Http(format!("{}: HTTP {}", base_url, resp.status()));
+                    continue;
+                }
+                Err(e) => {
+                    last_error = SourceError::Http(format!("{}: {}", base_url, e));
+                    continue;
+                }
+            };
+
+            let size = data.len();
+            match decode(data.to_vec()) {
+                Ok(decoded) => {
+                    info!("Fetched {} from {} ({} bytes)", cid, base_url, size);
+                    return Ok(decoded);
+                }
+                Err(e) => last_error = SourceError::Http(format!("{}: {}", base_url, e)),
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+/// BlockExc test doubles shared by the fetcher and prefetch tests
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use crate::blockexc::BlockRequest;
+    use std::collections::HashMap;
+    use tokio::sync::mpsc;
+
+    /// Build a manifest dataset in `store`, returning (manifest CID, data, block CIDs)
+    pub(crate) async fn build_dataset(
+        store: &BlockStore,
+        nblocks: usize,
+    ) -> (Cid, Vec<u8>, Vec<Cid>) {
+        let block_size = 1024;
+        let data: Vec<u8> = (0..nblocks * block_size - 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        let mut block_cids = Vec::new();
+        for chunk in data.chunks(block_size) {
+            let block = Block::new(chunk.to_vec()).unwrap();
+            block_cids.push(block.cid);
+            store.put(block).await.unwrap();
+        }
+
+        let tree = ArchivistTree::new(block_cids.clone()).unwrap();
+        let metadata = Block::new(tree.serialize_block_list()).unwrap();
+        store.put(metadata.clone()).await.unwrap();
+
+        let manifest = Manifest::new(
+            tree.root_cid().unwrap(),
+            block_size as u64,
+            data.len() as u64,
+            None,
+            None,
+            None,
+            Some(format!("metadata:{}", metadata.cid)),
+            None,
+        );
+        let manifest_block = manifest.to_block().unwrap();
+        store.put(manifest_block.clone()).await.unwrap();
+
+        (manifest_block.cid, data, block_cids)
+    }
+
+    /// CIDs requested from the mock swarm, in order
+    pub(crate) type RequestLog = Arc<std::sync::Mutex<Vec<Cid>>>;
+
+    /// Serve BlockExc requests from `remote`, failing the first
+    /// `failures[cid]` requests for each CID by dropping the responder
+    pub(crate) fn spawn_mock_swarm(
+        remote: Arc<BlockStore>,
+        mut failures: HashMap<Cid, usize>,
+    ) -> (mpsc::UnboundedSender<BlockRequest>, RequestLog) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<BlockRequest>();
+        let requested = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = requested.clone();
+
+        tokio::spawn(async move {
+            while let Some(request) = rx.recv().await {
+                log.lock().unwrap().push(request.cid);
+
+                if let Some(remaining) = failures.get_mut(&request.cid) {
+                    if *remaining > 0 {
+                        *remaining -= 1;
+                        continue;
+                    }
+                }
+
+                if let Ok(block) = remote.get(&request.cid).await {
+                    if let Some(responder) = request.response_tx.lock().await.take() {
+                        let _ = responder.send(block);
+                    }
+                }
+            }
+        });
+
+        (tx, requested)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{build_dataset, spawn_mock_swarm};
+    use super::*;
+    use crate::blockexc::BlockRequest;
+    use crate::botg::BoTgConfig;
+    use crate::metrics::Metrics;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::mpsc;
+
+    fn fetcher_for(
+        local: Arc<BlockStore>,
+        request_tx: mpsc::UnboundedSender<BlockRequest>,
+    ) -> BlockFetcher {
+        let client = BlockExcClient::new(local.clone(), Metrics::new(), 3, request_tx);
+        BlockFetcher::new(local, Arc::new(client))
+    }
+
+    #[tokio::test]
+    async fn test_fetch_manifest_with_partial_availability() {
+        let remote = Arc::new(BlockStore::new());
+        let local = Arc::new(BlockStore::new());
+        let (manifest_cid, data, block_cids) = build_dataset(&remote, 20).await;
+
+        // Some blocks are already local, some need retries
+        for cid in block_cids.iter().step_by(3) {
+            local.put(remote.get(cid).await.unwrap()).await.unwrap();
+        }
+        let failures: HashMap<Cid, usize> = [(block_cids[1], 2), (block_cids[10], 3)].into();
+
+        let (tx, requested) = spawn_mock_swarm(remote, failures);
+        let fetcher = fetcher_for(local.clone(), tx);
+
+        let fetched = fetcher.fetch_manifest(manifest_cid).await.unwrap();
+        assert_eq!(fetched, data);
+
+        // Local blocks were never requested; retried blocks were requested again
+        let requested = requested.lock().unwrap().clone();
+        for cid in block_cids.iter().step_by(3) {
+            assert!(!requested.contains(cid));
+        }
+        assert_eq!(requested.iter().filter(|c| **c == block_cids[1]).count(), 3);
+        assert_eq!(
+            requested.iter().filter(|c| **c == block_cids[10]).count(),
+            4
+        );
+
+        // Everything is stored locally afterwards
+        for cid in &block_cids {
+            assert!(local.has(cid).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_manifest_fails_when_block_missing() {
+        let remote = Arc::new(BlockStore::new());
+        let local = Arc::new(BlockStore::new());
+        let (manifest_cid, _, block_cids) = build_dataset(&remote, 5).await;
+        remote.delete(&block_cids[4]).await.unwrap();
+
+        let (tx, requested) = spawn_mock_swarm(remote, HashMap::new());
+        let fetcher = fetcher_for(local, tx);
+
+        match fetcher.fetch_manifest(manifest_cid).await {
+            Err(FetchError::BlockUnavailable { cid, attempts, .. }) => {
+                assert_eq!(cid, block_cids[4]);
+                assert_eq!(attempts, DEFAULT_MAX_RETRIES + 1);
+            }
+            other => panic!("expected BlockUnavailable, got {:?}", other),
+        }
+        let requested = requested.lock().unwrap().clone();
+        assert_eq!(requested.iter().filter(|c| **c == block_cids[4]).count(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_non_manifest_cid() {
+        let local = Arc::new(BlockStore::new());
+        let (tx, _requested) = spawn_mock_swarm(Arc::new(BlockStore::new()), HashMap::new());
+        let fetcher = fetcher_for(local, tx);
+
+        let cid = Block::new(b"plain block".to_vec()).unwrap().cid;
+        assert!(matches!(
+            fetcher.fetch_manifest(cid).await,
+            Err(FetchError::NotAManifest(_))
+        ));
+    }
+
+    /// Serve blocks from `store` over the Archivist stream endpoint, counting
+    /// requests. Returns the server's base URL.
+    async fn spawn_http_peer(store: Arc<BlockStore>) -> (String, Arc<AtomicUsize>) {
+        use axum::extract::{Path, State};
+        use axum::http::StatusCode;
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new()
+            .route(
+                "/api/archivist/v1/data/{cid}/network/stream",
+                axum::routing::get(
+                    |State((store, hits)): State<(Arc<BlockStore>, Arc<AtomicUsize>)>,
+                     Path(cid): Path<String>| async move {
+                        hits.fetch_add(1, Ordering::SeqCst);
+                        let cid: Cid = cid.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+                        store
+                            .get(&cid)
+                            .await
+                            .map(|block| block.data)
+                            .map_err(|_| StatusCode::NOT_FOUND)
+                    },
+                ),
+            )
+            .with_state((store, hits.clone()));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}", addr), hits)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_chain_short_circuits_on_first_success() {
+        let remote = Arc::new(BlockStore::new());
+        let local = Arc::new(BlockStore::new());
+        let block = Block::new(b"chain block".to_vec()).unwrap();
+        remote.put(block.clone()).await.unwrap();
+
+        let (tx, requested) = spawn_mock_swarm(remote.clone(), HashMap::new());
+        let (url, hits) = spawn_http_peer(remote).await;
+        let mut fetcher = fetcher_for(local.clone(), tx);
+        fetcher.set_http_fallback(reqwest::Client::new(), vec![url]);
+
+        // BlockExc answers, so HTTP is never tried
+        let fetched = fetcher.fetch_block(block.cid).await.unwrap();
+        assert_eq!(fetched.data, block.data);
+        assert_eq!(requested.lock().unwrap().as_slice(), &[block.cid]);
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+        assert!(local.has(&block.cid).await);
+
+        // Now stored locally, so no network source is tried
+        fetcher.fetch_block(block.cid).await.unwrap();
+        assert_eq!(requested.lock().unwrap().len(), 1);
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_chain_follows_configured_order() {
+        let remote = Arc::new(BlockStore::new());
+        let block = Block::new(b"ordered block".to_vec()).unwrap();
+        remote.put(block.clone()).await.unwrap();
+
+        let (tx, requested) = spawn_mock_swarm(remote.clone(), HashMap::new());
+        let (url, hits) = spawn_http_peer(remote).await;
+        let mut fetcher = fetcher_for(Arc::new(BlockStore::new()), tx);
+        fetcher.set_http_fallback(reqwest::Client::new(), vec![url]);
+        fetcher.set_strategy(vec![FetchSource::Http, FetchSource::BlockExc]);
+
+        fetcher.fetch_block(block.cid).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(requested.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_chain_falls_through_failed_stages() {
+        let http_store = Arc::new(BlockStore::new());
+        let local = Arc::new(BlockStore::new());
+        let block = Block::new(b"http only block".to_vec()).unwrap();
+        http_store.put(block.clone()).await.unwrap();
+
+        // Neither the BlockExc swarm nor BoTG (no peers) has the block
+        let (tx, requested) = spawn_mock_swarm(Arc::new(BlockStore::new()), HashMap::new());
+        let (url, hits) = spawn_http_peer(http_store).await;
+        let mut fetcher = fetcher_for(local.clone(), tx);
+        fetcher.set_botg(Arc::new(BoTgProtocol::new(BoTgConfig::default())));
+        fetcher.set_http_fallback(reqwest::Client::new(), vec![url]);
+        fetcher.set_stage_timeout(FetchSource::BlockExc, Duration::from_millis(100));
+        fetcher.set_stage_timeout(FetchSource::BoTG, Duration::from_millis(100));
+        fetcher.set_max_retries(0);
+
+        let fetched = fetcher.fetch_block(block.cid).await.unwrap();
+        assert_eq!(fetched.data, block.data);
+        assert_eq!(requested.lock().unwrap().as_slice(), &[block.cid]);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(local.has(&block.cid).await);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_chain_rejects_corrupt_http_data() {
+        let http_store = Arc::new(BlockStore::new());
+        let block = Block::new(b"genuine".to_vec()).unwrap();
+        // Serve different bytes under the requested CID
+        http_store
+            .put(Block {
+                cid: block.cid,
+                data: b"tampered".to_vec(),
+            })
+            .await
+            .unwrap();
+
+        let (tx, _requested) = spawn_mock_swarm(Arc::new(BlockStore::new()), HashMap::new());
+        let (url, _hits) = spawn_http_peer(http_store).await;
+        let mut fetcher = fetcher_for(Arc::new(BlockStore::new()), tx);
+        fetcher.set_http_fallback(reqwest::Client::new(), vec![url]);
+        fetcher.set_strategy(vec![FetchSource::Http]);
+        fetcher.set_max_retries(0);
+
+        assert!(matches!(
+            fetcher.fetch_block(block.cid).await,
+            Err(FetchError::BlockUnavailable {
+                source: SourceError::Http(_),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fetch_block_is_bounded_by_fetch_timeout() {
+        // A swarm that never answers
+        let (tx, _rx) = mpsc::unbounded_channel::<BlockRequest>();
+        let mut fetcher = fetcher_for(Arc::new(BlockStore::new()), tx);
+        fetcher.set_stage_timeout(FetchSource::BlockExc, Duration::from_secs(10));
+        fetcher.set_fetch_timeout(Duration::from_secs(25));
+
+        let cid = Block::new(b"never answered".to_vec()).unwrap().cid;
+        let started = tokio::time::Instant::now();
+        match fetcher.fetch_block(cid).await {
+            Err(e @ FetchError::DeadlineExceeded { .. }) => {
+                assert_eq!(
+                    axum::http::StatusCode::from(e),
+                    axum::http::StatusCode::GATEWAY_TIMEOUT
+                );
+            }
+            other => panic!("expected DeadlineExceeded, got {:?}", other),
+        }
+        // Four attempts of 10s each would take 40s
+        assert!(started.elapsed() <= Duration::from_secs(25));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reports_timeout_over_later_not_found() {
+        let (tx, _rx) = mpsc::unbounded_channel::<BlockRequest>();
+        let (url, hits) = spawn_http_peer(Arc::new(BlockStore::new())).await;
+        let mut fetcher = fetcher_for(Arc::new(BlockStore::new()), tx);
+        fetcher.set_http_fallback(reqwest::Client::new(), vec![url]);
+        fetcher.set_stage_timeout(FetchSource::BlockExc, Duration::from_millis(50));
+        fetcher.set_max_retries(0);
+
+        let cid = Block::new(b"slow block".to_vec()).unwrap().cid;
+        match fetcher.fetch_block(cid).await {
+            Err(FetchError::BlockUnavailable {
+                source: SourceError::Timeout(_),
+                ..
+            }) => {}
+            other => panic!("expected a BlockExc timeout, got {:?}", other),
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Every source lacking the block is a plain "not found"
+        fetcher.set_strategy(vec![FetchSource::Local, FetchSource::Http]);
+        let error = fetcher.fetch_block(cid).await.unwrap_err();
+        assert!(matches!(
+            error,
+            FetchError::BlockUnavailable {
+                source: SourceError::NotFound,
+                ..
+            }
+        ));
+        assert_eq!(
+            axum::http::StatusCode::from(error),
+            axum::http::StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_manifest_is_downloaded_from_http_once() {
+        let remote = Arc::new(BlockStore::new());
+        let (manifest_cid, data, _) = build_dataset(&remote, 3).await;
+        // HTTP peers answer a manifest CID with its dataset
+        let http_store = Arc::new(BlockStore::new());
+        http_store
+            .put(Block {
+                cid: manifest_cid,
+                data: data.clone(),
+            })
+            .await
+            .unwrap();
+
+        let (tx, requested) = spawn_mock_swarm(Arc::new(BlockStore::new()), HashMap::new());
+        let (url, hits) = spawn_http_peer(http_store).await;
+        let mut fetcher = fetcher_for(Arc::new(BlockStore::new()), tx);
+        fetcher.set_http_fallback(reqwest::Client::new(), vec![url]);
+        fetcher.set_max_retries(0);
+
+        assert_eq!(fetcher.fetch_manifest(manifest_cid).await.unwrap(), data);
+        assert_eq!(requested.lock().unwrap().as_slice(), &[manifest_cid]);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,27 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`
+//!
+//! Only compiled under `cfg(fuzzing)`, which cargo-fuzz sets. Each entry
+//! point feeds untrusted bytes to a decoder and discards the result: errors
+//! are expected, panics are bugs.
+//!
+//! ```text
+//! cargo +nightly fuzz run decode_message
+//! cargo +nightly fuzz run read_length_prefixed
+//! ```
+
+/// Decode `data` as a BlockExc protobuf message
+pub fn decode_message(data: &[u8]) {
+    let _ = crate::messages::decode_message(data);
+}
+
+/// Read one length-prefixed frame from `data`, then decode its payload
+pub fn read_length_prefixed(data: &[u8]) {
+    let mut reader = futures::io::Cursor::new(data);
+    let frame = futures::executor::block_on(crate::blockexc::read_length_prefixed(
+        &mut reader,
+        crate::blockexc::DEFAULT_MAX_MESSAGE_BYTES,
+    ));
+    if let Ok(frame) = frame {
+        decode_message(&frame);
+    }
+}
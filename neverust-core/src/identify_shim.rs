@@ -9,10 +9,47 @@
 //! - Keep everything else identical to rust-libp2p's implementation
 //!
 //! This preserves all functionality while fixing only the SPR encoding.
+//!
+//! ## SPR exchange
+//!
+//! Alongside identify, every new connection opens a substream on
+//! `/archivist/identify/1.0.0` and sends the local node's current SPR as one
+//! length-prefixed frame. The SPR the remote sends back is verified with
+//! [`parse_spr_bytes`]; its addresses are reported to the swarm as external
+//! addresses of that peer and emitted as [`IdentifyShimEvent::ReceivedSpr`].
+//! Records with a lower `seq` than the last one accepted from a peer are
+//! ignored as stale.
 
+use crate::blockexc::{read_length_prefixed, write_length_prefixed};
 use crate::identify_spr;
-use libp2p::{core::Endpoint, identify, identity::Keypair, Multiaddr, PeerId};
+use crate::spr::{parse_spr_bytes, SprRecord};
+use either::Either;
+use futures::{future::BoxFuture, stream::FuturesUnordered, AsyncWriteExt, FutureExt, StreamExt};
+use libp2p::core::upgrade::ReadyUpgrade;
+use libp2p::swarm::{
+    handler::{
+        ConnectionEvent, ConnectionHandlerSelect, DialUpgradeError, FullyNegotiatedInbound,
+        FullyNegotiatedOutbound,
+    },
+    ConnectionHandler, ConnectionHandlerEvent, FromSwarm, NetworkBehaviour, StreamProtocol,
+    SubstreamProtocol, THandlerInEvent, ToSwarm,
+};
+use libp2p::{core::Endpoint, identify, identity::Keypair, Multiaddr, PeerId, Stream};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::io;
 use std::task::{Context, Poll};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Protocol used to exchange SPRs when a connection is established
+pub const SPR_PROTOCOL_ID: &str = "/archivist/identify/1.0.0";
+
+/// Largest SPR accepted from a peer
+const MAX_SPR_SIZE: usize = 8 * 1024;
+
+/// Time allowed for sending or receiving an SPR
+const SPR_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Custom Identify Config with nim-libp2p compatible SPR
 pub struct IdentifyConfig {
@@ -46,18 +83,175 @@ impl IdentifyConfig {
     }
 }
 
-/// Custom Identify Behaviour using nim-libp2p compatible SPR
+/// Events emitted by [`IdentifyBehaviour`]
+#[derive(Debug)]
+pub enum IdentifyShimEvent {
+    /// Event from the standard identify protocol
+    Identify(Box<identify::Event>),
+    /// A peer sent a valid SPR listing these addresses
+    ReceivedSpr(PeerId, Vec<Multiaddr>),
+}
+
+/// Connection handler that sends our SPR to the remote and reads theirs
 ///
-/// This is a simplified wrapper that delegates to the standard identify::Behaviour
-/// but with custom SPR encoding. Since we can't easily intercept the handler's
-/// message encoding, we use the standard behaviour WITHOUT SPR, which works fine.
+/// The SPR is sent once per connection on an outbound substream. Each
+/// inbound substream carries one SPR from the remote, which is passed to the
+/// behaviour undecoded.
+pub struct SprExchangeHandler {
+    peer_id: PeerId,
+    /// Our SPR, until the outbound substream for it has been requested
+    local_spr: Option<Vec<u8>>,
+    /// SPRs being written to the remote
+    sends: FuturesUnordered<BoxFuture<'static, io::Result<()>>>,
+    /// SPRs being read from the remote
+    receives: FuturesUnordered<BoxFuture<'static, io::Result<Vec<u8>>>>,
+}
+
+impl SprExchangeHandler {
+    pub fn new(peer_id: PeerId, local_spr: Option<Vec<u8>>) -> Self {
+        Self {
+            peer_id,
+            local_spr,
+            sends: FuturesUnordered::new(),
+            receives: FuturesUnordered::new(),
+        }
+    }
+
+    fn send_spr(mut stream: Stream, spr: Vec<u8>) -> BoxFuture<'static, io::Result<()>> {
+        async move {
+            tokio::time::timeout(SPR_EXCHANGE_TIMEOUT, async {
+                write_length_prefixed(&mut stream, &spr).await?;
+                stream.close().await
+            })
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "SPR send timed out"))?
+        }
+        .boxed()
+    }
+
+    fn receive_spr(mut stream: Stream) -> BoxFuture<'static, io::Result<Vec<u8>>> {
+        async move {
+            tokio::time::timeout(
+                SPR_EXCHANGE_TIMEOUT,
+                read_length_prefixed(&mut stream, MAX_SPR_SIZE),
+            )
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "SPR receive timed out"))?
+        }
+        .boxed()
+    }
+}
+
+impl ConnectionHandler for SprExchangeHandler {
+    type FromBehaviour = Infallible;
+    /// Raw SPR bytes received from the remote
+    type ToBehaviour = Vec<u8>;
+    type InboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = Vec<u8>;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(ReadyUpgrade::new(StreamProtocol::new(SPR_PROTOCOL_ID)), ())
+    }
+
+    fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
+        match event {}
+    }
+
+    fn connection_keep_alive(&self) -> bool {
+        self.local_spr.is_some() || !self.sends.is_empty() || !self.receives.is_empty()
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::ToBehaviour>,
+    > {
+        if let Some(spr) = self.local_spr.take() {
+            return Poll::Ready(ConnectionHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(
+                    ReadyUpgrade::new(StreamProtocol::new(SPR_PROTOCOL_ID)),
+                    spr,
+                ),
+            });
+        }
+
+        while let Poll::Ready(Some(result)) = self.sends.poll_next_unpin(cx) {
+            match result {
+                Ok(()) => debug!("Identify shim: Sent SPR to {}", self.peer_id),
+                Err(e) => debug!(
+                    "Identify shim: Failed to send SPR to {}: {}",
+                    self.peer_id, e
+                ),
+            }
+        }
+
+        while let Poll::Ready(Some(result)) = self.receives.poll_next_unpin(cx) {
+            match result {
+                Ok(spr) => return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(spr)),
+                Err(e) => debug!(
+                    "Identify shim: Failed to receive SPR from {}: {}",
+                    self.peer_id, e
+                ),
+            }
+        }
+
+        Poll::Pending
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        match event {
+            ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound {
+                protocol: stream,
+                ..
+            }) => {
+                self.receives.push(Self::receive_spr(stream));
+            }
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
+                protocol: stream,
+                info: spr,
+            }) => {
+                self.sends.push(Self::send_spr(stream, spr));
+            }
+            ConnectionEvent::DialUpgradeError(DialUpgradeError { error, .. }) => {
+                // Expected for peers without the protocol (e.g. Archivist nodes)
+                debug!(
+                    "Identify shim: {} did not accept SPR exchange: {}",
+                    self.peer_id, error
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Custom Identify Behaviour using nim-libp2p compatible SPR
 ///
-/// For now, this is functionally equivalent to using identify::Behaviour::new()
-/// with Config::new (no SPR). When we need SPR, we'll extend this to inject
-/// our custom SPR bytes at the protocol level.
+/// Delegates to the standard identify::Behaviour, configured WITHOUT SPR
+/// since nim-libp2p v1.9.0 cannot decode rust-libp2p's envelopes. Signed peer
+/// records are exchanged on a separate protocol instead, using our own
+/// nim-libp2p compatible encoding (see the module docs).
 pub struct IdentifyBehaviour {
     inner: identify::Behaviour,
     keypair: Keypair,
+    /// Current listen addresses
+    listen_addrs: Vec<Multiaddr>,
+    /// Confirmed external addresses, advertised in preference to listen addresses
+    external_addrs: Vec<Multiaddr>,
+    /// Latest SPR accepted from each connected peer
+    peer_records: HashMap<PeerId, SprRecord>,
+    /// Events to emit from `poll`
+    pending_events: VecDeque<ToSwarm<IdentifyShimEvent, THandlerInEvent<Self>>>,
 }
 
 impl IdentifyBehaviour {
@@ -74,6 +268,10 @@ impl IdentifyBehaviour {
         Self {
             inner,
             keypair: config.keypair,
+            listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            peer_records: HashMap::new(),
+            pending_events: VecDeque::new(),
         }
     }
 
@@ -82,13 +280,84 @@ impl IdentifyBehaviour {
         let peer_id = PeerId::from(self.keypair.public());
         identify_spr::create_signed_peer_record(&self.keypair, peer_id, addrs)
     }
+
+    /// SPR sent to newly connected peers, if we have addresses to advertise
+    fn local_spr(&self) -> Option<Vec<u8>> {
+        let addrs = if self.external_addrs.is_empty() {
+            &self.listen_addrs
+        } else {
+            &self.external_addrs
+        };
+        if addrs.is_empty() {
+            return None;
+        }
+
+        match self.generate_spr(addrs.clone()) {
+            Ok(spr) => Some(spr),
+            Err(e) => {
+                warn!("Identify shim: Failed to generate SPR: {}", e);
+                None
+            }
+        }
+    }
+
+    fn spr_handler(&self, peer: PeerId) -> SprExchangeHandler {
+        SprExchangeHandler::new(peer, self.local_spr())
+    }
+
+    /// Validate an SPR received from `peer_id` and queue its addresses
+    fn on_spr_received(&mut self, peer_id: PeerId, spr: &[u8]) {
+        let record = match parse_spr_bytes(spr) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("Identify shim: Invalid SPR from {}: {}", peer_id, e);
+                return;
+            }
+        };
+        if record.peer_id != peer_id {
+            warn!(
+                "Identify shim: {} sent an SPR for {}, ignoring",
+                peer_id, record.peer_id
+            );
+            return;
+        }
+        if let Some(known) = self.peer_records.get(&peer_id) {
+            if known.is_newer_than(&record) {
+                debug!(
+                    "Identify shim: Ignoring stale SPR from {} (seq {} < {})",
+                    peer_id, record.seq, known.seq
+                );
+                return;
+            }
+        }
+
+        debug!(
+            "Identify shim: Received SPR from {} (seq {}) with addrs {:?}",
+            peer_id, record.seq, record.addrs
+        );
+        for address in record.to_multiaddrs() {
+            self.pending_events
+                .push_back(ToSwarm::NewExternalAddrOfPeer {
+                    peer_id,
+                    address: address.clone(),
+                });
+        }
+        self.pending_events
+            .push_back(ToSwarm::GenerateEvent(IdentifyShimEvent::ReceivedSpr(
+                peer_id,
+                record.addrs.clone(),
+            )));
+        self.peer_records.insert(peer_id, record);
+    }
 }
 
-// Delegate all NetworkBehaviour methods to inner
-impl libp2p::swarm::NetworkBehaviour for IdentifyBehaviour {
-    type ConnectionHandler =
-        <identify::Behaviour as libp2p::swarm::NetworkBehaviour>::ConnectionHandler;
-    type ToSwarm = identify::Event;
+// Delegate identify to inner, and run the SPR exchange alongside it
+impl NetworkBehaviour for IdentifyBehaviour {
+    type ConnectionHandler = ConnectionHandlerSelect<
+        <identify::Behaviour as NetworkBehaviour>::ConnectionHandler,
+        SprExchangeHandler,
+    >;
+    type ToSwarm = IdentifyShimEvent;
 
     fn handle_established_inbound_connection(
         &mut self,
@@ -97,12 +366,13 @@ impl libp2p::swarm::NetworkBehaviour for IdentifyBehaviour {
         local_addr: &Multiaddr,
         remote_addr: &Multiaddr,
     ) -> Result<libp2p::swarm::THandler<Self>, libp2p::swarm::ConnectionDenied> {
-        self.inner.handle_established_inbound_connection(
+        let identify = self.inner.handle_established_inbound_connection(
             connection_id,
             peer,
             local_addr,
             remote_addr,
-        )
+        )?;
+        Ok(identify.select(self.spr_handler(peer)))
     }
 
     fn handle_established_outbound_connection(
@@ -113,16 +383,29 @@ impl libp2p::swarm::NetworkBehaviour for IdentifyBehaviour {
         role_override: Endpoint,
         port_use: libp2p::core::transport::PortUse,
     ) -> Result<libp2p::swarm::THandler<Self>, libp2p::swarm::ConnectionDenied> {
-        self.inner.handle_established_outbound_connection(
+        let identify = self.inner.handle_established_outbound_connection(
             connection_id,
             peer,
             addr,
             role_override,
             port_use,
-        )
+        )?;
+        Ok(identify.select(self.spr_handler(peer)))
     }
 
-    fn on_swarm_event(&mut self, event: libp2p::swarm::FromSwarm) {
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match &event {
+            FromSwarm::NewListenAddr(e) => self.listen_addrs.push(e.addr.clone()),
+            FromSwarm::ExpiredListenAddr(e) => self.listen_addrs.retain(|a| a != e.addr),
+            FromSwarm::ExternalAddrConfirmed(e) if !self.external_addrs.contains(e.addr) => {
+                self.external_addrs.push(e.addr.clone());
+            }
+            FromSwarm::ExternalAddrExpired(e) => self.external_addrs.retain(|a| a != e.addr),
+            FromSwarm::ConnectionClosed(e) if e.remaining_established == 0 => {
+                self.peer_records.remove(&e.peer_id);
+            }
+            _ => {}
+        }
         self.inner.on_swarm_event(event);
     }
 
@@ -132,15 +415,25 @@ impl libp2p::swarm::NetworkBehaviour for IdentifyBehaviour {
         connection_id: libp2p::swarm::ConnectionId,
         event: libp2p::swarm::THandlerOutEvent<Self>,
     ) {
-        self.inner
-            .on_connection_handler_event(peer_id, connection_id, event);
+        match event {
+            Either::Left(event) => {
+                self.inner
+                    .on_connection_handler_event(peer_id, connection_id, event);
+            }
+            Either::Right(spr) => self.on_spr_received(peer_id, &spr),
+        }
     }
 
-    fn poll(
-        &mut self,
-        cx: &mut Context,
-    ) -> Poll<libp2p::swarm::ToSwarm<Self::ToSwarm, libp2p::swarm::THandlerInEvent<Self>>> {
-        self.inner.poll(cx)
+    fn poll(&mut self, cx: &mut Context) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        self.inner.poll(cx).map(|event| {
+            event
+                .map_out(|e| IdentifyShimEvent::Identify(Box::new(e)))
+                .map_in(Either::Left)
+        })
     }
 }
 
@@ -165,4 +458,127 @@ mod tests {
         let spr = behaviour.generate_spr(addrs);
         assert!(spr.is_ok());
     }
+
+    #[test]
+    fn test_stale_spr_is_ignored() {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        let config =
+            IdentifyConfig::new("Archivist Node".to_string(), &Keypair::generate_ed25519());
+        let mut behaviour = IdentifyBehaviour::new(config);
+
+        let remote = Keypair::generate_ed25519();
+        let peer_id = remote.public().to_peer_id();
+        let spr_bytes = |addr: &str, seq| {
+            let spr = crate::spr::generate_spr(&remote, &[addr.parse().unwrap()], seq).unwrap();
+            URL_SAFE_NO_PAD
+                .decode(spr.strip_prefix("spr:").unwrap())
+                .unwrap()
+        };
+
+        behaviour.on_spr_received(peer_id, &spr_bytes("/ip4/10.0.0.1/tcp/8070", 5));
+        let queued = behaviour.pending_events.len();
+        assert!(queued > 0);
+
+        // An older record is dropped without queueing any events
+        behaviour.on_spr_received(peer_id, &spr_bytes("/ip4/10.0.0.2/tcp/8070", 4));
+        assert_eq!(behaviour.pending_events.len(), queued);
+        assert_eq!(behaviour.peer_records[&peer_id].seq, 5);
+
+        behaviour.on_spr_received(peer_id, &spr_bytes("/ip4/10.0.0.3/tcp/8070", 6));
+        assert!(behaviour.pending_events.len() > queued);
+        assert_eq!(behaviour.peer_records[&peer_id].seq, 6);
+    }
+
+    async fn listening_swarm() -> (libp2p::Swarm<crate::p2p::Behaviour>, Multiaddr) {
+        use libp2p::swarm::SwarmEvent;
+
+        let (mut swarm, _, _) = crate::p2p::create_swarm(
+            std::sync::Arc::new(crate::storage::BlockStore::new()),
+            "altruistic".to_string(),
+            0,
+            crate::metrics::Metrics::new(),
+        )
+        .await
+        .unwrap();
+        swarm
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = swarm.select_next_some().await {
+                return (swarm, address);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peers_exchange_sprs_on_connect() {
+        use crate::p2p::BehaviourEvent;
+        use libp2p::swarm::SwarmEvent;
+
+        let (mut listener, listener_addr) = listening_swarm().await;
+        let (mut dialer, dialer_addr) = listening_swarm().await;
+        let listener_id = *listener.local_peer_id();
+        let dialer_id = *dialer.local_peer_id();
+
+        dialer.dial(listener_addr.clone()).unwrap();
+
+        let mut dialer_learned = None;
+        let mut listener_learned = None;
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while dialer_learned.is_none() || listener_learned.is_none() {
+                tokio::select! {
+                    event = dialer.select_next_some() => {
+                        if let SwarmEvent::Behaviour(BehaviourEvent::ReceivedSpr(peer, addrs)) = event {
+                            dialer_learned = Some((peer, addrs));
+                        }
+                    }
+                    event = listener.select_next_some() => {
+                        if let SwarmEvent::Behaviour(BehaviourEvent::ReceivedSpr(peer, addrs)) = event {
+                            listener_learned = Some((peer, addrs));
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .expect("SPR exchange did not complete");
+
+        assert_eq!(dialer_learned, Some((listener_id, vec![listener_addr])));
+        assert_eq!(listener_learned, Some((dialer_id, vec![dialer_addr])));
+    }
+
+    #[test]
+    fn test_spr_for_other_peer_is_ignored() {
+        let keypair = Keypair::generate_secp256k1();
+        let mut behaviour =
+            IdentifyBehaviour::new(IdentifyConfig::new("Archivist Node".to_string(), &keypair));
+
+        let other = Keypair::generate_secp256k1();
+        let addr: Multiaddr = "/ip4/10.0.0.2/tcp/8070".parse().unwrap();
+        let spr = identify_spr::create_signed_peer_record(
+            &other,
+            other.public().to_peer_id(),
+            vec![addr.clone()],
+        )
+        .unwrap();
+
+        // Claimed by a different connection peer: dropped
+        behaviour.on_spr_received(PeerId::random(), &spr);
+        assert!(behaviour.pending_events.is_empty());
+
+        // Garbage: dropped
+        behaviour.on_spr_received(other.public().to_peer_id(), b"not an spr");
+        assert!(behaviour.pending_events.is_empty());
+
+        behaviour.on_spr_received(other.public().to_peer_id(), &spr);
+        assert!(matches!(
+            behaviour.pending_events.pop_front(),
+            Some(ToSwarm::NewExternalAddrOfPeer { address, .. }) if address == addr
+        ));
+        assert!(matches!(
+            behaviour.pending_events.pop_front(),
+            Some(ToSwarm::GenerateEvent(IdentifyShimEvent::ReceivedSpr(_, addrs))) if addrs == vec![addr]
+        ));
+    }
 }
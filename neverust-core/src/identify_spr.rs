@@ -79,7 +79,7 @@ struct Envelope {
 const PEER_RECORD_DOMAIN: &str = "libp2p-peer-record";
 
 /// Payload type multicodec for peer records
-const PEER_RECORD_PAYLOAD_TYPE: &[u8] = &[0x03, 0x01];
+pub(crate) const PEER_RECORD_PAYLOAD_TYPE: &[u8] = &[0x03, 0x01];
 
 /// Create a signed peer record envelope compatible with nim-libp2p v1.9.0
 ///
@@ -116,18 +116,7 @@ pub fn create_signed_peer_record(
         .map_err(|e| format!("Failed to encode PeerRecord: {}", e))?;
 
     // 3. Create signature buffer matching nim-libp2p's format
-    // Concatenate: domain_len + domain + payload_type_len + payload_type + payload_len + payload
-    let mut signature_buffer = Vec::new();
-
-    // Write lengths as unsigned varint (matching nim-libp2p's VBuffer)
-    write_varint(&mut signature_buffer, PEER_RECORD_DOMAIN.len() as u64);
-    signature_buffer.extend_from_slice(PEER_RECORD_DOMAIN.as_bytes());
-
-    write_varint(&mut signature_buffer, PEER_RECORD_PAYLOAD_TYPE.len() as u64);
-    signature_buffer.extend_from_slice(PEER_RECORD_PAYLOAD_TYPE);
-
-    write_varint(&mut signature_buffer, payload.len() as u64);
-    signature_buffer.extend_from_slice(&payload);
+    let signature_buffer = peer_record_signing_buffer(PEER_RECORD_PAYLOAD_TYPE, &payload);
 
     // 4. Sign the buffer
     let signature = keypair
@@ -154,6 +143,26 @@ pub fn create_signed_peer_record(
     Ok(envelope_bytes)
 }
 
+/// Build the bytes a peer record envelope's signature covers
+///
+/// Concatenates domain_len + domain + payload_type_len + payload_type +
+/// payload_len + payload, with lengths as unsigned varints (matching
+/// nim-libp2p's VBuffer).
+pub(crate) fn peer_record_signing_buffer(payload_type: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+
+    write_varint(&mut buffer, PEER_RECORD_DOMAIN.len() as u64);
+    buffer.extend_from_slice(PEER_RECORD_DOMAIN.as_bytes());
+
+    write_varint(&mut buffer, payload_type.len() as u64);
+    buffer.extend_from_slice(payload_type);
+
+    write_varint(&mut buffer, payload.len() as u64);
+    buffer.extend_from_slice(payload);
+
+    buffer
+}
+
 /// Encode public key in protobuf format matching nim-libp2p
 ///
 /// Protobuf definition:
@@ -2,42 +2,57 @@
 //!
 //! Core P2P networking and storage functionality for the Archivist node.
 
+pub mod advertiser;
 pub mod api;
 pub mod archivist_cluster;
 pub mod archivist_tree;
 pub mod blockexc;
 pub mod botg;
+pub mod car;
 pub mod chunker;
 pub mod cid_blake3;
 pub mod citadel;
 pub mod citadel_sync;
 pub mod cluster;
 pub mod config;
+pub mod content_router;
 pub mod dht_provider;
 pub mod folder_manifest;
 pub mod discovery;
+pub mod discovery_engine;
+pub mod erasure;
 pub mod eth_key;
+pub mod fetcher;
+#[cfg(fuzzing)]
+pub mod fuzz;
 pub mod identify_shim;
 pub mod identify_spr;
 pub mod manifest;
 pub mod marketplace;
 pub mod messages;
 pub mod metrics;
+pub mod offline;
 pub mod p2p;
 pub mod pending_blocks;
+pub mod prefetch;
 pub mod primitive_lab;
 pub mod primitive_pipeline;
+pub mod rate_limit;
+pub mod request_log;
 pub mod runtime;
 pub mod spr;
+pub mod startup;
 pub mod storage;
 pub mod traffic;
 
+pub use advertiser::{Advertiser, AdvertiserError};
 pub use archivist_cluster::{
     ArchivistCluster, ClusterError, ClusterMember, MemberBackend, PinOutcome,
 };
-pub use archivist_tree::{ArchivistProof, ArchivistTree, ProofNode};
+pub use archivist_tree::{verify_inclusion, ArchivistProof, ArchivistTree, ProofNode};
 pub use botg::{BlockId, BlockRollup, BoTgConfig, BoTgError, BoTgProtocol};
 pub use chunker::{Chunker, DEFAULT_BLOCK_SIZE};
+pub use cid::Cid;
 pub use cid_blake3::{blake3_cid, blake3_hash, verify_blake3, CidError, StreamingVerifier};
 pub use citadel::{
     fetch_flagship_trust_snapshot, run_defederation_simulation, DefederationGuardConfig,
@@ -46,11 +61,14 @@ pub use citadel::{
 };
 pub use cluster::{select_replicas, upload_path_for_cid_str, ClusterNode};
 pub use config::Config;
+pub use content_router::ContentRouter;
+pub use erasure::{ErasureEncoder, ErasureError, ErasureParams};
 pub use eth_key::{load_or_generate as load_or_generate_eth_key, EthKey, EthKeyError};
+pub use fetcher::{BlockFetcher, FetchError, FetchSource};
 pub use folder_manifest::{
     is_directory, DirectoryEntry, DirectoryManifest, DirectoryManifestError, DIRECTORY_CODEC,
 };
-pub use discovery::{Discovery, DiscoveryError, DiscoveryStats};
+pub use discovery::{Discovery, DiscoveryConfig, DiscoveryError, DiscoveryEvent, DiscoveryStats};
 pub use manifest::{
     ErasureInfo, Manifest, ManifestError, StrategyType, VerificationInfo, BLAKE3_CODEC,
     BLOCK_CODEC, MANIFEST_CODEC, SHA256_CODEC,
@@ -60,8 +78,11 @@ pub use marketplace::{
     PurchaseState, SaleAvailabilityInput, SaleAvailabilityRecord, SalesSlotRecord,
     SalesSlotResponse, SalesSlotState, StorageRequestInput,
 };
-pub use metrics::Metrics;
-pub use p2p::{create_swarm, Behaviour, P2PError};
-pub use runtime::run_node;
+pub use metrics::{Metrics, MetricsSnapshot};
+pub use p2p::{create_swarm, create_swarm_with_peers, Behaviour, P2PError};
+pub use prefetch::PrefetchEngine;
+pub use runtime::{run_node, run_node_with_handle, NodeHandle, RunHandle};
 pub use spr::{parse_spr_records, SprError};
-pub use storage::{Block, BlockStore, BlockStoreStats, StorageError};
+pub use storage::{
+    Block, BlockStore, BlockStoreStats, IntegrityReport, PutStats, StorageError, StorageEvent,
+};
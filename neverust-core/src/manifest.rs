@@ -9,7 +9,9 @@ use sha2::{Digest, Sha256};
 use std::io::Cursor;
 use thiserror::Error;
 
-use crate::storage::Block;
+use crate::archivist_tree::{ArchivistTree, ArchivistTreeError};
+use crate::cid_blake3::codec_name;
+use crate::storage::{Block, BlockStore};
 
 /// Archivist manifest codec (0xcd01)
 pub const MANIFEST_CODEC: u64 = 0xcd01;
@@ -26,6 +28,9 @@ pub const BLAKE3_CODEC: u64 = 0x1e;
 /// Default block size (64KB)
 pub const DEFAULT_BLOCK_SIZE: u64 = 64 * 1024;
 
+/// Default slot cell size for verifiable manifests (2KB)
+pub const DEFAULT_CELL_SIZE: u64 = 2048;
+
 #[derive(Debug, Error)]
 pub enum ManifestError {
     #[error("Protobuf encode error: {0}")]
@@ -42,6 +47,9 @@ pub enum ManifestError {
 
     #[error("Multihash error: {0}")]
     MultihashError(String),
+
+    #[error("Tree error: {0}")]
+    TreeError(#[from] ArchivistTreeError),
 }
 
 pub type Result<T> = std::result::Result<T, ManifestError>;
@@ -195,11 +203,184 @@ impl Manifest {
             .is_some()
     }
 
+    /// CID of the tree metadata block (the serialized block CID list)
+    ///
+    /// Stored in the filename field as `metadata:<cid>` by the upload path.
+    pub fn metadata_cid(&self) -> Option<Cid> {
+        self.filename
+            .as_deref()
+            .and_then(|s| s.strip_prefix("metadata:"))
+            .and_then(|s| s.parse().ok())
+    }
+
     /// Get number of blocks in the dataset
     pub fn blocks_count(&self) -> usize {
         ((self.dataset_size + self.block_size - 1) / self.block_size) as usize
     }
 
+    /// Number of blocks implied by the dataset and block sizes
+    ///
+    /// Same as [`Self::blocks_count`]: the division rounds up, since a final
+    /// partial block still takes a whole block in the tree.
+    pub fn blocks_count_from_size(&self) -> usize {
+        self.blocks_count()
+    }
+
+    /// Stream the dataset's block CIDs from the tree metadata block in `store`
+    ///
+    /// The metadata block is read on first poll; CIDs are then parsed one at
+    /// a time instead of being collected into a list.
+    pub fn blocks_iterator<'a>(
+        &'a self,
+        store: &'a BlockStore,
+    ) -> impl futures::Stream<Item = Result<Cid>> + 'a {
+        use futures::StreamExt;
+
+        futures::stream::once(async move {
+            let missing = || ManifestError::InvalidManifest("missing tree metadata".to_string());
+            let metadata_cid = self.metadata_cid().ok_or_else(missing)?;
+            let metadata = store.get(&metadata_cid).await.map_err(|e| {
+                if e.is_not_found() {
+                    missing()
+                } else {
+                    ManifestError::InvalidManifest(format!("failed to read tree metadata: {}", e))
+                }
+            })?;
+            Ok(ArchivistTree::block_list_iter(metadata.data)?)
+        })
+        .flat_map(|cids: Result<_>| {
+            let cids: Box<dyn Iterator<Item = Result<Cid>> + Send> = match cids {
+                Ok(cids) => Box::new(cids.map(|cid| cid.map_err(ManifestError::from))),
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            };
+            futures::stream::iter(cids)
+        })
+    }
+
+    /// Total size of the dataset's blocks in bytes
+    ///
+    /// Manifests don't compress data, so this is the same as `dataset_size`.
+    pub fn total_uncompressed_size(&self) -> u64 {
+        self.dataset_size
+    }
+
+    /// Estimated time to download the dataset at `bytes_per_second`
+    ///
+    /// Returns infinity when the rate is zero.
+    pub fn estimated_download_seconds(&self, bytes_per_second: u64) -> f64 {
+        if bytes_per_second == 0 {
+            return f64::INFINITY;
+        }
+        self.total_uncompressed_size() as f64 / bytes_per_second as f64
+    }
+
+    /// Size of the original content, before erasure coding
+    pub fn original_dataset_size(&self) -> u64 {
+        self.erasure
+            .as_ref()
+            .map_or(self.dataset_size, |e| e.original_dataset_size)
+    }
+
+    /// Number of blocks holding original content
+    ///
+    /// For protected manifests these are the leading blocks of the tree; the
+    /// parity blocks follow them.
+    pub fn original_blocks_count(&self) -> usize {
+        self.original_dataset_size().div_ceil(self.block_size) as usize
+    }
+
+    /// Compute the slot roots of a dataset
+    ///
+    /// Partitions `block_cids` into `ec_m` slots according to `strategy`
+    /// (linear: contiguous runs of `ceil(n / ec_m)` blocks; stepped: every
+    /// `ec_m`-th block starting at the slot index) and returns the root CID of
+    /// an `ArchivistTree` built over each slot's blocks.
+    pub fn compute_slot_roots(
+        block_cids: &[Cid],
+        ec_m: usize,
+        strategy: StrategyType,
+        cell_size: u64,
+    ) -> Result<Vec<Cid>> {
+        if ec_m == 0 || cell_size == 0 {
+            return Err(ManifestError::InvalidManifest(format!(
+                "ec_m ({}) and cell_size ({}) must both be positive",
+                ec_m, cell_size
+            )));
+        }
+        if block_cids.len() < ec_m {
+            return Err(ManifestError::InvalidManifest(format!(
+                "{} blocks cannot fill {} slots",
+                block_cids.len(),
+                ec_m
+            )));
+        }
+
+        let slots: Vec<Vec<Cid>> = match strategy {
+            StrategyType::LinearStrategy => {
+                let per_slot = block_cids.len().div_ceil(ec_m);
+                let mut slots: Vec<Vec<Cid>> = block_cids
+                    .chunks(per_slot)
+                    .map(|slot| slot.to_vec())
+                    .collect();
+                // Short datasets can leave trailing slots without blocks
+                slots.resize(ec_m, Vec::new());
+                slots
+            }
+            StrategyType::SteppedStrategy => (0..ec_m)
+                .map(|slot| {
+                    block_cids
+                        .iter()
+                        .skip(slot)
+                        .step_by(ec_m)
+                        .copied()
+                        .collect()
+                })
+                .collect(),
+        };
+
+        slots
+            .into_iter()
+            .enumerate()
+            .map(|(index, slot)| {
+                if slot.is_empty() {
+                    return Err(ManifestError::InvalidManifest(format!(
+                        "slot {} has no blocks",
+                        index
+                    )));
+                }
+                Ok(ArchivistTree::new(slot)?.root_cid()?)
+            })
+            .collect()
+    }
+
+    /// Make a protected manifest verifiable
+    ///
+    /// Computes the slot roots of `block_cids` with the manifest's `ec_m` and
+    /// protected strategy, and the verify root over those slot roots.
+    pub fn with_verification(mut self, block_cids: &[Cid]) -> Result<Self> {
+        let erasure = self.erasure.as_mut().ok_or_else(|| {
+            ManifestError::InvalidManifest(
+                "only protected manifests can be made verifiable".to_string(),
+            )
+        })?;
+
+        let slot_roots = Self::compute_slot_roots(
+            block_cids,
+            erasure.ec_m as usize,
+            erasure.protected_strategy,
+            DEFAULT_CELL_SIZE,
+        )?;
+        let verify_root = ArchivistTree::new(slot_roots.clone())?.root_cid()?;
+
+        erasure.verification = Some(VerificationInfo {
+            verify_root,
+            slot_roots,
+            cell_size: DEFAULT_CELL_SIZE,
+            verifiable_strategy: erasure.protected_strategy,
+        });
+        Ok(self)
+    }
+
     /// Encode the manifest to protobuf bytes
     ///
     /// Follows the exact protobuf structure used by Archivist:
@@ -353,25 +534,50 @@ impl Manifest {
         })
     }
 
+    /// Serialize this manifest without wrapping it in a [`Block`]
+    ///
+    /// Same bytes as [`Manifest::encode`] and the data of
+    /// [`Manifest::to_block`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        self.encode()
+    }
+
+    /// Deserialize a manifest from bytes produced by [`Manifest::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::decode(bytes)
+    }
+
+    /// Compute the CID [`Manifest::to_block`] would give this manifest
+    pub fn cid(&self) -> Result<Cid> {
+        self.cid_for(&self.encode()?)
+    }
+
     /// Create a Block from this manifest
     ///
     /// The block will have codec 0xcd01 (ManifestCodec)
     pub fn to_block(&self) -> Result<Block> {
         let data = self.encode()?;
+        let cid = self.cid_for(&data)?;
 
+        Ok(Block { cid, data })
+    }
+
+    /// CID of the encoded manifest `data`, hashed with `self.hcodec`
+    fn cid_for(&self, data: &[u8]) -> Result<Cid> {
         // Create CID with manifest codec:
         // CID = <version><codec><multihash>
         // Use the manifest hash codec for compatibility with peer implementations.
         let hash_bytes = match self.hcodec {
-            BLAKE3_CODEC => blake3::hash(&data).as_bytes().to_vec(),
+            BLAKE3_CODEC => blake3::hash(data).as_bytes().to_vec(),
             SHA256_CODEC => {
                 let mut hasher = Sha256::new();
-                hasher.update(&data);
+                hasher.update(data);
                 hasher.finalize().to_vec()
             }
             codec => {
                 return Err(ManifestError::InvalidManifest(format!(
-                    "Unsupported manifest hash codec: 0x{:x}",
+                    "Unsupported manifest hash codec: {} (0x{:x})",
+                    codec_name(codec),
                     codec
                 )))
             }
@@ -400,10 +606,8 @@ impl Manifest {
         // Multihash
         cid_bytes.extend_from_slice(&multihash);
 
-        let cid = Cid::try_from(cid_bytes)
-            .map_err(|e| ManifestError::CidError(format!("Failed to create CID: {}", e)))?;
-
-        Ok(Block { cid, data })
+        Cid::try_from(cid_bytes)
+            .map_err(|e| ManifestError::CidError(format!("Failed to create CID: {}", e)))
     }
 
     /// Create a manifest from a Block
@@ -412,8 +616,10 @@ impl Manifest {
         let codec = block.cid.codec();
         if codec != MANIFEST_CODEC {
             return Err(ManifestError::InvalidManifest(format!(
-                "Block has codec 0x{:x}, expected manifest codec 0x{:x}",
-                codec, MANIFEST_CODEC
+                "Block has codec {} (0x{:x}), expected manifest codec {}",
+                codec_name(codec),
+                codec,
+                codec_name(MANIFEST_CODEC)
             )));
         }
 
@@ -558,6 +764,25 @@ mod tests {
         assert!(!manifest.is_verifiable());
     }
 
+    #[test]
+    fn test_download_estimate() {
+        let manifest = Manifest::new(
+            create_test_cid(b"estimate tree"),
+            DEFAULT_BLOCK_SIZE,
+            10 * 1024 * 1024,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(manifest.total_uncompressed_size(), 10 * 1024 * 1024);
+        assert_eq!(manifest.estimated_download_seconds(1024 * 1024), 10.0);
+        assert_eq!(manifest.estimated_download_seconds(4 * 1024 * 1024), 2.5);
+        assert!(manifest.estimated_download_seconds(0).is_infinite());
+    }
+
     #[test]
     fn test_manifest_encode_decode_roundtrip() {
         let tree_cid = create_test_cid(b"test tree");
@@ -679,6 +904,36 @@ mod tests {
         assert_ne!(block1.cid, block3.cid);
     }
 
+    #[test]
+    fn test_manifest_cid_matches_block_cid() {
+        let mut manifest = Manifest::new(
+            create_test_cid(b"test tree"),
+            DEFAULT_BLOCK_SIZE,
+            4096,
+            None,
+            None,
+            None,
+            Some("test.bin".to_string()),
+            Some("application/octet-stream".to_string()),
+        );
+
+        for hcodec in [SHA256_CODEC, BLAKE3_CODEC] {
+            manifest.hcodec = hcodec;
+            let block = manifest.to_block().unwrap();
+            assert_eq!(manifest.cid().unwrap(), block.cid);
+
+            let bytes = manifest.to_bytes().unwrap();
+            assert_eq!(bytes, block.data);
+            assert_eq!(Manifest::from_bytes(&bytes).unwrap(), manifest);
+        }
+
+        manifest.hcodec = 0x12345;
+        assert!(matches!(
+            manifest.cid(),
+            Err(ManifestError::InvalidManifest(_))
+        ));
+    }
+
     #[test]
     fn test_manifest_blocks_count() {
         let tree_cid = create_test_cid(b"test tree");
@@ -694,6 +949,49 @@ mod tests {
         // 10 blocks
         let manifest = Manifest::new(tree_cid, 1024, 10240, None, None, None, None, None);
         assert_eq!(manifest.blocks_count(), 10);
+        assert_eq!(manifest.blocks_count_from_size(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_iterator_matches_block_list() {
+        use futures::{StreamExt, TryStreamExt};
+
+        let store = BlockStore::new();
+        let block_cids: Vec<Cid> = (0..50u32)
+            .map(|i| create_test_cid(&i.to_le_bytes()))
+            .collect();
+        let tree = ArchivistTree::new(block_cids).unwrap();
+        let metadata = Block::new(tree.serialize_block_list()).unwrap();
+        store.put(metadata.clone()).await.unwrap();
+
+        let manifest = Manifest::new(
+            tree.root_cid().unwrap(),
+            1024,
+            50 * 1024,
+            None,
+            None,
+            None,
+            Some(format!("metadata:{}", metadata.cid)),
+            None,
+        );
+        let streamed: Vec<Cid> = manifest
+            .blocks_iterator(&store)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            streamed,
+            ArchivistTree::deserialize_block_list(&metadata.data).unwrap()
+        );
+
+        // Without the metadata block the stream yields a single error
+        store.delete(&metadata.cid).await.unwrap();
+        let results: Vec<_> = manifest.blocks_iterator(&store).collect().await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            &results[0],
+            Err(ManifestError::InvalidManifest(msg)) if msg == "missing tree metadata"
+        ));
     }
 
     #[test]
@@ -726,6 +1024,10 @@ mod tests {
         assert_eq!(erasure.original_tree_cid, original_tree_cid);
         assert_eq!(erasure.original_dataset_size, 800 * 1024);
         assert_eq!(erasure.protected_strategy, StrategyType::SteppedStrategy);
+
+        assert_eq!(manifest.blocks_count(), 16);
+        assert_eq!(manifest.original_dataset_size(), 800 * 1024);
+        assert_eq!(manifest.original_blocks_count(), 13);
     }
 
     #[test]
@@ -837,6 +1139,111 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compute_slot_roots_linear() {
+        let block_cids: Vec<Cid> = (0..6u8).map(|i| create_test_cid(&[i])).collect();
+
+        let roots = Manifest::compute_slot_roots(
+            &block_cids,
+            3,
+            StrategyType::LinearStrategy,
+            DEFAULT_CELL_SIZE,
+        )
+        .unwrap();
+
+        // Slot 0 => [0, 1], slot 1 => [2, 3], slot 2 => [4, 5]
+        let expected: Vec<Cid> = block_cids
+            .chunks(2)
+            .map(|slot| {
+                ArchivistTree::new(slot.to_vec())
+                    .unwrap()
+                    .root_cid()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(roots, expected);
+    }
+
+    #[test]
+    fn test_compute_slot_roots_stepped() {
+        let block_cids: Vec<Cid> = (0..7u8).map(|i| create_test_cid(&[i])).collect();
+
+        let roots = Manifest::compute_slot_roots(
+            &block_cids,
+            3,
+            StrategyType::SteppedStrategy,
+            DEFAULT_CELL_SIZE,
+        )
+        .unwrap();
+
+        // Slot 0 => [0, 3, 6], slot 1 => [1, 4], slot 2 => [2, 5]
+        let slot_root = |indices: &[usize]| {
+            let slot = indices.iter().map(|&i| block_cids[i]).collect();
+            ArchivistTree::new(slot).unwrap().root_cid().unwrap()
+        };
+        assert_eq!(
+            roots,
+            vec![
+                slot_root(&[0, 3, 6]),
+                slot_root(&[1, 4]),
+                slot_root(&[2, 5])
+            ]
+        );
+
+        assert!(Manifest::compute_slot_roots(
+            &block_cids[..2],
+            3,
+            StrategyType::SteppedStrategy,
+            DEFAULT_CELL_SIZE
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_manifest_with_verification() {
+        let block_cids: Vec<Cid> = (0..6u8).map(|i| create_test_cid(&[i])).collect();
+        let manifest = Manifest::new_protected(
+            create_test_cid(b"test tree"),
+            DEFAULT_BLOCK_SIZE,
+            6 * DEFAULT_BLOCK_SIZE,
+            BLOCK_CODEC,
+            SHA256_CODEC,
+            1,
+            4,
+            2,
+            create_test_cid(b"original tree"),
+            4 * DEFAULT_BLOCK_SIZE,
+            StrategyType::SteppedStrategy,
+            None,
+            None,
+        );
+
+        let manifest = manifest.with_verification(&block_cids).unwrap();
+        assert!(manifest.is_verifiable());
+        let verification = manifest
+            .erasure
+            .as_ref()
+            .unwrap()
+            .verification
+            .clone()
+            .unwrap();
+        assert_eq!(verification.slot_roots.len(), 2);
+        assert_eq!(
+            verification.verify_root,
+            ArchivistTree::new(verification.slot_roots.clone())
+                .unwrap()
+                .root_cid()
+                .unwrap()
+        );
+        assert_eq!(
+            Manifest::decode(&manifest.encode().unwrap()).unwrap(),
+            manifest
+        );
+
+        let unprotected = Manifest::new(block_cids[0], 1024, 1024, None, None, None, None, None);
+        assert!(unprotected.with_verification(&block_cids).is_err());
+    }
+
     #[test]
     fn test_manifest_from_block_wrong_codec() {
         // Create a block with wrong codec
@@ -4,6 +4,40 @@
 //! Using prost derive macros for encoding/decoding
 
 use prost::Message as ProstMessage;
+use thiserror::Error;
+
+/// Protobuf field number of [`Message::integrity`]
+const INTEGRITY_TAG: u32 = 8;
+
+/// Length of the integrity check value in bytes
+pub const INTEGRITY_LEN: usize = 4;
+
+/// Longest CID accepted as a [`CompressedWantlist`] key
+///
+/// Every expanded entry copies its key, so this bounds the memory a
+/// compressed wantlist can expand to.
+pub const MAX_COMPRESSED_KEY_BYTES: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum MessageError {
+    #[error("Failed to decode message: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("Failed to encode message: {0}")]
+    Encode(#[from] prost::EncodeError),
+
+    #[error("Message too large: {size} bytes of block data, max {max}")]
+    TooLarge { size: usize, max: usize },
+
+    #[error("Message integrity check failed: expected {expected:02x?}, computed {actual:02x?}")]
+    IntegrityFailed { expected: Vec<u8>, actual: Vec<u8> },
+
+    #[error("Compressed wantlist has {entries} entries, max {max}")]
+    WantlistTooLarge { entries: usize, max: usize },
+
+    #[error("Compressed wantlist key is {len} bytes, max {max}")]
+    KeyTooLong { len: usize, max: usize },
+}
 
 #[derive(Clone, PartialEq, prost::Message)]
 pub struct Message {
@@ -24,6 +58,18 @@ pub struct Message {
 
     #[prost(message, optional, tag = "7")]
     pub payment: Option<StateChannelUpdate>,
+
+    /// First 4 bytes of the BLAKE3 hash of the message encoded without this
+    /// field; set by [`encode_message`] and checked by [`decode_message`]
+    #[prost(bytes = "vec", optional, tag = "8")]
+    pub integrity: Option<Vec<u8>>,
+
+    /// Trie-compressed wants, read alongside [`Message::wantlist`]
+    ///
+    /// Neverust extension: Archivist nodes skip the unknown field, so only
+    /// send it to peers known to understand it.
+    #[prost(message, optional, tag = "9")]
+    pub compressed_wantlist: Option<CompressedWantlist>,
 }
 
 #[derive(Clone, PartialEq, prost::Message)]
@@ -35,6 +81,270 @@ pub struct Wantlist {
     pub full: bool,
 }
 
+impl Wantlist {
+    /// A full wantlist cancelling every block in `cids`
+    ///
+    /// Lets a peer drop all of our wants at once, e.g. when a session ends
+    /// or the connection is closing.
+    pub fn cancel_all(cids: Vec<Vec<u8>>) -> Self {
+        Self {
+            entries: cids.into_iter().map(WantlistEntry::cancel_cid).collect(),
+            full: true,
+        }
+    }
+
+    /// Whether every entry cancels a want (true for an empty wantlist)
+    pub fn is_cancel_only(&self) -> bool {
+        self.entries.iter().all(|entry| entry.cancel)
+    }
+
+    /// Compress `entries` into a prefix tree over their CID bytes
+    ///
+    /// Blocks of one manifest share their CID prefix (and tree leaves their
+    /// whole tree CID), so each shared prefix is stored once instead of once
+    /// per entry.
+    pub fn compress_entries(entries: Vec<WantlistEntry>) -> CompressedWantlist {
+        let mut keyed = Vec::with_capacity(entries.len());
+        let mut unaddressed = Vec::new();
+        for entry in entries {
+            match entry.address {
+                Some(ref address) => {
+                    let key = address.cid_bytes().to_vec();
+                    keyed.push((key, CompressedEntry::from_entry(&entry, address)));
+                }
+                None => unaddressed.push(entry),
+            }
+        }
+        // Stable, so entries for the same CID keep their relative order
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        CompressedWantlist {
+            root: (!keyed.is_empty()).then(|| TrieNode::build(&keyed, 0)),
+            unaddressed,
+            full: false,
+        }
+    }
+}
+
+/// A wantlist stored as a radix trie over the entries' CID bytes
+///
+/// Built by [`Wantlist::compress_entries`]. Decompressing yields the entries
+/// ordered by CID bytes, followed by any entries without an address.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompressedWantlist {
+    #[prost(message, optional, tag = "1")]
+    pub root: Option<TrieNode>,
+
+    /// Entries without an address, which have no key in the trie
+    #[prost(message, repeated, tag = "2")]
+    pub unaddressed: Vec<WantlistEntry>,
+
+    #[prost(bool, tag = "3")]
+    pub full: bool,
+}
+
+impl CompressedWantlist {
+    /// Expand back into a flat [`Wantlist`]
+    pub fn to_wantlist(&self) -> Wantlist {
+        let mut entries = Vec::with_capacity(self.len());
+        if let Some(root) = &self.root {
+            root.collect(&mut Vec::new(), &mut entries);
+        }
+        entries.extend(self.unaddressed.iter().cloned());
+        Wantlist {
+            entries,
+            full: self.full,
+        }
+    }
+
+    /// Number of entries in the compressed wantlist
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, TrieNode::len) + self.unaddressed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Length of the longest key in the trie
+    pub fn max_key_len(&self) -> usize {
+        self.root.as_ref().map_or(0, TrieNode::max_key_len)
+    }
+}
+
+/// A node of a [`CompressedWantlist`]
+///
+/// A node's key is its parent's key followed by `label`; `entries` are the
+/// wants whose CID is exactly that key.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TrieNode {
+    #[prost(bytes = "vec", tag = "1")]
+    pub label: Vec<u8>,
+
+    #[prost(message, repeated, tag = "2")]
+    pub entries: Vec<CompressedEntry>,
+
+    #[prost(message, repeated, tag = "3")]
+    pub children: Vec<TrieNode>,
+}
+
+impl TrieNode {
+    /// Build the node for `items`, which are sorted and share `key[..depth]`
+    fn build(items: &[(Vec<u8>, CompressedEntry)], depth: usize) -> Self {
+        let first = &items[0].0;
+        let last = &items[items.len() - 1].0;
+        let end = depth
+            + first[depth..]
+                .iter()
+                .zip(&last[depth..])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+        // Keys ending at this node sort before the longer ones
+        let split = items.partition_point(|(key, _)| key.len() == end);
+        let entries = items[..split]
+            .iter()
+            .map(|(_, entry)| entry.clone())
+            .collect();
+
+        let mut children = Vec::new();
+        let mut rest = &items[split..];
+        while let Some((key, _)) = rest.first() {
+            let byte = key[end];
+            let group = rest.partition_point(|(key, _)| key[end] == byte);
+            children.push(Self::build(&rest[..group], end));
+            rest = &rest[group..];
+        }
+
+        Self {
+            label: first[depth..end].to_vec(),
+            entries,
+            children,
+        }
+    }
+
+    fn collect(&self, prefix: &mut Vec<u8>, out: &mut Vec<WantlistEntry>) {
+        let parent_len = prefix.len();
+        prefix.extend_from_slice(&self.label);
+        out.extend(self.entries.iter().map(|entry| entry.to_entry(prefix)));
+        for child in &self.children {
+            child.collect(prefix, out);
+        }
+        prefix.truncate(parent_len);
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len() + self.children.iter().map(Self::len).sum::<usize>()
+    }
+
+    fn max_key_len(&self) -> usize {
+        self.label.len()
+            + self
+                .children
+                .iter()
+                .map(Self::max_key_len)
+                .max()
+                .unwrap_or(0)
+    }
+}
+
+/// A [`WantlistEntry`] whose CID is given by its position in the trie
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompressedEntry {
+    /// Whether the trie key is a tree CID (see [`BlockAddress::leaf`])
+    #[prost(bool, tag = "1")]
+    pub leaf: bool,
+
+    #[prost(uint64, tag = "2")]
+    pub index: u64,
+
+    #[prost(int32, tag = "3")]
+    pub priority: i32,
+
+    #[prost(bool, tag = "4")]
+    pub cancel: bool,
+
+    #[prost(enumeration = "WantType", tag = "5")]
+    pub want_type: i32,
+
+    #[prost(bool, tag = "6")]
+    pub send_dont_have: bool,
+}
+
+impl CompressedEntry {
+    fn from_entry(entry: &WantlistEntry, address: &BlockAddress) -> Self {
+        Self {
+            leaf: address.leaf,
+            index: address.index,
+            priority: entry.priority,
+            cancel: entry.cancel,
+            want_type: entry.want_type,
+            send_dont_have: entry.send_dont_have,
+        }
+    }
+
+    fn to_entry(&self, cid: &[u8]) -> WantlistEntry {
+        let address = if self.leaf {
+            BlockAddress::from_tree_leaf(cid.to_vec(), self.index)
+        } else {
+            BlockAddress::from_cid(cid.to_vec())
+        };
+        WantlistEntry {
+            address: Some(address),
+            priority: self.priority,
+            cancel: self.cancel,
+            want_type: self.want_type,
+            send_dont_have: self.send_dont_have,
+        }
+    }
+}
+
+impl Message {
+    /// A message carrying only [`Wantlist::cancel_all`] for `cids`
+    pub fn cancel_all(cids: Vec<Vec<u8>>) -> Self {
+        Self {
+            wantlist: Some(Wantlist::cancel_all(cids)),
+            ..Default::default()
+        }
+    }
+
+    /// Merge [`Message::compressed_wantlist`] into [`Message::wantlist`]
+    ///
+    /// Leaves a single flat wantlist for handlers to read; the result is
+    /// full if either part was. Compressed wantlists with more than
+    /// `max_entries` entries or keys longer than [`MAX_COMPRESSED_KEY_BYTES`]
+    /// are rejected before anything is expanded.
+    pub fn expand_wantlist(&mut self, max_entries: usize) -> Result<(), MessageError> {
+        let Some(compressed) = self.compressed_wantlist.take() else {
+            return Ok(());
+        };
+        let entries = compressed.len();
+        if entries > max_entries {
+            return Err(MessageError::WantlistTooLarge {
+                entries,
+                max: max_entries,
+            });
+        }
+        let len = compressed.max_key_len();
+        if len > MAX_COMPRESSED_KEY_BYTES {
+            return Err(MessageError::KeyTooLong {
+                len,
+                max: MAX_COMPRESSED_KEY_BYTES,
+            });
+        }
+
+        let expanded = compressed.to_wantlist();
+        match &mut self.wantlist {
+            Some(wantlist) => {
+                wantlist.entries.extend(expanded.entries);
+                wantlist.full |= expanded.full;
+            }
+            None => self.wantlist = Some(expanded),
+        }
+        Ok(())
+    }
+}
+
 /// BlockAddress represents a location in the content-addressed storage system.
 /// It can reference either:
 /// - A simple block by CID (leaf=false, cid set)
@@ -152,6 +462,33 @@ impl WantlistEntry {
         Self::from_cid(cid.to_bytes(), want_type)
     }
 
+    /// Create a WantBlock entry for a manifest block
+    ///
+    /// Manifests are addressed by their own CID (`leaf = false`) and ask for
+    /// a DontHave presence so a missing manifest is reported promptly.
+    pub fn from_manifest_cid(cid_bytes: Vec<u8>) -> Self {
+        Self {
+            address: Some(BlockAddress::from_cid(cid_bytes)),
+            priority: 1,
+            cancel: false,
+            want_type: WantType::WantBlock as i32,
+            send_dont_have: true,
+        }
+    }
+
+    /// Create a WantBlock entry for a data block (`leaf = true`) or a
+    /// manifest (`leaf = false`) requested by CID
+    ///
+    /// Both kinds are addressed by CID; use [`WantlistEntry::from_tree_leaf`]
+    /// to request a block by its position in a tree.
+    pub fn from_cid_typed(cid_bytes: Vec<u8>, leaf: bool) -> Self {
+        if leaf {
+            Self::from_cid(cid_bytes, WantType::WantBlock)
+        } else {
+            Self::from_manifest_cid(cid_bytes)
+        }
+    }
+
     /// Create a WantlistEntry for a Merkle tree leaf
     pub fn from_tree_leaf(tree_cid: Vec<u8>, index: u64, want_type: WantType) -> Self {
         Self {
@@ -238,6 +575,13 @@ impl BlockDelivery {
         }
     }
 
+    /// Create a BlockDelivery from a stored block
+    ///
+    /// The CID comes from the block itself, so it is always well-formed.
+    pub fn from_verified_block(block: &crate::storage::Block) -> Self {
+        Self::from_cid_and_data(block.cid.to_bytes(), block.data.clone())
+    }
+
     /// Create a BlockDelivery for a Merkle tree leaf with proof
     pub fn from_tree_leaf(
         cid: Vec<u8>,
@@ -277,6 +621,16 @@ impl BlockPresence {
         }
     }
 
+    /// Create a BlockPresence from a CID struct, so the address always holds
+    /// a valid CID
+    pub fn from_valid_cid(
+        cid: &cid::Cid,
+        presence_type: BlockPresenceType,
+        price: Vec<u8>,
+    ) -> Self {
+        Self::from_cid(cid.to_bytes(), presence_type, price)
+    }
+
     /// Get the CID bytes from this presence notification
     pub fn cid_bytes(&self) -> Option<&[u8]> {
         self.address.as_ref().map(|addr| addr.cid_bytes())
@@ -302,21 +656,93 @@ pub struct StateChannelUpdate {
     pub update: Vec<u8>,
 }
 
+/// Compute the integrity check value of an encoded message
+pub fn integrity_check(payload: &[u8]) -> [u8; INTEGRITY_LEN] {
+    let mut check = [0u8; INTEGRITY_LEN];
+    check.copy_from_slice(&blake3::hash(payload).as_bytes()[..INTEGRITY_LEN]);
+    check
+}
+
 /// Encode a BlockExc message to bytes
+///
+/// The integrity field is (re)computed over the rest of the message and
+/// appended as the last field.
 pub fn encode_message(msg: &Message) -> Result<Vec<u8>, prost::EncodeError> {
-    let mut buf = Vec::new();
-    msg.encode(&mut buf)?;
+    let mut buf = Vec::with_capacity(msg.encoded_len() + INTEGRITY_LEN + 2);
+    if msg.integrity.is_some() {
+        let unsealed = Message {
+            integrity: None,
+            ..msg.clone()
+        };
+        unsealed.encode(&mut buf)?;
+    } else {
+        msg.encode(&mut buf)?;
+    }
+
+    let check = integrity_check(&buf).to_vec();
+    prost::encoding::bytes::encode(INTEGRITY_TAG, &check, &mut buf);
     Ok(buf)
 }
 
+/// Encode a BlockExc message, refusing one the receiver would reject
+///
+/// The size is estimated from the block data in the payload, so an
+/// oversized message is caught before it is encoded.
+pub fn encode_message_checked(msg: &Message, max_bytes: usize) -> Result<Vec<u8>, MessageError> {
+    let size: usize = msg.payload.iter().map(|block| block.data.len()).sum();
+    if size > max_bytes {
+        return Err(MessageError::TooLarge {
+            size,
+            max: max_bytes,
+        });
+    }
+    Ok(encode_message(msg)?)
+}
+
+/// Bytes of an encoded message with its integrity field left out
+///
+/// Fields are copied as received, so unknown fields stay covered by the
+/// check.
+fn without_integrity(bytes: &[u8]) -> Result<Vec<u8>, prost::DecodeError> {
+    let mut rest = bytes;
+    let mut out = Vec::with_capacity(bytes.len());
+    while !rest.is_empty() {
+        let field = rest;
+        let (tag, wire_type) = prost::encoding::decode_key(&mut rest)?;
+        prost::encoding::skip_field(
+            wire_type,
+            tag,
+            &mut rest,
+            prost::encoding::DecodeContext::default(),
+        )?;
+        if tag != INTEGRITY_TAG {
+            out.extend_from_slice(&field[..field.len() - rest.len()]);
+        }
+    }
+    Ok(out)
+}
+
 /// Decode a BlockExc message from bytes
-pub fn decode_message(bytes: &[u8]) -> Result<Message, prost::DecodeError> {
-    Message::decode(bytes)
+///
+/// Messages without an integrity field (e.g. from Archivist nodes) are
+/// accepted as is. When present it is verified against the received bytes
+/// and then cleared, so the result compares equal to the message that was
+/// encoded.
+pub fn decode_message(bytes: &[u8]) -> Result<Message, MessageError> {
+    let mut msg = Message::decode(bytes)?;
+    if let Some(expected) = msg.integrity.take() {
+        let actual = integrity_check(&without_integrity(bytes)?).to_vec();
+        if expected != actual {
+            return Err(MessageError::IntegrityFailed { expected, actual });
+        }
+    }
+    Ok(msg)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_encode_decode_empty_message() {
@@ -327,6 +753,8 @@ mod tests {
             pending_bytes: 0,
             account: None,
             payment: None,
+            integrity: None,
+            compressed_wantlist: None,
         };
 
         let encoded = encode_message(&msg).unwrap();
@@ -353,6 +781,8 @@ mod tests {
             pending_bytes: 0,
             account: None,
             payment: None,
+            integrity: None,
+            compressed_wantlist: None,
         };
 
         let encoded = encode_message(&msg).unwrap();
@@ -380,6 +810,8 @@ mod tests {
             pending_bytes: 0,
             account: None,
             payment: None,
+            integrity: None,
+            compressed_wantlist: None,
         };
 
         let encoded = encode_message(&msg).unwrap();
@@ -405,6 +837,8 @@ mod tests {
             pending_bytes: 0,
             account: None,
             payment: None,
+            integrity: None,
+            compressed_wantlist: None,
         };
 
         let encoded = encode_message(&msg).unwrap();
@@ -460,6 +894,8 @@ mod tests {
             payment: Some(StateChannelUpdate {
                 update: b"signed_nitro_state_json".to_vec(),
             }),
+            integrity: None,
+            compressed_wantlist: None,
         };
 
         let encoded = encode_message(&msg).unwrap();
@@ -542,6 +978,36 @@ mod tests {
         assert_eq!(entry.priority, 0);
     }
 
+    #[test]
+    fn test_cancel_all_message() {
+        let cids = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        let msg = Message::cancel_all(cids.clone());
+
+        let wantlist = msg.wantlist.as_ref().unwrap();
+        assert!(wantlist.full);
+        assert!(wantlist.is_cancel_only());
+        assert_eq!(wantlist.entries.len(), 2);
+        for (entry, cid) in wantlist.entries.iter().zip(&cids) {
+            assert!(entry.cancel);
+            assert_eq!(entry.cid_bytes().unwrap(), &cid[..]);
+        }
+        assert!(msg.payload.is_empty());
+        assert!(msg.block_presences.is_empty());
+        assert_eq!(msg.pending_bytes, 0);
+        assert_eq!(msg.integrity, None);
+
+        // Survives the wire format
+        let decoded = decode_message(&encode_message(&msg).unwrap()).unwrap();
+        assert!(decoded.wantlist.unwrap().is_cancel_only());
+
+        let mut mixed = Wantlist::cancel_all(cids);
+        assert!(mixed.is_cancel_only());
+        mixed
+            .entries
+            .push(WantlistEntry::from_cid(vec![7], WantType::WantBlock));
+        assert!(!mixed.is_cancel_only());
+    }
+
     #[test]
     fn test_block_delivery_simple_cid() {
         // Test BlockDelivery with simple CID
@@ -597,4 +1063,391 @@ mod tests {
         assert_eq!(returned_proof.mcodec, 0x12);
         assert_eq!(returned_proof.nleaves, 100);
     }
+
+    #[test]
+    fn test_integrity_check_detects_tampering() {
+        let msg = Message {
+            payload: vec![BlockDelivery::from_cid_and_data(
+                vec![1, 2, 3],
+                b"block data".to_vec(),
+            )],
+            ..Default::default()
+        };
+
+        let encoded = encode_message(&msg).unwrap();
+        let sealed = Message::decode(encoded.as_slice()).unwrap();
+        assert_eq!(sealed.integrity.as_ref().map(Vec::len), Some(INTEGRITY_LEN));
+
+        // Flip a byte inside the block data
+        let mut tampered = encoded.clone();
+        let pos = tampered
+            .windows(b"block data".len())
+            .position(|w| w == b"block data")
+            .unwrap();
+        tampered[pos] ^= 0xff;
+        assert!(matches!(
+            decode_message(&tampered),
+            Err(MessageError::IntegrityFailed { .. })
+        ));
+
+        // Messages without an integrity field are accepted
+        let unsealed = msg.encode_to_vec();
+        assert_eq!(decode_message(&unsealed).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_integrity_check_covers_unknown_fields() {
+        let msg = Message {
+            pending_bytes: 42,
+            ..Default::default()
+        };
+
+        // A newer sender adds a field this decoder doesn't know about
+        let mut encoded = msg.encode_to_vec();
+        prost::encoding::uint64::encode(15, &7, &mut encoded);
+        let check = integrity_check(&encoded).to_vec();
+        let mut sealed = encoded.clone();
+        prost::encoding::bytes::encode(INTEGRITY_TAG, &check, &mut sealed);
+        assert_eq!(decode_message(&sealed).unwrap(), msg);
+
+        // Tampering with the unknown field is still caught
+        let mut tampered = encoded;
+        *tampered.last_mut().unwrap() = 8;
+        prost::encoding::bytes::encode(INTEGRITY_TAG, &check, &mut tampered);
+        assert!(matches!(
+            decode_message(&tampered),
+            Err(MessageError::IntegrityFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encode_checked_rejects_oversized_payload() {
+        let msg = Message {
+            payload: vec![BlockDelivery::from_cid_and_data(
+                vec![0x12, 0x20, 1, 2, 3],
+                vec![0u8; 200],
+            )],
+            ..Default::default()
+        };
+
+        let encoded = encode_message_checked(&msg, 500).unwrap();
+        assert_eq!(encoded, encode_message(&msg).unwrap());
+        assert!(matches!(
+            encode_message_checked(&msg, 100),
+            Err(MessageError::TooLarge {
+                size: 200,
+                max: 100
+            })
+        ));
+    }
+
+    #[test]
+    fn test_wantlist_entry_from_manifest_cid() {
+        let cid = vec![0x01, 0xcd, 0x01, 0x42];
+        let entry = WantlistEntry::from_manifest_cid(cid.clone());
+
+        let addr = entry.address.as_ref().unwrap();
+        assert!(!addr.leaf);
+        assert_eq!(addr.cid, cid);
+        assert_eq!(entry.priority, 1);
+        assert!(!entry.cancel);
+        assert_eq!(entry.want_type, WantType::WantBlock as i32);
+        assert!(entry.send_dont_have);
+
+        assert_eq!(WantlistEntry::from_cid_typed(cid.clone(), false), entry);
+        assert_eq!(
+            WantlistEntry::from_cid_typed(cid.clone(), true),
+            WantlistEntry::from_cid(cid, WantType::WantBlock)
+        );
+    }
+
+    #[test]
+    fn test_cid_typed_constructors_round_trip() {
+        let block = crate::storage::Block::new(b"verified block".to_vec()).unwrap();
+        let delivery = BlockDelivery::from_verified_block(&block);
+        let presence =
+            BlockPresence::from_valid_cid(&block.cid, BlockPresenceType::PresenceHave, vec![0]);
+
+        let msg = Message {
+            wantlist: None,
+            payload: vec![delivery],
+            block_presences: vec![presence],
+            pending_bytes: 0,
+            account: None,
+            payment: None,
+            integrity: None,
+            compressed_wantlist: None,
+        };
+        let decoded = decode_message(&encode_message(&msg).unwrap()).unwrap();
+
+        let delivery = &decoded.payload[0];
+        assert_eq!(
+            cid::Cid::try_from(delivery.cid.as_slice()).unwrap(),
+            block.cid
+        );
+        assert_eq!(delivery.data, block.data);
+        let presence = &decoded.block_presences[0];
+        assert_eq!(
+            cid::Cid::try_from(presence.cid_bytes().unwrap()).unwrap(),
+            block.cid
+        );
+        assert_eq!(presence.r#type, BlockPresenceType::PresenceHave as i32);
+    }
+
+    #[test]
+    fn test_compressed_wantlist_round_trip() {
+        // Blocks of one manifest; sorted, since decompression yields CID order
+        let mut cids: Vec<Vec<u8>> = (0..100u32)
+            .map(|i| {
+                crate::cid_blake3::blake3_cid(&i.to_le_bytes())
+                    .unwrap()
+                    .to_bytes()
+            })
+            .collect();
+        cids.sort();
+        let entries: Vec<WantlistEntry> = cids
+            .into_iter()
+            .map(|cid| WantlistEntry::from_cid(cid, WantType::WantBlock))
+            .collect();
+        let original = Wantlist {
+            entries: entries.clone(),
+            full: false,
+        };
+
+        let compressed = Wantlist::compress_entries(entries);
+        assert_eq!(compressed.len(), 100);
+        assert!(compressed.encoded_len() < original.encoded_len());
+        assert_eq!(compressed.to_wantlist(), original);
+
+        // Also after a trip over the wire
+        let decoded = CompressedWantlist::decode(compressed.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded.to_wantlist(), original);
+    }
+
+    #[test]
+    fn test_compressed_wantlist_tree_leaves() {
+        let tree_cid = crate::cid_blake3::blake3_cid(b"manifest tree")
+            .unwrap()
+            .to_bytes();
+        let mut entries: Vec<WantlistEntry> = (0..500)
+            .map(|index| {
+                WantlistEntry::from_tree_leaf(tree_cid.clone(), index, WantType::WantBlock)
+            })
+            .collect();
+        entries.push(WantlistEntry::cancel_cid(vec![1, 2, 3]));
+        entries.push(WantlistEntry {
+            address: None,
+            ..WantlistEntry::from_cid(vec![], WantType::WantHave)
+        });
+        let original = Wantlist {
+            entries: entries.clone(),
+            full: false,
+        };
+
+        let compressed = Wantlist::compress_entries(entries);
+        // The shared tree CID is stored once rather than 500 times
+        assert!(compressed.encoded_len() * 3 < original.encoded_len());
+
+        let expanded = compressed.to_wantlist();
+        assert_eq!(expanded.entries.len(), original.entries.len());
+        // The short CID sorts before the tree CID; unaddressed entries go last
+        assert_eq!(expanded.entries[0], original.entries[500]);
+        assert_eq!(expanded.entries[1..501], original.entries[..500]);
+        assert_eq!(expanded.entries[501], original.entries[501]);
+    }
+
+    #[test]
+    fn test_expand_wantlist_merges_compressed_entries() {
+        let mut compressed = Wantlist::compress_entries(vec![WantlistEntry::from_cid(
+            vec![4, 5, 6],
+            WantType::WantHave,
+        )]);
+        compressed.full = true;
+        let msg = Message {
+            wantlist: Some(Wantlist {
+                entries: vec![WantlistEntry::from_cid(vec![1, 2, 3], WantType::WantBlock)],
+                full: false,
+            }),
+            compressed_wantlist: Some(compressed),
+            ..Default::default()
+        };
+
+        let mut decoded = decode_message(&encode_message(&msg).unwrap()).unwrap();
+        assert_eq!(decoded, msg);
+        decoded.expand_wantlist(16).unwrap();
+        assert!(decoded.compressed_wantlist.is_none());
+        let wantlist = decoded.wantlist.unwrap();
+        assert!(wantlist.full);
+        let cids: Vec<&[u8]> = wantlist
+            .entries
+            .iter()
+            .map(|entry| entry.cid_bytes().unwrap())
+            .collect();
+        assert_eq!(cids, vec![&[1, 2, 3][..], &[4, 5, 6][..]]);
+
+        let mut empty = Wantlist::compress_entries(vec![]);
+        assert!(empty.is_empty());
+        assert!(empty.root.is_none());
+        empty.full = true;
+        let mut msg = Message {
+            compressed_wantlist: Some(empty),
+            ..Default::default()
+        };
+        msg.expand_wantlist(16).unwrap();
+        assert_eq!(
+            msg.wantlist,
+            Some(Wantlist {
+                entries: vec![],
+                full: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_expand_wantlist_rejects_oversized_compressed_wantlists() {
+        let entries = (0..10u8)
+            .map(|i| WantlistEntry::from_cid(vec![1, 2, i], WantType::WantHave))
+            .collect();
+        let mut msg = Message {
+            compressed_wantlist: Some(Wantlist::compress_entries(entries)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            msg.clone().expand_wantlist(9),
+            Err(MessageError::WantlistTooLarge {
+                entries: 10,
+                max: 9
+            })
+        ));
+        assert!(msg.expand_wantlist(10).is_ok());
+
+        // Many tiny entries under one long label would each copy the label
+        let mut msg = Message {
+            compressed_wantlist: Some(CompressedWantlist {
+                root: Some(TrieNode {
+                    label: vec![7; MAX_COMPRESSED_KEY_BYTES + 1],
+                    entries: vec![CompressedEntry::default(); 4],
+                    children: vec![],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            msg.expand_wantlist(16),
+            Err(MessageError::KeyTooLong { .. })
+        ));
+        assert!(msg.wantlist.is_none());
+    }
+
+    fn bytes(max: usize) -> impl Strategy<Value = Vec<u8>> {
+        proptest::collection::vec(any::<u8>(), 0..max)
+    }
+
+    fn block_address() -> impl Strategy<Value = BlockAddress> {
+        (any::<bool>(), bytes(40), any::<u64>(), bytes(40)).prop_map(
+            |(leaf, tree_cid, index, cid)| BlockAddress {
+                leaf,
+                tree_cid,
+                index,
+                cid,
+            },
+        )
+    }
+
+    fn wantlist() -> impl Strategy<Value = Wantlist> {
+        let entry = (
+            proptest::option::of(block_address()),
+            any::<i32>(),
+            any::<bool>(),
+            any::<i32>(),
+            any::<bool>(),
+        )
+            .prop_map(|(address, priority, cancel, want_type, send_dont_have)| {
+                WantlistEntry {
+                    address,
+                    priority,
+                    cancel,
+                    want_type,
+                    send_dont_have,
+                }
+            });
+        (proptest::collection::vec(entry, 0..4), any::<bool>())
+            .prop_map(|(entries, full)| Wantlist { entries, full })
+    }
+
+    fn block_delivery() -> impl Strategy<Value = BlockDelivery> {
+        let proof = (
+            any::<u64>(),
+            any::<u64>(),
+            any::<u64>(),
+            proptest::collection::vec(bytes(32).prop_map(|hash| ProofNode { hash }), 0..4),
+        )
+            .prop_map(|(mcodec, index, nleaves, path)| ArchivistProof {
+                mcodec,
+                index,
+                nleaves,
+                path,
+            });
+        (
+            bytes(40),
+            bytes(256),
+            proptest::option::of(block_address()),
+            proptest::option::of(proof),
+        )
+            .prop_map(|(cid, data, address, proof)| BlockDelivery {
+                cid,
+                data,
+                address,
+                proof,
+            })
+    }
+
+    fn message() -> impl Strategy<Value = Message> {
+        let presence = (
+            proptest::option::of(block_address()),
+            any::<i32>(),
+            bytes(8),
+        )
+            .prop_map(|(address, r#type, price)| BlockPresence {
+                address,
+                r#type,
+                price,
+            });
+        (
+            proptest::option::of(wantlist()),
+            proptest::collection::vec(block_delivery(), 0..3),
+            proptest::collection::vec(presence, 0..4),
+            any::<i32>(),
+            proptest::option::of(bytes(20).prop_map(|address| AccountMessage { address })),
+            proptest::option::of(bytes(20).prop_map(|update| StateChannelUpdate { update })),
+        )
+            .prop_map(
+                |(wantlist, payload, block_presences, pending_bytes, account, payment)| Message {
+                    wantlist,
+                    payload,
+                    block_presences,
+                    pending_bytes,
+                    account,
+                    payment,
+                    integrity: None,
+                    compressed_wantlist: None,
+                },
+            )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(128))]
+
+        #[test]
+        fn prop_encode_decode_round_trip(msg in message()) {
+            let decoded = decode_message(&encode_message(&msg).unwrap()).unwrap();
+            prop_assert_eq!(decoded, msg);
+        }
+
+        #[test]
+        fn prop_decode_arbitrary_bytes_never_panics(data in bytes(512)) {
+            let _ = decode_message(&data);
+        }
+    }
 }
@@ -1,17 +1,48 @@
 //! Prometheus metrics for benchmarking and monitoring
 //!
-//! Thread-safe metrics collection using atomic types
+//! Thread-safe metrics collection using atomic types. Metrics are exposed in
+//! the Prometheus text format or, for clients that ask for it, OpenMetrics.
 
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Content type of [`Metrics::to_prometheus`] output
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Content type of [`Metrics::to_openmetrics`] output
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 /// Global metrics collector for Neverust node
 #[derive(Clone)]
 pub struct Metrics {
     inner: Arc<MetricsInner>,
 }
 
+/// Counter values taken by [`Metrics::snapshot_and_reset`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub total_peers_seen: u64,
+    pub blocks_sent: u64,
+    pub blocks_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub total_exchange_time_ms: u64,
+    pub total_exchanges: u64,
+    pub discovery_queries: u64,
+    pub discovery_successes: u64,
+    pub discovery_failures: u64,
+    pub blocks_from_discovery: u64,
+    pub upload_blocks_deduplicated: u64,
+    pub uploads_deduplicated: u64,
+    pub advertisements_queued: u64,
+    pub advertisements_succeeded: u64,
+    pub advertisements_failed: u64,
+}
+
 struct MetricsInner {
     // Peer connection metrics
     peer_connections: AtomicUsize,
@@ -39,6 +70,16 @@ struct MetricsInner {
     discovery_failures: AtomicU64,
     blocks_from_discovery: AtomicU64,
 
+    // Upload deduplication metrics
+    upload_blocks_deduplicated: AtomicU64,
+    uploads_deduplicated: AtomicU64,
+
+    // DHT advertisement metrics
+    advertisements_queued: AtomicU64,
+    advertisements_succeeded: AtomicU64,
+    advertisements_failed: AtomicU64,
+    advertisement_queue_depth: AtomicUsize,
+
     // Node start time for uptime calculation
     start_time: SystemTime,
 }
@@ -62,6 +103,12 @@ impl Metrics {
                 discovery_successes: AtomicU64::new(0),
                 discovery_failures: AtomicU64::new(0),
                 blocks_from_discovery: AtomicU64::new(0),
+                upload_blocks_deduplicated: AtomicU64::new(0),
+                uploads_deduplicated: AtomicU64::new(0),
+                advertisements_queued: AtomicU64::new(0),
+                advertisements_succeeded: AtomicU64::new(0),
+                advertisements_failed: AtomicU64::new(0),
+                advertisement_queue_depth: AtomicUsize::new(0),
                 start_time: SystemTime::now(),
             }),
         }
@@ -118,6 +165,18 @@ impl Metrics {
         self.inner.bytes_received.load(Ordering::Relaxed)
     }
 
+    /// Average receive rate since the metrics were created
+    pub fn bytes_per_second_received(&self) -> u64 {
+        let elapsed = self
+            .inner
+            .start_time
+            .elapsed()
+            .unwrap_or_default()
+            .as_secs()
+            .max(1);
+        self.bytes_received() / elapsed
+    }
+
     // Cache metrics
 
     pub fn cache_hit(&self) {
@@ -204,6 +263,78 @@ impl Metrics {
         }
     }
 
+    // Upload deduplication metrics
+
+    /// Record uploaded blocks that were already stored and not written again
+    pub fn upload_blocks_deduplicated_add(&self, blocks: u64) {
+        self.inner
+            .upload_blocks_deduplicated
+            .fetch_add(blocks, Ordering::Relaxed);
+    }
+
+    /// Record an upload whose blocks were all already stored
+    pub fn upload_deduplicated(&self) {
+        self.inner
+            .uploads_deduplicated
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn upload_blocks_deduplicated(&self) -> u64 {
+        self.inner
+            .upload_blocks_deduplicated
+            .load(Ordering::Relaxed)
+    }
+
+    pub fn uploads_deduplicated(&self) -> u64 {
+        self.inner.uploads_deduplicated.load(Ordering::Relaxed)
+    }
+
+    // Advertisement metrics
+
+    /// Record a block queued for DHT advertisement
+    pub fn advertisement_queued(&self) {
+        self.inner
+            .advertisements_queued
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record blocks successfully advertised to the DHT
+    pub fn advertisements_succeeded_add(&self, blocks: u64) {
+        self.inner
+            .advertisements_succeeded
+            .fetch_add(blocks, Ordering::Relaxed);
+    }
+
+    /// Record blocks whose DHT advertisement failed
+    pub fn advertisements_failed_add(&self, blocks: u64) {
+        self.inner
+            .advertisements_failed
+            .fetch_add(blocks, Ordering::Relaxed);
+    }
+
+    /// Set the number of blocks waiting in the advertisement queue
+    pub fn set_advertisement_queue_depth(&self, depth: usize) {
+        self.inner
+            .advertisement_queue_depth
+            .store(depth, Ordering::Relaxed);
+    }
+
+    pub fn advertisements_queued(&self) -> u64 {
+        self.inner.advertisements_queued.load(Ordering::Relaxed)
+    }
+
+    pub fn advertisements_succeeded(&self) -> u64 {
+        self.inner.advertisements_succeeded.load(Ordering::Relaxed)
+    }
+
+    pub fn advertisements_failed(&self) -> u64 {
+        self.inner.advertisements_failed.load(Ordering::Relaxed)
+    }
+
+    pub fn advertisement_queue_depth(&self) -> usize {
+        self.inner.advertisement_queue_depth.load(Ordering::Relaxed)
+    }
+
     // Uptime
 
     pub fn uptime_seconds(&self) -> u64 {
@@ -214,99 +345,295 @@ impl Metrics {
             .as_secs()
     }
 
+    // Reset
+
+    /// Read every counter and set it back to zero
+    ///
+    /// Each counter is swapped atomically, so no increment is lost between
+    /// the read and the clear. The peer connection and advertisement queue
+    /// gauges are left untouched.
+    pub fn snapshot_and_reset(&self) -> MetricsSnapshot {
+        let take = |counter: &AtomicU64| counter.swap(0, Ordering::Relaxed);
+        let inner = &self.inner;
+        MetricsSnapshot {
+            total_peers_seen: take(&inner.total_peers_seen),
+            blocks_sent: take(&inner.blocks_sent),
+            blocks_received: take(&inner.blocks_received),
+            bytes_sent: take(&inner.bytes_sent),
+            bytes_received: take(&inner.bytes_received),
+            cache_hits: take(&inner.cache_hits),
+            cache_misses: take(&inner.cache_misses),
+            total_exchange_time_ms: take(&inner.total_exchange_time_ms),
+            total_exchanges: take(&inner.total_exchanges),
+            discovery_queries: take(&inner.discovery_queries),
+            discovery_successes: take(&inner.discovery_successes),
+            discovery_failures: take(&inner.discovery_failures),
+            blocks_from_discovery: take(&inner.blocks_from_discovery),
+            upload_blocks_deduplicated: take(&inner.upload_blocks_deduplicated),
+            uploads_deduplicated: take(&inner.uploads_deduplicated),
+            advertisements_queued: take(&inner.advertisements_queued),
+            advertisements_succeeded: take(&inner.advertisements_succeeded),
+            advertisements_failed: take(&inner.advertisements_failed),
+        }
+    }
+
+    /// Set every counter back to zero
+    #[cfg(test)]
+    pub fn reset(&self) {
+        self.snapshot_and_reset();
+    }
+
+    /// Every exported metric family with its current value
+    fn families(&self, block_count: usize, total_bytes: usize) -> Vec<Family> {
+        let uptime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - self.uptime_seconds();
+        vec![
+            Family::gauge(
+                "neverust_block_count",
+                "Total number of blocks stored",
+                block_count,
+            ),
+            Family::gauge(
+                "neverust_block_bytes",
+                "Total bytes of block data stored",
+                total_bytes,
+            ),
+            // A counter to Prometheus, but an OpenMetrics counter's sample
+            // would have to be renamed to neverust_uptime_seconds_total
+            Family::gauge(
+                "neverust_uptime_seconds",
+                "Time since node started in seconds",
+                uptime,
+            )
+            .with_prometheus_kind("counter"),
+            Family::gauge(
+                "neverust_peer_connections",
+                "Current number of active peer connections",
+                self.peer_connections(),
+            ),
+            Family::counter(
+                "neverust_peers_seen",
+                "Total number of unique peers seen since start",
+                self.total_peers_seen(),
+            )
+            .with_prometheus_name("neverust_total_peers_seen"),
+            Family::counter(
+                "neverust_blocks_sent",
+                "Total number of blocks sent to peers",
+                self.blocks_sent(),
+            ),
+            Family::counter(
+                "neverust_blocks_received",
+                "Total number of blocks received from peers",
+                self.blocks_received(),
+            ),
+            Family::counter(
+                "neverust_bytes_sent",
+                "Total bytes sent to peers",
+                self.bytes_sent(),
+            ),
+            Family::counter(
+                "neverust_bytes_received",
+                "Total bytes received from peers",
+                self.bytes_received(),
+            ),
+            Family::counter(
+                "neverust_cache_hits",
+                "Total number of cache hits",
+                self.cache_hits(),
+            ),
+            Family::counter(
+                "neverust_cache_misses",
+                "Total number of cache misses",
+                self.cache_misses(),
+            ),
+            Family::gauge(
+                "neverust_avg_exchange_time_ms",
+                "Average block exchange time in milliseconds",
+                format!("{:.2}", self.avg_exchange_time_ms()),
+            ),
+            Family::counter(
+                "neverust_discovery_queries",
+                "Total number of discovery queries initiated",
+                self.discovery_queries(),
+            ),
+            Family::counter(
+                "neverust_discovery_successes",
+                "Total number of successful discovery queries",
+                self.discovery_successes(),
+            ),
+            Family::counter(
+                "neverust_discovery_failures",
+                "Total number of failed discovery queries",
+                self.discovery_failures(),
+            ),
+            Family::counter(
+                "neverust_blocks_from_discovery",
+                "Total blocks retrieved via discovery",
+                self.blocks_from_discovery(),
+            ),
+            Family::gauge(
+                "neverust_discovery_success_rate",
+                "Discovery query success rate (percentage)",
+                format!("{:.2}", self.discovery_success_rate()),
+            ),
+            Family::counter(
+                "neverust_upload_blocks_deduplicated",
+                "Uploaded blocks that were already stored",
+                self.upload_blocks_deduplicated(),
+            ),
+            Family::counter(
+                "neverust_uploads_deduplicated",
+                "Uploads whose blocks were all already stored",
+                self.uploads_deduplicated(),
+            ),
+            Family::gauge(
+                "neverust_advertisement_queue_depth",
+                "Blocks waiting to be advertised to the DHT",
+                self.advertisement_queue_depth(),
+            ),
+            Family::counter(
+                "neverust_advertisements_queued",
+                "Blocks queued for DHT advertisement",
+                self.advertisements_queued(),
+            ),
+            Family::counter(
+                "neverust_advertisements_succeeded",
+                "Blocks successfully advertised to the DHT",
+                self.advertisements_succeeded(),
+            ),
+            Family::counter(
+                "neverust_advertisements_failed",
+                "Blocks whose DHT advertisement failed",
+                self.advertisements_failed(),
+            ),
+        ]
+    }
+
     /// Generate Prometheus-formatted metrics text
     pub fn to_prometheus(&self, block_count: usize, total_bytes: usize) -> String {
-        format!(
-            "# HELP neverust_block_count Total number of blocks stored\n\
-             # TYPE neverust_block_count gauge\n\
-             neverust_block_count {}\n\
-             \n\
-             # HELP neverust_block_bytes Total bytes of block data stored\n\
-             # TYPE neverust_block_bytes gauge\n\
-             neverust_block_bytes {}\n\
-             \n\
-             # HELP neverust_uptime_seconds Time since node started in seconds\n\
-             # TYPE neverust_uptime_seconds counter\n\
-             neverust_uptime_seconds {}\n\
-             \n\
-             # HELP neverust_peer_connections Current number of active peer connections\n\
-             # TYPE neverust_peer_connections gauge\n\
-             neverust_peer_connections {}\n\
-             \n\
-             # HELP neverust_total_peers_seen Total number of unique peers seen since start\n\
-             # TYPE neverust_total_peers_seen counter\n\
-             neverust_total_peers_seen {}\n\
-             \n\
-             # HELP neverust_blocks_sent_total Total number of blocks sent to peers\n\
-             # TYPE neverust_blocks_sent_total counter\n\
-             neverust_blocks_sent_total {}\n\
-             \n\
-             # HELP neverust_blocks_received_total Total number of blocks received from peers\n\
-             # TYPE neverust_blocks_received_total counter\n\
-             neverust_blocks_received_total {}\n\
-             \n\
-             # HELP neverust_bytes_sent_total Total bytes sent to peers\n\
-             # TYPE neverust_bytes_sent_total counter\n\
-             neverust_bytes_sent_total {}\n\
-             \n\
-             # HELP neverust_bytes_received_total Total bytes received from peers\n\
-             # TYPE neverust_bytes_received_total counter\n\
-             neverust_bytes_received_total {}\n\
-             \n\
-             # HELP neverust_cache_hits_total Total number of cache hits\n\
-             # TYPE neverust_cache_hits_total counter\n\
-             neverust_cache_hits_total {}\n\
-             \n\
-             # HELP neverust_cache_misses_total Total number of cache misses\n\
-             # TYPE neverust_cache_misses_total counter\n\
-             neverust_cache_misses_total {}\n\
-             \n\
-             # HELP neverust_avg_exchange_time_ms Average block exchange time in milliseconds\n\
-             # TYPE neverust_avg_exchange_time_ms gauge\n\
-             neverust_avg_exchange_time_ms {:.2}\n\
-             \n\
-             # HELP neverust_discovery_queries_total Total number of discovery queries initiated\n\
-             # TYPE neverust_discovery_queries_total counter\n\
-             neverust_discovery_queries_total {}\n\
-             \n\
-             # HELP neverust_discovery_successes_total Total number of successful discovery queries\n\
-             # TYPE neverust_discovery_successes_total counter\n\
-             neverust_discovery_successes_total {}\n\
-             \n\
-             # HELP neverust_discovery_failures_total Total number of failed discovery queries\n\
-             # TYPE neverust_discovery_failures_total counter\n\
-             neverust_discovery_failures_total {}\n\
-             \n\
-             # HELP neverust_blocks_from_discovery_total Total blocks retrieved via discovery\n\
-             # TYPE neverust_blocks_from_discovery_total counter\n\
-             neverust_blocks_from_discovery_total {}\n\
-             \n\
-             # HELP neverust_discovery_success_rate Discovery query success rate (percentage)\n\
-             # TYPE neverust_discovery_success_rate gauge\n\
-             neverust_discovery_success_rate {:.2}\n",
-            block_count,
-            total_bytes,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-                - self.uptime_seconds(),
-            self.peer_connections(),
-            self.total_peers_seen(),
-            self.blocks_sent(),
-            self.blocks_received(),
-            self.bytes_sent(),
-            self.bytes_received(),
-            self.cache_hits(),
-            self.cache_misses(),
-            self.avg_exchange_time_ms(),
-            self.discovery_queries(),
-            self.discovery_successes(),
-            self.discovery_failures(),
-            self.blocks_from_discovery(),
-            self.discovery_success_rate(),
-        )
+        self.families(block_count, total_bytes)
+            .iter()
+            .map(|family| {
+                let name = family.prometheus_name();
+                format!(
+                    "# HELP {name} {}\n# TYPE {name} {}\n{name} {}\n",
+                    family.help,
+                    family.prometheus_kind.unwrap_or(family.kind),
+                    family.value
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Generate metrics in the OpenMetrics text format
+    ///
+    /// Exposes the same metrics as [`Metrics::to_prometheus`]. Counter
+    /// families are named without the `_total` suffix their samples carry,
+    /// so scrapers see the same series names in both formats, except
+    /// `neverust_total_peers_seen`, which becomes `neverust_peers_seen_total`.
+    /// `neverust_uptime_seconds` keeps its name by being typed as a gauge.
+    pub fn to_openmetrics(&self, block_count: usize, total_bytes: usize) -> String {
+        // OpenMetrics allows no blank lines and must end with "# EOF"
+        let mut output = String::new();
+        for family in self.families(block_count, total_bytes) {
+            let name = family.name;
+            let sample = family.sample_name();
+            output.push_str(&format!(
+                "# TYPE {name} {}\n# HELP {name} {}\n{sample} {}\n",
+                family.kind, family.help, family.value
+            ));
+        }
+        output.push_str("# EOF\n");
+        output
+    }
+}
+
+/// A metric family as exported by [`Metrics::to_prometheus`] and
+/// [`Metrics::to_openmetrics`]
+struct Family {
+    /// OpenMetrics family name; counters' samples add `_total`
+    name: &'static str,
+    /// Prometheus name, when it isn't the sample name
+    prometheus_name: Option<&'static str>,
+    kind: &'static str,
+    /// Prometheus type, when it isn't `kind`
+    prometheus_kind: Option<&'static str>,
+    help: &'static str,
+    value: String,
+}
+
+impl Family {
+    fn gauge(name: &'static str, help: &'static str, value: impl ToString) -> Self {
+        Self {
+            name,
+            prometheus_name: None,
+            kind: "gauge",
+            prometheus_kind: None,
+            help,
+            value: value.to_string(),
+        }
     }
+
+    fn counter(name: &'static str, help: &'static str, value: impl ToString) -> Self {
+        Self {
+            kind: "counter",
+            ..Self::gauge(name, help, value)
+        }
+    }
+
+    /// Export the family to Prometheus under an older name
+    fn with_prometheus_name(mut self, name: &'static str) -> Self {
+        self.prometheus_name = Some(name);
+        self
+    }
+
+    /// Export the family to Prometheus under another type
+    fn with_prometheus_kind(mut self, kind: &'static str) -> Self {
+        self.prometheus_kind = Some(kind);
+        self
+    }
+
+    /// Name of the family's sample line
+    fn sample_name(&self) -> String {
+        if self.kind == "counter" {
+            format!("{}_total", self.name)
+        } else {
+            self.name.to_string()
+        }
+    }
+
+    fn prometheus_name(&self) -> String {
+        self.prometheus_name
+            .map_or_else(|| self.sample_name(), str::to_string)
+    }
+}
+
+/// Whether an `Accept` header prefers OpenMetrics over the Prometheus format
+///
+/// OpenMetrics must be named explicitly; `text/plain` and wildcards count
+/// towards the Prometheus format. Equal `q` values go to OpenMetrics.
+pub fn prefers_openmetrics(accept: &str) -> bool {
+    let mut openmetrics_q = 0.0f32;
+    let mut prometheus_q = 0.0f32;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media_type.as_str() {
+            "application/openmetrics-text" => openmetrics_q = openmetrics_q.max(q),
+            "text/plain" | "text/*" | "*/*" => prometheus_q = prometheus_q.max(q),
+            _ => {}
+        }
+    }
+    openmetrics_q > 0.0 && openmetrics_q >= prometheus_q
 }
 
 impl Default for Metrics {
@@ -352,6 +679,10 @@ mod tests {
         metrics.block_sent(50);
         assert_eq!(metrics.blocks_sent(), 2);
         assert_eq!(metrics.bytes_sent(), 150);
+
+        // Averaged over at least one second of uptime
+        let rate = metrics.bytes_per_second_received();
+        assert!(rate > 0 && rate <= 200);
     }
 
     #[test]
@@ -388,5 +719,135 @@ mod tests {
         assert!(output.contains("neverust_block_bytes 1024"));
         assert!(output.contains("neverust_peer_connections 1"));
         assert!(output.contains("neverust_blocks_sent_total 1"));
+        assert!(output.contains("# TYPE neverust_uptime_seconds counter\n"));
+        assert!(output.contains("\nneverust_uptime_seconds "));
+        assert!(output.contains("# TYPE neverust_total_peers_seen counter\n"));
+        assert!(output.contains("neverust_total_peers_seen 1\n"));
+    }
+
+    #[test]
+    fn test_formats_export_the_same_families() {
+        let metrics = Metrics::new();
+        let prometheus = metrics.to_prometheus(0, 0);
+        let openmetrics = metrics.to_openmetrics(0, 0);
+        let types = |output: &str| output.lines().filter(|l| l.starts_with("# TYPE")).count();
+        assert_eq!(types(&prometheus), types(&openmetrics));
+
+        // Gauges keep their name, counters keep their sample name
+        assert!(openmetrics.contains("# TYPE neverust_uptime_seconds gauge\n"));
+        assert!(openmetrics.contains("\nneverust_uptime_seconds "));
+        assert!(prometheus.contains("\nneverust_advertisements_failed_total 0\n"));
+        assert!(openmetrics.contains("\nneverust_advertisements_failed_total 0\n"));
+    }
+
+    #[test]
+    fn test_openmetrics_output() {
+        let metrics = Metrics::new();
+        metrics.peer_connected();
+        metrics.block_sent(100);
+
+        let output = metrics.to_openmetrics(42, 1024);
+        assert!(output.ends_with("# EOF\n"));
+
+        let metadata = regex::Regex::new(r"^# (TYPE|HELP) ([a-z_]+) (.+)$").unwrap();
+        let sample = regex::Regex::new(r"^([a-z_]+) ([0-9.]+)$").unwrap();
+        let mut family = None;
+        let mut counters = 0;
+        let mut lines = output.lines();
+        for line in lines.by_ref().take_while(|line| *line != "# EOF") {
+            if let Some(caps) = metadata.captures(line) {
+                if &caps[1] == "TYPE" {
+                    family = Some((caps[2].to_string(), caps[3].to_string()));
+                }
+                continue;
+            }
+            let caps = sample
+                .captures(line)
+                .unwrap_or_else(|| panic!("invalid line {:?}", line));
+            let (name, kind) = family.clone().expect("sample before its TYPE");
+            if kind == "counter" {
+                assert_eq!(caps[1], format!("{}_total", name));
+                counters += 1;
+            } else {
+                assert_eq!(caps[1], name);
+            }
+        }
+        assert!(counters > 0);
+        assert_eq!(lines.next(), None, "content after # EOF");
+
+        assert!(output.contains("neverust_block_count 42\n"));
+        assert!(output.contains("neverust_peers_seen_total 1\n"));
+        assert!(output.contains("# TYPE neverust_blocks_sent counter\n"));
+        assert!(output.contains("neverust_blocks_sent_total 1\n"));
+    }
+
+    #[test]
+    fn test_prefers_openmetrics() {
+        // Prometheus's default scrape Accept header
+        assert!(prefers_openmetrics(
+            "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1"
+        ));
+        assert!(prefers_openmetrics("application/openmetrics-text"));
+        assert!(!prefers_openmetrics(""));
+        assert!(!prefers_openmetrics("*/*"));
+        assert!(!prefers_openmetrics(
+            "text/plain, application/openmetrics-text; q=0.5"
+        ));
+        assert!(!prefers_openmetrics("application/openmetrics-text; q=0"));
+    }
+
+    #[test]
+    fn test_reset_zeroes_counters() {
+        let metrics = Metrics::new();
+        metrics.peer_connected();
+        metrics.block_sent(100);
+        metrics.block_received(200);
+        metrics.cache_hit();
+        metrics.record_exchange_time(50);
+        metrics.discovery_query();
+        metrics.discovery_success();
+
+        let snapshot = metrics.snapshot_and_reset();
+        assert_eq!(snapshot.blocks_sent, 1);
+        assert_eq!(snapshot.bytes_received, 200);
+        assert_eq!(snapshot.total_exchange_time_ms, 50);
+        assert_eq!(snapshot.discovery_successes, 1);
+        assert_eq!(metrics.snapshot_and_reset(), MetricsSnapshot::default());
+
+        metrics.block_sent(100);
+        metrics.cache_miss();
+        metrics.reset();
+
+        let output = metrics.to_prometheus(0, 0);
+        assert!(output.contains("neverust_blocks_sent_total 0"));
+        assert!(output.contains("neverust_bytes_sent_total 0"));
+        assert!(output.contains("neverust_cache_misses_total 0"));
+        assert!(output.contains("neverust_avg_exchange_time_ms 0.00"));
+        assert!(output.contains("neverust_discovery_success_rate 0.00"));
+        // Connections are a gauge, not a counter
+        assert!(output.contains("neverust_peer_connections 1"));
+    }
+
+    #[test]
+    fn test_advertisement_metrics() {
+        let metrics = Metrics::new();
+        metrics.advertisement_queued();
+        metrics.advertisement_queued();
+        metrics.advertisements_succeeded_add(1);
+        metrics.advertisements_failed_add(1);
+        metrics.set_advertisement_queue_depth(7);
+
+        let output = metrics.to_prometheus(0, 0);
+        assert!(output.contains("neverust_advertisements_queued_total 2"));
+        assert!(output.contains("neverust_advertisements_succeeded_total 1"));
+        assert!(output.contains("neverust_advertisements_failed_total 1"));
+        assert!(output.contains("neverust_advertisement_queue_depth 7"));
+
+        let snapshot = metrics.snapshot_and_reset();
+        assert_eq!(snapshot.advertisements_queued, 2);
+        assert_eq!(snapshot.advertisements_failed, 1);
+        // The queue depth is a gauge, not a counter
+        assert_eq!(metrics.advertisement_queue_depth(), 7);
+        assert_eq!(metrics.advertisements_succeeded(), 0);
     }
 }
@@ -0,0 +1,131 @@
+//! Block store commands that run without a node
+//!
+//! These back the `info`, `verify`, `export`, `import` and `manifest show`
+//! subcommands, which open a block store directly (e.g. `./data/blocks`).
+//! A running node holds its store open, so stop it first.
+
+use cid::Cid;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::api::ManifestDetailResponse;
+use crate::car::{export_car, import_car, CarError};
+use crate::manifest::{Manifest, ManifestError};
+use crate::storage::{BlockStore, StorageError};
+
+/// An offline block store command parsed from the CLI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfflineCommand {
+    /// Print block counts and sizes
+    Info { store_path: PathBuf },
+    /// Check every block against its CID
+    Verify { store_path: PathBuf },
+    /// Write every block to a CAR file
+    Export {
+        store_path: PathBuf,
+        output: PathBuf,
+    },
+    /// Store the blocks of a CAR file, creating the store if needed
+    Import { store_path: PathBuf, input: PathBuf },
+    /// Decode and print a manifest
+    ManifestShow {
+        store_path: PathBuf,
+        manifest_cid: Cid,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum OfflineError {
+    #[error("No block store at {0}")]
+    StoreNotFound(PathBuf),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("CAR error: {0}")]
+    Car(#[from] CarError),
+
+    #[error("Manifest error: {0}")]
+    Manifest(#[from] ManifestError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error(
+        "Integrity check failed: {corrupted} corrupted blocks, {index_mismatches} index mismatches"
+    )]
+    IntegrityFailed {
+        corrupted: usize,
+        index_mismatches: usize,
+    },
+}
+
+/// Run `command`, writing its report to `out`
+pub async fn run<W: Write>(command: OfflineCommand, out: &mut W) -> Result<(), OfflineError> {
+    match command {
+        OfflineCommand::Info { store_path } => {
+            let store = open_existing(&store_path)?;
+            let stats = store.stats().await;
+            writeln!(out, "Blocks:       {}", stats.block_count)?;
+            writeln!(out, "Total size:   {} bytes", stats.total_size)?;
+            writeln!(out, "Size on disk: {} bytes", store.size_on_disk().await?)?;
+            let mut codecs: Vec<_> = store.count_by_codec().await?.into_iter().collect();
+            codecs.sort();
+            for (codec, count) in codecs {
+                writeln!(out, "Codec 0x{:x}: {} blocks", codec, count)?;
+            }
+        }
+        OfflineCommand::Verify { store_path } => {
+            let report = open_existing(&store_path)?.verify_integrity().await?;
+            writeln!(out, "Checked {} blocks", report.checked)?;
+            for cid in &report.corrupted {
+                writeln!(out, "Corrupted: {}", cid)?;
+            }
+            for cid in &report.index_mismatches {
+                writeln!(out, "Index mismatch: {}", cid)?;
+            }
+            if !report.is_ok() {
+                return Err(OfflineError::IntegrityFailed {
+                    corrupted: report.corrupted.len(),
+                    index_mismatches: report.index_mismatches.len(),
+                });
+            }
+            writeln!(out, "All blocks match their CIDs")?;
+        }
+        OfflineCommand::Export { store_path, output } => {
+            let store = open_existing(&store_path)?;
+            let mut file = io::BufWriter::new(std::fs::File::create(&output)?);
+            let exported = export_car(&store, &mut file).await?;
+            writeln!(out, "Exported {} blocks to {}", exported, output.display())?;
+        }
+        OfflineCommand::Import { store_path, input } => {
+            let store = BlockStore::new_with_path(&store_path)?;
+            let mut file = io::BufReader::new(std::fs::File::open(&input)?);
+            let (roots, imported) = import_car(&store, &mut file).await?;
+            writeln!(out, "Imported {} blocks from {}", imported, input.display())?;
+            for root in roots {
+                writeln!(out, "Root: {}", root)?;
+            }
+        }
+        OfflineCommand::ManifestShow {
+            store_path,
+            manifest_cid,
+        } => {
+            let block = open_existing(&store_path)?.get(&manifest_cid).await?;
+            let manifest = Manifest::from_block(&block)?;
+            let detail = ManifestDetailResponse::new(&manifest_cid, &manifest);
+            serde_json::to_writer_pretty(&mut *out, &detail).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Open the store at `path`, which must already exist
+fn open_existing(path: &Path) -> Result<BlockStore, OfflineError> {
+    if !path.exists() {
+        return Err(OfflineError::StoreNotFound(path.to_path_buf()));
+    }
+    Ok(BlockStore::new_with_path(path)?)
+}
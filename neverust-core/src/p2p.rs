@@ -5,14 +5,17 @@
 //!
 //! Identify protocol is used for SPR (Signed Peer Record) exchange.
 
-use libp2p::{identify, noise, tcp, PeerId, Swarm, SwarmBuilder};
+use libp2p::core::transport::TransportError;
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::DialError;
+use libp2p::{identify, noise, ping, tcp, Multiaddr, PeerId, Swarm, SwarmBuilder};
 use libp2p_mplex as mplex;
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
 
 use crate::blockexc::BlockExcBehaviour;
-use crate::identify_shim::{IdentifyBehaviour, IdentifyConfig};
+use crate::identify_shim::{IdentifyBehaviour, IdentifyConfig, IdentifyShimEvent};
 use crate::storage::BlockStore;
 
 #[derive(Error, Debug)]
@@ -27,7 +30,7 @@ pub enum P2PError {
     Io(#[from] std::io::Error),
 }
 
-/// Network behavior with BlockExc + Identify protocols
+/// Network behavior with BlockExc + Identify + Ping protocols
 /// Identify is required for SPR (Signed Peer Record) exchange with Archivist nodes
 ///
 /// Uses custom IdentifyBehaviour shim for nim-libp2p v1.9.0 compatibility
@@ -36,12 +39,53 @@ pub enum P2PError {
 pub struct Behaviour {
     pub blockexc: BlockExcBehaviour,
     pub identify: IdentifyBehaviour,
+    pub ping: ping::Behaviour,
+}
+
+impl Behaviour {
+    /// Get a snapshot of the behaviour's protocol state
+    ///
+    /// Only the BlockExc fields are filled in; swarm connections, the DHT
+    /// routing table and BoTG live outside the behaviour and are added by
+    /// the runtime (see [`crate::runtime::RuntimeHandle::stats`]).
+    pub fn stats(&self) -> BehaviourStats {
+        let blockexc = self.blockexc.stats();
+        BehaviourStats {
+            blockexc_pending_requests: blockexc.pending_requests,
+            blockexc_connected_peers: blockexc.connected_peers,
+            ..BehaviourStats::default()
+        }
+    }
+
+    /// Connected peer with the lowest ping round-trip time
+    pub fn best_latency_peer(&self) -> Option<PeerId> {
+        self.blockexc.best_latency_peer()
+    }
+}
+
+/// Statistics of a running node's network protocols
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BehaviourStats {
+    /// Peers with at least one open swarm connection
+    pub connected_peers: usize,
+    /// BlockExc block requests waiting for a response
+    pub blockexc_pending_requests: usize,
+    /// Peers with an open BlockExc connection
+    pub blockexc_connected_peers: usize,
+    /// Nodes in the DiscV5 routing table
+    pub discovery_routing_table_size: usize,
+    /// BoTG rollups not yet sent
+    pub botg_pending_rollups: usize,
 }
 
 #[derive(Debug)]
 pub enum BehaviourEvent {
     BlockExc(crate::blockexc::BlockExcToBehaviour),
     Identify(Box<identify::Event>),
+    /// A connected peer sent a valid SPR listing these addresses
+    ReceivedSpr(PeerId, Vec<Multiaddr>),
+    /// Outcome of a ping to a connected peer
+    Ping(ping::Event),
 }
 
 impl From<crate::blockexc::BlockExcToBehaviour> for BehaviourEvent {
@@ -50,9 +94,20 @@ impl From<crate::blockexc::BlockExcToBehaviour> for BehaviourEvent {
     }
 }
 
-impl From<identify::Event> for BehaviourEvent {
-    fn from(event: identify::Event) -> Self {
-        BehaviourEvent::Identify(Box::new(event))
+impl From<IdentifyShimEvent> for BehaviourEvent {
+    fn from(event: IdentifyShimEvent) -> Self {
+        match event {
+            IdentifyShimEvent::Identify(event) => BehaviourEvent::Identify(event),
+            IdentifyShimEvent::ReceivedSpr(peer_id, addrs) => {
+                BehaviourEvent::ReceivedSpr(peer_id, addrs)
+            }
+        }
+    }
+}
+
+impl From<ping::Event> for BehaviourEvent {
+    fn from(event: ping::Event) -> Self {
+        BehaviourEvent::Ping(event)
     }
 }
 
@@ -118,12 +173,13 @@ pub async fn create_swarm(
     let identify_config = IdentifyConfig::new("Archivist Node".to_string(), &keypair);
     let identify_behaviour = IdentifyBehaviour::new(identify_config);
 
-    // Create behavior: BlockExc + Identify
+    // Create behavior: BlockExc + Identify + Ping (RTTs feed peer selection)
     let (blockexc_behaviour, block_request_tx) =
         BlockExcBehaviour::new(block_store, mode, price_per_byte, metrics);
     let behaviour = Behaviour {
         blockexc: blockexc_behaviour,
         identify: identify_behaviour,
+        ping: ping::Behaviour::new(ping::Config::new()),
     };
 
     // Build swarm with TCP transport to match Archivist nodes.
@@ -148,10 +204,67 @@ pub async fn create_swarm(
     Ok((swarm, block_request_tx, keypair))
 }
 
+/// Create a new P2P swarm and immediately dial `initial_peers`
+///
+/// Returns (swarm, block_request_tx, keypair, dial_results), where
+/// `dial_results[i]` is the outcome of dialing `initial_peers[i]`. An `Ok`
+/// only means the dial is pending; connection failures surface later as
+/// swarm events.
+#[allow(clippy::type_complexity)]
+pub async fn create_swarm_with_peers(
+    block_store: Arc<BlockStore>,
+    mode: String,
+    price_per_byte: u64,
+    metrics: crate::metrics::Metrics,
+    initial_peers: Vec<Multiaddr>,
+) -> Result<
+    (
+        Swarm<Behaviour>,
+        tokio::sync::mpsc::UnboundedSender<crate::blockexc::BlockRequest>,
+        libp2p::identity::Keypair,
+        Vec<Result<(), DialError>>,
+    ),
+    P2PError,
+> {
+    let (mut swarm, block_request_tx, keypair) =
+        create_swarm(block_store, mode, price_per_byte, metrics).await?;
+
+    let dial_results = initial_peers
+        .into_iter()
+        .map(|addr| {
+            // The swarm only reports unsupported addresses asynchronously,
+            // so reject anything our TCP-only transport can't dial up front
+            if !is_tcp_dialable(&addr) {
+                return Err(DialError::Transport(vec![(
+                    addr.clone(),
+                    TransportError::MultiaddrNotSupported(addr),
+                )]));
+            }
+            swarm.dial(addr)
+        })
+        .collect();
+
+    Ok((swarm, block_request_tx, keypair, dial_results))
+}
+
+/// Whether `addr` starts with an IP or DNS host followed by a TCP port
+fn is_tcp_dialable(addr: &Multiaddr) -> bool {
+    let mut protocols = addr.iter();
+    matches!(
+        protocols.next(),
+        Some(
+            Protocol::Ip4(_)
+                | Protocol::Ip6(_)
+                | Protocol::Dns(_)
+                | Protocol::Dns4(_)
+                | Protocol::Dns6(_)
+        )
+    ) && matches!(protocols.next(), Some(Protocol::Tcp(_)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use libp2p::Multiaddr;
 
     #[tokio::test]
     async fn test_create_swarm() {
@@ -176,4 +289,33 @@ mod tests {
         let result = swarm.listen_on(addr);
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_create_swarm_with_peers_reports_dial_results() {
+        let block_store = Arc::new(BlockStore::new());
+        let metrics = crate::metrics::Metrics::new();
+        let valid: Multiaddr = "/ip4/127.0.0.1/tcp/1".parse().unwrap();
+        // Only TCP is configured, so a UDP address cannot be dialed at all
+        let invalid: Multiaddr = "/ip4/127.0.0.1/udp/1".parse().unwrap();
+        let (swarm, _block_request_tx, _keypair, dial_results) = create_swarm_with_peers(
+            block_store,
+            "altruistic".to_string(),
+            1,
+            metrics,
+            vec![valid, invalid],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(dial_results.len(), 2);
+        assert!(dial_results[0].is_ok());
+        assert!(dial_results[1].is_err());
+        assert_eq!(
+            swarm
+                .network_info()
+                .connection_counters()
+                .num_pending_outgoing(),
+            1
+        );
+    }
 }
@@ -3,12 +3,18 @@
 //! This module manages blocks that we're waiting for from peers,
 //! tracking retries, in-flight status, and providing async completion
 //! via oneshot channels.
+//!
+//! Requests given a timeout with [`PendingBlocksManager::set_timeout`] are
+//! swept by the expiry task: an expired request with retries left is handed
+//! to the retry callback (typically re-broadcasting the want via BlockExc),
+//! otherwise it is removed and the failure callback runs.
 
 use cid::Cid;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 use tracing::{trace, warn};
 
 use crate::storage::Block;
@@ -19,11 +25,24 @@ const DEFAULT_MAX_RETRIES: u32 = 3;
 /// Default interval between retry attempts (matches Nim: 500ms)
 const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
 
+/// How often the expiry task checks for timed-out requests
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Callback invoked with the CID of an expired block request
+pub type BlockCallback = Arc<dyn Fn(Cid) + Send + Sync>;
+
 /// Error returned when retries are exhausted for a block
 #[derive(Debug, thiserror::Error)]
 #[error("Retries exhausted for block: {0}")]
 pub struct RetriesExhaustedError(pub Cid);
 
+/// Errors from [`PendingBlocksManager::try_insert`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum PendingBlocksError {
+    #[error("Pending block limit reached")]
+    CapacityExceeded,
+}
+
 /// Tracks a single pending block request
 struct PendingBlock {
     /// The CID of the block we're waiting for
@@ -38,6 +57,12 @@ struct PendingBlock {
     in_flight: bool,
     /// When we started requesting this block (for metrics)
     start_time: Instant,
+    /// Timeout applied to each attempt, if set
+    timeout: Option<Duration>,
+    /// When the current attempt expires
+    expires_at: Option<tokio::time::Instant>,
+    /// Number of times the request was retried after expiring
+    retry_count: u32,
 }
 
 /// Internal state for the pending blocks manager
@@ -48,6 +73,60 @@ struct PendingBlocksState {
     max_retries: u32,
     /// Interval between retry attempts
     retry_interval: Duration,
+    /// Called when an expired request is retried
+    on_retry: Option<BlockCallback>,
+    /// Called when an expired request has no retries left
+    on_failure: Option<BlockCallback>,
+    /// Most requests [`PendingBlocksManager::try_insert`] accepts (None = unlimited)
+    max_pending: Option<usize>,
+}
+
+impl PendingBlocksState {
+    fn new(max_retries: u32, retry_interval: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            max_retries,
+            retry_interval,
+            on_retry: None,
+            on_failure: None,
+            max_pending: None,
+        }
+    }
+
+    /// Insert a pending request, see [`PendingBlocksManager::add_pending`]
+    fn add_pending(&mut self, cid: Cid) -> oneshot::Receiver<Block> {
+        // If already pending, we can't return a new receiver for the existing request
+        // In the Nim version, this returns the same Future handle
+        // For Rust, we need to either use broadcast channels or document that
+        // callers should check is_pending() first
+        if let Some(_existing) = self.pending.get(&cid) {
+            // Create a new receiver that will never complete
+            // In practice, callers should check is_pending() before calling this
+            let (tx, rx) = oneshot::channel();
+            drop(tx); // Drop sender immediately - this receiver will error
+            trace!(cid = ?cid, "Block already pending, returning dummy receiver");
+            return rx;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+
+        let pending_block = PendingBlock {
+            _cid: cid,
+            sender,
+            retries_left: self.max_retries,
+            last_attempt: Instant::now(),
+            in_flight: false,
+            start_time: Instant::now(),
+            timeout: None,
+            expires_at: None,
+            retry_count: 0,
+        };
+
+        self.pending.insert(cid, pending_block);
+        trace!(cid = ?cid, "Added pending block request");
+
+        receiver
+    }
 }
 
 /// Manages pending block requests with retry logic
@@ -62,26 +141,27 @@ pub struct PendingBlocksManager {
 impl PendingBlocksManager {
     /// Create a new pending blocks manager with default configuration
     pub fn new() -> Self {
-        Self {
-            state: Arc::new(Mutex::new(PendingBlocksState {
-                pending: HashMap::new(),
-                max_retries: DEFAULT_MAX_RETRIES,
-                retry_interval: DEFAULT_RETRY_INTERVAL,
-            })),
-        }
+        Self::with_config(DEFAULT_MAX_RETRIES, DEFAULT_RETRY_INTERVAL)
     }
 
     /// Create a new pending blocks manager with custom retry configuration
     pub fn with_config(max_retries: u32, retry_interval: Duration) -> Self {
         Self {
-            state: Arc::new(Mutex::new(PendingBlocksState {
-                pending: HashMap::new(),
+            state: Arc::new(Mutex::new(PendingBlocksState::new(
                 max_retries,
                 retry_interval,
-            })),
+            ))),
         }
     }
 
+    /// Create a manager whose [`Self::try_insert`] accepts at most
+    /// `max_pending` requests at a time
+    pub fn new_with_limit(max_pending: usize) -> Self {
+        let manager = Self::new();
+        manager.state.lock().unwrap().max_pending = Some(max_pending);
+        manager
+    }
+
     /// Check if a block is currently pending
     pub fn is_pending(&self, cid: &Cid) -> bool {
         let state = self.state.lock().unwrap();
@@ -93,36 +173,28 @@ impl PendingBlocksManager {
     /// If the block is already pending, returns a new receiver for the existing request.
     /// The receiver will be notified when the block arrives via `complete()`.
     pub fn add_pending(&self, cid: Cid) -> oneshot::Receiver<Block> {
-        let mut state = self.state.lock().unwrap();
+        self.state.lock().unwrap().add_pending(cid)
+    }
 
-        // If already pending, we can't return a new receiver for the existing request
-        // In the Nim version, this returns the same Future handle
-        // For Rust, we need to either use broadcast channels or document that
-        // callers should check is_pending() first
-        if let Some(_existing) = state.pending.get(&cid) {
-            // Create a new receiver that will never complete
-            // In practice, callers should check is_pending() before calling this
-            let (tx, rx) = oneshot::channel();
-            drop(tx); // Drop sender immediately - this receiver will error
-            trace!(cid = ?cid, "Block already pending, returning dummy receiver");
-            return rx;
+    /// Add a pending block request unless the manager is at capacity
+    ///
+    /// Behaves like [`Self::add_pending`], but a new request is rejected with
+    /// [`PendingBlocksError::CapacityExceeded`] once `max_pending` requests
+    /// are pending. Requests for blocks that are already pending are not
+    /// counted against the limit.
+    pub fn try_insert(&self, cid: Cid) -> Result<oneshot::Receiver<Block>, PendingBlocksError> {
+        // Check and insert under one lock so concurrent callers cannot
+        // overshoot the limit
+        let mut state = self.state.lock().unwrap();
+        if !state.pending.contains_key(&cid)
+            && state
+                .max_pending
+                .is_some_and(|max| state.pending.len() >= max)
+        {
+            trace!(cid = ?cid, "Pending block limit reached, rejecting request");
+            return Err(PendingBlocksError::CapacityExceeded);
         }
-
-        let (sender, receiver) = oneshot::channel();
-
-        let pending_block = PendingBlock {
-            _cid: cid,
-            sender,
-            retries_left: state.max_retries,
-            last_attempt: Instant::now(),
-            in_flight: false,
-            start_time: Instant::now(),
-        };
-
-        state.pending.insert(cid, pending_block);
-        trace!(cid = ?cid, "Added pending block request");
-
-        receiver
+        Ok(state.add_pending(cid))
     }
 
     /// Complete a pending block request, sending the block to waiters
@@ -231,6 +303,20 @@ impl PendingBlocksManager {
         state.pending.len()
     }
 
+    /// Most requests accepted by [`Self::try_insert`] (`usize::MAX` if unlimited)
+    pub fn capacity(&self) -> usize {
+        self.state.lock().unwrap().max_pending.unwrap_or(usize::MAX)
+    }
+
+    /// Requests [`Self::try_insert`] accepts before reaching capacity
+    pub fn remaining_capacity(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .max_pending
+            .unwrap_or(usize::MAX)
+            .saturating_sub(state.pending.len())
+    }
+
     /// Check if there are no pending blocks
     pub fn is_empty(&self) -> bool {
         let state = self.state.lock().unwrap();
@@ -253,6 +339,125 @@ impl PendingBlocksManager {
             .unwrap_or(false)
     }
 
+    /// Maximum number of retries per block
+    pub fn max_retries(&self) -> u32 {
+        self.state.lock().unwrap().max_retries
+    }
+
+    /// Number of times a block was retried after its request expired
+    ///
+    /// Returns 0 for blocks that are not pending.
+    pub fn retry_count(&self, cid: &Cid) -> u32 {
+        let state = self.state.lock().unwrap();
+        state.pending.get(cid).map(|p| p.retry_count).unwrap_or(0)
+    }
+
+    /// Expire a pending request if it is not completed within `duration`
+    ///
+    /// Each retry of the request gets the same timeout. Returns false if the
+    /// block is not pending.
+    pub fn set_timeout(&self, cid: &Cid, duration: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(pending) = state.pending.get_mut(cid) {
+            pending.timeout = Some(duration);
+            pending.expires_at = Some(tokio::time::Instant::now() + duration);
+            trace!(cid = ?cid, timeout_ms = duration.as_millis(), "Set pending block timeout");
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Set the callback invoked when an expired request is retried
+    ///
+    /// The callback should re-send the want, e.g. via
+    /// [`BlockExcBehaviour::broadcast_want`](crate::blockexc::BlockExcBehaviour::broadcast_want).
+    pub fn on_retry(&self, callback: impl Fn(Cid) + Send + Sync + 'static) {
+        self.state.lock().unwrap().on_retry = Some(Arc::new(callback));
+    }
+
+    /// Set the callback invoked when an expired request has no retries left
+    ///
+    /// The request has already been removed (its waiter receives a channel
+    /// error) when the callback runs.
+    pub fn on_failure(&self, callback: impl Fn(Cid) + Send + Sync + 'static) {
+        self.state.lock().unwrap().on_failure = Some(Arc::new(callback));
+    }
+
+    /// Retry or remove every request whose timeout has passed
+    ///
+    /// Returns the number of expired requests.
+    pub fn expire_timed_out(&self) -> usize {
+        let now = tokio::time::Instant::now();
+        let mut retried = Vec::new();
+        let mut failed = Vec::new();
+
+        let (on_retry, on_failure) = {
+            let mut state = self.state.lock().unwrap();
+            let expired: Vec<Cid> = state
+                .pending
+                .iter()
+                .filter(|(_, p)| p.expires_at.is_some_and(|at| at <= now))
+                .map(|(cid, _)| *cid)
+                .collect();
+
+            for cid in expired {
+                let pending = state
+                    .pending
+                    .get_mut(&cid)
+                    .expect("expired block is pending");
+                if pending.retries_left > 0 {
+                    pending.retries_left -= 1;
+                    pending.retry_count += 1;
+                    pending.in_flight = false;
+                    pending.last_attempt = Instant::now();
+                    pending.expires_at = pending.timeout.map(|timeout| now + timeout);
+                    warn!(
+                        cid = ?cid,
+                        retries_left = pending.retries_left,
+                        "Pending block request expired, retrying"
+                    );
+                    retried.push(cid);
+                } else {
+                    state.pending.remove(&cid);
+                    warn!(cid = ?cid, "Pending block request expired, retries exhausted");
+                    failed.push(cid);
+                }
+            }
+
+            (state.on_retry.clone(), state.on_failure.clone())
+        };
+
+        // Run callbacks without holding the lock so they can use the manager
+        if let Some(on_retry) = on_retry {
+            retried.iter().for_each(|cid| on_retry(*cid));
+        }
+        if let Some(on_failure) = on_failure {
+            failed.iter().for_each(|cid| on_failure(*cid));
+        }
+
+        retried.len() + failed.len()
+    }
+
+    /// Spawn the background task that expires timed-out requests every second
+    ///
+    /// The task stops once every handle to the manager has been dropped.
+    pub fn spawn_expiry_task(&self) -> JoinHandle<()> {
+        let state: Weak<Mutex<PendingBlocksState>> = Arc::downgrade(&self.state);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                PendingBlocksManager { state }.expire_timed_out();
+            }
+        })
+    }
+
     /// Clear all pending requests
     ///
     /// All waiters will receive channel errors.
@@ -262,6 +467,21 @@ impl PendingBlocksManager {
         trace!("Cleared all pending blocks");
     }
 
+    /// Remove every pending request and hand back its completion channel
+    ///
+    /// Used during shutdown so the caller decides how waiters are told;
+    /// dropping a returned sender fails its receiver immediately.
+    pub fn drain(&self) -> Vec<(Cid, oneshot::Sender<Block>)> {
+        let mut state = self.state.lock().unwrap();
+        let drained: Vec<_> = state
+            .pending
+            .drain()
+            .map(|(cid, pending)| (cid, pending.sender))
+            .collect();
+        trace!(count = drained.len(), "Drained pending blocks");
+        drained
+    }
+
     /// Remove a pending block request without completing it
     ///
     /// The waiter will receive a channel error.
@@ -484,6 +704,33 @@ mod tests {
         assert!(manager.is_empty());
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_drain_fails_waiters_without_waiting_for_timeout() {
+        let manager = PendingBlocksManager::new();
+        let block1 = create_test_block(b"block 1");
+        let block2 = create_test_block(b"block 2");
+
+        let rx1 = manager.add_pending(block1.cid);
+        let rx2 = manager.add_pending(block2.cid);
+        manager.set_timeout(&block1.cid, Duration::from_secs(60));
+
+        let mut drained = manager.drain();
+        drained.sort_by_key(|(cid, _)| cid.to_bytes());
+        let mut expected = vec![block1.cid, block2.cid];
+        expected.sort_by_key(|cid| cid.to_bytes());
+        assert_eq!(
+            drained.iter().map(|(cid, _)| *cid).collect::<Vec<_>>(),
+            expected
+        );
+        assert!(manager.is_empty());
+
+        drop(drained);
+        let start = tokio::time::Instant::now();
+        assert!(rx1.await.is_err());
+        assert!(rx2.await.is_err());
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
     #[test]
     fn test_duplicate_pending() {
         let manager = PendingBlocksManager::new();
@@ -501,6 +748,75 @@ mod tests {
         assert!(receiver2.try_recv().is_err());
     }
 
+    /// Collects the CIDs passed to a callback
+    fn recorder() -> (Arc<Mutex<Vec<Cid>>>, impl Fn(Cid) + Send + Sync + 'static) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        (seen, move |cid| sink.lock().unwrap().push(cid))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout_retries_then_fails() {
+        let manager = PendingBlocksManager::with_config(2, Duration::from_millis(0));
+        let (retried, on_retry) = recorder();
+        let (failed, on_failure) = recorder();
+        manager.on_retry(on_retry);
+        manager.on_failure(on_failure);
+        let _task = manager.spawn_expiry_task();
+
+        let cid = create_test_block(b"slow block").cid;
+        let receiver = manager.add_pending(cid);
+        assert!(manager.set_timeout(&cid, Duration::from_secs(5)));
+        assert_eq!(manager.max_retries(), 2);
+        assert_eq!(manager.retry_count(&cid), 0);
+
+        // Not expired yet
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(manager.retry_count(&cid), 0);
+
+        // First expiry re-queues the block
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(manager.retry_count(&cid), 1);
+        assert_eq!(manager.retries_remaining(&cid), Some(1));
+        assert_eq!(*retried.lock().unwrap(), vec![cid]);
+
+        // Second expiry uses the last retry
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(manager.retry_count(&cid), 2);
+        assert!(failed.lock().unwrap().is_empty());
+
+        // Third expiry gives up
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert!(!manager.is_pending(&cid));
+        assert_eq!(retried.lock().unwrap().len(), 2);
+        assert_eq!(*failed.lock().unwrap(), vec![cid]);
+        assert!(receiver.await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_completed_block_does_not_expire() {
+        let manager = PendingBlocksManager::new();
+        let (failed, on_failure) = recorder();
+        manager.on_failure(on_failure);
+        let _task = manager.spawn_expiry_task();
+
+        let block = create_test_block(b"fast block");
+        let untimed = create_test_block(b"no timeout").cid;
+        let receiver = manager.add_pending(block.cid);
+        manager.add_pending(untimed);
+        manager.set_timeout(&block.cid, Duration::from_secs(2));
+        assert!(!manager.set_timeout(&create_test_block(b"unknown").cid, Duration::from_secs(1)));
+
+        assert!(manager.complete(&block.cid, block.clone()));
+        assert_eq!(receiver.await.unwrap().cid, block.cid);
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(manager.expire_timed_out(), 0);
+        assert!(manager.is_pending(&untimed));
+        assert_eq!(manager.retry_count(&untimed), 0);
+        assert!(failed.lock().unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_multiple_blocks() {
         let manager = PendingBlocksManager::new();
@@ -530,4 +846,64 @@ mod tests {
         assert_eq!(received2.cid, block2.cid);
         assert_eq!(received3.cid, block3.cid);
     }
+
+    #[test]
+    fn test_try_insert_respects_limit() {
+        let max_pending = 4;
+        let manager = PendingBlocksManager::new_with_limit(max_pending);
+        assert_eq!(manager.capacity(), max_pending);
+
+        let mut receivers = Vec::new();
+        for i in 0..max_pending {
+            let cid = create_test_block(format!("block {}", i).as_bytes()).cid;
+            receivers.push(manager.try_insert(cid).unwrap());
+            assert_eq!(manager.remaining_capacity(), max_pending - i - 1);
+        }
+
+        let overflow = create_test_block(b"one too many").cid;
+        assert_eq!(
+            manager.try_insert(overflow).unwrap_err(),
+            PendingBlocksError::CapacityExceeded
+        );
+        assert!(!manager.is_pending(&overflow));
+        assert_eq!(manager.len(), max_pending);
+
+        // Completing a request frees a slot
+        let first = create_test_block(b"block 0");
+        assert!(manager.complete(&first.cid, first.clone()));
+        assert_eq!(manager.remaining_capacity(), 1);
+        assert!(manager.try_insert(overflow).is_ok());
+    }
+
+    #[test]
+    fn test_concurrent_try_insert_never_exceeds_limit() {
+        let max_pending = 8;
+        let manager = PendingBlocksManager::new_with_limit(max_pending);
+
+        let threads: Vec<_> = (0..32)
+            .map(|i| {
+                let manager = manager.clone();
+                std::thread::spawn(move || {
+                    let cid = create_test_block(format!("block {}", i).as_bytes()).cid;
+                    manager.try_insert(cid).is_ok()
+                })
+            })
+            .collect();
+        let accepted = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .filter(|&accepted| accepted)
+            .count();
+
+        assert_eq!(accepted, max_pending);
+        assert_eq!(manager.len(), max_pending);
+    }
+
+    #[test]
+    fn test_unlimited_manager_capacity() {
+        let manager = PendingBlocksManager::new();
+        assert_eq!(manager.capacity(), usize::MAX);
+        manager.add_pending(create_test_block(b"block").cid);
+        assert_eq!(manager.remaining_capacity(), usize::MAX - 1);
+    }
 }
@@ -0,0 +1,387 @@
+//! Manifest-aware block prefetching
+//!
+//! Sequential downloads read manifest blocks in order, so when block N is
+//! accessed the engine requests blocks N+1..=N+K in the background via the
+//! BlockExc client. Blocks already in the local store or already in flight
+//! are skipped.
+//!
+//! A download primes the engine with [`PrefetchEngine::hint_manifest`], which
+//! records the manifest's block layout; subsequent accesses to those blocks,
+//! reported through [`PrefetchEngine::get`] or
+//! [`PrefetchEngine::prefetch_after`], trigger the lookahead. A dataset's
+//! layout is forgotten once its last block is accessed, or after
+//! [`HINT_IDLE_TIMEOUT`] without any access.
+
+use cid::Cid;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+
+use crate::archivist_tree::ArchivistTree;
+use crate::blockexc::{BlockExcClient, BlockExcError};
+use crate::manifest::Manifest;
+use crate::storage::{Block, BlockStore, StorageError};
+
+/// Default number of blocks prefetched ahead of the accessed block
+pub const DEFAULT_LOOKAHEAD: usize = 4;
+
+/// How long a hinted dataset is kept without any of its blocks being accessed
+pub const HINT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Position of a block within a hinted dataset
+#[derive(Clone, Copy)]
+struct BlockPosition {
+    tree_cid: Cid,
+    index: usize,
+}
+
+/// Block layout of a hinted manifest
+struct HintedDataset {
+    block_cids: Arc<Vec<Cid>>,
+    /// Lowest index registered in `Hints::positions`
+    first_index: usize,
+    last_access: Instant,
+}
+
+/// Hinted datasets and the positions of their blocks
+#[derive(Default)]
+struct Hints {
+    /// Tree CID -> dataset layout
+    datasets: HashMap<Cid, HintedDataset>,
+    /// Hinted block CID -> position in its dataset
+    positions: HashMap<Cid, BlockPosition>,
+}
+
+impl Hints {
+    /// Forget a dataset and the positions of its blocks
+    fn forget(&mut self, tree_cid: &Cid) {
+        let Some(dataset) = self.datasets.remove(tree_cid) else {
+            return;
+        };
+        for cid in &dataset.block_cids[dataset.first_index..] {
+            // Blocks shared with another dataset may point there instead
+            if self
+                .positions
+                .get(cid)
+                .is_some_and(|position| position.tree_cid == *tree_cid)
+            {
+                self.positions.remove(cid);
+            }
+        }
+    }
+
+    /// Forget datasets not accessed within [`HINT_IDLE_TIMEOUT`]
+    fn forget_idle(&mut self, now: Instant) {
+        let idle: Vec<Cid> = self
+            .datasets
+            .iter()
+            .filter(|(_, dataset)| now.duration_since(dataset.last_access) >= HINT_IDLE_TIMEOUT)
+            .map(|(tree_cid, _)| *tree_cid)
+            .collect();
+        for tree_cid in idle {
+            debug!("Prefetch: forgetting idle manifest tree {}", tree_cid);
+            self.forget(&tree_cid);
+        }
+    }
+}
+
+/// Block store wrapper that prefetches upcoming manifest blocks
+pub struct PrefetchEngine {
+    block_store: Arc<BlockStore>,
+    blockexc: Arc<BlockExcClient>,
+    lookahead: usize,
+    hints: Arc<RwLock<Hints>>,
+    /// Blocks with a prefetch request outstanding
+    in_flight: Arc<RwLock<HashSet<Cid>>>,
+}
+
+impl PrefetchEngine {
+    /// Create a new prefetch engine
+    ///
+    /// # Arguments
+    ///
+    /// * `block_store` - Local block store prefetched blocks are written to
+    /// * `blockexc` - Client used to request blocks from the network
+    /// * `lookahead` - Number of blocks to prefetch after each access
+    pub fn new(
+        block_store: Arc<BlockStore>,
+        blockexc: Arc<BlockExcClient>,
+        lookahead: usize,
+    ) -> Self {
+        Self {
+            block_store,
+            blockexc,
+            lookahead,
+            hints: Arc::new(RwLock::new(Hints::default())),
+            in_flight: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    /// Get the underlying block store
+    pub fn block_store(&self) -> &Arc<BlockStore> {
+        &self.block_store
+    }
+
+    /// Record a manifest's block layout so accesses to its blocks trigger
+    /// prefetching
+    ///
+    /// The manifest's tree metadata block must be in the local store. Blocks
+    /// before `starting_index` are not registered, since a download starting
+    /// there will not read them. Returns the number of newly registered
+    /// blocks, which is zero when the manifest is already hinted from
+    /// `starting_index` or earlier.
+    pub async fn hint_manifest(
+        &self,
+        manifest: &Manifest,
+        starting_index: usize,
+    ) -> Result<usize, StorageError> {
+        let tree_cid = manifest.tree_cid;
+        let now = Instant::now();
+        {
+            let mut hints = self.hints.write().await;
+            hints.forget_idle(now);
+            if let Some(dataset) = hints.datasets.get_mut(&tree_cid) {
+                if dataset.first_index <= starting_index {
+                    dataset.last_access = now;
+                    return Ok(0);
+                }
+            }
+        }
+
+        let metadata_cid = manifest.metadata_cid().ok_or_else(|| {
+            StorageError::BlockNotFound("manifest has no metadata CID".to_string())
+        })?;
+        let metadata = self.block_store.get(&metadata_cid).await?;
+        let block_cids = ArchivistTree::deserialize_block_list(&metadata.data)
+            .map_err(|e| StorageError::DatabaseError(e.to_string()))?;
+
+        let mut hints = self.hints.write().await;
+        // Extend an existing hint backwards rather than registering it twice
+        let end = hints
+            .datasets
+            .get(&tree_cid)
+            .map_or(block_cids.len(), |dataset| dataset.first_index);
+        let start = starting_index.min(end);
+        for (index, cid) in block_cids.iter().enumerate().take(end).skip(start) {
+            hints
+                .positions
+                .insert(*cid, BlockPosition { tree_cid, index });
+        }
+        hints.datasets.insert(
+            tree_cid,
+            HintedDataset {
+                block_cids: Arc::new(block_cids),
+                first_index: start,
+                last_access: now,
+            },
+        );
+
+        let registered = end - start;
+        info!(
+            "Prefetch: registered {} blocks of manifest tree {} from index {}",
+            registered, tree_cid, starting_index
+        );
+        Ok(registered)
+    }
+
+    /// Get a block (locally or from the network) and prefetch the blocks that
+    /// follow it in its manifest
+    pub async fn get(&self, cid: &Cid) -> Result<Block, BlockExcError> {
+        let block = self.blockexc.request_block(*cid).await?;
+        self.prefetch_after(cid).await;
+        Ok(block)
+    }
+
+    /// Number of prefetch requests currently outstanding
+    pub async fn in_flight_count(&self) -> usize {
+        self.in_flight.read().await.len()
+    }
+
+    /// Number of manifests whose block layout is currently hinted
+    pub async fn hinted_count(&self) -> usize {
+        self.hints.read().await.datasets.len()
+    }
+
+    /// Report an access to `cid`, spawning background requests for the next
+    /// `lookahead` blocks of its dataset
+    ///
+    /// Accessing the last block of a dataset forgets the dataset's layout.
+    pub async fn prefetch_after(&self, cid: &Cid) {
+        let upcoming = {
+            let mut hints = self.hints.write().await;
+            let Some(position) = hints.positions.get(cid).copied() else {
+                return;
+            };
+            let Some(dataset) = hints.datasets.get_mut(&position.tree_cid) else {
+                return;
+            };
+            dataset.last_access = Instant::now();
+
+            let block_cids = dataset.block_cids.clone();
+            if position.index + 1 >= block_cids.len() {
+                debug!(
+                    "Prefetch: finished manifest tree {}, forgetting its layout",
+                    position.tree_cid
+                );
+                hints.forget(&position.tree_cid);
+            }
+
+            let start = (position.index + 1).min(block_cids.len());
+            let end = (start + self.lookahead).min(block_cids.len());
+            block_cids[start..end].to_vec()
+        };
+
+        for next in upcoming {
+            if self.block_store.has(&next).await {
+                continue;
+            }
+            if !self.in_flight.write().await.insert(next) {
+                continue;
+            }
+
+            debug!("Prefetch: requesting block {} after {}", next, cid);
+            let blockexc = self.blockexc.clone();
+            let block_store = self.block_store.clone();
+            let in_flight = self.in_flight.clone();
+            tokio::spawn(async move {
+                match blockexc.request_block(next).await {
+                    Ok(block) => {
+                        if let Err(e) = block_store.put(block).await {
+                            debug!("Prefetch: failed to store block {}: {}", next, e);
+                        }
+                    }
+                    Err(e) => debug!("Prefetch: block {} unavailable: {}", next, e),
+                }
+                in_flight.write().await.remove(&next);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::mock::{build_dataset, spawn_mock_swarm, RequestLog};
+    use crate::metrics::Metrics;
+    use std::time::Duration;
+
+    /// Set up a local store holding the manifest, its metadata and block 0,
+    /// with the remaining blocks served by a mock swarm
+    async fn setup(nblocks: usize) -> (PrefetchEngine, Manifest, Vec<Cid>, RequestLog) {
+        let remote = Arc::new(BlockStore::new());
+        let local = Arc::new(BlockStore::new());
+        let (manifest_cid, _, block_cids) = build_dataset(&remote, nblocks).await;
+
+        let manifest_block = remote.get(&manifest_cid).await.unwrap();
+        let manifest = Manifest::from_block(&manifest_block).unwrap();
+        let metadata_cid = manifest.metadata_cid().unwrap();
+        local.put(manifest_block).await.unwrap();
+        local
+            .put(remote.get(&metadata_cid).await.unwrap())
+            .await
+            .unwrap();
+        local
+            .put(remote.get(&block_cids[0]).await.unwrap())
+            .await
+            .unwrap();
+
+        let (tx, requested) = spawn_mock_swarm(remote, HashMap::new());
+        let client = BlockExcClient::new(local.clone(), Metrics::new(), 3, tx);
+        let engine = PrefetchEngine::new(local, Arc::new(client), DEFAULT_LOOKAHEAD);
+        (engine, manifest, block_cids, requested)
+    }
+
+    async fn wait_for_requests(requested: &RequestLog, count: usize) -> Vec<Cid> {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if requested.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("prefetch requests not sent");
+        requested.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_access_triggers_lookahead_prefetch() {
+        let (engine, manifest, block_cids, requested) = setup(10).await;
+        assert_eq!(engine.hint_manifest(&manifest, 0).await.unwrap(), 10);
+
+        engine.get(&block_cids[0]).await.unwrap();
+
+        let mut seen = wait_for_requests(&requested, 4).await;
+        seen.sort();
+        let mut expected = block_cids[1..=4].to_vec();
+        expected.sort();
+        assert_eq!(seen, expected);
+
+        // Prefetched blocks land in the local store
+        for cid in &block_cids[1..=4] {
+            for _ in 0..100 {
+                if engine.block_store().has(cid).await {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(engine.block_store().has(cid).await);
+        }
+        assert!(!engine.block_store().has(&block_cids[5]).await);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_skips_local_blocks() {
+        let (engine, manifest, block_cids, requested) = setup(10).await;
+        let remote_block = engine.blockexc.request_block(block_cids[2]).await.unwrap();
+        engine.block_store().put(remote_block).await.unwrap();
+        requested.lock().unwrap().clear();
+
+        engine.hint_manifest(&manifest, 0).await.unwrap();
+        engine.get(&block_cids[0]).await.unwrap();
+
+        wait_for_requests(&requested, 3).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut seen = requested.lock().unwrap().clone();
+        seen.sort();
+        let mut expected = vec![block_cids[1], block_cids[3], block_cids[4]];
+        expected.sort();
+        assert_eq!(seen, expected);
+    }
+
+    #[tokio::test]
+    async fn test_unhinted_blocks_do_not_prefetch() {
+        let (engine, _, block_cids, requested) = setup(6).await;
+
+        engine.get(&block_cids[0]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(requested.lock().unwrap().is_empty());
+        assert_eq!(engine.in_flight_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_finished_and_idle_datasets_are_forgotten() {
+        let (engine, manifest, block_cids, _) = setup(6).await;
+        assert_eq!(engine.hint_manifest(&manifest, 2).await.unwrap(), 4);
+        // Already covered from an earlier index
+        assert_eq!(engine.hint_manifest(&manifest, 3).await.unwrap(), 0);
+        // Extending the hint backwards only registers the missing blocks
+        assert_eq!(engine.hint_manifest(&manifest, 0).await.unwrap(), 2);
+        assert_eq!(engine.hints.read().await.positions.len(), 6);
+
+        // Reading the last block finishes the download
+        engine.prefetch_after(&block_cids[5]).await;
+        assert_eq!(engine.hinted_count().await, 0);
+        assert!(engine.hints.read().await.positions.is_empty());
+
+        engine.hint_manifest(&manifest, 0).await.unwrap();
+        let later = Instant::now() + HINT_IDLE_TIMEOUT;
+        engine.hints.write().await.forget_idle(later);
+        assert_eq!(engine.hinted_count().await, 0);
+        assert!(engine.hints.read().await.positions.is_empty());
+    }
+}
@@ -0,0 +1,380 @@
+//! Per-client rate limiting for the REST API
+//!
+//! [`RateLimitLayer`] gives every client IP a token bucket holding up to
+//! `burst_size` requests that refills at `requests_per_second`. A request
+//! that finds its bucket empty is answered with `429 Too Many Requests` and a
+//! `Retry-After` header giving the seconds until the next token.
+//!
+//! Clients are identified by the socket address axum records in
+//! [`ConnectInfo`]. When that address is one of the configured
+//! `trusted_proxies`, the rightmost `X-Forwarded-For` hop that isn't a
+//! trusted proxy is used instead; hops further left were written by the
+//! client and can't be believed.
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+use tracing::debug;
+
+/// Request header naming the original client when behind a proxy
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Largest number of tracked clients
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Number of clients kept once the table is full; evicting down to it
+/// spreads the cost of an eviction pass over many new clients
+const EVICT_TO_CLIENTS: usize = MAX_TRACKED_CLIENTS * 9 / 10;
+
+/// Rate limit applied to each client IP
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Sustained request rate
+    pub requests_per_second: u32,
+    /// Requests a client can make at once after being idle
+    pub burst_size: u32,
+    /// Reverse proxies whose `X-Forwarded-For` header is believed (empty
+    /// ignores the header)
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets of every client seen so far
+#[derive(Debug)]
+struct Limiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl Limiter {
+    /// Take a token from `client`'s bucket, or return how long until one is
+    /// available
+    fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&client) {
+            self.evict(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        *bucket = self.refill(*bucket, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// Shrink `buckets` to [`EVICT_TO_CLIENTS`], dropping full buckets first
+    /// and then the least recently seen clients
+    fn evict(&self, buckets: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        // Full buckets hold no state worth keeping
+        buckets.retain(|_, bucket| self.refill(*bucket, now).tokens < self.burst);
+        if buckets.len() <= EVICT_TO_CLIENTS {
+            return;
+        }
+
+        let mut seen: Vec<(Instant, IpAddr)> = buckets
+            .iter()
+            .map(|(client, bucket)| (bucket.updated, *client))
+            .collect();
+        let excess = seen.len() - EVICT_TO_CLIENTS;
+        seen.select_nth_unstable(excess - 1);
+        for (_, client) in &seen[..excess] {
+            buckets.remove(client);
+        }
+    }
+
+    fn refill(&self, bucket: Bucket, now: Instant) -> Bucket {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        Bucket {
+            tokens: (bucket.tokens + elapsed * self.rate).min(self.burst),
+            updated: now,
+        }
+    }
+}
+
+/// Layer that limits the request rate of each client IP
+#[derive(Clone, Debug)]
+pub struct RateLimitLayer {
+    limiter: Arc<Limiter>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    exempt_paths: Arc<Vec<String>>,
+}
+
+impl RateLimitLayer {
+    /// Limit every path to `config`; a zero rate or burst is treated as 1
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            limiter: Arc::new(Limiter {
+                rate: config.requests_per_second.max(1) as f64,
+                burst: config.burst_size.max(1) as f64,
+                buckets: Mutex::new(HashMap::new()),
+            }),
+            trusted_proxies: Arc::new(config.trusted_proxies),
+            exempt_paths: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Never limit requests for exactly these paths
+    pub fn with_exempt_paths(mut self, paths: &[&str]) -> Self {
+        self.exempt_paths = Arc::new(paths.iter().map(|path| path.to_string()).collect());
+        self
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service produced by [`RateLimitLayer`]
+#[derive(Clone, Debug)]
+pub struct RateLimit<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> Service<Request<Body>> for RateLimit<S>
+where
+    S: Service<Request<Body>, Response = Response>,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let path = request.uri().path();
+        if !self.layer.exempt_paths.iter().any(|exempt| exempt == path) {
+            let client = client_ip(&request, &self.layer.trusted_proxies);
+            if let Err(wait) = self.layer.limiter.check(client, Instant::now()) {
+                debug!("Rate limited API request from {} to {}", client, path);
+                return futures::future::ready(Ok(too_many_requests(wait))).boxed();
+            }
+        }
+        self.inner.call(request).boxed()
+    }
+}
+
+/// Client address from the connection, or from `X-Forwarded-For` when the
+/// connection comes from one of `trusted_proxies`
+///
+/// Requests without a recorded connection (e.g. in tests) come from the
+/// unspecified address.
+fn client_ip(request: &Request<Body>, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    // Walk back from the hop closest to us while it's a proxy we trust
+    let hops = request
+        .headers()
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in hops.into_iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(serde_json::json!({ "error": "Too many requests" })),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::util::ServiceExt;
+
+    const PROXY: &str = "192.0.2.1:443";
+
+    fn limited_app(config: RateLimitConfig) -> Router {
+        Router::new()
+            .route("/limited", get(|| async { "ok" }))
+            .route("/health", get(|| async { "ok" }))
+            .layer(RateLimitLayer::new(config).with_exempt_paths(&["/health"]))
+    }
+
+    /// Send a request through the trusted proxy on behalf of `client`
+    async fn send(app: &Router, path: &str, client: &str) -> Response {
+        let mut request = Request::get(path)
+            .header(FORWARDED_FOR_HEADER, client)
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(PROXY.parse::<SocketAddr>().unwrap()));
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    fn config(requests_per_second: u32, burst_size: u32) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_second,
+            burst_size,
+            trusted_proxies: vec![PROXY.parse::<SocketAddr>().unwrap().ip()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_burst_plus_one_is_rejected() {
+        let burst = 5;
+        let app = limited_app(config(1, burst));
+
+        let mut rejected = Vec::new();
+        for _ in 0..=burst {
+            let response = send(&app, "/limited", "10.0.0.1").await;
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                rejected.push(response);
+            }
+        }
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].headers()[header::RETRY_AFTER], "1");
+
+        // Other clients and exempt paths are unaffected
+        let response = send(&app, "/limited", "10.0.0.1, 192.168.0.1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(&app, "/health", "10.0.0.1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_forged_forwarded_for_is_ignored() {
+        let app = limited_app(config(1, 1));
+        let direct = |forged: &'static str| {
+            let app = app.clone();
+            async move {
+                let mut request = Request::get("/limited")
+                    .header(FORWARDED_FOR_HEADER, forged)
+                    .body(Body::empty())
+                    .unwrap();
+                request.extensions_mut().insert(ConnectInfo(
+                    "203.0.113.5:1234".parse::<SocketAddr>().unwrap(),
+                ));
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+
+        // A client that isn't a trusted proxy can't pick its own identity
+        assert_eq!(direct("10.0.0.1").await, StatusCode::OK);
+        assert_eq!(direct("10.0.0.2").await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let layer = RateLimitLayer::new(config(2, 1));
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let start = Instant::now();
+
+        assert!(layer.limiter.check(client, start).is_ok());
+        assert_eq!(
+            layer.limiter.check(client, start),
+            Err(Duration::from_millis(500))
+        );
+        assert!(layer
+            .limiter
+            .check(client, start + Duration::from_millis(500))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_client_ip_falls_back_to_socket_address() {
+        let proxy: SocketAddr = PROXY.parse().unwrap();
+        let trusted = [proxy.ip()];
+        let mut request = Request::get("/").body(Body::empty()).unwrap();
+        assert_eq!(
+            client_ip(&request, &trusted),
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        );
+
+        request.extensions_mut().insert(ConnectInfo(proxy));
+        assert_eq!(client_ip(&request, &trusted), proxy.ip());
+
+        // The rightmost hop that isn't a trusted proxy is the client; hops to
+        // its left were supplied by that client
+        request.headers_mut().insert(
+            FORWARDED_FOR_HEADER,
+            HeaderValue::from_static("10.9.9.9, 203.0.113.9, 192.0.2.1"),
+        );
+        assert_eq!(
+            client_ip(&request, &trusted),
+            "203.0.113.9".parse::<IpAddr>().unwrap()
+        );
+
+        // Without a trusted proxy the header is ignored
+        assert_eq!(client_ip(&request, &[]), proxy.ip());
+    }
+
+    #[test]
+    fn test_client_table_is_bounded() {
+        let layer = RateLimitLayer::new(config(1, 2));
+        let start = Instant::now();
+        for i in 0..MAX_TRACKED_CLIENTS as u32 + 10 {
+            let client = IpAddr::V4(Ipv4Addr::from(i));
+            assert!(layer
+                .limiter
+                .check(client, start + Duration::from_micros(i as u64))
+                .is_ok());
+        }
+
+        let buckets = layer.limiter.buckets.lock().unwrap();
+        assert!(buckets.len() <= MAX_TRACKED_CLIENTS);
+        // The most recent clients are kept, the oldest evicted
+        assert!(buckets.contains_key(&IpAddr::V4(Ipv4Addr::from(MAX_TRACKED_CLIENTS as u32 + 9))));
+        assert!(!buckets.contains_key(&IpAddr::V4(Ipv4Addr::from(0))));
+    }
+}
@@ -0,0 +1,243 @@
+//! Structured request logging for the REST API
+//!
+//! [`RequestLogLayer`] assigns every request a UUID, runs the handler inside
+//! an `api_request` span carrying that ID (so storage and BlockExc logs
+//! emitted while serving it can be correlated), echoes the ID back in the
+//! `X-Request-Id` response header and emits one `info` event per request:
+//!
+//! ```text
+//! request_id=… method=GET path=/health status=200 latency_ms=3 request_bytes=0 response_bytes=57
+//! ```
+//!
+//! [`json_layer`] writes these events as one JSON object per line.
+
+use axum::body::{Body, HttpBody};
+use axum::http::{header, HeaderMap, HeaderValue, Request};
+use axum::response::Response;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+use tracing::{info, info_span, Instrument, Metadata, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use uuid::Uuid;
+
+/// Response header carrying the request ID
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Tracing target of the per-request events
+pub const TARGET: &str = module_path!();
+
+/// Whether `metadata` belongs to a per-request event
+pub fn is_request_event(metadata: &Metadata<'_>) -> bool {
+    metadata.is_event() && metadata.target() == TARGET
+}
+
+/// Subscriber layer writing the per-request events to `writer` as one JSON
+/// object per line, ignoring all other events
+pub fn json_layer<S, W>(writer: W) -> impl tracing_subscriber::Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    use tracing_subscriber::Layer as _;
+
+    tracing_subscriber::fmt::layer()
+        .json()
+        .flatten_event(true)
+        .with_current_span(false)
+        .with_span_list(false)
+        .with_writer(writer)
+        .with_filter(filter_fn(is_request_event))
+}
+
+/// Layer that logs one structured event per API request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestLogLayer;
+
+impl<S> Layer<S> for RequestLogLayer {
+    type Service = RequestLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLog { inner }
+    }
+}
+
+/// Service produced by [`RequestLogLayer`]
+#[derive(Clone, Debug)]
+pub struct RequestLog<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestLog<S>
+where
+    S: Service<Request<Body>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let request_id = Uuid::new_v4();
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let request_bytes = body_len(request.headers(), request.body());
+        let started = Instant::now();
+
+        let span = info_span!("api_request", %request_id);
+        let response = span.in_scope(|| self.inner.call(request));
+
+        async move {
+            let mut response = response.await?;
+            if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
+                response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            }
+
+            info!(
+                %request_id,
+                %method,
+                path = %path,
+                status = response.status().as_u16(),
+                latency_ms = started.elapsed().as_millis() as u64,
+                request_bytes,
+                response_bytes = body_len(response.headers(), response.body()),
+                "API request"
+            );
+            Ok(response)
+        }
+        .instrument(span)
+        .boxed()
+    }
+}
+
+/// Body size from `Content-Length`, falling back to the body's exact size
+/// hint (0 for streamed bodies of unknown length)
+fn body_len(headers: &HeaderMap, body: &Body) -> usize {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .or_else(|| body.size_hint().exact().map(|len| len as usize))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use axum::Router;
+    use std::sync::{Arc, Mutex};
+    use tower::util::ServiceExt;
+    use tracing_test::traced_test;
+
+    #[tokio::test]
+    #[traced_test]
+    async fn test_request_is_logged_with_id_header() {
+        let app = Router::new()
+            .route(
+                "/echo",
+                post(|body: String| async move {
+                    info!("handling echo");
+                    body
+                }),
+            )
+            .layer(RequestLogLayer);
+
+        let response = app
+            .oneshot(
+                Request::post("/echo")
+                    .header(header::CONTENT_LENGTH, "5")
+                    .body(Body::from("hello"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let request_id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(Uuid::parse_str(&request_id).is_ok());
+
+        assert!(logs_contain(&format!("request_id={}", request_id)));
+        assert!(logs_contain("method=POST"));
+        assert!(logs_contain("path=/echo"));
+        assert!(logs_contain("status=200"));
+        assert!(logs_contain("latency_ms="));
+        assert!(logs_contain("request_bytes=5"));
+        assert!(logs_contain("response_bytes=5"));
+        // Handler logs run inside the request span
+        assert!(logs_contain(&format!(
+            "api_request{{request_id={}}}: neverust_core::request_log::tests: handling echo",
+            request_id
+        )));
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_layer_writes_one_object_per_request() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = SharedBuffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(json_layer(move || writer.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route(
+                "/echo",
+                post(|body: String| async move {
+                    info!("handling echo");
+                    body
+                }),
+            )
+            .layer(RequestLogLayer);
+        let response = app
+            .oneshot(
+                Request::post("/echo")
+                    .header(header::CONTENT_LENGTH, "5")
+                    .body(Body::from("hello"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+
+        // Only the request event is written, not the handler's log
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1, "{}", output);
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(event["request_id"], request_id);
+        assert_eq!(event["method"], "POST");
+        assert_eq!(event["path"], "/echo");
+        assert_eq!(event["status"], 200);
+        assert!(event["latency_ms"].is_u64());
+        assert_eq!(event["request_bytes"], 5);
+        assert_eq!(event["response_bytes"], 5);
+    }
+}
@@ -4,25 +4,220 @@
 //! the lifecycle of the P2P node.
 
 use crate::{
+    advertiser::Advertiser,
     api,
-    blockexc::BlockExcClient,
+    blockexc::{BlockExcClient, BlockExcConfig, WantEntry},
     botg::{BoTgConfig, BoTgProtocol},
     citadel::{fetch_flagship_trust_snapshot, DefederationGuardConfig, DefederationNode},
     citadel_sync::{configured_citadel_mesh_sync, spawn_citadel_mesh_sync},
     config::Config,
-    discovery::Discovery,
+    content_router,
+    discovery::{Discovery, DiscoveryConfig},
+    discovery_engine::DiscoveryEngine,
+    erasure::ErasureParams,
+    fetcher::BlockFetcher,
     marketplace::{MarketplaceRuntimeInfo, MarketplaceStore},
     metrics::Metrics,
-    p2p::{create_swarm, P2PError},
+    p2p::{create_swarm_with_peers, Behaviour, BehaviourStats, P2PError},
+    pending_blocks::PendingBlocksManager,
+    prefetch::{PrefetchEngine, DEFAULT_LOOKAHEAD},
+    startup::{self, CheckResult},
     storage::BlockStore,
     traffic,
 };
 use futures::StreamExt;
-use libp2p::{swarm::SwarmEvent, Multiaddr};
+use libp2p::{swarm::SwarmEvent, Multiaddr, PeerId, Swarm};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::signal;
-use tokio::sync::RwLock as AsyncRwLock;
-use tracing::{error, info, warn};
+use tokio::sync::{mpsc, oneshot, watch, RwLock as AsyncRwLock};
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+
+/// Capacity of the runtime command channel
+const COMMAND_CHANNEL_CAPACITY: usize = 64;
+
+/// Commands processed by the node's main event loop
+#[derive(Debug)]
+pub enum RuntimeCommand {
+    /// Dial a peer at the given address
+    Dial(Multiaddr),
+    /// Dial a known peer unless already connected or dialing it
+    DialPeer(PeerId, Multiaddr),
+    /// Close all connections to a peer
+    DisconnectPeer(PeerId),
+    /// Report network protocol statistics
+    Stats(oneshot::Sender<BehaviourStats>),
+    /// Report the blocks BlockExc is waiting for
+    Wantlist(oneshot::Sender<Vec<WantEntry>>),
+    /// Report the latest ping round-trip time of a peer
+    PeerLatency(PeerId, oneshot::Sender<Option<Duration>>),
+}
+
+/// Handle for controlling a running node's swarm
+///
+/// Commands are queued to the event loop started by [`run_node`], so peers
+/// can be added or dropped without restarting the node.
+#[derive(Debug, Clone)]
+pub struct RuntimeHandle {
+    command_tx: mpsc::Sender<RuntimeCommand>,
+}
+
+impl RuntimeHandle {
+    /// Create a handle and the receiver consumed by the event loop
+    pub fn channel() -> (Self, mpsc::Receiver<RuntimeCommand>) {
+        let (command_tx, command_rx) = mpsc::channel(COMMAND_CHANNEL_CAPACITY);
+        (Self { command_tx }, command_rx)
+    }
+
+    /// Dial a peer at `addr`
+    pub async fn dial(&self, addr: Multiaddr) -> Result<(), P2PError> {
+        self.send(RuntimeCommand::Dial(addr)).await
+    }
+
+    /// Queue a dial to `peer_id` at `addr` without waiting
+    ///
+    /// Usable from synchronous callbacks; fails if the command queue is full.
+    pub fn try_dial_peer(&self, peer_id: PeerId, addr: Multiaddr) -> Result<(), P2PError> {
+        self.command_tx
+            .try_send(RuntimeCommand::DialPeer(peer_id, addr))
+            .map_err(|e| P2PError::Swarm(format!("Cannot queue dial to {}: {}", peer_id, e)))
+    }
+
+    /// Close all connections to `peer_id`
+    pub async fn disconnect_peer(&self, peer_id: PeerId) -> Result<(), P2PError> {
+        self.send(RuntimeCommand::DisconnectPeer(peer_id)).await
+    }
+
+    /// Get statistics of the swarm, DHT and BoTG
+    pub async fn stats(&self) -> Result<BehaviourStats, P2PError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(RuntimeCommand::Stats(reply_tx)).await?;
+        reply_rx
+            .await
+            .map_err(|_| P2PError::Swarm("Node event loop dropped the stats request".to_string()))
+    }
+
+    /// Get the blocks BlockExc is currently waiting for
+    pub async fn wantlist(&self) -> Result<Vec<WantEntry>, P2PError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(RuntimeCommand::Wantlist(reply_tx)).await?;
+        reply_rx.await.map_err(|_| {
+            P2PError::Swarm("Node event loop dropped the wantlist request".to_string())
+        })
+    }
+
+    /// Get the latest ping round-trip time of `peer_id`
+    ///
+    /// `None` if the peer is not connected or has not answered a ping yet.
+    pub async fn peer_latency(&self, peer_id: PeerId) -> Result<Option<Duration>, P2PError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.send(RuntimeCommand::PeerLatency(peer_id, reply_tx))
+            .await?;
+        reply_rx
+            .await
+            .map_err(|_| P2PError::Swarm("Node event loop dropped the latency request".to_string()))
+    }
+
+    async fn send(&self, command: RuntimeCommand) -> Result<(), P2PError> {
+        self.command_tx
+            .send(command)
+            .await
+            .map_err(|_| P2PError::Swarm("Node event loop is not running".to_string()))
+    }
+}
+
+/// Apply a runtime command to the swarm
+fn handle_runtime_command(
+    swarm: &mut Swarm<Behaviour>,
+    discovery: Option<&Arc<Discovery>>,
+    botg: &Arc<BoTgProtocol>,
+    command: RuntimeCommand,
+) {
+    match command {
+        RuntimeCommand::Dial(addr) => {
+            info!("Dialing {} (runtime command)", addr);
+            if let Err(e) = swarm.dial(addr.clone()) {
+                warn!("Failed to dial {}: {}", addr, e);
+            }
+        }
+        RuntimeCommand::DialPeer(peer_id, addr) => {
+            use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+
+            let opts = DialOpts::peer_id(peer_id)
+                .addresses(vec![addr.clone()])
+                .condition(PeerCondition::DisconnectedAndNotDialing)
+                .build();
+            if let Err(e) = swarm.dial(opts) {
+                debug!("Not dialing {} at {}: {}", peer_id, addr, e);
+            }
+        }
+        RuntimeCommand::DisconnectPeer(peer_id) => {
+            if swarm.disconnect_peer_id(peer_id).is_ok() {
+                swarm.behaviour_mut().blockexc.expect_disconnect(peer_id);
+                info!("Disconnecting from {} (runtime command)", peer_id);
+            } else {
+                warn!("Cannot disconnect from {}: not connected", peer_id);
+            }
+        }
+        RuntimeCommand::Stats(reply) => {
+            let mut stats = swarm.behaviour().stats();
+            stats.connected_peers = swarm.connected_peers().count();
+            stats.discovery_routing_table_size =
+                discovery.map_or(0, |discovery| discovery.routing_table_size());
+
+            // BoTG state sits behind async locks; don't hold up the event loop
+            let botg = botg.clone();
+            tokio::spawn(async move {
+                stats.botg_pending_rollups = botg.stats().await.pending_rollups;
+                let _ = reply.send(stats);
+            });
+        }
+        RuntimeCommand::Wantlist(reply) => {
+            let _ = reply.send(swarm.behaviour().blockexc.wantlist_snapshot());
+        }
+        RuntimeCommand::PeerLatency(peer_id, reply) => {
+            let _ = reply.send(swarm.behaviour().blockexc.ping_rtt(&peer_id));
+        }
+    }
+}
+
+/// Publish the current listen addresses in the local ENR
+fn publish_listen_addrs(
+    discovery: Option<&Arc<Discovery>>,
+    listen_addrs: &std::sync::RwLock<Vec<Multiaddr>>,
+) {
+    let (Some(discovery), Ok(addrs)) = (discovery, listen_addrs.read()) else {
+        return;
+    };
+    if let Err(e) = discovery.set_listen_addrs(addrs.clone()) {
+        warn!("Failed to publish listen addresses in ENR: {}", e);
+    }
+}
+
+/// Wait until the process is asked to stop (SIGTERM or Ctrl+C)
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use signal::unix::{signal as unix_signal, SignalKind};
+
+        match unix_signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => info!("Received SIGTERM, shutting down..."),
+                    _ = signal::ctrl_c() => info!("Received Ctrl+C, shutting down..."),
+                }
+                return;
+            }
+            Err(e) => warn!("Failed to install SIGTERM handler: {}", e),
+        }
+    }
+
+    if let Err(e) = signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl+C: {}", e);
+    }
+    info!("Received Ctrl+C, shutting down...");
+}
 
 fn derive_citadel_host_id(config: &Config) -> u8 {
     if let Some(host_id) = config.citadel_host_id {
@@ -38,29 +233,254 @@ fn derive_citadel_host_id(config: &Config) -> u8 {
     blake3::hash(host_name.as_bytes()).as_bytes()[0]
 }
 
-/// Run the Archivist node with the given configuration
+/// Event loop task of a node started with [`run_node_with_handle`]
+///
+/// Completes once the node has shut down.
+pub type RunHandle = tokio::task::JoinHandle<()>;
+
+/// Handle to a node started with [`run_node_with_handle`]
+#[derive(Clone)]
+pub struct NodeHandle {
+    peer_id: PeerId,
+    listen_addrs: Arc<std::sync::RwLock<Vec<Multiaddr>>>,
+    api_addr: std::net::SocketAddr,
+    block_store: Arc<BlockStore>,
+    runtime: RuntimeHandle,
+    stop_tx: Arc<watch::Sender<bool>>,
+}
+
+impl NodeHandle {
+    /// libp2p peer ID of the node
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Addresses the node's swarm is listening on
+    pub fn listen_addrs(&self) -> Vec<Multiaddr> {
+        self.listen_addrs
+            .read()
+            .map(|addrs| addrs.clone())
+            .unwrap_or_default()
+    }
+
+    /// Address the node's REST API is listening on
+    pub fn api_addr(&self) -> std::net::SocketAddr {
+        self.api_addr
+    }
+
+    /// The node's block store
+    pub fn block_store(&self) -> Arc<BlockStore> {
+        self.block_store.clone()
+    }
+
+    /// Handle for sending commands to the node's event loop
+    pub fn runtime(&self) -> RuntimeHandle {
+        self.runtime.clone()
+    }
+
+    /// Ask the node to shut down
+    ///
+    /// The node's [`RunHandle`] completes once shutdown has finished.
+    pub fn shutdown(&self) {
+        let _ = self.stop_tx.send(true);
+    }
+}
+
+/// Run the Archivist node with the given configuration until SIGTERM or Ctrl+C
 pub async fn run_node(config: Config) -> Result<(), P2PError> {
+    let (run, node) = run_node_with_handle(config).await?;
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        node.shutdown();
+    });
+    run.await
+        .map_err(|e| P2PError::Swarm(format!("Node event loop failed: {}", e)))
+}
+
+/// Start the Archivist node in the background
+///
+/// Returns once the swarm is listening on TCP, so the node can be dialed at
+/// [`NodeHandle::listen_addrs`]. Ports set to 0 are picked by the OS, which
+/// lets several nodes run in one process.
+pub async fn run_node_with_handle(config: Config) -> Result<(RunHandle, NodeHandle), P2PError> {
+    // Validate erasure coding settings before starting anything
+    let erasure_params = if config.ec_k > 0 {
+        let params = ErasureParams::new(config.ec_k as usize, config.ec_m as usize)
+            .map_err(|e| P2PError::Swarm(format!("Invalid erasure coding config: {}", e)))?;
+        info!(
+            "Erasure coding uploads with ec_k={} ec_m={}",
+            params.ec_k, params.ec_m
+        );
+        Some(params)
+    } else {
+        None
+    };
+
     // Create block store with persistent redb backend
     let blocks_path = config.data_dir.join("blocks");
-    let block_store = Arc::new(
-        BlockStore::new_with_path(&blocks_path)
-            .map_err(|e| P2PError::Swarm(format!("Failed to open block store: {}", e)))?,
-    );
+    let mut block_store = BlockStore::new_with_path(&blocks_path)
+        .map_err(|e| P2PError::Swarm(format!("Failed to open block store: {}", e)))?;
+    block_store.set_verify_on_read(config.verify_on_read);
+    block_store.set_delete_corrupted_on_read(config.delete_corrupted_on_read);
+    let block_store = Arc::new(block_store);
     info!("Initialized persistent block store at {:?}", blocks_path);
+    if config.rebuild_index {
+        block_store
+            .rebuild_index()
+            .await
+            .map_err(|e| P2PError::Swarm(format!("Failed to rebuild block index: {}", e)))?;
+    }
+
+    // Startup health checks: refuse to start with an unusable store or API port
+    let (api_listener, api_port_check) =
+        match startup::bind_api_listener(&config.api_bind, config.api_port).await {
+            Ok(listener) => (Some(listener), Ok(())),
+            Err(e) => (None, Err(e)),
+        };
+    let mut checks = vec![
+        CheckResult::required(
+            "block_store",
+            startup::check_block_store(&block_store).await,
+        ),
+        CheckResult::required("api_port", api_port_check),
+    ];
+    if config.min_free_disk_bytes > 0 {
+        checks.push(CheckResult::optional(
+            "free_disk",
+            startup::check_free_disk(&config.data_dir, config.min_free_disk_bytes),
+        ));
+    }
+    startup::evaluate(checks)
+        .map_err(|e| P2PError::Swarm(format!("Startup health check failed: {}", e)))?;
+    let api_listener = api_listener.expect("api_port check passed");
+    let api_addr = api_listener
+        .local_addr()
+        .map_err(|e| P2PError::Transport(format!("Failed to read API address: {}", e)))?;
 
     // Create metrics collector
     let metrics = Metrics::new();
     info!("Initialized metrics collector");
 
-    // Create swarm first to get peer ID (pass metrics for P2P traffic tracking)
-    let (mut swarm, block_request_tx, keypair) = create_swarm(
+    // Fetch bootstrap nodes early
+    let bootstrap_addrs = if config.bootstrap_nodes.is_empty() && config.no_bootstrap {
+        info!("Bootstrap disabled, starting without bootstrap peers");
+        Vec::new()
+    } else if config.bootstrap_nodes.is_empty() {
+        info!("No bootstrap nodes configured, fetching...");
+        Config::fetch_bootstrap_nodes()
+            .await
+            .map_err(|e| P2PError::Transport(format!("Failed to fetch bootstrap nodes: {}", e)))?
+    } else {
+        // Resolve any SPR-formatted bootstrap nodes into multiaddrs
+        let mut resolved = Vec::new();
+        for node in &config.bootstrap_nodes {
+            if node.starts_with("spr:") {
+                match crate::spr::parse_spr_records(node) {
+                    Ok(records) => {
+                        for record in records {
+                            for addr in record.to_multiaddrs() {
+                                let addr_str = addr.to_string();
+                                // SPR contains UDP discovery addresses — convert to TCP
+                                let tcp_addr = addr_str.replace("/udp/", "/tcp/");
+                                let full_addr = format!("{}/p2p/{}", tcp_addr, record.peer_id);
+                                info!("Resolved SPR bootstrap: {}", full_addr);
+                                resolved.push(full_addr);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse SPR bootstrap node: {}", e);
+                    }
+                }
+            } else {
+                resolved.push(node.clone());
+            }
+        }
+        resolved
+    };
+
+    let bootstrap_multiaddrs: Vec<Multiaddr> = bootstrap_addrs
+        .iter()
+        .filter_map(|addr| match addr.parse() {
+            Ok(addr) => Some(addr),
+            Err(_) => {
+                warn!("Invalid bootstrap address: {}", addr);
+                None
+            }
+        })
+        .collect();
+
+    // Create swarm first to get peer ID (pass metrics for P2P traffic tracking).
+    // Bootstrap peers are dialed directly (Archivist doesn't use Kademlia -
+    // uses custom BlockExc protocol); with dial_on_startup disabled they are
+    // left for discovery or explicit dials instead
+    let initial_peers = if config.dial_on_startup {
+        bootstrap_multiaddrs.clone()
+    } else {
+        Vec::new()
+    };
+    let (mut swarm, block_request_tx, keypair, dial_results) = create_swarm_with_peers(
         block_store.clone(),
         config.mode.clone(),
         config.price_per_byte,
         metrics.clone(),
+        initial_peers.clone(),
     )
     .await?;
-    let peer_id = swarm.local_peer_id().to_string();
+    for (addr, result) in initial_peers.iter().zip(dial_results) {
+        match result {
+            Ok(()) => info!("Dialing bootstrap: {}", addr),
+            Err(e) => error!("Failed to dial bootstrap peer {}: {}", addr, e),
+        }
+    }
+    let local_peer_id = *swarm.local_peer_id();
+    let peer_id = local_peer_id.to_string();
+    swarm.behaviour_mut().blockexc.set_config(BlockExcConfig {
+        max_message_bytes: config.blockexc_max_message_bytes,
+        enable_v2: config.enable_blockexc_v2,
+        ..BlockExcConfig::default()
+    });
+    if config.peer_rate_limit_bytes > 0 {
+        info!(
+            "Limiting inbound BlockExc traffic to {} bytes/s per peer",
+            config.peer_rate_limit_bytes
+        );
+        swarm
+            .behaviour_mut()
+            .blockexc
+            .set_inbound_rate_limit(Some(config.peer_rate_limit_bytes));
+    }
+
+    // Periodically drop stale BlockPresence routes
+    let content_router = swarm.behaviour().blockexc.content_router();
+    {
+        let content_router = content_router.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(content_router::DEFAULT_EVICT_INTERVAL);
+            loop {
+                tick.tick().await;
+                content_router.evict_stale(content_router::DEFAULT_ROUTE_TTL);
+            }
+        });
+    }
+
+    // Periodically flush the block store to disk
+    if let Some(secs) = config.checkpoint_interval_secs.filter(|secs| *secs > 0) {
+        let block_store = block_store.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(std::time::Duration::from_secs(secs));
+            // The first tick completes immediately
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let started = std::time::Instant::now();
+                match block_store.checkpoint().await {
+                    Ok(()) => debug!("Block store checkpoint took {:?}", started.elapsed()),
+                    Err(e) => warn!("Block store checkpoint failed: {}", e),
+                }
+            }
+        });
+    }
 
     // Optional Citadel/Lens mode for defederation modeling and local control-plane APIs.
     let citadel_node: Option<Arc<AsyncRwLock<DefederationNode>>> = if config.citadel_mode {
@@ -152,18 +572,39 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
         }
     }
 
+    // Re-broadcast the wants of BlockExc requests that go unanswered
+    let want_retries = PendingBlocksManager::new();
+    let want_tx = swarm.behaviour().blockexc.want_sender();
+    want_retries.on_retry(move |cid| {
+        let _ = want_tx.send(cid);
+    });
+    let want_expiry_task = want_retries.spawn_expiry_task();
+
     // Initialize BlockExc client for requesting blocks from peers (via channel to swarm)
-    let _blockexc_client = Arc::new(BlockExcClient::new(
-        block_store.clone(),
-        metrics.clone(),
-        3, // max_retries
-        block_request_tx,
-    ));
-    info!("Initialized BlockExc client with 3 max retries");
+    let blockexc_client = Arc::new(
+        BlockExcClient::new(
+            block_store.clone(),
+            metrics.clone(),
+            want_retries.max_retries(),
+            block_request_tx,
+        )
+        .with_timeout(Duration::from_secs(config.blockexc_request_timeout_secs))
+        .with_want_retries(want_retries.clone()),
+    );
+    info!(
+        "Initialized BlockExc client with {} max retries and a {}s request timeout",
+        want_retries.max_retries(),
+        config.blockexc_request_timeout_secs
+    );
 
     // Initialize BoTG (Block-over-TGP) protocol for high-speed block exchange
-    // Use disc_port + 1 for BoTG since DiscV5 uses disc_port (8090)
-    let botg_port = config.disc_port + 1;
+    // Use disc_port + 1 for BoTG since DiscV5 uses disc_port (8090), or any
+    // free port when the discovery port is picked by the OS
+    let botg_port = if config.disc_port == 0 {
+        0
+    } else {
+        config.disc_port + 1
+    };
     info!("Initializing BoTG protocol on UDP port {}", botg_port);
     let botg_config = BoTgConfig {
         local_peer_id: rand::random(), // Generate random peer ID for TGP
@@ -192,7 +633,8 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
     let botg = Arc::new(botg_protocol);
 
     // Start BoTG receive loop
-    botg.clone().start_receive_loop();
+    let botg_receive_loop = botg.clone().start_receive_loop();
+    let botg_storage_listener = botg.clone().start_storage_listener();
     info!("BoTG ready for high-speed block exchange via UDP");
 
     // Initialize DiscV5 peer discovery on the main discovery port
@@ -205,7 +647,7 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
         config.disc_port
     );
 
-    let discv5_bootstrap = if config.bootstrap_nodes.is_empty() {
+    let discv5_bootstrap = if config.bootstrap_nodes.is_empty() && !config.no_bootstrap {
         match Config::fetch_discv5_bootstrap_nodes().await {
             Ok(nodes) => {
                 info!(
@@ -229,24 +671,60 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
     // Get announce addresses for this node
     let announce_addrs = config.announce_addrs.clone();
 
-    let discovery =
-        match Discovery::new(&keypair, discv5_addr, announce_addrs, discv5_bootstrap).await {
-            Ok(disc) => {
-                info!("DiscV5 initialized successfully on {}", discv5_addr);
-                Some(Arc::new(disc))
-            }
-            Err(e) => {
-                warn!(
-                    "Failed to initialize DiscV5: {}. Continuing without peer discovery.",
-                    e
-                );
-                None
-            }
-        };
+    let (api_runtime, mut command_rx) = RuntimeHandle::channel();
+    let node_runtime = api_runtime.clone();
+    let discovery = match Discovery::new(
+        &keypair,
+        discv5_addr,
+        announce_addrs,
+        discv5_bootstrap,
+        DiscoveryConfig::default(),
+    )
+    .await
+    {
+        Ok(mut disc) => {
+            info!("DiscV5 initialized successfully on {}", discv5_addr);
+            // Dial libp2p peers as the DHT discovers them
+            let runtime = api_runtime.clone();
+            disc.set_dial_callback(Arc::new(move |peer_id, addr| {
+                if let Err(e) = runtime.try_dial_peer(peer_id, addr) {
+                    debug!("{}", e);
+                }
+            }));
+            Some(Arc::new(disc))
+        }
+        Err(e) => {
+            warn!(
+                "Failed to initialize DiscV5: {}. Continuing without peer discovery.",
+                e
+            );
+            None
+        }
+    };
 
     // Start DiscV5 event loop in background when discovery is available.
     let discovery_ref = discovery.clone();
+    let mut discovery_engine = None;
+    let mut discovery_requests = None;
+    let mut advertiser = None;
     if let Some(discovery) = discovery {
+        let (mut engine, request_tx, engine_handle) = DiscoveryEngine::new(discovery.clone());
+        discovery_requests = Some(request_tx);
+        // Hold queries back while no peer is connected
+        let peer_metrics = metrics.clone();
+        engine.set_peer_count_source(Arc::new(move || peer_metrics.peer_connections()));
+        tokio::spawn(engine.run());
+        discovery_engine = Some(engine_handle);
+
+        // Announce stored blocks, and blocks as they are stored, to the DHT
+        let mut block_advertiser = Advertiser::from_config(discovery.clone(), &config)
+            .with_block_store(block_store.clone());
+        block_advertiser.set_metrics(metrics.clone());
+        match block_advertiser.start().await {
+            Ok(()) => advertiser = Some(block_advertiser),
+            Err(e) => warn!("Failed to start block advertiser: {}", e),
+        }
+
         tokio::spawn(async move {
             info!("Starting DiscV5 event loop");
             discovery.run().await;
@@ -269,7 +747,7 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
                 let mut added = 0;
                 for peer_str in &docker_peers {
                     if let Ok(peer_addr) = peer_str.parse() {
-                        botg.add_peer(peer_addr).await;
+                        botg.add_static_peer(peer_addr).await;
                         added += 1;
                     }
                 }
@@ -283,6 +761,7 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
 
     // Prepare listen addresses collection (will be populated as we receive NewListenAddr events)
     let listen_addrs = Arc::new(std::sync::RwLock::new(Vec::new()));
+    let spr_cache = api::SprCacheHandle::default();
 
     // Start REST API server in background with peer ID and BoTG
     let api_block_store = block_store.clone();
@@ -291,6 +770,7 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
     let api_botg = botg.clone();
     let api_keypair = Arc::new(keypair);
     let api_listen_addrs = listen_addrs.clone();
+    let api_spr_cache = spr_cache.clone();
     let api_port = config.api_port;
     let api_bind = config.api_bind.clone();
     let api_citadel = citadel_node.clone();
@@ -319,8 +799,28 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
         prover: config.prover,
     };
     let api_announce_addrs = config.announce_addrs.clone();
+    let api_runtime_config = Arc::new(api::RuntimeConfig::from(&config));
     let api_discovery = discovery_ref.clone();
-    tokio::spawn(async move {
+    let api_discovery_engine = discovery_engine.clone();
+    let api_content_router = content_router.clone();
+    let mut api_block_fetcher = BlockFetcher::new(block_store.clone(), blockexc_client.clone());
+    api_block_fetcher.set_strategy(config.fetch_strategy.clone());
+    api_block_fetcher.set_botg(botg.clone());
+    api_block_fetcher.set_http_fallback(
+        reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(2))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new()),
+        api::fallback_http_peer_urls(),
+    );
+    let api_block_fetcher = Arc::new(api_block_fetcher);
+    let api_prefetch = Arc::new(PrefetchEngine::new(
+        block_store.clone(),
+        blockexc_client.clone(),
+        DEFAULT_LOOKAHEAD,
+    ));
+    let (shutdown_tx, mut api_shutdown_rx) = watch::channel(false);
+    let api_task = tokio::spawn(async move {
         let app = api::create_router_with_runtime(
             api_block_store,
             api_metrics,
@@ -328,24 +828,34 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
             api_botg,
             api_keypair,
             api_listen_addrs,
-            api_citadel,
-            api_marketplace,
-            api_marketplace_info,
-            api_announce_addrs,
-            api_discovery,
+            api::ApiDeps {
+                citadel_node: api_citadel,
+                marketplace: api_marketplace,
+                marketplace_runtime: api_marketplace_info,
+                announce_addrs: api_announce_addrs,
+                discovery: api_discovery,
+                block_fetcher: Some(api_block_fetcher),
+                prefetch: Some(api_prefetch),
+                content_router: Some(api_content_router),
+                erasure: erasure_params,
+                runtime: Some(api_runtime),
+                discovery_engine: api_discovery_engine,
+                runtime_config: api_runtime_config,
+                spr_cache: api_spr_cache,
+            },
         );
-        let addr = format!("{}:{}", api_bind, api_port);
-        info!("Starting REST API on {}", addr);
+        info!("Starting REST API on {}:{}", api_bind, api_port);
 
-        match tokio::net::TcpListener::bind(&addr).await {
-            Ok(listener) => {
-                if let Err(e) = axum::serve(listener, app).await {
-                    error!("REST API server failed: {}", e);
-                }
-            }
-            Err(e) => {
-                error!("Failed to bind REST API listener on {}: {}", addr, e);
-            }
+        let shutdown = async move {
+            let _ = api_shutdown_rx.wait_for(|stopping| *stopping).await;
+        };
+        // Connection info lets the rate limiter tell clients apart
+        let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+        if let Err(e) = axum::serve(api_listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+        {
+            error!("REST API server failed: {}", e);
         }
     });
 
@@ -403,200 +913,371 @@ pub async fn run_node(config: Config) -> Result<(), P2PError> {
 
     info!("Node started with peer ID: {}", swarm.local_peer_id());
 
-    // Fetch bootstrap nodes early
-    let bootstrap_addrs = if config.bootstrap_nodes.is_empty() {
-        info!("No bootstrap nodes configured, fetching...");
-        Config::fetch_bootstrap_nodes()
-            .await
-            .map_err(|e| P2PError::Transport(format!("Failed to fetch bootstrap nodes: {}", e)))?
-    } else {
-        // Resolve any SPR-formatted bootstrap nodes into multiaddrs
-        let mut resolved = Vec::new();
-        for node in &config.bootstrap_nodes {
-            if node.starts_with("spr:") {
-                match crate::spr::parse_spr_records(node) {
-                    Ok(records) => {
-                        for (peer_id, addrs) in records {
-                            for addr in addrs {
-                                let addr_str = addr.to_string();
-                                // SPR contains UDP discovery addresses — convert to TCP
-                                let tcp_addr = addr_str.replace("/udp/", "/tcp/");
-                                let full_addr = format!("{}/p2p/{}", tcp_addr, peer_id);
-                                info!("Resolved SPR bootstrap: {}", full_addr);
-                                resolved.push(full_addr);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Failed to parse SPR bootstrap node: {}", e);
-                    }
-                }
-            } else {
-                resolved.push(node.clone());
-            }
-        }
-        resolved
-    };
+    // Bootstrap reachability is optional, so check it without delaying startup
+    tokio::spawn(async move {
+        let outcome = startup::check_bootstrap_reachable(
+            &bootstrap_multiaddrs,
+            startup::BOOTSTRAP_CHECK_TIMEOUT,
+        )
+        .await;
+        let _ = startup::evaluate(vec![CheckResult::optional("bootstrap", outcome)]);
+    });
 
-    // Track if we've established listen addresses
-    let mut tcp_listening = false;
-    let mut bootstrapped = false;
-
-    // Main event loop
-    loop {
-        tokio::select! {
-            event = swarm.select_next_some() => {
-                match event {
-                    SwarmEvent::NewListenAddr { address, .. } => {
-                        // Track transport types
-                        if address.to_string().contains("/tcp/") {
-                            info!("Listening on TCP: {}", address);
-                            tcp_listening = true;
-                        } else {
-                            info!("Listening on {}", address);
-                        }
+    let (stop_tx, mut stop_rx) = watch::channel(false);
+    let (listening_tx, listening_rx) = oneshot::channel();
+    let node = NodeHandle {
+        peer_id: local_peer_id,
+        listen_addrs: listen_addrs.clone(),
+        api_addr,
+        block_store: block_store.clone(),
+        runtime: node_runtime,
+        stop_tx: Arc::new(stop_tx),
+    };
 
-                        // Add to listen addresses collection
-                        if let Ok(mut addrs) = listen_addrs.write() {
-                            addrs.push(address.clone());
-                        } else {
-                            warn!("Failed to record listen address due to poisoned lock");
-                        }
+    let run = tokio::spawn(async move {
+        let mut listening_tx = Some(listening_tx);
 
-                        // Once TCP is listening, dial bootstrap nodes
-                        if tcp_listening && !bootstrapped {
-                            info!("TCP transport ready, dialing bootstrap nodes...");
-
-                            // Dial all bootstrap peers directly
-                            // (Archivist doesn't use Kademlia - uses custom BlockExc protocol)
-                            for node_addr in &bootstrap_addrs {
-                                info!("Dialing bootstrap: {}", node_addr);
-                                if let Ok(addr) = node_addr.parse::<Multiaddr>() {
-                                    if let Err(e) = swarm.dial(addr.clone()) {
-                                        error!("Failed to dial bootstrap peer {}: {:?}", node_addr, e);
-                                    } else {
-                                        info!("Dialing {}", node_addr);
-                                    }
-                                } else {
-                                    warn!("Invalid bootstrap address: {}", node_addr);
+        // Main event loop
+        loop {
+            tokio::select! {
+                event = swarm.select_next_some() => {
+                    match event {
+                        SwarmEvent::NewListenAddr { address, .. } => {
+                            // Track transport types
+                            if address.to_string().contains("/tcp/") {
+                                info!("Listening on TCP: {}", address);
+                                if let Some(listening_tx) = listening_tx.take() {
+                                    let _ = listening_tx.send(());
                                 }
+                            } else {
+                                info!("Listening on {}", address);
                             }
 
-                            bootstrapped = true;
+                            // Add to listen addresses collection
+                            if let Ok(mut addrs) = listen_addrs.write() {
+                                addrs.push(address.clone());
+                            } else {
+                                warn!("Failed to record listen address due to poisoned lock");
+                            }
+                            spr_cache.invalidate();
+                            publish_listen_addrs(discovery_ref.as_ref(), &listen_addrs);
                         }
-                    }
-                    SwarmEvent::ConnectionEstablished {
-                        peer_id,
-                        endpoint,
-                        ..
-                    } => {
-                        info!(
-                            "Connected to peer: {} at {}",
+                        SwarmEvent::ExpiredListenAddr { address, .. } => {
+                            info!("No longer listening on {}", address);
+                            if let Ok(mut addrs) = listen_addrs.write() {
+                                addrs.retain(|addr| *addr != address);
+                            }
+                            publish_listen_addrs(discovery_ref.as_ref(), &listen_addrs);
+                        }
+                        SwarmEvent::ConnectionEstablished {
                             peer_id,
-                            endpoint.get_remote_address()
-                        );
-                        metrics.peer_connected();
-                    }
-                    SwarmEvent::ConnectionClosed {
-                        peer_id,
-                        cause,
-                        ..
-                    } => {
-                        // Idle connection timeouts are expected when neither side has blocks to exchange
-                        // Only warn on unexpected disconnect reasons
-                        if let Some(ref error) = cause {
-                            if error.to_string().contains("UnexpectedEof") {
-                                info!("Connection closed with {} (idle timeout)", peer_id);
+                            endpoint,
+                            ..
+                        } => {
+                            info!(
+                                "Connected to peer: {} at {}",
+                                peer_id,
+                                endpoint.get_remote_address()
+                            );
+                            metrics.peer_connected();
+                        }
+                        SwarmEvent::ConnectionClosed {
+                            peer_id,
+                            cause,
+                            ..
+                        } => {
+                            // Idle connection timeouts are expected when neither side has blocks to exchange
+                            // Only warn on unexpected disconnect reasons
+                            if let Some(ref error) = cause {
+                                if error.to_string().contains("UnexpectedEof") {
+                                    info!("Connection closed with {} (idle timeout)", peer_id);
+                                } else {
+                                    warn!("Connection closed with {}: {:?}", peer_id, cause);
+                                }
                             } else {
-                                warn!("Connection closed with {}: {:?}", peer_id, cause);
+                                info!("Connection gracefully closed with {}", peer_id);
                             }
-                        } else {
-                            info!("Connection gracefully closed with {}", peer_id);
+                            metrics.peer_disconnected();
                         }
-                        metrics.peer_disconnected();
-                    }
-                    SwarmEvent::Behaviour(event) => {
-                        use crate::p2p::BehaviourEvent;
-                        match event {
-                            BehaviourEvent::BlockExc(blockexc_event) => {
-                                use crate::blockexc::BlockExcToBehaviour;
-
-                                match blockexc_event {
-                                    BlockExcToBehaviour::BlockReceived { cid, data } => {
-                                        info!(
-                                            "Block received via BlockExc: {} ({} bytes)",
-                                            cid,
-                                            data.len()
-                                        );
-
-                                        metrics.block_received(data.len());
-
-                                        // Auto-provide the block to the DHT
-                                        if let Some(ref disc) = discovery_ref {
-                                            let disc = disc.clone();
-                                            let cid_clone = cid;
-                                            tokio::spawn(async move {
-                                                if let Err(e) = disc.provide(&cid_clone).await {
-                                                    warn!("Failed to provide block to DHT: {}", e);
-                                                }
-                                            });
+                        SwarmEvent::Behaviour(event) => {
+                            use crate::p2p::BehaviourEvent;
+                            match event {
+                                BehaviourEvent::BlockExc(blockexc_event) => {
+                                    use crate::blockexc::BlockExcToBehaviour;
+
+                                    match blockexc_event {
+                                        BlockExcToBehaviour::BlocksReceived { blocks } => {
+                                            info!(
+                                                "Blocks received via BlockExc: {}",
+                                                blocks.len()
+                                            );
+                                            // Counted on storage and announced by the advertiser
+                                        }
+                                        BlockExcToBehaviour::BlockPresence { cid, has_block } => {
+                                            info!(
+                                                "Block presence notification: {} - {}",
+                                                cid,
+                                                if has_block { "available" } else { "not available" }
+                                            );
+                                            // Future enhancement: track which peers have which blocks
+                                            // for smarter routing and retry logic
+                                        }
+                                        BlockExcToBehaviour::RequestCompleted { .. }
+                                        | BlockExcToBehaviour::ProtocolNegotiated { .. } => {
+                                            // Tracked by the behaviour itself
                                         }
-                                    }
-                                    BlockExcToBehaviour::BlockPresence { cid, has_block } => {
-                                        info!(
-                                            "Block presence notification: {} - {}",
-                                            cid,
-                                            if has_block { "available" } else { "not available" }
-                                        );
-                                        // Future enhancement: track which peers have which blocks
-                                        // for smarter routing and retry logic
                                     }
                                 }
-                            }
-                            BehaviourEvent::Identify(identify_event) => {
-                                use libp2p::identify::Event;
-                                match *identify_event {
-                                    Event::Received { peer_id, info, .. } => {
-                                        info!(
-                                            "Identified peer {}: protocol_version={}, agent_version={}",
-                                            peer_id, info.protocol_version, info.agent_version
-                                        );
-
-                                        // Log supported protocols
-                                        info!("Peer {} protocols: {:?}", peer_id, info.protocols);
-                                    }
-                                    Event::Sent { peer_id, .. } => {
-                                        info!("Sent identify info to {}", peer_id);
-                                    }
-                                    Event::Pushed { peer_id, .. } => {
-                                        info!("Pushed identify update to {}", peer_id);
-                                    }
-                                    Event::Error { peer_id, error, .. } => {
-                                        warn!("Identify error with {}: {}", peer_id, error);
+                                BehaviourEvent::Identify(identify_event) => {
+                                    use libp2p::identify::Event;
+                                    match *identify_event {
+                                        Event::Received { peer_id, info, .. } => {
+                                            info!(
+                                                "Identified peer {}: protocol_version={}, agent_version={}",
+                                                peer_id, info.protocol_version, info.agent_version
+                                            );
+
+                                            // Log supported protocols
+                                            info!("Peer {} protocols: {:?}", peer_id, info.protocols);
+                                        }
+                                        Event::Sent { peer_id, .. } => {
+                                            info!("Sent identify info to {}", peer_id);
+                                        }
+                                        Event::Pushed { peer_id, .. } => {
+                                            info!("Pushed identify update to {}", peer_id);
+                                        }
+                                        Event::Error { peer_id, error, .. } => {
+                                            warn!("Identify error with {}: {}", peer_id, error);
+                                        }
                                     }
                                 }
+                                BehaviourEvent::ReceivedSpr(peer_id, addrs) => {
+                                    info!("Received SPR from {}: {:?}", peer_id, addrs);
+                                }
+                                BehaviourEvent::Ping(ping_event) => match ping_event.result {
+                                    Ok(rtt) => swarm
+                                        .behaviour_mut()
+                                        .blockexc
+                                        .record_ping_rtt(ping_event.peer, rtt),
+                                    Err(e) => debug!("Ping to {} failed: {}", ping_event.peer, e),
+                                },
                             }
                         }
+                        SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
+                            info!("Incoming connection from {} on {}", send_back_addr, local_addr);
+                        }
+                        SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                            error!("Outgoing connection error to {:?}: {}", peer_id, error);
+                        }
+                        SwarmEvent::IncomingConnectionError { local_addr, send_back_addr, error, .. } => {
+                            error!("Incoming connection error from {} on {}: {}", send_back_addr, local_addr, error);
+                        }
+                        _ => {}
                     }
-                    SwarmEvent::IncomingConnection { local_addr, send_back_addr, .. } => {
-                        info!("Incoming connection from {} on {}", send_back_addr, local_addr);
-                    }
-                    SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
-                        error!("Outgoing connection error to {:?}: {}", peer_id, error);
-                    }
-                    SwarmEvent::IncomingConnectionError { local_addr, send_back_addr, error, .. } => {
-                        error!("Incoming connection error from {} on {}: {}", send_back_addr, local_addr, error);
-                    }
-                    _ => {}
+                }
+                Some(command) = command_rx.recv() => {
+                    handle_runtime_command(&mut swarm, discovery_ref.as_ref(), &botg, command);
+                }
+                Ok(_) = stop_rx.wait_for(|stopping| *stopping) => {
+                    break;
                 }
             }
-            _ = signal::ctrl_c() => {
-                info!("Received Ctrl+C, shutting down...");
+        }
+
+        // Stop taking new work, then give in-progress work until the drain
+        // timeout to finish
+        let drain_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+        let deadline = Instant::now() + drain_timeout;
+        info!("Shutting down (drain timeout {:?})", drain_timeout);
+        let _ = shutdown_tx.send(true);
+
+        // Fail BlockExc requests first so API requests waiting on them finish
+        want_expiry_task.abort();
+        let dropped = swarm.behaviour_mut().blockexc.drain_pending_requests();
+        info!("Shutdown: dropped {} pending BlockExc requests", dropped);
+
+        info!("Shutdown: waiting for REST API requests to finish");
+        let api_finished = async {
+            let mut api_task = api_task;
+            // Keep the swarm polled so connections stay serviced meanwhile
+            loop {
+                tokio::select! {
+                    _ = &mut api_task => break,
+                    _ = swarm.select_next_some() => {}
+                }
+            }
+        };
+        if tokio::time::timeout_at(deadline, api_finished)
+            .await
+            .is_err()
+        {
+            warn!("Shutdown: REST API requests still running at drain timeout");
+        }
+
+        if let Some(engine) = &discovery_engine {
+            engine.shutdown().await;
+        }
+        // Close the engine's request queue now that nothing may queue more
+        drop(discovery_requests);
+
+        if let Some(advertiser) = &advertiser {
+            info!("Shutdown: stopping block advertiser");
+            advertiser.stop().await;
+        }
+
+        info!("Shutdown: stopping BoTG receive loop");
+        botg_receive_loop.abort();
+        botg_storage_listener.abort();
+
+        info!(
+            "Shutdown: flushing block store ({} writes in progress)",
+            block_store.pending_writes()
+        );
+        match block_store
+            .flush(deadline.saturating_duration_since(Instant::now()))
+            .await
+        {
+            Ok(()) => info!("Shutdown: block store flushed"),
+            Err(e) => warn!("Shutdown: block store flush incomplete: {}", e),
+        }
+
+        let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+        info!("Shutdown: closing connections to {} peers", peers.len());
+        for peer_id in peers {
+            swarm.behaviour_mut().blockexc.expect_disconnect(peer_id);
+            let _ = swarm.disconnect_peer_id(peer_id);
+        }
+        while swarm.connected_peers().next().is_some() {
+            if tokio::time::timeout_at(deadline, swarm.select_next_some())
+                .await
+                .is_err()
+            {
+                warn!("Shutdown: connections still open at drain timeout");
                 break;
             }
         }
+
+        info!("Node stopped");
+    });
+
+    // Wait until the swarm is listening so the node can be dialed
+    let _ = listening_rx.await;
+    Ok((run, node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::p2p::create_swarm;
+    use std::time::Duration;
+
+    async fn test_swarm() -> Swarm<Behaviour> {
+        let (swarm, _, _) = create_swarm(
+            Arc::new(BlockStore::new()),
+            "altruistic".to_string(),
+            1,
+            Metrics::new(),
+        )
+        .await
+        .unwrap();
+        swarm
+    }
+
+    #[tokio::test]
+    async fn test_runtime_commands_reach_swarm() {
+        let mut listener = test_swarm().await;
+        let mut dialer = test_swarm().await;
+        let listener_id = *listener.local_peer_id();
+
+        listener
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let listen_addr = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = listener.select_next_some().await {
+                break address;
+            }
+        };
+
+        let botg = Arc::new(BoTgProtocol::new(BoTgConfig::default()));
+        let (handle, mut command_rx) = RuntimeHandle::channel();
+        handle.dial(listen_addr.clone()).await.unwrap();
+
+        // Commands drive the swarm as in the event loop
+        let dial = command_rx.recv().await.unwrap();
+        assert!(matches!(&dial, RuntimeCommand::Dial(addr) if *addr == listen_addr));
+        handle_runtime_command(&mut dialer, None, &botg, dial);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                tokio::select! {
+                    event = dialer.select_next_some() => {
+                        if let SwarmEvent::ConnectionEstablished { peer_id, .. } = event {
+                            assert_eq!(peer_id, listener_id);
+                            break;
+                        }
+                    }
+                    _ = listener.select_next_some() => {}
+                }
+            }
+        })
+        .await
+        .expect("dial command did not connect");
+
+        let (stats, ()) = tokio::join!(handle.stats(), async {
+            let command = command_rx.recv().await.unwrap();
+            assert!(matches!(command, RuntimeCommand::Stats(_)));
+            handle_runtime_command(&mut dialer, None, &botg, command);
+        });
+        let stats = stats.unwrap();
+        assert_eq!(stats.connected_peers, 1);
+        assert_eq!(stats.blockexc_pending_requests, 0);
+        assert_eq!(stats.discovery_routing_table_size, 0);
+        assert_eq!(stats.botg_pending_rollups, 0);
+
+        // Discovered peers that are already connected are not dialed again
+        handle
+            .try_dial_peer(listener_id, listen_addr.clone())
+            .unwrap();
+        let dial_peer = command_rx.recv().await.unwrap();
+        assert!(matches!(&dial_peer, RuntimeCommand::DialPeer(peer, _) if *peer == listener_id));
+        handle_runtime_command(&mut dialer, None, &botg, dial_peer);
+        assert_eq!(
+            dialer
+                .network_info()
+                .connection_counters()
+                .num_pending_outgoing(),
+            0
+        );
+
+        handle.disconnect_peer(listener_id).await.unwrap();
+        let disconnect = command_rx.recv().await.unwrap();
+        assert!(matches!(disconnect, RuntimeCommand::DisconnectPeer(peer) if peer == listener_id));
+        handle_runtime_command(&mut dialer, None, &botg, disconnect);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                tokio::select! {
+                    event = dialer.select_next_some() => {
+                        if let SwarmEvent::ConnectionClosed { peer_id, .. } = event {
+                            assert_eq!(peer_id, listener_id);
+                            break;
+                        }
+                    }
+                    _ = listener.select_next_some() => {}
+                }
+            }
+        })
+        .await
+        .expect("disconnect command did not close the connection");
+        assert!(!dialer.is_connected(&listener_id));
     }
 
-    info!("Node stopped");
-    Ok(())
+    #[tokio::test]
+    async fn test_runtime_handle_errors_when_loop_stopped() {
+        let (handle, command_rx) = RuntimeHandle::channel();
+        drop(command_rx);
+        assert!(matches!(
+            handle.dial("/ip4/127.0.0.1/tcp/1".parse().unwrap()).await,
+            Err(P2PError::Swarm(_))
+        ));
+    }
 }
@@ -18,14 +18,24 @@
 //! - Standard `PeerRecord::from_signed_envelope` may fail with "payload extraction error"
 //! - Alternative: manual protobuf parsing of the envelope payload
 //!
+//! ## Signatures
+//! Parsed records are verified: the envelope signature must cover the
+//! payload (in the libp2p envelope signing format) under the envelope's
+//! public key, and the peer ID inside the record must belong to that key.
+//!
 //! ## References
 //! - Archivist testnet SPR endpoint: https://spr.archivist.storage/testnet
 //! - libp2p SignedEnvelope: https://github.com/libp2p/specs/blob/master/RFC/0003-routing-records.md
 
-use libp2p::{identity::Keypair, Multiaddr, PeerId};
+use libp2p::{
+    identity::{Keypair, PublicKey},
+    Multiaddr, PeerId,
+};
 use prost::Message;
 use thiserror::Error;
 
+use crate::identify_spr::{peer_record_signing_buffer, PEER_RECORD_PAYLOAD_TYPE};
+
 #[derive(Error, Debug)]
 pub enum SprError {
     #[error("Base64 decode error: {0}")]
@@ -43,6 +53,16 @@ pub enum SprError {
     #[error("Signature error: {0}")]
     Signature(String),
 
+    #[error("Invalid SPR signature")]
+    InvalidSignature,
+
+    #[error("SPR signed by {expected_peer} but the record is for {record_peer}")]
+    SignatureMismatch {
+        // Boxed to keep `SprError` small
+        expected_peer: Box<PeerId>,
+        record_peer: Box<PeerId>,
+    },
+
     #[error("Encoding error: {0}")]
     Encoding(String),
 }
@@ -53,7 +73,7 @@ struct ArchivistSpr {
     #[prost(bytes = "vec", optional, tag = "1")]
     peer_id: Option<Vec<u8>>,
     #[prost(bytes = "vec", optional, tag = "2")]
-    seq_bytes: Option<Vec<u8>>, // 2 bytes
+    payload_type: Option<Vec<u8>>, // [0x03, 0x01] for libp2p-peer-record
     #[prost(bytes = "vec", repeated, tag = "3")] // Contains nested PeerInfo protobuf
     peer_record: Vec<Vec<u8>>,
     #[prost(bytes = "vec", repeated, tag = "5")] // Signature (DER format)
@@ -71,48 +91,65 @@ struct PeerInfo {
     addrs: Vec<Vec<u8>>,
 }
 
-/// Parse SPR records from testnet endpoint response
-pub fn parse_spr_records(spr_text: &str) -> Result<Vec<(PeerId, Vec<Multiaddr>)>, SprError> {
-    let mut results = Vec::new();
-
-    for line in spr_text.lines() {
-        if let Some(spr_data) = line.strip_prefix("spr:") {
-            match parse_single_spr(spr_data) {
-                Ok((peer_id, addrs)) => {
-                    tracing::info!("Parsed SPR: peer_id={}, addrs={:?}", peer_id, addrs);
-                    results.push((peer_id, addrs));
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to parse SPR record: {}", e);
-                    continue;
-                }
-            }
-        }
-    }
-
-    Ok(results)
-}
-
-/// Parsed SPR record with full details.
+/// Parsed and verified SPR record
 #[derive(Clone, Debug)]
 pub struct SprRecord {
     pub peer_id: PeerId,
     pub addrs: Vec<Multiaddr>,
+    /// Sequence number; higher values supersede older records for the same peer
+    pub seq: u64,
+    /// Envelope signature over the payload
+    pub signature: Vec<u8>,
     /// Raw secp256k1 compressed public key bytes (33 bytes).
     pub secp256k1_pubkey: Option<Vec<u8>>,
+    public_key: PublicKey,
+    payload_type: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl SprRecord {
+    /// Addresses advertised by the record
+    pub fn to_multiaddrs(&self) -> &[Multiaddr] {
+        &self.addrs
+    }
+
+    /// Re-check the envelope signature against the signer's public key
+    ///
+    /// Also fails if `peer_id` or `seq` no longer match the signed payload.
+    pub fn verify_signature(&self) -> Result<(), SprError> {
+        let signed = peer_record_signing_buffer(&self.payload_type, &self.payload);
+        if !self.public_key.verify(&signed, &self.signature) {
+            return Err(SprError::InvalidSignature);
+        }
+
+        let peer_info = PeerInfo::decode(&self.payload[..])?;
+        let signed_peer = peer_info.peer_id.as_deref().map(PeerId::from_bytes);
+        if peer_info.seq != self.seq || !matches!(signed_peer, Some(Ok(p)) if p == self.peer_id) {
+            return Err(SprError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Whether this record supersedes `other` (same peer, higher `seq`)
+    pub fn is_newer_than(&self, other: &SprRecord) -> bool {
+        self.peer_id == other.peer_id && self.seq > other.seq
+    }
 }
 
-/// Parse SPR records returning full details including raw public keys.
-pub fn parse_spr_records_full(spr_text: &str) -> Result<Vec<SprRecord>, SprError> {
+/// Parse SPR records from testnet endpoint response
+///
+/// Lines that fail to decode or verify are skipped with a warning.
+pub fn parse_spr_records(spr_text: &str) -> Result<Vec<SprRecord>, SprError> {
     let mut results = Vec::new();
 
     for line in spr_text.lines() {
         if let Some(spr_data) = line.strip_prefix("spr:") {
-            match parse_single_spr_full(spr_data) {
+            match parse_single_spr(spr_data) {
                 Ok(record) => {
                     tracing::info!(
-                        "Parsed SPR: peer_id={}, addrs={:?}",
+                        "Parsed SPR: peer_id={}, seq={}, addrs={:?}",
                         record.peer_id,
+                        record.seq,
                         record.addrs
                     );
                     results.push(record);
@@ -128,21 +165,31 @@ pub fn parse_spr_records_full(spr_text: &str) -> Result<Vec<SprRecord>, SprError
     Ok(results)
 }
 
-/// Parse a single SPR with full details.
-fn parse_single_spr_full(spr_base64: &str) -> Result<SprRecord, SprError> {
+/// Parse a single base64-encoded SPR record
+fn parse_single_spr(spr_base64: &str) -> Result<SprRecord, SprError> {
     use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-    use libp2p::identity::PublicKey;
 
     let bytes = URL_SAFE_NO_PAD.decode(spr_base64)?;
-    let spr = ArchivistSpr::decode(&bytes[..])?;
+    parse_spr_bytes(&bytes)
+}
 
+/// Parse a single SPR from its raw (non-base64) protobuf bytes.
+///
+/// The record's signature is verified before its addresses are returned.
+pub fn parse_spr_bytes(bytes: &[u8]) -> Result<SprRecord, SprError> {
+    let spr = ArchivistSpr::decode(bytes)?;
+
+    // The peer_id field contains a PublicKey protobuf (not raw peer ID bytes)
     let peer_id_bytes = spr
         .peer_id
+        .as_ref()
         .ok_or_else(|| SprError::Protobuf(prost::DecodeError::new("Missing peer_id")))?;
 
-    let public_key = PublicKey::try_decode_protobuf(&peer_id_bytes)
+    let public_key = PublicKey::try_decode_protobuf(peer_id_bytes)
         .map_err(|e| SprError::InvalidPeerId(e.to_string()))?;
 
+    let peer_info = verify_spr(&spr, &public_key)?;
+
     // Extract raw secp256k1 compressed key if available
     let secp256k1_pubkey = if let Ok(secp_key) = public_key.clone().try_into_secp256k1() {
         Some(secp_key.to_bytes().to_vec())
@@ -153,81 +200,68 @@ fn parse_single_spr_full(spr_base64: &str) -> Result<SprRecord, SprError> {
     let peer_id = public_key.to_peer_id();
 
     let mut addrs = Vec::new();
-    for peer_record_bytes in &spr.peer_record {
-        if let Ok(peer_info) = PeerInfo::decode(&peer_record_bytes[..]) {
-            for addr_bytes in peer_info.addrs {
-                #[derive(Clone, PartialEq, Message)]
-                struct AddrWrapper {
-                    #[prost(bytes = "vec", optional, tag = "1")]
-                    addr: Option<Vec<u8>>,
-                }
-
-                if let Ok(wrapper) = AddrWrapper::decode(&addr_bytes[..]) {
-                    if let Some(raw_addr) = wrapper.addr {
-                        if let Ok(addr) = Multiaddr::try_from(raw_addr) {
-                            addrs.push(addr);
-                        }
-                    }
+    for addr_bytes in peer_info.addrs {
+        // The addr_bytes are wrapped in a protobuf message with field 1
+        if let Ok(wrapper) = AddrWrapper::decode(&addr_bytes[..]) {
+            if let Some(raw_addr) = wrapper.addr {
+                if let Ok(addr) = Multiaddr::try_from(raw_addr) {
+                    addrs.push(addr);
                 }
             }
         }
     }
 
+    // verify_spr checked there is exactly one payload and signature
     Ok(SprRecord {
         peer_id,
         addrs,
+        seq: peer_info.seq,
+        signature: spr.signature.into_iter().next().unwrap_or_default(),
         secp256k1_pubkey,
+        public_key,
+        payload_type: spr.payload_type.unwrap_or_default(),
+        payload: spr.peer_record.into_iter().next().unwrap_or_default(),
     })
 }
 
-/// Parse a single base64-encoded SPR record
-fn parse_single_spr(spr_base64: &str) -> Result<(PeerId, Vec<Multiaddr>), SprError> {
-    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-    use libp2p::identity::PublicKey;
-
-    // Decode URL-safe base64 (SPR records use URL-safe encoding)
-    let bytes = URL_SAFE_NO_PAD.decode(spr_base64)?;
+/// Verify an SPR's signature and that its record belongs to the signer
+///
+/// Returns the decoded record on success.
+fn verify_spr(spr: &ArchivistSpr, public_key: &PublicKey) -> Result<PeerInfo, SprError> {
+    let ([payload], [signature]) = (spr.peer_record.as_slice(), spr.signature.as_slice()) else {
+        return Err(SprError::InvalidSignature);
+    };
 
-    // Decode ArchivistSpr protobuf
-    let spr = ArchivistSpr::decode(&bytes[..])?;
+    let payload_type = spr.payload_type.as_deref().unwrap_or_default();
+    let signed = peer_record_signing_buffer(payload_type, payload);
+    if !public_key.verify(&signed, signature) {
+        return Err(SprError::InvalidSignature);
+    }
 
-    // Extract peer_id bytes
-    let peer_id_bytes = spr
+    let peer_info = PeerInfo::decode(&payload[..])?;
+    let record_peer_bytes = peer_info
         .peer_id
-        .ok_or_else(|| SprError::Protobuf(prost::DecodeError::new("Missing peer_id")))?;
-
-    // The peer_id field contains a PublicKey protobuf (not raw peer ID bytes)
-    let public_key = PublicKey::try_decode_protobuf(&peer_id_bytes)
+        .as_ref()
+        .ok_or_else(|| SprError::InvalidPeerId("missing record peer_id".to_string()))?;
+    let record_peer = PeerId::from_bytes(record_peer_bytes)
         .map_err(|e| SprError::InvalidPeerId(e.to_string()))?;
 
-    // Derive PeerId from public key
-    let peer_id = public_key.to_peer_id();
-
-    // Field 3 contains nested PeerInfo protobuf with multiaddrs
-    let mut addrs = Vec::new();
-    for peer_record_bytes in &spr.peer_record {
-        if let Ok(peer_info) = PeerInfo::decode(&peer_record_bytes[..]) {
-            for addr_bytes in peer_info.addrs {
-                // The addr_bytes are wrapped in a protobuf message with field 1
-                // Try decoding as nested protobuf first
-                #[derive(Clone, PartialEq, Message)]
-                struct AddrWrapper {
-                    #[prost(bytes = "vec", optional, tag = "1")]
-                    addr: Option<Vec<u8>>,
-                }
-
-                if let Ok(wrapper) = AddrWrapper::decode(&addr_bytes[..]) {
-                    if let Some(raw_addr) = wrapper.addr {
-                        if let Ok(addr) = Multiaddr::try_from(raw_addr) {
-                            addrs.push(addr);
-                        }
-                    }
-                }
-            }
-        }
+    let expected_peer = public_key.to_peer_id();
+    if record_peer != expected_peer {
+        return Err(SprError::SignatureMismatch {
+            expected_peer: Box::new(expected_peer),
+            record_peer: Box::new(record_peer),
+        });
     }
 
-    Ok((peer_id, addrs))
+    Ok(peer_info)
+}
+
+/// Multiaddr wrapper used for each address in a PeerInfo record
+#[derive(Clone, PartialEq, Message)]
+struct AddrWrapper {
+    #[prost(bytes = "vec", optional, tag = "1")]
+    addr: Option<Vec<u8>>,
 }
 
 /// Generate an SPR (Signed Peer Record) for this node
@@ -237,31 +271,17 @@ fn parse_single_spr(spr_base64: &str) -> Result<(PeerId, Vec<Multiaddr>), SprErr
 pub fn generate_spr(keypair: &Keypair, addrs: &[Multiaddr], seq: u64) -> Result<String, SprError> {
     use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 
-    // Get public key bytes (protobuf-encoded)
-    let public_key = keypair.public();
-    let peer_id_bytes = public_key.encode_protobuf();
-
-    // Convert sequence number to 2-byte big-endian
-    let seq_bytes = (seq as u16).to_be_bytes().to_vec();
-
     // Create PeerInfo with addresses
     let mut peer_info = PeerInfo {
-        peer_id: Some(peer_id_bytes.clone()),
+        peer_id: Some(keypair.public().to_peer_id().to_bytes()),
         seq,
         addrs: Vec::new(),
     };
 
     // Wrap each address in AddrWrapper protobuf
-    #[derive(Clone, PartialEq, Message)]
-    struct AddrWrapper {
-        #[prost(bytes = "vec", optional, tag = "1")]
-        addr: Option<Vec<u8>>,
-    }
-
     for addr in addrs {
-        let addr_bytes = addr.to_vec();
         let wrapper = AddrWrapper {
-            addr: Some(addr_bytes),
+            addr: Some(addr.to_vec()),
         };
         let mut wrapper_bytes = Vec::new();
         wrapper
@@ -276,44 +296,32 @@ pub fn generate_spr(keypair: &Keypair, addrs: &[Multiaddr], seq: u64) -> Result<
         .encode(&mut peer_info_bytes)
         .map_err(|e| SprError::Encoding(format!("Failed to encode peer info: {}", e)))?;
 
-    // Create ArchivistSpr (without signature first)
-    let spr_unsigned = ArchivistSpr {
-        peer_id: Some(peer_id_bytes),
-        seq_bytes: Some(seq_bytes),
-        peer_record: vec![peer_info_bytes.clone()],
-        signature: Vec::new(), // Will be filled after signing
-    };
+    let final_bytes = sign_spr(keypair, peer_info_bytes)?;
 
-    // Encode unsigned SPR
-    let mut unsigned_bytes = Vec::new();
-    spr_unsigned
-        .encode(&mut unsigned_bytes)
-        .map_err(|e| SprError::Encoding(format!("Failed to encode SPR: {}", e)))?;
+    // Encode as base64 URL-safe, in spr: format
+    Ok(format!("spr:{}", URL_SAFE_NO_PAD.encode(&final_bytes)))
+}
 
-    // Sign the peer_record payload
+/// Sign an encoded PeerInfo and wrap it in an SPR envelope
+fn sign_spr(keypair: &Keypair, peer_info_bytes: Vec<u8>) -> Result<Vec<u8>, SprError> {
+    // Sign the peer record in the libp2p envelope format
+    let signed = peer_record_signing_buffer(PEER_RECORD_PAYLOAD_TYPE, &peer_info_bytes);
     let signature = keypair
-        .sign(&peer_info_bytes)
+        .sign(&signed)
         .map_err(|e| SprError::Signature(format!("Failed to sign: {}", e)))?;
 
-    // Create final SPR with signature
     let spr_signed = ArchivistSpr {
-        peer_id: spr_unsigned.peer_id,
-        seq_bytes: spr_unsigned.seq_bytes,
-        peer_record: spr_unsigned.peer_record,
+        peer_id: Some(keypair.public().encode_protobuf()),
+        payload_type: Some(PEER_RECORD_PAYLOAD_TYPE.to_vec()),
+        peer_record: vec![peer_info_bytes],
         signature: vec![signature],
     };
 
-    // Encode final SPR
     let mut final_bytes = Vec::new();
     spr_signed
         .encode(&mut final_bytes)
         .map_err(|e| SprError::Encoding(format!("Failed to encode signed SPR: {}", e)))?;
-
-    // Encode as base64 URL-safe
-    let spr_base64 = URL_SAFE_NO_PAD.encode(&final_bytes);
-
-    // Return in spr: format
-    Ok(format!("spr:{}", spr_base64))
+    Ok(final_bytes)
 }
 
 #[cfg(test)]
@@ -332,11 +340,14 @@ mod tests {
                     panic!("No records parsed - check parse_spr_records logic");
                 }
                 assert_eq!(records.len(), 1);
-                let (peer_id, addrs) = &records[0];
+                let record = &records[0];
                 println!("Parsed SPR successfully!");
-                println!("  Peer ID: {}", peer_id);
-                println!("  Addresses: {:?}", addrs);
-                assert!(addrs.len() > 0, "Should have at least one address");
+                println!("  Peer ID: {}", record.peer_id);
+                println!("  Addresses: {:?}", record.addrs);
+                assert!(
+                    !record.to_multiaddrs().is_empty(),
+                    "Should have at least one address"
+                );
             }
             Err(e) => {
                 panic!("Failed to parse SPR: {}", e);
@@ -344,6 +355,112 @@ mod tests {
         }
     }
 
+    fn encode_peer_info(peer_info: &PeerInfo) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        peer_info.encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_generated_spr_verifies() {
+        let keypair = Keypair::generate_secp256k1();
+        let addr: Multiaddr = "/ip4/10.0.0.1/udp/8090".parse().unwrap();
+        let spr = generate_spr(&keypair, std::slice::from_ref(&addr), 42).unwrap();
+
+        let records = parse_spr_records(&spr).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].peer_id, keypair.public().to_peer_id());
+        assert_eq!(records[0].to_multiaddrs(), &[addr]);
+        assert_eq!(records[0].seq, 42);
+        assert!(records[0].verify_signature().is_ok());
+    }
+
+    #[test]
+    fn test_testnet_spr_record_fields() {
+        let spr_text = "spr:CiUIAhIhA5mg11LZgFQ4XzIRb1T5xw9muFW1ALNKTijyKhQmvKYXEgIDARpJCicAJQgCEiEDmaDXUtmAVDhfMhFvVPnHD2a4VbUAs0pOKPIqFCa8phcQl-XFxQYaCwoJBE4vqKqRAnU6GgsKCQROL6iqkQJ1OipHMEUCIQDfzVYbN6A_O4i29e_FtDDUo7GJS3bkXRQtoteYbPSFtgIgcc8Kgj2ggVJyK16EY9xi4bY2lpTTeNIRjvslXSRdN5w";
+
+        let records = parse_spr_records(spr_text).unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+
+        assert_eq!(
+            record.peer_id.to_string(),
+            "16Uiu2HAmNzgyd948rRhmuZ6HSLU2r78kzDXkg5pi12atgQe48vNz"
+        );
+        assert_eq!(record.seq, 1756459671);
+        // The testnet record advertises its discovery address twice
+        let expected_addr: Multiaddr = "/ip4/78.47.168.170/udp/30010".parse().unwrap();
+        assert_eq!(
+            record.to_multiaddrs(),
+            &[expected_addr.clone(), expected_addr]
+        );
+        assert!(!record.signature.is_empty());
+        assert_eq!(record.secp256k1_pubkey.as_ref().map(Vec::len), Some(33));
+        assert!(record.verify_signature().is_ok());
+
+        let mut newer = record.clone();
+        newer.seq += 1;
+        assert!(newer.is_newer_than(record));
+        assert!(!record.is_newer_than(&newer));
+        assert!(!record.is_newer_than(record));
+        // The signed payload still carries the old seq, so the bumped copy fails
+        assert!(matches!(
+            newer.verify_signature(),
+            Err(SprError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_seq_fails_verification() {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        let keypair = Keypair::generate_ed25519();
+        let addrs = vec!["/ip4/10.0.0.1/udp/8090".parse().unwrap()];
+        let spr = generate_spr(&keypair, &addrs, 7).unwrap();
+        let bytes = URL_SAFE_NO_PAD
+            .decode(spr.strip_prefix("spr:").unwrap())
+            .unwrap();
+        assert!(parse_spr_bytes(&bytes).is_ok());
+
+        // Bump the sequence number but keep the original signature
+        let mut envelope = ArchivistSpr::decode(&bytes[..]).unwrap();
+        let mut peer_info = PeerInfo::decode(&envelope.peer_record[0][..]).unwrap();
+        peer_info.seq += 1;
+        envelope.peer_record = vec![encode_peer_info(&peer_info)];
+        let mut tampered = Vec::new();
+        envelope.encode(&mut tampered).unwrap();
+
+        assert!(matches!(
+            parse_spr_bytes(&tampered),
+            Err(SprError::InvalidSignature)
+        ));
+        let tampered_text = format!("spr:{}", URL_SAFE_NO_PAD.encode(&tampered));
+        assert!(parse_spr_records(&tampered_text).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_record_for_other_peer_is_rejected() {
+        let signer = Keypair::generate_ed25519();
+        let other = PeerId::random();
+        let peer_info = PeerInfo {
+            peer_id: Some(other.to_bytes()),
+            seq: 1,
+            addrs: Vec::new(),
+        };
+        let bytes = sign_spr(&signer, encode_peer_info(&peer_info)).unwrap();
+
+        match parse_spr_bytes(&bytes) {
+            Err(SprError::SignatureMismatch {
+                expected_peer,
+                record_peer,
+            }) => {
+                assert_eq!(*expected_peer, signer.public().to_peer_id());
+                assert_eq!(*record_peer, other);
+            }
+            other => panic!("expected SignatureMismatch, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_parse_single_spr_direct() {
         use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
@@ -360,11 +477,11 @@ mod tests {
                 if let Some(peer_id) = &spr.peer_id {
                     println!("  peer_id (field 1): {} bytes", peer_id.len());
                 }
-                if let Some(seq) = &spr.seq_bytes {
+                if let Some(payload_type) = &spr.payload_type {
                     println!(
-                        "  seq_bytes (field 2): {} bytes - {}",
-                        seq.len(),
-                        hex::encode(seq)
+                        "  payload_type (field 2): {} bytes - {}",
+                        payload_type.len(),
+                        hex::encode(payload_type)
                     );
                 }
                 println!("  peer_record (field 3) count: {}", spr.peer_record.len());
@@ -403,11 +520,11 @@ mod tests {
         }
 
         match parse_single_spr(spr_data) {
-            Ok((peer_id, addrs)) => {
+            Ok(record) => {
                 println!("\nFull parse successful!");
-                println!("  Peer ID: {}", peer_id);
-                println!("  Addresses: {:?}", addrs);
-                assert!(addrs.len() > 0, "Should have at least one address");
+                println!("  Peer ID: {}", record.peer_id);
+                println!("  Addresses: {:?}", record.addrs);
+                assert!(!record.addrs.is_empty(), "Should have at least one address");
             }
             Err(e) => {
                 panic!("parse_single_spr failed: {}", e);
@@ -0,0 +1,262 @@
+//! Startup health checks run by [`crate::runtime::run_node`]
+//!
+//! Required checks (block store read/write, REST API port) abort startup when
+//! they fail. Optional checks (bootstrap reachability, free disk space) only
+//! log a warning.
+
+use crate::storage::{Block, BlockStore};
+use futures::future::select_ok;
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info, warn};
+
+/// How long to wait for a TCP connection to a bootstrap peer
+pub const BOOTSTRAP_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error("Block store read/write check failed: {0}")]
+    BlockStore(String),
+
+    #[error("REST API address {addr} is not bindable: {source}")]
+    ApiPort {
+        addr: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("None of {0} bootstrap peer(s) is reachable over TCP")]
+    BootstrapUnreachable(usize),
+
+    #[error("Only {available} bytes free in the data directory, {required} required")]
+    LowDiskSpace { available: u64, required: u64 },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Outcome of a single startup check
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    /// Whether a failure aborts startup
+    pub required: bool,
+    pub outcome: Result<(), StartupError>,
+}
+
+impl CheckResult {
+    /// A check whose failure aborts startup
+    pub fn required(name: &'static str, outcome: Result<(), StartupError>) -> Self {
+        Self {
+            name,
+            required: true,
+            outcome,
+        }
+    }
+
+    /// A check whose failure is only logged
+    pub fn optional(name: &'static str, outcome: Result<(), StartupError>) -> Self {
+        Self {
+            name,
+            required: false,
+            outcome,
+        }
+    }
+}
+
+/// Log every check result and return the first required failure
+pub fn evaluate(results: Vec<CheckResult>) -> Result<(), StartupError> {
+    let mut first_failure = None;
+    for result in results {
+        match result.outcome {
+            Ok(()) => info!("Startup check '{}' passed", result.name),
+            Err(e) if result.required => {
+                error!("Startup check '{}' failed: {}", result.name, e);
+                first_failure.get_or_insert(e);
+            }
+            Err(e) => warn!("Startup check '{}' failed: {}", result.name, e),
+        }
+    }
+    first_failure.map_or(Ok(()), Err)
+}
+
+/// Write, read back and delete a random block
+pub async fn check_block_store(store: &BlockStore) -> Result<(), StartupError> {
+    let data: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+    let block = Block::new(data.clone()).map_err(|e| StartupError::BlockStore(e.to_string()))?;
+    let cid = block.cid;
+
+    store
+        .put(block)
+        .await
+        .map_err(|e| StartupError::BlockStore(format!("write: {}", e)))?;
+    let read = store.get(&cid).await;
+    // Clean up even when the read failed
+    let deleted = store.delete(&cid).await;
+
+    let read = read.map_err(|e| StartupError::BlockStore(format!("read: {}", e)))?;
+    if read.data != data {
+        return Err(StartupError::BlockStore(
+            "read back different data than written".to_string(),
+        ));
+    }
+    deleted.map_err(|e| StartupError::BlockStore(format!("delete: {}", e)))
+}
+
+/// Bind the REST API listener, so a port conflict is caught before startup
+pub async fn bind_api_listener(bind: &str, port: u16) -> Result<TcpListener, StartupError> {
+    let addr = format!("{}:{}", bind, port);
+    TcpListener::bind(&addr)
+        .await
+        .map_err(|source| StartupError::ApiPort { addr, source })
+}
+
+/// Check that at least one bootstrap peer accepts a TCP connection
+///
+/// Addresses without an IP and TCP port (e.g. DNS or UDP-only) are skipped.
+pub async fn check_bootstrap_reachable(
+    addrs: &[Multiaddr],
+    timeout: Duration,
+) -> Result<(), StartupError> {
+    let attempts: Vec<_> = addrs
+        .iter()
+        .filter_map(tcp_socket_addr)
+        .map(|addr| {
+            Box::pin(async move {
+                match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+                    Ok(Ok(_)) => Ok(()),
+                    _ => Err(()),
+                }
+            })
+        })
+        .collect();
+
+    if attempts.is_empty() {
+        return Err(StartupError::BootstrapUnreachable(addrs.len()));
+    }
+    select_ok(attempts)
+        .await
+        .map(|_| ())
+        .map_err(|_| StartupError::BootstrapUnreachable(addrs.len()))
+}
+
+/// Check that `path` has at least `min_free_bytes` available
+pub fn check_free_disk(path: &Path, min_free_bytes: u64) -> Result<(), StartupError> {
+    let available = available_disk_bytes(path)?;
+    if available < min_free_bytes {
+        return Err(StartupError::LowDiskSpace {
+            available,
+            required: min_free_bytes,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn available_disk_bytes(path: &Path) -> Result<u64, StartupError> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| StartupError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+    // SAFETY: `stat` is plain data and fully written by a successful call.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is a valid NUL-terminated string and `stat` a valid pointer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_disk_bytes(_path: &Path) -> Result<u64, StartupError> {
+    Ok(u64::MAX)
+}
+
+fn tcp_socket_addr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut ip = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::Ip4(v4) => ip = Some(IpAddr::V4(v4)),
+            Protocol::Ip6(v6) => ip = Some(IpAddr::V6(v6)),
+            Protocol::Tcp(port) => return ip.map(|ip| SocketAddr::new(ip, port)),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_block_store_check_cleans_up() {
+        let store = BlockStore::new();
+        check_block_store(&store).await.unwrap();
+        assert_eq!(store.stats().await.block_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_api_port_in_use_aborts_startup() {
+        let taken = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let outcome = bind_api_listener("127.0.0.1", port).await.map(|_| ());
+        assert!(matches!(outcome, Err(StartupError::ApiPort { .. })));
+        assert!(matches!(
+            evaluate(vec![
+                CheckResult::required("block_store", Ok(())),
+                CheckResult::required("api_port", outcome),
+            ]),
+            Err(StartupError::ApiPort { .. })
+        ));
+
+        drop(taken);
+        assert!(bind_api_listener("127.0.0.1", port).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_reachability() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = {
+            let probe = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            probe.local_addr().unwrap().port()
+        };
+        let addr =
+            |port: u16| -> Multiaddr { format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap() };
+        let timeout = Duration::from_millis(500);
+
+        assert!(
+            check_bootstrap_reachable(&[addr(closed), addr(open)], timeout)
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            check_bootstrap_reachable(&[addr(closed)], timeout).await,
+            Err(StartupError::BootstrapUnreachable(1))
+        ));
+        assert!(check_bootstrap_reachable(&[], timeout).await.is_err());
+    }
+
+    #[test]
+    fn test_optional_failures_do_not_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let low_disk = check_free_disk(dir.path(), u64::MAX);
+        assert!(matches!(low_disk, Err(StartupError::LowDiskSpace { .. })));
+        assert!(check_free_disk(dir.path(), 1).is_ok());
+
+        assert!(evaluate(vec![
+            CheckResult::required("block_store", Ok(())),
+            CheckResult::required("api_port", Ok(())),
+            CheckResult::optional("bootstrap", Err(StartupError::BootstrapUnreachable(2))),
+            CheckResult::optional("free_disk", low_disk),
+        ])
+        .is_ok());
+    }
+}
@@ -19,12 +19,19 @@ use std::os::fd::AsRawFd;
 #[cfg(unix)]
 use std::os::unix::fs::FileExt;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
-use tracing::{debug, info, warn};
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+use tracing::{debug, error, info, warn};
 
 use crate::cid_blake3::{blake3_cid, sha256_cid, verify_blake3, CidError};
+use crate::fetcher::FetchError;
 
 const BLOCKS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("blocks");
+/// Per-block metadata (CID -> block size), written in the same transaction as
+/// `BLOCKS_TABLE` so size lookups never need to touch block bodies.
+const BLOCK_META_TABLE: TableDefinition<&str, u64> = TableDefinition::new("block_meta");
 const DELTA_INDEX_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("delta_index");
 const DELTA_CLASS_STATE_TABLE: TableDefinition<&str, &[u8]> =
     TableDefinition::new("delta_class_state");
@@ -34,6 +41,16 @@ const DELTAFLAT_STATE_FULL: u8 = 1;
 const DELTAFLAT_STATE_TOMBSTONE: u8 = 2;
 const DELTAFLAT_MAX_CID_BYTES: usize = 96;
 const DELTAFLAT_MAX_LANES: usize = 4096;
+/// Blocks written to the target per `put_many` call in `clone_to`.
+const CLONE_BATCH_SIZE: usize = 1000;
+/// Copied-block interval between `clone_to` progress log lines.
+const CLONE_PROGRESS_INTERVAL: usize = 10_000;
+/// CIDs a [`BlockStore::iterate_cids`] scan may read ahead of its consumer.
+pub const ITERATE_BUFFER: usize = 100;
+/// How long [`BlockStore::count_by_codec`] reuses its last scan.
+pub const CODEC_COUNT_TTL: Duration = Duration::from_secs(60);
+/// How long [`BlockStore::disk_stats`] reuses its last scan.
+pub const DISK_STATS_TTL: Duration = Duration::from_secs(60);
 
 #[derive(Debug, thiserror::Error)]
 pub enum StorageError {
@@ -51,6 +68,54 @@ pub enum StorageError {
 
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+
+    #[error("Timed out waiting for {pending} in-progress writes")]
+    FlushTimeout { pending: usize },
+
+    #[error("Failed to fetch block {cid}: {source}")]
+    FetchFailed {
+        cid: String,
+        #[source]
+        source: Box<FetchError>,
+    },
+}
+
+impl StorageError {
+    /// Whether the requested block is not in the store
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, StorageError::BlockNotFound(_))
+    }
+
+    /// Whether block data did not match its CID
+    pub fn is_verification_failed(&self) -> bool {
+        matches!(self, StorageError::VerificationFailed(_))
+    }
+
+    /// Whether the error came from the filesystem
+    pub fn is_io_error(&self) -> bool {
+        matches!(self, StorageError::IoError(_))
+    }
+
+    /// Whether the error came from the storage backend database
+    pub fn is_database_error(&self) -> bool {
+        matches!(self, StorageError::DatabaseError(_))
+    }
+}
+
+impl From<StorageError> for axum::http::StatusCode {
+    fn from(err: StorageError) -> Self {
+        use axum::http::StatusCode;
+
+        match err {
+            StorageError::BlockNotFound(_) => StatusCode::NOT_FOUND,
+            StorageError::FetchFailed { source, .. } => (*source).into(),
+            StorageError::VerificationFailed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            StorageError::BlockExists(_) => StatusCode::CONFLICT,
+            StorageError::DatabaseError(_)
+            | StorageError::IoError(_)
+            | StorageError::FlushTimeout { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 /// A block with its CID and data
@@ -79,6 +144,16 @@ impl Block {
         Ok(Self { cid, data })
     }
 
+    /// Create a block whose CID was computed elsewhere, without verifying it
+    ///
+    /// The caller vouches that `cid` addresses `data`, e.g. for blocks
+    /// hashed by another implementation with a codec `blake3_cid` does not
+    /// produce. Use [`Block::from_cid_and_data`] for anything untrusted.
+    #[doc(hidden)]
+    pub fn with_custom_cid(cid: Cid, data: Vec<u8>) -> Self {
+        Self { cid, data }
+    }
+
     /// Get the size of the block in bytes
     pub fn size(&self) -> usize {
         self.data.len()
@@ -113,6 +188,7 @@ struct DeltaClassState {
     next_offset: u64,
 }
 
+#[derive(Clone)]
 struct RedbStore {
     db: Arc<Database>,
     db_path: PathBuf,
@@ -147,6 +223,7 @@ struct GeomTreeStore {
     bytes_per_level: usize,
 }
 
+#[derive(Clone)]
 enum StoreBackend {
     Redb(RedbStore),
     DeltaStore(DeltaStore),
@@ -154,12 +231,143 @@ enum StoreBackend {
     GeomTree(GeomTreeStore),
 }
 
+/// Counts in-progress writes so shutdown can wait for them to finish
+#[derive(Default)]
+struct WriteTracker {
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+/// Marks a write as in progress until dropped
+struct WriteGuard<'a>(&'a WriteTracker);
+
+impl<'a> WriteGuard<'a> {
+    fn new(tracker: &'a WriteTracker) -> Self {
+        tracker.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(tracker)
+    }
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Outcome counters for single-block puts
+#[derive(Default)]
+struct PutCounters {
+    inserted: AtomicUsize,
+    deduplicated: AtomicUsize,
+}
+
+/// Capacity of the storage event channel; slower subscribers miss events
+/// once they fall this far behind
+///
+/// Sized for several upload commit batches (4096 default-size blocks each)
+/// written back to back.
+pub const STORAGE_EVENT_CAPACITY: usize = 16 * 1024;
+
+/// Change to the set of stored or pinned blocks, see [`BlockStore::subscribe`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageEvent {
+    BlockStored(Cid),
+    BlockDeleted(Cid),
+    BlockPinned(Cid),
+    BlockUnpinned(Cid),
+}
+
+/// Block counts per CID codec and when they were computed
+type CodecCounts = (std::time::Instant, HashMap<u64, usize>);
+
 /// Persistent block storage with pluggable backend.
 pub struct BlockStore {
     backend: StoreBackend,
+    writes: WriteTracker,
+    puts: PutCounters,
+    events: broadcast::Sender<StorageEvent>,
+    /// Last `count_by_codec` result and when it was computed
+    codec_counts: RwLock<Option<CodecCounts>>,
+    /// Last `disk_stats` result, or its error message, and when it was
+    /// computed
+    disk_stats: RwLock<Option<(std::time::Instant, Result<BlockStoreDiskStats, String>)>>,
+    /// Make `get` re-verify blocks, see [`BlockStore::get_with_verify`]
+    verify_on_read: bool,
+    /// Delete blocks that fail verification in `get_with_verify`
+    delete_corrupted_on_read: bool,
 }
 
 impl BlockStore {
+    fn from_backend(backend: StoreBackend) -> Self {
+        let (events, _) = broadcast::channel(STORAGE_EVENT_CAPACITY);
+        Self {
+            backend,
+            writes: WriteTracker::default(),
+            puts: PutCounters::default(),
+            events,
+            codec_counts: RwLock::new(None),
+            disk_stats: RwLock::new(None),
+            verify_on_read: false,
+            delete_corrupted_on_read: false,
+        }
+    }
+
+    /// Make [`BlockStore::get`] re-verify every block it reads
+    pub fn set_verify_on_read(&mut self, verify: bool) {
+        self.verify_on_read = verify;
+    }
+
+    /// Delete blocks that fail verification on read
+    pub fn set_delete_corrupted_on_read(&mut self, delete: bool) {
+        self.delete_corrupted_on_read = delete;
+    }
+
+    /// Subscribe to storage events
+    ///
+    /// Every subscriber receives every event sent after it subscribed. A
+    /// subscriber more than [`STORAGE_EVENT_CAPACITY`] events behind gets
+    /// `RecvError::Lagged` and skips ahead.
+    pub fn subscribe(&self) -> broadcast::Receiver<StorageEvent> {
+        self.events.subscribe()
+    }
+
+    /// Stream of storage events that skips over events missed by lagging
+    pub fn event_stream(&self) -> futures::stream::BoxStream<'static, StorageEvent> {
+        use futures::StreamExt;
+
+        futures::stream::unfold(self.subscribe(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((event, events)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Storage event subscriber missed {} events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Announce that a block was pinned or unpinned
+    ///
+    /// Pins are tracked by the API layer; the store only relays the change
+    /// to subscribers.
+    pub fn record_pin(&self, cid: Cid, pinned: bool) {
+        self.emit(if pinned {
+            StorageEvent::BlockPinned(cid)
+        } else {
+            StorageEvent::BlockUnpinned(cid)
+        });
+    }
+
+    fn emit(&self, event: StorageEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+
     /// Create a new block store with a temp-file backend (for testing).
     pub fn new() -> Self {
         let temp_dir =
@@ -186,15 +394,11 @@ impl BlockStore {
         match backend.as_str() {
             "deltastore" | "delta" | "delta-store" => {
                 let delta = DeltaStore::open(path)?;
-                Ok(Self {
-                    backend: StoreBackend::DeltaStore(delta),
-                })
+                Ok(Self::from_backend(StoreBackend::DeltaStore(delta)))
             }
             "deltaflat" | "delta-flat" | "deltastore-flat" => {
                 let deltaflat = DeltaFlatStore::open(path)?;
-                Ok(Self {
-                    backend: StoreBackend::DeltaFlat(deltaflat),
-                })
+                Ok(Self::from_backend(StoreBackend::DeltaFlat(deltaflat)))
             }
             "geomtree" => {
                 let root = Self::resolve_geomtree_root(path);
@@ -207,20 +411,16 @@ impl BlockStore {
                     "Opened geomtree block store at {:?} (fsync_writes={}, shard_levels={}, bytes_per_level={})",
                     root, fsync_writes, shard_levels, bytes_per_level
                 );
-                Ok(Self {
-                    backend: StoreBackend::GeomTree(GeomTreeStore {
-                        root,
-                        fsync_writes,
-                        shard_levels,
-                        bytes_per_level,
-                    }),
-                })
+                Ok(Self::from_backend(StoreBackend::GeomTree(GeomTreeStore {
+                    root,
+                    fsync_writes,
+                    shard_levels,
+                    bytes_per_level,
+                })))
             }
             "redb" => {
                 let redb = RedbStore::open(path)?;
-                Ok(Self {
-                    backend: StoreBackend::Redb(redb),
-                })
+                Ok(Self::from_backend(StoreBackend::Redb(redb)))
             }
             other => {
                 warn!(
@@ -228,9 +428,7 @@ impl BlockStore {
                     other
                 );
                 let redb = RedbStore::open(path)?;
-                Ok(Self {
-                    backend: StoreBackend::Redb(redb),
-                })
+                Ok(Self::from_backend(StoreBackend::Redb(redb)))
             }
         }
     }
@@ -290,18 +488,107 @@ impl BlockStore {
     }
 
     /// Store multiple blocks, verifying CID integrity.
+    ///
+    /// Sends a `BlockStored` event per newly stored block once the batch is
+    /// written; blocks that were already stored send none.
     pub async fn put_many(&self, blocks: Vec<Block>) -> Result<(), StorageError> {
+        let cids: Vec<Cid> = blocks.iter().map(|block| block.cid).collect();
+        let stored = self.has_many(&cids).await;
+        let mut seen = HashSet::new();
+        let new_cids = cids
+            .into_iter()
+            .zip(stored)
+            .filter(|&(cid, stored)| !stored && seen.insert(cid))
+            .map(|(cid, _)| cid)
+            .collect();
+        self.write_many(blocks, new_cids).await
+    }
+
+    /// Write `blocks` and send a `BlockStored` event for each of `new_cids`
+    async fn write_many(&self, blocks: Vec<Block>, new_cids: Vec<Cid>) -> Result<(), StorageError> {
+        let _write = WriteGuard::new(&self.writes);
         match &self.backend {
             StoreBackend::Redb(redb) => redb.put_many(blocks).await,
             StoreBackend::DeltaStore(delta) => delta.put_many(blocks).await,
             StoreBackend::DeltaFlat(deltaflat) => deltaflat.put_many(blocks).await,
             StoreBackend::GeomTree(tree) => tree.put_many(blocks).await,
+        }?;
+        for cid in new_cids {
+            self.emit(StorageEvent::BlockStored(cid));
         }
+        Ok(())
     }
 
     /// Store a block, verifying its CID.
+    ///
+    /// Blocks are content-addressed, so putting a block that is already
+    /// stored is a successful no-op and leaves the existing data untouched.
     pub async fn put(&self, block: Block) -> Result<(), StorageError> {
-        self.put_many(vec![block]).await
+        if self.has(&block.cid).await {
+            debug!("Skipping duplicate put of block {}", block.cid);
+            self.puts.deduplicated.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        let cid = block.cid;
+        self.write_many(vec![block], vec![cid]).await?;
+        self.puts.inserted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Store a block, failing with `BlockExists` if it is already stored.
+    pub async fn put_strict(&self, block: Block) -> Result<(), StorageError> {
+        if self.has(&block.cid).await {
+            debug!("Rejecting duplicate put of block {}", block.cid);
+            self.puts.deduplicated.fetch_add(1, Ordering::Relaxed);
+            return Err(StorageError::BlockExists(block.cid.to_string()));
+        }
+        let cid = block.cid;
+        self.write_many(vec![block], vec![cid]).await?;
+        self.puts.inserted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Counts of single-block puts (`put`, `put_strict`, `put_data`) that
+    /// stored a new block versus found it already present.
+    ///
+    /// Batched writes through `put_many` are not counted.
+    pub fn put_stats(&self) -> PutStats {
+        PutStats {
+            inserted: self.puts.inserted.load(Ordering::Relaxed),
+            deduplicated: self.puts.deduplicated.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Number of writes currently in progress
+    pub fn pending_writes(&self) -> usize {
+        self.writes.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Wait for in-progress writes to finish
+    ///
+    /// Every backend has made a write durable (or handed it to the OS, for
+    /// file backends without fsync) by the time it returns, so once no writes
+    /// are in flight the store is safe to close. Returns `FlushTimeout` if
+    /// writes are still running after `timeout`.
+    pub async fn flush(&self, timeout: Duration) -> Result<(), StorageError> {
+        let idle = async {
+            loop {
+                let notified = self.writes.idle.notified();
+                tokio::pin!(notified);
+                // Register before checking so a concurrent notify isn't missed
+                notified.as_mut().enable();
+                if self.pending_writes() == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+
+        tokio::time::timeout(timeout, idle)
+            .await
+            .map_err(|_| StorageError::FlushTimeout {
+                pending: self.pending_writes(),
+            })
     }
 
     /// Store raw data, computing and verifying CID.
@@ -313,7 +600,39 @@ impl BlockStore {
     }
 
     /// Retrieve a block by CID.
+    ///
+    /// Blocks are not re-verified unless the store was configured with
+    /// [`BlockStore::set_verify_on_read`].
     pub async fn get(&self, cid: &Cid) -> Result<Block, StorageError> {
+        if self.verify_on_read {
+            return self.get_with_verify(cid).await;
+        }
+        self.get_unverified(cid).await
+    }
+
+    /// Retrieve a block and check its data still matches the CID
+    ///
+    /// Catches data corrupted at rest. A corrupted block is logged and, if
+    /// the store was configured with
+    /// [`BlockStore::set_delete_corrupted_on_read`], deleted.
+    pub async fn get_with_verify(&self, cid: &Cid) -> Result<Block, StorageError> {
+        let block = self.get_unverified(cid).await?;
+        if let Err(e) = verify_blake3(&block.data, &block.cid) {
+            error!("Stored block {} is corrupted: {}", cid, e);
+            if self.delete_corrupted_on_read {
+                match self.delete(cid).await {
+                    Ok(()) => warn!("Deleted corrupted block {}", cid),
+                    Err(delete_err) => {
+                        error!("Failed to delete corrupted block {}: {}", cid, delete_err)
+                    }
+                }
+            }
+            return Err(e.into());
+        }
+        Ok(block)
+    }
+
+    async fn get_unverified(&self, cid: &Cid) -> Result<Block, StorageError> {
         match &self.backend {
             StoreBackend::Redb(redb) => redb.get(cid).await,
             StoreBackend::DeltaStore(delta) => delta.get(cid).await,
@@ -322,6 +641,35 @@ impl BlockStore {
         }
     }
 
+    /// Retrieve a block, fetching and storing it when it is not held locally
+    ///
+    /// `fetcher` is only called on a local miss; the block it returns must
+    /// have the requested CID.
+    pub async fn get_or_fetch<F, Fut>(&self, cid: &Cid, fetcher: &F) -> Result<Block, StorageError>
+    where
+        F: Fn(Cid) -> Fut,
+        Fut: std::future::Future<Output = Result<Block, FetchError>>,
+    {
+        match self.get(cid).await {
+            Err(e) if e.is_not_found() => {}
+            result => return result,
+        }
+
+        let fetch_failed = |e: FetchError| StorageError::FetchFailed {
+            cid: cid.to_string(),
+            source: Box::new(e),
+        };
+        let block = fetcher(*cid).await.map_err(fetch_failed)?;
+        if block.cid != *cid {
+            return Err(fetch_failed(FetchError::UnexpectedBlock {
+                expected: *cid,
+                actual: block.cid,
+            }));
+        }
+        self.put(block.clone()).await?;
+        Ok(block)
+    }
+
     /// Read a byte range from a block without loading the full block.
     /// Only supported on GeomTree backend (falls back to full read on others).
     /// Returns (data, total_size).
@@ -350,15 +698,21 @@ impl BlockStore {
     }
 
     /// Get total size of a block without reading its data.
-    /// Only supported on GeomTree (falls back to full read on others).
+    /// Supported on redb and GeomTree (falls back to full read on others).
     pub async fn block_size(&self, cid: &Cid) -> Result<u64, StorageError> {
-        match &self.backend {
-            StoreBackend::GeomTree(tree) => tree.file_size(cid).await,
-            _ => {
-                let block = self.get(cid).await?;
-                Ok(block.data.len() as u64)
-            }
-        }
+        Ok(self.get_metadata(cid).await?.size as u64)
+    }
+
+    /// Get metadata for a block without loading its data.
+    ///
+    /// Returns `StorageError::BlockNotFound` if the block is not stored.
+    pub async fn get_metadata(&self, cid: &Cid) -> Result<BlockMetadata, StorageError> {
+        let size = match &self.backend {
+            StoreBackend::Redb(redb) => redb.block_size(cid).await?,
+            StoreBackend::GeomTree(tree) => tree.file_size(cid).await? as usize,
+            _ => self.get(cid).await?.data.len(),
+        };
+        Ok(BlockMetadata { cid: *cid, size })
     }
 
     /// Check if a block exists.
@@ -371,6 +725,20 @@ impl BlockStore {
         }
     }
 
+    /// Check which of `cids` exist, in the same order.
+    ///
+    /// Database backends answer the whole batch from one read transaction;
+    /// file backends check the blocks concurrently.
+    pub async fn has_many(&self, cids: &[Cid]) -> Vec<bool> {
+        match &self.backend {
+            StoreBackend::Redb(redb) => redb.has_many(cids).await,
+            StoreBackend::DeltaStore(delta) => delta.has_many(cids).await,
+            StoreBackend::DeltaFlat(_) | StoreBackend::GeomTree(_) => {
+                futures::future::join_all(cids.iter().map(|cid| self.has(cid))).await
+            }
+        }
+    }
+
     /// Delete a block.
     pub async fn delete(&self, cid: &Cid) -> Result<(), StorageError> {
         match &self.backend {
@@ -378,7 +746,9 @@ impl BlockStore {
             StoreBackend::DeltaStore(delta) => delta.delete(cid).await,
             StoreBackend::DeltaFlat(deltaflat) => deltaflat.delete(cid).await,
             StoreBackend::GeomTree(tree) => tree.delete(cid).await,
-        }
+        }?;
+        self.emit(StorageEvent::BlockDeleted(*cid));
+        Ok(())
     }
 
     /// Get all CIDs in the store.
@@ -391,6 +761,49 @@ impl BlockStore {
         }
     }
 
+    /// Stream every CID in the store without collecting them first
+    ///
+    /// The backend is scanned on a blocking thread that stays at most
+    /// [`ITERATE_BUFFER`] CIDs ahead of the consumer. Dropping the stream
+    /// stops the scan.
+    pub fn iterate_cids(&self) -> futures::stream::BoxStream<'static, Result<Cid, StorageError>> {
+        use futures::StreamExt;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(ITERATE_BUFFER);
+        let backend = self.backend.clone();
+        tokio::task::spawn_blocking(move || {
+            let scanned = backend.scan_cids(&mut |cid| tx.blocking_send(Ok(cid)).is_ok());
+            if let Err(e) = scanned {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+
+        futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|item| (item, rx))
+        })
+        .boxed()
+    }
+
+    /// Stream every block in the store, reading each one as it is consumed
+    ///
+    /// Blocks deleted while the stream is running are skipped.
+    pub fn iterate(&self) -> futures::stream::BoxStream<'_, Result<Block, StorageError>> {
+        use futures::StreamExt;
+
+        self.iterate_cids()
+            .filter_map(move |cid| async move {
+                let cid = match cid {
+                    Ok(cid) => cid,
+                    Err(e) => return Some(Err(e)),
+                };
+                match self.get(&cid).await {
+                    Err(e) if e.is_not_found() => None,
+                    result => Some(result),
+                }
+            })
+            .boxed()
+    }
+
     /// Get statistics about the block store.
     pub async fn stats(&self) -> BlockStoreStats {
         match &self.backend {
@@ -401,6 +814,181 @@ impl BlockStore {
         }
     }
 
+    /// Count stored blocks per CID codec
+    ///
+    /// Scans every CID in the store; the result is reused for
+    /// [`CODEC_COUNT_TTL`], so it can lag behind recent writes.
+    pub async fn count_by_codec(&self) -> Result<HashMap<u64, usize>, StorageError> {
+        if let Some((computed_at, counts)) = self.codec_counts.read().unwrap().as_ref() {
+            if computed_at.elapsed() < CODEC_COUNT_TTL {
+                return Ok(counts.clone());
+            }
+        }
+
+        let backend = self.backend.clone();
+        let counts = tokio::task::spawn_blocking(move || {
+            let mut counts = HashMap::new();
+            backend.scan_cids(&mut |cid| {
+                *counts.entry(cid.codec()).or_insert(0) += 1;
+                true
+            })?;
+            Ok::<_, StorageError>(counts)
+        })
+        .await
+        .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))??;
+
+        *self.codec_counts.write().unwrap() = Some((std::time::Instant::now(), counts.clone()));
+        Ok(counts)
+    }
+
+    /// Measure the files holding the store's data
+    ///
+    /// Walks the backend's database file or directory tree; the result, or
+    /// the error, is reused for [`DISK_STATS_TTL`].
+    pub async fn disk_stats(&self) -> Result<BlockStoreDiskStats, StorageError> {
+        if let Some((computed_at, stats)) = self.disk_stats.read().unwrap().as_ref() {
+            if computed_at.elapsed() < DISK_STATS_TTL {
+                return stats.clone().map_err(disk_stats_error);
+            }
+        }
+
+        let path = self.backend.root_path().to_path_buf();
+        let stats = tokio::task::spawn_blocking(move || measure_disk_usage(&path))
+            .await
+            .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))
+            .and_then(|stats| stats)
+            .map_err(|e| e.to_string());
+
+        *self.disk_stats.write().unwrap() = Some((std::time::Instant::now(), stats.clone()));
+        stats.map_err(disk_stats_error)
+    }
+
+    /// Bytes the store's files take up on disk
+    ///
+    /// Unlike [`BlockStoreStats::total_size`], this includes database and
+    /// filesystem overhead.
+    pub async fn size_on_disk(&self) -> Result<u64, StorageError> {
+        Ok(self.disk_stats().await?.allocated_size)
+    }
+
+    /// Copy every block in this store into `target`.
+    ///
+    /// Blocks are written in batches of `CLONE_BATCH_SIZE`; blocks already in
+    /// the target are left untouched. Returns the number of blocks copied.
+    pub async fn clone_to(&self, target: &BlockStore) -> Result<usize, StorageError> {
+        let cids = self.list_cids().await;
+        info!("Cloning {} blocks to target store", cids.len());
+        let copied = self.clone_cids_to(&cids, target).await?;
+        info!("Cloned {} blocks to target store", copied);
+        Ok(copied)
+    }
+
+    /// Copy the given blocks into `target`.
+    ///
+    /// CIDs missing from this store are skipped. Returns the number of blocks
+    /// copied.
+    pub async fn clone_cids_to(
+        &self,
+        cids: &[Cid],
+        target: &BlockStore,
+    ) -> Result<usize, StorageError> {
+        let mut copied = 0usize;
+        let mut batch = Vec::with_capacity(CLONE_BATCH_SIZE.min(cids.len()));
+
+        for cid in cids {
+            match self.get(cid).await {
+                Ok(block) => batch.push(block),
+                Err(e) if e.is_not_found() => {
+                    debug!("Skipping clone of missing block {}", cid);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+
+            if batch.len() == CLONE_BATCH_SIZE {
+                copied += flush_clone_batch(&mut batch, target, copied).await?;
+            }
+        }
+        copied += flush_clone_batch(&mut batch, target, copied).await?;
+
+        Ok(copied)
+    }
+
+    /// Rebuild the block metadata index from the stored block data.
+    ///
+    /// Replaces every metadata entry with one computed from the block bodies,
+    /// in a single transaction. Returns the number of blocks indexed. Only the
+    /// redb backend keeps a separate metadata index.
+    pub async fn rebuild_index(&self) -> Result<usize, StorageError> {
+        match &self.backend {
+            StoreBackend::Redb(redb) => redb.rebuild_index().await,
+            _ => Err(StorageError::DatabaseError(
+                "index rebuild is only supported by the redb backend".to_string(),
+            )),
+        }
+    }
+
+    /// Compare the block metadata index with the stored block data.
+    ///
+    /// Returns the CIDs whose metadata is missing, has the wrong size, or has
+    /// no block behind it. Always empty for backends without a separate
+    /// metadata index.
+    pub async fn check_index_consistency(&self) -> Result<Vec<Cid>, StorageError> {
+        match &self.backend {
+            StoreBackend::Redb(redb) => redb.check_index_consistency().await,
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Read every block and check its data still matches its CID.
+    ///
+    /// Also compares the metadata index with the stored data, see
+    /// [`BlockStore::check_index_consistency`]. Corrupted blocks are only
+    /// reported, never deleted.
+    pub async fn verify_integrity(&self) -> Result<IntegrityReport, StorageError> {
+        let mut report = IntegrityReport::default();
+        for cid in self.list_cids().await {
+            let block = match self.get_unverified(&cid).await {
+                Ok(block) => block,
+                Err(e) if e.is_not_found() => continue,
+                Err(e) => return Err(e),
+            };
+            report.checked += 1;
+            if let Err(e) = verify_blake3(&block.data, &cid) {
+                warn!("Stored block {} is corrupted: {}", cid, e);
+                report.corrupted.push(cid);
+            }
+        }
+        report.index_mismatches = self.check_index_consistency().await?;
+        Ok(report)
+    }
+
+    /// Flush block data buffered by the OS to disk.
+    ///
+    /// redb commits are durable on their own, but the file backends only
+    /// fsync writes when their `*_FSYNC` flag is set; a checkpoint bounds
+    /// how much of their data a power loss can take. Blocks written while
+    /// the checkpoint runs may not be covered.
+    pub async fn checkpoint(&self) -> Result<(), StorageError> {
+        let path = self.backend.root_path().to_path_buf();
+        tokio::task::spawn_blocking(move || sync_filesystem(&path))
+            .await
+            .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))?
+    }
+
+    /// Write a point-in-time copy of the store to `path`.
+    ///
+    /// The copy can be opened with [`BlockStore::new_with_path`]. Writes are
+    /// held back while it is taken. Only the redb backend supports snapshots.
+    pub async fn create_snapshot(&self, path: &Path) -> Result<(), StorageError> {
+        match &self.backend {
+            StoreBackend::Redb(redb) => redb.create_snapshot(path).await,
+            _ => Err(StorageError::DatabaseError(
+                "snapshots are only supported by the redb backend".to_string(),
+            )),
+        }
+    }
+
     /// Clear all blocks.
     pub async fn clear(&self) {
         match &self.backend {
@@ -412,6 +1000,134 @@ impl BlockStore {
     }
 }
 
+impl StoreBackend {
+    /// Database file or root directory holding the backend's data
+    fn root_path(&self) -> &Path {
+        match self {
+            StoreBackend::Redb(redb) => &redb.db_path,
+            StoreBackend::DeltaStore(delta) => &delta.root,
+            StoreBackend::DeltaFlat(deltaflat) => &deltaflat.root,
+            StoreBackend::GeomTree(tree) => &tree.root,
+        }
+    }
+
+    /// Pass every stored CID to `visit` until it returns false (blocking)
+    fn scan_cids(&self, visit: &mut dyn FnMut(Cid) -> bool) -> Result<(), StorageError> {
+        match self {
+            StoreBackend::Redb(redb) => redb.scan_cids(visit),
+            StoreBackend::DeltaStore(delta) => delta.scan_cids(visit),
+            StoreBackend::DeltaFlat(deltaflat) => deltaflat.scan_cids(visit),
+            StoreBackend::GeomTree(tree) => tree.scan_cids(visit),
+        }
+    }
+}
+
+/// Error returned for a cached [`BlockStore::disk_stats`] failure
+fn disk_stats_error(message: String) -> StorageError {
+    StorageError::IoError(std::io::Error::other(message))
+}
+
+/// Add up the sizes of the files at or below `path` (blocking)
+///
+/// Files and directories removed while the walk runs, such as compacted
+/// database files, are skipped.
+fn measure_disk_usage(root: &Path) -> Result<BlockStoreDiskStats, StorageError> {
+    // Only entries below the root may vanish without failing the walk
+    let skip_missing = |path: &Path, e: std::io::Error| {
+        if e.kind() == std::io::ErrorKind::NotFound && path != root {
+            Ok(())
+        } else {
+            Err(e)
+        }
+    };
+
+    let mut stats = BlockStoreDiskStats::default();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                skip_missing(&path, e)?;
+                continue;
+            }
+        };
+        if metadata.is_dir() {
+            let entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    skip_missing(&path, e)?;
+                    continue;
+                }
+            };
+            for entry in entries {
+                match entry {
+                    Ok(entry) => pending.push(entry.path()),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        } else if metadata.is_file() {
+            stats.file_count += 1;
+            stats.data_size += metadata.len();
+            stats.allocated_size += allocated_bytes(&metadata);
+        }
+    }
+    Ok(stats)
+}
+
+/// Bytes allocated on disk for a file
+#[cfg(unix)]
+fn allocated_bytes(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always counted in 512-byte units
+    metadata.blocks() * 512
+}
+
+/// Bytes allocated on disk for a file
+#[cfg(not(unix))]
+fn allocated_bytes(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// Flush the filesystem holding `path` to disk (blocking)
+#[cfg(target_os = "linux")]
+fn sync_filesystem(path: &Path) -> Result<(), StorageError> {
+    let file = fs::File::open(path)?;
+    // SAFETY: `file` keeps the descriptor open for the duration of the call.
+    if unsafe { libc::syncfs(file.as_raw_fd()) } == -1 {
+        return Err(StorageError::IoError(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Flush `path` to disk (blocking)
+#[cfg(not(target_os = "linux"))]
+fn sync_filesystem(path: &Path) -> Result<(), StorageError> {
+    fs::File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+/// Write a pending `clone_cids_to` batch and log progress when it crosses a
+/// `CLONE_PROGRESS_INTERVAL` boundary. Returns the number of blocks written.
+async fn flush_clone_batch(
+    batch: &mut Vec<Block>,
+    target: &BlockStore,
+    copied_so_far: usize,
+) -> Result<usize, StorageError> {
+    if batch.is_empty() {
+        return Ok(0);
+    }
+
+    let count = batch.len();
+    target.put_many(std::mem::take(batch)).await?;
+
+    let total = copied_so_far + count;
+    if total / CLONE_PROGRESS_INTERVAL > copied_so_far / CLONE_PROGRESS_INTERVAL {
+        info!("Clone progress: {} blocks copied", total);
+    }
+    Ok(count)
+}
+
 impl RedbStore {
     fn open(path: &Path) -> Result<Self, StorageError> {
         let db_path = Self::resolve_db_path(path);
@@ -429,6 +1145,9 @@ impl RedbStore {
         {
             let write_txn = db.begin_write().map_err(Self::db_err)?;
             write_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
+            write_txn
+                .open_table(BLOCK_META_TABLE)
+                .map_err(Self::db_err)?;
             write_txn.commit().map_err(Self::db_err)?;
         }
 
@@ -472,6 +1191,9 @@ impl RedbStore {
             let write_txn = db.begin_write().map_err(Self::db_err)?;
             {
                 let mut table = write_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
+                let mut meta = write_txn
+                    .open_table(BLOCK_META_TABLE)
+                    .map_err(Self::db_err)?;
                 for (key, value) in &prepared {
                     if table.get(key.as_str()).map_err(Self::db_err)?.is_some() {
                         debug!("Block already exists: {}", key);
@@ -480,6 +1202,8 @@ impl RedbStore {
                     table
                         .insert(key.as_str(), value.as_slice())
                         .map_err(Self::db_err)?;
+                    meta.insert(key.as_str(), value.len() as u64)
+                        .map_err(Self::db_err)?;
                 }
             }
             write_txn.commit().map_err(Self::db_err)?;
@@ -516,6 +1240,32 @@ impl RedbStore {
         })
     }
 
+    async fn block_size(&self, cid: &Cid) -> Result<usize, StorageError> {
+        let key = cid.to_string();
+        let db = Arc::clone(&self.db);
+
+        tokio::task::spawn_blocking(move || {
+            let read_txn = db.begin_read().map_err(Self::db_err)?;
+            let meta = read_txn
+                .open_table(BLOCK_META_TABLE)
+                .map_err(Self::db_err)?;
+            if let Some(size) = meta.get(key.as_str()).map_err(Self::db_err)? {
+                return Ok(size.value() as usize);
+            }
+
+            // Blocks written before the metadata table existed have no entry;
+            // fall back to the stored value length.
+            let table = read_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
+            table
+                .get(key.as_str())
+                .map_err(Self::db_err)?
+                .map(|v| v.value().len())
+                .ok_or(StorageError::BlockNotFound(key))
+        })
+        .await
+        .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))?
+    }
+
     async fn has(&self, cid: &Cid) -> bool {
         let cid_str = cid.to_string();
         let db = Arc::clone(&self.db);
@@ -531,6 +1281,24 @@ impl RedbStore {
         .unwrap_or(false)
     }
 
+    async fn has_many(&self, cids: &[Cid]) -> Vec<bool> {
+        let keys: Vec<String> = cids.iter().map(Cid::to_string).collect();
+        let count = keys.len();
+        let db = Arc::clone(&self.db);
+
+        tokio::task::spawn_blocking(move || {
+            let read_txn = db.begin_read().map_err(Self::db_err)?;
+            let table = read_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
+            keys.iter()
+                .map(|key| Ok(table.get(key.as_str()).map_err(Self::db_err)?.is_some()))
+                .collect::<Result<Vec<bool>, StorageError>>()
+        })
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or_else(|| vec![false; count])
+    }
+
     async fn delete(&self, cid: &Cid) -> Result<(), StorageError> {
         let cid_str = cid.to_string();
         let db = Arc::clone(&self.db);
@@ -543,6 +1311,10 @@ impl RedbStore {
                 if table.remove(key.as_str()).map_err(Self::db_err)?.is_none() {
                     return Err(StorageError::BlockNotFound(key));
                 }
+                let mut meta = write_txn
+                    .open_table(BLOCK_META_TABLE)
+                    .map_err(Self::db_err)?;
+                meta.remove(key.as_str()).map_err(Self::db_err)?;
             }
             write_txn.commit().map_err(Self::db_err)?;
             Ok::<(), StorageError>(())
@@ -554,21 +1326,30 @@ impl RedbStore {
         Ok(())
     }
 
-    async fn list_cids(&self) -> Vec<Cid> {
-        let db = Arc::clone(&self.db);
+    /// Pass every stored CID to `visit` until it returns false (blocking)
+    fn scan_cids(&self, visit: &mut dyn FnMut(Cid) -> bool) -> Result<(), StorageError> {
+        let read_txn = self.db.begin_read().map_err(Self::db_err)?;
+        let table = read_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
 
-        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
-            let read_txn = db.begin_read().map_err(Self::db_err)?;
-            let table = read_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
-            let mut cids = Vec::new();
-
-            for entry in table.iter().map_err(Self::db_err)? {
-                let (key, _) = entry.map_err(Self::db_err)?;
-                if let Ok(cid) = key.value().parse::<Cid>() {
-                    cids.push(cid);
+        for entry in table.iter().map_err(Self::db_err)? {
+            let (key, _) = entry.map_err(Self::db_err)?;
+            if let Ok(cid) = key.value().parse::<Cid>() {
+                if !visit(cid) {
+                    break;
                 }
             }
+        }
+        Ok(())
+    }
 
+    async fn list_cids(&self) -> Vec<Cid> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
+            let mut cids = Vec::new();
+            store.scan_cids(&mut |cid| {
+                cids.push(cid);
+                true
+            })?;
             Ok(cids)
         })
         .await
@@ -615,6 +1396,100 @@ impl RedbStore {
         })
     }
 
+    async fn rebuild_index(&self) -> Result<usize, StorageError> {
+        let db = Arc::clone(&self.db);
+
+        let indexed = tokio::task::spawn_blocking(move || -> Result<usize, StorageError> {
+            let write_txn = db.begin_write().map_err(Self::db_err)?;
+            let indexed = {
+                let table = write_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
+                let mut meta = write_txn
+                    .open_table(BLOCK_META_TABLE)
+                    .map_err(Self::db_err)?;
+
+                let mut stale = Vec::new();
+                for entry in meta.iter().map_err(Self::db_err)? {
+                    let (key, _) = entry.map_err(Self::db_err)?;
+                    stale.push(key.value().to_string());
+                }
+                for key in stale {
+                    meta.remove(key.as_str()).map_err(Self::db_err)?;
+                }
+
+                let mut indexed = 0usize;
+                for entry in table.iter().map_err(Self::db_err)? {
+                    let (key, value) = entry.map_err(Self::db_err)?;
+                    if key.value().parse::<Cid>().is_err() {
+                        warn!("Skipping block with invalid CID key {}", key.value());
+                        continue;
+                    }
+                    meta.insert(key.value(), value.value().len() as u64)
+                        .map_err(Self::db_err)?;
+                    indexed += 1;
+                }
+                indexed
+            };
+            write_txn.commit().map_err(Self::db_err)?;
+            Ok(indexed)
+        })
+        .await
+        .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))??;
+
+        info!("Rebuilt block index for {} blocks", indexed);
+        Ok(indexed)
+    }
+
+    async fn check_index_consistency(&self) -> Result<Vec<Cid>, StorageError> {
+        let db = Arc::clone(&self.db);
+
+        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
+            let read_txn = db.begin_read().map_err(Self::db_err)?;
+            let table = read_txn.open_table(BLOCKS_TABLE).map_err(Self::db_err)?;
+            let meta = read_txn
+                .open_table(BLOCK_META_TABLE)
+                .map_err(Self::db_err)?;
+            let mut inconsistent = Vec::new();
+
+            for entry in table.iter().map_err(Self::db_err)? {
+                let (key, value) = entry.map_err(Self::db_err)?;
+                let size = meta.get(key.value()).map_err(Self::db_err)?;
+                if size.map(|size| size.value()) != Some(value.value().len() as u64) {
+                    inconsistent.extend(key.value().parse::<Cid>().ok());
+                }
+            }
+            for entry in meta.iter().map_err(Self::db_err)? {
+                let (key, _) = entry.map_err(Self::db_err)?;
+                if table.get(key.value()).map_err(Self::db_err)?.is_none() {
+                    inconsistent.extend(key.value().parse::<Cid>().ok());
+                }
+            }
+
+            Ok(inconsistent)
+        })
+        .await
+        .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))?
+    }
+
+    async fn create_snapshot(&self, path: &Path) -> Result<(), StorageError> {
+        let db = Arc::clone(&self.db);
+        let source = self.db_path.clone();
+        let target = Self::resolve_db_path(path);
+
+        tokio::task::spawn_blocking(move || -> Result<(), StorageError> {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // Holding the write transaction keeps the file at the last commit
+            let write_txn = db.begin_write().map_err(Self::db_err)?;
+            fs::copy(&source, &target)?;
+            write_txn.abort().map_err(Self::db_err)?;
+            info!("Wrote redb snapshot to {:?}", target);
+            Ok(())
+        })
+        .await
+        .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))?
+    }
+
     async fn clear(&self) {
         let db = Arc::clone(&self.db);
         let db_path = self.db_path.clone();
@@ -629,8 +1504,12 @@ impl RedbStore {
                     keys.push(key.value().to_string());
                 }
 
+                let mut meta = write_txn
+                    .open_table(BLOCK_META_TABLE)
+                    .map_err(Self::db_err)?;
                 for key in keys {
                     let _ = table.remove(key.as_str()).map_err(Self::db_err)?;
+                    let _ = meta.remove(key.as_str()).map_err(Self::db_err)?;
                 }
             }
             write_txn.commit().map_err(Self::db_err)?;
@@ -991,6 +1870,30 @@ impl DeltaStore {
         .unwrap_or(false)
     }
 
+    async fn has_many(&self, cids: &[Cid]) -> Vec<bool> {
+        let keys: Vec<String> = cids.iter().map(Cid::to_string).collect();
+        let count = keys.len();
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || -> Result<Vec<bool>, StorageError> {
+            let read_txn = db.begin_read().map_err(RedbStore::db_err)?;
+            let index = read_txn
+                .open_table(DELTA_INDEX_TABLE)
+                .map_err(RedbStore::db_err)?;
+            keys.iter()
+                .map(|key| {
+                    Ok(index
+                        .get(key.as_str())
+                        .map_err(RedbStore::db_err)?
+                        .is_some())
+                })
+                .collect()
+        })
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or_else(|| vec![false; count])
+    }
+
     async fn delete(&self, cid: &Cid) -> Result<(), StorageError> {
         let cid_key = cid.to_string();
         let db = Arc::clone(&self.db);
@@ -1015,20 +1918,31 @@ impl DeltaStore {
         .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))?
     }
 
-    async fn list_cids(&self) -> Vec<Cid> {
-        let db = Arc::clone(&self.db);
-        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
-            let read_txn = db.begin_read().map_err(RedbStore::db_err)?;
-            let index = read_txn
-                .open_table(DELTA_INDEX_TABLE)
-                .map_err(RedbStore::db_err)?;
-            let mut out = Vec::new();
-            for entry in index.iter().map_err(RedbStore::db_err)? {
-                let (k, _) = entry.map_err(RedbStore::db_err)?;
-                if let Ok(cid) = k.value().parse::<Cid>() {
-                    out.push(cid);
+    /// Pass every stored CID to `visit` until it returns false (blocking)
+    fn scan_cids(&self, visit: &mut dyn FnMut(Cid) -> bool) -> Result<(), StorageError> {
+        let read_txn = self.db.begin_read().map_err(RedbStore::db_err)?;
+        let index = read_txn
+            .open_table(DELTA_INDEX_TABLE)
+            .map_err(RedbStore::db_err)?;
+        for entry in index.iter().map_err(RedbStore::db_err)? {
+            let (k, _) = entry.map_err(RedbStore::db_err)?;
+            if let Ok(cid) = k.value().parse::<Cid>() {
+                if !visit(cid) {
+                    break;
                 }
             }
+        }
+        Ok(())
+    }
+
+    async fn list_cids(&self) -> Vec<Cid> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
+            let mut out = Vec::new();
+            store.scan_cids(&mut |cid| {
+                out.push(cid);
+                true
+            })?;
             Ok(out)
         })
         .await
@@ -2276,42 +3190,53 @@ impl DeltaFlatStore {
         .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))?
     }
 
-    async fn list_cids(&self) -> Vec<Cid> {
-        let store = self.clone();
-        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
-            let mut out = Vec::new();
-            for class_id in 0..DELTA_SIZE_CLASSES.len() as u8 {
-                for lane_id in 0..store.lane_count {
-                    let lane_dir = store.lane_dir(class_id, lane_id);
-                    if !lane_dir.exists() {
-                        continue;
-                    }
-                    let control_path = store.class_control_path(class_id, lane_id);
-                    let index_path = store.class_index_path(class_id, lane_id);
-                    if !control_path.exists() || !index_path.exists() {
-                        continue;
-                    }
+    /// Pass every stored CID to `visit` until it returns false (blocking)
+    fn scan_cids(&self, visit: &mut dyn FnMut(Cid) -> bool) -> Result<(), StorageError> {
+        for class_id in 0..DELTA_SIZE_CLASSES.len() as u8 {
+            for lane_id in 0..self.lane_count {
+                let lane_dir = self.lane_dir(class_id, lane_id);
+                if !lane_dir.exists() {
+                    continue;
+                }
+                let control_path = self.class_control_path(class_id, lane_id);
+                let index_path = self.class_index_path(class_id, lane_id);
+                if !control_path.exists() || !index_path.exists() {
+                    continue;
+                }
 
-                    let _lane_guard = store.lane_read_guard(class_id, lane_id)?;
-                    let mut control_file = fs::OpenOptions::new().read(true).open(&control_path)?;
-                    let mut index_file = fs::OpenOptions::new().read(true).open(&index_path)?;
-                    let Some(control) = Self::read_control_raw(&mut control_file)? else {
-                        continue;
-                    };
-                    let buckets = Self::bucket_count(&control)?;
-                    for bucket in 0..buckets {
-                        let page = Self::read_bucket(&mut index_file, bucket)?;
-                        for slot in 0..DELTAFLAT_ENTRIES_PER_BUCKET {
-                            let Some(entry) = Self::decode_entry(&page, slot)? else {
-                                continue;
-                            };
-                            if let Ok(cid) = Cid::try_from(entry.cid.as_slice()) {
-                                out.push(cid);
+                let _lane_guard = self.lane_read_guard(class_id, lane_id)?;
+                let mut control_file = fs::OpenOptions::new().read(true).open(&control_path)?;
+                let mut index_file = fs::OpenOptions::new().read(true).open(&index_path)?;
+                let Some(control) = Self::read_control_raw(&mut control_file)? else {
+                    continue;
+                };
+                let buckets = Self::bucket_count(&control)?;
+                for bucket in 0..buckets {
+                    let page = Self::read_bucket(&mut index_file, bucket)?;
+                    for slot in 0..DELTAFLAT_ENTRIES_PER_BUCKET {
+                        let Some(entry) = Self::decode_entry(&page, slot)? else {
+                            continue;
+                        };
+                        if let Ok(cid) = Cid::try_from(entry.cid.as_slice()) {
+                            if !visit(cid) {
+                                return Ok(());
                             }
                         }
                     }
                 }
             }
+        }
+        Ok(())
+    }
+
+    async fn list_cids(&self) -> Vec<Cid> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
+            let mut out = Vec::new();
+            store.scan_cids(&mut |cid| {
+                out.push(cid);
+                true
+            })?;
             Ok(out)
         })
         .await
@@ -2629,24 +3554,32 @@ impl GeomTreeStore {
         .map_err(|e| StorageError::IoError(std::io::Error::other(e.to_string())))?
     }
 
-    async fn list_cids(&self) -> Vec<Cid> {
-        let root = self.root.clone();
-        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
-            let files = Self::walk_block_files(&root)?;
-            let mut cids = Vec::new();
-
-            for path in files {
-                let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
-                    continue;
-                };
-                let Some(cid_str) = name.strip_suffix(Self::FILE_EXT) else {
-                    continue;
-                };
-                if let Ok(cid) = cid_str.parse::<Cid>() {
-                    cids.push(cid);
+    /// Pass every stored CID to `visit` until it returns false (blocking)
+    fn scan_cids(&self, visit: &mut dyn FnMut(Cid) -> bool) -> Result<(), StorageError> {
+        for path in Self::walk_block_files(&self.root)? {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let Some(cid_str) = name.strip_suffix(Self::FILE_EXT) else {
+                continue;
+            };
+            if let Ok(cid) = cid_str.parse::<Cid>() {
+                if !visit(cid) {
+                    break;
                 }
             }
+        }
+        Ok(())
+    }
 
+    async fn list_cids(&self) -> Vec<Cid> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<Cid>, StorageError> {
+            let mut cids = Vec::new();
+            store.scan_cids(&mut |cid| {
+                cids.push(cid);
+                true
+            })?;
             Ok(cids)
         })
         .await
@@ -2715,6 +3648,13 @@ impl Default for BlockStore {
     }
 }
 
+/// Size information for a stored block, available without loading its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockMetadata {
+    pub cid: Cid,
+    pub size: usize,
+}
+
 /// Statistics about the block store
 #[derive(Debug, Clone)]
 pub struct BlockStoreStats {
@@ -2722,12 +3662,167 @@ pub struct BlockStoreStats {
     pub total_size: usize,
 }
 
+/// Findings of [`BlockStore::verify_integrity`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Blocks read and hashed
+    pub checked: usize,
+    /// Blocks whose data no longer matches their CID
+    pub corrupted: Vec<Cid>,
+    /// CIDs whose metadata index entry disagrees with the stored data
+    pub index_mismatches: Vec<Cid>,
+}
+
+impl IntegrityReport {
+    /// Whether no problems were found
+    pub fn is_ok(&self) -> bool {
+        self.corrupted.is_empty() && self.index_mismatches.is_empty()
+    }
+}
+
+/// Disk usage of the block store's files, see [`BlockStore::disk_stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockStoreDiskStats {
+    /// Files holding the store's data
+    pub file_count: u64,
+    /// Combined length of those files
+    pub data_size: u64,
+    /// Bytes allocated for those files on disk
+    pub allocated_size: u64,
+}
+
+/// Outcome counts of single-block puts, see [`BlockStore::put_stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PutStats {
+    /// Puts that stored a new block
+    pub inserted: usize,
+    /// Puts of a block that was already stored
+    pub deduplicated: usize,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::Path;
     use std::sync::Arc;
 
+    #[test]
+    fn test_storage_error_predicates() {
+        let not_found = StorageError::BlockNotFound("cid".to_string());
+        assert!(not_found.is_not_found());
+        assert!(!not_found.is_verification_failed());
+
+        let verification =
+            StorageError::VerificationFailed(CidError::InvalidCid("bad".to_string()));
+        assert!(verification.is_verification_failed());
+        assert!(!verification.is_not_found());
+
+        let io = StorageError::IoError(std::io::Error::other("disk"));
+        assert!(io.is_io_error());
+        assert!(!io.is_database_error());
+
+        let database = StorageError::DatabaseError("corrupt".to_string());
+        assert!(database.is_database_error());
+        assert!(!database.is_io_error());
+    }
+
+    #[test]
+    fn test_storage_error_status_codes() {
+        use crate::fetcher::SourceError;
+        use axum::http::StatusCode;
+
+        let missing = Block::new(b"missing".to_vec()).unwrap().cid;
+        let cases = [
+            (
+                StorageError::BlockNotFound("cid".to_string()),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                StorageError::VerificationFailed(CidError::InvalidCid("bad".to_string())),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                StorageError::BlockExists("cid".to_string()),
+                StatusCode::CONFLICT,
+            ),
+            (
+                StorageError::DatabaseError("corrupt".to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                StorageError::IoError(std::io::Error::other("disk")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                StorageError::FlushTimeout { pending: 1 },
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                StorageError::FetchFailed {
+                    cid: "cid".to_string(),
+                    source: Box::new(FetchError::BlockUnavailable {
+                        cid: missing,
+                        attempts: 1,
+                        source: SourceError::NotFound,
+                    }),
+                },
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                StorageError::FetchFailed {
+                    cid: "cid".to_string(),
+                    source: Box::new(FetchError::DeadlineExceeded {
+                        cid: missing,
+                        timeout: Duration::from_secs(1),
+                    }),
+                },
+                StatusCode::GATEWAY_TIMEOUT,
+            ),
+        ];
+        for (err, expected) in cases {
+            assert_eq!(StatusCode::from(err), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_or_fetch_caches_fetched_block() {
+        let store = BlockStore::new();
+        let block = Block::new(b"fetched once".to_vec()).unwrap();
+        let calls = AtomicUsize::new(0);
+        let fetcher = |cid: Cid| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            let block = block.clone();
+            async move {
+                if cid == block.cid {
+                    Ok(block)
+                } else {
+                    Err(FetchError::NotAManifest(cid))
+                }
+            }
+        };
+
+        assert_eq!(
+            store.get_or_fetch(&block.cid, &fetcher).await.unwrap(),
+            block
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(store.has(&block.cid).await);
+
+        // Served locally from now on
+        assert_eq!(
+            store.get_or_fetch(&block.cid, &fetcher).await.unwrap(),
+            block
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let missing = Block::new(b"nowhere".to_vec()).unwrap().cid;
+        assert!(matches!(
+            store.get_or_fetch(&missing, &fetcher).await,
+            Err(StorageError::FetchFailed { .. })
+        ));
+        assert!(!store.has(&missing).await);
+    }
+
     #[tokio::test]
     async fn test_block_new() {
         let data = b"hello world".to_vec();
@@ -2752,6 +3847,15 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_block_with_custom_cid_skips_verification() {
+        let cid = Block::new(b"hello world".to_vec()).unwrap().cid;
+        let block = Block::with_custom_cid(cid, b"goodbye world".to_vec());
+
+        assert_eq!(block.cid, cid);
+        assert!(verify_blake3(&block.data, &block.cid).is_err());
+    }
+
     #[tokio::test]
     async fn test_store_put_get() {
         let store = BlockStore::new();
@@ -2797,6 +3901,28 @@ mod tests {
         assert!(store.has(&cid).await);
     }
 
+    #[tokio::test]
+    async fn test_has_many_matches_has_on_every_backend() {
+        for backend in ["redb", "deltastore", "deltaflat", "geomtree"] {
+            let temp_dir = std::env::temp_dir().join(format!(
+                "neverust-has-many-test-{}-{}",
+                backend,
+                rand::random::<u64>()
+            ));
+            let store = BlockStore::new_with_backend(&temp_dir, backend).unwrap();
+            let stored = Block::new(b"stored".to_vec()).unwrap();
+            let missing = Block::new(b"missing".to_vec()).unwrap();
+            store.put(stored.clone()).await.unwrap();
+
+            let present = store
+                .has_many(&[missing.cid, stored.cid, missing.cid])
+                .await;
+            assert_eq!(present, vec![false, true, false], "backend {}", backend);
+            assert!(store.has_many(&[]).await.is_empty());
+            let _ = std::fs::remove_dir_all(&temp_dir);
+        }
+    }
+
     #[tokio::test]
     async fn test_store_delete() {
         let store = BlockStore::new();
@@ -2817,6 +3943,30 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_store_get_metadata() {
+        let store = BlockStore::new();
+        let data = vec![7u8; 4096];
+        let cid = store.put_data(data).await.unwrap();
+
+        let metadata = store.get_metadata(&cid).await.unwrap();
+        assert_eq!(metadata.cid, cid);
+        assert_eq!(metadata.size, 4096);
+        assert_eq!(store.block_size(&cid).await.unwrap(), 4096);
+
+        store.delete(&cid).await.unwrap();
+        assert!(store.get_metadata(&cid).await.unwrap_err().is_not_found());
+    }
+
+    #[tokio::test]
+    async fn test_store_get_metadata_unknown_cid() {
+        let store = BlockStore::new();
+        let cid = Block::new(b"never stored".to_vec()).unwrap().cid;
+
+        let err = store.get_metadata(&cid).await.unwrap_err();
+        assert!(matches!(err, StorageError::BlockNotFound(_)));
+    }
+
     #[tokio::test]
     async fn test_store_list_cids() {
         let store = BlockStore::new();
@@ -2838,6 +3988,45 @@ mod tests {
         assert!(cids.contains(&cid3));
     }
 
+    #[tokio::test]
+    async fn test_iterate_streams_every_block() {
+        use futures::{StreamExt, TryStreamExt};
+
+        for backend in ["redb", "deltastore", "deltaflat", "geomtree"] {
+            let temp_dir = std::env::temp_dir()
+                .join(format!("neverust-iterate-test-{}", rand::random::<u64>()));
+            let store = BlockStore::new_with_backend(&temp_dir, backend).unwrap();
+
+            // More blocks than the scan may read ahead
+            let count = ITERATE_BUFFER * 5 / 2;
+            let mut expected = HashSet::new();
+            for i in 0..count {
+                expected.insert(
+                    store
+                        .put_data(format!("block {}", i).into_bytes())
+                        .await
+                        .unwrap(),
+                );
+            }
+
+            let cids: HashSet<Cid> = store.iterate_cids().try_collect().await.unwrap();
+            assert_eq!(cids, expected, "{} CIDs", backend);
+
+            let mut seen = HashSet::new();
+            let mut blocks = store.iterate();
+            while let Some(block) = blocks.next().await {
+                let block = block.unwrap();
+                assert_eq!(Block::new(block.data).unwrap().cid, block.cid);
+                seen.insert(block.cid);
+            }
+            assert_eq!(seen, expected, "{} blocks", backend);
+
+            // Stopping early leaves the rest of the store unread
+            let first: Vec<_> = store.iterate_cids().take(5).collect().await;
+            assert_eq!(first.len(), 5);
+        }
+    }
+
     #[tokio::test]
     async fn test_store_stats() {
         let store = BlockStore::new();
@@ -2860,6 +4049,186 @@ mod tests {
         assert_eq!(stats.total_size, 300);
     }
 
+    #[tokio::test]
+    async fn test_count_by_codec() {
+        let store = BlockStore::new();
+        let manifest = crate::manifest::Manifest::new(
+            blake3_cid(b"tree").unwrap(),
+            1024,
+            2048,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        store.put(manifest.to_block().unwrap()).await.unwrap();
+        store.put_data(b"data block 1".to_vec()).await.unwrap();
+        store.put_data(b"data block 2".to_vec()).await.unwrap();
+
+        let counts = store.count_by_codec().await.unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[&crate::manifest::MANIFEST_CODEC], 1);
+        assert_eq!(counts[&0xcd02], 2);
+
+        // Counts are cached until the TTL runs out
+        store.put_data(b"data block 3".to_vec()).await.unwrap();
+        assert_eq!(store.count_by_codec().await.unwrap()[&0xcd02], 2);
+        *store.codec_counts.write().unwrap() = None;
+        assert_eq!(store.count_by_codec().await.unwrap()[&0xcd02], 3);
+    }
+
+    #[tokio::test]
+    async fn test_disk_stats_after_store() {
+        for backend in ["redb", "deltaflat", "geomtree"] {
+            let temp_dir = std::env::temp_dir().join(format!(
+                "neverust-disk-stats-test-{}",
+                rand::random::<u64>()
+            ));
+            let store = BlockStore::new_with_backend(&temp_dir, backend).unwrap();
+            store.put_data(vec![7u8; 64 * 1024]).await.unwrap();
+
+            let stats = store.disk_stats().await.unwrap();
+            assert!(stats.file_count > 0, "{}", backend);
+            assert!(stats.data_size >= 64 * 1024, "{}", backend);
+            assert!(stats.allocated_size > 0, "{}", backend);
+            assert_eq!(
+                store.size_on_disk().await.unwrap(),
+                stats.allocated_size,
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_measure_disk_usage_requires_root() {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "neverust-disk-usage-test-{}",
+            rand::random::<u64>()
+        ));
+        fs::create_dir_all(temp_dir.join("sub")).unwrap();
+        fs::write(temp_dir.join("sub").join("file"), [0u8; 100]).unwrap();
+        let stats = measure_disk_usage(&temp_dir).unwrap();
+        assert_eq!((stats.file_count, stats.data_size), (1, 100));
+
+        // Missing entries below the root are skipped, a missing root is not
+        fs::remove_dir_all(&temp_dir).unwrap();
+        assert!(measure_disk_usage(&temp_dir).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disk_stats_caches_failures() {
+        let temp_dir = std::env::temp_dir().join(format!(
+            "neverust-disk-stats-failure-test-{}",
+            rand::random::<u64>()
+        ));
+        let store = BlockStore::new_with_path(&temp_dir).unwrap();
+        *store.disk_stats.write().unwrap() =
+            Some((std::time::Instant::now(), Err("walk failed".to_string())));
+
+        let err = store.disk_stats().await.unwrap_err();
+        assert!(err.to_string().contains("walk failed"));
+
+        // Once the TTL passes the store is measured again
+        let computed_at = std::time::Instant::now() - DISK_STATS_TTL;
+        store.disk_stats.write().unwrap().as_mut().unwrap().0 = computed_at;
+        assert!(store.disk_stats().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_with_verify_detects_corruption_at_rest() {
+        let data_block = Block::new(b"data at rest".to_vec()).unwrap();
+        // Manifests are hashed like any block but carry their own codec
+        let manifest_data = b"manifest at rest".to_vec();
+        let manifest_cid = Cid::new_v1(
+            crate::manifest::MANIFEST_CODEC,
+            *blake3_cid(&manifest_data).unwrap().hash(),
+        );
+        let manifest_block = Block::from_cid_and_data(manifest_cid, manifest_data).unwrap();
+
+        for block in [data_block, manifest_block] {
+            let temp_dir = std::env::temp_dir().join(format!(
+                "neverust-verify-read-test-{}",
+                rand::random::<u64>()
+            ));
+            let mut store = BlockStore::new_with_backend(&temp_dir, "redb").unwrap();
+            store.put(block.clone()).await.unwrap();
+            assert_eq!(store.get_with_verify(&block.cid).await.unwrap(), block);
+
+            // Intact blocks pass verify-on-read and survive delete-on-read
+            store.set_verify_on_read(true);
+            store.set_delete_corrupted_on_read(true);
+            assert_eq!(store.get(&block.cid).await.unwrap(), block);
+            assert!(store.has(&block.cid).await);
+            store.set_verify_on_read(false);
+            store.set_delete_corrupted_on_read(false);
+
+            // Flip the stored bytes behind the store's back
+            let mut corrupted = block.data.clone();
+            corrupted[0] ^= 0xff;
+            let StoreBackend::Redb(redb) = &store.backend else {
+                unreachable!("opened with the redb backend");
+            };
+            let write_txn = redb.db.begin_write().unwrap();
+            {
+                let mut table = write_txn.open_table(BLOCKS_TABLE).unwrap();
+                table
+                    .insert(block.cid.to_string().as_str(), corrupted.as_slice())
+                    .unwrap();
+            }
+            write_txn.commit().unwrap();
+
+            // A plain get does not re-verify
+            assert_eq!(store.get(&block.cid).await.unwrap().data, corrupted);
+            let err = store.get_with_verify(&block.cid).await.unwrap_err();
+            assert!(err.is_verification_failed(), "{}", err);
+            assert!(store.has(&block.cid).await);
+
+            store.set_verify_on_read(true);
+            assert!(store
+                .get(&block.cid)
+                .await
+                .unwrap_err()
+                .is_verification_failed());
+            assert!(store.has(&block.cid).await);
+
+            store.set_delete_corrupted_on_read(true);
+            assert!(store
+                .get(&block.cid)
+                .await
+                .unwrap_err()
+                .is_verification_failed());
+            assert!(!store.has(&block.cid).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_integrity_reports_corrupted_blocks() {
+        let store = BlockStore::new();
+        let good = Block::new(b"intact block".to_vec()).unwrap();
+        store.put(good).await.unwrap();
+        assert_eq!(
+            store.verify_integrity().await.unwrap(),
+            IntegrityReport {
+                checked: 1,
+                ..IntegrityReport::default()
+            }
+        );
+
+        let cid = blake3_cid(b"original").unwrap();
+        store
+            .put(Block::with_custom_cid(cid, b"tampered".to_vec()))
+            .await
+            .unwrap();
+        let report = store.verify_integrity().await.unwrap();
+        assert_eq!(report.checked, 2);
+        assert_eq!(report.corrupted, vec![cid]);
+        assert!(!report.is_ok());
+        // Reporting leaves the block in place
+        assert!(store.has(&cid).await);
+    }
+
     #[tokio::test]
     async fn test_store_clear() {
         let store = BlockStore::new();
@@ -2879,6 +4248,230 @@ mod tests {
         assert_eq!(stats.total_size, 0);
     }
 
+    #[tokio::test]
+    async fn test_store_clone_to() {
+        let source = BlockStore::new();
+        let target = BlockStore::new();
+
+        for i in 0..25u8 {
+            source.put_data(vec![i; 64 + i as usize]).await.unwrap();
+        }
+
+        let copied = source.clone_to(&target).await.unwrap();
+        assert_eq!(copied, 25);
+
+        let source_stats = source.stats().await;
+        let target_stats = target.stats().await;
+        assert_eq!(source_stats.block_count, target_stats.block_count);
+        assert_eq!(source_stats.total_size, target_stats.total_size);
+
+        for cid in source.list_cids().await {
+            assert_eq!(
+                source.get(&cid).await.unwrap().data,
+                target.get(&cid).await.unwrap().data
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_clone_cids_to() {
+        let source = BlockStore::new();
+        let target = BlockStore::new();
+
+        let cid1 = source.put_data(b"block 1".to_vec()).await.unwrap();
+        let cid2 = source.put_data(b"block 2".to_vec()).await.unwrap();
+        let cid3 = source.put_data(b"block 3".to_vec()).await.unwrap();
+        let missing = Block::new(b"not in source".to_vec()).unwrap().cid;
+
+        let copied = source
+            .clone_cids_to(&[cid1, cid3, missing], &target)
+            .await
+            .unwrap();
+        assert_eq!(copied, 2);
+        assert!(target.has(&cid1).await);
+        assert!(!target.has(&cid2).await);
+        assert!(target.has(&cid3).await);
+        assert!(!target.has(&missing).await);
+    }
+
+    #[tokio::test]
+    async fn test_store_flush_waits_for_writes() {
+        let store = Arc::new(BlockStore::new());
+        store.flush(Duration::from_millis(10)).await.unwrap();
+
+        let guard = WriteGuard::new(&store.writes);
+        assert_eq!(store.pending_writes(), 1);
+        assert!(matches!(
+            store.flush(Duration::from_millis(20)).await,
+            Err(StorageError::FlushTimeout { pending: 1 })
+        ));
+
+        let flushing = {
+            let store = store.clone();
+            tokio::spawn(async move { store.flush(Duration::from_secs(5)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(guard);
+        flushing.await.unwrap().unwrap();
+        assert_eq!(store.pending_writes(), 0);
+
+        store.put_data(b"after flush".to_vec()).await.unwrap();
+        assert_eq!(store.pending_writes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_store_put_deduplicates() {
+        let store = BlockStore::new();
+        let block = Block::new(b"dedup me".to_vec()).unwrap();
+        let other = Block::new(b"something else".to_vec()).unwrap();
+
+        store.put(block.clone()).await.unwrap();
+        store.put(block.clone()).await.unwrap();
+        assert_eq!(
+            store.put_stats(),
+            PutStats {
+                inserted: 1,
+                deduplicated: 1
+            }
+        );
+
+        let err = store.put_strict(block.clone()).await.unwrap_err();
+        assert!(matches!(err, StorageError::BlockExists(ref cid) if *cid == block.cid.to_string()));
+        store.put_strict(other.clone()).await.unwrap();
+        store.put_data(other.data.clone()).await.unwrap();
+        assert_eq!(
+            store.put_stats(),
+            PutStats {
+                inserted: 2,
+                deduplicated: 3
+            }
+        );
+
+        assert_eq!(store.get(&block.cid).await.unwrap().data, block.data);
+        assert_eq!(store.stats().await.block_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_storage_events_reach_every_subscriber() {
+        use futures::StreamExt;
+
+        let store = BlockStore::new();
+        let mut first = store.subscribe();
+        let mut second = store.subscribe();
+        let mut stream = store.event_stream();
+
+        let block = Block::new(b"event block".to_vec()).unwrap();
+        store.put(block.clone()).await.unwrap();
+        // Duplicate puts store nothing and send no event
+        store.put(block.clone()).await.unwrap();
+        // Batches only announce blocks they newly stored, once each
+        let other = Block::new(b"other event block".to_vec()).unwrap();
+        store
+            .put_many(vec![block.clone(), other.clone(), other.clone()])
+            .await
+            .unwrap();
+        store.record_pin(block.cid, true);
+        store.record_pin(block.cid, false);
+        store.delete(&block.cid).await.unwrap();
+
+        let expected = [
+            StorageEvent::BlockStored(block.cid),
+            StorageEvent::BlockStored(other.cid),
+            StorageEvent::BlockPinned(block.cid),
+            StorageEvent::BlockUnpinned(block.cid),
+            StorageEvent::BlockDeleted(block.cid),
+        ];
+        for event in expected {
+            assert_eq!(first.recv().await.unwrap(), event);
+            assert_eq!(second.recv().await.unwrap(), event);
+            assert_eq!(stream.next().await.unwrap(), event);
+        }
+        assert!(first.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_index_recovers_corrupted_metadata() {
+        let temp_dir =
+            std::env::temp_dir().join(format!("neverust-index-test-{}", rand::random::<u64>()));
+        let store = BlockStore::new_with_backend(&temp_dir, "redb").unwrap();
+        let blocks: Vec<Block> = (0..3)
+            .map(|i| Block::new(vec![i as u8; 100 + i]).unwrap())
+            .collect();
+        store.put_many(blocks.clone()).await.unwrap();
+        assert!(store.check_index_consistency().await.unwrap().is_empty());
+
+        // Corrupt the metadata table directly: a wrong size, a missing entry
+        // and an entry for a block that does not exist
+        let orphan = Block::new(b"never stored".to_vec()).unwrap().cid;
+        let StoreBackend::Redb(redb) = &store.backend else {
+            panic!("expected redb backend");
+        };
+        let write_txn = redb.db.begin_write().unwrap();
+        {
+            let mut meta = write_txn.open_table(BLOCK_META_TABLE).unwrap();
+            meta.insert(blocks[0].cid.to_string().as_str(), 1).unwrap();
+            meta.remove(blocks[1].cid.to_string().as_str()).unwrap();
+            meta.insert(orphan.to_string().as_str(), 42).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        let mut inconsistent = store.check_index_consistency().await.unwrap();
+        inconsistent.sort();
+        let mut expected = vec![blocks[0].cid, blocks[1].cid, orphan];
+        expected.sort();
+        assert_eq!(inconsistent, expected);
+
+        assert_eq!(store.rebuild_index().await.unwrap(), 3);
+        assert!(store.check_index_consistency().await.unwrap().is_empty());
+        for block in &blocks {
+            assert_eq!(
+                store.block_size(&block.cid).await.unwrap(),
+                block.data.len() as u64
+            );
+        }
+        assert!(store.block_size(&orphan).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_and_snapshot() {
+        let temp_dir =
+            std::env::temp_dir().join(format!("neverust-snapshot-test-{}", rand::random::<u64>()));
+        let store = BlockStore::new_with_backend(temp_dir.join("store"), "redb").unwrap();
+        let block = Block::new(b"checkpointed".to_vec()).unwrap();
+        store.put(block.clone()).await.unwrap();
+
+        store.checkpoint().await.unwrap();
+
+        let snapshot_path = temp_dir.join("snapshot");
+        store.create_snapshot(&snapshot_path).await.unwrap();
+        // Later writes do not reach the snapshot
+        store
+            .put(Block::new(b"after snapshot".to_vec()).unwrap())
+            .await
+            .unwrap();
+
+        let snapshot = BlockStore::new_with_backend(&snapshot_path, "redb").unwrap();
+        assert_eq!(snapshot.get(&block.cid).await.unwrap(), block);
+        assert_eq!(snapshot.stats().await.block_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_requires_redb() {
+        let temp_dir =
+            std::env::temp_dir().join(format!("neverust-snapshot-test-{}", rand::random::<u64>()));
+        let store = BlockStore::new_with_backend(temp_dir.join("store"), "geomtree").unwrap();
+        store
+            .put(Block::new(b"file backend".to_vec()).unwrap())
+            .await
+            .unwrap();
+
+        store.checkpoint().await.unwrap();
+        assert!(store
+            .create_snapshot(&temp_dir.join("snapshot"))
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_store_idempotent_put() {
         let store = BlockStore::new();
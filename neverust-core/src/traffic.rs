@@ -7,17 +7,92 @@
 //! - No centralized coordination - truly peer-to-peer
 //!
 //! Enable with: ENABLE_TRAFFIC_GEN=true
+//!
+//! Also provides [`TrafficLimiter`], a token bucket used to rate limit
+//! inbound BlockExc traffic per peer.
 
 use crate::botg::BoTgProtocol;
 use crate::storage::{Block, BlockStore};
 use rand::Rng;
 use std::collections::HashSet;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
 use tracing::{info, warn};
 
+/// Token-bucket rate limiter for a byte stream
+///
+/// The bucket holds up to one second's worth of bytes and refills
+/// continuously at `bytes_per_second`. A consume larger than the bucket is
+/// allowed once the bucket is full and leaves it in debt, so oversized
+/// messages are delayed rather than rejected forever.
+#[derive(Debug)]
+pub struct TrafficLimiter {
+    bytes_per_second: u64,
+    bucket: Mutex<TokenBucket>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    /// Available bytes; negative while paying off an oversized consume
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TrafficLimiter {
+    /// Create a limiter allowing `bytes_per_second`, starting with a full
+    /// bucket
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        Self {
+            bytes_per_second,
+            bucket: Mutex::new(TokenBucket {
+                tokens: bytes_per_second as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Sustained rate in bytes per second
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Take `bytes` from the bucket if it has capacity
+    ///
+    /// Returns `Err(wait)` with how long until the bucket can cover the
+    /// request; nothing is taken in that case.
+    pub fn try_consume(&self, bytes: usize) -> Result<(), Duration> {
+        let rate = self.bytes_per_second as f64;
+        let mut bucket = self.bucket.lock().unwrap();
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.last_refill = now;
+
+        let needed = (bytes as f64).min(rate);
+        if bucket.tokens >= needed {
+            bucket.tokens -= bytes as f64;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((needed - bucket.tokens) / rate))
+        }
+    }
+
+    /// Wait until `bytes` can be taken from the bucket, then take them
+    ///
+    /// Returns how long the caller was delayed.
+    pub async fn consume(&self, bytes: usize) -> Duration {
+        let start = Instant::now();
+        while let Err(wait) = self.try_consume(bytes) {
+            sleep(wait).await;
+        }
+        start.elapsed()
+    }
+}
+
 /// Traffic generator configuration
 #[derive(Debug, Clone)]
 pub struct TrafficConfig {
@@ -306,3 +381,47 @@ fn parse_size(s: &str) -> Option<usize> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limiter_delays_burst_beyond_capacity() {
+        let limiter = TrafficLimiter::new(1000);
+
+        // The full bucket absorbs one second's worth immediately
+        assert_eq!(limiter.try_consume(600), Ok(()));
+        assert_eq!(limiter.try_consume(400), Ok(()));
+        assert_eq!(limiter.try_consume(500), Err(Duration::from_millis(500)));
+
+        let waited = limiter.consume(500).await;
+        assert!(
+            waited >= Duration::from_millis(499) && waited <= Duration::from_millis(510),
+            "waited {:?}",
+            waited
+        );
+        assert!(limiter.try_consume(1).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limiter_refill_is_capped() {
+        let limiter = TrafficLimiter::new(1000);
+        sleep(Duration::from_secs(10)).await;
+
+        assert_eq!(limiter.try_consume(1000), Ok(()));
+        assert!(limiter.try_consume(100).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limiter_oversized_consume_waits_for_full_bucket() {
+        let limiter = TrafficLimiter::new(1000);
+        limiter.try_consume(250).unwrap();
+
+        // 3000 bytes only needs a full bucket, then pays off the debt
+        assert_eq!(limiter.try_consume(3000), Err(Duration::from_millis(250)));
+        let waited = limiter.consume(3000).await;
+        assert!(waited >= Duration::from_millis(249) && waited <= Duration::from_millis(260));
+        assert_eq!(limiter.try_consume(1), Err(Duration::from_millis(2001)));
+    }
+}
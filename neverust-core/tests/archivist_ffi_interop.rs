@@ -13,7 +13,9 @@ use tempfile::TempDir;
 use tower::util::ServiceExt;
 
 use libp2p::identity::Keypair;
-use neverust_core::{api, Block, BlockStore, BoTgConfig, BoTgProtocol, Discovery, Metrics};
+use neverust_core::{
+    api, Block, BlockStore, BoTgConfig, BoTgProtocol, Discovery, DiscoveryConfig, Metrics,
+};
 
 type FfiStart = unsafe extern "C" fn(
     data_dir: *const c_char,
@@ -512,6 +514,7 @@ async fn ffi_embedded_archivist_discv5_provide_roundtrip() {
                 .expect("socket addr 1"),
             vec!["/ip4/127.0.0.1/tcp/28070".to_string()],
             vec![archivist_spr.clone()],
+            DiscoveryConfig::default(),
         )
         .await
         .expect("create discovery 1"),
@@ -524,6 +527,7 @@ async fn ffi_embedded_archivist_discv5_provide_roundtrip() {
                 .expect("socket addr 2"),
             vec!["/ip4/127.0.0.1/tcp/28071".to_string()],
             vec![archivist_spr],
+            DiscoveryConfig::default(),
         )
         .await
         .expect("create discovery 2"),
@@ -1,4 +1,4 @@
-use neverust_core::{Block, Discovery};
+use neverust_core::{Block, Discovery, DiscoveryConfig};
 use libp2p::identity::Keypair;
 use std::sync::Arc;
 use std::time::Duration;
@@ -57,6 +57,7 @@ async fn test_devnet_discv5_provide_roundtrip() {
             format!("0.0.0.0:{port1}").parse().expect("socket addr 1"),
             vec!["/ip4/127.0.0.1/tcp/28070".to_string()],
             bootstrap_nodes.clone(),
+            DiscoveryConfig::default(),
         )
         .await
         .expect("create discovery 1"),
@@ -67,6 +68,7 @@ async fn test_devnet_discv5_provide_roundtrip() {
             format!("0.0.0.0:{port2}").parse().expect("socket addr 2"),
             vec!["/ip4/127.0.0.1/tcp/28071".to_string()],
             bootstrap_nodes,
+            DiscoveryConfig::default(),
         )
         .await
         .expect("create discovery 2"),
@@ -0,0 +1,108 @@
+#![cfg(unix)]
+
+use std::time::Duration;
+
+use cid::Cid;
+use neverust_core::archivist_tree::ArchivistTree;
+use neverust_core::manifest::Manifest;
+use neverust_core::runtime::shutdown_signal;
+use neverust_core::{run_node_with_handle, Config};
+use tempfile::tempdir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::timeout;
+
+/// SIGTERM during an upload: the node finishes the upload before shutting
+/// down, and every block of it is stored
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn sigterm_waits_for_in_progress_block_writes() {
+    // Keep a SIGTERM listener installed for the whole test so a signal that
+    // arrives before `shutdown_signal` registers cannot kill the test process
+    let _guard = signal(SignalKind::terminate()).expect("install SIGTERM handler");
+    // Store each block as it arrives, so the upload is seen to be running
+    std::env::set_var("NEVERUST_UPLOAD_COMMIT_BATCH_BLOCKS", "1");
+
+    let dir = tempdir().expect("tempdir");
+    let (run, node) = run_node_with_handle(Config {
+        data_dir: dir.path().to_path_buf(),
+        listen_port: 0,
+        disc_port: 0,
+        api_port: 0,
+        api_bind: "127.0.0.1".to_string(),
+        no_bootstrap: true,
+        shutdown_timeout_secs: 30,
+        ..Config::default()
+    })
+    .await
+    .expect("start node");
+    // Shut down on SIGTERM, as `run_node` does
+    let signalled = {
+        let node = node.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            node.shutdown();
+        })
+    };
+
+    // Four full 1 MiB blocks and a short one
+    let payload: Vec<u8> = (0..4 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+    let (first_half, second_half) = payload.split_at(payload.len() / 2);
+    let mut upload = tokio::net::TcpStream::connect(node.api_addr())
+        .await
+        .expect("connect to API");
+    let head = format!(
+        "POST /api/archivist/v1/data HTTP/1.1\r\nHost: {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        node.api_addr(),
+        payload.len()
+    );
+    upload.write_all(head.as_bytes()).await.unwrap();
+    upload.write_all(first_half).await.unwrap();
+    timeout(Duration::from_secs(10), async {
+        while node.block_store().stats().await.block_count == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("upload did not start storing blocks");
+
+    while !signalled.is_finished() {
+        // SAFETY: sending a signal to our own process
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGTERM);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // The rest of the upload is still accepted
+    upload.write_all(second_half).await.unwrap();
+    let mut response = Vec::new();
+    upload.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    let manifest_cid: Cid = response
+        .rsplit("\r\n\r\n")
+        .next()
+        .unwrap()
+        .trim()
+        .parse()
+        .expect("manifest CID");
+
+    timeout(Duration::from_secs(40), run)
+        .await
+        .expect("node did not shut down")
+        .expect("node event loop");
+
+    let store = node.block_store();
+    assert_eq!(store.pending_writes(), 0);
+    let manifest = Manifest::from_block(&store.get(&manifest_cid).await.expect("manifest"))
+        .expect("decode manifest");
+    let metadata = store
+        .get(&manifest.metadata_cid().expect("metadata CID"))
+        .await
+        .expect("tree metadata");
+    let block_cids = ArchivistTree::deserialize_block_list(&metadata.data).unwrap();
+    assert_eq!(block_cids.len(), 5);
+    for cid in &block_cids {
+        assert!(store.has(cid).await, "block {} missing after shutdown", cid);
+    }
+}
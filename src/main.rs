@@ -2,13 +2,26 @@
 //!
 //! A high-performance P2P storage node implementation using rust-libp2p.
 
-use neverust_core::{load_or_generate_eth_key, run_node, Config};
 use neverust_core::config::CliAction;
+use neverust_core::request_log;
+use neverust_core::{load_or_generate_eth_key, run_node, Config};
 use std::error::Error;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::process::ExitCode;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
     match Config::parse_cli()? {
         CliAction::GenerateKey(path) => {
             // Standalone key generation — no node startup needed.
@@ -17,6 +30,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             println!("ETH address: {}", key.address_string());
             println!("Key file:    {}", path.display());
         }
+        CliAction::Offline(command) => {
+            // Reports go to stdout, so no logging is set up.
+            neverust_core::offline::run(command, &mut std::io::stdout()).await?;
+        }
         CliAction::Start(mut config) => {
             init_logging(&config.log_level);
             tracing::info!("Starting Neverust node...");
@@ -45,6 +62,12 @@ fn init_logging(level: &str) {
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(level)),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(
+            tracing_subscriber::fmt::layer().with_filter(filter_fn(|metadata| {
+                !request_log::is_request_event(metadata)
+            })),
+        )
+        // API request events go out as JSON lines
+        .with(request_log::json_layer(std::io::stdout))
         .init();
 }
@@ -2,6 +2,7 @@
 
 use futures_util::StreamExt;
 use libp2p::Multiaddr;
+use neverust_core::blockexc::{BlockExcClient, BlockExcConfig, ProtocolVersion};
 use neverust_core::{create_swarm, Block, BlockStore, Metrics};
 use std::sync::Arc;
 use std::time::Duration;
@@ -125,6 +126,158 @@ async fn test_two_nodes_exchange_blocks() -> Result<(), Box<dyn std::error::Erro
     Ok(())
 }
 
+/// Download a block stored on one node through the other node's BlockExc client
+#[tokio::test]
+async fn test_block_download_over_blockexc() -> Result<(), Box<dyn std::error::Error>> {
+    let store1 = Arc::new(BlockStore::new());
+    let store2 = Arc::new(BlockStore::new());
+    let (mut swarm1, _tx1, _keypair1) =
+        create_swarm(store1.clone(), "altruistic".to_string(), 1, Metrics::new()).await?;
+    let (mut swarm2, tx2, _keypair2) =
+        create_swarm(store2.clone(), "altruistic".to_string(), 1, Metrics::new()).await?;
+
+    let test_block = Block::new(b"Downloaded over BlockExc".to_vec())?;
+    let test_cid = test_block.cid;
+    store1.put(test_block.clone()).await?;
+
+    swarm1.listen_on("/ip4/127.0.0.1/tcp/0".parse()?)?;
+    let node1_addr = loop {
+        if let Some(libp2p::swarm::SwarmEvent::NewListenAddr { address, .. }) = swarm1.next().await
+        {
+            break address;
+        }
+    };
+    let peer1_id = *swarm1.local_peer_id();
+    swarm2.dial(format!("{}/p2p/{}", node1_addr, peer1_id).parse::<Multiaddr>()?)?;
+
+    let client = BlockExcClient::new(store2.clone(), Metrics::new(), 3, tx2);
+    let download = tokio::spawn(async move {
+        // Give the connection a moment to come up before asking for the block
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        client
+            .request_block_with_timeout(test_cid, Duration::from_secs(10))
+            .await
+    });
+    tokio::pin!(download);
+
+    let downloaded = timeout(Duration::from_secs(15), async {
+        loop {
+            tokio::select! {
+                result = &mut download => break result,
+                _ = swarm1.next() => {}
+                _ = swarm2.next() => {}
+            }
+        }
+    })
+    .await???;
+
+    assert_eq!(downloaded.data, test_block.data);
+    assert_eq!(downloaded.cid, test_cid);
+    assert!(store2.has(&test_cid).await, "downloaded block is stored");
+    Ok(())
+}
+
+/// Download a block from one node to another, returning the BlockExc
+/// version each side recorded for the other
+async fn download_with_versions(
+    server_enables_v2: bool,
+    client_enables_v2: bool,
+) -> Result<(Option<ProtocolVersion>, Option<ProtocolVersion>), Box<dyn std::error::Error>> {
+    let store1 = Arc::new(BlockStore::new());
+    let store2 = Arc::new(BlockStore::new());
+    let (mut swarm1, _tx1, _keypair1) =
+        create_swarm(store1.clone(), "altruistic".to_string(), 1, Metrics::new()).await?;
+    let (mut swarm2, tx2, _keypair2) =
+        create_swarm(store2.clone(), "altruistic".to_string(), 1, Metrics::new()).await?;
+    swarm1.behaviour_mut().blockexc.set_config(BlockExcConfig {
+        enable_v2: server_enables_v2,
+        ..BlockExcConfig::default()
+    });
+    swarm2.behaviour_mut().blockexc.set_config(BlockExcConfig {
+        enable_v2: client_enables_v2,
+        ..BlockExcConfig::default()
+    });
+
+    let test_block = Block::new(b"Downloaded over a negotiated version".to_vec())?;
+    let test_cid = test_block.cid;
+    store1.put(test_block.clone()).await?;
+
+    swarm1.listen_on("/ip4/127.0.0.1/tcp/0".parse()?)?;
+    let node1_addr = loop {
+        if let Some(libp2p::swarm::SwarmEvent::NewListenAddr { address, .. }) = swarm1.next().await
+        {
+            break address;
+        }
+    };
+    let peer1_id = *swarm1.local_peer_id();
+    let peer2_id = *swarm2.local_peer_id();
+    swarm2.dial(format!("{}/p2p/{}", node1_addr, peer1_id).parse::<Multiaddr>()?)?;
+
+    let client = BlockExcClient::new(store2.clone(), Metrics::new(), 3, tx2);
+    let download = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        client
+            .request_block_with_timeout(test_cid, Duration::from_secs(10))
+            .await
+    });
+    tokio::pin!(download);
+
+    let downloaded = timeout(Duration::from_secs(15), async {
+        loop {
+            tokio::select! {
+                result = &mut download => break result,
+                _ = swarm1.next() => {}
+                _ = swarm2.next() => {}
+            }
+        }
+    })
+    .await???;
+    assert_eq!(downloaded.data, test_block.data);
+
+    // Give the serving node's handler a moment to report its stream
+    let _ = timeout(Duration::from_millis(500), async {
+        loop {
+            tokio::select! {
+                _ = swarm1.next() => {}
+                _ = swarm2.next() => {}
+            }
+        }
+    })
+    .await;
+
+    Ok((
+        swarm1.behaviour().blockexc.protocol_version(&peer2_id),
+        swarm2.behaviour().blockexc.protocol_version(&peer1_id),
+    ))
+}
+
+/// Two nodes that support v2 exchange blocks over it
+#[tokio::test]
+async fn test_v2_nodes_negotiate_v2() -> Result<(), Box<dyn std::error::Error>> {
+    let versions = download_with_versions(true, true).await?;
+    assert_eq!(
+        versions,
+        (Some(ProtocolVersion::V2), Some(ProtocolVersion::V2))
+    );
+    Ok(())
+}
+
+/// A v2 node falls back to v1 with a v1-only node, whichever one serves
+#[tokio::test]
+async fn test_v2_node_falls_back_to_v1() -> Result<(), Box<dyn std::error::Error>> {
+    let versions = download_with_versions(false, true).await?;
+    assert_eq!(
+        versions,
+        (Some(ProtocolVersion::V1), Some(ProtocolVersion::V1))
+    );
+    let versions = download_with_versions(true, false).await?;
+    assert_eq!(
+        versions,
+        (Some(ProtocolVersion::V1), Some(ProtocolVersion::V1))
+    );
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_block_storage() -> Result<(), Box<dyn std::error::Error>> {
     let store = BlockStore::new();
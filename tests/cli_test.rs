@@ -0,0 +1,150 @@
+//! Integration tests for the offline block store subcommands
+
+use assert_cmd::Command;
+use neverust_core::{Block, BlockStore, Cid, Manifest};
+use predicates::prelude::*;
+use std::path::Path;
+
+/// Fill a new store at `path` with three blocks and a manifest over them
+///
+/// The store is dropped before returning so the CLI can open it.
+async fn populate_store(path: &Path) -> (Vec<Cid>, Cid) {
+    let store = BlockStore::new_with_path(path).unwrap();
+    let mut cids = Vec::new();
+    for i in 0..3u8 {
+        let block = Block::new(vec![i; 100]).unwrap();
+        cids.push(block.cid);
+        store.put(block).await.unwrap();
+    }
+    let manifest = Manifest::new(
+        cids[0],
+        100,
+        300,
+        None,
+        None,
+        None,
+        Some("data.bin".to_string()),
+        None,
+    );
+    let manifest_block = manifest.to_block().unwrap();
+    store.put(manifest_block.clone()).await.unwrap();
+    (cids, manifest_block.cid)
+}
+
+fn neverust() -> Command {
+    Command::new(assert_cmd::cargo::cargo_bin!("neverust"))
+}
+
+#[tokio::test]
+async fn test_info_reports_block_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let store_path = dir.path().join("blocks");
+    populate_store(&store_path).await;
+
+    neverust()
+        .arg("info")
+        .arg(&store_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Blocks:       4"));
+}
+
+#[test]
+fn test_info_fails_for_missing_store() {
+    let dir = tempfile::tempdir().unwrap();
+
+    neverust()
+        .arg("info")
+        .arg(dir.path().join("missing"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Error: No block store at"))
+        .stderr(predicate::str::contains("StoreNotFound").not());
+}
+
+#[tokio::test]
+async fn test_verify_detects_corruption() {
+    let dir = tempfile::tempdir().unwrap();
+    let store_path = dir.path().join("blocks");
+    let (cids, _) = populate_store(&store_path).await;
+
+    neverust()
+        .arg("verify")
+        .arg(&store_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Checked 4 blocks"))
+        .stdout(predicate::str::contains("All blocks match their CIDs"));
+
+    // Store different data under an existing CID
+    {
+        let store = BlockStore::new_with_path(&store_path).unwrap();
+        store.delete(&cids[1]).await.unwrap();
+        store
+            .put(Block::with_custom_cid(cids[1], vec![0xff; 100]))
+            .await
+            .unwrap();
+    }
+
+    neverust()
+        .arg("verify")
+        .arg(&store_path)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(format!("Corrupted: {}", cids[1])));
+}
+
+#[tokio::test]
+async fn test_export_then_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let source_path = dir.path().join("source");
+    let target_path = dir.path().join("target");
+    let car_path = dir.path().join("blocks.car");
+    let (cids, manifest_cid) = populate_store(&source_path).await;
+
+    neverust()
+        .arg("export")
+        .arg(&source_path)
+        .arg(&car_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exported 4 blocks"));
+
+    neverust()
+        .arg("import")
+        .arg(&target_path)
+        .arg(&car_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 4 blocks"))
+        .stdout(predicate::str::contains(format!("Root: {}", manifest_cid)));
+
+    let target = BlockStore::new_with_path(&target_path).unwrap();
+    for cid in cids.iter().chain([&manifest_cid]) {
+        assert!(target.has(cid).await, "missing block {}", cid);
+    }
+}
+
+#[tokio::test]
+async fn test_manifest_show_prints_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let store_path = dir.path().join("blocks");
+    let (_, manifest_cid) = populate_store(&store_path).await;
+
+    neverust()
+        .args(["manifest", "show"])
+        .arg(&store_path)
+        .arg(manifest_cid.to_string())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"dataset_size\": 300"))
+        .stdout(predicate::str::contains("\"blocks_count\": 3"))
+        .stdout(predicate::str::contains("\"filename\": \"data.bin\""));
+
+    neverust()
+        .args(["manifest", "show"])
+        .arg(&store_path)
+        .arg("not-a-cid")
+        .assert()
+        .failure();
+}
@@ -88,6 +88,36 @@ async fn test_manifest_with_metadata() {
     assert_eq!(manifest.block_size, DEFAULT_BLOCK_SIZE as u64);
 }
 
+/// Test that the manifest block size follows the chunker's configured size
+#[tokio::test]
+async fn test_manifest_block_size_matches_chunker() {
+    let store = Arc::new(BlockStore::new());
+    let test_data = vec![7u8; 10_000];
+
+    let mut chunker = Chunker::with_chunk_size(&test_data[..], 4096);
+    assert_eq!(chunker.chunk_size(), 4096);
+
+    let mut block_cids = Vec::new();
+    while let Some(chunk) = chunker.next_chunk().await.expect("Chunking failed") {
+        let cid = store.put_data(chunk).await.expect("Failed to store block");
+        block_cids.push(cid);
+    }
+
+    let manifest = Manifest::new(
+        block_cids[0],
+        chunker.chunk_size() as u64,
+        test_data.len() as u64,
+        None,
+        None,
+        None,
+        None,
+        None,
+    );
+
+    assert_eq!(manifest.block_size, 4096);
+    assert_eq!(manifest.blocks_count(), block_cids.len());
+}
+
 /// Test large data chunking and storage
 #[tokio::test]
 async fn test_large_data_manifest() {
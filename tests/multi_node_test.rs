@@ -0,0 +1,143 @@
+//! Integration test running several Neverust nodes in one process
+
+use neverust_core::fetcher::FetchSource;
+use neverust_core::storage::Block;
+use neverust_core::{run_node_with_handle, Config, NodeHandle};
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// Config for a node that only uses OS-assigned ports and no bootstrap peers
+fn local_config(data_dir: &Path) -> Config {
+    Config {
+        data_dir: data_dir.to_path_buf(),
+        listen_port: 0,
+        disc_port: 0,
+        api_port: 0,
+        api_bind: "127.0.0.1".to_string(),
+        no_bootstrap: true,
+        shutdown_timeout_secs: 5,
+        ..Config::default()
+    }
+}
+
+/// Wait until `node` reports a connection to exactly one peer
+async fn wait_for_connection(node: &NodeHandle) {
+    loop {
+        let stats = node.runtime().stats().await.unwrap();
+        if stats.connected_peers == 1 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_two_nodes_connect_in_process() {
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter("info")
+        .with_test_writer()
+        .try_init();
+
+    let dir1 = tempfile::tempdir().unwrap();
+    let dir2 = tempfile::tempdir().unwrap();
+    let (run1, node1) = run_node_with_handle(local_config(dir1.path()))
+        .await
+        .unwrap();
+    let (run2, node2) = run_node_with_handle(local_config(dir2.path()))
+        .await
+        .unwrap();
+    assert_ne!(node1.peer_id(), node2.peer_id());
+
+    // Each node listens on its own dynamically assigned port
+    let addr2 = node2.listen_addrs()[0].clone();
+    assert!(!node1.listen_addrs().contains(&addr2));
+
+    node1.runtime().dial(addr2).await.unwrap();
+    timeout(Duration::from_secs(10), async {
+        wait_for_connection(&node1).await;
+        wait_for_connection(&node2).await;
+    })
+    .await
+    .expect("nodes did not connect");
+
+    node1.shutdown();
+    node2.shutdown();
+    timeout(Duration::from_secs(10), async {
+        run1.await.unwrap();
+        run2.await.unwrap();
+    })
+    .await
+    .expect("nodes did not shut down");
+}
+
+/// GET `path` from `addr`, returning the raw HTTP response
+async fn http_get(addr: std::net::SocketAddr, path: String) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, addr
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[tokio::test]
+async fn test_shutdown_fails_api_requests_waiting_on_blockexc() {
+    let dir1 = tempfile::tempdir().unwrap();
+    let dir2 = tempfile::tempdir().unwrap();
+    // Only BlockExc can serve the block, and it would wait far longer than
+    // the test allows
+    let config = Config {
+        fetch_strategy: vec![FetchSource::Local, FetchSource::BlockExc],
+        blockexc_request_timeout_secs: 120,
+        shutdown_timeout_secs: 60,
+        ..local_config(dir1.path())
+    };
+    let (run1, node1) = run_node_with_handle(config).await.unwrap();
+    let (run2, node2) = run_node_with_handle(local_config(dir2.path()))
+        .await
+        .unwrap();
+    node1
+        .runtime()
+        .dial(node2.listen_addrs()[0].clone())
+        .await
+        .unwrap();
+    timeout(Duration::from_secs(10), wait_for_connection(&node1))
+        .await
+        .expect("nodes did not connect");
+
+    // Ask node1's API for a block neither node has
+    let missing = Block::new(b"stored nowhere".to_vec()).unwrap().cid;
+    let path = format!("/api/archivist/v1/data/{}/network/stream", missing);
+    let download = tokio::spawn(http_get(node1.api_addr(), path));
+    timeout(Duration::from_secs(10), async {
+        while node1.runtime().wantlist().await.unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("BlockExc request was not sent");
+
+    // Shutdown fails the request instead of waiting out the drain timeout
+    node1.shutdown();
+    let response = timeout(Duration::from_secs(15), download)
+        .await
+        .expect("API request still waiting after shutdown")
+        .unwrap();
+    assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+    timeout(Duration::from_secs(15), run1)
+        .await
+        .expect("node did not shut down")
+        .unwrap();
+
+    node2.shutdown();
+    timeout(Duration::from_secs(10), run2)
+        .await
+        .expect("node did not shut down")
+        .unwrap();
+}